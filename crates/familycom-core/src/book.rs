@@ -0,0 +1,636 @@
+//! Keepsake book export (EPUB).
+//!
+//! Turns a stretch of one conversation (say, a whole year) into an EPUB
+//! that can be read on an e-reader or converted to PDF and printed as a
+//! family chat book:
+//!
+//! ```text
+//! familia-2026.epub
+//! ├── title page          "Conversación con PC-Sala", date range, count
+//! ├── Enero 2026          one chapter per month, with a heading per day
+//! ├── Febrero 2026        images received that month appear in between
+//! └── ...                 the messages, at the time they arrived
+//! ```
+//!
+//! This module only builds the book; fetching the messages, finding the
+//! images and running a PDF converter is done by `familycom book`. Like the
+//! recap, it takes the timezone as a parameter so it can be tested.
+//!
+//! EPUB is a ZIP file, written here with a small "stored" (uncompressed)
+//! ZIP writer: the format requires the first entry to be uncompressed
+//! anyway, and photos are already compressed.
+
+use crate::types::{Direction, Message, Timestamp};
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Label used for our own messages, same as in the TUI.
+const OWN_NAME: &str = "Yo";
+
+/// Label used for daemon-generated messages (e.g. the weekly recap).
+const SYSTEM_NAME: &str = "FamilyCom";
+
+const STYLESHEET: &str = "\
+body { font-family: serif; line-height: 1.4; }
+h1 { text-align: center; margin-bottom: 1.5em; }
+h2 { font-size: 1em; text-align: center; color: #666; margin: 1.5em 0 0.5em; }
+p.msg { margin: 0.3em 0; }
+p.sent { margin-left: 2em; }
+p.system { font-style: italic; color: #666; }
+.time { color: #888; font-size: 0.85em; }
+.name { font-weight: bold; }
+figure { margin: 1em 0; text-align: center; }
+figure img { max-width: 100%; }
+figcaption { font-size: 0.85em; color: #666; }
+.title-page { text-align: center; margin-top: 30%; }
+";
+
+/// A picture to include in the book, e.g. a photo received from the peer.
+#[derive(Debug, Clone)]
+pub struct BookImage {
+    /// Original file name, used as the caption.
+    pub file_name: String,
+    /// When it was received; decides where in the book it goes.
+    pub received_at: Timestamp,
+    /// The file contents.
+    pub data: Vec<u8>,
+}
+
+/// Everything that goes into a keepsake book.
+#[derive(Debug, Clone)]
+pub struct Book {
+    /// Book title, e.g. "Familia 2026".
+    pub title: String,
+    /// Display name of the other side of the conversation.
+    pub peer_name: String,
+    /// When the book was made (EPUB metadata).
+    pub created_at: Timestamp,
+    /// The conversation, oldest first.
+    pub messages: Vec<Message>,
+    /// Pictures to place among the messages. Ones that aren't a supported
+    /// image type (see `is_image`) are left out.
+    pub images: Vec<BookImage>,
+}
+
+/// One entry of a chapter, in time order.
+#[derive(Debug, Clone, Copy)]
+pub enum ChapterItem<'a> {
+    Message(&'a Message),
+    /// An image, with its index in `Book::images`.
+    Image(usize, &'a BookImage),
+}
+
+impl ChapterItem<'_> {
+    fn timestamp(&self) -> Timestamp {
+        match self {
+            ChapterItem::Message(msg) => msg.timestamp,
+            ChapterItem::Image(_, image) => image.received_at,
+        }
+    }
+}
+
+/// The messages and images of one calendar month.
+#[derive(Debug, Clone)]
+pub struct Chapter<'a> {
+    pub year: i32,
+    /// 1-12.
+    pub month: u32,
+    pub items: Vec<ChapterItem<'a>>,
+}
+
+impl Chapter<'_> {
+    /// Chapter title, e.g. "Febrero 2026".
+    pub fn title(&self) -> String {
+        format!("{} {}", month_name(self.month), self.year)
+    }
+
+    /// File name inside the EPUB, e.g. "2026-02.xhtml".
+    fn file_name(&self) -> String {
+        format!("{:04}-{:02}.xhtml", self.year, self.month)
+    }
+}
+
+impl Book {
+    /// Splits the book into monthly chapters (in `tz`), oldest first.
+    /// Months without messages or images get no chapter.
+    pub fn chapters<Tz: TimeZone>(&self, tz: &Tz) -> Vec<Chapter<'_>> {
+        let mut months: BTreeMap<(i32, u32), Vec<ChapterItem<'_>>> = BTreeMap::new();
+        let items = self
+            .messages
+            .iter()
+            .map(ChapterItem::Message)
+            .chain(
+                self.images
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, image)| is_image(&image.file_name))
+                    .map(|(i, image)| ChapterItem::Image(i, image)),
+            );
+        for item in items {
+            let Some(date) = local_date(item.timestamp(), tz) else {
+                continue;
+            };
+            months.entry((date.year(), date.month())).or_default().push(item);
+        }
+
+        months
+            .into_iter()
+            .map(|((year, month), mut items)| {
+                // Stable: on equal timestamps messages stay before images
+                items.sort_by_key(|item| item.timestamp());
+                Chapter { year, month, items }
+            })
+            .collect()
+    }
+
+    /// Writes the book as an EPUB 3 file to `out`, returning the writer.
+    pub fn write_epub<W: Write, Tz: TimeZone>(&self, out: W, tz: &Tz) -> io::Result<W>
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let chapters = self.chapters(tz);
+        let mut zip = StoredZip::new(out);
+
+        // Must be first and uncompressed, so readers can sniff the type
+        zip.add("mimetype", b"application/epub+zip")?;
+        zip.add("META-INF/container.xml", CONTAINER_XML.as_bytes())?;
+        zip.add("OEBPS/content.opf", self.package_document(&chapters).as_bytes())?;
+        zip.add("OEBPS/nav.xhtml", self.navigation(&chapters).as_bytes())?;
+        zip.add("OEBPS/style.css", STYLESHEET.as_bytes())?;
+        zip.add("OEBPS/title.xhtml", self.title_page(tz).as_bytes())?;
+        for chapter in &chapters {
+            let path = format!("OEBPS/{}", chapter.file_name());
+            zip.add(&path, self.chapter_page(chapter, tz).as_bytes())?;
+        }
+        for chapter in &chapters {
+            for item in &chapter.items {
+                if let ChapterItem::Image(i, image) = item {
+                    zip.add(&format!("OEBPS/{}", image_path(*i, image)), &image.data)?;
+                }
+            }
+        }
+        zip.finish()
+    }
+
+    /// `content.opf`: metadata, the list of files, and the reading order.
+    fn package_document(&self, chapters: &[Chapter<'_>]) -> String {
+        let modified = DateTime::<Utc>::from_timestamp_millis(self.created_at.as_millis())
+            .unwrap_or_default()
+            .format("%Y-%m-%dT%H:%M:%SZ");
+
+        let mut manifest = String::new();
+        let mut spine = String::from("    <itemref idref=\"title\"/>\n");
+        for chapter in chapters {
+            let id = format!("ch-{:04}-{:02}", chapter.year, chapter.month);
+            manifest.push_str(&format!(
+                "    <item id=\"{id}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                chapter.file_name()
+            ));
+            spine.push_str(&format!("    <itemref idref=\"{id}\"/>\n"));
+            for item in &chapter.items {
+                if let ChapterItem::Image(i, image) = item {
+                    manifest.push_str(&format!(
+                        "    <item id=\"img-{i}\" href=\"{}\" media-type=\"{}\"/>\n",
+                        image_path(*i, image),
+                        image_media_type(&image.file_name).unwrap_or("application/octet-stream")
+                    ));
+                }
+            }
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="es">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:familycom:book:{created}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>es</dc:language>
+    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="css" href="style.css" media-type="text/css"/>
+    <item id="title" href="title.xhtml" media-type="application/xhtml+xml"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+            created = self.created_at.as_millis(),
+            title = escape_xml(&self.title),
+        )
+    }
+
+    /// `nav.xhtml`: the table of contents, one entry per month.
+    fn navigation(&self, chapters: &[Chapter<'_>]) -> String {
+        let mut entries = String::new();
+        for chapter in chapters {
+            entries.push_str(&format!(
+                "      <li><a href=\"{}\">{}</a></li>\n",
+                chapter.file_name(),
+                chapter.title()
+            ));
+        }
+        xhtml_page(
+            "Índice",
+            &format!(
+                "  <nav epub:type=\"toc\" id=\"toc\">\n    <h1>Índice</h1>\n    <ol>\n{entries}    </ol>\n  </nav>\n"
+            ),
+        )
+    }
+
+    fn title_page<Tz: TimeZone>(&self, tz: &Tz) -> String {
+        let dates: Vec<NaiveDate> = self
+            .messages
+            .iter()
+            .filter_map(|m| local_date(m.timestamp, tz))
+            .collect();
+        let range = match (dates.first(), dates.last()) {
+            (Some(first), Some(last)) => format!(
+                "    <p>Del {} al {}</p>\n",
+                first.format("%d/%m/%Y"),
+                last.format("%d/%m/%Y")
+            ),
+            _ => String::new(),
+        };
+        let count = self.messages.len();
+        xhtml_page(
+            &self.title,
+            &format!(
+                "  <div class=\"title-page\">\n    <h1>{}</h1>\n    <p>Conversación con {}</p>\n{range}    <p>{count} {}</p>\n  </div>\n",
+                escape_xml(&self.title),
+                escape_xml(&self.peer_name),
+                if count == 1 { "mensaje" } else { "mensajes" },
+            ),
+        )
+    }
+
+    fn chapter_page<Tz: TimeZone>(&self, chapter: &Chapter<'_>, tz: &Tz) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let mut body = format!("  <h1>{}</h1>\n", chapter.title());
+        let mut current_day = None;
+        for item in &chapter.items {
+            let Some(at) = tz.timestamp_millis_opt(item.timestamp().as_millis()).earliest() else {
+                continue;
+            };
+            let day = at.date_naive();
+            if current_day != Some(day) {
+                current_day = Some(day);
+                body.push_str(&format!("  <h2>{}</h2>\n", day.format("%d/%m/%Y")));
+            }
+            let time = at.format("%H:%M");
+
+            match item {
+                ChapterItem::Message(msg) => {
+                    let (class, name) = match msg.direction {
+                        Direction::Sent => ("sent", OWN_NAME),
+                        Direction::Received => ("received", self.peer_name.as_str()),
+                        Direction::System => ("system", SYSTEM_NAME),
+                    };
+                    body.push_str(&format!(
+                        "  <p class=\"msg {class}\"><span class=\"time\">{time}</span> <span class=\"name\">{}:</span> {}</p>\n",
+                        escape_xml(name),
+                        escape_xml(&msg.content).replace('\n', "<br/>")
+                    ));
+                }
+                ChapterItem::Image(i, image) => {
+                    let caption = escape_xml(&image.file_name);
+                    body.push_str(&format!(
+                        "  <figure>\n    <img src=\"{}\" alt=\"{caption}\"/>\n    <figcaption>{time} · {caption}</figcaption>\n  </figure>\n",
+                        image_path(*i, image)
+                    ));
+                }
+            }
+        }
+        xhtml_page(&chapter.title(), &body)
+    }
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// Wraps `body` in an XHTML document with the book's stylesheet.
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="es" xml:lang="es">
+<head>
+  <meta charset="UTF-8"/>
+  <title>{}</title>
+  <link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+        escape_xml(title)
+    )
+}
+
+/// Whether `file_name` is a picture type EPUB readers can show.
+pub fn is_image(file_name: &str) -> bool {
+    image_media_type(file_name).is_some()
+}
+
+/// MIME type of an image, from its extension.
+fn image_media_type(file_name: &str) -> Option<&'static str> {
+    let ext = file_name.rsplit_once('.')?.1.to_ascii_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// Path of an image inside `OEBPS/`. Numbered rather than named after the
+/// file, so odd characters in received file names can't break the book.
+fn image_path(index: usize, image: &BookImage) -> String {
+    let ext = image
+        .file_name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    format!("images/img-{index}.{ext}")
+}
+
+/// Spanish month name (1 = January).
+pub fn month_name(month: u32) -> &'static str {
+    match month {
+        1 => "Enero",
+        2 => "Febrero",
+        3 => "Marzo",
+        4 => "Abril",
+        5 => "Mayo",
+        6 => "Junio",
+        7 => "Julio",
+        8 => "Agosto",
+        9 => "Septiembre",
+        10 => "Octubre",
+        11 => "Noviembre",
+        12 => "Diciembre",
+        _ => "?",
+    }
+}
+
+fn local_date<Tz: TimeZone>(ts: Timestamp, tz: &Tz) -> Option<NaiveDate> {
+    tz.timestamp_millis_opt(ts.as_millis()).earliest().map(|dt| dt.date_naive())
+}
+
+/// Escapes text for use in XHTML content and attribute values.
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters aren't allowed in XML at all
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// ---------------------------------------------------------------------------
+// Minimal ZIP writer (stored entries only)
+// ---------------------------------------------------------------------------
+
+/// DOS date for 1980-01-01, the earliest ZIP can express. Entry times
+/// don't matter for a book, and a fixed one keeps output reproducible.
+const DOS_DATE_1980: u16 = (1 << 5) | 1;
+
+/// Central directory record kept until `finish`.
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes a ZIP archive whose entries are all stored uncompressed.
+struct StoredZip<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<ZipEntry>,
+}
+
+impl<W: Write> StoredZip<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let too_big = || io::Error::new(io::ErrorKind::InvalidInput, "book too large for a ZIP32 archive");
+        let size = u32::try_from(data.len()).map_err(|_| too_big())?;
+        let offset = u32::try_from(self.offset).map_err(|_| too_big())?;
+        let crc = crc32(data);
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        header.extend_from_slice(&0x0800u16.to_le_bytes()); // UTF-8 names
+        header.extend_from_slice(&0u16.to_le_bytes()); // stored
+        header.extend_from_slice(&0u16.to_le_bytes()); // time
+        header.extend_from_slice(&DOS_DATE_1980.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes()); // compressed
+        header.extend_from_slice(&size.to_le_bytes()); // uncompressed
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // extra field
+        header.extend_from_slice(name.as_bytes());
+
+        self.out.write_all(&header)?;
+        self.out.write_all(data)?;
+        self.offset += (header.len() + data.len()) as u64;
+        self.entries.push(ZipEntry {
+            name: name.to_string(),
+            crc,
+            size,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the underlying writer.
+    fn finish(mut self) -> io::Result<W> {
+        let too_big = || io::Error::new(io::ErrorKind::InvalidInput, "book too large for a ZIP32 archive");
+        let directory_offset = u32::try_from(self.offset).map_err(|_| too_big())?;
+
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            directory.extend_from_slice(&0x0800u16.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&DOS_DATE_1980.to_le_bytes());
+            directory.extend_from_slice(&entry.crc.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&entry.size.to_le_bytes());
+            directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes()); // extra field
+            directory.extend_from_slice(&0u16.to_le_bytes()); // comment
+            directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+            directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+            directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+            directory.extend_from_slice(&entry.offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        let directory_size = u32::try_from(directory.len()).map_err(|_| too_big())?;
+        let count = u16::try_from(self.entries.len()).map_err(|_| too_big())?;
+
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // this disk
+        end.extend_from_slice(&0u16.to_le_bytes()); // directory disk
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&directory_size.to_le_bytes());
+        end.extend_from_slice(&directory_offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // comment
+
+        self.out.write_all(&directory)?;
+        self.out.write_all(&end)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// CRC-32 (IEEE), as used by ZIP.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageId, PeerId};
+    use chrono::FixedOffset;
+
+    fn tz() -> FixedOffset {
+        FixedOffset::west_opt(3 * 3600).unwrap()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32) -> Timestamp {
+        let dt = tz().with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap();
+        Timestamp::from_millis(dt.timestamp_millis())
+    }
+
+    fn msg(ts: Timestamp, direction: Direction, content: &str) -> Message {
+        Message {
+            id: MessageId::generate(),
+            peer_id: PeerId::new("peer-1"),
+            direction,
+            content: content.to_string(),
+            timestamp: ts,
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        }
+    }
+
+    fn book() -> Book {
+        Book {
+            title: "Familia 2026".to_string(),
+            peer_name: "Abuela".to_string(),
+            created_at: at(2027, 1, 1, 12),
+            messages: vec![
+                msg(at(2026, 1, 5, 10), Direction::Received, "Feliz año <3"),
+                msg(at(2026, 1, 5, 11), Direction::Sent, "Igualmente & besos"),
+                msg(at(2026, 3, 2, 9), Direction::Received, "Ya llegó la primavera"),
+            ],
+            images: vec![
+                BookImage {
+                    file_name: "torta.JPG".to_string(),
+                    received_at: at(2026, 1, 5, 10),
+                    data: vec![0xFF, 0xD8, 0xFF],
+                },
+                BookImage {
+                    file_name: "receta.pdf".to_string(),
+                    received_at: at(2026, 2, 1, 10),
+                    data: vec![1, 2, 3],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn chapters_per_month_with_images_in_time_order() {
+        let book = book();
+        let chapters = book.chapters(&tz());
+        // February only had a PDF, which isn't an image: no chapter
+        let titles: Vec<String> = chapters.iter().map(|c| c.title()).collect();
+        assert_eq!(titles, ["Enero 2026", "Marzo 2026"]);
+
+        let january = &chapters[0].items;
+        assert_eq!(january.len(), 3);
+        // Same time as the first message: the message comes first
+        assert!(matches!(january[0], ChapterItem::Message(m) if m.content == "Feliz año <3"));
+        assert!(matches!(january[1], ChapterItem::Image(0, _)));
+        assert!(matches!(january[2], ChapterItem::Message(_)));
+    }
+
+    #[test]
+    fn chapter_page_escapes_content() {
+        let book = book();
+        let chapters = book.chapters(&tz());
+        let page = book.chapter_page(&chapters[0], &tz());
+        assert!(page.contains("<h2>05/01/2026</h2>"));
+        assert!(page.contains("Feliz año &lt;3"));
+        assert!(page.contains("Igualmente &amp; besos"));
+        assert!(page.contains("<img src=\"images/img-0.jpg\" alt=\"torta.JPG\"/>"));
+    }
+
+    #[test]
+    fn epub_starts_with_stored_mimetype() {
+        let bytes = book().write_epub(Vec::new(), &tz()).unwrap();
+        // Local header signature, then the name at offset 30 and the
+        // uncompressed contents right after it
+        assert_eq!(&bytes[0..4], &[0x50, 0x4b, 0x03, 0x04]);
+        assert_eq!(&bytes[8..10], &[0, 0]); // stored
+        assert_eq!(&bytes[30..38], b"mimetype");
+        assert_eq!(&bytes[38..58], b"application/epub+zip");
+        // End of central directory record at the very end
+        let end = bytes.len() - 22;
+        assert_eq!(&bytes[end..end + 4], &[0x50, 0x4b, 0x05, 0x06]);
+    }
+
+    #[test]
+    fn crc32_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
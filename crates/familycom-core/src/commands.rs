@@ -0,0 +1,111 @@
+//! Slash commands typed in the message box.
+//!
+//! A message starting with `/` is a command for the local daemon, not
+//! something to send: the daemon runs it and answers with a
+//! `Direction::System` message in the open conversation.
+//!
+//! ```text
+//! /ping PC-Sala   check that a peer answers, and how fast
+//! /ping           same, for the peer of the open conversation
+//! /who            who is online
+//! /status         this daemon's state
+//! /help           the list of commands
+//! ```
+//!
+//! To send a message that starts with `/`, double it: `//etc/hosts` is
+//! sent as `/etc/hosts`.
+//!
+//! This module only parses; running the commands lives in the daemon, and
+//! clients use `Input::parse` to know not to show a command as sent.
+
+/// What was typed in the message box.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input<'a> {
+    /// Text to send, with a leading `//` turned into `/`.
+    Message(&'a str),
+    /// A command for the daemon.
+    Command(SlashCommand),
+}
+
+/// A command the daemon runs instead of sending the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    /// `/ping [peer]`: the named peer (display name or peer ID), or the
+    /// peer of the open conversation.
+    Ping(Option<String>),
+    /// `/who`
+    Who,
+    /// `/status`
+    Status,
+    /// `/help`
+    Help,
+    /// Anything else, by name (without the `/`).
+    Unknown(String),
+}
+
+/// The help text, also shown after an unknown command.
+pub const HELP: &str = "Comandos: /ping [nombre] (comprobar si alguien responde), \
+/who (quien esta en linea), /status (estado de este equipo), /help. \
+Para enviar un mensaje que empiece con /, escribe //.";
+
+impl<'a> Input<'a> {
+    /// Classifies the (trimmed) content of the message box.
+    pub fn parse(content: &'a str) -> Self {
+        let Some(rest) = content.strip_prefix('/') else {
+            return Input::Message(content);
+        };
+        if rest.starts_with('/') {
+            return Input::Message(rest);
+        }
+        let (name, arg) = match rest.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim()).filter(|arg| !arg.is_empty())),
+            None => (rest, None),
+        };
+        let command = match name.to_lowercase().as_str() {
+            "ping" => SlashCommand::Ping(arg.map(str::to_string)),
+            "who" => SlashCommand::Who,
+            "status" => SlashCommand::Status,
+            "help" => SlashCommand::Help,
+            _ => SlashCommand::Unknown(name.to_string()),
+        };
+        Input::Command(command)
+    }
+
+    /// Whether this is a command rather than a message to send.
+    pub fn is_command(&self) -> bool {
+        matches!(self, Input::Command(_))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_and_arguments() {
+        assert_eq!(Input::parse("/who"), Input::Command(SlashCommand::Who));
+        assert_eq!(Input::parse("/STATUS"), Input::Command(SlashCommand::Status));
+        assert_eq!(
+            Input::parse("/ping  PC de la Abuela "),
+            Input::Command(SlashCommand::Ping(Some("PC de la Abuela".to_string())))
+        );
+        assert_eq!(Input::parse("/ping"), Input::Command(SlashCommand::Ping(None)));
+        assert_eq!(
+            Input::parse("/hola a todos"),
+            Input::Command(SlashCommand::Unknown("hola".to_string()))
+        );
+    }
+
+    #[test]
+    fn messages_pass_through() {
+        assert_eq!(Input::parse("hola"), Input::Message("hola"));
+        assert_eq!(Input::parse("1/2 taza"), Input::Message("1/2 taza"));
+        // A doubled slash sends the rest as is
+        assert_eq!(Input::parse("//etc/hosts"), Input::Message("/etc/hosts"));
+        assert!(!Input::parse("//who").is_command());
+    }
+}
@@ -3,6 +3,7 @@
 //! The config file lives at a platform-appropriate location:
 //! - Linux: `~/.config/familycom/config.toml`
 //! - macOS: `~/Library/Application Support/familycom/config.toml`
+//! - Windows: `%APPDATA%\familycom\config.toml`
 //!
 //! On first run, no config file exists. The daemon detects this and
 //! creates one with a fresh `peer_id` and the user's chosen display name.
@@ -14,11 +15,88 @@
 //! display_name = "PC-Sala"
 //! tcp_port = 0        # 0 means auto-assign
 //! # network_interface = "enp5s0"  # optional: restrict mDNS to this interface
+//! # network_interface = ["enp5s0", "wlan0"]  # or several (wired and Wi-Fi), or "all"
+//! notifications_enabled = true
+//! # avatar = "🐱"            # optional: emoji shown next to our name
+//! # accent_color = "#ff8800" # optional: color of our name on other screens
+//! # ipc_listen = ["tcp:127.0.0.1:7878"]  # extra IPC endpoints besides the Unix socket
+//! # http_listen = "127.0.0.1:7879"     # HTTP API and web chat page ("0.0.0.0:7879" for the LAN)
+//! # quiet_hours = "22:00-07:00"  # do not disturb: no notifications, except urgent ("!!...")
+//! # webhook_url = "http://homeassistant.local:8123/api/webhook/familycom"  # POST each received message
+//!
+//! [downloads]
+//! # root = "/home/ana/Descargas/FamilyCom"  # default: <Downloads>/FamilyCom
+//! per_peer_folders = true   # files from each peer are in <root>/<peer name>/
+//!
+//! [networks]
+//! require_trusted = true    # stay silent on networks not listed below
+//!
+//! [[networks.trusted]]
+//! name = "Casa"
+//! gateway_mac = "a4:2b:b0:12:34:56"  # router MAC, logged by the daemon
+//! # ssid = "MiCasa"                  # or match by Wi-Fi name
+//!
+//! [delivery]
+//! default = "ack_required"  # ack_required | fire_and_forget
+//!
+//! [delivery.peers]
+//! "Timbre" = "fire_and_forget"  # by display name or peer_id
+//!
+//! [terminal]
+//! title = true              # unread count in the terminal title (TUI)
+//! bell = "unfocused"        # unfocused | always | never
+//!
+//! [storage]                 # low-storage mode, e.g. a Pi Zero on an SD card
+//! # archive_peer = "NAS"    # peer keeping the full history (name or peer_id)
+//! # keep_days = 30          # only keep this much history locally
+//! # max_db_mb = 50          # trim the oldest archived messages past this size
+//! # truncate_chars = 500    # shorten long archived messages kept locally
+//! # archiver = true         # on the NAS: keep everyone's history, forever
+//!
+//! [mqtt]                    # bridge to a home-automation broker (off without broker)
+//! # broker = "192.168.1.10:1883"
+//! # topic_prefix = "familycom"
+//! # username = "familycom"
+//! # password = "secreto"
+//!
+//! [email]                   # email messages a peer hasn't received (off without smtp_relay)
+//! # smtp_relay = "192.168.1.1:25"
+//! # allow_remote_relay = true   # needed for a relay not on this machine (no TLS)
+//! # from = "familycom@casa.lan"
+//! # after_hours = 4
+//!
+//! [email.addresses]
+//! # "PC-Abuela" = "abuela@example.com"  # by display name or peer_id
+//!
+//! [kid_mode]                # a child's machine: only talk to these peers
+//! # enabled = true
+//! # allowed = ["550e8400-...", "7c9e6679-..."]  # peer_ids only, names can be faked
+//!
+//! [away]
+//! idle_minutes = 10         # show as away after this long without input (0 = never)
+//! ```
+//!
+//! # TUI Preferences
+//!
+//! What the TUI changes by itself (the peer list's width, ...) goes in
+//! `tui.toml` in the same directory, see `TuiConfig`. config.toml is the
+//! daemon's, and the daemon reloads it whenever it changes.
+//!
+//! ```toml
+//! peer_list_hidden = false  # F4 hides and shows the peer list
+//! peer_list_width = 25      # percent of the window, Ctrl+Left/Right
+//! vim_mode = false          # normal mode to move around, i to type
+//! relative_times = false    # "hace 5 min" instead of "10:30"
 //! ```
 
-use crate::types::PeerId;
+use crate::ipc::IpcEndpoint;
+use crate::types::{AccentColor, Avatar, DeliveryMode, PeerId};
+use chrono::NaiveTime;
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 /// Errors that can occur when loading or saving configuration.
@@ -54,7 +132,7 @@ pub enum ConfigError {
 /// This is what gets saved to and loaded from the TOML config file.
 /// All fields have sensible defaults except `peer_id` which must be
 /// generated on first run.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AppConfig {
     /// Unique identifier for this machine (UUID v4, generated once).
     pub peer_id: String,
@@ -72,11 +150,863 @@ pub struct AppConfig {
     #[serde(default)]
     pub terminal_command: Option<String>,
 
-    /// Optional: restrict mDNS to this network interface (e.g. "enp5s0").
-    /// If not set, the default-route interface is auto-detected.
-    /// Useful when Docker or VPN interfaces cause mDNS conflicts.
+    /// Optional: restrict mDNS to this network interface (e.g. "enp5s0"),
+    /// to several, or to `"all"` LAN interfaces for homes with both wired
+    /// and wireless segments. If not set, the default-route interface is
+    /// auto-detected. Useful when Docker or VPN interfaces cause mDNS
+    /// conflicts.
+    #[serde(default)]
+    pub network_interface: Option<NetworkInterfaces>,
+
+    /// Whether the daemon shows desktop notifications for incoming messages.
+    #[serde(default = "default_true")]
+    pub notifications_enabled: bool,
+
+    /// Optional: emoji advertised to other peers next to our display name.
+    #[serde(default)]
+    pub avatar: Option<Avatar>,
+
+    /// Optional: color other peers use to render our name.
+    #[serde(default)]
+    pub accent_color: Option<AccentColor>,
+
+    /// Where files from peers are kept (the book's pictures come from
+    /// there).
+    #[serde(default)]
+    pub downloads: DownloadConfig,
+
+    /// Which networks the daemon may advertise itself and accept peers on.
+    #[serde(default)]
+    pub networks: NetworkConfig,
+
+    /// Extra IPC endpoints to listen on besides the Unix socket, e.g.
+    /// `"tcp:127.0.0.1:7878"` or a Windows named pipe `"pipe:..."`.
+    #[serde(default)]
+    pub ipc_listen: Vec<IpcEndpoint>,
+
+    /// Optional: where to serve the HTTP API (`GET /peers`, `GET
+    /// /messages/<peer>`, `POST /messages`) and the web chat page at `/`,
+    /// e.g. `"127.0.0.1:7879"`.
+    /// Unauthenticated, so only bind it beyond localhost on a trusted LAN.
+    /// Browsers must open it by IP address (or `localhost`), not by name.
+    #[serde(default)]
+    pub http_listen: Option<SocketAddr>,
+
+    /// Whether sends wait for an ACK, per peer.
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+
+    /// How the TUI signals new messages through the terminal.
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+
+    /// Low-storage mode: how much history to keep locally.
+    #[serde(default)]
+    pub storage: StorageConfig,
+
+    /// Optional: daily do-not-disturb window, e.g. `"22:00-07:00"`.
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+
+    /// Optional: where to POST each received message as JSON (sender,
+    /// preview, timestamp), e.g. a Home Assistant or ntfy webhook.
+    #[serde(default)]
+    pub webhook_url: Option<WebhookUrl>,
+
+    /// MQTT bridge: messages and presence out, messages to send in.
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+
+    /// Email fallback for messages a peer doesn't pick up.
+    #[serde(default)]
+    pub email: EmailConfig,
+
+    /// Kid mode: only exchange messages with an allow-listed set of peers.
+    #[serde(default)]
+    pub kid_mode: KidModeConfig,
+
+    /// Automatic away status when nobody is at the keyboard.
+    #[serde(default)]
+    pub away: AwayConfig,
+}
+
+/// Where files from peers are kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadConfig {
+    /// Directory the files are under. If not set, `default_root()` is
+    /// used.
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+
+    /// Whether each peer has its own subfolder (named after the peer).
+    #[serde(default = "default_true")]
+    pub per_peer_folders: bool,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            root: None,
+            per_peer_folders: true,
+        }
+    }
+}
+
+impl DownloadConfig {
+    /// Returns the configured root, or the platform default.
+    pub fn root_dir(&self) -> Option<PathBuf> {
+        self.root.clone().or_else(Self::default_root)
+    }
+
+    /// The default download root: `<Downloads>/FamilyCom`, falling back to
+    /// the data directory on systems without a Downloads folder.
+    pub fn default_root() -> Option<PathBuf> {
+        dirs::download_dir()
+            .map(|d| d.join("FamilyCom"))
+            .or_else(|| AppConfig::data_dir().map(|d| d.join("downloads")))
+    }
+}
+
+/// Per-network profiles: where the daemon is allowed to be visible.
+///
+/// By default the daemon runs on any network. With `require_trusted`, it
+/// only advertises itself over mDNS and accepts peer connections while the
+/// current network matches one of `trusted`; elsewhere (a café, a hotel)
+/// it stays silent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Only be visible on networks listed in `trusted`.
+    #[serde(default)]
+    pub require_trusted: bool,
+
+    /// Networks considered home (or otherwise safe).
+    #[serde(default)]
+    pub trusted: Vec<TrustedNetwork>,
+}
+
+impl NetworkConfig {
+    /// Returns the trusted profile matching `network`, if any.
+    pub fn matching(&self, network: &NetworkFingerprint) -> Option<&TrustedNetwork> {
+        self.trusted.iter().find(|t| t.matches(network))
+    }
+
+    /// Whether the daemon may be visible on `network`.
+    pub fn allows(&self, network: &NetworkFingerprint) -> bool {
+        !self.require_trusted || self.matching(network).is_some()
+    }
+}
+
+/// A network marked as trusted, identified by its gateway's MAC address
+/// and/or its Wi-Fi name. Either one matching is enough.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustedNetwork {
+    /// Label used in logs, e.g. "Casa".
+    pub name: String,
+
+    /// MAC address of the default gateway, e.g. "a4:2b:b0:12:34:56".
+    /// Compared case-insensitively; '-' separators are accepted.
+    #[serde(default)]
+    pub gateway_mac: Option<String>,
+
+    /// Wi-Fi network name (SSID).
+    #[serde(default)]
+    pub ssid: Option<String>,
+}
+
+impl TrustedNetwork {
+    /// Whether this profile describes `network`. A profile with neither a
+    /// MAC nor an SSID matches nothing.
+    pub fn matches(&self, network: &NetworkFingerprint) -> bool {
+        let mac_matches = match (&self.gateway_mac, &network.gateway_mac) {
+            (Some(ours), Some(theirs)) => normalize_mac(ours) == normalize_mac(theirs),
+            _ => false,
+        };
+        let ssid_matches = match (&self.ssid, &network.ssid) {
+            (Some(ours), Some(theirs)) => ours == theirs,
+            _ => false,
+        };
+        mac_matches || ssid_matches
+    }
+}
+
+/// What we could observe about the network we're currently on.
+///
+/// Detection is platform-specific and best effort (done by the daemon);
+/// either field may be missing, e.g. no SSID on a wired connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkFingerprint {
+    /// MAC address of the default gateway.
+    pub gateway_mac: Option<String>,
+    /// Name of the connected Wi-Fi network.
+    pub ssid: Option<String>,
+}
+
+impl std::fmt::Display for NetworkFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "gateway_mac = {}, ssid = {}",
+            self.gateway_mac.as_deref().unwrap_or("?"),
+            self.ssid.as_deref().unwrap_or("?")
+        )
+    }
+}
+
+/// Lowercases a MAC address and uses ':' separators.
+fn normalize_mac(mac: &str) -> String {
+    mac.trim().to_ascii_lowercase().replace('-', ":")
+}
+
+/// Serde default for boolean settings that are on unless turned off.
+fn default_true() -> bool {
+    true
+}
+
+/// Delivery mode for outgoing messages.
+///
+/// Every peer uses `default` unless it has an entry in `peers`, keyed by
+/// peer ID or display name. The peer ID wins if both match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryConfig {
+    /// Mode for peers without an override.
+    #[serde(default)]
+    pub default: DeliveryMode,
+
+    /// Per-peer overrides, e.g. `"Timbre" = "fire_and_forget"`.
+    #[serde(default)]
+    pub peers: BTreeMap<String, DeliveryMode>,
+}
+
+impl DeliveryConfig {
+    /// Returns the delivery mode for a peer.
+    pub fn mode_for(&self, peer_id: &PeerId, display_name: Option<&str>) -> DeliveryMode {
+        self.peers
+            .get(peer_id.as_str())
+            .or_else(|| display_name.and_then(|name| self.peers.get(name)))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// How the TUI signals activity through the terminal itself, for people
+/// who keep it in a tmux or screen window instead of relying on desktop
+/// notifications.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerminalConfig {
+    /// Show the unread count in the terminal title, e.g. "FamilyCom (3)".
+    #[serde(default = "default_true")]
+    pub title: bool,
+
+    /// When to ring the terminal bell on a new message.
+    #[serde(default)]
+    pub bell: BellMode,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            title: true,
+            bell: BellMode::default(),
+        }
+    }
+}
+
+/// The TUI's own preferences, kept in `tui.toml` and saved by the TUI as
+/// they change. Each TUI on the machine shares them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TuiConfig {
+    /// Hide the peer list, leaving the width to the messages.
+    #[serde(default)]
+    pub peer_list_hidden: bool,
+
+    /// Width of the peer list, as a percentage of the window.
+    #[serde(default = "default_peer_list_width")]
+    pub peer_list_width: u16,
+
+    /// Vim-style modes: the panels are navigated with j/k/gg/G (normal
+    /// mode) and `i` starts typing (insert mode) until Esc. Off by default.
+    #[serde(default)]
+    pub vim_mode: bool,
+
+    /// Show message times relative to now ("hace 5 min", "ayer 21:03")
+    /// instead of the clock time.
     #[serde(default)]
-    pub network_interface: Option<String>,
+    pub relative_times: bool,
+}
+
+fn default_peer_list_width() -> u16 {
+    25
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            peer_list_hidden: false,
+            peer_list_width: default_peer_list_width(),
+            vim_mode: false,
+            relative_times: false,
+        }
+    }
+}
+
+impl TuiConfig {
+    /// Narrowest and widest the peer list can be made, in percent.
+    pub const PEER_LIST_WIDTHS: std::ops::RangeInclusive<u16> = 10..=60;
+
+    /// Returns the full path to the TUI's preferences file.
+    pub fn file_path() -> Result<PathBuf, ConfigError> {
+        Ok(AppConfig::config_dir()
+            .ok_or(ConfigError::NoConfigDir)?
+            .join("tui.toml"))
+    }
+
+    /// Loads the preferences from `file_path()`, or the defaults if the
+    /// file doesn't exist yet.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_from(&Self::file_path()?)
+    }
+
+    /// Loads the preferences from a specific file, or the defaults if it
+    /// doesn't exist. Out-of-range values are brought back in range.
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::ReadFile {
+            path: path.to_owned(),
+            source: e,
+        })?;
+        let mut config: Self = toml::from_str(&content).map_err(|e| ConfigError::ParseFile {
+            path: path.to_owned(),
+            source: e,
+        })?;
+        config.peer_list_width = config
+            .peer_list_width
+            .clamp(*Self::PEER_LIST_WIDTHS.start(), *Self::PEER_LIST_WIDTHS.end());
+        Ok(config)
+    }
+
+    /// Saves the preferences to `file_path()`.
+    pub fn save(&self) -> Result<(), ConfigError> {
+        self.save_to(&Self::file_path()?)
+    }
+
+    /// Saves the preferences to a specific file, creating its directory
+    /// if needed.
+    pub fn save_to(&self, path: &Path) -> Result<(), ConfigError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| ConfigError::WriteFile {
+                path: path.to_owned(),
+                source: e,
+            })?;
+        }
+        let content = toml::to_string_pretty(self)?;
+        std::fs::write(path, content).map_err(|e| ConfigError::WriteFile {
+            path: path.to_owned(),
+            source: e,
+        })
+    }
+}
+
+/// Low-storage mode for devices with little disk (`[storage]`).
+///
+/// Messages are copied to `archive_peer` as they arrive; once a message
+/// is safely there, it can be dropped locally when it's older than
+/// `keep_days` or the database grows past `max_db_mb`, and shortened to
+/// `truncate_chars`. Older history is then fetched from the archive when
+/// scrolling back. Without an `archive_peer` nothing is ever removed.
+///
+/// The archive itself is a daemon with `archiver = true`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// The peer that keeps the full history, by display name or peer ID.
+    #[serde(default)]
+    pub archive_peer: Option<String>,
+
+    /// Drop archived messages older than this many days.
+    #[serde(default)]
+    pub keep_days: Option<u32>,
+
+    /// Drop the oldest archived messages while the database is larger
+    /// than this many megabytes.
+    #[serde(default)]
+    pub max_db_mb: Option<u64>,
+
+    /// Shorten archived messages longer than this many characters.
+    #[serde(default)]
+    pub truncate_chars: Option<u32>,
+
+    /// Act as the household archive: store the history other daemons
+    /// push (kept indefinitely) and answer their history queries.
+    #[serde(default)]
+    pub archiver: bool,
+}
+
+impl StorageConfig {
+    /// Whether history is copied to an archive peer at all.
+    pub fn low_storage(&self) -> bool {
+        self.archive_peer.is_some()
+    }
+
+    /// Whether `peer` is the configured archive.
+    pub fn is_archive(&self, peer_id: &PeerId, display_name: &str) -> bool {
+        self.archive_peer
+            .as_deref()
+            .is_some_and(|archive| archive == peer_id.as_str() || archive == display_name)
+    }
+
+    /// The database size cap in bytes, if any.
+    pub fn max_db_bytes(&self) -> Option<u64> {
+        self.max_db_mb.map(|mb| mb * 1024 * 1024)
+    }
+}
+
+/// MQTT bridge for home automation (`[mqtt]`), off unless `broker` is set.
+///
+/// Received messages are published to `<prefix>/messages`, each peer's
+/// presence (retained) to `<prefix>/presence/<peer_id>`, and messages
+/// published to `<prefix>/send` are sent to peers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    /// Broker address, `host:port` or just `host` for port 1883. Plain
+    /// TCP, no TLS.
+    #[serde(default)]
+    pub broker: Option<String>,
+
+    /// First level of every topic.
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+
+    #[serde(default)]
+    pub username: Option<String>,
+
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            topic_prefix: default_topic_prefix(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+fn default_topic_prefix() -> String {
+    "familycom".to_string()
+}
+
+/// Email fallback (`[email]`), off unless `smtp_relay` is set.
+///
+/// A message still undelivered `after_hours` after it was sent is emailed,
+/// once, to the peer's address in `addresses`. Peers without an address
+/// are left alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailConfig {
+    /// SMTP relay, `host:port` or just `host` for port 25. Plain SMTP
+    /// without TLS or authentication, as a router or home server accepts
+    /// from the LAN: the emails, and the messages in them, can be read by
+    /// anyone on the way.
+    #[serde(default)]
+    pub smtp_relay: Option<String>,
+
+    /// Whether emails may go to a relay that isn't on this machine, in
+    /// plain text. Without it, only `localhost` or a loopback address is
+    /// used, and the fallback stays off otherwise.
+    #[serde(default)]
+    pub allow_remote_relay: bool,
+
+    /// Sender address of the emails.
+    #[serde(default = "default_email_from")]
+    pub from: String,
+
+    /// How long a message may stay undelivered before it's emailed.
+    #[serde(default = "default_email_after_hours")]
+    pub after_hours: u32,
+
+    /// Email address of each peer, by display name or peer ID.
+    #[serde(default)]
+    pub addresses: BTreeMap<String, String>,
+}
+
+impl EmailConfig {
+    /// Whether the fallback is on.
+    pub fn enabled(&self) -> bool {
+        self.smtp_relay.is_some()
+    }
+
+    /// Whether emails may be sent to `smtp_relay`: one on this machine,
+    /// or any with `allow_remote_relay`.
+    pub fn relay_allowed(&self) -> bool {
+        let Some(relay) = &self.smtp_relay else {
+            return false;
+        };
+        if self.allow_remote_relay {
+            return true;
+        }
+        // `host:port`, `host`, `[::1]:port` or `::1`
+        let host = match relay.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+                port.parse::<u16>().map_or(relay.as_str(), |_| host)
+            }
+            _ => relay,
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+
+    /// The address to email `peer` at, if it has one.
+    pub fn address_for(&self, peer_id: &PeerId, display_name: &str) -> Option<&str> {
+        self.addresses
+            .get(peer_id.as_str())
+            .or_else(|| self.addresses.get(display_name))
+            .map(String::as_str)
+    }
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            smtp_relay: None,
+            allow_remote_relay: false,
+            from: default_email_from(),
+            after_hours: default_email_after_hours(),
+            addresses: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_email_from() -> String {
+    "familycom@localhost".to_string()
+}
+
+fn default_email_after_hours() -> u32 {
+    4
+}
+
+/// Kid mode (`[kid_mode]`), for a child's machine: while `enabled`, the
+/// daemon only accepts messages from and sends messages to the peers in
+/// `allowed`. Everyone else is ignored, and flagged in the audit log.
+///
+/// Peers are listed by peer ID only. A display name is whatever the
+/// other machine announces, so it can't be trusted here.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KidModeConfig {
+    /// Whether the restriction applies.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Peer IDs this machine may talk to.
+    #[serde(default)]
+    pub allowed: Vec<String>,
+}
+
+impl KidModeConfig {
+    /// Whether messages may be exchanged with `peer_id`. Always true with
+    /// kid mode off.
+    pub fn allows(&self, peer_id: &PeerId) -> bool {
+        !self.enabled || self.allowed.iter().any(|id| id == peer_id.as_str())
+    }
+}
+
+/// Automatic away (`[away]`): after `idle_minutes` without keyboard or
+/// mouse input, peers are told we're away, and available again as soon as
+/// someone is back. Input is the desktop's idle time where it can be read,
+/// and keys pressed in the TUI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AwayConfig {
+    /// Minutes without input before we're shown as away; 0 never does.
+    #[serde(default = "default_away_idle_minutes")]
+    pub idle_minutes: u32,
+}
+
+impl AwayConfig {
+    /// How long without input makes us away, if automatic away is on.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_minutes > 0).then(|| Duration::from_secs(u64::from(self.idle_minutes) * 60))
+    }
+}
+
+impl Default for AwayConfig {
+    fn default() -> Self {
+        Self {
+            idle_minutes: default_away_idle_minutes(),
+        }
+    }
+}
+
+fn default_away_idle_minutes() -> u32 {
+    10
+}
+
+/// When the TUI rings the terminal bell for a received message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BellMode {
+    /// Only while the terminal doesn't have focus (default). Relies on the
+    /// terminal reporting focus changes; in tmux that needs
+    /// `set -g focus-events on`.
+    #[default]
+    Unfocused,
+    /// On every received message.
+    Always,
+    /// Never.
+    Never,
+}
+
+impl BellMode {
+    /// Whether a received message should ring the bell.
+    pub fn rings(self, focused: bool) -> bool {
+        match self {
+            BellMode::Unfocused => !focused,
+            BellMode::Always => true,
+            BellMode::Never => false,
+        }
+    }
+}
+
+/// A daily do-not-disturb window in local time, written `"HH:MM-HH:MM"`.
+///
+/// The start is included and the end isn't. A window whose end comes
+/// before its start runs past midnight, e.g. `"22:00-07:00"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// Error returned when parsing `QuietHours` from a string fails.
+#[derive(Debug, Error)]
+#[error("invalid quiet hours '{0}': expected HH:MM-HH:MM")]
+pub struct QuietHoursError(String);
+
+impl QuietHours {
+    /// Whether `time` falls inside the window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl std::str::FromStr for QuietHours {
+    type Err = QuietHoursError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || QuietHoursError(s.to_string());
+        let (start, end) = s.split_once('-').ok_or_else(error)?;
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| error());
+        Ok(Self {
+            start: time(start)?,
+            end: time(end)?,
+        })
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = QuietHoursError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<QuietHours> for String {
+    fn from(hours: QuietHours) -> Self {
+        hours.to_string()
+    }
+}
+
+impl std::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+/// The `network_interface` setting: one interface, a list, or `"all"`.
+///
+/// ```toml
+/// network_interface = "enp5s0"
+/// network_interface = ["enp5s0", "wlan0"]
+/// network_interface = "all"   # every interface with a private IPv4 address
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NetworkInterfaces {
+    /// One interface by name, or `"all"`.
+    One(String),
+    /// Several interfaces by name.
+    List(Vec<String>),
+}
+
+impl NetworkInterfaces {
+    /// The value meaning every LAN interface.
+    pub const ALL: &'static str = "all";
+
+    /// Parses the form `Display` writes: `all`, or comma-separated names.
+    pub fn parse(s: &str) -> Self {
+        let mut names: Vec<String> = s
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if names.len() == 1 {
+            Self::One(names.remove(0))
+        } else {
+            Self::List(names)
+        }
+    }
+
+    /// Whether this means every LAN interface rather than named ones.
+    pub fn is_all(&self) -> bool {
+        matches!(self, Self::One(name) if name.eq_ignore_ascii_case(Self::ALL))
+    }
+
+    /// The named interfaces; empty for `"all"`.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            _ if self.is_all() => Vec::new(),
+            Self::One(name) => vec![name.as_str()],
+            Self::List(names) => names.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// The interface that identifies the network we're on (its gateway and
+    /// Wi-Fi name): the first one named, or `None` for the default-route
+    /// interface.
+    pub fn primary(&self) -> Option<&str> {
+        self.names().first().copied()
+    }
+}
+
+impl std::fmt::Display for NetworkInterfaces {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::One(name) => f.write_str(name),
+            Self::List(names) => f.write_str(&names.join(", ")),
+        }
+    }
+}
+
+/// A plain `http://` URL for `webhook_url`, split into what a request
+/// needs. There's no TLS, so `https://` is refused: point it at a service
+/// on the LAN (Home Assistant, a self-hosted ntfy).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct WebhookUrl {
+    /// Host name or IP address (IPv6 without brackets).
+    pub host: String,
+    pub port: u16,
+    /// Path and query, starting with `/`.
+    pub path: String,
+}
+
+/// Error returned when parsing a `WebhookUrl` from a string fails.
+#[derive(Debug, Error)]
+pub enum WebhookUrlError {
+    #[error("invalid webhook URL '{0}': only http:// is supported")]
+    Scheme(String),
+
+    #[error("invalid webhook URL '{0}': expected http://host[:port][/path]")]
+    Malformed(String),
+}
+
+impl WebhookUrl {
+    /// The `Host` header value: the host, bracketed if IPv6, and the port
+    /// unless it's 80.
+    pub fn authority(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        match self.port {
+            80 => host,
+            port => format!("{host}:{port}"),
+        }
+    }
+}
+
+impl std::str::FromStr for WebhookUrl {
+    type Err = WebhookUrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || WebhookUrlError::Malformed(s.to_string());
+        let rest = s
+            .trim()
+            .strip_prefix("http://")
+            .ok_or_else(|| WebhookUrlError::Scheme(s.to_string()))?;
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+            Some(i) => (&rest[..i], rest[i..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        let (host, port) = match authority.strip_prefix('[') {
+            // [v6]:port
+            Some(bracketed) => {
+                let (host, after) = bracketed.split_once(']').ok_or_else(malformed)?;
+                (host, after.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        if host.is_empty() || host.contains('@') {
+            return Err(malformed());
+        }
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| malformed())?,
+            None => 80,
+        };
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+impl TryFrom<String> for WebhookUrl {
+    type Error = WebhookUrlError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<WebhookUrl> for String {
+    fn from(url: WebhookUrl) -> Self {
+        url.to_string()
+    }
+}
+
+impl std::fmt::Display for WebhookUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.path)
+    }
+}
+
+/// Settings the daemon only reads at startup.
+pub const RESTART_REQUIRED: &[&str] = &["peer_id", "tcp_port", "ipc_listen", "http_listen"];
+
+/// The outcome of re-reading the config file while the daemon runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigReload {
+    /// The config to run with from now on: the new file, except for
+    /// settings in `RESTART_REQUIRED`, which keep their running values.
+    pub config: AppConfig,
+    /// Changed settings that take effect right away.
+    pub applied: Vec<&'static str>,
+    /// Changed settings that need a daemon restart.
+    pub restart_required: Vec<&'static str>,
 }
 
 impl AppConfig {
@@ -84,6 +1014,7 @@ impl AppConfig {
     ///
     /// - Linux: `~/.config/familycom/`
     /// - macOS: `~/Library/Application Support/familycom/`
+    /// - Windows: `%APPDATA%\familycom\`
     ///
     /// Returns `None` if the platform's config directory can't be determined
     /// (very rare — would mean $HOME is not set).
@@ -102,6 +1033,7 @@ impl AppConfig {
     ///
     /// - Linux: `~/.local/share/familycom/`
     /// - macOS: `~/Library/Application Support/familycom/`
+    /// - Windows: `%APPDATA%\familycom\`
     pub fn data_dir() -> Option<PathBuf> {
         dirs::data_dir().map(|d| d.join("familycom"))
     }
@@ -129,6 +1061,13 @@ impl AppConfig {
         }
     }
 
+    /// Returns the default named pipe used for IPC on Windows, which has
+    /// no Unix sockets: `\\.\pipe\familycom-{user}`.
+    pub fn default_pipe_name() -> String {
+        let user = std::env::var("USERNAME").unwrap_or_else(|_| "unknown".to_string());
+        format!(r"\\.\pipe\familycom-{user}")
+    }
+
     /// Loads the config from the default config file path.
     ///
     /// Returns `Ok(None)` if the config file doesn't exist yet (first run).
@@ -185,6 +1124,54 @@ impl AppConfig {
         Ok(())
     }
 
+    /// Names (as in config.toml) of the settings that differ from `other`.
+    pub fn changed_settings(&self, other: &AppConfig) -> Vec<&'static str> {
+        let changes = [
+            ("peer_id", self.peer_id != other.peer_id),
+            ("display_name", self.display_name != other.display_name),
+            ("tcp_port", self.tcp_port != other.tcp_port),
+            ("terminal_command", self.terminal_command != other.terminal_command),
+            ("network_interface", self.network_interface != other.network_interface),
+            ("notifications_enabled", self.notifications_enabled != other.notifications_enabled),
+            ("avatar", self.avatar != other.avatar),
+            ("accent_color", self.accent_color != other.accent_color),
+            ("downloads", self.downloads != other.downloads),
+            ("networks", self.networks != other.networks),
+            ("ipc_listen", self.ipc_listen != other.ipc_listen),
+            ("http_listen", self.http_listen != other.http_listen),
+            ("delivery", self.delivery != other.delivery),
+            ("terminal", self.terminal != other.terminal),
+            ("storage", self.storage != other.storage),
+            ("quiet_hours", self.quiet_hours != other.quiet_hours),
+            ("webhook_url", self.webhook_url != other.webhook_url),
+            ("mqtt", self.mqtt != other.mqtt),
+            ("email", self.email != other.email),
+            ("kid_mode", self.kid_mode != other.kid_mode),
+            ("away", self.away != other.away),
+        ];
+        changes.into_iter().filter(|(_, changed)| *changed).map(|(name, _)| name).collect()
+    }
+
+    /// Applies a freshly loaded `new` config on top of the running one.
+    pub fn reload(&self, new: AppConfig) -> ConfigReload {
+        let (restart_required, applied) = self
+            .changed_settings(&new)
+            .into_iter()
+            .partition(|name| RESTART_REQUIRED.contains(name));
+        let config = AppConfig {
+            peer_id: self.peer_id.clone(),
+            tcp_port: self.tcp_port,
+            ipc_listen: self.ipc_listen.clone(),
+            http_listen: self.http_listen,
+            ..new
+        };
+        ConfigReload {
+            config,
+            applied,
+            restart_required,
+        }
+    }
+
     /// Creates a new config for first-run with a fresh peer ID.
     pub fn new_first_run(display_name: &str) -> Self {
         Self {
@@ -193,6 +1180,22 @@ impl AppConfig {
             tcp_port: 0,
             terminal_command: None,
             network_interface: None,
+            notifications_enabled: true,
+            avatar: None,
+            accent_color: None,
+            downloads: DownloadConfig::default(),
+            networks: NetworkConfig::default(),
+            ipc_listen: Vec::new(),
+            http_listen: None,
+            delivery: DeliveryConfig::default(),
+            terminal: TerminalConfig::default(),
+            storage: StorageConfig::default(),
+            quiet_hours: None,
+            webhook_url: None,
+            mqtt: MqttConfig::default(),
+            email: EmailConfig::default(),
+            kid_mode: KidModeConfig::default(),
+            away: AwayConfig::default(),
         }
     }
 }
@@ -218,6 +1221,22 @@ mod tests {
             tcp_port: 9876,
             terminal_command: None,
             network_interface: None,
+            notifications_enabled: true,
+            avatar: None,
+            accent_color: None,
+            downloads: DownloadConfig::default(),
+            networks: NetworkConfig::default(),
+            ipc_listen: Vec::new(),
+            http_listen: None,
+            delivery: DeliveryConfig::default(),
+            terminal: TerminalConfig::default(),
+            storage: StorageConfig::default(),
+            quiet_hours: None,
+            webhook_url: None,
+            mqtt: MqttConfig::default(),
+            email: EmailConfig::default(),
+            kid_mode: KidModeConfig::default(),
+            away: AwayConfig::default(),
         };
 
         config.save_to(&path).unwrap();
@@ -258,6 +1277,22 @@ mod tests {
             tcp_port: 0,
             terminal_command: None,
             network_interface: None,
+            notifications_enabled: true,
+            avatar: None,
+            accent_color: None,
+            downloads: DownloadConfig::default(),
+            networks: NetworkConfig::default(),
+            ipc_listen: Vec::new(),
+            http_listen: None,
+            delivery: DeliveryConfig::default(),
+            terminal: TerminalConfig::default(),
+            storage: StorageConfig::default(),
+            quiet_hours: None,
+            webhook_url: None,
+            mqtt: MqttConfig::default(),
+            email: EmailConfig::default(),
+            kid_mode: KidModeConfig::default(),
+            away: AwayConfig::default(),
         };
 
         config.save_to(&path).unwrap();
@@ -265,10 +1300,409 @@ mod tests {
         assert_eq!(loaded.display_name, "Habitación de Mamá");
     }
 
+    #[test]
+    fn quiet_hours_can_span_midnight() {
+        let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let night: QuietHours = "22:00-07:00".parse().unwrap();
+        assert!(night.contains(at(23, 30)) && night.contains(at(0, 0)) && night.contains(at(6, 59)));
+        assert!(!night.contains(at(7, 0)) && !night.contains(at(12, 0)));
+
+        let nap: QuietHours = "13:30 - 15:00".parse().unwrap();
+        assert!(nap.contains(at(13, 30)) && !nap.contains(at(15, 0)));
+        assert!("22:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+            quiet_hours = "22:00-07:00"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.quiet_hours, Some(night));
+        assert!(toml::to_string(&config).unwrap().contains(r#"quiet_hours = "22:00-07:00""#));
+    }
+
+    #[test]
+    fn webhook_urls_are_plain_http() {
+        let url: WebhookUrl = "http://homeassistant.local:8123/api/webhook/familycom".parse().unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("homeassistant.local", 8123));
+        assert_eq!(url.path, "/api/webhook/familycom");
+
+        let bare: WebhookUrl = "http://192.168.1.5".parse().unwrap();
+        assert_eq!((bare.port, bare.path.as_str()), (80, "/"));
+        assert_eq!(bare.to_string(), "http://192.168.1.5/");
+        let v6: WebhookUrl = "http://[fe80::1]:8080/hook?x=1".parse().unwrap();
+        assert_eq!((v6.host.as_str(), v6.authority()), ("fe80::1", "[fe80::1]:8080".to_string()));
+        assert_eq!(v6.path, "/hook?x=1");
+
+        assert!(matches!(
+            "https://ntfy.sh/familia".parse::<WebhookUrl>(),
+            Err(WebhookUrlError::Scheme(_))
+        ));
+        assert!("http://:80/".parse::<WebhookUrl>().is_err());
+        assert!("http://host:99999/".parse::<WebhookUrl>().is_err());
+
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+            webhook_url = "http://ntfy.lan/familia"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.webhook_url.map(|url| url.to_string()).as_deref(), Some("http://ntfy.lan/familia"));
+    }
+
+    #[test]
+    fn notifications_enabled_defaults_to_true() {
+        // Config files written before the setting existed must keep notifications on
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+            "#,
+        )
+        .unwrap();
+        assert!(config.notifications_enabled);
+    }
+
+    #[test]
+    fn invalid_accent_color_rejected() {
+        let result: Result<AppConfig, _> = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+            accent_color = "orange"
+            "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn download_settings_from_toml() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+
+            [downloads]
+            root = "/srv/familia"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.downloads.root_dir(), Some(PathBuf::from("/srv/familia")));
+        assert!(config.downloads.per_peer_folders);
+    }
+
+    #[test]
+    fn terminal_settings_from_toml() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+
+            [terminal]
+            bell = "always"
+            "#,
+        )
+        .unwrap();
+        assert!(config.terminal.title);
+        assert_eq!(config.terminal.bell, BellMode::Always);
+        assert!(config.terminal.bell.rings(true));
+        assert!(!BellMode::Unfocused.rings(true));
+        assert!(BellMode::Unfocused.rings(false));
+    }
+
+    #[test]
+    fn storage_settings_from_toml() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "Pi"
+
+            [storage]
+            archive_peer = "NAS"
+            keep_days = 30
+            max_db_mb = 50
+            "#,
+        )
+        .unwrap();
+        assert!(config.storage.low_storage());
+        assert!(!config.storage.archiver);
+        assert!(config.storage.is_archive(&PeerId::new("nas-id"), "NAS"));
+        assert!(!config.storage.is_archive(&PeerId::new("sala-id"), "Sala"));
+        assert_eq!(config.storage.max_db_bytes(), Some(50 * 1024 * 1024));
+        assert_eq!(config.storage.truncate_chars, None);
+
+        // Off unless an archive is configured
+        assert!(!StorageConfig::default().low_storage());
+    }
+
+    #[test]
+    fn email_settings_from_toml() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "Sala"
+
+            [email]
+            smtp_relay = "192.168.1.1"
+
+            [email.addresses]
+            "PC-Abuela" = "abuela@example.com"
+            "taller-id" = "tio@example.com"
+            "#,
+        )
+        .unwrap();
+        assert!(config.email.enabled());
+        assert_eq!(config.email.after_hours, 4);
+        assert_eq!(config.email.from, "familycom@localhost");
+        let abuela = config.email.address_for(&PeerId::new("abuela-id"), "PC-Abuela");
+        assert_eq!(abuela, Some("abuela@example.com"));
+        let taller = config.email.address_for(&PeerId::new("taller-id"), "Taller");
+        assert_eq!(taller, Some("tio@example.com"));
+        assert_eq!(config.email.address_for(&PeerId::new("cocina-id"), "Cocina"), None);
+
+        assert!(!EmailConfig::default().enabled());
+    }
+
+    #[test]
+    fn email_only_goes_to_a_remote_relay_when_allowed() {
+        let email = |relay: &str| EmailConfig {
+            smtp_relay: Some(relay.to_string()),
+            ..EmailConfig::default()
+        };
+        for local in ["localhost", "localhost:2525", "127.0.0.1", "127.0.0.1:25", "::1", "[::1]:25"] {
+            assert!(email(local).relay_allowed(), "{local}");
+        }
+        for remote in ["192.168.1.1", "192.168.1.1:25", "router.lan:25", "fe80::1", "[fe80::1]:25"] {
+            assert!(!email(remote).relay_allowed(), "{remote}");
+            let allowed = EmailConfig {
+                allow_remote_relay: true,
+                ..email(remote)
+            };
+            assert!(allowed.relay_allowed(), "{remote}");
+        }
+        assert!(!EmailConfig::default().relay_allowed());
+    }
+
+    #[test]
+    fn kid_mode_only_allows_listed_peer_ids() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "Cuarto de Sofi"
+
+            [kid_mode]
+            enabled = true
+            allowed = ["mama-id", "papa-id"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.kid_mode.allows(&PeerId::new("mama-id")));
+        assert!(!config.kid_mode.allows(&PeerId::new("vecino-id")));
+
+        // Listed but off: everyone is allowed
+        let off = KidModeConfig {
+            enabled: false,
+            ..config.kid_mode
+        };
+        assert!(off.allows(&PeerId::new("vecino-id")));
+        assert!(KidModeConfig::default().allows(&PeerId::new("vecino-id")));
+    }
+
+    #[test]
+    fn away_defaults_to_ten_minutes_and_zero_turns_it_off() {
+        assert_eq!(AwayConfig::default().idle_timeout(), Some(Duration::from_secs(600)));
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "Estudio"
+
+            [away]
+            idle_minutes = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.away.idle_timeout(), None);
+    }
+
+    #[test]
+    fn network_interface_accepts_one_several_or_all() {
+        let parse = |value: &str| -> NetworkInterfaces {
+            let config: AppConfig =
+                toml::from_str(&format!("peer_id = \"id\"\ndisplay_name = \"Sala\"\nnetwork_interface = {value}"))
+                    .unwrap();
+            config.network_interface.unwrap()
+        };
+
+        let one = parse(r#""enp5s0""#);
+        assert_eq!(one.names(), ["enp5s0"]);
+        let several = parse(r#"["enp5s0", "wlan0"]"#);
+        assert_eq!(several.names(), ["enp5s0", "wlan0"]);
+        assert_eq!(several.primary(), Some("enp5s0"));
+        let all = parse(r#""all""#);
+        assert!(all.is_all());
+        assert!(all.names().is_empty());
+        assert_eq!(all.primary(), None);
+
+        // The setup wizard round-trips through the displayed form
+        assert_eq!(NetworkInterfaces::parse(&several.to_string()), several);
+        assert_eq!(NetworkInterfaces::parse(&one.to_string()), one);
+    }
+
+    #[test]
+    fn reload_keeps_restart_only_settings() {
+        let running = AppConfig::new_first_run("Sala");
+        let mut file = running.clone();
+        file.display_name = "Sala de estar".to_string();
+        file.notifications_enabled = false;
+        file.tcp_port = 9876;
+
+        let reload = running.reload(file);
+        assert_eq!(reload.applied, vec!["display_name", "notifications_enabled"]);
+        assert_eq!(reload.restart_required, vec!["tcp_port"]);
+        assert_eq!(reload.config.display_name, "Sala de estar");
+        assert_eq!(reload.config.tcp_port, running.tcp_port);
+
+        let unchanged = running.reload(running.clone());
+        assert!(unchanged.applied.is_empty() && unchanged.restart_required.is_empty());
+    }
+
+    #[test]
+    fn any_network_allowed_unless_trust_required() {
+        let cafe = NetworkFingerprint {
+            gateway_mac: Some("00:11:22:33:44:55".to_string()),
+            ssid: Some("Cafe-WiFi".to_string()),
+        };
+        assert!(NetworkConfig::default().allows(&cafe));
+    }
+
+    #[test]
+    fn trusted_networks_match_by_mac_or_ssid() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+
+            [networks]
+            require_trusted = true
+
+            [[networks.trusted]]
+            name = "Casa"
+            gateway_mac = "A4-2B-B0-12-34-56"
+
+            [[networks.trusted]]
+            name = "Abuela"
+            ssid = "Familia Pérez"
+            "#,
+        )
+        .unwrap();
+        let networks = &config.networks;
+
+        let home = NetworkFingerprint {
+            gateway_mac: Some("a4:2b:b0:12:34:56".to_string()),
+            ssid: None,
+        };
+        assert_eq!(networks.matching(&home).map(|t| t.name.as_str()), Some("Casa"));
+
+        let grandma = NetworkFingerprint {
+            gateway_mac: Some("de:ad:be:ef:00:01".to_string()),
+            ssid: Some("Familia Pérez".to_string()),
+        };
+        assert_eq!(networks.matching(&grandma).map(|t| t.name.as_str()), Some("Abuela"));
+
+        let cafe = NetworkFingerprint {
+            gateway_mac: Some("00:11:22:33:44:55".to_string()),
+            ssid: Some("Cafe-WiFi".to_string()),
+        };
+        assert!(!networks.allows(&cafe));
+        // Nothing detected at all: not trusted either
+        assert!(!networks.allows(&NetworkFingerprint::default()));
+    }
+
+    #[test]
+    fn ipc_listen_endpoints_from_toml() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+            ipc_listen = ["tcp:127.0.0.1:7878"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.ipc_listen, vec![IpcEndpoint::Tcp("127.0.0.1:7878".parse().unwrap())]);
+
+        // A LAN address is a config error, not a silently exposed socket
+        let result: Result<AppConfig, _> = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+            ipc_listen = ["tcp:192.168.1.10:7878"]
+            "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn delivery_mode_per_peer() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            peer_id = "id"
+            display_name = "PC"
+
+            [delivery.peers]
+            "Timbre" = "fire_and_forget"
+            "peer-42" = "ack_required"
+            "#,
+        )
+        .unwrap();
+        let delivery = &config.delivery;
+
+        assert_eq!(delivery.default, DeliveryMode::AckRequired);
+        assert_eq!(
+            delivery.mode_for(&PeerId::new("peer-7"), Some("Timbre")),
+            DeliveryMode::FireAndForget
+        );
+        assert_eq!(
+            delivery.mode_for(&PeerId::new("peer-8"), Some("PC-Sala")),
+            DeliveryMode::AckRequired
+        );
+        // The peer ID entry takes precedence over the name
+        assert_eq!(
+            delivery.mode_for(&PeerId::new("peer-42"), Some("Timbre")),
+            DeliveryMode::AckRequired
+        );
+    }
+
     #[test]
     fn first_run_generates_unique_ids() {
         let a = AppConfig::new_first_run("A");
         let b = AppConfig::new_first_run("B");
         assert_ne!(a.peer_id, b.peer_id);
     }
+
+    #[test]
+    fn tui_config_roundtrip_and_defaults() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("tui.toml");
+        assert_eq!(TuiConfig::load_from(&path).unwrap(), TuiConfig::default());
+
+        let config = TuiConfig {
+            peer_list_hidden: true,
+            peer_list_width: 40,
+            vim_mode: true,
+            relative_times: true,
+        };
+        config.save_to(&path).unwrap();
+        assert_eq!(TuiConfig::load_from(&path).unwrap(), config);
+
+        // Hand-edited to something unusable
+        std::fs::write(&path, "peer_list_width = 95\n").unwrap();
+        let loaded = TuiConfig::load_from(&path).unwrap();
+        assert!(!loaded.peer_list_hidden);
+        assert_eq!(loaded.peer_list_width, 60);
+    }
 }
@@ -19,11 +19,67 @@
 //! - Built-in full UTF-8 support
 //! - With the `bundled` feature, rusqlite compiles SQLite from source,
 //!   so no system library is needed.
-
-use crate::types::{Direction, Message, MessageId, PeerId, PeerInfo, Timestamp};
-use rusqlite::{params, Connection, OptionalExtension};
-use std::path::Path;
+//!
+//! # Corruption Recovery
+//!
+//! A power cut or a full disk can leave the file damaged. `open_or_recover`
+//! runs `PRAGMA integrity_check` on open; if the file is corrupt it is
+//! renamed to `familycom.db.corrupt-<millis>`, a fresh database is created
+//! in its place, and every row that can still be read is copied across.
+//! The caller gets a `RecoveryReport` describing what happened.
+
+use crate::types::{
+    AccentColor, AuditAction, AuditEntry, Avatar, Capability, ConversationSummary, Direction, Message,
+    MessageCursor, MessageId, MessageNote, NoteMatch, NotificationPrefs, PageDirection, PeerId,
+    PeerInfo, ScheduledMessage, Timestamp,
+};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, ErrorCode, OptionalExtension};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
+use tracing::warn;
+
+/// Schema of the `messages` table and its indexes.
+///
+/// Kept separate from `migrate`'s main batch because older databases need
+/// the table rebuilt when its CHECK constraint changes (see
+/// `allow_system_messages`).
+const MESSAGES_SCHEMA: &str = "
+    -- Chat messages (sent, received, and daemon-generated)
+    CREATE TABLE IF NOT EXISTS messages (
+        id        TEXT PRIMARY KEY,
+        peer_id   TEXT NOT NULL,
+        direction TEXT NOT NULL CHECK(direction IN ('sent', 'received', 'system')),
+        content   TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        delivered INTEGER NOT NULL DEFAULT 0,
+        fire_and_forget INTEGER NOT NULL DEFAULT 0,
+        FOREIGN KEY (peer_id) REFERENCES peers(id)
+    );
+
+    -- Index for fetching messages with a specific peer, newest first
+    CREATE INDEX IF NOT EXISTS idx_messages_peer_time
+        ON messages(peer_id, timestamp DESC);
+
+    -- Index for fetching all recent messages across all peers
+    CREATE INDEX IF NOT EXISTS idx_messages_timestamp
+        ON messages(timestamp DESC);
+";
+
+/// Tables copied during salvage, parents before children.
+const SALVAGE_TABLES: [&str; 10] = [
+    "config",
+    "peers",
+    "peer_notifications",
+    "messages",
+    "message_notes",
+    "read_state",
+    "peer_read_state",
+    "audit_log",
+    "archive",
+    "scheduled_messages",
+];
 
 /// Errors that can occur during database operations.
 #[derive(Debug, Error)]
@@ -33,6 +89,47 @@ pub enum DatabaseError {
 
     #[error("invalid data in database: {0}")]
     InvalidData(String),
+
+    #[error("database failed integrity check: {0}")]
+    Corrupt(String),
+
+    #[error("failed to move corrupt database aside: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl DatabaseError {
+    /// Returns true if this error means the file itself is damaged (as
+    /// opposed to e.g. a permissions problem), so recovery is worth trying.
+    pub fn is_corruption(&self) -> bool {
+        match self {
+            Self::Corrupt(_) => true,
+            Self::Sqlite(rusqlite::Error::SqliteFailure(err, _)) => {
+                matches!(err.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the row being inserted already exists, e.g. a chat
+    /// message the sender retried after its ACK was lost.
+    pub fn is_duplicate(&self) -> bool {
+        matches!(
+            self,
+            Self::Sqlite(rusqlite::Error::SqliteFailure(err, _))
+                if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY
+        )
+    }
+}
+
+/// What `Database::open_or_recover` did with a corrupt database file.
+#[derive(Debug, Clone)]
+pub struct RecoveryReport {
+    /// Where the corrupt file was moved to (kept for manual inspection).
+    pub corrupt_path: PathBuf,
+    /// Why the file was considered corrupt.
+    pub reason: String,
+    /// How many rows were copied into the fresh database.
+    pub salvaged_rows: u64,
 }
 
 /// The database handle wrapping a SQLite connection.
@@ -70,10 +167,30 @@ impl Database {
         conn.pragma_update(None, "foreign_keys", "ON")?;
 
         let db = Self { conn };
+        db.integrity_check()?;
         db.migrate()?;
         Ok(db)
     }
 
+    /// Opens the database like `open`, recovering from corruption.
+    ///
+    /// If the file is damaged, it is moved aside, a fresh database is
+    /// created at `path`, and readable rows are salvaged into it. Returns
+    /// the report alongside the database so the daemon can tell the user
+    /// instead of refusing to start. Other errors (permissions, disk) are
+    /// returned unchanged.
+    pub fn open_or_recover(path: &Path) -> Result<(Self, Option<RecoveryReport>), DatabaseError> {
+        match Self::open(path) {
+            Ok(db) => Ok((db, None)),
+            Err(e) if e.is_corruption() => {
+                warn!(path = %path.display(), error = %e, "database is corrupt, recovering");
+                let report = Self::recover(path, e.to_string())?;
+                Ok((Self::open(path)?, Some(report)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Opens an in-memory database (useful for tests).
     pub fn open_in_memory() -> Result<Self, DatabaseError> {
         let conn = Connection::open_in_memory()?;
@@ -102,29 +219,235 @@ impl Database {
                 id            TEXT PRIMARY KEY,
                 display_name  TEXT NOT NULL,
                 last_seen_at  INTEGER NOT NULL,
-                addresses     TEXT NOT NULL  -- JSON array of 'ip:port' strings
+                addresses     TEXT NOT NULL,  -- JSON array of 'ip:port' strings
+                avatar        TEXT,           -- optional emoji
+                accent_color  TEXT,           -- optional '#rrggbb'
+                capabilities  TEXT            -- comma-separated, see Capability
             );
 
-            -- Chat messages (both sent and received)
-            CREATE TABLE IF NOT EXISTS messages (
-                id        TEXT PRIMARY KEY,
+            -- Per-peer notification settings, kept apart from `peers`
+            -- because discovery rewrites those rows wholesale
+            CREATE TABLE IF NOT EXISTS peer_notifications (
+                peer_id TEXT PRIMARY KEY,
+                muted   INTEGER NOT NULL DEFAULT 0,
+                sound   INTEGER NOT NULL DEFAULT 1
+            );
+
+            -- Per-conversation read watermark: everything received at or
+            -- before last_read_at counts as read
+            CREATE TABLE IF NOT EXISTS read_state (
+                peer_id      TEXT PRIMARY KEY,
+                last_read_at INTEGER NOT NULL
+            );
+
+            -- How far each peer has read what we sent them, from their
+            -- read receipts (same clock as our messages' timestamps)
+            CREATE TABLE IF NOT EXISTS peer_read_state (
+                peer_id    TEXT PRIMARY KEY,
+                read_up_to INTEGER NOT NULL
+            );
+
+            -- Settings changes (renames, etc.), newest looked up first
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id        INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                action    TEXT NOT NULL,
+                peer_id   TEXT,           -- NULL when it concerns this machine
+                detail    TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp
+                ON audit_log(timestamp DESC);
+
+            -- Private notes on messages, one per message. Never sent to
+            -- peers. No foreign key: the messages table may be rebuilt by a
+            -- migration (see `allow_system_messages`), which would rewrite
+            -- the reference.
+            CREATE TABLE IF NOT EXISTS message_notes (
+                message_id TEXT PRIMARY KEY,
+                note       TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+
+            -- Other daemons' history, when this one is the household
+            -- archive. `owner_id` is the daemon it came from and `peer_id`
+            -- the other side of the conversation, as the owner stores it.
+            -- Never pruned.
+            CREATE TABLE IF NOT EXISTS archive (
+                owner_id  TEXT NOT NULL,
+                id        TEXT NOT NULL,
                 peer_id   TEXT NOT NULL,
-                direction TEXT NOT NULL CHECK(direction IN ('sent', 'received')),
+                direction TEXT NOT NULL,
                 content   TEXT NOT NULL,
                 timestamp INTEGER NOT NULL,
                 delivered INTEGER NOT NULL DEFAULT 0,
-                FOREIGN KEY (peer_id) REFERENCES peers(id)
+                fire_and_forget INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (owner_id, id)
             );
 
-            -- Index for fetching messages with a specific peer, newest first
-            CREATE INDEX IF NOT EXISTS idx_messages_peer_time
-                ON messages(peer_id, timestamp DESC);
+            CREATE INDEX IF NOT EXISTS idx_archive_conversation
+                ON archive(owner_id, peer_id, timestamp DESC);
+
+            -- Messages to send later. A row is removed once the message is
+            -- dispatched and stored in `messages`.
+            CREATE TABLE IF NOT EXISTS scheduled_messages (
+                id      INTEGER PRIMARY KEY AUTOINCREMENT,
+                peer_id TEXT NOT NULL,
+                content TEXT NOT NULL,
+                send_at INTEGER NOT NULL
+            );
 
-            -- Index for fetching all recent messages across all peers
-            CREATE INDEX IF NOT EXISTS idx_messages_timestamp
-                ON messages(timestamp DESC);
+            CREATE INDEX IF NOT EXISTS idx_scheduled_messages_send_at
+                ON scheduled_messages(send_at);
             ",
         )?;
+
+        self.conn.execute_batch(MESSAGES_SCHEMA)?;
+
+        // Columns added after the first release. `CREATE TABLE IF NOT EXISTS`
+        // doesn't touch existing tables, so older databases need them added.
+        self.add_column_if_missing("peers", "avatar", "TEXT")?;
+        self.add_column_if_missing("peers", "accent_color", "TEXT")?;
+        self.add_column_if_missing("peers", "capabilities", "TEXT")?;
+        self.allow_system_messages()?;
+        self.add_column_if_missing("messages", "fire_and_forget", "INTEGER NOT NULL DEFAULT 0")?;
+        // Set once the message is stored on the archive peer (low-storage mode)
+        self.add_column_if_missing("messages", "archived", "INTEGER NOT NULL DEFAULT 0")?;
+        // Set once an undelivered message has been emailed (`[email]`)
+        self.add_column_if_missing("messages", "emailed", "INTEGER NOT NULL DEFAULT 0")?;
+        // Sent to the whole house at once (`Announce`)
+        self.add_column_if_missing("messages", "announcement", "INTEGER NOT NULL DEFAULT 0")?;
+        self.add_column_if_missing("archive", "announcement", "INTEGER NOT NULL DEFAULT 0")?;
+        // The message a reply answers
+        self.add_column_if_missing("messages", "reply_to", "TEXT")?;
+        self.add_column_if_missing("archive", "reply_to", "TEXT")?;
+        // Set while an outgoing message waits for its first send attempt
+        self.add_column_if_missing("messages", "pending", "INTEGER NOT NULL DEFAULT 0")?;
+        Ok(())
+    }
+
+    /// Rebuilds a `messages` table created before `Direction::System` existed.
+    ///
+    /// SQLite can't alter a CHECK constraint in place, so the table is
+    /// renamed, recreated from `MESSAGES_SCHEMA`, and refilled, all in one
+    /// transaction.
+    fn allow_system_messages(&self) -> Result<(), DatabaseError> {
+        let sql: String = self.conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'messages'",
+            [],
+            |row| row.get(0),
+        )?;
+        if sql.contains("'system'") {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute_batch("ALTER TABLE messages RENAME TO messages_old")?;
+        // The old indexes moved with the renamed table; drop them so the
+        // schema can recreate them on the new one.
+        tx.execute_batch(
+            "DROP INDEX IF EXISTS idx_messages_peer_time;
+             DROP INDEX IF EXISTS idx_messages_timestamp;",
+        )?;
+        tx.execute_batch(MESSAGES_SCHEMA)?;
+        tx.execute_batch(
+            "INSERT INTO messages (id, peer_id, direction, content, timestamp, delivered)
+                 SELECT id, peer_id, direction, content, timestamp, delivered FROM messages_old;
+             DROP TABLE messages_old;",
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Runs `PRAGMA integrity_check`, which returns the single row "ok"
+    /// for a healthy file and one row per problem otherwise.
+    fn integrity_check(&self) -> Result<(), DatabaseError> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let problems = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if problems.len() == 1 && problems[0] == "ok" {
+            return Ok(());
+        }
+        // The list can be long; the first few are enough to explain it
+        Err(DatabaseError::Corrupt(
+            problems.into_iter().take(3).collect::<Vec<_>>().join("; "),
+        ))
+    }
+
+    /// Moves the corrupt file at `path` aside and salvages what it can
+    /// into a fresh database at `path`.
+    ///
+    /// This is a best-effort version of the sqlite3 shell's `.recover`:
+    /// each table is read row by row, and reading stops at the first
+    /// damaged page. Whatever was read before that is kept.
+    fn recover(path: &Path, reason: String) -> Result<RecoveryReport, DatabaseError> {
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("familycom.db");
+        let corrupt_path = path.with_file_name(format!(
+            "{file_name}.corrupt-{}",
+            Timestamp::now().as_millis()
+        ));
+
+        // The WAL holds committed transactions not yet checkpointed into
+        // the main file, so it has to travel with it.
+        std::fs::rename(path, &corrupt_path)?;
+        for suffix in ["-wal", "-shm"] {
+            let sidecar = PathBuf::from(format!("{}{suffix}", path.display()));
+            if sidecar.exists() {
+                std::fs::rename(&sidecar, format!("{}{suffix}", corrupt_path.display()))?;
+            }
+        }
+
+        let fresh = Self::open(path)?;
+        let salvaged_rows = match Connection::open(&corrupt_path) {
+            Ok(old) => fresh.salvage_from(&old)?,
+            Err(e) => {
+                warn!(error = %e, "could not open corrupt database for salvage");
+                0
+            }
+        };
+
+        Ok(RecoveryReport {
+            corrupt_path,
+            reason,
+            salvaged_rows,
+        })
+    }
+
+    /// Copies every readable row of the known tables from `old`.
+    fn salvage_from(&self, old: &Connection) -> Result<u64, DatabaseError> {
+        // A message whose peer row was lost is still worth keeping
+        self.conn.pragma_update(None, "foreign_keys", "OFF")?;
+        let tx = self.conn.unchecked_transaction()?;
+        let mut total = 0;
+        for table in SALVAGE_TABLES {
+            let copied = salvage_table(old, &tx, table);
+            if copied > 0 {
+                warn!(table, rows = copied, "salvaged rows from corrupt database");
+            }
+            total += copied;
+        }
+        tx.commit()?;
+        self.conn.pragma_update(None, "foreign_keys", "ON")?;
+        Ok(total)
+    }
+
+    /// Adds a column to an existing table unless it's already there.
+    ///
+    /// SQLite has no `ADD COLUMN IF NOT EXISTS`, so we check
+    /// `PRAGMA table_info` first. Table and column names come from our own
+    /// constants (never user input), so formatting them into SQL is safe.
+    fn add_column_if_missing(&self, table: &str, column: &str, decl: &str) -> Result<(), DatabaseError> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({table})"))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .any(|name| name == column);
+        if !exists {
+            self.conn
+                .execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+        }
         Ok(())
     }
 
@@ -170,26 +493,51 @@ impl Database {
             .map_err(|e| DatabaseError::InvalidData(format!("failed to serialize addresses: {e}")))?;
 
         self.conn.execute(
-            "INSERT OR REPLACE INTO peers (id, display_name, last_seen_at, addresses)
-             VALUES (?1, ?2, ?3, ?4)",
+            "INSERT OR REPLACE INTO peers
+                 (id, display_name, last_seen_at, addresses, avatar, accent_color, capabilities)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
                 peer.id.as_str(),
                 peer.display_name,
                 peer.last_seen_at.as_millis(),
                 addresses_json,
+                peer.avatar.as_ref().map(|a| a.as_str()),
+                peer.accent_color.map(|c| c.to_string()),
+                Capability::join(&peer.capabilities),
             ],
         )?;
         Ok(())
     }
 
+    /// Updates `last_seen_at` for several peers in one transaction.
+    ///
+    /// The daemon collects sightings of peers whose details didn't change
+    /// and flushes them periodically, instead of rewriting the whole row on
+    /// every mDNS announcement. Unknown peer IDs are skipped. Returns the
+    /// number of peers updated.
+    pub fn touch_peers(&self, seen: &[(PeerId, Timestamp)]) -> Result<usize, DatabaseError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut updated = 0;
+        {
+            let mut stmt = tx.prepare("UPDATE peers SET last_seen_at = ?2 WHERE id = ?1")?;
+            for (peer_id, last_seen_at) in seen {
+                updated += stmt.execute(params![peer_id.as_str(), last_seen_at.as_millis()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
+
     /// Returns all known peers.
     ///
     /// The `online` field is always set to `false` here — the daemon
     /// maintains online status in memory based on mDNS events, not in the DB.
     pub fn get_peers(&self) -> Result<Vec<PeerInfo>, DatabaseError> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT id, display_name, last_seen_at, addresses FROM peers ORDER BY display_name")?;
+        let mut stmt = self.conn.prepare(
+            "SELECT id, display_name, last_seen_at, addresses, avatar, accent_color, capabilities, muted, sound
+             FROM peers LEFT JOIN peer_notifications ON peer_notifications.peer_id = peers.id
+             ORDER BY display_name",
+        )?;
 
         let peers = stmt
             .query_map([], |row| {
@@ -197,13 +545,21 @@ impl Database {
                 let display_name: String = row.get(1)?;
                 let last_seen_at: i64 = row.get(2)?;
                 let addresses_json: String = row.get(3)?;
-                Ok((id, display_name, last_seen_at, addresses_json))
+                let avatar: Option<String> = row.get(4)?;
+                let accent_color: Option<String> = row.get(5)?;
+                let capabilities: Option<String> = row.get(6)?;
+                let defaults = NotificationPrefs::default();
+                let notifications = NotificationPrefs {
+                    muted: row.get::<_, Option<bool>>(7)?.unwrap_or(defaults.muted),
+                    sound: row.get::<_, Option<bool>>(8)?.unwrap_or(defaults.sound),
+                };
+                Ok((id, display_name, last_seen_at, addresses_json, avatar, accent_color, capabilities, notifications))
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
         peers
             .into_iter()
-            .map(|(id, display_name, last_seen_at, addresses_json)| {
+            .map(|(id, display_name, last_seen_at, addresses_json, avatar, accent_color, capabilities, notifications)| {
                 let addresses: Vec<String> =
                     serde_json::from_str(&addresses_json).map_err(|e| {
                         DatabaseError::InvalidData(format!("bad addresses JSON: {e}"))
@@ -214,6 +570,116 @@ impl Database {
                     addresses,
                     last_seen_at: Timestamp::from_millis(last_seen_at),
                     online: false, // Caller (daemon) sets this from mDNS state
+                    do_not_disturb: false,
+                    away: false,
+                    // Cosmetic fields: a bad value is dropped rather than
+                    // failing the whole peer list.
+                    avatar: avatar.and_then(|a| Avatar::new(a).ok()),
+                    accent_color: accent_color.and_then(|c| AccentColor::parse(&c).ok()),
+                    // Last advertised; kept for offline peers so clients
+                    // know what they'll understand when they're back
+                    capabilities: capabilities.as_deref().map(Capability::parse_list).unwrap_or_default(),
+                    notifications,
+                    interface_addresses: Default::default(),
+                })
+            })
+            .collect()
+    }
+
+    /// Stores the notification settings for a peer.
+    pub fn set_peer_notifications(&self, peer_id: &PeerId, prefs: NotificationPrefs) -> Result<(), DatabaseError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO peer_notifications (peer_id, muted, sound) VALUES (?1, ?2, ?3)",
+            params![peer_id.as_str(), prefs.muted, prefs.sound],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the notification settings of every peer that has any. Peers
+    /// missing from the map use `NotificationPrefs::default()`.
+    pub fn peer_notifications(&self) -> Result<HashMap<PeerId, NotificationPrefs>, DatabaseError> {
+        let mut stmt = self.conn.prepare("SELECT peer_id, muted, sound FROM peer_notifications")?;
+        let prefs = stmt
+            .query_map([], |row| {
+                Ok((
+                    PeerId::new(row.get::<_, String>(0)?),
+                    NotificationPrefs {
+                        muted: row.get(1)?,
+                        sound: row.get(2)?,
+                    },
+                ))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(prefs)
+    }
+
+    /// Returns the display name we last stored for a peer, if we know it.
+    ///
+    /// Used to notice renames before `upsert_peer` overwrites the old name.
+    pub fn peer_display_name(&self, peer_id: &PeerId) -> Result<Option<String>, DatabaseError> {
+        let name = self
+            .conn
+            .query_row(
+                "SELECT display_name FROM peers WHERE id = ?1",
+                params![peer_id.as_str()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(name)
+    }
+
+    // -----------------------------------------------------------------------
+    // Audit log operations
+    // -----------------------------------------------------------------------
+
+    /// Appends an entry to the settings audit log.
+    pub fn record_audit(&self, entry: &AuditEntry) -> Result<(), DatabaseError> {
+        self.conn.execute(
+            "INSERT INTO audit_log (timestamp, action, peer_id, detail) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                entry.timestamp.as_millis(),
+                entry.action.as_db_str(),
+                entry.peer_id.as_ref().map(|p| p.as_str()),
+                entry.detail,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns audit log entries, newest first.
+    ///
+    /// Like `get_messages`, `before` pages back through older entries.
+    pub fn get_audit_log(
+        &self,
+        limit: u32,
+        before: Option<Timestamp>,
+    ) -> Result<Vec<AuditEntry>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT timestamp, action, peer_id, detail
+             FROM audit_log
+             WHERE timestamp < ?1
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?2",
+        )?;
+        let before = before.map(|t| t.as_millis()).unwrap_or(i64::MAX);
+        let rows = stmt
+            .query_map(params![before, limit], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(timestamp, action, peer_id, detail)| {
+                Ok(AuditEntry {
+                    timestamp: Timestamp::from_millis(timestamp),
+                    action: AuditAction::from_db_str(&action).map_err(DatabaseError::InvalidData)?,
+                    peer_id: peer_id.map(PeerId::new),
+                    detail,
                 })
             })
             .collect()
@@ -228,9 +694,22 @@ impl Database {
     /// The message must have a unique `id`. If a message with the same ID
     /// already exists, this will return an error (duplicate primary key).
     pub fn save_message(&self, msg: &Message) -> Result<(), DatabaseError> {
+        self.insert_message(msg, false)
+    }
+
+    /// Saves an outgoing message as pending, before the first attempt to
+    /// send it: the outbound journal. Clear it with `clear_pending` once
+    /// the attempt is over (`mark_delivered` also does); whatever is still
+    /// pending when the daemon starts never got its attempt, and is sent
+    /// again (`get_undelivered`, `get_pending_peers`).
+    pub fn save_pending(&self, msg: &Message) -> Result<(), DatabaseError> {
+        self.insert_message(msg, true)
+    }
+
+    fn insert_message(&self, msg: &Message, pending: bool) -> Result<(), DatabaseError> {
         self.conn.execute(
-            "INSERT INTO messages (id, peer_id, direction, content, timestamp, delivered)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO messages (id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, pending, reply_to)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
             params![
                 msg.id.as_str(),
                 msg.peer_id.as_str(),
@@ -238,11 +717,37 @@ impl Database {
                 msg.content,
                 msg.timestamp.as_millis(),
                 msg.delivered as i32,
+                msg.fire_and_forget as i32,
+                msg.announcement as i32,
+                pending as i32,
+                msg.reply_to.as_ref().map(MessageId::as_str),
             ],
         )?;
         Ok(())
     }
 
+    /// Takes a message out of the outbound journal: an attempt to send it
+    /// was made, whatever came of it.
+    pub fn clear_pending(&self, message_id: &MessageId) -> Result<(), DatabaseError> {
+        self.conn.execute(
+            "UPDATE messages SET pending = 0 WHERE id = ?1",
+            params![message_id.as_str()],
+        )?;
+        Ok(())
+    }
+
+    /// The peers with messages still in the outbound journal.
+    pub fn get_pending_peers(&self) -> Result<Vec<PeerId>, DatabaseError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT peer_id FROM messages WHERE pending = 1 ORDER BY peer_id")?;
+        let peers = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|id| id.map(PeerId::new))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(peers)
+    }
+
     /// Retrieves messages exchanged with a specific peer.
     ///
     /// Returns up to `limit` messages, ordered newest-first.
@@ -257,20 +762,20 @@ impl Database {
         let messages = if let Some(before_ts) = before {
             // Fetch messages older than the given timestamp
             let mut stmt = self.conn.prepare(
-                "SELECT id, peer_id, direction, content, timestamp, delivered
+                "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
                  FROM messages
                  WHERE peer_id = ?1 AND timestamp < ?2
-                 ORDER BY timestamp DESC
+                 ORDER BY timestamp DESC, id DESC
                  LIMIT ?3",
             )?;
             Self::collect_messages(&mut stmt, params![peer_id.as_str(), before_ts.as_millis(), limit])?
         } else {
             // Fetch the most recent messages
             let mut stmt = self.conn.prepare(
-                "SELECT id, peer_id, direction, content, timestamp, delivered
+                "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
                  FROM messages
                  WHERE peer_id = ?1
-                 ORDER BY timestamp DESC
+                 ORDER BY timestamp DESC, id DESC
                  LIMIT ?2",
             )?;
             Self::collect_messages(&mut stmt, params![peer_id.as_str(), limit])?
@@ -279,6 +784,92 @@ impl Database {
         Ok(messages)
     }
 
+    /// Returns up to `limit` messages with a peer on the far side of
+    /// `cursor`, newest first either way.
+    pub fn get_messages_at(
+        &self,
+        peer_id: &PeerId,
+        limit: u32,
+        cursor: &MessageCursor,
+    ) -> Result<Vec<Message>, DatabaseError> {
+        let (millis, id) = (cursor.timestamp.as_millis(), cursor.id.as_str());
+        match cursor.direction {
+            PageDirection::Older => {
+                let mut stmt = self.conn.prepare(
+                    "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+                     FROM messages
+                     WHERE peer_id = ?1 AND (timestamp, id) < (?2, ?3)
+                     ORDER BY timestamp DESC, id DESC
+                     LIMIT ?4",
+                )?;
+                Self::collect_messages(&mut stmt, params![peer_id.as_str(), millis, id, limit])
+            }
+            PageDirection::Newer => {
+                // The `limit` messages right after the cursor, not the latest ones
+                let mut stmt = self.conn.prepare(
+                    "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+                     FROM messages
+                     WHERE peer_id = ?1 AND (timestamp, id) > (?2, ?3)
+                     ORDER BY timestamp ASC, id ASC
+                     LIMIT ?4",
+                )?;
+                let mut messages = Self::collect_messages(&mut stmt, params![peer_id.as_str(), millis, id, limit])?;
+                messages.reverse();
+                Ok(messages)
+            }
+        }
+    }
+
+    /// Returns every message (all peers) with `start <= timestamp < end`,
+    /// oldest first. Used for periodic summaries like the weekly recap.
+    pub fn get_messages_between(&self, start: Timestamp, end: Timestamp) -> Result<Vec<Message>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+             FROM messages
+             WHERE timestamp >= ?1 AND timestamp < ?2
+             ORDER BY timestamp ASC",
+        )?;
+        Self::collect_messages(&mut stmt, params![start.as_millis(), end.as_millis()])
+    }
+
+    /// Returns up to `limit` messages with a peer in the order they happened,
+    /// starting right after the message `after` (or at the very first one).
+    ///
+    /// Pages are keyed on (timestamp, id), so walking a whole conversation by
+    /// passing the last ID of each page never skips or repeats a message,
+    /// even when several share a timestamp. An unknown `after` ID yields an
+    /// empty page.
+    pub fn get_timeline(
+        &self,
+        peer_id: &PeerId,
+        after: Option<&MessageId>,
+        limit: u32,
+    ) -> Result<Vec<Message>, DatabaseError> {
+        match after {
+            Some(after) => {
+                let mut stmt = self.conn.prepare(
+                    "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+                     FROM messages
+                     WHERE peer_id = ?1
+                       AND (timestamp, id) > (SELECT timestamp, id FROM messages WHERE id = ?2)
+                     ORDER BY timestamp ASC, id ASC
+                     LIMIT ?3",
+                )?;
+                Self::collect_messages(&mut stmt, params![peer_id.as_str(), after.as_str(), limit])
+            }
+            None => {
+                let mut stmt = self.conn.prepare(
+                    "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+                     FROM messages
+                     WHERE peer_id = ?1
+                     ORDER BY timestamp ASC, id ASC
+                     LIMIT ?2",
+                )?;
+                Self::collect_messages(&mut stmt, params![peer_id.as_str(), limit])
+            }
+        }
+    }
+
     /// Helper: collects message rows from a prepared statement into a Vec.
     ///
     /// This avoids duplicating the row-mapping logic between the two
@@ -295,12 +886,15 @@ impl Database {
                 let content: String = row.get(3)?;
                 let timestamp: i64 = row.get(4)?;
                 let delivered: i32 = row.get(5)?;
-                Ok((id, peer_id, direction, content, timestamp, delivered))
+                let fire_and_forget: i32 = row.get(6)?;
+                let announcement: i32 = row.get(7)?;
+                let reply_to: Option<String> = row.get(8)?;
+                Ok((id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to))
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
         rows.into_iter()
-            .map(|(id, peer_id, direction, content, timestamp, delivered)| {
+            .map(|(id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to)| {
                 let direction = Direction::from_db_str(&direction)
                     .map_err(DatabaseError::InvalidData)?;
                 Ok(Message {
@@ -310,109 +904,1006 @@ impl Database {
                     content,
                     timestamp: Timestamp::from_millis(timestamp),
                     delivered: delivered != 0,
+                    fire_and_forget: fire_and_forget != 0,
+                    announcement: announcement != 0,
+                    reply_to: reply_to.map(MessageId::new),
                 })
             })
             .collect()
     }
 
-    /// Marks a message as delivered (ACK received or sent).
+    /// Returns a single message by ID, if it exists.
+    pub fn get_message(&self, message_id: &MessageId) -> Result<Option<Message>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+             FROM messages
+             WHERE id = ?1",
+        )?;
+        Ok(Self::collect_messages(&mut stmt, params![message_id.as_str()])?.pop())
+    }
+
+    /// Deletes a message together with its private note.
+    ///
+    /// Returns `Ok(false)` if no message with that ID exists.
+    pub fn delete_message(&self, message_id: &MessageId) -> Result<bool, DatabaseError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let deleted = tx.execute("DELETE FROM messages WHERE id = ?1", params![message_id.as_str()])?;
+        if deleted == 0 {
+            return Ok(false);
+        }
+        tx.execute(
+            "DELETE FROM message_notes WHERE message_id = ?1",
+            params![message_id.as_str()],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Marks a message as delivered (ACK received or sent), which also
+    /// takes it out of the outbound journal.
     ///
     /// Returns `Ok(true)` if a message was updated, `Ok(false)` if no
     /// message with that ID exists.
     pub fn mark_delivered(&self, message_id: &MessageId) -> Result<bool, DatabaseError> {
         let rows_affected = self.conn.execute(
-            "UPDATE messages SET delivered = 1 WHERE id = ?1",
+            "UPDATE messages SET delivered = 1, pending = 0 WHERE id = ?1",
             params![message_id.as_str()],
         )?;
         Ok(rows_affected > 0)
     }
 
-    /// Returns the count of unread (undelivered received) messages from a peer.
-    ///
-    /// Useful for showing unread badges in the TUI peer list.
-    pub fn unread_count(&self, peer_id: &PeerId) -> Result<u32, DatabaseError> {
-        let count: u32 = self.conn.query_row(
-            "SELECT COUNT(*) FROM messages
-             WHERE peer_id = ?1 AND direction = 'received' AND delivered = 0",
-            params![peer_id.as_str()],
-            |row| row.get(0),
+    /// Returns the messages sent to a peer that it never acknowledged,
+    /// oldest first. Fire-and-forget messages are never acknowledged, so
+    /// they're only included while still pending (see `save_pending`).
+    pub fn get_undelivered(&self, peer_id: &PeerId) -> Result<Vec<Message>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+             FROM messages
+             WHERE peer_id = ?1 AND direction = ?2 AND delivered = 0 AND (fire_and_forget = 0 OR pending = 1)
+             ORDER BY timestamp ASC, id ASC",
         )?;
-        Ok(count)
+        Self::collect_messages(&mut stmt, params![peer_id.as_str(), Direction::Sent.as_db_str()])
     }
-}
-
-// ---------------------------------------------------------------------------
-// Tests
-// ---------------------------------------------------------------------------
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    /// Helper: creates an in-memory database for testing.
-    fn test_db() -> Database {
-        Database::open_in_memory().expect("failed to create test database")
+    /// Returns the undelivered messages, to any peer, sent at or before
+    /// `sent_before` and not emailed yet, oldest first.
+    pub fn get_overdue_undelivered(&self, sent_before: Timestamp) -> Result<Vec<Message>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+             FROM messages
+             WHERE direction = ?1 AND delivered = 0 AND fire_and_forget = 0
+                   AND emailed = 0 AND timestamp <= ?2
+             ORDER BY timestamp ASC, id ASC",
+        )?;
+        Self::collect_messages(&mut stmt, params![Direction::Sent.as_db_str(), sent_before.as_millis()])
     }
 
-    /// Helper: creates a test peer and inserts it into the database.
-    fn insert_test_peer(db: &Database, id: &str, name: &str) {
-        let peer = PeerInfo {
-            id: PeerId::new(id),
-            display_name: name.to_string(),
-            addresses: vec!["192.168.1.10:9876".to_string()],
-            last_seen_at: Timestamp::now(),
-            online: true,
-        };
-        db.upsert_peer(&peer).unwrap();
+    /// Records that these undelivered messages have been emailed.
+    pub fn mark_emailed(&self, message_ids: &[MessageId]) -> Result<usize, DatabaseError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut marked = 0;
+        for id in message_ids {
+            marked += tx.execute("UPDATE messages SET emailed = 1 WHERE id = ?1", params![id.as_str()])?;
+        }
+        tx.commit()?;
+        Ok(marked)
     }
 
-    #[test]
-    fn config_set_and_get() {
-        let db = test_db();
-        db.set_config("peer_id", "abc-123").unwrap();
-        assert_eq!(db.get_config("peer_id").unwrap(), Some("abc-123".to_string()));
+    // -----------------------------------------------------------------------
+    // Message note operations
+    // -----------------------------------------------------------------------
+
+    /// Attaches a private note to a message, replacing any previous note.
+    /// A blank `note` removes it.
+    ///
+    /// Returns `Ok(false)` if no message with that ID exists.
+    pub fn set_message_note(
+        &self,
+        message_id: &MessageId,
+        note: &str,
+        updated_at: Timestamp,
+    ) -> Result<bool, DatabaseError> {
+        let exists: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM messages WHERE id = ?1)",
+            params![message_id.as_str()],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Ok(false);
+        }
+
+        let note = note.trim();
+        if note.is_empty() {
+            self.conn.execute(
+                "DELETE FROM message_notes WHERE message_id = ?1",
+                params![message_id.as_str()],
+            )?;
+        } else {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO message_notes (message_id, note, updated_at) VALUES (?1, ?2, ?3)",
+                params![message_id.as_str(), note, updated_at.as_millis()],
+            )?;
+        }
+        Ok(true)
     }
 
-    #[test]
-    fn config_get_missing_key() {
-        let db = test_db();
-        assert_eq!(db.get_config("nonexistent").unwrap(), None);
+    /// Returns the notes on messages exchanged with `peer_id`.
+    pub fn get_message_notes(&self, peer_id: &PeerId) -> Result<Vec<MessageNote>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT n.message_id, n.note, n.updated_at
+             FROM message_notes n JOIN messages m ON m.id = n.message_id
+             WHERE m.peer_id = ?1
+             ORDER BY m.timestamp",
+        )?;
+        let notes = stmt
+            .query_map(params![peer_id.as_str()], |row| {
+                Ok(MessageNote {
+                    message_id: MessageId::new(row.get::<_, String>(0)?),
+                    note: row.get(1)?,
+                    updated_at: Timestamp::from_millis(row.get(2)?),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(notes)
     }
 
-    #[test]
-    fn config_update_existing() {
-        let db = test_db();
-        db.set_config("name", "old").unwrap();
-        db.set_config("name", "new").unwrap();
-        assert_eq!(db.get_config("name").unwrap(), Some("new".to_string()));
+    /// Finds messages with a peer whose text contains `query`
+    /// (case-insensitive for ASCII), newest first.
+    pub fn search_messages(&self, peer_id: &PeerId, query: &str, limit: u32) -> Result<Vec<Message>, DatabaseError> {
+        let pattern = format!("%{}%", escape_like(query.trim()));
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+             FROM messages
+             WHERE peer_id = ?1 AND content LIKE ?2 ESCAPE '\\'
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?3",
+        )?;
+        Self::collect_messages(&mut stmt, params![peer_id.as_str(), pattern, limit])
     }
 
-    #[test]
-    fn peer_upsert_and_get() {
-        let db = test_db();
-        insert_test_peer(&db, "peer-1", "PC-Sala");
+    /// Finds notes containing `query` (case-insensitive for ASCII), newest
+    /// message first, together with the messages they annotate.
+    pub fn search_message_notes(&self, query: &str, limit: u32) -> Result<Vec<NoteMatch>, DatabaseError> {
+        let pattern = format!("%{}%", escape_like(query.trim()));
+        let mut stmt = self.conn.prepare(
+            "SELECT n.note, n.updated_at,
+                    m.id, m.peer_id, m.direction, m.content, m.timestamp, m.delivered, m.fire_and_forget, m.announcement, m.reply_to
+             FROM message_notes n JOIN messages m ON m.id = n.message_id
+             WHERE n.note LIKE ?1 ESCAPE '\\'
+             ORDER BY m.timestamp DESC
+             LIMIT ?2",
+        )?;
+        let rows = stmt
+            .query_map(params![pattern, limit], |row| {
+                let note: String = row.get(0)?;
+                let updated_at: i64 = row.get(1)?;
+                let id: String = row.get(2)?;
+                let peer_id: String = row.get(3)?;
+                let direction: String = row.get(4)?;
+                let content: String = row.get(5)?;
+                let timestamp: i64 = row.get(6)?;
+                let delivered: i32 = row.get(7)?;
+                let fire_and_forget: i32 = row.get(8)?;
+                let announcement: i32 = row.get(9)?;
+                let reply_to: Option<String> = row.get(10)?;
+                Ok((note, updated_at, id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let peers = db.get_peers().unwrap();
-        assert_eq!(peers.len(), 1);
-        assert_eq!(peers[0].id.as_str(), "peer-1");
-        assert_eq!(peers[0].display_name, "PC-Sala");
-        assert_eq!(peers[0].addresses, vec!["192.168.1.10:9876"]);
-        assert!(!peers[0].online); // DB always returns online=false
+        rows.into_iter()
+            .map(|(note, updated_at, id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to)| {
+                let direction = Direction::from_db_str(&direction).map_err(DatabaseError::InvalidData)?;
+                let message_id = MessageId::new(id);
+                Ok(NoteMatch {
+                    note: MessageNote {
+                        message_id: message_id.clone(),
+                        note,
+                        updated_at: Timestamp::from_millis(updated_at),
+                    },
+                    message: Message {
+                        id: message_id,
+                        peer_id: PeerId::new(peer_id),
+                        direction,
+                        content,
+                        timestamp: Timestamp::from_millis(timestamp),
+                        delivered: delivered != 0,
+                        fire_and_forget: fire_and_forget != 0,
+                        announcement: announcement != 0,
+                        reply_to: reply_to.map(MessageId::new),
+                    },
+                })
+            })
+            .collect()
     }
 
-    #[test]
-    fn peer_upsert_updates_existing() {
-        let db = test_db();
-        insert_test_peer(&db, "peer-1", "Old Name");
-        insert_test_peer(&db, "peer-1", "New Name");
+    // -----------------------------------------------------------------------
+    // Read-state operations
+    // -----------------------------------------------------------------------
 
-        let peers = db.get_peers().unwrap();
-        assert_eq!(peers.len(), 1);
-        assert_eq!(peers[0].display_name, "New Name");
+    /// Moves the read watermark for a conversation forward to `up_to`.
+    ///
+    /// The watermark never moves backwards: marking an older timestamp
+    /// as read (e.g., from a TUI with stale state) is a no-op.
+    pub fn mark_read(&self, peer_id: &PeerId, up_to: Timestamp) -> Result<(), DatabaseError> {
+        self.conn.execute(
+            "INSERT INTO read_state (peer_id, last_read_at) VALUES (?1, ?2)
+             ON CONFLICT(peer_id) DO UPDATE
+             SET last_read_at = MAX(last_read_at, excluded.last_read_at)",
+            params![peer_id.as_str(), up_to.as_millis()],
+        )?;
+        Ok(())
     }
 
-    #[test]
-    fn message_save_and_get() {
+    /// Returns the read watermark for a conversation.
+    ///
+    /// Returns `None` if the conversation was never marked as read.
+    pub fn last_read_at(&self, peer_id: &PeerId) -> Result<Option<Timestamp>, DatabaseError> {
+        let value = self
+            .conn
+            .query_row(
+                "SELECT last_read_at FROM read_state WHERE peer_id = ?1",
+                params![peer_id.as_str()],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        Ok(value.map(Timestamp::from_millis))
+    }
+
+    /// Records a read receipt: the peer has read our messages up to
+    /// `up_to`. Like `mark_read`, never moves backwards, so a receipt that
+    /// arrives late changes nothing.
+    pub fn mark_read_by_peer(&self, peer_id: &PeerId, up_to: Timestamp) -> Result<(), DatabaseError> {
+        self.conn.execute(
+            "INSERT INTO peer_read_state (peer_id, read_up_to) VALUES (?1, ?2)
+             ON CONFLICT(peer_id) DO UPDATE
+             SET read_up_to = MAX(read_up_to, excluded.read_up_to)",
+            params![peer_id.as_str(), up_to.as_millis()],
+        )?;
+        Ok(())
+    }
+
+    /// The timestamp of the newest message received from a peer at or
+    /// before `up_to` (any, if `None`), i.e. what a read receipt for
+    /// `up_to` should name: message timestamps come from the sender's
+    /// clock, not ours.
+    pub fn last_received_at(
+        &self,
+        peer_id: &PeerId,
+        up_to: Option<Timestamp>,
+    ) -> Result<Option<Timestamp>, DatabaseError> {
+        let value: Option<i64> = self.conn.query_row(
+            "SELECT MAX(timestamp) FROM messages
+             WHERE peer_id = ?1 AND direction = 'received' AND (?2 IS NULL OR timestamp <= ?2)",
+            params![peer_id.as_str(), up_to.map(|t| t.as_millis())],
+            |row| row.get(0),
+        )?;
+        Ok(value.map(Timestamp::from_millis))
+    }
+
+    /// Returns how many messages are stored for a conversation.
+    pub fn message_count(&self, peer_id: &PeerId) -> Result<u32, DatabaseError> {
+        let count: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE peer_id = ?1",
+            params![peer_id.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// Returns the count of unread received messages from a peer.
+    ///
+    /// A received message is unread if it is newer than the conversation's
+    /// read watermark (see `mark_read`). Useful for showing unread badges
+    /// in the TUI peer list.
+    pub fn unread_count(&self, peer_id: &PeerId) -> Result<u32, DatabaseError> {
+        let count: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages
+             WHERE peer_id = ?1 AND direction = 'received'
+               AND timestamp > COALESCE(
+                   (SELECT last_read_at FROM read_state WHERE peer_id = ?1), -1)",
+            params![peer_id.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// Returns the count of unread received messages across all
+    /// conversations, for status bars that only need the total.
+    pub fn total_unread_count(&self) -> Result<u32, DatabaseError> {
+        let count: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages m
+             WHERE m.direction = 'received'
+               AND m.timestamp > COALESCE(
+                   (SELECT last_read_at FROM read_state r WHERE r.peer_id = m.peer_id), -1)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// Returns one summary per conversation: the last message and the
+    /// unread count, most recently active conversation first.
+    ///
+    /// Done in a single query (rather than `get_messages` + `unread_count`
+    /// per peer) so the TUI can refresh its whole peer list cheaply. Peers
+    /// we never exchanged a message with are not included.
+    pub fn get_conversation_summaries(&self) -> Result<Vec<ConversationSummary>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.peer_id, m.content, m.direction, m.timestamp,
+                    (SELECT COUNT(*) FROM messages u
+                     WHERE u.peer_id = m.peer_id AND u.direction = 'received'
+                       AND u.timestamp > COALESCE(
+                           (SELECT last_read_at FROM read_state r WHERE r.peer_id = m.peer_id), -1)),
+                    (SELECT p.read_up_to FROM peer_read_state p WHERE p.peer_id = m.peer_id)
+             FROM messages m
+             WHERE m.rowid = (SELECT l.rowid FROM messages l
+                              WHERE l.peer_id = m.peer_id
+                              ORDER BY l.timestamp DESC, l.rowid DESC
+                              LIMIT 1)
+             ORDER BY m.timestamp DESC",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                let peer_id: String = row.get(0)?;
+                let content: String = row.get(1)?;
+                let direction: String = row.get(2)?;
+                let timestamp: i64 = row.get(3)?;
+                let unread: u32 = row.get(4)?;
+                let read_by_peer: Option<i64> = row.get(5)?;
+                Ok((peer_id, content, direction, timestamp, unread, read_by_peer))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(peer_id, content, direction, timestamp, unread_count, read_by_peer)| {
+                Ok(ConversationSummary {
+                    peer_id: PeerId::new(peer_id),
+                    last_message_preview: ConversationSummary::preview(&content),
+                    last_direction: Direction::from_db_str(&direction).map_err(DatabaseError::InvalidData)?,
+                    last_timestamp: Timestamp::from_millis(timestamp),
+                    unread_count,
+                    read_by_peer: read_by_peer.map(Timestamp::from_millis),
+                })
+            })
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+    // Scheduled messages
+    // -----------------------------------------------------------------------
+
+    /// Stores a message to send at `send_at` and returns its row ID.
+    pub fn schedule_message(&self, peer_id: &PeerId, content: &str, send_at: Timestamp) -> Result<i64, DatabaseError> {
+        self.conn.execute(
+            "INSERT INTO scheduled_messages (peer_id, content, send_at) VALUES (?1, ?2, ?3)",
+            params![peer_id.as_str(), content, send_at.as_millis()],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Returns the scheduled messages due at `now`, oldest first.
+    pub fn due_scheduled_messages(&self, now: Timestamp) -> Result<Vec<ScheduledMessage>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, content, send_at
+             FROM scheduled_messages
+             WHERE send_at <= ?1
+             ORDER BY send_at ASC, id ASC",
+        )?;
+        let due = stmt
+            .query_map(params![now.as_millis()], |row| {
+                Ok(ScheduledMessage {
+                    id: row.get(0)?,
+                    peer_id: PeerId::new(row.get::<_, String>(1)?),
+                    content: row.get(2)?,
+                    send_at: Timestamp::from_millis(row.get(3)?),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(due)
+    }
+
+    /// Removes a scheduled message (dispatched or cancelled).
+    ///
+    /// Returns `Ok(false)` if there was no such row.
+    pub fn remove_scheduled_message(&self, id: i64) -> Result<bool, DatabaseError> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM scheduled_messages WHERE id = ?1", params![id])?;
+        Ok(removed > 0)
+    }
+
+    // -----------------------------------------------------------------------
+    // Low-storage mode
+    // -----------------------------------------------------------------------
+
+    /// Returns up to `limit` messages not yet stored on the archive peer,
+    /// oldest first.
+    pub fn unarchived_messages(&self, limit: u32) -> Result<Vec<Message>, DatabaseError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+             FROM messages
+             WHERE archived = 0
+             ORDER BY timestamp ASC, id ASC
+             LIMIT ?1",
+        )?;
+        Self::collect_messages(&mut stmt, params![limit])
+    }
+
+    /// Records that the archive peer has stored these messages.
+    pub fn mark_archived(&self, message_ids: &[MessageId]) -> Result<usize, DatabaseError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut marked = 0;
+        for id in message_ids {
+            marked += tx.execute("UPDATE messages SET archived = 1 WHERE id = ?1", params![id.as_str()])?;
+        }
+        tx.commit()?;
+        Ok(marked)
+    }
+
+    /// Deletes up to `limit` archived messages older than `before` (any
+    /// age if `None`), oldest first.
+    ///
+    /// Messages with a private note are kept: notes never leave this
+    /// machine, so deleting the message would lose the note for good.
+    pub fn prune_archived(&self, before: Option<Timestamp>, limit: u32) -> Result<usize, DatabaseError> {
+        let before = before.map_or(i64::MAX, |ts| ts.as_millis());
+        let deleted = self.conn.execute(
+            "DELETE FROM messages WHERE id IN (
+                 SELECT id FROM messages
+                 WHERE archived = 1 AND timestamp < ?1
+                   AND id NOT IN (SELECT message_id FROM message_notes)
+                 ORDER BY timestamp ASC
+                 LIMIT ?2)",
+            params![before, limit],
+        )?;
+        Ok(deleted)
+    }
+
+    /// Shortens archived messages longer than `max_chars` characters,
+    /// ending them with "…". The full text stays on the archive peer.
+    pub fn truncate_archived(&self, max_chars: u32) -> Result<usize, DatabaseError> {
+        let truncated = self.conn.execute(
+            "UPDATE messages SET content = substr(content, 1, ?1) || '…'
+             WHERE archived = 1 AND length(content) > ?1",
+            params![max_chars],
+        )?;
+        Ok(truncated)
+    }
+
+    /// Stores history pushed by `owner_id` (this daemon is the archive).
+    ///
+    /// A message already stored is updated rather than duplicated: owners
+    /// push a message again after editing it.
+    pub fn store_archived(&self, owner_id: &PeerId, messages: &[Message]) -> Result<usize, DatabaseError> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut stored = 0;
+        for msg in messages {
+            stored += tx.execute(
+                "INSERT INTO archive (owner_id, id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT (owner_id, id) DO UPDATE SET
+                     content = excluded.content,
+                     delivered = excluded.delivered",
+                params![
+                    owner_id.as_str(),
+                    msg.id.as_str(),
+                    msg.peer_id.as_str(),
+                    msg.direction.as_db_str(),
+                    msg.content,
+                    msg.timestamp.as_millis(),
+                    msg.delivered as i32,
+                    msg.fire_and_forget as i32,
+                    msg.announcement as i32,
+                    msg.reply_to.as_ref().map(MessageId::as_str),
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    /// Returns up to `limit` messages `owner_id` archived here from its
+    /// conversation with `peer_id`, newest first, older than `before`.
+    pub fn get_archived(
+        &self,
+        owner_id: &PeerId,
+        peer_id: &PeerId,
+        before: Option<Timestamp>,
+        limit: u32,
+    ) -> Result<Vec<Message>, DatabaseError> {
+        let before = before.map_or(i64::MAX, |ts| ts.as_millis());
+        let mut stmt = self.conn.prepare(
+            "SELECT id, peer_id, direction, content, timestamp, delivered, fire_and_forget, announcement, reply_to
+             FROM archive
+             WHERE owner_id = ?1 AND peer_id = ?2 AND timestamp < ?3
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?4",
+        )?;
+        Self::collect_messages(&mut stmt, params![owner_id.as_str(), peer_id.as_str(), before, limit])
+    }
+
+    /// Bytes of the database in use. Deleting rows frees pages for reuse
+    /// without shrinking the file, so this is what a size cap compares.
+    pub fn used_bytes(&self) -> Result<u64, DatabaseError> {
+        let pragma = |name: &str| -> Result<u64, DatabaseError> {
+            Ok(self.conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get(0))?)
+        };
+        let pages = pragma("page_count")?.saturating_sub(pragma("freelist_count")?);
+        Ok(pages * pragma("page_size")?)
+    }
+}
+
+/// Escapes `%`, `_` and `\` so `s` matches literally inside a `LIKE`
+/// pattern using `ESCAPE '\'`.
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Copies rows of `table` from `old` into `new`, stopping at the first
+/// read error. Returns the number of rows inserted.
+///
+/// Columns are matched by name, so a table from an older schema (missing
+/// later columns) still copies cleanly.
+fn salvage_table(old: &Connection, new: &Connection, table: &str) -> u64 {
+    let Ok(mut stmt) = old.prepare(&format!("SELECT * FROM {table}")) else {
+        return 0;
+    };
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let insert = format!(
+        "INSERT OR IGNORE INTO {table} ({}) VALUES ({placeholders})",
+        columns.join(", ")
+    );
+
+    let Ok(mut rows) = stmt.query([]) else {
+        return 0;
+    };
+    let mut copied = 0;
+    while let Ok(Some(row)) = rows.next() {
+        let values: Result<Vec<Value>, _> = (0..columns.len()).map(|i| row.get(i)).collect();
+        let Ok(values) = values else { break };
+        if let Ok(n) = new.execute(&insert, params_from_iter(values)) {
+            copied += n as u64;
+        }
+    }
+    copied
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helper: creates an in-memory database for testing.
+    fn test_db() -> Database {
+        Database::open_in_memory().expect("failed to create test database")
+    }
+
+    /// Helper: creates a test peer and inserts it into the database.
+    fn insert_test_peer(db: &Database, id: &str, name: &str) {
+        let peer = PeerInfo {
+            id: PeerId::new(id),
+            display_name: name.to_string(),
+            addresses: vec!["192.168.1.10:9876".to_string()],
+            last_seen_at: Timestamp::now(),
+            online: true,
+            do_not_disturb: false,
+            away: false,
+            avatar: None,
+            accent_color: None,
+            capabilities: Vec::new(),
+            notifications: NotificationPrefs::default(),
+            interface_addresses: Default::default(),
+        };
+        db.upsert_peer(&peer).unwrap();
+    }
+
+    #[test]
+    fn touch_peers_updates_last_seen() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        insert_test_peer(&db, "peer-2", "Laptop");
+
+        let updated = db
+            .touch_peers(&[
+                (PeerId::new("peer-1"), Timestamp::from_millis(1_000)),
+                (PeerId::new("peer-2"), Timestamp::from_millis(2_000)),
+                (PeerId::new("unknown"), Timestamp::from_millis(3_000)),
+            ])
+            .unwrap();
+        assert_eq!(updated, 2);
+
+        let peers = db.get_peers().unwrap();
+        let seen = |id: &str| peers.iter().find(|p| p.id.as_str() == id).unwrap().last_seen_at;
+        assert_eq!(seen("peer-1"), Timestamp::from_millis(1_000));
+        assert_eq!(seen("peer-2"), Timestamp::from_millis(2_000));
+        // Other columns are left alone
+        assert_eq!(peers.iter().find(|p| p.id.as_str() == "peer-1").unwrap().display_name, "PC-Sala");
+    }
+
+    #[test]
+    fn peer_notifications_survive_rediscovery() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        insert_test_peer(&db, "peer-2", "Laptop");
+        let muted = NotificationPrefs {
+            muted: true,
+            sound: false,
+        };
+        db.set_peer_notifications(&PeerId::new("peer-1"), muted).unwrap();
+
+        // Discovery rewrites the peer row; the settings are kept
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+
+        let peers = db.get_peers().unwrap();
+        let prefs = |id: &str| peers.iter().find(|p| p.id.as_str() == id).unwrap().notifications;
+        assert_eq!(prefs("peer-1"), muted);
+        assert_eq!(prefs("peer-2"), NotificationPrefs::default());
+
+        let all = db.peer_notifications().unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[&PeerId::new("peer-1")], muted);
+    }
+
+    #[test]
+    fn config_set_and_get() {
+        let db = test_db();
+        db.set_config("peer_id", "abc-123").unwrap();
+        assert_eq!(db.get_config("peer_id").unwrap(), Some("abc-123".to_string()));
+    }
+
+    #[test]
+    fn config_get_missing_key() {
+        let db = test_db();
+        assert_eq!(db.get_config("nonexistent").unwrap(), None);
+    }
+
+    #[test]
+    fn config_update_existing() {
+        let db = test_db();
+        db.set_config("name", "old").unwrap();
+        db.set_config("name", "new").unwrap();
+        assert_eq!(db.get_config("name").unwrap(), Some("new".to_string()));
+    }
+
+    #[test]
+    fn peer_upsert_and_get() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+
+        let peers = db.get_peers().unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id.as_str(), "peer-1");
+        assert_eq!(peers[0].display_name, "PC-Sala");
+        assert_eq!(peers[0].addresses, vec!["192.168.1.10:9876"]);
+        assert!(!peers[0].online); // DB always returns online=false
+    }
+
+    #[test]
+    fn peer_upsert_updates_existing() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "Old Name");
+        insert_test_peer(&db, "peer-1", "New Name");
+
+        let peers = db.get_peers().unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].display_name, "New Name");
+    }
+
+    #[test]
+    fn peer_avatar_color_and_capabilities_roundtrip() {
+        let db = test_db();
+        let peer = PeerInfo {
+            id: PeerId::new("peer-1"),
+            display_name: "Cocina".to_string(),
+            addresses: vec![],
+            last_seen_at: Timestamp::now(),
+            online: true,
+            do_not_disturb: false,
+            away: false,
+            avatar: Some(Avatar::new("🍳").unwrap()),
+            accent_color: Some(AccentColor::parse("#ff8800").unwrap()),
+            capabilities: vec![Capability::Retract, Capability::Groups],
+            notifications: NotificationPrefs::default(),
+            interface_addresses: Default::default(),
+        };
+        db.upsert_peer(&peer).unwrap();
+
+        let peers = db.get_peers().unwrap();
+        assert_eq!(peers[0].avatar.as_ref().map(|a| a.as_str()), Some("🍳"));
+        assert_eq!(peers[0].accent_color, peer.accent_color);
+        assert_eq!(peers[0].capabilities, peer.capabilities);
+    }
+
+    #[test]
+    fn migrate_adds_missing_peer_columns() {
+        // Simulate a database created before avatar/accent_color/capabilities existed
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE peers (
+                id TEXT PRIMARY KEY,
+                display_name TEXT NOT NULL,
+                last_seen_at INTEGER NOT NULL,
+                addresses TEXT NOT NULL
+            );
+            INSERT INTO peers VALUES ('old', 'Viejo', 0, '[]');",
+        )
+        .unwrap();
+        let db = Database { conn };
+        db.migrate().unwrap();
+
+        let peers = db.get_peers().unwrap();
+        assert_eq!(peers[0].display_name, "Viejo");
+        assert!(peers[0].avatar.is_none());
+        assert!(peers[0].capabilities.is_empty());
+    }
+
+    #[test]
+    fn migrate_allows_system_messages_in_old_table() {
+        // Simulate a messages table from before Direction::System existed
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE peers (
+                id TEXT PRIMARY KEY,
+                display_name TEXT NOT NULL,
+                last_seen_at INTEGER NOT NULL,
+                addresses TEXT NOT NULL
+            );
+            CREATE TABLE messages (
+                id        TEXT PRIMARY KEY,
+                peer_id   TEXT NOT NULL,
+                direction TEXT NOT NULL CHECK(direction IN ('sent', 'received')),
+                content   TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                delivered INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY (peer_id) REFERENCES peers(id)
+            );
+            CREATE INDEX idx_messages_peer_time ON messages(peer_id, timestamp DESC);
+            INSERT INTO peers VALUES ('peer-1', 'Viejo', 0, '[]');
+            INSERT INTO messages VALUES ('m1', 'peer-1', 'received', 'hola', 1000, 1);",
+        )
+        .unwrap();
+        let db = Database { conn };
+        db.migrate().unwrap();
+
+        db.save_message(&Message {
+            id: MessageId::new("m2"),
+            peer_id: PeerId::new("peer-1"),
+            direction: Direction::System,
+            content: "Resumen".to_string(),
+            timestamp: Timestamp::from_millis(2000),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        })
+        .unwrap();
+
+        let messages = db.get_messages(&PeerId::new("peer-1"), 10, None).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].direction, Direction::System);
+        assert_eq!(messages[1].content, "hola");
+    }
+
+    #[test]
+    fn saving_a_message_twice_is_a_duplicate() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "Cocina");
+        let message = Message {
+            id: MessageId::new("m1"),
+            peer_id: PeerId::new("peer-1"),
+            direction: Direction::Received,
+            content: "hola".to_string(),
+            timestamp: Timestamp::from_millis(1000),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        db.save_message(&message).unwrap();
+
+        let err = db.save_message(&message).unwrap_err();
+        assert!(err.is_duplicate());
+        assert!(!err.is_corruption());
+    }
+
+    #[test]
+    fn messages_between_is_half_open() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        for (id, ts) in [("a", 999), ("b", 1000), ("c", 1999), ("d", 2000)] {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new("peer-1"),
+                direction: Direction::Received,
+                content: id.to_string(),
+                timestamp: Timestamp::from_millis(ts),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+
+        let found = db
+            .get_messages_between(Timestamp::from_millis(1000), Timestamp::from_millis(2000))
+            .unwrap();
+        let ids: Vec<&str> = found.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(ids, ["b", "c"]);
+    }
+
+    #[test]
+    fn timeline_pages_walk_the_whole_conversation() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        insert_test_peer(&db, "peer-2", "Laptop");
+        // "b" and "c" share a timestamp, and would straddle a page boundary
+        for (id, peer, ts) in [
+            ("a", "peer-1", 100),
+            ("c", "peer-1", 200),
+            ("b", "peer-1", 200),
+            ("x", "peer-2", 150),
+            ("d", "peer-1", 300),
+        ] {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new(peer),
+                direction: Direction::Received,
+                content: id.to_string(),
+                timestamp: Timestamp::from_millis(ts),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+
+        let peer = PeerId::new("peer-1");
+        let mut seen = Vec::new();
+        let mut after: Option<MessageId> = None;
+        loop {
+            let page = db.get_timeline(&peer, after.as_ref(), 2).unwrap();
+            let Some(last) = page.last() else { break };
+            after = Some(last.id.clone());
+            seen.extend(page.into_iter().map(|m| m.content));
+        }
+        assert_eq!(seen, ["a", "b", "c", "d"]);
+
+        assert!(db.get_timeline(&peer, Some(&MessageId::new("nope")), 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn delete_message_removes_its_note() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        let id = MessageId::new("m1");
+        db.save_message(&Message {
+            id: id.clone(),
+            peer_id: PeerId::new("peer-1"),
+            direction: Direction::Sent,
+            content: "Llego a las 8".to_string(),
+            timestamp: Timestamp::from_millis(1000),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        })
+        .unwrap();
+        db.set_message_note(&id, "llamar antes", Timestamp::from_millis(3000)).unwrap();
+
+        assert!(db.delete_message(&id).unwrap());
+        assert!(db.get_message(&id).unwrap().is_none());
+        assert!(db.get_message_notes(&PeerId::new("peer-1")).unwrap().is_empty());
+        assert!(!db.delete_message(&id).unwrap());
+    }
+
+    #[test]
+    fn healthy_database_needs_no_recovery() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("familycom.db");
+        drop(Database::open(&path).unwrap());
+
+        let (_db, report) = Database::open_or_recover(&path).unwrap();
+        assert!(report.is_none());
+    }
+
+    #[test]
+    fn garbage_file_is_moved_aside() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("familycom.db");
+        std::fs::write(&path, vec![0xA5u8; 4096]).unwrap();
+
+        let (db, report) = Database::open_or_recover(&path).unwrap();
+        let report = report.expect("corruption should be reported");
+        assert!(report.corrupt_path.exists());
+        assert_eq!(report.salvaged_rows, 0);
+
+        // The fresh database is fully usable
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        assert_eq!(db.get_peers().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn recover_salvages_readable_rows() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("familycom.db");
+        {
+            let db = Database::open(&path).unwrap();
+            insert_test_peer(&db, "peer-1", "PC-Sala");
+            db.save_message(&Message {
+                id: MessageId::new("msg-1"),
+                peer_id: PeerId::new("peer-1"),
+                direction: Direction::Received,
+                content: "sigue aqui".to_string(),
+                timestamp: Timestamp::from_millis(1000),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+            db.mark_read_by_peer(&PeerId::new("peer-1"), Timestamp::from_millis(1000)).unwrap();
+        }
+
+        let report = Database::recover(&path, "test".to_string()).unwrap();
+        assert_eq!(report.salvaged_rows, 3);
+
+        let db = Database::open(&path).unwrap();
+        let messages = db.get_messages(&PeerId::new("peer-1"), 10, None).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "sigue aqui");
+        let summaries = db.get_conversation_summaries().unwrap();
+        assert_eq!(summaries[0].read_by_peer, Some(Timestamp::from_millis(1000)));
+    }
+
+    #[test]
+    fn audit_log_newest_first_with_paging() {
+        let db = test_db();
+        for (ts, detail) in [(1000, "a"), (2000, "b"), (3000, "c")] {
+            db.record_audit(&AuditEntry {
+                timestamp: Timestamp::from_millis(ts),
+                action: AuditAction::PeerRenamed,
+                peer_id: Some(PeerId::new("peer-1")),
+                detail: detail.to_string(),
+            })
+            .unwrap();
+        }
+
+        let all = db.get_audit_log(10, None).unwrap();
+        let details: Vec<&str> = all.iter().map(|e| e.detail.as_str()).collect();
+        assert_eq!(details, ["c", "b", "a"]);
+        assert_eq!(all[0].peer_id, Some(PeerId::new("peer-1")));
+
+        let older = db.get_audit_log(10, Some(Timestamp::from_millis(2000))).unwrap();
+        assert_eq!(older.len(), 1);
+        assert_eq!(older[0].detail, "a");
+    }
+
+    #[test]
+    fn peer_display_name_lookup() {
+        let db = test_db();
+        assert_eq!(db.peer_display_name(&PeerId::new("peer-1")).unwrap(), None);
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        assert_eq!(
+            db.peer_display_name(&PeerId::new("peer-1")).unwrap().as_deref(),
+            Some("PC-Sala")
+        );
+    }
+
+    #[test]
+    fn message_save_and_get() {
         let db = test_db();
         insert_test_peer(&db, "peer-1", "PC-Sala");
 
@@ -423,6 +1914,9 @@ mod tests {
             content: "Hola, qué tal?".to_string(),
             timestamp: Timestamp::from_millis(1000),
             delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
         };
         db.save_message(&msg).unwrap();
 
@@ -446,6 +1940,9 @@ mod tests {
                 content: format!("Message {i}"),
                 timestamp: Timestamp::from_millis(i * 1000),
                 delivered: false,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
             };
             db.save_message(&msg).unwrap();
         }
@@ -470,6 +1967,9 @@ mod tests {
                 content: format!("Message {i}"),
                 timestamp: Timestamp::from_millis(i * 1000),
                 delivered: false,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
             };
             db.save_message(&msg).unwrap();
         }
@@ -497,6 +1997,9 @@ mod tests {
             content: "Hello".to_string(),
             timestamp: Timestamp::now(),
             delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
         };
         db.save_message(&msg).unwrap();
 
@@ -508,6 +2011,218 @@ mod tests {
         assert!(messages[0].delivered);
     }
 
+    #[test]
+    fn undelivered_are_pending_sent_messages_oldest_first() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC");
+        let msg = |id: &str, direction, at, delivered, fire_and_forget| Message {
+            id: MessageId::new(id),
+            peer_id: PeerId::new("peer-1"),
+            direction,
+            content: "Hola".to_string(),
+            timestamp: Timestamp::from_millis(at),
+            delivered,
+            fire_and_forget,
+            announcement: false,
+            reply_to: None,
+        };
+        db.save_message(&msg("late", Direction::Sent, 3, false, false)).unwrap();
+        db.save_message(&msg("early", Direction::Sent, 1, false, false)).unwrap();
+        db.save_message(&msg("acked", Direction::Sent, 2, true, false)).unwrap();
+        db.save_message(&msg("no-ack", Direction::Sent, 2, false, true)).unwrap();
+        db.save_message(&msg("theirs", Direction::Received, 2, false, false)).unwrap();
+
+        let pending = db.get_undelivered(&PeerId::new("peer-1")).unwrap();
+        let ids: Vec<&str> = pending.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["early", "late"]);
+    }
+
+    #[test]
+    fn message_notes_set_search_and_remove() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        for (id, content, ts) in [("msg-1", "Av. Providencia 1234, of. 56", 1000), ("msg-2", "Nos vemos a las 5", 2000)] {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new("peer-1"),
+                direction: Direction::Received,
+                content: content.to_string(),
+                timestamp: Timestamp::from_millis(ts),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+
+        let id = MessageId::new("msg-1");
+        assert!(db.set_message_note(&id, "dirección del Dentista", Timestamp::from_millis(3000)).unwrap());
+        assert!(!db.set_message_note(&MessageId::new("nope"), "x", Timestamp::from_millis(3000)).unwrap());
+
+        let notes = db.get_message_notes(&PeerId::new("peer-1")).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note, "dirección del Dentista");
+
+        let found = db.search_message_notes("dentista", 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message.content, "Av. Providencia 1234, of. 56");
+        // LIKE wildcards in the query are taken literally
+        assert!(db.search_message_notes("%", 10).unwrap().is_empty());
+
+        // A blank note removes it
+        assert!(db.set_message_note(&id, "  ", Timestamp::from_millis(4000)).unwrap());
+        assert!(db.get_message_notes(&PeerId::new("peer-1")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_messages_within_one_conversation() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        insert_test_peer(&db, "peer-2", "Notebook");
+        for (id, peer, content, ts) in [
+            ("msg-1", "peer-1", "Compra pan", 1000),
+            ("msg-2", "peer-1", "Y tambien PAN integral", 2000),
+            ("msg-3", "peer-1", "Nos vemos a las 5", 3000),
+            ("msg-4", "peer-2", "pan para todos", 4000),
+            ("msg-5", "peer-1", "100% listo", 5000),
+        ] {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new(peer),
+                direction: Direction::Received,
+                content: content.to_string(),
+                timestamp: Timestamp::from_millis(ts),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+
+        let found = db.search_messages(&PeerId::new("peer-1"), " pan ", 10).unwrap();
+        let ids: Vec<_> = found.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["msg-2", "msg-1"]);
+        assert_eq!(db.search_messages(&PeerId::new("peer-1"), "pan", 1).unwrap().len(), 1);
+        // LIKE wildcards in the query are taken literally
+        let found = db.search_messages(&PeerId::new("peer-1"), "0%", 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id.as_str(), "msg-5");
+        assert!(db.search_messages(&PeerId::new("peer-1"), "_", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn message_fire_and_forget_roundtrip() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "Timbre");
+
+        db.save_message(&Message {
+            id: MessageId::new("msg-1"),
+            peer_id: PeerId::new("peer-1"),
+            direction: Direction::Sent,
+            content: "Ding dong".to_string(),
+            timestamp: Timestamp::now(),
+            delivered: false,
+            fire_and_forget: true,
+            announcement: false,
+            reply_to: None,
+        })
+        .unwrap();
+
+        let messages = db.get_messages(&PeerId::new("peer-1"), 1, None).unwrap();
+        assert!(messages[0].fire_and_forget);
+        assert!(!messages[0].delivered);
+    }
+
+    #[test]
+    fn replies_keep_the_message_they_answer() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "Cocina");
+
+        let reply = Message {
+            id: MessageId::new("msg-2"),
+            peer_id: PeerId::new("peer-1"),
+            direction: Direction::Sent,
+            content: "Sí, a las nueve".to_string(),
+            timestamp: Timestamp::now(),
+            delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: Some(MessageId::new("msg-1")),
+        };
+        db.save_message(&reply).unwrap();
+        let messages = db.get_messages(&PeerId::new("peer-1"), 1, None).unwrap();
+        assert_eq!(messages[0].reply_to, Some(MessageId::new("msg-1")));
+
+        let owner = PeerId::new("owner");
+        db.store_archived(&owner, &[reply]).unwrap();
+        let archived = db.get_archived(&owner, &PeerId::new("peer-1"), None, 1).unwrap();
+        assert_eq!(archived[0].reply_to, Some(MessageId::new("msg-1")));
+    }
+
+    #[test]
+    fn announcements_are_stored_and_archived_as_such() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "Cocina");
+
+        let announcement = Message {
+            id: MessageId::new("msg-1"),
+            peer_id: PeerId::new("peer-1"),
+            direction: Direction::Received,
+            content: "A cenar!".to_string(),
+            timestamp: Timestamp::now(),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: true,
+            reply_to: None,
+        };
+        db.save_message(&announcement).unwrap();
+        let messages = db.get_messages(&PeerId::new("peer-1"), 1, None).unwrap();
+        assert!(messages[0].announcement);
+
+        let owner = PeerId::new("peer-sala");
+        db.store_archived(&owner, &[announcement]).unwrap();
+        let archived = db.get_archived(&owner, &PeerId::new("peer-1"), None, 1).unwrap();
+        assert!(archived[0].announcement);
+    }
+
+    #[test]
+    fn journaled_messages_stay_pending_until_their_attempt() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "Cocina");
+        let peer = PeerId::new("peer-1");
+        let message = |id: &str, fire_and_forget| Message {
+            id: MessageId::new(id),
+            peer_id: peer.clone(),
+            direction: Direction::Sent,
+            content: "Ya voy".to_string(),
+            timestamp: Timestamp::now(),
+            delivered: false,
+            fire_and_forget,
+            announcement: false,
+            reply_to: None,
+        };
+        db.save_pending(&message("msg-1", false)).unwrap();
+        db.save_pending(&message("msg-2", true)).unwrap();
+        db.save_message(&message("msg-3", true)).unwrap();
+
+        // A pending fire-and-forget message is resent; a plain one isn't
+        assert_eq!(db.get_pending_peers().unwrap(), std::slice::from_ref(&peer));
+        let undelivered: Vec<String> = db
+            .get_undelivered(&peer)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.id.to_string())
+            .collect();
+        assert_eq!(undelivered, ["msg-1", "msg-2"]);
+
+        db.mark_delivered(&MessageId::new("msg-1")).unwrap();
+        db.clear_pending(&MessageId::new("msg-2")).unwrap();
+        assert!(db.get_pending_peers().unwrap().is_empty());
+        assert!(db.get_undelivered(&peer).unwrap().is_empty());
+    }
+
     #[test]
     fn message_mark_delivered_nonexistent() {
         let db = test_db();
@@ -519,7 +2234,8 @@ mod tests {
         let db = test_db();
         insert_test_peer(&db, "peer-1", "PC");
 
-        // Insert 3 received undelivered messages
+        // Insert 3 received messages. They are delivered (we ACKed them),
+        // but that has nothing to do with whether the user has read them.
         for i in 1..=3 {
             let msg = Message {
                 id: MessageId::new(format!("msg-{i}")),
@@ -527,7 +2243,10 @@ mod tests {
                 direction: Direction::Received,
                 content: format!("Incoming {i}"),
                 timestamp: Timestamp::from_millis(i * 1000),
-                delivered: false,
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
             };
             db.save_message(&msg).unwrap();
         }
@@ -538,16 +2257,111 @@ mod tests {
             peer_id: PeerId::new("peer-1"),
             direction: Direction::Sent,
             content: "Outgoing".to_string(),
-            timestamp: Timestamp::now(),
+            timestamp: Timestamp::from_millis(4000),
             delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
         };
         db.save_message(&sent).unwrap();
 
         assert_eq!(db.unread_count(&PeerId::new("peer-1")).unwrap(), 3);
+        assert_eq!(db.message_count(&PeerId::new("peer-1")).unwrap(), 4);
 
-        // Mark one as delivered
-        db.mark_delivered(&MessageId::new("msg-1")).unwrap();
+        // Read up to the first message
+        db.mark_read(&PeerId::new("peer-1"), Timestamp::from_millis(1000)).unwrap();
         assert_eq!(db.unread_count(&PeerId::new("peer-1")).unwrap(), 2);
+        assert_eq!(db.total_unread_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn read_watermark_never_moves_backwards() {
+        let db = test_db();
+        let peer = PeerId::new("peer-1");
+        assert_eq!(db.last_read_at(&peer).unwrap(), None);
+
+        db.mark_read(&peer, Timestamp::from_millis(5000)).unwrap();
+        db.mark_read(&peer, Timestamp::from_millis(2000)).unwrap();
+        assert_eq!(db.last_read_at(&peer).unwrap(), Some(Timestamp::from_millis(5000)));
+    }
+
+    #[test]
+    fn conversation_summaries_show_last_message_and_unread() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        insert_test_peer(&db, "peer-2", "Laptop");
+        insert_test_peer(&db, "peer-3", "Nunca escribe");
+
+        let save = |id: &str, peer: &str, direction: Direction, content: &str, ts: i64| {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new(peer),
+                direction,
+                content: content.to_string(),
+                timestamp: Timestamp::from_millis(ts),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        };
+        save("a", "peer-1", Direction::Received, "Hola", 1000);
+        save("b", "peer-1", Direction::Received, "¿Cenamos?", 2000);
+        save("c", "peer-2", Direction::Received, "Ya llegué", 3000);
+        save("d", "peer-2", Direction::Sent, "Genial", 4000);
+        db.mark_read(&PeerId::new("peer-1"), Timestamp::from_millis(1000)).unwrap();
+
+        let summaries = db.get_conversation_summaries().unwrap();
+        assert_eq!(summaries.len(), 2, "peers without messages are left out");
+
+        // Most recently active first
+        assert_eq!(summaries[0].peer_id, PeerId::new("peer-2"));
+        assert_eq!(summaries[0].last_message_preview, "Genial");
+        assert_eq!(summaries[0].last_direction, Direction::Sent);
+        assert_eq!(summaries[0].unread_count, 1);
+
+        assert_eq!(summaries[1].peer_id, PeerId::new("peer-1"));
+        assert_eq!(summaries[1].last_message_preview, "¿Cenamos?");
+        assert_eq!(summaries[1].last_timestamp, Timestamp::from_millis(2000));
+        assert_eq!(summaries[1].unread_count, 1);
+    }
+
+    #[test]
+    fn read_receipts_show_in_summaries() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC-Sala");
+        let peer = PeerId::new("peer-1");
+        let messages = [
+            ("a", Direction::Received, 1000),
+            ("b", Direction::Sent, 2000),
+            ("c", Direction::Received, 3000),
+        ];
+        for (id, direction, ts) in messages {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: peer.clone(),
+                direction,
+                content: "Hola".to_string(),
+                timestamp: Timestamp::from_millis(ts),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+
+        // Only received messages count when answering with a receipt
+        let received = db.last_received_at(&peer, Some(Timestamp::from_millis(2500))).unwrap();
+        assert_eq!(received, Some(Timestamp::from_millis(1000)));
+        assert_eq!(db.last_received_at(&peer, Some(Timestamp::from_millis(500))).unwrap(), None);
+        assert_eq!(db.last_received_at(&peer, None).unwrap(), Some(Timestamp::from_millis(3000)));
+
+        assert_eq!(db.get_conversation_summaries().unwrap()[0].read_by_peer, None);
+        db.mark_read_by_peer(&peer, Timestamp::from_millis(2000)).unwrap();
+        db.mark_read_by_peer(&peer, Timestamp::from_millis(1500)).unwrap();
+        assert_eq!(db.get_conversation_summaries().unwrap()[0].read_by_peer, Some(Timestamp::from_millis(2000)));
     }
 
     #[test]
@@ -562,6 +2376,9 @@ mod tests {
             content: "¡Hola! ¿Cómo está la niña? Está jugando en el salón.".to_string(),
             timestamp: Timestamp::now(),
             delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
         };
         db.save_message(&msg).unwrap();
 
@@ -571,4 +2388,175 @@ mod tests {
             "¡Hola! ¿Cómo está la niña? Está jugando en el salón."
         );
     }
+
+    #[test]
+    fn archived_messages_can_be_pruned_and_truncated() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC");
+        for i in 1..=4 {
+            let msg = Message {
+                id: MessageId::new(format!("msg-{i}")),
+                peer_id: PeerId::new("peer-1"),
+                direction: Direction::Received,
+                content: format!("Mensaje número {i}"),
+                timestamp: Timestamp::from_millis(i * 1000),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            };
+            db.save_message(&msg).unwrap();
+        }
+
+        // Oldest first, so the archive gets history in order
+        let pending = db.unarchived_messages(3).unwrap();
+        let ids: Vec<&str> = pending.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["msg-1", "msg-2", "msg-3"]);
+
+        let ids: Vec<MessageId> = pending.into_iter().map(|m| m.id).collect();
+        assert_eq!(db.mark_archived(&ids).unwrap(), 3);
+        assert_eq!(db.unarchived_messages(10).unwrap().len(), 1);
+
+        // Only archived messages are shortened
+        assert_eq!(db.truncate_archived(7).unwrap(), 3);
+        assert_eq!(db.get_message(&MessageId::new("msg-1")).unwrap().unwrap().content, "Mensaje…");
+        assert_eq!(db.get_message(&MessageId::new("msg-4")).unwrap().unwrap().content, "Mensaje número 4");
+
+        // A note keeps msg-1 here; the unarchived msg-4 is never pruned
+        db.set_message_note(&MessageId::new("msg-1"), "guardar", Timestamp::from_millis(5000))
+            .unwrap();
+        assert_eq!(db.prune_archived(Some(Timestamp::from_millis(2500)), 100).unwrap(), 1);
+        assert!(db.get_message(&MessageId::new("msg-2")).unwrap().is_none());
+        assert_eq!(db.prune_archived(None, 100).unwrap(), 1);
+        let left: Vec<String> = db
+            .get_messages(&PeerId::new("peer-1"), 10, None)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.id.to_string())
+            .collect();
+        assert_eq!(left, ["msg-4", "msg-1"]);
+        assert!(db.used_bytes().unwrap() > 0);
+    }
+
+    #[test]
+    fn overdue_messages_are_emailed_once() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "Abuela");
+        let sent = |id: &str, at: i64, delivered: bool, fire_and_forget: bool| Message {
+            id: MessageId::new(id),
+            peer_id: PeerId::new("peer-1"),
+            direction: Direction::Sent,
+            content: "Se fue la luz".to_string(),
+            timestamp: Timestamp::from_millis(at),
+            delivered,
+            fire_and_forget,
+            announcement: false,
+            reply_to: None,
+        };
+        db.save_message(&sent("old", 1000, false, false)).unwrap();
+        db.save_message(&sent("acked", 1500, true, false)).unwrap();
+        db.save_message(&sent("ping", 1800, false, true)).unwrap();
+        db.save_message(&sent("recent", 5000, false, false)).unwrap();
+
+        let overdue = db.get_overdue_undelivered(Timestamp::from_millis(2000)).unwrap();
+        let ids: Vec<&str> = overdue.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["old"]);
+
+        assert_eq!(db.mark_emailed(&[MessageId::new("old")]).unwrap(), 1);
+        assert!(db.get_overdue_undelivered(Timestamp::from_millis(2000)).unwrap().is_empty());
+        let later = db.get_overdue_undelivered(Timestamp::from_millis(9000)).unwrap();
+        assert_eq!(later.len(), 1);
+        assert_eq!(later[0].id.as_str(), "recent");
+    }
+
+    #[test]
+    fn scheduled_messages_come_due_in_order() {
+        let db = test_db();
+        let peer = PeerId::new("peer-1");
+        let late = db.schedule_message(&peer, "Feliz cumpleaños", Timestamp::from_millis(3000)).unwrap();
+        let early = db.schedule_message(&peer, "Ya salgo", Timestamp::from_millis(1000)).unwrap();
+        db.schedule_message(&peer, "Mañana", Timestamp::from_millis(9000)).unwrap();
+
+        let due = db.due_scheduled_messages(Timestamp::from_millis(3000)).unwrap();
+        let ids: Vec<i64> = due.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [early, late]);
+        assert_eq!(due[1].content, "Feliz cumpleaños");
+        assert_eq!(due[1].peer_id, peer);
+
+        assert!(db.remove_scheduled_message(early).unwrap());
+        assert!(!db.remove_scheduled_message(early).unwrap());
+        assert_eq!(db.due_scheduled_messages(Timestamp::from_millis(3000)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn archive_stores_each_message_once() {
+        let db = test_db();
+        let owner = PeerId::new("peer-pi");
+        let abuela = PeerId::new("peer-abuela");
+        let msg = |id: &str, content: &str, ts: i64| Message {
+            id: MessageId::new(id),
+            peer_id: abuela.clone(),
+            direction: Direction::Received,
+            content: content.to_string(),
+            timestamp: Timestamp::from_millis(ts),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+
+        db.store_archived(&owner, &[msg("m1", "Hola", 1000), msg("m2", "¿Qué tal?", 2000)])
+            .unwrap();
+        // Pushed again (e.g. the ACK was lost), m2 since edited
+        db.store_archived(&owner, &[msg("m2", "¿Qué tal estás?", 2000), msg("m3", "Chau", 3000)])
+            .unwrap();
+        // Another owner's copy of the same ID is kept apart
+        db.store_archived(&PeerId::new("peer-sala"), &[msg("m1", "Otro", 1000)])
+            .unwrap();
+
+        let page = db.get_archived(&owner, &abuela, None, 10).unwrap();
+        let contents: Vec<&str> = page.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["Chau", "¿Qué tal estás?", "Hola"]);
+
+        let older = db.get_archived(&owner, &abuela, Some(Timestamp::from_millis(3000)), 1).unwrap();
+        assert_eq!(older.len(), 1);
+        assert_eq!(older[0].id, MessageId::new("m2"));
+
+        // Archived history never shows up as this daemon's own messages
+        assert!(db.get_messages(&abuela, 10, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn cursor_pages_through_equal_timestamps() {
+        let db = test_db();
+        insert_test_peer(&db, "peer-1", "PC");
+        // Five messages in the same millisecond: timestamp alone can't page them
+        for i in 1..=5 {
+            let msg = Message {
+                id: MessageId::new(format!("msg-{i}")),
+                peer_id: PeerId::new("peer-1"),
+                direction: Direction::Received,
+                content: format!("Mensaje {i}"),
+                timestamp: Timestamp::from_millis(1000),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            };
+            db.save_message(&msg).unwrap();
+        }
+        let peer = PeerId::new("peer-1");
+        let ids = |page: &[Message]| page.iter().map(|m| m.id.to_string()).collect::<Vec<_>>();
+
+        let first = db.get_messages(&peer, 2, None).unwrap();
+        assert_eq!(ids(&first), ["msg-5", "msg-4"]);
+        let second = db.get_messages_at(&peer, 2, &MessageCursor::older_than(&first[1])).unwrap();
+        assert_eq!(ids(&second), ["msg-3", "msg-2"]);
+        let third = db.get_messages_at(&peer, 2, &MessageCursor::older_than(&second[1])).unwrap();
+        assert_eq!(ids(&third), ["msg-1"]);
+
+        // Forward again from the oldest: the next ones after it, newest first
+        let forward = db.get_messages_at(&peer, 2, &MessageCursor::newer_than(&third[0])).unwrap();
+        assert_eq!(ids(&forward), ["msg-3", "msg-2"]);
+    }
 }
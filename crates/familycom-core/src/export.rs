@@ -0,0 +1,226 @@
+//! Conversation export (`ExportConversation`).
+//!
+//! The daemon owns the database, so it writes exports itself and clients
+//! only name the conversation, the format and where to save it. Three
+//! formats:
+//!
+//! - `text`: one line per message, readable anywhere
+//!
+//!   ```text
+//!   2026-02-13 10:30:12  Abuela: Hola, como estas?
+//!   2026-02-13 10:31:02  Yo: Bien! Aqui trabajando
+//!   ```
+//!
+//! - `json`: the stored `Message`s, for scripts and other tools
+//! - `epub`: a keepsake book with a chapter per month (see `book`)
+//!
+//! Like the recap and the book, the timezone is a parameter so the output
+//! can be tested.
+
+use crate::book::Book;
+use crate::types::{Direction, Message, PeerId, Timestamp};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Label used for our own messages, same as in the TUI.
+const OWN_NAME: &str = "Yo";
+
+/// Label used for daemon-generated messages (e.g. the weekly recap).
+const SYSTEM_NAME: &str = "FamilyCom";
+
+/// File format of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Text,
+    Json,
+    Epub,
+}
+
+impl ExportFormat {
+    /// The usual file extension, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Json => "json",
+            ExportFormat::Epub => "epub",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Text => "text",
+            ExportFormat::Json => "json",
+            ExportFormat::Epub => "epub",
+        })
+    }
+}
+
+/// One conversation, ready to be written out.
+#[derive(Debug, Clone, Serialize)]
+pub struct Export {
+    pub peer_id: PeerId,
+    /// Display name of the other side of the conversation.
+    pub peer_name: String,
+    pub exported_at: Timestamp,
+    /// The conversation, oldest first.
+    pub messages: Vec<Message>,
+}
+
+impl Export {
+    /// Writes the export to `out` in `format`, with times in `tz`.
+    pub fn write<W: Write, Tz: TimeZone>(&self, format: ExportFormat, out: W, tz: &Tz) -> io::Result<()>
+    where
+        Tz::Offset: fmt::Display,
+    {
+        match format {
+            ExportFormat::Text => self.write_text(out, tz),
+            ExportFormat::Json => {
+                let mut out = out;
+                serde_json::to_writer_pretty(&mut out, self)?;
+                out.write_all(b"\n")
+            }
+            ExportFormat::Epub => {
+                let book = Book {
+                    title: format!("Conversación con {}", self.peer_name),
+                    peer_name: self.peer_name.clone(),
+                    created_at: self.exported_at,
+                    messages: self.messages.clone(),
+                    images: Vec::new(),
+                };
+                book.write_epub(out, tz).map(drop)
+            }
+        }
+    }
+
+    fn write_text<W: Write, Tz: TimeZone>(&self, mut out: W, tz: &Tz) -> io::Result<()>
+    where
+        Tz::Offset: fmt::Display,
+    {
+        writeln!(out, "Conversación con {}", self.peer_name)?;
+        writeln!(
+            out,
+            "Exportada el {} ({} mensajes)",
+            local_time(self.exported_at, tz),
+            self.messages.len()
+        )?;
+        writeln!(out)?;
+        for message in &self.messages {
+            let speaker = match message.direction {
+                Direction::Received => self.peer_name.as_str(),
+                Direction::Sent => OWN_NAME,
+                Direction::System => SYSTEM_NAME,
+            };
+            // Continuation lines are indented, so every message still
+            // starts with its time
+            let content = message.content.replace('\n', "\n    ");
+            writeln!(out, "{}  {speaker}: {content}", local_time(message.timestamp, tz))?;
+        }
+        Ok(())
+    }
+}
+
+/// "2026-02-13 10:30:12" in `tz`.
+fn local_time<Tz: TimeZone>(timestamp: Timestamp, tz: &Tz) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    DateTime::<Utc>::from_timestamp_millis(timestamp.as_millis())
+        .unwrap_or_default()
+        .with_timezone(tz)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MessageId;
+    use chrono::FixedOffset;
+
+    fn tz() -> FixedOffset {
+        FixedOffset::west_opt(3 * 3600).unwrap()
+    }
+
+    fn at(hour: u32, minute: u32) -> Timestamp {
+        let dt = tz().with_ymd_and_hms(2026, 2, 13, hour, minute, 0).unwrap();
+        Timestamp::from_millis(dt.timestamp_millis())
+    }
+
+    fn msg(ts: Timestamp, direction: Direction, content: &str) -> Message {
+        Message {
+            id: MessageId::generate(),
+            peer_id: PeerId::new("peer-1"),
+            direction,
+            content: content.to_string(),
+            timestamp: ts,
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        }
+    }
+
+    fn export() -> Export {
+        Export {
+            peer_id: PeerId::new("peer-1"),
+            peer_name: "Abuela".to_string(),
+            exported_at: at(12, 0),
+            messages: vec![
+                msg(at(10, 30), Direction::Received, "Hola, como estas?"),
+                msg(at(10, 31), Direction::Sent, "Bien!\nAqui trabajando"),
+            ],
+        }
+    }
+
+    fn written(format: ExportFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        export().write(format, &mut out, &tz()).unwrap();
+        out
+    }
+
+    #[test]
+    fn text_export_has_a_line_per_message() {
+        let text = String::from_utf8(written(ExportFormat::Text)).unwrap();
+        assert_eq!(
+            text,
+            "Conversación con Abuela\n\
+             Exportada el 2026-02-13 12:00:00 (2 mensajes)\n\
+             \n\
+             2026-02-13 10:30:00  Abuela: Hola, como estas?\n\
+             2026-02-13 10:31:00  Yo: Bien!\n    Aqui trabajando\n"
+        );
+    }
+
+    #[test]
+    fn json_export_keeps_the_messages() {
+        let value: serde_json::Value = serde_json::from_slice(&written(ExportFormat::Json)).unwrap();
+        assert_eq!(value["peer_name"], "Abuela");
+        let messages: Vec<Message> = serde_json::from_value(value["messages"].clone()).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "Bien!\nAqui trabajando");
+    }
+
+    #[test]
+    fn epub_export_is_a_book() {
+        let epub = written(ExportFormat::Epub);
+        assert!(epub.starts_with(b"PK"));
+        assert!(epub.windows(20).any(|w| w == b"application/epub+zip"));
+    }
+
+    #[test]
+    fn format_names_match_the_wire() {
+        for format in [ExportFormat::Text, ExportFormat::Json, ExportFormat::Epub] {
+            let json = serde_json::to_string(&format).unwrap();
+            assert_eq!(json, format!("\"{format}\""));
+        }
+    }
+}
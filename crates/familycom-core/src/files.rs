@@ -0,0 +1,45 @@
+//! Safe file and folder names from text that came over the network.
+//!
+//! Peer names are chosen on the other machine, so before one is used as a
+//! folder under `[downloads]` (`<root>/<peer name>/` with
+//! `per_peer_folders`) it is reduced to a single safe path component: no
+//! separators, no `..`, no hidden-file dots, no control characters.
+
+/// Reduces untrusted text to a single safe file or folder name.
+///
+/// Returns `None` if nothing usable is left.
+pub fn sanitize_component(name: &str) -> Option<String> {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Leading dots would make hidden files (or "..")
+    let cleaned = cleaned.trim().trim_start_matches('.').trim();
+    if cleaned.is_empty() || cleaned.chars().all(|c| c == '_') {
+        None
+    } else {
+        Some(cleaned.to_string())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_from_the_network_cannot_escape_the_root() {
+        assert_eq!(sanitize_component("../../etc/passwd").as_deref(), Some("_.._etc_passwd"));
+        assert_eq!(sanitize_component(".bashrc").as_deref(), Some("bashrc"));
+        assert_eq!(sanitize_component("PC de Juan").as_deref(), Some("PC de Juan"));
+        assert_eq!(sanitize_component(".."), None);
+        assert_eq!(sanitize_component("///"), None);
+    }
+}
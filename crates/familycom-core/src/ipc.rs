@@ -29,9 +29,37 @@
 //! ... later, when a message arrives ...
 //! Daemon → TUI:  {"type":"NewMessage","message":{...}}
 //! ```
+//!
+//! A subscription can be narrowed with an `EventFilter`, e.g. a status-bar
+//! widget that only counts new messages from one peer:
+//!
+//! ```text
+//! {"Subscribe":{"peers":["<peer id>"],"events":["NewMessage"]}}
+//! ```
+//!
+//! Giving it a `name` adds it alongside the connection's other
+//! subscriptions instead of replacing the filter; `Unsubscribe` cancels
+//! one and `ListSubscriptions` shows them all.
+//!
+//! Clients that would rather speak JSON-RPC 2.0 can, on the same socket;
+//! see `jsonrpc`.
+//!
+//! # Transports
+//!
+//! The JSON-lines protocol doesn't depend on the transport. Besides the
+//! Unix socket, the daemon can listen on localhost TCP or a Windows named
+//! pipe; see `IpcEndpoint`.
 
-use crate::types::{Message, MessageId, PeerId, PeerInfo, Timestamp};
+use crate::export::ExportFormat;
+use crate::types::{
+    AuditEntry, ConversationSummary, Message, MessageId, MessageNote, NoteMatch, PeerId,
+    PeerInfo, Timestamp,
+};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
 use thiserror::Error;
 
 /// Errors that can occur during IPC communication.
@@ -50,6 +78,17 @@ pub enum IpcError {
 /// Maximum IPC line length: 1 MB (same limit as the wire protocol).
 pub const MAX_IPC_LINE_LENGTH: usize = 1_048_576;
 
+/// Target size of one `MessagesChunk` line, leaving room under
+/// `MAX_IPC_LINE_LENGTH` for the JSON around the messages.
+pub const MESSAGES_CHUNK_BYTES: usize = MAX_IPC_LINE_LENGTH / 2;
+
+/// Version of the IPC request/response format, reported in `Pong`.
+///
+/// Bumped whenever a change would break existing clients (a variant or
+/// field removed or renamed), not for additions that `#[serde(default)]`
+/// already covers.
+pub const IPC_PROTOCOL_VERSION: u32 = 1;
+
 // ---------------------------------------------------------------------------
 // Client → Daemon requests
 // ---------------------------------------------------------------------------
@@ -73,6 +112,22 @@ pub enum ClientRequest {
         /// Used for pagination (loading older messages).
         #[serde(default)]
         before: Option<Timestamp>,
+        /// `next_cursor` or `prev_cursor` from an earlier `Messages`, to
+        /// continue from there instead. Takes precedence over `before`.
+        #[serde(default)]
+        cursor: Option<String>,
+    },
+
+    /// Request a page of a conversation in chronological order, for
+    /// walking it from the start (story mode). The daemon responds with
+    /// `Timeline`; pass the last ID of each page as `after` to get the next.
+    GetTimeline {
+        peer_id: PeerId,
+        /// Start right after this message; from the beginning if omitted.
+        #[serde(default)]
+        after: Option<MessageId>,
+        /// Maximum number of messages to return.
+        limit: u32,
     },
 
     /// Send a text message to a peer.
@@ -81,6 +136,120 @@ pub enum ClientRequest {
         peer_id: PeerId,
         /// The message text.
         content: String,
+        /// The message of the conversation this one answers, if it's a
+        /// reply.
+        #[serde(default)]
+        reply_to: Option<MessageId>,
+    },
+
+    /// Send a text message to a peer later. The daemon responds with `Ok`;
+    /// once `send_at` has passed (checked every minute) the message is sent
+    /// like a `SendMessage` and pushed to subscribers as `NewMessage`.
+    ScheduleMessage {
+        /// The recipient peer.
+        peer_id: PeerId,
+        /// The message text.
+        content: String,
+        /// When to send it.
+        send_at: Timestamp,
+    },
+
+    /// Send a house-wide announcement ("A cenar!") to every online peer at
+    /// once. Peers show it as a banner and notify it even in
+    /// do-not-disturb. The daemon responds with `Announced` and pushes each
+    /// sent copy as `NewMessage`.
+    Announce {
+        /// The announcement text.
+        content: String,
+    },
+
+    /// Delete a message from the local history. With `retract`, also ask
+    /// the peer to delete it; only messages we sent can be retracted, and
+    /// only to peers advertising `Capability::Retract` (else `not_supported`,
+    /// with nothing deleted). The daemon responds with `Ok` and pushes
+    /// `MessageDeleted`.
+    DeleteMessage {
+        message_id: MessageId,
+        #[serde(default)]
+        retract: bool,
+    },
+
+    /// Send a message we sent before, and that never got through, again
+    /// (see `MessageFailed`). The daemon responds with `MessageSent` once
+    /// the peer has it, and pushes `MessageDelivered`; if it fails again,
+    /// with `delivery_failed`, pushing `MessageFailed`.
+    RetryMessage {
+        message_id: MessageId,
+    },
+
+    /// Mark a conversation as read up to a point in time.
+    ///
+    /// Moves the peer's read watermark forward; unread counts are computed
+    /// from it. Peers advertising `Capability::ReadReceipts` are told, and
+    /// see their messages as read. The daemon responds with `Ok`.
+    MarkRead {
+        /// Which conversation to mark.
+        peer_id: PeerId,
+        /// Mark messages up to (and including) this timestamp as read.
+        /// If omitted, the daemon uses the newest received message's
+        /// timestamp, which comes from the peer's clock.
+        #[serde(default)]
+        up_to: Option<Timestamp>,
+    },
+
+    /// Set how a peer's messages are notified on this machine. The
+    /// settings are kept while the peer is offline and show up in its
+    /// `PeerInfo`. The daemon responds with `Ok`.
+    SetPeerNotifications {
+        /// Which peer the settings are for.
+        peer_id: PeerId,
+        /// No desktop notifications for this peer's messages.
+        muted: bool,
+        /// Whether this peer's notifications may play a sound.
+        sound: bool,
+    },
+
+    /// Turn do-not-disturb on or off by hand, overriding `quiet_hours`
+    /// until cleared with `null`. While it's on, only urgent messages are
+    /// notified and peers see us as "do not disturb". The daemon responds
+    /// with `DoNotDisturb`.
+    SetDoNotDisturb {
+        #[serde(default)]
+        enabled: Option<bool>,
+    },
+
+    /// Whether do-not-disturb is on, and why. The daemon responds with
+    /// `DoNotDisturb`.
+    GetDoNotDisturb,
+
+    /// Someone is at the keyboard (a key pressed in the TUI), which ends
+    /// automatic away. Clients send it at most every few seconds. The
+    /// daemon responds with `Ok`.
+    ReportActivity,
+
+    /// Tell the daemon which conversation this client shows (`None` for
+    /// none) and whether its window has focus. While a focused client shows
+    /// a conversation, its messages aren't notified on the desktop. Holds
+    /// until the next `SetViewing` or until the connection closes. The
+    /// daemon responds with `Ok`.
+    SetViewing {
+        peer_id: Option<PeerId>,
+        focused: bool,
+    },
+
+    /// The user is writing a message to this peer. Clients send it at most
+    /// every few seconds while keys are pressed; the daemon passes it on to
+    /// the peer if it's online and advertises `Capability::Typing`, without
+    /// storing or retrying it. The daemon responds with `Ok`.
+    ReportTyping {
+        peer_id: PeerId,
+    },
+
+    /// Turn desktop notifications on or off (`notifications_enabled`,
+    /// saved to config.toml). Unlike do-not-disturb, nothing gets through
+    /// while they're off. The daemon responds with `Ok`.
+    SetNotificationsEnabled {
+        enabled: bool,
     },
 
     /// Get the current configuration (display name, peer ID).
@@ -92,12 +261,121 @@ pub enum ClientRequest {
         name: String,
     },
 
+    /// Re-read config.toml and apply what can change while running
+    /// (display name, notifications, network interface, ...). The file
+    /// wins over `--name`. The daemon responds with `ConfigReloaded`.
+    /// On Unix, sending the daemon SIGHUP does the same.
+    ReloadConfig,
+
+    /// Get the unread total and how many peers are online, e.g. for a
+    /// status bar. Cheaper than `GetConversations` + `ListPeers`. The
+    /// daemon responds with `Summary`.
+    GetSummary,
+
+    /// Stop the daemon cleanly. The daemon responds with `ShuttingDown`,
+    /// then closes every IPC connection.
+    Shutdown,
+
+    /// Like `Shutdown`, but the daemon starts again with the same
+    /// arguments once it has stopped (e.g. to pick up `tcp_port` changes).
+    Restart,
+
+    /// Request the health of the daemon's subsystems (TCP server, IPC
+    /// server, notifications, ...). The daemon responds with `Status`.
+    GetStatus,
+
+    /// Cheap health check. The daemon responds with `Pong`, echoing
+    /// `sent_at` so the caller can measure the round trip.
+    Ping {
+        #[serde(default)]
+        sent_at: Option<Timestamp>,
+    },
+
+    /// Fetch the settings audit log (renames, etc.), newest first.
+    /// The daemon responds with `AuditLog`.
+    GetAuditLog {
+        /// Maximum number of entries to return.
+        limit: u32,
+        /// If provided, only return entries older than this timestamp.
+        #[serde(default)]
+        before: Option<Timestamp>,
+    },
+
+    /// Attach a private note to a message (a blank note removes it).
+    /// Notes stay in the local database and are never sent to peers.
+    /// The daemon responds with `Ok`.
+    SetMessageNote {
+        message_id: MessageId,
+        note: String,
+    },
+
+    /// Request the notes on a conversation's messages. The daemon responds
+    /// with `MessageNotes`.
+    GetMessageNotes {
+        peer_id: PeerId,
+    },
+
+    /// Search the text of one conversation's messages (case-insensitive).
+    /// The daemon responds with `SearchResults`, newest first, at most
+    /// `limit` of them.
+    SearchMessages {
+        peer_id: PeerId,
+        query: String,
+        limit: u32,
+    },
+
+    /// Search note text across all conversations. The daemon responds with
+    /// `NoteSearchResults`.
+    SearchNotes {
+        query: String,
+    },
+
+    /// Request a summary of every conversation (last message, unread
+    /// count). The daemon responds with `Conversations`.
+    GetConversations,
+
+    /// Write a whole conversation to a file on this machine (see
+    /// `export`). `path` must be absolute and not exist yet. The daemon
+    /// sends `ExportProgress` while it reads the history, then `Exported`.
+    ExportConversation {
+        peer_id: PeerId,
+        format: ExportFormat,
+        path: String,
+    },
+
     /// Subscribe to real-time events (new messages, peer online/offline).
     ///
     /// After subscribing, the daemon will push `ServerMessage` events
     /// to this client whenever something happens, without the client
-    /// needing to poll.
-    Subscribe,
+    /// needing to poll. Subscribing again replaces the filter.
+    ///
+    /// A client can hold several subscriptions with different filters by
+    /// naming them; an event that matches any of them is pushed once.
+    Subscribe {
+        /// Names this subscription, so it can be replaced or cancelled on
+        /// its own. Unnamed subscriptions share one slot.
+        #[serde(default)]
+        name: Option<String>,
+        /// Which events to push; the default is all of them.
+        #[serde(flatten)]
+        filter: EventFilter,
+        /// When resubscribing after a reconnect: the `seq` of the last event
+        /// received. The daemon answers `Resumed` instead of `Ok` and
+        /// replays the events missed since then.
+        #[serde(default)]
+        since: Option<String>,
+    },
+
+    /// Cancel one subscription (the unnamed one if `name` is absent).
+    /// Events stop once none are left.
+    Unsubscribe {
+        #[serde(default)]
+        name: Option<String>,
+    },
+
+    /// List this connection's subscriptions. The daemon responds with
+    /// `Subscriptions`.
+    ListSubscriptions,
 }
 
 // ---------------------------------------------------------------------------
@@ -119,9 +397,42 @@ pub enum ServerMessage {
         peers: Vec<PeerInfo>,
     },
 
-    /// Response to `GetMessages`: a page of message history.
+    /// Response to `GetMessages`: a page of message history, newest first.
     Messages {
         messages: Vec<Message>,
+        /// Pass as `cursor` for the page of older messages. `None` once
+        /// the start of the conversation is reached.
+        #[serde(default)]
+        next_cursor: Option<String>,
+        /// Pass as `cursor` for messages newer than this page.
+        #[serde(default)]
+        prev_cursor: Option<String>,
+    },
+
+    /// Part of a `GetMessages` response too large for one line. The
+    /// daemon sends the page as consecutive chunks (newest first, like
+    /// `Messages`) followed by `MessagesEnd`. Pushed events may arrive
+    /// between chunks.
+    MessagesChunk {
+        messages: Vec<Message>,
+    },
+
+    /// Ends a chunked `GetMessages` response.
+    MessagesEnd {
+        /// Number of messages across all chunks.
+        total: u32,
+        /// As in `Messages`.
+        #[serde(default)]
+        next_cursor: Option<String>,
+        #[serde(default)]
+        prev_cursor: Option<String>,
+    },
+
+    /// Response to `GetTimeline`: the next messages, oldest first. Fewer
+    /// than `limit` means the end of the conversation was reached.
+    Timeline {
+        peer_id: PeerId,
+        messages: Vec<Message>,
     },
 
     /// Acknowledgment that a message was sent (and its assigned ID).
@@ -129,17 +440,32 @@ pub enum ServerMessage {
         message_id: MessageId,
     },
 
+    /// Response to `Announce`: the copy sent to each peer that was
+    /// online. Empty if nobody was.
+    Announced {
+        message_ids: Vec<MessageId>,
+    },
+
     /// Pushed event: a new message was received from a peer.
     NewMessage {
         message: Message,
     },
 
+    /// Pushed event: several messages from one peer, oldest first. Sent
+    /// instead of one `NewMessage` each when the peer is flooding, so a
+    /// burst is shown (and notified) once.
+    NewMessages {
+        peer_id: PeerId,
+        messages: Vec<Message>,
+    },
+
     /// Pushed event: a peer came online (discovered via mDNS).
     PeerOnline {
         peer: PeerInfo,
     },
 
-    /// Pushed event: a peer went offline (mDNS goodbye or timeout).
+    /// Pushed event: a peer went offline (mDNS goodbye, or it stopped
+    /// answering the daemon's pings).
     PeerOffline {
         peer_id: PeerId,
     },
@@ -149,6 +475,36 @@ pub enum ServerMessage {
         message_id: MessageId,
     },
 
+    /// Pushed event: a message we sent couldn't be handed to the peer (it
+    /// was offline or unreachable). It stays undelivered until the peer is
+    /// seen again or `RetryMessage` gets it through.
+    MessageFailed {
+        message: Message,
+        /// Why, for showing (e.g. "connection refused").
+        reason: String,
+    },
+
+    /// Pushed event: a message was deleted, locally or retracted by the
+    /// peer who sent it.
+    MessageDeleted {
+        peer_id: PeerId,
+        message_id: MessageId,
+    },
+
+    /// Pushed event: a read receipt. The peer has read the messages we sent
+    /// them up to `up_to`; also in `ConversationSummary::read_by_peer`.
+    MessagesRead {
+        peer_id: PeerId,
+        up_to: Timestamp,
+    },
+
+    /// Pushed event: the peer is writing to us. Repeated every few seconds
+    /// while they type; nothing says when they stop, so clients drop it
+    /// once no other one follows (or a message from the peer arrives).
+    PeerTyping {
+        peer_id: PeerId,
+    },
+
     /// Response to `GetConfig`: the current local configuration.
     Config {
         /// This machine's display name.
@@ -157,15 +513,450 @@ pub enum ServerMessage {
         peer_id: PeerId,
     },
 
+    /// Response to `SetDoNotDisturb` and `GetDoNotDisturb`.
+    DoNotDisturb {
+        /// Whether do-not-disturb is on right now.
+        active: bool,
+        /// The manual setting, if any; `None` follows `quiet_hours`.
+        manual: Option<bool>,
+    },
+
+    /// Response to `ReloadConfig`: which settings changed, by their names
+    /// in config.toml.
+    ConfigReloaded {
+        /// Changed and already in effect.
+        applied: Vec<String>,
+        /// Changed in the file, but the daemon keeps running with the old
+        /// value until it's restarted.
+        restart_required: Vec<String>,
+    },
+
+    /// Response to `GetSummary`.
+    Summary {
+        /// Unread received messages across all conversations.
+        unread: u32,
+        /// Peers currently online.
+        online_peers: u32,
+    },
+
+    /// Response to `Shutdown` and `Restart`, sent before the connection
+    /// is closed.
+    ShuttingDown {
+        /// Whether the daemon will start again.
+        restart: bool,
+    },
+
+    /// Response to `GetStatus`: health of each supervised subsystem.
+    Status {
+        subsystems: Vec<SubsystemStatus>,
+        /// Set if the database was found corrupt at startup and rebuilt.
+        #[serde(default)]
+        database_recovery: Option<DatabaseRecovery>,
+    },
+
+    /// Response to `Ping`.
+    Pong {
+        /// The `sent_at` from the request, unchanged.
+        sent_at: Option<Timestamp>,
+        /// The daemon's clock when it answered.
+        daemon_time: Timestamp,
+        /// The daemon's crate version, e.g. "0.1.0".
+        version: String,
+        /// The daemon's `IPC_PROTOCOL_VERSION`.
+        protocol_version: u32,
+    },
+
+    /// Event: a supervised subsystem (mDNS discovery, the TCP server, an
+    /// IPC listener...) died and has just been restarted. `GetStatus`
+    /// reports the same counts.
+    SubsystemRestarted {
+        /// The subsystem's name, as in `SubsystemStatus::name`.
+        name: String,
+        /// How many times it has been restarted so far.
+        restarts: u32,
+        /// Why it died.
+        reason: String,
+    },
+
+    /// Response to `GetConversations`: one summary per conversation,
+    /// most recently active first.
+    Conversations {
+        conversations: Vec<ConversationSummary>,
+    },
+
+    /// Response to `GetMessageNotes`.
+    MessageNotes {
+        peer_id: PeerId,
+        notes: Vec<MessageNote>,
+    },
+
+    /// Response to `SearchMessages`: matching messages, newest first.
+    SearchResults {
+        peer_id: PeerId,
+        query: String,
+        messages: Vec<Message>,
+    },
+
+    /// Response to `SearchNotes`: matching notes, newest message first.
+    NoteSearchResults {
+        results: Vec<NoteMatch>,
+    },
+
+    /// Response to `GetAuditLog`: a page of audit entries, newest first.
+    AuditLog {
+        entries: Vec<AuditEntry>,
+    },
+
+    /// Sent while an `ExportConversation` reads the history: `exported`
+    /// of `total` messages so far.
+    ExportProgress {
+        peer_id: PeerId,
+        exported: u32,
+        total: u32,
+    },
+
+    /// Final response to `ExportConversation`.
+    Exported {
+        peer_id: PeerId,
+        /// The file written.
+        path: String,
+        /// How many messages it holds.
+        messages: u32,
+    },
+
+    /// Response to `ListSubscriptions`, ordered by name (unnamed first).
+    Subscriptions {
+        subscriptions: Vec<Subscription>,
+    },
+
+    /// Response to `Subscribe` with `since`. The `replayed` missed events
+    /// (after filtering) follow right after. If `complete` is false, older
+    /// events were no longer kept or the daemon restarted, so the client
+    /// should fetch its state again.
+    Resumed {
+        replayed: u32,
+        complete: bool,
+    },
+
     /// Error response when a request fails.
     Error {
-        /// Machine-readable error code (e.g., "peer_not_found", "db_error").
-        code: String,
+        /// Why the request failed, for clients that react to specific errors.
+        code: ErrorCode,
         /// Human-readable error description.
         message: String,
     },
 }
 
+/// Machine-readable reason carried by `ServerMessage::Error`.
+///
+/// Serialized as snake_case strings ("peer_not_found", "db_error"), the
+/// same values the field carried when it was a plain string. Codes added
+/// by newer daemons decode as `Unknown`, so older clients still parse the
+/// error and can show its message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request was malformed or its arguments were out of range.
+    InvalidRequest,
+    /// The message text failed validation (empty, too long).
+    InvalidContent,
+    /// The display name failed validation.
+    InvalidName,
+    /// No known peer has the given ID.
+    PeerNotFound,
+    /// No stored message has the given ID.
+    MessageNotFound,
+    /// The message was sent by someone else, so it can't be changed.
+    NotOwner,
+    /// The peer doesn't support the requested feature.
+    NotSupported,
+    /// The message was deleted locally but the peer couldn't be told.
+    RetractFailed,
+    /// The config file couldn't be read, parsed or written.
+    ConfigError,
+    /// A database query failed.
+    DbError,
+    /// The export file couldn't be written.
+    ExportFailed,
+    /// The connection has no subscription with the given name.
+    SubscriptionNotFound,
+    /// Kid mode is on and the peer isn't in its allow list.
+    PeerNotAllowed,
+    /// The peer couldn't be reached to hand it the message.
+    DeliveryFailed,
+    /// Something went wrong inside the daemon (e.g. a poisoned lock).
+    InternalError,
+    /// A code this build doesn't know about.
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    /// Returns the code as it appears on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::InvalidContent => "invalid_content",
+            ErrorCode::InvalidName => "invalid_name",
+            ErrorCode::PeerNotFound => "peer_not_found",
+            ErrorCode::MessageNotFound => "message_not_found",
+            ErrorCode::NotOwner => "not_owner",
+            ErrorCode::NotSupported => "not_supported",
+            ErrorCode::RetractFailed => "retract_failed",
+            ErrorCode::ConfigError => "config_error",
+            ErrorCode::DbError => "db_error",
+            ErrorCode::ExportFailed => "export_failed",
+            ErrorCode::SubscriptionNotFound => "subscription_not_found",
+            ErrorCode::PeerNotAllowed => "peer_not_allowed",
+            ErrorCode::DeliveryFailed => "delivery_failed",
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Health of one supervised daemon subsystem, as reported by `GetStatus`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubsystemStatus {
+    /// Short identifier, e.g. "tcp_server" or "ipc_server".
+    pub name: String,
+    /// Whether the subsystem is currently running.
+    pub state: SubsystemState,
+    /// How many times the subsystem has been restarted after failing.
+    pub restarts: u32,
+    /// Why the subsystem last failed, if it ever did.
+    pub last_error: Option<String>,
+}
+
+/// Details of a database corruption recovery performed at daemon startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatabaseRecovery {
+    /// Where the corrupt file was moved to.
+    pub corrupt_path: String,
+    /// Why the database was considered corrupt.
+    pub reason: String,
+    /// How many rows were copied into the new database.
+    pub salvaged_rows: u64,
+}
+
+/// Lifecycle state of a supervised subsystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubsystemState {
+    /// The subsystem's task is running.
+    Running,
+    /// The subsystem failed and is waiting out its backoff before restarting.
+    Restarting,
+}
+
+/// The `type` tags of the events the daemon pushes to subscribers.
+pub const EVENT_TYPES: &[&str] = &[
+    "NewMessage",
+    "NewMessages",
+    "PeerOnline",
+    "PeerOffline",
+    "MessageDelivered",
+    "MessageFailed",
+    "MessageDeleted",
+    "MessagesRead",
+    "PeerTyping",
+    "SubsystemRestarted",
+];
+
+/// Narrows the events a `Subscribe` receives. An empty list doesn't
+/// filter, so the default filter passes everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventFilter {
+    /// Only events about these peers. Events that aren't about a peer
+    /// (`MessageDelivered`) never match a peer filter.
+    #[serde(default)]
+    pub peers: Vec<PeerId>,
+    /// Only these event types, by their `type` tag (see `EVENT_TYPES`).
+    /// `NewMessage` also passes `NewMessages`, so a subscriber to new
+    /// messages doesn't miss the ones sent in a flood.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// One of a connection's subscriptions, as listed by `ListSubscriptions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// `None` for the unnamed subscription.
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub filter: EventFilter,
+}
+
+impl EventFilter {
+    /// Rejects event types the daemon never sends, which would otherwise
+    /// silently filter out everything.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.events.iter().find(|e| !EVENT_TYPES.contains(&e.as_str())) {
+            Some(unknown) => Err(format!(
+                "unknown event type '{unknown}' (expected one of: {})",
+                EVENT_TYPES.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether a pushed event should reach this subscriber.
+    pub fn matches(&self, event: &ServerMessage) -> bool {
+        if !self.events.is_empty() {
+            match event.event_type() {
+                Some(kind) if self.events.iter().any(|e| e == kind || (kind == "NewMessages" && e == "NewMessage")) => {}
+                _ => return false,
+            }
+        }
+        if !self.peers.is_empty() {
+            match event.event_peer() {
+                Some(peer) if self.peers.contains(peer) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl ServerMessage {
+    /// Whether more frames of the same response follow this one: the
+    /// chunks of a large history page and an export's progress reports.
+    pub fn is_partial(&self) -> bool {
+        matches!(self, ServerMessage::MessagesChunk { .. } | ServerMessage::ExportProgress { .. })
+    }
+
+    /// The `type` tag if this is a pushed event, `None` for responses.
+    pub fn event_type(&self) -> Option<&'static str> {
+        match self {
+            ServerMessage::NewMessage { .. } => Some("NewMessage"),
+            ServerMessage::NewMessages { .. } => Some("NewMessages"),
+            ServerMessage::PeerOnline { .. } => Some("PeerOnline"),
+            ServerMessage::PeerOffline { .. } => Some("PeerOffline"),
+            ServerMessage::MessageDelivered { .. } => Some("MessageDelivered"),
+            ServerMessage::MessageFailed { .. } => Some("MessageFailed"),
+            ServerMessage::MessageDeleted { .. } => Some("MessageDeleted"),
+            ServerMessage::MessagesRead { .. } => Some("MessagesRead"),
+            ServerMessage::PeerTyping { .. } => Some("PeerTyping"),
+            ServerMessage::SubsystemRestarted { .. } => Some("SubsystemRestarted"),
+            _ => None,
+        }
+    }
+
+    /// The peer a pushed event is about, if it names one.
+    pub fn event_peer(&self) -> Option<&PeerId> {
+        match self {
+            ServerMessage::NewMessage { message } | ServerMessage::MessageFailed { message, .. } => {
+                Some(&message.peer_id)
+            }
+            ServerMessage::PeerOnline { peer } => Some(&peer.id),
+            ServerMessage::PeerOffline { peer_id }
+            | ServerMessage::NewMessages { peer_id, .. }
+            | ServerMessage::MessageDeleted { peer_id, .. }
+            | ServerMessage::MessagesRead { peer_id, .. }
+            | ServerMessage::PeerTyping { peer_id } => Some(peer_id),
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Transport endpoints
+// ---------------------------------------------------------------------------
+
+/// Where an IPC server listens (or a client connects).
+///
+/// Written as `unix:<path>`, `tcp:<addr>:<port>`, or `pipe:<name>`; a
+/// bare path means a Unix socket. TCP endpoints must be on a loopback
+/// address: IPC has no authentication, so it must never be reachable from
+/// the LAN.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum IpcEndpoint {
+    /// Unix domain socket at this path.
+    Unix(PathBuf),
+    /// TCP on a loopback address, e.g. `127.0.0.1:7878`.
+    Tcp(SocketAddr),
+    /// Windows named pipe, e.g. `\\.\pipe\familycom`.
+    NamedPipe(String),
+}
+
+/// Errors from parsing an `IpcEndpoint`.
+#[derive(Debug, Error, PartialEq)]
+pub enum IpcEndpointError {
+    #[error("invalid TCP address '{0}' (expected e.g. tcp:127.0.0.1:7878)")]
+    InvalidTcpAddr(String),
+    #[error("IPC over TCP must listen on a loopback address, got {0}")]
+    NotLoopback(SocketAddr),
+    #[error("empty IPC endpoint")]
+    Empty,
+}
+
+impl IpcEndpoint {
+    /// Short name of the transport, e.g. for naming a subsystem.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IpcEndpoint::Unix(_) => "unix",
+            IpcEndpoint::Tcp(_) => "tcp",
+            IpcEndpoint::NamedPipe(_) => "pipe",
+        }
+    }
+}
+
+impl FromStr for IpcEndpoint {
+    type Err = IpcEndpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(IpcEndpointError::Empty);
+        }
+        if let Some(addr) = s.strip_prefix("tcp:") {
+            let addr: SocketAddr = addr
+                .parse()
+                .map_err(|_| IpcEndpointError::InvalidTcpAddr(addr.to_string()))?;
+            if !addr.ip().is_loopback() {
+                return Err(IpcEndpointError::NotLoopback(addr));
+            }
+            return Ok(IpcEndpoint::Tcp(addr));
+        }
+        if let Some(name) = s.strip_prefix("pipe:") {
+            return Ok(IpcEndpoint::NamedPipe(name.to_string()));
+        }
+        let path = s.strip_prefix("unix:").unwrap_or(s);
+        Ok(IpcEndpoint::Unix(PathBuf::from(path)))
+    }
+}
+
+impl TryFrom<String> for IpcEndpoint {
+    type Error = IpcEndpointError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IpcEndpoint> for String {
+    fn from(endpoint: IpcEndpoint) -> Self {
+        endpoint.to_string()
+    }
+}
+
+impl fmt::Display for IpcEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpcEndpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            IpcEndpoint::Tcp(addr) => write!(f, "tcp:{addr}"),
+            IpcEndpoint::NamedPipe(name) => write!(f, "pipe:{name}"),
+        }
+    }
+}
+
 /// Serializes a `ClientRequest` to a JSON line (with trailing newline).
 pub fn encode_request(request: &ClientRequest) -> Result<String, IpcError> {
     let mut json = serde_json::to_string(request)?;
@@ -186,12 +977,89 @@ pub fn encode_response(response: &ServerMessage) -> Result<String, IpcError> {
     Ok(json)
 }
 
+/// Splits a response into the frames to write to the client.
+///
+/// Everything is a single frame except a `Messages` response whose line
+/// would exceed `MESSAGES_CHUNK_BYTES`: that becomes `MessagesChunk`
+/// frames of about that size, then `MessagesEnd`. A message larger than
+/// a chunk on its own gets a chunk to itself.
+pub fn into_frames(response: ServerMessage) -> Vec<ServerMessage> {
+    let ServerMessage::Messages {
+        messages,
+        next_cursor,
+        prev_cursor,
+    } = response
+    else {
+        return vec![response];
+    };
+    let sizes: Vec<usize> = messages
+        .iter()
+        .map(|m| serde_json::to_vec(m).map_or(0, |json| json.len() + 1))
+        .collect();
+    if sizes.iter().sum::<usize>() <= MESSAGES_CHUNK_BYTES {
+        return vec![ServerMessage::Messages {
+            messages,
+            next_cursor,
+            prev_cursor,
+        }];
+    }
+
+    let total = messages.len() as u32;
+    let mut frames = Vec::new();
+    let mut chunk = Vec::new();
+    let mut chunk_bytes = 0;
+    for (message, size) in messages.into_iter().zip(sizes) {
+        if !chunk.is_empty() && chunk_bytes + size > MESSAGES_CHUNK_BYTES {
+            frames.push(ServerMessage::MessagesChunk {
+                messages: std::mem::take(&mut chunk),
+            });
+            chunk_bytes = 0;
+        }
+        chunk.push(message);
+        chunk_bytes += size;
+    }
+    if !chunk.is_empty() {
+        frames.push(ServerMessage::MessagesChunk { messages: chunk });
+    }
+    frames.push(ServerMessage::MessagesEnd {
+        total,
+        next_cursor,
+        prev_cursor,
+    });
+    frames
+}
+
 /// Deserializes a `ServerMessage` from a JSON line.
 pub fn decode_response(line: &str) -> Result<ServerMessage, IpcError> {
     let response = serde_json::from_str(line.trim())?;
     Ok(response)
 }
 
+/// Serializes a pushed event along with its sequence number. `seq` is an
+/// opaque string the client passes back as `Subscribe { since }` after
+/// reconnecting; it sits next to `type`, so clients that don't resume
+/// can ignore it.
+pub fn encode_event(seq: &str, event: &ServerMessage) -> Result<String, IpcError> {
+    let mut value = serde_json::to_value(event)?;
+    if let serde_json::Value::Object(fields) = &mut value {
+        fields.insert("seq".to_string(), seq.into());
+    }
+    let mut json = serde_json::to_string(&value)?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// Like `decode_response`, also returning the `seq` of a pushed event
+/// (`None` for responses).
+pub fn decode_frame(line: &str) -> Result<(ServerMessage, Option<String>), IpcError> {
+    let mut value: serde_json::Value = serde_json::from_str(line.trim())?;
+    let seq = value
+        .as_object_mut()
+        .and_then(|fields| fields.remove("seq"))
+        .and_then(|seq| seq.as_str().map(String::from));
+    Ok((serde_json::from_value(value)?, seq))
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -199,7 +1067,38 @@ pub fn decode_response(line: &str) -> Result<ServerMessage, IpcError> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::Timestamp;
+    use crate::types::{NotificationPrefs, Timestamp};
+
+    #[test]
+    fn endpoint_parsing() {
+        assert_eq!(
+            "/run/user/1000/familycom.sock".parse::<IpcEndpoint>().unwrap(),
+            IpcEndpoint::Unix(PathBuf::from("/run/user/1000/familycom.sock"))
+        );
+        assert_eq!(
+            "tcp:127.0.0.1:7878".parse::<IpcEndpoint>().unwrap(),
+            IpcEndpoint::Tcp("127.0.0.1:7878".parse().unwrap())
+        );
+        assert_eq!(
+            r"pipe:\\.\pipe\familycom".parse::<IpcEndpoint>().unwrap(),
+            IpcEndpoint::NamedPipe(r"\\.\pipe\familycom".to_string())
+        );
+        // Display round-trips
+        let endpoint: IpcEndpoint = "tcp:[::1]:7878".parse().unwrap();
+        assert_eq!(endpoint.to_string().parse::<IpcEndpoint>().unwrap(), endpoint);
+    }
+
+    #[test]
+    fn endpoint_rejects_lan_tcp() {
+        assert!(matches!(
+            "tcp:0.0.0.0:7878".parse::<IpcEndpoint>(),
+            Err(IpcEndpointError::NotLoopback(_))
+        ));
+        assert!(matches!(
+            "tcp:localhost".parse::<IpcEndpoint>(),
+            Err(IpcEndpointError::InvalidTcpAddr(_))
+        ));
+    }
 
     #[test]
     fn request_list_peers_roundtrip() {
@@ -215,11 +1114,12 @@ mod tests {
         let req = ClientRequest::SendMessage {
             peer_id: PeerId::new("peer-1"),
             content: "¡Hola desde la sala!".to_string(),
+            reply_to: None,
         };
         let json = encode_request(&req).unwrap();
         let decoded = decode_request(&json).unwrap();
         match decoded {
-            ClientRequest::SendMessage { peer_id, content } => {
+            ClientRequest::SendMessage { peer_id, content, .. } => {
                 assert_eq!(peer_id.as_str(), "peer-1");
                 assert_eq!(content, "¡Hola desde la sala!");
             }
@@ -227,12 +1127,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn send_message_reply_to_is_optional() {
+        // Older clients don't send `reply_to`
+        match decode_request(r#"{"SendMessage":{"peer_id":"peer-1","content":"hola"}}"#).unwrap() {
+            ClientRequest::SendMessage { reply_to, .. } => assert!(reply_to.is_none()),
+            _ => panic!("expected SendMessage"),
+        }
+
+        let req = ClientRequest::SendMessage {
+            peer_id: PeerId::new("peer-1"),
+            content: "Sí, a las nueve".to_string(),
+            reply_to: Some(MessageId::new("msg-1")),
+        };
+        match decode_request(&encode_request(&req).unwrap()).unwrap() {
+            ClientRequest::SendMessage { reply_to, .. } => assert_eq!(reply_to, Some(MessageId::new("msg-1"))),
+            _ => panic!("expected SendMessage"),
+        }
+    }
+
     #[test]
     fn request_get_messages_with_pagination() {
         let req = ClientRequest::GetMessages {
             peer_id: PeerId::new("peer-1"),
             limit: 50,
             before: Some(Timestamp::from_millis(1707849600000)),
+            cursor: None,
         };
         let json = encode_request(&req).unwrap();
         let decoded = decode_request(&json).unwrap();
@@ -241,15 +1161,380 @@ mod tests {
                 peer_id,
                 limit,
                 before,
+                cursor,
             } => {
                 assert_eq!(peer_id.as_str(), "peer-1");
                 assert_eq!(limit, 50);
                 assert_eq!(before.unwrap().as_millis(), 1707849600000);
+                assert!(cursor.is_none());
+            }
+            _ => panic!("expected GetMessages"),
+        }
+    }
+
+    #[test]
+    fn messages_cursors_are_optional() {
+        // Clients and daemons from before cursors existed
+        let decoded = decode_response(r#"{"type":"Messages","messages":[]}"#).unwrap();
+        assert!(matches!(
+            decoded,
+            ServerMessage::Messages {
+                next_cursor: None,
+                prev_cursor: None,
+                ..
+            }
+        ));
+        match decode_request(r#"{"GetMessages":{"peer_id":"p","limit":10,"cursor":"abc"}}"#).unwrap() {
+            ClientRequest::GetMessages { cursor, before, .. } => {
+                assert_eq!(cursor.as_deref(), Some("abc"));
+                assert!(before.is_none());
             }
             _ => panic!("expected GetMessages"),
         }
     }
 
+    #[test]
+    fn request_get_timeline_from_start() {
+        match decode_request(r#"{"GetTimeline":{"peer_id":"peer-1","limit":100}}"#).unwrap() {
+            ClientRequest::GetTimeline { peer_id, after, limit } => {
+                assert_eq!(peer_id.as_str(), "peer-1");
+                assert!(after.is_none());
+                assert_eq!(limit, 100);
+            }
+            _ => panic!("expected GetTimeline"),
+        }
+    }
+
+    #[test]
+    fn request_delete_message_defaults_to_local() {
+        match decode_request(r#"{"DeleteMessage":{"message_id":"m1"}}"#).unwrap() {
+            ClientRequest::DeleteMessage { message_id, retract } => {
+                assert_eq!(message_id.as_str(), "m1");
+                assert!(!retract);
+            }
+            _ => panic!("expected DeleteMessage"),
+        }
+    }
+
+    #[test]
+    fn reload_config_roundtrip() {
+        assert!(matches!(decode_request(r#""ReloadConfig""#).unwrap(), ClientRequest::ReloadConfig));
+
+        let response = ServerMessage::ConfigReloaded {
+            applied: vec!["display_name".to_string()],
+            restart_required: vec!["tcp_port".to_string()],
+        };
+        let encoded = encode_response(&response).unwrap();
+        assert!(encoded.starts_with(r#"{"type":"ConfigReloaded""#));
+        match decode_response(&encoded).unwrap() {
+            ServerMessage::ConfigReloaded { restart_required, .. } => assert_eq!(restart_required, ["tcp_port"]),
+            _ => panic!("expected ConfigReloaded"),
+        }
+    }
+
+    #[test]
+    fn shutdown_and_restart_requests() {
+        assert!(matches!(decode_request(r#""Shutdown""#).unwrap(), ClientRequest::Shutdown));
+        assert!(matches!(decode_request(r#""Restart""#).unwrap(), ClientRequest::Restart));
+        let encoded = encode_response(&ServerMessage::ShuttingDown { restart: true }).unwrap();
+        assert_eq!(encoded.trim(), r#"{"type":"ShuttingDown","restart":true}"#);
+    }
+
+    #[test]
+    fn request_mark_read_without_timestamp() {
+        // `up_to` is optional so simple clients can send just the peer ID
+        let decoded = decode_request(r#"{"MarkRead":{"peer_id":"peer-1"}}"#).unwrap();
+        match decoded {
+            ClientRequest::MarkRead { peer_id, up_to } => {
+                assert_eq!(peer_id.as_str(), "peer-1");
+                assert!(up_to.is_none());
+            }
+            _ => panic!("expected MarkRead"),
+        }
+    }
+
+    #[test]
+    fn peer_notifications_default_when_absent() {
+        // Older daemons don't send `notifications`
+        let json = r#"{"type":"PeerList","peers":[{"id":"p1","display_name":"Abuela","addresses":[],"last_seen_at":0,"online":false}]}"#;
+        match decode_response(json).unwrap() {
+            ServerMessage::PeerList { peers } => {
+                assert_eq!(peers[0].notifications, NotificationPrefs::default());
+            }
+            _ => panic!("expected PeerList"),
+        }
+    }
+
+    #[test]
+    fn subscribe_filter_is_optional() {
+        match decode_request(r#"{"Subscribe":{}}"#).unwrap() {
+            ClientRequest::Subscribe { name, filter, since } => {
+                assert!(name.is_none());
+                assert_eq!(filter, EventFilter::default());
+                assert!(since.is_none());
+            }
+            _ => panic!("expected Subscribe"),
+        }
+        match decode_request(r#"{"Subscribe":{"peers":["peer-1"],"events":["NewMessage"]}}"#).unwrap() {
+            ClientRequest::Subscribe { filter, .. } => {
+                assert_eq!(filter.peers, vec![PeerId::new("peer-1")]);
+                assert_eq!(filter.events, vec!["NewMessage".to_string()]);
+            }
+            _ => panic!("expected Subscribe"),
+        }
+        match decode_request(r#"{"Subscribe":{"events":["NewMessage"],"since":"abc-7"}}"#).unwrap() {
+            ClientRequest::Subscribe { filter, since, .. } => {
+                assert_eq!(filter.events, vec!["NewMessage".to_string()]);
+                assert_eq!(since.as_deref(), Some("abc-7"));
+            }
+            _ => panic!("expected Subscribe"),
+        }
+    }
+
+    #[test]
+    fn export_request_roundtrip() {
+        let json = r#"{"ExportConversation":{"peer_id":"p1","format":"epub","path":"/home/ana/abuela.epub"}}"#;
+        match decode_request(json).unwrap() {
+            ClientRequest::ExportConversation { peer_id, format, path } => {
+                assert_eq!(peer_id.as_str(), "p1");
+                assert_eq!(format, ExportFormat::Epub);
+                assert_eq!(path, "/home/ana/abuela.epub");
+            }
+            _ => panic!("expected ExportConversation"),
+        }
+
+        let resp = ServerMessage::ExportProgress {
+            peer_id: PeerId::new("p1"),
+            exported: 500,
+            total: 1200,
+        };
+        let encoded = encode_response(&resp).unwrap();
+        assert_eq!(
+            encoded.trim(),
+            r#"{"type":"ExportProgress","peer_id":"p1","exported":500,"total":1200}"#
+        );
+    }
+
+    #[test]
+    fn named_subscriptions_roundtrip() {
+        match decode_request(r#"{"Subscribe":{"name":"badge","events":["NewMessage"]}}"#).unwrap() {
+            ClientRequest::Subscribe { name, filter, .. } => {
+                assert_eq!(name.as_deref(), Some("badge"));
+                assert_eq!(filter.events, vec!["NewMessage".to_string()]);
+            }
+            _ => panic!("expected Subscribe"),
+        }
+        // Without a name it cancels the unnamed subscription
+        assert!(matches!(
+            decode_request(r#"{"Unsubscribe":{}}"#).unwrap(),
+            ClientRequest::Unsubscribe { name: None }
+        ));
+
+        let resp = ServerMessage::Subscriptions {
+            subscriptions: vec![Subscription {
+                name: Some("badge".to_string()),
+                filter: EventFilter {
+                    peers: vec![],
+                    events: vec!["NewMessage".to_string()],
+                },
+            }],
+        };
+        let encoded = encode_response(&resp).unwrap();
+        assert_eq!(
+            encoded.trim(),
+            r#"{"type":"Subscriptions","subscriptions":[{"name":"badge","peers":[],"events":["NewMessage"]}]}"#
+        );
+        match decode_response(&encoded).unwrap() {
+            ServerMessage::Subscriptions { subscriptions } => assert_eq!(subscriptions.len(), 1),
+            _ => panic!("expected Subscriptions"),
+        }
+    }
+
+    #[test]
+    fn event_seq_roundtrip() {
+        let event = ServerMessage::PeerOffline {
+            peer_id: PeerId::new("peer-1"),
+        };
+        let line = encode_event("abc-7", &event).unwrap();
+        assert!(line.ends_with('\n'));
+
+        // Clients that don't resume still read the event
+        assert!(matches!(decode_response(&line).unwrap(), ServerMessage::PeerOffline { .. }));
+
+        let (decoded, seq) = decode_frame(&line).unwrap();
+        assert!(matches!(decoded, ServerMessage::PeerOffline { .. }));
+        assert_eq!(seq.as_deref(), Some("abc-7"));
+
+        let (_, seq) = decode_frame(&encode_response(&ServerMessage::Ok).unwrap()).unwrap();
+        assert!(seq.is_none());
+    }
+
+    #[test]
+    fn event_filter_matches_peer_and_type() {
+        let online = ServerMessage::PeerOnline {
+            peer: PeerInfo {
+                id: PeerId::new("peer-1"),
+                display_name: "Abuela".to_string(),
+                addresses: vec![],
+                last_seen_at: Timestamp::from_millis(0),
+                online: true,
+                do_not_disturb: false,
+                away: false,
+                avatar: None,
+                accent_color: None,
+                capabilities: Vec::new(),
+                notifications: NotificationPrefs::default(),
+                interface_addresses: Default::default(),
+            },
+        };
+        let offline_other = ServerMessage::PeerOffline {
+            peer_id: PeerId::new("peer-2"),
+        };
+        let delivered = ServerMessage::MessageDelivered {
+            message_id: MessageId::new("m1"),
+        };
+
+        let all = EventFilter::default();
+        assert!(all.matches(&online) && all.matches(&offline_other) && all.matches(&delivered));
+
+        let one_peer = EventFilter {
+            peers: vec![PeerId::new("peer-1")],
+            events: vec![],
+        };
+        assert!(one_peer.matches(&online));
+        assert!(!one_peer.matches(&offline_other));
+        assert!(!one_peer.matches(&delivered), "events without a peer can't match a peer filter");
+
+        let only_offline = EventFilter {
+            peers: vec![],
+            events: vec!["PeerOffline".to_string()],
+        };
+        assert!(!only_offline.matches(&online));
+        assert!(only_offline.matches(&offline_other));
+        assert!(only_offline.validate().is_ok());
+
+        let typo = EventFilter {
+            peers: vec![],
+            events: vec!["NewMesage".to_string()],
+        };
+        assert!(typo.validate().unwrap_err().contains("NewMesage"));
+    }
+
+    #[test]
+    fn response_audit_log_roundtrip() {
+        use crate::types::AuditAction;
+
+        let resp = ServerMessage::AuditLog {
+            entries: vec![AuditEntry {
+                timestamp: Timestamp::from_millis(1000),
+                action: AuditAction::PeerRenamed,
+                peer_id: Some(PeerId::new("peer-1")),
+                detail: "'PC-Sala' -> 'PC de Juan'".to_string(),
+            }],
+        };
+        let json = encode_response(&resp).unwrap();
+        assert!(json.contains(r#""action":"peer_renamed""#));
+        match decode_response(&json).unwrap() {
+            ServerMessage::AuditLog { entries } => {
+                assert_eq!(entries.len(), 1);
+                assert_eq!(entries[0].action, AuditAction::PeerRenamed);
+            }
+            _ => panic!("expected AuditLog"),
+        }
+    }
+
+    #[test]
+    fn request_set_message_note_roundtrip() {
+        let req = ClientRequest::SetMessageNote {
+            message_id: MessageId::new("msg-1"),
+            note: "dirección del dentista".to_string(),
+        };
+        let json = encode_request(&req).unwrap();
+        match decode_request(&json).unwrap() {
+            ClientRequest::SetMessageNote { message_id, note } => {
+                assert_eq!(message_id, MessageId::new("msg-1"));
+                assert_eq!(note, "dirección del dentista");
+            }
+            _ => panic!("expected SetMessageNote"),
+        }
+    }
+
+    #[test]
+    fn response_conversations_roundtrip() {
+        use crate::types::Direction;
+
+        let resp = ServerMessage::Conversations {
+            conversations: vec![ConversationSummary {
+                peer_id: PeerId::new("peer-1"),
+                last_message_preview: "¿Cenamos?".to_string(),
+                last_direction: Direction::Received,
+                last_timestamp: Timestamp::from_millis(2000),
+                unread_count: 3,
+                read_by_peer: Some(Timestamp::from_millis(1000)),
+            }],
+        };
+        let json = encode_response(&resp).unwrap();
+        match decode_response(&json).unwrap() {
+            ServerMessage::Conversations { conversations } => {
+                assert_eq!(conversations.len(), 1);
+                assert_eq!(conversations[0].unread_count, 3);
+            }
+            _ => panic!("expected Conversations"),
+        }
+    }
+
+    #[test]
+    fn retry_and_message_failed_roundtrip() {
+        match decode_request(r#"{"RetryMessage":{"message_id":"m1"}}"#).unwrap() {
+            ClientRequest::RetryMessage { message_id } => assert_eq!(message_id, MessageId::new("m1")),
+            other => panic!("expected RetryMessage, got {other:?}"),
+        }
+
+        let event = ServerMessage::MessageFailed {
+            message: Message {
+                id: MessageId::new("m1"),
+                peer_id: PeerId::new("peer-1"),
+                direction: crate::types::Direction::Sent,
+                content: "¿Vienes a cenar?".to_string(),
+                timestamp: Timestamp::from_millis(0),
+                delivered: false,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            },
+            reason: "connection refused".to_string(),
+        };
+        assert_eq!(event.event_type(), Some("MessageFailed"));
+        assert_eq!(event.event_peer(), Some(&PeerId::new("peer-1")));
+        let json = encode_response(&event).unwrap();
+        match decode_response(&json).unwrap() {
+            ServerMessage::MessageFailed { message, reason } => {
+                assert_eq!(message.id, MessageId::new("m1"));
+                assert_eq!(reason, "connection refused");
+            }
+            other => panic!("expected MessageFailed, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn event_subsystem_restarted_roundtrip() {
+        let event = ServerMessage::SubsystemRestarted {
+            name: "discovery".to_string(),
+            restarts: 2,
+            reason: "exited unexpectedly".to_string(),
+        };
+        let json = encode_response(&event).unwrap();
+        assert_eq!(event.event_type(), Some("SubsystemRestarted"));
+        assert!(event.event_peer().is_none());
+        match decode_response(&json).unwrap() {
+            ServerMessage::SubsystemRestarted { name, restarts, .. } => {
+                assert_eq!(name, "discovery");
+                assert_eq!(restarts, 2);
+            }
+            _ => panic!("expected SubsystemRestarted"),
+        }
+    }
+
     #[test]
     fn response_peer_list_roundtrip() {
         let resp = ServerMessage::PeerList {
@@ -259,6 +1544,16 @@ mod tests {
                 addresses: vec!["192.168.1.5:9876".to_string()],
                 last_seen_at: Timestamp::now(),
                 online: true,
+                do_not_disturb: false,
+                away: false,
+                avatar: None,
+                accent_color: None,
+                capabilities: Vec::new(),
+                notifications: NotificationPrefs {
+                    muted: true,
+                    sound: false,
+                },
+                interface_addresses: Default::default(),
             }],
         };
         let json = encode_response(&resp).unwrap();
@@ -267,6 +1562,8 @@ mod tests {
             ServerMessage::PeerList { peers } => {
                 assert_eq!(peers.len(), 1);
                 assert_eq!(peers[0].display_name, "Computador de Mamá");
+                assert!(peers[0].notifications.muted);
+                assert!(!peers[0].notifications.sound);
             }
             _ => panic!("expected PeerList"),
         }
@@ -275,26 +1572,115 @@ mod tests {
     #[test]
     fn response_error_roundtrip() {
         let resp = ServerMessage::Error {
-            code: "peer_not_found".to_string(),
+            code: ErrorCode::PeerNotFound,
             message: "No peer with ID 'abc' exists".to_string(),
         };
         let json = encode_response(&resp).unwrap();
+        assert!(json.contains(r#""code":"peer_not_found""#));
         let decoded = decode_response(&json).unwrap();
         match decoded {
             ServerMessage::Error { code, message } => {
-                assert_eq!(code, "peer_not_found");
+                assert_eq!(code, ErrorCode::PeerNotFound);
                 assert_eq!(message, "No peer with ID 'abc' exists");
             }
             _ => panic!("expected Error"),
         }
     }
 
+    #[test]
+    fn unknown_error_code_still_decodes() {
+        let json = r#"{"type":"Error","code":"from_the_future","message":"algo"}"#;
+        match decode_response(json).unwrap() {
+            ServerMessage::Error { code, message } => {
+                assert_eq!(code, ErrorCode::Unknown);
+                assert_eq!(message, "algo");
+            }
+            _ => panic!("expected Error"),
+        }
+    }
+
+    #[test]
+    fn response_status_roundtrip() {
+        let resp = ServerMessage::Status {
+            subsystems: vec![SubsystemStatus {
+                name: "ipc_server".to_string(),
+                state: SubsystemState::Restarting,
+                restarts: 2,
+                last_error: Some("panicked: boom".to_string()),
+            }],
+            database_recovery: None,
+        };
+        let json = encode_response(&resp).unwrap();
+        assert!(json.contains(r#""state":"restarting""#));
+        match decode_response(&json).unwrap() {
+            ServerMessage::Status { subsystems, database_recovery } => {
+                assert!(database_recovery.is_none());
+                assert_eq!(subsystems.len(), 1);
+                assert_eq!(subsystems[0].restarts, 2);
+                assert_eq!(subsystems[0].state, SubsystemState::Restarting);
+            }
+            _ => panic!("expected Status"),
+        }
+    }
+
+    #[test]
+    fn ping_without_timestamp_gets_pong() {
+        // `{"Ping":{}}` is all a script has to type
+        match decode_request(r#"{"Ping":{}}"#).unwrap() {
+            ClientRequest::Ping { sent_at } => assert!(sent_at.is_none()),
+            other => panic!("expected Ping, got {other:?}"),
+        }
+
+        let resp = ServerMessage::Pong {
+            sent_at: Some(Timestamp::from_millis(1_000)),
+            daemon_time: Timestamp::from_millis(1_005),
+            version: "0.1.0".to_string(),
+            protocol_version: IPC_PROTOCOL_VERSION,
+        };
+        let json = encode_response(&resp).unwrap();
+        match decode_response(&json).unwrap() {
+            ServerMessage::Pong { sent_at, version, protocol_version, .. } => {
+                assert_eq!(sent_at, Some(Timestamp::from_millis(1_000)));
+                assert_eq!(version, "0.1.0");
+                assert_eq!(protocol_version, IPC_PROTOCOL_VERSION);
+            }
+            _ => panic!("expected Pong"),
+        }
+    }
+
+    #[test]
+    fn do_not_disturb_override_can_be_cleared() {
+        match decode_request(r#"{"SetDoNotDisturb":{"enabled":true}}"#).unwrap() {
+            ClientRequest::SetDoNotDisturb { enabled } => assert_eq!(enabled, Some(true)),
+            other => panic!("expected SetDoNotDisturb, got {other:?}"),
+        }
+        // Back to following quiet_hours
+        match decode_request(r#"{"SetDoNotDisturb":{}}"#).unwrap() {
+            ClientRequest::SetDoNotDisturb { enabled } => assert!(enabled.is_none()),
+            other => panic!("expected SetDoNotDisturb, got {other:?}"),
+        }
+
+        let json = encode_response(&ServerMessage::DoNotDisturb { active: true, manual: None }).unwrap();
+        assert_eq!(json.trim(), r#"{"type":"DoNotDisturb","active":true,"manual":null}"#);
+    }
+
+    #[test]
+    fn status_without_recovery_field_decodes() {
+        // Daemons predating corruption recovery don't send the field
+        let json = r#"{"type":"Status","subsystems":[]}"#;
+        match decode_response(json).unwrap() {
+            ServerMessage::Status { database_recovery, .. } => assert!(database_recovery.is_none()),
+            _ => panic!("expected Status"),
+        }
+    }
+
     #[test]
     fn json_lines_are_single_line() {
         // Each encoded message should be exactly one line (no embedded newlines)
         let req = ClientRequest::SendMessage {
             peer_id: PeerId::new("peer-1"),
             content: "This is a\nmultiline message".to_string(),
+            reply_to: None,
         };
         let json = encode_request(&req).unwrap();
         // The JSON itself shouldn't contain raw newlines (they're escaped as \n)
@@ -312,20 +1698,175 @@ mod tests {
                 peer_id: PeerId::new("p"),
                 limit: 10,
                 before: None,
+                cursor: None,
             },
             ClientRequest::SendMessage {
                 peer_id: PeerId::new("p"),
                 content: "hi".to_string(),
+                reply_to: None,
+            },
+            ClientRequest::ScheduleMessage {
+                peer_id: PeerId::new("p"),
+                content: "hi".to_string(),
+                send_at: Timestamp::from_millis(1_000),
+            },
+            ClientRequest::Announce {
+                content: "A cenar!".to_string(),
+            },
+            ClientRequest::MarkRead {
+                peer_id: PeerId::new("p"),
+                up_to: None,
+            },
+            ClientRequest::SetPeerNotifications {
+                peer_id: PeerId::new("p"),
+                muted: true,
+                sound: false,
+            },
+            ClientRequest::SetDoNotDisturb { enabled: Some(true) },
+            ClientRequest::GetDoNotDisturb,
+            ClientRequest::ReportActivity,
+            ClientRequest::SetViewing {
+                peer_id: Some(PeerId::new("p")),
+                focused: true,
+            },
+            ClientRequest::ReportTyping {
+                peer_id: PeerId::new("p"),
+            },
+            ClientRequest::SearchMessages {
+                peer_id: PeerId::new("p"),
+                query: "pan".to_string(),
+                limit: 50,
             },
+            ClientRequest::SetNotificationsEnabled { enabled: false },
             ClientRequest::GetConfig,
+            ClientRequest::GetStatus,
+            ClientRequest::Ping { sent_at: None },
             ClientRequest::SetDisplayName {
                 name: "New Name".to_string(),
             },
-            ClientRequest::Subscribe,
+            ClientRequest::Subscribe {
+                name: None,
+                filter: EventFilter::default(),
+                since: None,
+            },
+            ClientRequest::Unsubscribe {
+                name: Some("badge".to_string()),
+            },
+            ClientRequest::ListSubscriptions,
+            ClientRequest::ExportConversation {
+                peer_id: PeerId::new("p"),
+                format: ExportFormat::Text,
+                path: "/tmp/abuela.txt".to_string(),
+            },
         ];
         for req in requests {
             let json = encode_request(&req).unwrap();
             assert!(!json.is_empty());
         }
     }
+
+    fn history_message(i: usize, content: String) -> Message {
+        Message {
+            id: MessageId::new(format!("m{i}")),
+            peer_id: PeerId::new("p"),
+            direction: crate::types::Direction::Received,
+            content,
+            timestamp: Timestamp::from_millis(i as i64),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        }
+    }
+
+    #[test]
+    fn batched_messages_match_new_message_filters() {
+        let batch = ServerMessage::NewMessages {
+            peer_id: PeerId::new("p"),
+            messages: (0..3).map(|i| history_message(i, "hola".to_string())).collect(),
+        };
+        let json = encode_response(&batch).unwrap();
+        assert!(json.contains(r#""type":"NewMessages""#));
+        assert!(matches!(decode_response(&json).unwrap(), ServerMessage::NewMessages { messages, .. } if messages.len() == 3));
+
+        let chat_from_p = EventFilter {
+            peers: vec![PeerId::new("p")],
+            events: vec!["NewMessage".to_string()],
+        };
+        assert!(chat_from_p.matches(&batch));
+        let only_batches = EventFilter {
+            peers: vec![],
+            events: vec!["NewMessages".to_string()],
+        };
+        assert!(only_batches.validate().is_ok());
+        assert!(!only_batches.matches(&ServerMessage::NewMessage {
+            message: history_message(0, "hola".to_string()),
+        }));
+    }
+
+    #[test]
+    fn small_history_is_one_frame() {
+        let messages = (0..3).map(|i| history_message(i, "hola".to_string())).collect();
+        let frames = into_frames(ServerMessage::Messages {
+            messages,
+            next_cursor: None,
+            prev_cursor: Some("c".to_string()),
+        });
+        assert!(matches!(&frames[..], [ServerMessage::Messages { messages, .. }] if messages.len() == 3));
+        assert!(matches!(&into_frames(ServerMessage::Ok)[..], [ServerMessage::Ok]));
+    }
+
+    #[test]
+    fn large_history_is_chunked() {
+        // 2000 messages of 1 KB: about 2 MB, over the line limit as one frame
+        let messages: Vec<Message> = (0..2000).map(|i| history_message(i, "x".repeat(1024))).collect();
+        let frames = into_frames(ServerMessage::Messages {
+            messages: messages.clone(),
+            next_cursor: Some("older".to_string()),
+            prev_cursor: None,
+        });
+
+        assert!(frames.len() > 2);
+        let Some((ServerMessage::MessagesEnd { total, next_cursor, .. }, chunks)) = frames.split_last() else {
+            panic!("expected MessagesEnd last");
+        };
+        assert_eq!(*total, 2000);
+        // The cursors travel with the end of the page
+        assert_eq!(next_cursor.as_deref(), Some("older"));
+
+        let mut ids = Vec::new();
+        for frame in chunks {
+            let line = encode_response(frame).unwrap();
+            assert!(line.len() <= MAX_IPC_LINE_LENGTH);
+            match frame {
+                ServerMessage::MessagesChunk { messages } => ids.extend(messages.iter().map(|m| m.id.clone())),
+                other => panic!("expected MessagesChunk, got {other:?}"),
+            }
+        }
+        // Order is preserved across chunks
+        assert_eq!(ids, messages.iter().map(|m| m.id.clone()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn oversized_message_gets_its_own_chunk() {
+        let messages = vec![
+            history_message(0, "a".to_string()),
+            history_message(1, "b".repeat(MESSAGES_CHUNK_BYTES)),
+            history_message(2, "c".to_string()),
+        ];
+        let frames = into_frames(ServerMessage::Messages {
+            messages,
+            next_cursor: None,
+            prev_cursor: None,
+        });
+        let sizes: Vec<usize> = frames
+            .iter()
+            .filter_map(|f| match f {
+                ServerMessage::MessagesChunk { messages } => Some(messages.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sizes, vec![1, 1, 1]);
+        assert!(matches!(frames.last(), Some(ServerMessage::MessagesEnd { total: 3, .. })));
+    }
 }
@@ -0,0 +1,262 @@
+//! JSON-RPC 2.0 framing for the IPC socket.
+//!
+//! Besides its own JSON lines (see `ipc`), the daemon understands JSON-RPC
+//! 2.0 on the same socket, so editors and generic tooling can talk to it
+//! without FamilyCom-specific serialization. A line is read as JSON-RPC if
+//! it's an object with a `"jsonrpc"` member; both kinds can be mixed on
+//! one connection.
+//!
+//! Methods are the `ClientRequest` variant names and params its fields,
+//! by name. Results are the `ServerMessage` the native protocol would
+//! send, `type` included:
+//!
+//! ```text
+//! → {"jsonrpc":"2.0","id":1,"method":"ListPeers"}
+//! ← {"jsonrpc":"2.0","id":1,"result":{"type":"PeerList","peers":[...]}}
+//! → {"jsonrpc":"2.0","id":2,"method":"SendMessage","params":{"peer_id":"...","content":"Hola"}}
+//! ← {"jsonrpc":"2.0","id":2,"error":{"code":-32000,"message":"...","data":{"code":"peer_not_found"}}}
+//! ```
+//!
+//! Responses that the native protocol spreads over several lines
+//! (`MessagesChunk`, `ExportProgress`) send their earlier frames as
+//! `progress` notifications naming the request, and the last one as the
+//! result. After a `Subscribe` call, pushed events arrive as `event`
+//! notifications whose params are the event plus its `seq`. Batches aren't
+//! supported.
+//!
+//! Each line stays a single JSON value, so newline-delimited JSON-RPC
+//! clients work unchanged.
+
+use crate::ipc::{ClientRequest, ErrorCode, IpcError, ServerMessage};
+use serde_json::{json, Map, Value};
+
+/// Not a valid JSON-RPC request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// No `ClientRequest` has that name.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// The params don't fit the method, or the daemon rejected an argument
+/// (`ErrorCode::InvalidRequest`).
+pub const INVALID_PARAMS: i64 = -32602;
+/// Any other `ServerMessage::Error`; its `ErrorCode` is in `data.code`.
+pub const SERVER_ERROR: i64 = -32000;
+
+/// A decoded JSON-RPC request.
+#[derive(Debug)]
+pub struct Call {
+    /// `None` for a notification, which gets no response.
+    pub id: Option<Value>,
+    pub request: ClientRequest,
+}
+
+/// A JSON-RPC request that couldn't be decoded.
+#[derive(Debug)]
+pub struct CallError {
+    /// The request's `id`, if it got that far.
+    pub id: Option<Value>,
+    pub code: i64,
+    pub message: String,
+}
+
+/// Decodes a line if it's JSON-RPC. `None` means it isn't (or isn't JSON
+/// at all) and should be read as a native request.
+pub fn decode_call(line: &str) -> Option<Result<Call, CallError>> {
+    match serde_json::from_str(line.trim()).ok()? {
+        Value::Object(fields) if fields.contains_key("jsonrpc") => Some(parse_call(fields)),
+        Value::Array(items) if items.iter().any(|item| item.get("jsonrpc").is_some()) => Some(Err(CallError {
+            id: None,
+            code: INVALID_REQUEST,
+            message: "batch requests are not supported".to_string(),
+        })),
+        _ => None,
+    }
+}
+
+fn parse_call(mut fields: Map<String, Value>) -> Result<Call, CallError> {
+    let id = fields.remove("id");
+    let error = |code, message: String| CallError {
+        id: id.clone(),
+        code,
+        message,
+    };
+    if fields.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(error(INVALID_REQUEST, "\"jsonrpc\" must be \"2.0\"".to_string()));
+    }
+    let Some(Value::String(method)) = fields.remove("method") else {
+        return Err(error(INVALID_REQUEST, "\"method\" must be a string".to_string()));
+    };
+    let params = match fields.remove("params") {
+        None | Some(Value::Null) => Map::new(),
+        Some(Value::Object(params)) => params,
+        Some(_) => return Err(error(INVALID_PARAMS, "params must be an object (by name)".to_string())),
+    };
+
+    // Natively, requests without fields ("ListPeers") are plain strings
+    // and the rest objects
+    let unit = if params.is_empty() {
+        serde_json::from_value(Value::String(method.clone())).ok()
+    } else {
+        None
+    };
+    let request = match unit {
+        Some(request) => request,
+        None => {
+            let mut tagged = Map::new();
+            tagged.insert(method.clone(), Value::Object(params));
+            serde_json::from_value(Value::Object(tagged)).map_err(|e| {
+                if e.to_string().starts_with("unknown variant") {
+                    error(METHOD_NOT_FOUND, format!("unknown method '{method}'"))
+                } else {
+                    error(INVALID_PARAMS, e.to_string())
+                }
+            })?
+        }
+    };
+    Ok(Call { id, request })
+}
+
+/// Serializes the response to call `id`. Errors become JSON-RPC errors
+/// with the `ErrorCode` in `data`; anything else is the `result`.
+pub fn encode_response(id: &Value, response: &ServerMessage) -> Result<String, IpcError> {
+    let value = match response {
+        ServerMessage::Error { code, message } => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+                "code": error_code(*code),
+                "message": message,
+                "data": { "code": code.as_str() },
+            },
+        }),
+        _ => json!({ "jsonrpc": "2.0", "id": id, "result": serde_json::to_value(response)? }),
+    };
+    to_line(&value)
+}
+
+/// Serializes the error response for a request that couldn't be decoded.
+pub fn encode_error(error: &CallError) -> Result<String, IpcError> {
+    to_line(&json!({
+        "jsonrpc": "2.0",
+        "id": error.id.clone().unwrap_or(Value::Null),
+        "error": { "code": error.code, "message": error.message },
+    }))
+}
+
+/// Serializes a frame that comes before the response to call `id` (see
+/// `ServerMessage::is_partial`) as a `progress` notification.
+pub fn encode_progress(id: &Value, frame: &ServerMessage) -> Result<String, IpcError> {
+    to_line(&json!({
+        "jsonrpc": "2.0",
+        "method": "progress",
+        "params": { "id": id, "value": serde_json::to_value(frame)? },
+    }))
+}
+
+/// Serializes a pushed event as an `event` notification, with its `seq`
+/// next to `type` as in `ipc::encode_event`.
+pub fn encode_event(seq: &str, event: &ServerMessage) -> Result<String, IpcError> {
+    let mut params = serde_json::to_value(event)?;
+    if let Value::Object(fields) = &mut params {
+        fields.insert("seq".to_string(), seq.into());
+    }
+    to_line(&json!({ "jsonrpc": "2.0", "method": "event", "params": params }))
+}
+
+fn error_code(code: ErrorCode) -> i64 {
+    match code {
+        ErrorCode::InvalidRequest => INVALID_PARAMS,
+        _ => SERVER_ERROR,
+    }
+}
+
+fn to_line(value: &Value) -> Result<String, IpcError> {
+    let mut json = serde_json::to_string(value)?;
+    json.push('\n');
+    Ok(json)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PeerId;
+
+    fn call(line: &str) -> Call {
+        decode_call(line).expect("is JSON-RPC").expect("decodes")
+    }
+
+    fn call_error(line: &str) -> CallError {
+        decode_call(line).expect("is JSON-RPC").expect_err("fails")
+    }
+
+    #[test]
+    fn native_lines_are_not_jsonrpc() {
+        assert!(decode_call(r#""ListPeers""#).is_none());
+        assert!(decode_call(r#"{"Subscribe":{}}"#).is_none());
+        assert!(decode_call("not json").is_none());
+    }
+
+    #[test]
+    fn methods_map_to_requests() {
+        let decoded = call(r#"{"jsonrpc":"2.0","id":1,"method":"ListPeers"}"#);
+        assert_eq!(decoded.id, Some(json!(1)));
+        assert!(matches!(decoded.request, ClientRequest::ListPeers));
+
+        let decoded = call(r#"{"jsonrpc":"2.0","id":"a","method":"MarkRead","params":{"peer_id":"p1"}}"#);
+        match decoded.request {
+            ClientRequest::MarkRead { peer_id, up_to } => {
+                assert_eq!(peer_id, PeerId::new("p1"));
+                assert!(up_to.is_none());
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        // Every field optional: no params at all still works
+        let decoded = call(r#"{"jsonrpc":"2.0","method":"Subscribe"}"#);
+        assert!(decoded.id.is_none());
+        assert!(matches!(decoded.request, ClientRequest::Subscribe { .. }));
+    }
+
+    #[test]
+    fn bad_calls_get_standard_error_codes() {
+        let e = call_error(r#"{"jsonrpc":"2.0","id":7,"method":"Fly"}"#);
+        assert_eq!((e.code, e.id), (METHOD_NOT_FOUND, Some(json!(7))));
+        let e = call_error(r#"{"jsonrpc":"2.0","id":7,"method":"MarkRead","params":{}}"#);
+        assert_eq!(e.code, INVALID_PARAMS);
+        let e = call_error(r#"{"jsonrpc":"2.0","id":7,"method":"MarkRead","params":["p1"]}"#);
+        assert_eq!(e.code, INVALID_PARAMS);
+        let e = call_error(r#"{"jsonrpc":"1.0","id":7,"method":"ListPeers"}"#);
+        assert_eq!(e.code, INVALID_REQUEST);
+        let e = call_error(r#"[{"jsonrpc":"2.0","id":7,"method":"ListPeers"}]"#);
+        assert_eq!(e.code, INVALID_REQUEST);
+    }
+
+    #[test]
+    fn responses_wrap_server_messages() {
+        let ok: Value = serde_json::from_str(&encode_response(&json!(1), &ServerMessage::Ok).unwrap()).unwrap();
+        assert_eq!(ok, json!({ "jsonrpc": "2.0", "id": 1, "result": { "type": "Ok" } }));
+
+        let error = ServerMessage::Error {
+            code: ErrorCode::PeerNotFound,
+            message: "unknown peer p1".to_string(),
+        };
+        let value: Value = serde_json::from_str(&encode_response(&json!(2), &error).unwrap()).unwrap();
+        assert_eq!(value["error"]["code"], SERVER_ERROR);
+        assert_eq!(value["error"]["data"]["code"], "peer_not_found");
+        assert!(value.get("result").is_none());
+    }
+
+    #[test]
+    fn events_are_notifications() {
+        let event = ServerMessage::PeerOffline {
+            peer_id: PeerId::new("p1"),
+        };
+        let value: Value = serde_json::from_str(&encode_event("abc-3", &event).unwrap()).unwrap();
+        assert_eq!(value["method"], "event");
+        assert_eq!(value["params"]["type"], "PeerOffline");
+        assert_eq!(value["params"]["seq"], "abc-3");
+        assert!(value.get("id").is_none());
+    }
+}
@@ -1,12 +1,51 @@
 //! # familycom-core
 //!
 //! Shared library for the FamilyCom LAN messenger.
-//! Contains domain types, wire protocol, IPC protocol, database layer, and configuration.
+//! Contains domain types, wire protocol, IPC protocol, database layer, configuration,
+//! the weekly recap content, the keepsake book (EPUB) builder, conversation
+//! export, story-mode replay pacing and slash-command parsing.
 //!
 //! This crate is used by both the daemon (`familycomd`) and the TUI client (`familycom`).
+//!
+//! # Features
+//!
+//! All enabled by default. Without them only the types and the pure
+//! modules (recap, book, replay, commands) are built.
+//!
+//! - `db`: the SQLite message store (pulls in a bundled SQLite)
+//! - `protocol`: the peer-to-peer MessagePack wire protocol (framing and
+//!   blocking I/O)
+//! - `tokio`: async protocol I/O over tokio streams (implies `protocol`)
+//! - `ipc`: the JSON-lines daemon/client protocol (with its JSON-RPC 2.0
+//!   framing) and conversation export
+//! - `config`: the config file and downloads settings (implies `ipc`)
+//!
+//! # WebAssembly
+//!
+//! With only the `ipc` feature the crate builds for `wasm32-unknown-unknown`,
+//! so a browser client can share the exact types and IPC JSON:
+//!
+//! ```bash
+//! cargo build -p familycom-core --no-default-features --features ipc \
+//!     --target wasm32-unknown-unknown
+//! ```
 
+pub mod book;
+pub mod commands;
+#[cfg(feature = "config")]
 pub mod config;
+#[cfg(feature = "db")]
 pub mod db;
+#[cfg(feature = "ipc")]
+pub mod export;
+#[cfg(feature = "config")]
+pub mod files;
+#[cfg(feature = "ipc")]
 pub mod ipc;
+#[cfg(feature = "ipc")]
+pub mod jsonrpc;
+#[cfg(feature = "protocol")]
 pub mod protocol;
+pub mod recap;
+pub mod replay;
 pub mod types;
@@ -28,12 +28,26 @@
 //! # Message Types
 //!
 //! - `Chat`: a text message from one peer to another
-//! - `Ack`: confirms receipt of a `Chat` message
+//! - `Ack`: confirms receipt of a `Chat` (or `Retract`) message
+//! - `Retract`: asks the receiver to delete a message we sent earlier
+//! - `Typing`: the sender is writing to the receiver (not acknowledged)
+//! - `Read`: read receipt, how far the sender has read the receiver's messages
 //! - `Ping` / `Pong`: keepalive to detect disconnected peers
+//! - `HistoryPush` / `HistoryQuery` / `HistoryPage`: history sync with an
+//!   archive peer, for daemons in low-storage mode
+//!
+//! # I/O
+//!
+//! The framing itself is pure: `encode_frame` builds a frame and
+//! `decode_frame` / `FrameDecoder` pick frames out of whatever bytes have
+//! arrived, so any event loop can drive them. On top of that there are
+//! tokio adapters (`read_message` / `write_message`, behind the `tokio`
+//! feature) and blocking `std::io` ones in `blocking`.
 
-use crate::types::{MessageId, PeerId, Timestamp};
+use crate::types::{Message, MessageId, PeerId, Timestamp};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+#[cfg(feature = "tokio")]
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 /// Maximum frame size: 1 MB. Any frame larger than this is rejected
@@ -80,6 +94,14 @@ pub enum PeerMessage {
         content: String,
         /// When the message was created (Unix millis).
         timestamp: Timestamp,
+        /// A house-wide announcement, sent to every online peer at once.
+        /// Older peers ignore it and show a plain message.
+        #[serde(default)]
+        announcement: bool,
+        /// The message this one answers (one of the receiver's, or one of
+        /// ours it has). Older peers ignore it and show a plain message.
+        #[serde(default)]
+        reply_to: Option<MessageId>,
     },
 
     /// Acknowledgment that a message was received and stored.
@@ -91,6 +113,35 @@ pub enum PeerMessage {
         message_id: MessageId,
     },
 
+    /// Asks the receiver to delete a `Chat` we sent earlier. Acknowledged
+    /// like a `Chat`. Receivers only honour it for messages that came from
+    /// `sender_id`.
+    Retract {
+        /// The ID of the message to delete.
+        message_id: MessageId,
+        /// Who is retracting it (must be who sent it).
+        sender_id: PeerId,
+    },
+
+    /// The sender is writing a message to the receiver. Sent fire-and-forget
+    /// every few seconds while they type, and never stored; the receiver
+    /// forgets it when no other one follows.
+    Typing {
+        /// Who is typing.
+        sender_id: PeerId,
+    },
+
+    /// Read receipt: the sender has read the receiver's messages up to
+    /// `up_to`, a timestamp of one of them (so on the receiver's clock).
+    /// Sent fire-and-forget whenever the conversation is marked read; a
+    /// lost one is made up for by the next.
+    Read {
+        /// Who read them.
+        sender_id: PeerId,
+        /// Timestamp of the newest message read.
+        up_to: Timestamp,
+    },
+
     /// Keepalive ping. The receiver should respond with `Pong`.
     ///
     /// Used to detect if a TCP connection is still alive when there's
@@ -100,6 +151,34 @@ pub enum PeerMessage {
 
     /// Response to a `Ping`.
     Pong,
+
+    /// Copies part of our history to an archive peer (low-storage mode).
+    /// The archive answers with `Ack { message_id: batch_id }` once the
+    /// messages are stored; only then may the sender drop its own copies.
+    HistoryPush {
+        /// Identifies this batch in the `Ack`.
+        batch_id: MessageId,
+        /// Whose history this is (the sender).
+        owner_id: PeerId,
+        /// The messages, as stored by the owner (`peer_id` is the other
+        /// side of each conversation).
+        messages: Vec<Message>,
+    },
+
+    /// Asks an archive peer for history it holds for `owner_id`: up to
+    /// `limit` messages with `peer_id`, newest first, older than `before`.
+    /// Answered with `HistoryPage` on the same connection.
+    HistoryQuery {
+        owner_id: PeerId,
+        peer_id: PeerId,
+        before: Option<Timestamp>,
+        limit: u32,
+    },
+
+    /// Response to `HistoryQuery`.
+    HistoryPage {
+        messages: Vec<Message>,
+    },
 }
 
 /// Encodes a `PeerMessage` into a length-prefixed byte buffer.
@@ -109,7 +188,7 @@ pub enum PeerMessage {
 /// - N bytes: MessagePack-encoded payload
 ///
 /// This is the format written to TCP streams.
-pub fn encode(msg: &PeerMessage) -> Result<Vec<u8>, ProtocolError> {
+pub fn encode_frame(msg: &PeerMessage) -> Result<Vec<u8>, ProtocolError> {
     // First, serialize the message to MessagePack bytes
     let payload = rmp_serde::to_vec_named(msg)?;
 
@@ -130,15 +209,90 @@ pub fn decode(payload: &[u8]) -> Result<PeerMessage, ProtocolError> {
     Ok(msg)
 }
 
+/// Decodes the frame at the start of `buf`.
+///
+/// Returns the message and how many bytes of `buf` it took up, or `None`
+/// if `buf` doesn't hold a whole frame yet. An oversized length prefix is
+/// reported as soon as the prefix itself has arrived.
+pub fn decode_frame(buf: &[u8]) -> Result<Option<(PeerMessage, usize)>, ProtocolError> {
+    let Some(len_buf) = buf.first_chunk::<4>() else {
+        return Ok(None);
+    };
+    let length = frame_length(*len_buf)?;
+    let Some(payload) = buf.get(4..4 + length) else {
+        return Ok(None);
+    };
+    Ok(Some((decode(payload)?, 4 + length)))
+}
+
+/// Validates a length prefix and returns the payload size it announces.
+fn frame_length(len_buf: [u8; 4]) -> Result<usize, ProtocolError> {
+    let length = u32::from_be_bytes(len_buf);
+    // Reject oversized frames before allocating for them
+    if length > MAX_FRAME_SIZE {
+        return Err(ProtocolError::FrameTooLarge { size: length });
+    }
+    Ok(length as usize)
+}
+
+/// Incremental frame decoder for byte streams that arrive in arbitrary
+/// pieces (non-blocking sockets, FFI callbacks, a WASM message handler).
+///
+/// ```
+/// # use familycom_core::protocol::{encode_frame, FrameDecoder, PeerMessage};
+/// let frame = encode_frame(&PeerMessage::Ping).unwrap();
+/// let mut decoder = FrameDecoder::new();
+/// decoder.push(&frame[..3]);
+/// assert!(decoder.next_message().unwrap().is_none());
+/// decoder.push(&frame[3..]);
+/// assert_eq!(decoder.next_message().unwrap(), Some(PeerMessage::Ping));
+/// ```
+///
+/// After an error the stream is out of sync and should be closed.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Creates a decoder with nothing buffered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends bytes received from the stream.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Returns the next complete message, or `None` until more bytes are
+    /// pushed. Call it in a loop: one push can complete several frames.
+    pub fn next_message(&mut self) -> Result<Option<PeerMessage>, ProtocolError> {
+        match decode_frame(&self.buf)? {
+            Some((msg, used)) => {
+                self.buf.drain(..used);
+                Ok(Some(msg))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// How many bytes are waiting for the rest of their frame.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+}
+
 /// Writes a `PeerMessage` to an async writer (e.g., a TCP stream).
 ///
 /// This is the main function used by the daemon to send messages over the network.
 /// It handles the full process: serialize → length-prefix → write to stream.
+#[cfg(feature = "tokio")]
 pub async fn write_message<W: AsyncWriteExt + Unpin>(
     writer: &mut W,
     msg: &PeerMessage,
 ) -> Result<(), ProtocolError> {
-    let frame = encode(msg)?;
+    let frame = encode_frame(msg)?;
     writer.write_all(&frame).await?;
     // Flush to ensure the data is sent immediately, not buffered.
     // This is important for chat apps where latency matters.
@@ -153,6 +307,7 @@ pub async fn write_message<W: AsyncWriteExt + Unpin>(
 ///
 /// Returns `ProtocolError::ConnectionClosed` if the peer closes the connection
 /// (indicated by reading 0 bytes when expecting the length prefix).
+#[cfg(feature = "tokio")]
 pub async fn read_message<R: AsyncReadExt + Unpin>(
     reader: &mut R,
 ) -> Result<PeerMessage, ProtocolError> {
@@ -166,21 +321,47 @@ pub async fn read_message<R: AsyncReadExt + Unpin>(
         }
         Err(e) => return Err(ProtocolError::Io(e)),
     }
-    let length = u32::from_be_bytes(len_buf);
 
     // Step 2: Validate the frame size to prevent memory exhaustion
-    if length > MAX_FRAME_SIZE {
-        return Err(ProtocolError::FrameTooLarge { size: length });
-    }
+    let length = frame_length(len_buf)?;
 
     // Step 3: Read exactly `length` bytes of payload
-    let mut payload = vec![0u8; length as usize];
+    let mut payload = vec![0u8; length];
     reader.read_exact(&mut payload).await?;
 
     // Step 4: Deserialize from MessagePack
     decode(&payload)
 }
 
+/// Blocking `std::io` versions of `read_message` / `write_message`, for
+/// tools without an async runtime.
+pub mod blocking {
+    use super::{decode, encode_frame, frame_length, PeerMessage, ProtocolError};
+    use std::io::{ErrorKind, Read, Write};
+
+    /// Writes a `PeerMessage` frame and flushes.
+    pub fn write_message<W: Write>(writer: &mut W, msg: &PeerMessage) -> Result<(), ProtocolError> {
+        writer.write_all(&encode_frame(msg)?)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads one `PeerMessage` frame, blocking until it has fully arrived.
+    ///
+    /// Returns `ProtocolError::ConnectionClosed` on EOF before a frame starts.
+    pub fn read_message<R: Read>(reader: &mut R) -> Result<PeerMessage, ProtocolError> {
+        let mut len_buf = [0u8; 4];
+        match reader.read_exact(&mut len_buf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(ProtocolError::ConnectionClosed),
+            Err(e) => return Err(ProtocolError::Io(e)),
+        }
+        let mut payload = vec![0u8; frame_length(len_buf)?];
+        reader.read_exact(&mut payload)?;
+        decode(&payload)
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -197,10 +378,12 @@ mod tests {
             sender_name: "PC-Sala".to_string(),
             content: "¡Hola! ¿Qué tal están?".to_string(),
             timestamp: Timestamp::from_millis(1707849600000),
+            announcement: false,
+            reply_to: None,
         };
 
         // Encode to bytes
-        let frame = encode(&msg).unwrap();
+        let frame = encode_frame(&msg).unwrap();
 
         // The first 4 bytes are the length prefix
         let length = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]);
@@ -216,15 +399,79 @@ mod tests {
         let msg = PeerMessage::Ack {
             message_id: MessageId::new("msg-456"),
         };
-        let frame = encode(&msg).unwrap();
+        let frame = encode_frame(&msg).unwrap();
         let decoded = decode(&frame[4..]).unwrap();
         assert_eq!(decoded, msg);
     }
 
+    #[test]
+    fn encode_decode_retract_roundtrip() {
+        let msg = PeerMessage::Retract {
+            message_id: MessageId::new("msg-789"),
+            sender_id: PeerId::new("peer-abc"),
+        };
+        let frame = encode_frame(&msg).unwrap();
+        assert_eq!(decode(&frame[4..]).unwrap(), msg);
+    }
+
+    #[test]
+    fn encode_decode_typing_roundtrip() {
+        let msg = PeerMessage::Typing {
+            sender_id: PeerId::new("peer-abc"),
+        };
+        let frame = encode_frame(&msg).unwrap();
+        assert_eq!(decode(&frame[4..]).unwrap(), msg);
+    }
+
+    #[test]
+    fn encode_decode_read_roundtrip() {
+        let msg = PeerMessage::Read {
+            sender_id: PeerId::new("peer-abc"),
+            up_to: Timestamp::from_millis(1707849600000),
+        };
+        let frame = encode_frame(&msg).unwrap();
+        assert_eq!(decode(&frame[4..]).unwrap(), msg);
+    }
+
+    #[test]
+    fn encode_decode_history_roundtrip() {
+        let message = Message {
+            id: MessageId::new("msg-1"),
+            peer_id: PeerId::new("peer-abuela"),
+            direction: crate::types::Direction::Sent,
+            content: "¿Venís el domingo?".to_string(),
+            timestamp: Timestamp::from_millis(1707849600000),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        let messages = [
+            PeerMessage::HistoryPush {
+                batch_id: MessageId::new("batch-1"),
+                owner_id: PeerId::new("peer-pi"),
+                messages: vec![message.clone()],
+            },
+            PeerMessage::HistoryQuery {
+                owner_id: PeerId::new("peer-pi"),
+                peer_id: PeerId::new("peer-abuela"),
+                before: Some(Timestamp::from_millis(1707849600001)),
+                limit: 50,
+            },
+            PeerMessage::HistoryPage {
+                messages: vec![message],
+            },
+        ];
+        for msg in messages {
+            let frame = encode_frame(&msg).unwrap();
+            assert_eq!(decode(&frame[4..]).unwrap(), msg);
+        }
+    }
+
     #[test]
     fn encode_decode_ping_pong() {
         for msg in [PeerMessage::Ping, PeerMessage::Pong] {
-            let frame = encode(&msg).unwrap();
+            let frame = encode_frame(&msg).unwrap();
             let decoded = decode(&frame[4..]).unwrap();
             assert_eq!(decoded, msg);
         }
@@ -239,9 +486,11 @@ mod tests {
             sender_name: "PC-Sala".to_string(),
             content: "Hola mundo!".to_string(),
             timestamp: Timestamp::from_millis(1707849600000),
+            announcement: false,
+            reply_to: None,
         };
 
-        let msgpack_frame = encode(&msg).unwrap();
+        let msgpack_frame = encode_frame(&msg).unwrap();
         let json_bytes = serde_json::to_vec(&msg).unwrap();
 
         // MessagePack should be notably smaller than JSON
@@ -253,7 +502,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn frame_decoder_handles_split_and_joined_frames() {
+        let messages = [
+            PeerMessage::Ping,
+            PeerMessage::Ack {
+                message_id: MessageId::new("m1"),
+            },
+            PeerMessage::Pong,
+        ];
+        let stream: Vec<u8> = messages.iter().flat_map(|m| encode_frame(m).unwrap()).collect();
+
+        // One byte at a time: frames complete mid-push
+        let mut decoder = FrameDecoder::new();
+        let mut received = Vec::new();
+        for byte in &stream {
+            decoder.push(std::slice::from_ref(byte));
+            while let Some(msg) = decoder.next_message().unwrap() {
+                received.push(msg);
+            }
+        }
+        assert_eq!(received, messages);
+        assert_eq!(decoder.buffered(), 0);
+
+        // All at once: several frames from one push
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&stream);
+        let mut received = Vec::new();
+        while let Some(msg) = decoder.next_message().unwrap() {
+            received.push(msg);
+        }
+        assert_eq!(received, messages);
+    }
+
+    #[test]
+    fn oversized_frame_rejected_from_prefix_alone() {
+        let prefix = (MAX_FRAME_SIZE + 1).to_be_bytes();
+        assert!(matches!(decode_frame(&prefix), Err(ProtocolError::FrameTooLarge { .. })));
+        assert!(decode_frame(&prefix[..3]).unwrap().is_none());
+    }
+
+    #[test]
+    fn blocking_write_read_roundtrip() {
+        let mut wire = Vec::new();
+        blocking::write_message(&mut wire, &PeerMessage::Ping).unwrap();
+        blocking::write_message(&mut wire, &PeerMessage::Pong).unwrap();
+
+        let mut reader = std::io::Cursor::new(wire);
+        assert_eq!(blocking::read_message(&mut reader).unwrap(), PeerMessage::Ping);
+        assert_eq!(blocking::read_message(&mut reader).unwrap(), PeerMessage::Pong);
+        assert!(matches!(blocking::read_message(&mut reader), Err(ProtocolError::ConnectionClosed)));
+    }
+
     /// Tests the async read/write functions using an in-memory pipe.
+    #[cfg(feature = "tokio")]
     #[tokio::test]
     async fn async_write_read_roundtrip() {
         // tokio::io::duplex creates a pair of connected streams,
@@ -266,6 +568,8 @@ mod tests {
             sender_name: "Test".to_string(),
             content: "Mensaje asíncrono!".to_string(),
             timestamp: Timestamp::now(),
+            announcement: false,
+            reply_to: None,
         };
 
         // Write the message on one end
@@ -277,6 +581,7 @@ mod tests {
     }
 
     /// Tests that multiple messages can be sent and received in sequence.
+    #[cfg(feature = "tokio")]
     #[tokio::test]
     async fn multiple_messages_in_sequence() {
         let (mut writer, mut reader) = tokio::io::duplex(4096);
@@ -290,6 +595,8 @@ mod tests {
                 sender_name: "A".to_string(),
                 content: "First".to_string(),
                 timestamp: Timestamp::from_millis(1000),
+                announcement: false,
+                reply_to: None,
             },
             PeerMessage::Ack {
                 message_id: MessageId::new("m1"),
@@ -0,0 +1,247 @@
+//! Weekly family recap.
+//!
+//! Once a week the daemon posts a short summary into each conversation
+//! that had activity, as a `Direction::System` message:
+//!
+//! ```text
+//! Resumen semanal (10/02 - 16/02): 12 mensajes con PC-Sala
+//! (5 enviados, 7 recibidos). Dia mas activo: miercoles (6 mensajes).
+//! ```
+//!
+//! This module only does the counting and the wording; scheduling and
+//! posting live in the daemon. Keeping it free of I/O and of the local
+//! timezone (callers pass one in) makes it straightforward to test.
+
+use crate::types::{Direction, Message, Timestamp};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Weekday};
+
+/// Weekdays in `num_days_from_monday` order.
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Counts for one conversation over one week.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeeklyRecap {
+    /// Monday the recapped week started on.
+    pub week_start: NaiveDate,
+    /// Messages we sent.
+    pub sent: u32,
+    /// Messages we received.
+    pub received: u32,
+    /// The weekday with the most messages and how many, if any.
+    /// Ties go to the earlier day.
+    pub busiest_day: Option<(Weekday, u32)>,
+}
+
+impl WeeklyRecap {
+    /// Tallies `messages` (one conversation, already limited to the week
+    /// starting on `week_start`). System messages, including earlier
+    /// recaps, are ignored.
+    pub fn compose<Tz: TimeZone>(week_start: NaiveDate, messages: &[Message], tz: &Tz) -> Self {
+        let mut sent = 0;
+        let mut received = 0;
+        let mut per_day = [0u32; 7];
+
+        for msg in messages {
+            match msg.direction {
+                Direction::Sent => sent += 1,
+                Direction::Received => received += 1,
+                Direction::System => continue,
+            }
+            if let Some(dt) = tz.timestamp_millis_opt(msg.timestamp.as_millis()).earliest() {
+                per_day[dt.weekday().num_days_from_monday() as usize] += 1;
+            }
+        }
+
+        // Iterate from Sunday back to Monday so `max_by_key`, which keeps
+        // the last maximum, settles ties on the earliest day.
+        let busiest_day = (0..7usize)
+            .rev()
+            .map(|i| (i, per_day[i]))
+            .filter(|&(_, n)| n > 0)
+            .max_by_key(|&(_, n)| n)
+            .map(|(i, n)| (WEEKDAYS[i], n));
+
+        Self {
+            week_start,
+            sent,
+            received,
+            busiest_day,
+        }
+    }
+
+    /// Total messages exchanged (system messages excluded).
+    pub fn total(&self) -> u32 {
+        self.sent + self.received
+    }
+
+    /// Whether there is nothing worth posting.
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// Renders the recap text for a conversation with `peer_name`.
+    pub fn render(&self, peer_name: &str) -> String {
+        let week_end = self.week_start + Duration::days(6);
+        let mut text = format!(
+            "Resumen semanal ({} - {}): {} {} con {peer_name} ({} {}, {} {}).",
+            self.week_start.format("%d/%m"),
+            week_end.format("%d/%m"),
+            self.total(),
+            plural(self.total(), "mensaje", "mensajes"),
+            self.sent,
+            plural(self.sent, "enviado", "enviados"),
+            self.received,
+            plural(self.received, "recibido", "recibidos"),
+        );
+        if let Some((day, n)) = self.busiest_day {
+            text.push_str(&format!(
+                " Dia mas activo: {} ({n} {}).",
+                weekday_name(day),
+                plural(n, "mensaje", "mensajes")
+            ));
+        }
+        text
+    }
+}
+
+/// Returns the Monday starting the most recent *complete* week before `now`,
+/// plus the `[start, end)` range of that week as timestamps.
+pub fn previous_week<Tz: TimeZone>(now: &DateTime<Tz>) -> (NaiveDate, Timestamp, Timestamp) {
+    let today = now.date_naive();
+    let this_monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let last_monday = this_monday - Duration::days(7);
+    let tz = now.timezone();
+    (
+        last_monday,
+        local_midnight(&tz, last_monday),
+        local_midnight(&tz, this_monday),
+    )
+}
+
+/// A stable key for a week, e.g. "2026-W07", used to avoid posting twice.
+pub fn week_key(week_start: NaiveDate) -> String {
+    let iso = week_start.iso_week();
+    format!("{}-W{:02}", iso.year(), iso.week())
+}
+
+/// Start of `date` in `tz`. Falls back to UTC if midnight doesn't exist
+/// there (DST gap).
+fn local_midnight<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> Timestamp {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let millis = tz
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|dt| dt.timestamp_millis())
+        .unwrap_or_else(|| midnight.and_utc().timestamp_millis());
+    Timestamp::from_millis(millis)
+}
+
+fn plural(n: u32, one: &'static str, many: &'static str) -> &'static str {
+    if n == 1 {
+        one
+    } else {
+        many
+    }
+}
+
+fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "lunes",
+        Weekday::Tue => "martes",
+        Weekday::Wed => "miercoles",
+        Weekday::Thu => "jueves",
+        Weekday::Fri => "viernes",
+        Weekday::Sat => "sabado",
+        Weekday::Sun => "domingo",
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MessageId, PeerId};
+    use chrono::Utc;
+
+    /// Monday 2026-02-09, 00:00 UTC
+    fn monday() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 2, 9).unwrap()
+    }
+
+    fn msg(day_offset: i64, direction: Direction) -> Message {
+        let at = monday().and_hms_opt(12, 0, 0).unwrap().and_utc() + Duration::days(day_offset);
+        Message {
+            id: MessageId::generate(),
+            peer_id: PeerId::new("peer-1"),
+            direction,
+            content: "hola".to_string(),
+            timestamp: Timestamp::from_millis(at.timestamp_millis()),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        }
+    }
+
+    #[test]
+    fn counts_and_busiest_day() {
+        let messages = vec![
+            msg(0, Direction::Sent),
+            msg(2, Direction::Received),
+            msg(2, Direction::Received),
+            msg(2, Direction::Sent),
+            msg(4, Direction::Received),
+            msg(4, Direction::System),
+        ];
+        let recap = WeeklyRecap::compose(monday(), &messages, &Utc);
+        assert_eq!(recap.sent, 2);
+        assert_eq!(recap.received, 3);
+        assert_eq!(recap.busiest_day, Some((Weekday::Wed, 3)));
+    }
+
+    #[test]
+    fn busiest_day_tie_goes_to_earlier_day() {
+        let messages = vec![msg(1, Direction::Sent), msg(5, Direction::Sent)];
+        let recap = WeeklyRecap::compose(monday(), &messages, &Utc);
+        assert_eq!(recap.busiest_day, Some((Weekday::Tue, 1)));
+    }
+
+    #[test]
+    fn only_system_messages_is_empty() {
+        let recap = WeeklyRecap::compose(monday(), &[msg(0, Direction::System)], &Utc);
+        assert!(recap.is_empty());
+        assert_eq!(recap.busiest_day, None);
+    }
+
+    #[test]
+    fn render_text() {
+        let messages = vec![msg(2, Direction::Received), msg(2, Direction::Sent)];
+        let recap = WeeklyRecap::compose(monday(), &messages, &Utc);
+        assert_eq!(
+            recap.render("PC-Sala"),
+            "Resumen semanal (09/02 - 15/02): 2 mensajes con PC-Sala \
+             (1 enviado, 1 recibido). Dia mas activo: miercoles (2 mensajes)."
+        );
+    }
+
+    #[test]
+    fn previous_week_from_midweek() {
+        // Thursday 2026-02-19 -> week of Monday 2026-02-09
+        let now = Utc.with_ymd_and_hms(2026, 2, 19, 15, 0, 0).unwrap();
+        let (start, from, to) = previous_week(&now);
+        assert_eq!(start, monday());
+        assert_eq!(to.as_millis() - from.as_millis(), 7 * 24 * 3600 * 1000);
+        assert_eq!(week_key(start), "2026-W07");
+    }
+}
@@ -0,0 +1,104 @@
+//! Pacing for story mode: replaying a conversation with its original rhythm.
+//!
+//! The real gap between two messages is divided by a speed factor and then
+//! clamped, so a burst of replies stays readable and a night of silence
+//! doesn't stall the replay:
+//!
+//! ```text
+//! real gap   x60        shown
+//! 2 s        33 ms  ->  0.4 s  (min_gap)
+//! 90 s       1.5 s  ->  1.5 s
+//! 8 h        8 min  ->  4 s    (max_gap)
+//! ```
+//!
+//! Fetching the messages (`Database::get_timeline`) and drawing them are up
+//! to the client; this module is only the arithmetic.
+
+use crate::types::Timestamp;
+use std::time::Duration;
+
+/// Speed factors a client steps through, slowest first.
+pub const SPEEDS: [u32; 5] = [1, 10, 60, 600, 3600];
+
+/// How fast a replay runs and how long it may pause between messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayPacing {
+    /// How many times faster than real time.
+    pub speed: u32,
+    /// Shortest pause between two messages.
+    pub min_gap: Duration,
+    /// Longest pause between two messages.
+    pub max_gap: Duration,
+}
+
+impl Default for ReplayPacing {
+    /// A minute of conversation per second, pauses of 0.4 to 4 seconds.
+    fn default() -> Self {
+        Self {
+            speed: 60,
+            min_gap: Duration::from_millis(400),
+            max_gap: Duration::from_secs(4),
+        }
+    }
+}
+
+impl ReplayPacing {
+    /// How long to wait before showing `next`, after `previous` was shown.
+    pub fn delay(&self, previous: Timestamp, next: Timestamp) -> Duration {
+        let real = u64::try_from(next.as_millis().saturating_sub(previous.as_millis())).unwrap_or(0);
+        let scaled = Duration::from_millis(real / u64::from(self.speed.max(1)));
+        scaled.max(self.min_gap).min(self.max_gap)
+    }
+
+    /// The next speed up in `SPEEDS`, or the same pacing at the top.
+    pub fn faster(self) -> Self {
+        let speed = SPEEDS.iter().copied().find(|&s| s > self.speed).unwrap_or(self.speed);
+        Self { speed, ..self }
+    }
+
+    /// The next speed down in `SPEEDS`, or the same pacing at the bottom.
+    pub fn slower(self) -> Self {
+        let speed = SPEEDS.iter().rev().copied().find(|&s| s < self.speed).unwrap_or(self.speed);
+        Self { speed, ..self }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> Timestamp {
+        Timestamp::from_millis(secs * 1000)
+    }
+
+    #[test]
+    fn gaps_are_scaled_then_clamped() {
+        let pacing = ReplayPacing::default();
+        assert_eq!(pacing.delay(at(0), at(90)), Duration::from_millis(1500));
+        assert_eq!(pacing.delay(at(0), at(2)), pacing.min_gap);
+        assert_eq!(pacing.delay(at(0), at(8 * 3600)), pacing.max_gap);
+        // Out-of-order clocks don't produce a negative wait
+        assert_eq!(pacing.delay(at(10), at(5)), pacing.min_gap);
+    }
+
+    #[test]
+    fn speed_steps_stop_at_the_ends() {
+        let fastest = ReplayPacing {
+            speed: SPEEDS[SPEEDS.len() - 1],
+            ..ReplayPacing::default()
+        };
+        assert_eq!(fastest.faster(), fastest);
+        assert_eq!(fastest.slower().speed, 600);
+
+        let slowest = ReplayPacing {
+            speed: 1,
+            ..ReplayPacing::default()
+        };
+        assert_eq!(slowest.slower(), slowest);
+        assert_eq!(slowest.faster().speed, 10);
+    }
+}
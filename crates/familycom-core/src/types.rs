@@ -13,6 +13,7 @@
 //! types work seamlessly with both MessagePack (wire protocol) and JSON (IPC).
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 
 // ---------------------------------------------------------------------------
@@ -282,6 +283,9 @@ impl fmt::Display for Timestamp {
 pub enum Direction {
     Sent,
     Received,
+    /// Generated locally by the daemon (e.g. the weekly recap). Never
+    /// sent over the network and never counts as unread.
+    System,
 }
 
 impl Direction {
@@ -290,6 +294,7 @@ impl Direction {
         match self {
             Direction::Sent => "sent",
             Direction::Received => "received",
+            Direction::System => "system",
         }
     }
 
@@ -297,16 +302,228 @@ impl Direction {
     ///
     /// # Errors
     ///
-    /// Returns an error if the string is neither "sent" nor "received".
+    /// Returns an error if the string is not "sent", "received" or "system".
     pub fn from_db_str(s: &str) -> Result<Self, String> {
         match s {
             "sent" => Ok(Direction::Sent),
             "received" => Ok(Direction::Received),
+            "system" => Ok(Direction::System),
             other => Err(format!("invalid direction: '{other}'")),
         }
     }
 }
 
+// ---------------------------------------------------------------------------
+// Avatar — a short emoji (or a couple of characters) shown next to a peer
+// ---------------------------------------------------------------------------
+
+/// A tiny visual marker for a peer, typically a single emoji like "🐱".
+///
+/// Advertised in mDNS TXT records, which are limited in size, so real
+/// images are out of scope — an emoji renders everywhere (terminal,
+/// notifications) and fits in a handful of bytes.
+///
+/// Validated on creation:
+/// - Must not be empty (after trimming)
+/// - Maximum 4 characters (room for emoji with modifiers, or initials)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Avatar(String);
+
+/// Errors that can occur when creating an `Avatar`.
+#[derive(Debug, thiserror::Error)]
+pub enum AvatarError {
+    #[error("avatar cannot be empty")]
+    Empty,
+    #[error("avatar cannot exceed {max} characters (got {got})")]
+    TooLong { max: usize, got: usize },
+}
+
+impl Avatar {
+    /// Maximum number of characters (Unicode scalar values).
+    pub const MAX_CHARS: usize = 4;
+
+    /// Creates a new `Avatar`, validating the input.
+    pub fn new(avatar: impl Into<String>) -> Result<Self, AvatarError> {
+        let avatar = avatar.into().trim().to_string();
+        if avatar.is_empty() {
+            return Err(AvatarError::Empty);
+        }
+        let chars = avatar.chars().count();
+        if chars > Self::MAX_CHARS {
+            return Err(AvatarError::TooLong {
+                max: Self::MAX_CHARS,
+                got: chars,
+            });
+        }
+        Ok(Self(avatar))
+    }
+
+    /// Returns the avatar as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Avatar {
+    type Error = AvatarError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<Avatar> for String {
+    fn from(avatar: Avatar) -> Self {
+        avatar.0
+    }
+}
+
+impl fmt::Display for Avatar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// AccentColor — a peer's chosen highlight color
+// ---------------------------------------------------------------------------
+
+/// An RGB color a peer picks for itself, written as `#rrggbb`.
+///
+/// Used to tint the peer's name in the TUI so family members can tell
+/// conversations apart at a glance. Serialized as the hex string so it's
+/// readable in config files, TXT records and IPC JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AccentColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Error returned when parsing an `AccentColor` from a string fails.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid color '{0}': expected #rrggbb")]
+pub struct AccentColorError(String);
+
+impl AccentColor {
+    /// Parses a color in `#rrggbb` form (the leading `#` is optional).
+    pub fn parse(s: &str) -> Result<Self, AccentColorError> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AccentColorError(s.to_string()));
+        }
+        // Each pair is valid hex (checked above), so these can't fail
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+        Ok(Self {
+            r: channel(0),
+            g: channel(2),
+            b: channel(4),
+        })
+    }
+}
+
+impl TryFrom<String> for AccentColor {
+    type Error = AccentColorError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<AccentColor> for String {
+    fn from(color: AccentColor) -> Self {
+        color.to_string()
+    }
+}
+
+impl fmt::Display for AccentColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Capability — optional features a peer supports
+// ---------------------------------------------------------------------------
+
+/// An optional feature a peer advertises support for.
+///
+/// Peers list theirs in the mDNS TXT record `caps` (comma-separated, e.g.
+/// `retract`), so clients can hide actions a peer wouldn't understand
+/// instead of failing after the fact. A peer without the record (an older
+/// version) supports none of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Deleting a sent message from the peer's machine too.
+    Retract,
+    /// Receiving files.
+    FileTransfer,
+    /// Emoji reactions on messages.
+    Reactions,
+    /// Group conversations.
+    Groups,
+    /// "Is typing" notices.
+    Typing,
+    /// Telling the sender how far their messages were read.
+    ReadReceipts,
+}
+
+impl Capability {
+    /// What this build implements, and so advertises.
+    pub const SUPPORTED: &'static [Capability] = &[Capability::Retract, Capability::Typing, Capability::ReadReceipts];
+
+    /// The name used in TXT records and the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Retract => "retract",
+            Capability::FileTransfer => "file_transfer",
+            Capability::Reactions => "reactions",
+            Capability::Groups => "groups",
+            Capability::Typing => "typing",
+            Capability::ReadReceipts => "read_receipts",
+        }
+    }
+
+    /// Parses a single name; `None` if it isn't one we know.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "retract" => Some(Capability::Retract),
+            "file_transfer" => Some(Capability::FileTransfer),
+            "reactions" => Some(Capability::Reactions),
+            "groups" => Some(Capability::Groups),
+            "typing" => Some(Capability::Typing),
+            "read_receipts" => Some(Capability::ReadReceipts),
+            _ => None,
+        }
+    }
+
+    /// Parses a comma-separated list. Names we don't know come from newer
+    /// versions and are skipped, as are duplicates.
+    pub fn parse_list(s: &str) -> Vec<Capability> {
+        let mut capabilities = Vec::new();
+        for capability in s.split(',').filter_map(Self::parse) {
+            if !capabilities.contains(&capability) {
+                capabilities.push(capability);
+            }
+        }
+        capabilities
+    }
+
+    /// Formats a list the way `parse_list` reads it.
+    pub fn join(capabilities: &[Capability]) -> String {
+        capabilities.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(",")
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 // ---------------------------------------------------------------------------
 // PeerInfo — information about a discovered peer
 // ---------------------------------------------------------------------------
@@ -327,6 +544,70 @@ pub struct PeerInfo {
     pub last_seen_at: Timestamp,
     /// Whether the peer is currently reachable (based on mDNS presence).
     pub online: bool,
+    /// Whether the peer advertises do-not-disturb (its quiet hours, or
+    /// turned on by hand). Presence, like `online`: never stored.
+    #[serde(default)]
+    pub do_not_disturb: bool,
+    /// Whether the peer advertises that nobody has used it for a while
+    /// (`[away]`). Presence, like `online`: never stored.
+    #[serde(default)]
+    pub away: bool,
+    /// Optional emoji the peer chose to represent itself.
+    #[serde(default)]
+    pub avatar: Option<Avatar>,
+    /// Optional highlight color the peer chose for its name.
+    #[serde(default)]
+    pub accent_color: Option<AccentColor>,
+    /// Optional features the peer advertised; empty for older peers.
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    /// How the user wants to be notified about this peer's messages. Set
+    /// locally (never advertised), so discovery always reports the default.
+    #[serde(default)]
+    pub notifications: NotificationPrefs,
+    /// Which of `addresses` we see on which of our network interfaces
+    /// (interface name → addresses), when discovery runs on several.
+    /// Presence, like `online`: never stored.
+    #[serde(default)]
+    pub interface_addresses: BTreeMap<String, Vec<String>>,
+}
+
+/// Per-peer desktop notification settings, set with `SetPeerNotifications`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationPrefs {
+    /// No desktop notifications for this peer's messages.
+    pub muted: bool,
+    /// Whether notifications may play a sound.
+    pub sound: bool,
+}
+
+impl Default for NotificationPrefs {
+    fn default() -> Self {
+        Self {
+            muted: false,
+            sound: true,
+        }
+    }
+}
+
+impl PeerInfo {
+    /// Whether `other` advertises the same details as `self`: name,
+    /// addresses, avatar, color and capabilities. Presence (`online`,
+    /// `last_seen_at`, `interface_addresses`) and local settings
+    /// (`notifications`) are ignored.
+    pub fn same_details(&self, other: &PeerInfo) -> bool {
+        self.id == other.id
+            && self.display_name == other.display_name
+            && self.addresses == other.addresses
+            && self.avatar == other.avatar
+            && self.accent_color == other.accent_color
+            && self.capabilities == other.capabilities
+    }
+
+    /// Whether the peer advertised `capability`.
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -336,7 +617,7 @@ pub struct PeerInfo {
 /// A complete chat message with all metadata.
 ///
 /// This is the main data type stored in SQLite and displayed in the TUI.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     /// Unique identifier for this message (UUID v4).
     pub id: MessageId,
@@ -352,6 +633,332 @@ pub struct Message {
     /// - For sent messages: true if we received an ACK from the peer
     /// - For received messages: true if we sent an ACK back
     pub delivered: bool,
+    /// Sent with `DeliveryMode::FireAndForget`: no ACK is expected, so
+    /// `delivered` stays false and the message shows as "sent" rather than
+    /// pending. Always false for received messages.
+    #[serde(default)]
+    pub fire_and_forget: bool,
+    /// A house-wide announcement ("A cenar!"), sent to every online peer
+    /// at once with `Announce`. Shown as a banner and always urgent.
+    #[serde(default)]
+    pub announcement: bool,
+    /// The message this one answers, if it's a reply. It may have been
+    /// deleted since, or be older than what's loaded.
+    #[serde(default)]
+    pub reply_to: Option<MessageId>,
+}
+
+impl Message {
+    /// Text a message starts with to get through do-not-disturb.
+    pub const URGENT_PREFIX: &'static str = "!!";
+
+    /// Whether the sender marked this message urgent, by starting it with
+    /// `URGENT_PREFIX`, or sent it as an announcement.
+    pub fn is_urgent(&self) -> bool {
+        self.announcement || self.content.starts_with(Self::URGENT_PREFIX)
+    }
+
+    /// How far a sent message got, given how far the peer has read our
+    /// messages (`ConversationSummary::read_by_peer`). `None` for received
+    /// and system messages.
+    pub fn status(&self, read_by_peer: Option<Timestamp>) -> Option<MessageStatus> {
+        if self.direction != Direction::Sent {
+            return None;
+        }
+        Some(if read_by_peer.is_some_and(|read| self.timestamp <= read) {
+            MessageStatus::Read
+        } else if self.delivered {
+            MessageStatus::Delivered
+        } else if self.fire_and_forget {
+            MessageStatus::Sent
+        } else {
+            MessageStatus::Pending
+        })
+    }
+}
+
+/// Delivery state of a sent message, from `Message::status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MessageStatus {
+    /// Not ACKed yet: still being sent, or waiting for the peer to come
+    /// back online.
+    Pending,
+    /// Sent fire-and-forget, so there won't be an ACK.
+    Sent,
+    /// ACKed by the peer's daemon.
+    Delivered,
+    /// The peer opened the conversation after it arrived. Only peers
+    /// advertising `Capability::ReadReceipts` say so.
+    Read,
+}
+
+// ---------------------------------------------------------------------------
+// MessageCursor — a position for paging through a conversation
+// ---------------------------------------------------------------------------
+
+/// Which way a `MessageCursor` pages from its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageDirection {
+    /// Messages before the position (scrolling back).
+    Older,
+    /// Messages after the position (catching up).
+    Newer,
+}
+
+/// A position in a conversation for paging with `GetMessages`.
+///
+/// Clients see cursors only as opaque strings (`next_cursor`,
+/// `prev_cursor`) that they hand back unchanged. Positions are
+/// (timestamp, id) pairs, so paging never skips or repeats messages that
+/// share a timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageCursor {
+    pub timestamp: Timestamp,
+    pub id: MessageId,
+    pub direction: PageDirection,
+}
+
+impl MessageCursor {
+    /// A cursor for the messages older than `msg`.
+    pub fn older_than(msg: &Message) -> Self {
+        Self {
+            timestamp: msg.timestamp,
+            id: msg.id.clone(),
+            direction: PageDirection::Older,
+        }
+    }
+
+    /// A cursor for the messages newer than `msg`.
+    pub fn newer_than(msg: &Message) -> Self {
+        Self {
+            direction: PageDirection::Newer,
+            ..Self::older_than(msg)
+        }
+    }
+
+    /// The string handed to clients. Hex, so nobody is tempted to build
+    /// one by hand.
+    pub fn encode(&self) -> String {
+        let direction = match self.direction {
+            PageDirection::Older => 'o',
+            PageDirection::Newer => 'n',
+        };
+        format!("{direction}{}.{}", self.timestamp.as_millis(), self.id)
+            .bytes()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// Parses a string made by `encode`; `None` for anything else.
+    pub fn decode(s: &str) -> Option<Self> {
+        if !s.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let plain = String::from_utf8(bytes).ok()?;
+        let (direction, rest) = plain.split_at_checked(1)?;
+        let direction = match direction {
+            "o" => PageDirection::Older,
+            "n" => PageDirection::Newer,
+            _ => return None,
+        };
+        let (timestamp, id) = rest.split_once('.')?;
+        Some(Self {
+            timestamp: Timestamp::from_millis(timestamp.parse().ok()?),
+            id: MessageId::new(id),
+            direction,
+        })
+    }
+
+    /// The cursors to return with a page of messages (newest first)
+    /// fetched with `limit` from `from` (the latest messages if `None`):
+    /// `(next, prev)`, for older and for newer messages.
+    ///
+    /// `next` is `None` once the start of the conversation is reached.
+    /// `prev` is only `None` for an empty conversation; an empty page
+    /// when catching up returns the same cursor, to try again later.
+    pub fn for_page(messages: &[Message], limit: u32, from: Option<&MessageCursor>) -> (Option<Self>, Option<Self>) {
+        let going_older = from.is_none_or(|c| c.direction == PageDirection::Older);
+        let next = messages
+            .last()
+            .filter(|_| !going_older || messages.len() >= limit as usize)
+            .map(Self::older_than);
+        let prev = match messages.first() {
+            Some(newest) => Some(Self::newer_than(newest)),
+            None => from.filter(|c| c.direction == PageDirection::Newer).cloned(),
+        };
+        (next, prev)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// DeliveryMode — whether sends to a peer wait for an ACK
+// ---------------------------------------------------------------------------
+
+/// How messages to a peer are delivered.
+///
+/// Chosen per peer in the config (`[delivery]`). Automation peers such as a
+/// doorbell script don't need confirmation, and waiting up to the ACK
+/// timeout for each send only slows them down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryMode {
+    /// Wait for the peer's ACK and mark the message delivered.
+    #[default]
+    AckRequired,
+    /// Write the message and return without waiting for an ACK.
+    FireAndForget,
+}
+
+// ---------------------------------------------------------------------------
+// MessageNote — a private annotation on a message
+// ---------------------------------------------------------------------------
+
+/// A private note the user attached to a message, e.g. "esta es la
+/// dirección del dentista".
+///
+/// Notes live only in the local database: they are not part of `Message`
+/// or any `PeerMessage`, so they are never sent to peers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageNote {
+    /// The annotated message.
+    pub message_id: MessageId,
+    /// The note text.
+    pub note: String,
+    /// When the note was last written.
+    pub updated_at: Timestamp,
+}
+
+/// A message waiting to be sent at a set time (`ScheduleMessage`).
+///
+/// It becomes a regular `Message` when the daemon dispatches it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledMessage {
+    /// Row ID in the local database.
+    pub id: i64,
+    /// The recipient peer.
+    pub peer_id: PeerId,
+    /// The message text.
+    pub content: String,
+    /// When to send it.
+    pub send_at: Timestamp,
+}
+
+/// A note matching a search, with the message it annotates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteMatch {
+    pub note: MessageNote,
+    pub message: Message,
+}
+
+// ---------------------------------------------------------------------------
+// ConversationSummary — one row of the conversation list
+// ---------------------------------------------------------------------------
+
+/// The latest state of a conversation with one peer: what was said last,
+/// when, and how many received messages are still unread.
+///
+/// Used by the TUI peer list to show WhatsApp-style previews.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    /// The other party.
+    pub peer_id: PeerId,
+    /// The start of the last message, on a single line.
+    pub last_message_preview: String,
+    /// Who wrote the last message.
+    pub last_direction: Direction,
+    /// When the last message was created.
+    pub last_timestamp: Timestamp,
+    /// Received messages newer than the read watermark.
+    pub unread_count: u32,
+    /// The peer's read watermark for our messages, from their last read
+    /// receipt: sent messages at or before it have been read. `None` if
+    /// they never sent one.
+    #[serde(default)]
+    pub read_by_peer: Option<Timestamp>,
+}
+
+impl ConversationSummary {
+    /// Maximum length of `last_message_preview`, in characters.
+    pub const PREVIEW_CHARS: usize = 60;
+
+    /// Builds a preview from a message body: newlines are folded into
+    /// spaces and long text is cut at `PREVIEW_CHARS` with an ellipsis.
+    pub fn preview(content: &str) -> String {
+        let single_line: String = content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if single_line.chars().count() > Self::PREVIEW_CHARS {
+            let cut: String = single_line.chars().take(Self::PREVIEW_CHARS - 1).collect();
+            format!("{}…", cut.trim_end())
+        } else {
+            single_line
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// AuditEntry — a recorded settings change
+// ---------------------------------------------------------------------------
+
+/// The kind of settings change (or blocked contact) recorded in the audit
+/// log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// This machine's display name was changed (via IPC).
+    DisplayNameChanged,
+    /// A peer announced itself under a different name than last time.
+    PeerRenamed,
+    /// A peer outside kid mode's allow list tried to reach this machine,
+    /// or was written to from it.
+    KidModeBlocked,
+}
+
+impl AuditAction {
+    /// Returns the string representation used in the database.
+    pub fn as_db_str(&self) -> &'static str {
+        match self {
+            AuditAction::DisplayNameChanged => "display_name_changed",
+            AuditAction::PeerRenamed => "peer_renamed",
+            AuditAction::KidModeBlocked => "kid_mode_blocked",
+        }
+    }
+
+    /// Parses an action from its database string representation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the string is not a known action.
+    pub fn from_db_str(s: &str) -> Result<Self, String> {
+        match s {
+            "display_name_changed" => Ok(AuditAction::DisplayNameChanged),
+            "peer_renamed" => Ok(AuditAction::PeerRenamed),
+            "kid_mode_blocked" => Ok(AuditAction::KidModeBlocked),
+            other => Err(format!("invalid audit action: '{other}'")),
+        }
+    }
+}
+
+/// One entry in the settings audit log.
+///
+/// Lets parents see when and how settings changed, e.g. when a kid
+/// renamed their machine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the change happened (or was noticed, for peer renames).
+    pub timestamp: Timestamp,
+    /// What kind of change it was.
+    pub action: AuditAction,
+    /// The peer the change concerns, if it isn't this machine.
+    #[serde(default)]
+    pub peer_id: Option<PeerId>,
+    /// Human-readable description, e.g. "'PC-Sala' -> 'PC de Juan'".
+    pub detail: String,
 }
 
 // ---------------------------------------------------------------------------
@@ -369,6 +976,16 @@ mod tests {
         assert_ne!(a, b, "two generated PeerIds should be different");
     }
 
+    #[test]
+    fn conversation_preview_is_single_line_and_bounded() {
+        assert_eq!(ConversationSummary::preview("Hola\n¿cenamos?"), "Hola ¿cenamos?");
+
+        let long = "ñ".repeat(200);
+        let preview = ConversationSummary::preview(&long);
+        assert_eq!(preview.chars().count(), ConversationSummary::PREVIEW_CHARS);
+        assert!(preview.ends_with('…'));
+    }
+
     #[test]
     fn peer_id_display() {
         let id = PeerId::new("abc-123");
@@ -424,6 +1041,48 @@ mod tests {
         assert!(MessageContent::new(long).is_err());
     }
 
+    #[test]
+    fn avatar_valid_emoji() {
+        let avatar = Avatar::new("🐱").unwrap();
+        assert_eq!(avatar.as_str(), "🐱");
+    }
+
+    #[test]
+    fn avatar_empty_or_long_rejected() {
+        assert!(Avatar::new("  ").is_err());
+        assert!(Avatar::new("abcde").is_err());
+    }
+
+    #[test]
+    fn accent_color_parse_and_display() {
+        let color = AccentColor::parse("#FF8800").unwrap();
+        assert_eq!((color.r, color.g, color.b), (0xff, 0x88, 0x00));
+        assert_eq!(color.to_string(), "#ff8800");
+        // The leading '#' is optional
+        assert_eq!(AccentColor::parse("ff8800").unwrap(), color);
+    }
+
+    #[test]
+    fn accent_color_invalid_rejected() {
+        assert!(AccentColor::parse("#fff").is_err());
+        assert!(AccentColor::parse("#gggggg").is_err());
+    }
+
+    #[test]
+    fn accent_color_serde_as_hex_string() {
+        let json = serde_json::to_string(&AccentColor::parse("#0a0b0c").unwrap()).unwrap();
+        assert_eq!(json, r##""#0a0b0c""##);
+        assert!(serde_json::from_str::<AccentColor>(r#""nope""#).is_err());
+    }
+
+    #[test]
+    fn capability_list_skips_unknown_and_duplicates() {
+        let capabilities = Capability::parse_list("retract, teleport,groups,retract");
+        assert_eq!(capabilities, vec![Capability::Retract, Capability::Groups]);
+        assert_eq!(Capability::join(&capabilities), "retract,groups");
+        assert!(Capability::parse_list("").is_empty());
+    }
+
     #[test]
     fn timestamp_now_is_positive() {
         let ts = Timestamp::now();
@@ -462,6 +1121,33 @@ mod tests {
         assert_eq!(id, parsed);
     }
 
+    #[test]
+    fn peer_info_same_details_ignores_presence() {
+        let peer = PeerInfo {
+            id: PeerId::new("peer-1"),
+            display_name: "PC-Sala".to_string(),
+            addresses: vec!["192.168.1.10:9876".to_string()],
+            last_seen_at: Timestamp::from_millis(1_000),
+            online: true,
+            do_not_disturb: false,
+            away: false,
+            avatar: None,
+            accent_color: None,
+            capabilities: Vec::new(),
+            notifications: NotificationPrefs::default(),
+            interface_addresses: Default::default(),
+        };
+
+        let mut seen_again = peer.clone();
+        seen_again.last_seen_at = Timestamp::from_millis(5_000);
+        seen_again.online = false;
+        assert!(peer.same_details(&seen_again));
+
+        let mut moved = peer.clone();
+        moved.addresses = vec!["192.168.1.20:9876".to_string()];
+        assert!(!peer.same_details(&moved));
+    }
+
     #[test]
     fn message_serde_json_roundtrip() {
         let msg = Message {
@@ -471,6 +1157,9 @@ mod tests {
             content: "Hola desde la cocina!".to_string(),
             timestamp: Timestamp::now(),
             delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
         };
         let json = serde_json::to_string(&msg).unwrap();
         let parsed: Message = serde_json::from_str(&json).unwrap();
@@ -478,4 +1167,69 @@ mod tests {
         assert_eq!(msg.content, parsed.content);
         assert_eq!(msg.direction, parsed.direction);
     }
+
+    #[test]
+    fn message_without_fire_and_forget_field() {
+        // Messages serialized before delivery modes existed
+        let json = r#"{"id":"m1","peer_id":"p1","direction":"sent","content":"Hola","timestamp":1,"delivered":true}"#;
+        let parsed: Message = serde_json::from_str(json).unwrap();
+        assert!(!parsed.fire_and_forget);
+    }
+
+    #[test]
+    fn message_status_follows_ack_and_read_receipt() {
+        let json = r#"{"id":"m1","peer_id":"p1","direction":"sent","content":"Hola","timestamp":1000,"delivered":false}"#;
+        let mut msg: Message = serde_json::from_str(json).unwrap();
+        assert_eq!(msg.status(None), Some(MessageStatus::Pending));
+        msg.delivered = true;
+        assert_eq!(msg.status(Some(Timestamp::from_millis(999))), Some(MessageStatus::Delivered));
+        assert_eq!(msg.status(Some(Timestamp::from_millis(1000))), Some(MessageStatus::Read));
+
+        msg.direction = Direction::Received;
+        assert_eq!(msg.status(Some(Timestamp::from_millis(1000))), None);
+    }
+
+    #[test]
+    fn audit_action_db_roundtrip() {
+        for action in [
+            AuditAction::DisplayNameChanged,
+            AuditAction::PeerRenamed,
+            AuditAction::KidModeBlocked,
+        ] {
+            assert_eq!(AuditAction::from_db_str(action.as_db_str()).unwrap(), action);
+        }
+        assert!(AuditAction::from_db_str("bogus").is_err());
+    }
+
+    #[test]
+    fn message_cursor_roundtrip_and_pages() {
+        let msg = |id: &str, ts: i64| Message {
+            id: MessageId::new(id),
+            peer_id: PeerId::new("p1"),
+            direction: Direction::Received,
+            content: "Hola".to_string(),
+            timestamp: Timestamp::from_millis(ts),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        let cursor = MessageCursor::older_than(&msg("m-1", 1707849600000));
+        assert_eq!(MessageCursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(MessageCursor::decode("nonsense"), None);
+        assert_eq!(MessageCursor::decode("6f"), None);
+
+        // A full page going back has more behind it; a short one doesn't
+        let page = [msg("m3", 3), msg("m2", 2)];
+        let (next, prev) = MessageCursor::for_page(&page, 2, None);
+        assert_eq!(next, Some(MessageCursor::older_than(&page[1])));
+        assert_eq!(prev, Some(MessageCursor::newer_than(&page[0])));
+        let (next, _) = MessageCursor::for_page(&page, 5, None);
+        assert_eq!(next, None);
+
+        // Catching up with nothing new keeps the same cursor
+        let newer = MessageCursor::newer_than(&page[0]);
+        assert_eq!(MessageCursor::for_page(&[], 5, Some(&newer)), (None, Some(newer)));
+        assert_eq!(MessageCursor::for_page(&[], 5, None), (None, None));
+    }
 }
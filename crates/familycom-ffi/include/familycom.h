@@ -0,0 +1,66 @@
+/*
+ * familycom.h - C bindings for the FamilyCom daemon (libfamilycom_ffi).
+ *
+ * A blocking client for the daemon's IPC socket. Build the library with
+ *
+ *     cargo build --release -p familycom-ffi
+ *
+ * and link against target/release/libfamilycom_ffi.so (.dylib / .dll).
+ *
+ * Every function reports failure with NULL or -1 and leaves a message for
+ * fc_last_error(). A client must only be used from one thread at a time.
+ */
+
+#ifndef FAMILYCOM_H
+#define FAMILYCOM_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An open, subscribed connection to the daemon. */
+typedef struct FcClient FcClient;
+
+/*
+ * Connects to the daemon and subscribes to its events.
+ *
+ * `endpoint` is written like the daemon's --ipc-listen ("unix:<path>",
+ * "tcp:127.0.0.1:<port>" or a bare socket path); NULL means the default
+ * socket. Returns NULL on failure.
+ */
+FcClient *fc_connect(const char *endpoint);
+
+/*
+ * Sends a text message to a peer, given by peer ID or display name
+ * (case-insensitive). Blocks until the daemon has tried to deliver it.
+ * Returns 0 on success, -1 on failure.
+ */
+int fc_send(FcClient *client, const char *peer, const char *text);
+
+/*
+ * Waits up to `timeout_ms` for the next event and returns it as a JSON
+ * string, e.g. {"type":"NewMessage","message":{...}}. Free it with
+ * fc_string_free().
+ *
+ * Returns NULL if no event arrived in time, or on failure; fc_last_error()
+ * is NULL in the first case.
+ */
+char *fc_poll_event(FcClient *client, int timeout_ms);
+
+/* Frees a string returned by fc_poll_event(). NULL is ignored. */
+void fc_string_free(char *s);
+
+/*
+ * Why the last call on this thread failed, or NULL if it didn't. Valid
+ * until the next fc_* call on the same thread; do not free.
+ */
+const char *fc_last_error(void);
+
+/* Closes the connection and frees the client. NULL is ignored. */
+void fc_disconnect(FcClient *client);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FAMILYCOM_H */
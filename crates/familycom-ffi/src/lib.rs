@@ -0,0 +1,456 @@
+//! C bindings for the FamilyCom daemon.
+//!
+//! A small blocking client for the daemon's IPC socket, for programs that
+//! can't speak Rust or run tokio (e.g. a C++ kiosk app that wants to show
+//! FamilyCom messages). See `include/familycom.h` for the C declarations.
+//!
+//! ```c
+//! FcClient *fc = fc_connect(NULL);            /* default socket */
+//! if (!fc) { fprintf(stderr, "%s\n", fc_last_error()); return 1; }
+//! fc_send(fc, "Abuela", "El kiosko esta encendido");
+//! for (;;) {
+//!     char *event = fc_poll_event(fc, 1000);  /* JSON, or NULL */
+//!     if (event) { show(event); fc_string_free(event); }
+//!     else if (fc_last_error()) break;        /* disconnected */
+//! }
+//! fc_disconnect(fc);
+//! ```
+//!
+//! Events are the daemon's `ServerMessage` JSON lines, unchanged (e.g.
+//! `{"type":"NewMessage","message":{...}}`), so callers can parse only the
+//! fields they care about.
+//!
+//! # Errors
+//!
+//! Functions report failure with `NULL` / `-1` and leave a description for
+//! `fc_last_error`, which is per thread and cleared by the next call.
+
+use familycom_core::config::AppConfig;
+use familycom_core::ipc::{self, ClientRequest, EventFilter, IpcEndpoint, ServerMessage};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::ptr;
+use std::time::Duration;
+
+/// How long to wait for the daemon to answer a request. Sending waits for
+/// the peer's ACK, which can take a while when it's unreachable.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records the error for `fc_last_error`.
+fn set_last_error(message: impl Into<String>) {
+    // Interior NULs would truncate the message in C anyway
+    let message = message.into().replace('\0', " ");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+/// A connection to the daemon over one of the IPC transports.
+enum Stream {
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Stream {
+    fn connect(endpoint: &IpcEndpoint) -> io::Result<Self> {
+        match endpoint {
+            #[cfg(unix)]
+            IpcEndpoint::Unix(path) => Ok(Stream::Unix(UnixStream::connect(path)?)),
+            IpcEndpoint::Tcp(addr) => Ok(Stream::Tcp(TcpStream::connect(addr)?)),
+            other => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} endpoints are not supported here", other.kind()),
+            )),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(s) => Ok(Stream::Unix(s.try_clone()?)),
+            Stream::Tcp(s) => Ok(Stream::Tcp(s.try_clone()?)),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        // A zero timeout means "block forever" to the OS
+        let timeout = Some(timeout.max(Duration::from_millis(1)));
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(s) => s.set_read_timeout(timeout),
+            Stream::Tcp(s) => s.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf),
+            Stream::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf),
+            Stream::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush(),
+            Stream::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+/// An open, subscribed connection to the daemon. Opaque to C.
+pub struct FcClient {
+    reader: BufReader<Stream>,
+    writer: Stream,
+    /// The start of a line whose end hasn't arrived yet (a poll timed out
+    /// mid-line).
+    partial: Vec<u8>,
+    /// Events that arrived while waiting for a response, oldest first.
+    events: VecDeque<String>,
+}
+
+impl FcClient {
+    /// Connects and subscribes to events.
+    fn connect(endpoint: &IpcEndpoint) -> Result<Self, String> {
+        let stream = Stream::connect(endpoint).map_err(|e| format!("could not connect to {endpoint}: {e}"))?;
+        let writer = stream.try_clone().map_err(|e| e.to_string())?;
+        let mut client = FcClient {
+            reader: BufReader::new(stream),
+            writer,
+            partial: Vec::new(),
+            events: VecDeque::new(),
+        };
+        match client.request(&ClientRequest::Subscribe {
+            name: None,
+            filter: EventFilter::default(),
+            since: None,
+        })? {
+            ServerMessage::Ok => Ok(client),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Sends a request and returns the daemon's response. Events that
+    /// arrive first are queued for `poll`.
+    fn request(&mut self, request: &ClientRequest) -> Result<ServerMessage, String> {
+        let line = ipc::encode_request(request).map_err(|e| e.to_string())?;
+        self.writer
+            .write_all(line.as_bytes())
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| format!("could not write to daemon: {e}"))?;
+
+        loop {
+            let Some(line) = self.read_line(RESPONSE_TIMEOUT)? else {
+                return Err("timed out waiting for the daemon".to_string());
+            };
+            let message = ipc::decode_response(&line).map_err(|e| format!("bad response from daemon: {e}"))?;
+            if message.event_type().is_some() {
+                self.events.push_back(line.trim_end().to_string());
+                continue;
+            }
+            if let ServerMessage::Error { code, message } = message {
+                return Err(format!("{code}: {message}"));
+            }
+            return Ok(message);
+        }
+    }
+
+    /// Returns the next event line, waiting up to `timeout` for one.
+    fn poll(&mut self, timeout: Duration) -> Result<Option<String>, String> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+        // Responses can't show up here: every request waits for its own
+        Ok(self.read_line(timeout)?.map(|line| line.trim_end().to_string()))
+    }
+
+    /// Reads one line, or `None` if none completed within `timeout`.
+    fn read_line(&mut self, timeout: Duration) -> Result<Option<String>, String> {
+        self.reader.get_ref().set_read_timeout(timeout).map_err(|e| e.to_string())?;
+        match self.reader.read_until(b'\n', &mut self.partial) {
+            Ok(_) if self.partial.ends_with(b"\n") => {
+                let line = String::from_utf8_lossy(&self.partial).into_owned();
+                self.partial.clear();
+                Ok(Some(line))
+            }
+            Ok(_) => Err("daemon closed the connection".to_string()),
+            // Whatever arrived stays in `partial` for the next call
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(format!("could not read from daemon: {e}")),
+        }
+    }
+
+    /// Sends `text` to a peer given by ID or display name.
+    fn send(&mut self, peer: &str, text: &str) -> Result<(), String> {
+        let peers = match self.request(&ClientRequest::ListPeers)? {
+            ServerMessage::PeerList { peers } => peers,
+            other => return Err(unexpected(&other)),
+        };
+        let peer_id = peers
+            .iter()
+            .find(|p| p.id.as_str() == peer)
+            .or_else(|| peers.iter().find(|p| p.display_name.eq_ignore_ascii_case(peer)))
+            .map(|p| p.id.clone())
+            .ok_or_else(|| format!("no peer called '{peer}'"))?;
+
+        match self.request(&ClientRequest::SendMessage {
+            peer_id,
+            content: text.to_string(),
+            reply_to: None,
+        })? {
+            ServerMessage::MessageSent { .. } => Ok(()),
+            other => Err(unexpected(&other)),
+        }
+    }
+}
+
+fn unexpected(message: &ServerMessage) -> String {
+    format!("unexpected response from daemon: {message:?}")
+}
+
+/// Reads a C string argument as UTF-8.
+///
+/// # Safety
+///
+/// `s` must be NULL or a valid NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{name} is NULL"));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| format!("{name} is not valid UTF-8"))
+}
+
+/// Connects to the daemon and subscribes to its events.
+///
+/// `endpoint` is written like the daemon's `--ipc-listen` (`unix:<path>`,
+/// `tcp:127.0.0.1:<port>`, or a bare socket path); NULL means the default
+/// socket. Returns NULL on failure.
+///
+/// # Safety
+///
+/// `endpoint` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fc_connect(endpoint: *const c_char) -> *mut FcClient {
+    clear_last_error();
+    let endpoint = if endpoint.is_null() {
+        IpcEndpoint::Unix(AppConfig::default_socket_path())
+    } else {
+        match str_arg(endpoint, "endpoint").and_then(|s| s.parse::<IpcEndpoint>().map_err(|e| e.to_string())) {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                set_last_error(e);
+                return ptr::null_mut();
+            }
+        }
+    };
+    match FcClient::connect(&endpoint) {
+        Ok(client) => Box::into_raw(Box::new(client)),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Sends a text message to a peer, given by peer ID or display name
+/// (case-insensitive). Blocks until the daemon has tried to deliver it.
+/// Returns 0 on success, -1 on failure.
+///
+/// # Safety
+///
+/// `client` must come from `fc_connect` and not have been disconnected;
+/// `peer` and `text` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn fc_send(client: *mut FcClient, peer: *const c_char, text: *const c_char) -> c_int {
+    clear_last_error();
+    let Some(client) = client.as_mut() else {
+        set_last_error("client is NULL");
+        return -1;
+    };
+    let result = str_arg(peer, "peer")
+        .and_then(|peer| Ok((peer, str_arg(text, "text")?)))
+        .and_then(|(peer, text)| client.send(peer, text));
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// Waits up to `timeout_ms` for the next event and returns it as a JSON
+/// string, to be released with `fc_string_free`.
+///
+/// Returns NULL if no event arrived in time, or on failure; the two are
+/// told apart by `fc_last_error` (NULL when it was just a timeout).
+///
+/// # Safety
+///
+/// `client` must come from `fc_connect` and not have been disconnected.
+#[no_mangle]
+pub unsafe extern "C" fn fc_poll_event(client: *mut FcClient, timeout_ms: c_int) -> *mut c_char {
+    clear_last_error();
+    let Some(client) = client.as_mut() else {
+        set_last_error("client is NULL");
+        return ptr::null_mut();
+    };
+    let timeout = Duration::from_millis(u64::try_from(timeout_ms).unwrap_or(0));
+    match client.poll(timeout) {
+        Ok(Some(event)) => CString::new(event).map_or(ptr::null_mut(), CString::into_raw),
+        Ok(None) => ptr::null_mut(),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a string returned by `fc_poll_event`. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string from `fc_poll_event` not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn fc_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Describes why the last call on this thread failed, or NULL if it
+/// didn't. Valid until the next `fc_*` call on the same thread.
+#[no_mangle]
+pub extern "C" fn fc_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Closes the connection and frees the client. NULL is ignored.
+///
+/// # Safety
+///
+/// `client` must be NULL or come from `fc_connect` and not have been
+/// disconnected before.
+#[no_mangle]
+pub unsafe extern "C" fn fc_disconnect(client: *mut FcClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A stand-in daemon that answers the given script: for each request
+    /// line it reads, it writes the corresponding reply lines.
+    fn fake_daemon(script: Vec<Vec<&'static str>>) -> (CString, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = CString::new(format!("tcp:{}", listener.local_addr().unwrap())).unwrap();
+        let daemon = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut requests = Vec::new();
+            for replies in script {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                requests.push(line.trim().to_string());
+                for reply in replies {
+                    writeln!(writer, "{reply}").unwrap();
+                }
+            }
+            requests
+        });
+        (endpoint, daemon)
+    }
+
+    #[test]
+    fn send_keeps_events_that_arrive_first() {
+        let (endpoint, daemon) = fake_daemon(vec![
+            vec![r#"{"type":"Ok"}"#],
+            vec![
+                r#"{"type":"PeerOffline","peer_id":"p2"}"#,
+                r#"{"type":"PeerList","peers":[{"id":"p1","display_name":"Abuela","addresses":[],"last_seen_at":0,"online":true}]}"#,
+            ],
+            vec![r#"{"type":"MessageSent","message_id":"m1"}"#],
+        ]);
+
+        unsafe {
+            let client = fc_connect(endpoint.as_ptr());
+            assert!(!client.is_null(), "{:?}", CStr::from_ptr(fc_last_error()));
+
+            let peer = CString::new("abuela").unwrap();
+            let text = CString::new("Hola").unwrap();
+            assert_eq!(fc_send(client, peer.as_ptr(), text.as_ptr()), 0);
+
+            // The event that came in during the send is delivered afterwards
+            let event = fc_poll_event(client, 0);
+            assert!(!event.is_null());
+            assert!(CStr::from_ptr(event).to_str().unwrap().contains("PeerOffline"));
+            fc_string_free(event);
+
+            let requests = daemon.join().unwrap();
+            assert!(requests[0].starts_with(r#"{"Subscribe""#));
+            assert!(requests[2].contains(r#""peer_id":"p1""#));
+
+            // The fake daemon hung up: polling reports it
+            assert!(fc_poll_event(client, 100).is_null());
+            assert!(!fc_last_error().is_null());
+            fc_disconnect(client);
+        }
+    }
+
+    #[test]
+    fn poll_times_out_quietly() {
+        let (endpoint, _daemon) = fake_daemon(vec![vec![r#"{"type":"Ok"}"#], vec![]]);
+        unsafe {
+            let client = fc_connect(endpoint.as_ptr());
+            assert!(!client.is_null());
+            assert!(fc_poll_event(client, 10).is_null());
+            assert!(fc_last_error().is_null());
+            fc_disconnect(client);
+        }
+    }
+
+    #[test]
+    fn connect_failure_sets_last_error() {
+        let endpoint = CString::new("tcp:192.168.1.5:7878").unwrap();
+        unsafe {
+            assert!(fc_connect(endpoint.as_ptr()).is_null());
+        }
+        let error = unsafe { CStr::from_ptr(fc_last_error()) }.to_str().unwrap();
+        assert!(error.contains("loopback"), "got {error}");
+    }
+}
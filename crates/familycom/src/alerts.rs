@@ -0,0 +1,97 @@
+//! Terminal title and bell on new messages (`[terminal]` in the config).
+//!
+//! For people who keep the TUI in a tmux or screen window: the title shows
+//! the unread count and the bell flags the window, so activity is visible
+//! without desktop notifications.
+
+use crossterm::terminal::SetTitle;
+use crossterm::QueueableCommand;
+use familycom_core::config::TerminalConfig;
+use std::io::{self, Write};
+
+/// Saves the current title on the terminal's title stack (xterm's
+/// XTWINOPS, also understood by tmux, VTE and others). Ignored elsewhere.
+const PUSH_TITLE: &[u8] = b"\x1b[22;0t";
+/// Restores the title saved by `PUSH_TITLE`.
+const POP_TITLE: &[u8] = b"\x1b[23;0t";
+
+/// Terminal-level signals of new activity.
+pub struct Alerts {
+    config: TerminalConfig,
+    /// Whether the terminal has focus, as last reported. Terminals that
+    /// don't report focus changes are assumed to always have it.
+    focused: bool,
+    /// The title last written, so it's only rewritten when it changes.
+    title: Option<String>,
+}
+
+impl Alerts {
+    pub fn new(config: TerminalConfig) -> Self {
+        Self {
+            config,
+            focused: true,
+            title: None,
+        }
+    }
+
+    /// Saves the terminal's title so `restore` can put it back on exit.
+    pub fn start(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.config.title {
+            out.write_all(PUSH_TITLE)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Records a focus change reported by the terminal.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// Whether the terminal has focus, as last reported.
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Shows `unread` in the title, if it changed since the last call.
+    pub fn update_title(&mut self, out: &mut impl Write, unread: u32) -> io::Result<()> {
+        if !self.config.title {
+            return Ok(());
+        }
+        let title = title_for(unread);
+        if self.title.as_ref() == Some(&title) {
+            return Ok(());
+        }
+        out.queue(SetTitle(&title))?;
+        out.flush()?;
+        self.title = Some(title);
+        Ok(())
+    }
+
+    /// Rings the bell for a received message, if the config says so.
+    pub fn message_received(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.config.bell.rings(self.focused) {
+            out.write_all(b"\x07")?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Puts back the title saved by `start`.
+    pub fn restore(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.config.title {
+            out.write_all(POP_TITLE)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// "FamilyCom", or "FamilyCom (3)" with unread messages.
+pub fn title_for(unread: u32) -> String {
+    if unread == 0 {
+        "FamilyCom".to_string()
+    } else {
+        format!("FamilyCom ({unread})")
+    }
+}
@@ -10,10 +10,34 @@
 //!
 //! This separation makes the app easy to test and reason about.
 
-use familycom_core::ipc::ServerMessage;
-use familycom_core::types::{Message, PeerId, PeerInfo};
+use familycom_core::config::TuiConfig;
+use familycom_core::ipc::{ClientRequest, ServerMessage};
+use familycom_core::replay::ReplayPacing;
+use familycom_core::types::{ConversationSummary, Direction, Message, MessageId, PeerId, PeerInfo, Timestamp};
 use ratatui::layout::Rect;
-use std::collections::HashMap;
+use ratatui::text::Line;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+/// How many activity feed entries to keep. Older ones are dropped.
+const MAX_ACTIVITY_ENTRIES: usize = 500;
+
+/// How many messages story mode fetches at a time.
+pub const REPLAY_PAGE: u32 = 200;
+
+/// How many messages are fetched at a time for the messages panel: the
+/// latest on opening a conversation, then older ones as the user scrolls up.
+pub const HISTORY_PAGE: u32 = 100;
+
+/// How much Ctrl+Left/Right change the peer list's width, in percent.
+const PEER_LIST_WIDTH_STEP: u16 = 5;
+
+/// Most matches a search asks the daemon for.
+pub const SEARCH_LIMIT: u32 = 200;
+
+/// How long "está escribiendo…" shows after the peer's last `PeerTyping`.
+/// Peers repeat it every few seconds while they type.
+pub const TYPING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(6);
 
 /// Screen rectangles of the three main panels, saved during each render pass.
 /// Used for mouse hit-testing: when the user clicks, we check which panel
@@ -23,6 +47,205 @@ pub struct PanelRects {
     pub peers: Rect,
     pub messages: Rect,
     pub input: Rect,
+    /// Only non-empty while the activity view is shown.
+    pub activity: Rect,
+}
+
+/// Which screen fills the content area above the input box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    /// Peer list and conversation (the default).
+    Chat,
+    /// Live log of daemon events, toggled with F2.
+    Activity,
+    /// Story mode: the selected conversation replayed from the start (r).
+    Replay,
+}
+
+/// State of story mode: a conversation shown again message by message,
+/// with its original pacing sped up (see `familycom_core::replay`).
+#[derive(Debug)]
+pub struct Replay {
+    pub peer_id: PeerId,
+    /// Messages fetched so far, oldest first. Pages are requested from
+    /// the daemon as the replay catches up with them.
+    pub messages: Vec<Message>,
+    /// How many of `messages` are on screen.
+    pub shown: usize,
+    pub pacing: ReplayPacing,
+    pub paused: bool,
+    /// When the next message appears; `None` while it hasn't been fetched.
+    next_at: Option<Instant>,
+    /// Whether a page was requested and hasn't arrived yet.
+    loading: bool,
+    /// Whether the daemon has sent the last page.
+    complete: bool,
+}
+
+impl Replay {
+    fn new(peer_id: PeerId) -> Self {
+        Self {
+            peer_id,
+            messages: Vec::new(),
+            shown: 0,
+            pacing: ReplayPacing::default(),
+            paused: false,
+            next_at: None,
+            loading: false,
+            complete: false,
+        }
+    }
+
+    /// Whether every message of the conversation is on screen.
+    pub fn finished(&self) -> bool {
+        self.complete && self.shown == self.messages.len()
+    }
+
+    /// Shows the next message if it's due (or right away with `force`).
+    fn advance(&mut self, now: Instant, force: bool) {
+        if self.shown >= self.messages.len() {
+            return;
+        }
+        if self.next_at.is_none() {
+            self.schedule(now);
+        }
+        if force || self.next_at.is_some_and(|at| at <= now) {
+            self.shown += 1;
+            self.schedule(now);
+        }
+    }
+
+    /// Works out when the message after the last one shown is due.
+    fn schedule(&mut self, now: Instant) {
+        let previous = self.shown.checked_sub(1).and_then(|i| self.messages.get(i));
+        self.next_at = match (previous, self.messages.get(self.shown)) {
+            (Some(previous), Some(next)) => Some(now + self.pacing.delay(previous.timestamp, next.timestamp)),
+            // The first message appears straight away
+            (None, Some(_)) => Some(now),
+            _ => None,
+        };
+    }
+}
+
+/// In-chat search (/): a query over the selected conversation and the
+/// messages matching it.
+#[derive(Debug)]
+pub struct Search {
+    pub peer_id: PeerId,
+    pub query: String,
+    /// Whether the query is still being typed (it's shown in the input box).
+    pub editing: bool,
+    /// Messages that match, from the daemon's `SearchResults`. Some may be
+    /// older than the loaded history.
+    pub matches: HashSet<MessageId>,
+    /// The match jumped to last (n/N).
+    pub current: Option<MessageId>,
+}
+
+/// The quick-switcher (Ctrl+K): a few letters of a peer's name to jump
+/// to their conversation.
+#[derive(Debug, Default)]
+pub struct Switcher {
+    pub query: String,
+    /// Which of the matches (see `TuiApp::switcher_matches`) Enter opens.
+    pub selected: usize,
+    /// A message's text to send to the chosen peer instead of opening
+    /// their conversation ("Reenviar a…" in the message popup).
+    pub forward: Option<String>,
+}
+
+/// Message selection (Enter in the messages): a message picked with
+/// Up/Down, and the popup of what can be done with it once Enter opens it.
+#[derive(Debug)]
+pub struct MessageMenu {
+    pub message_id: MessageId,
+    /// The highlighted entry of the popup (see
+    /// `TuiApp::message_menu_items`), while it's open.
+    pub item: Option<usize>,
+}
+
+/// What the message popup offers to do with the selected message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageAction {
+    /// Answer it: it's quoted above the input until the reply is sent.
+    Reply,
+    /// Copy its text to the clipboard.
+    Copy,
+    /// Write a private note on it.
+    Note,
+    /// Send it again, if it couldn't be delivered.
+    Retry,
+    /// Send its text to another peer.
+    Forward,
+    /// Delete it from our history.
+    Delete,
+    /// Delete it here and ask the peer to delete it too (ours only).
+    Retract,
+}
+
+impl MessageAction {
+    /// How the popup lists it.
+    pub fn label(self) -> &'static str {
+        match self {
+            MessageAction::Reply => "Responder",
+            MessageAction::Copy => "Copiar texto",
+            MessageAction::Note => "Nota privada",
+            MessageAction::Retry => "Reintentar envío",
+            MessageAction::Forward => "Reenviar a…",
+            MessageAction::Delete => "Borrar (solo aquí)",
+            MessageAction::Retract => "Borrar para todos",
+        }
+    }
+}
+
+/// Copy mode (v in the messages): a cursor over the open conversation as
+/// the panel draws it, to select some of it and copy that.
+#[derive(Debug, Default)]
+pub struct CopyMode {
+    /// The cursor's line, among `ui::messages::conversation_lines`.
+    pub line: usize,
+    /// The cursor's column within its line, in characters.
+    pub col: usize,
+    /// Where the selection started (v), if one has.
+    pub anchor: Option<(usize, usize)>,
+}
+
+/// Messages that arrived in a conversation while it was scrolled up, so
+/// the view stayed where it was.
+#[derive(Debug)]
+pub struct Unseen {
+    pub peer_id: PeerId,
+    /// The first of them; the "mensajes nuevos" divider goes above it.
+    pub first: MessageId,
+    /// How many haven't been scrolled down to yet (0 once at the bottom).
+    pub count: usize,
+}
+
+/// A `GetMessages` sent for the messages panel and not answered yet. The
+/// daemon answers in order, so these are matched up first-in, first-out.
+#[derive(Debug)]
+struct PageRequest {
+    peer_id: PeerId,
+    /// Whether it's an older page, to go before the loaded messages
+    /// (rather than the latest page, which replaces them).
+    older: bool,
+}
+
+/// Severity of an activity feed entry (controls its color).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+/// One line in the activity feed.
+#[derive(Debug, Clone)]
+pub struct ActivityEntry {
+    /// When the TUI received the event.
+    pub at: Timestamp,
+    pub level: ActivityLevel,
+    pub text: String,
 }
 
 /// Which panel currently has keyboard focus.
@@ -42,8 +265,13 @@ pub enum FocusedPanel {
 /// This indirection keeps input handling separate from state mutation.
 #[derive(Debug)]
 pub enum Action {
-    /// User wants to quit the TUI.
+    /// User wants to quit the TUI. With something typed in the input, asks
+    /// first.
     Quit,
+    /// Quit and discard what was typed (s at the prompt).
+    ConfirmQuit,
+    /// Keep going after all (n / Esc at the prompt).
+    CancelQuit,
     /// Switch focus to the next panel (Tab).
     NextFocus,
     /// Select the next peer in the list (Down / j).
@@ -54,6 +282,14 @@ pub enum Action {
     ScrollUp,
     /// Scroll messages down (newer).
     ScrollDown,
+    /// The first `g` of `gg` (vim mode): the next key decides.
+    PendingG,
+    /// Go to the top of the focused panel: the first peer, or the oldest
+    /// message (gg in vim mode).
+    JumpTop,
+    /// Go to the bottom of the focused panel: the last peer, or the newest
+    /// message (G in vim mode).
+    JumpBottom,
     /// Append a character to the input buffer.
     InputChar(char),
     /// Delete the character before the cursor.
@@ -74,6 +310,111 @@ pub enum Action {
     FocusPanel(FocusedPanel),
     /// Select a peer by index and focus the peer list (from clicking a row).
     SelectPeer(usize),
+    /// Switch between the chat and the activity feed (F2).
+    ToggleActivity,
+    /// Hide or show the peer list (F4).
+    TogglePeerList,
+    /// Make the peer list narrower (Ctrl+Left).
+    NarrowPeerList,
+    /// Make the peer list wider, showing it if it's hidden (Ctrl+Right).
+    WidenPeerList,
+    /// Export the selected conversation to a file (e). Handled in `main.rs`.
+    ExportConversation,
+    /// Copy the selected message's text to the clipboard (y). Handled in
+    /// `main.rs`.
+    CopyMessage,
+    /// Send a message that couldn't be delivered again (t). Handled in
+    /// `main.rs`.
+    RetryMessage,
+    /// Start writing a private note, on the newest message (n).
+    StartNote,
+    /// Move the note to the previous (older) message (Up while writing a note).
+    NoteTargetPrev,
+    /// Move the note to the next (newer) message (Down while writing a note).
+    NoteTargetNext,
+    /// Stop writing the note without saving it (Esc).
+    CancelNote,
+    /// Start typing a search over the selected conversation (/).
+    StartSearch,
+    /// Add a character to the search query.
+    SearchChar(char),
+    /// Delete the last character of the search query.
+    SearchBackspace,
+    /// Look the query up (Enter). Handled in `main.rs`.
+    SubmitSearch,
+    /// Mark every conversation as read (R). Handled in `main.rs`.
+    MarkAllRead,
+    /// Mute or unmute the selected peer's notifications (m). Handled in
+    /// `main.rs`.
+    ToggleMute,
+    /// Jump to the previous (older) match (n).
+    SearchOlder,
+    /// Jump to the next (newer) match (N).
+    SearchNewer,
+    /// Leave the search and drop its highlights (Esc).
+    CancelSearch,
+    /// Open the quick-switcher (Ctrl+K).
+    OpenSwitcher,
+    /// Add a character to the quick-switcher's query.
+    SwitcherChar(char),
+    /// Delete the last character of the quick-switcher's query.
+    SwitcherBackspace,
+    /// Highlight the previous match (Up).
+    SwitcherUp,
+    /// Highlight the next match (Down).
+    SwitcherDown,
+    /// Open the highlighted peer's conversation (Enter).
+    SubmitSwitcher,
+    /// Close the quick-switcher without switching (Esc).
+    CloseSwitcher,
+    /// Start selecting a message, to act on it (Enter in the messages).
+    SelectMessage,
+    /// Select the previous message, or the popup's previous entry.
+    MessageMenuUp,
+    /// Select the next message, or the popup's next entry.
+    MessageMenuDown,
+    /// Open the popup for the selected message, or do its highlighted
+    /// entry (Enter). Doing it is handled in `main.rs`.
+    SubmitMessageMenu,
+    /// Close the popup, or stop selecting (Esc).
+    CloseMessageMenu,
+    /// Stop replying, keeping what's typed (Esc in the input).
+    CancelReply,
+    /// Enter copy mode in the messages (v).
+    StartCopy,
+    /// Move the copy mode cursor a line up.
+    CopyUp,
+    /// Move the copy mode cursor a line down.
+    CopyDown,
+    /// Move the copy mode cursor a character left.
+    CopyLeft,
+    /// Move the copy mode cursor a character right.
+    CopyRight,
+    /// Move the copy mode cursor to the start of its line (0).
+    CopyLineStart,
+    /// Move the copy mode cursor to the end of its line ($).
+    CopyLineEnd,
+    /// Start the selection at the cursor, or drop it (v / Space).
+    CopyToggleSelection,
+    /// Copy the selection (or the cursor's line) to the clipboard and leave
+    /// copy mode (y / Enter). Handled in `main.rs`.
+    YankCopy,
+    /// Leave copy mode without copying (Esc).
+    CancelCopy,
+    /// Replay the selected conversation from the start (r).
+    StartReplay,
+    /// Leave story mode (Esc).
+    StopReplay,
+    /// Pause or resume the replay (Space).
+    ReplayTogglePause,
+    /// Replay at the next faster speed (+).
+    ReplayFaster,
+    /// Replay at the next slower speed (-).
+    ReplaySlower,
+    /// Show the next message now instead of waiting for it (Enter).
+    ReplaySkip,
+    /// Periodic tick while replaying: shows the next message when it's due.
+    ReplayTick,
     /// A server message was received from the daemon.
     ServerMessage(ServerMessage),
 }
@@ -87,14 +428,57 @@ pub struct TuiApp {
     /// Message history per peer (keyed by PeerId).
     /// Messages are stored oldest-first for display.
     pub messages: HashMap<PeerId, Vec<Message>>,
+    /// Cursor for the page before the loaded history of each conversation;
+    /// `None` once its first message is loaded.
+    older_cursors: HashMap<PeerId, Option<String>>,
+    /// History pages asked for, oldest request first.
+    page_requests: VecDeque<PageRequest>,
+    /// Last message and unread count per conversation, for the peer list.
+    pub conversations: HashMap<PeerId, ConversationSummary>,
+    /// Private notes on messages of loaded conversations.
+    pub notes: HashMap<MessageId, String>,
+    /// Messages we sent that the daemon couldn't deliver (`MessageFailed`),
+    /// until a retry gets them through.
+    pub failed: HashSet<MessageId>,
+    /// While writing a note: index (into `current_messages()`) of the
+    /// message it's for. Enter saves the input as that message's note.
+    pub note_target: Option<usize>,
     /// The text input buffer (what the user is currently typing).
     pub input: String,
     /// Cursor position within the input string (byte offset).
     pub input_cursor: usize,
+    /// What was being typed to other peers, put back in the input when
+    /// their conversation is selected again.
+    pub drafts: HashMap<PeerId, String>,
     /// Which panel currently has focus.
     pub focused: FocusedPanel,
-    /// Scroll offset for the messages panel (0 = bottom / newest).
+    /// Scroll offset for the messages panel, in lines up from the bottom
+    /// (0 = newest). Reaching the top loads the page before.
     pub messages_scroll: u16,
+    /// Which screen is shown in the content area.
+    pub view: View,
+    /// Recent daemon events, oldest first, for the activity view.
+    pub activity: VecDeque<ActivityEntry>,
+    /// Scroll offset for the activity view (0 = bottom / newest).
+    pub activity_scroll: u16,
+    /// Story mode state, while `view` is `View::Replay`.
+    pub replay: Option<Replay>,
+    /// The search over the selected conversation, if any.
+    pub search: Option<Search>,
+    /// New messages below the view of the open conversation, if any.
+    pub unseen: Option<Unseen>,
+    /// The quick-switcher, while it's open.
+    pub switcher: Option<Switcher>,
+    /// Copy mode, while it's on.
+    pub copy: Option<CopyMode>,
+    /// The selected message and its popup, while selecting one.
+    pub message_menu: Option<MessageMenu>,
+    /// The message of the open conversation being answered ("Responder"
+    /// in the message popup), quoted above the input and sent along with
+    /// the next message.
+    pub reply_to: Option<MessageId>,
+    /// When each peer last said they were writing to us.
+    typing: HashMap<PeerId, Instant>,
     /// Our display name (from daemon config).
     pub our_name: String,
     /// Our peer ID (from daemon config).
@@ -103,9 +487,16 @@ pub struct TuiApp {
     pub status: String,
     /// Whether the app should exit.
     pub should_quit: bool,
+    /// Asking whether to quit and discard what's typed in the input.
+    pub confirm_quit: bool,
     /// Screen rectangles of each panel from the last render pass.
     /// Updated every frame so mouse clicks can be mapped to panels.
     pub panel_rects: PanelRects,
+    /// A `g` was just pressed in vim mode, so another makes `gg`.
+    pub pending_g: bool,
+    /// Preferences kept in tui.toml. `main.rs` saves them when an action
+    /// changes them.
+    pub config: TuiConfig,
 }
 
 impl TuiApp {
@@ -115,15 +506,36 @@ impl TuiApp {
             peers: Vec::new(),
             selected_peer_idx: None,
             messages: HashMap::new(),
+            older_cursors: HashMap::new(),
+            page_requests: VecDeque::new(),
+            conversations: HashMap::new(),
+            notes: HashMap::new(),
+            failed: HashSet::new(),
+            note_target: None,
             input: String::new(),
             input_cursor: 0,
+            drafts: HashMap::new(),
             focused: FocusedPanel::PeerList,
             messages_scroll: 0,
+            view: View::Chat,
+            activity: VecDeque::new(),
+            activity_scroll: 0,
+            replay: None,
+            search: None,
+            unseen: None,
+            switcher: None,
+            copy: None,
+            message_menu: None,
+            reply_to: None,
+            typing: HashMap::new(),
             our_name: String::new(),
             our_peer_id: None,
             status: "Connecting...".to_string(),
             should_quit: false,
+            confirm_quit: false,
             panel_rects: PanelRects::default(),
+            pending_g: false,
+            config: TuiConfig::default(),
         }
     }
 
@@ -138,6 +550,25 @@ impl TuiApp {
         self.selected_peer().map(|p| &p.id)
     }
 
+    /// Selects the peer with this ID or display name (ignoring case) and
+    /// focuses the input, for `familycom --peer`. Returns whether there was
+    /// such a peer.
+    pub fn select_peer(&mut self, wanted: &str) -> bool {
+        let wanted = wanted.trim();
+        let found = self.peers.iter().position(|p| p.id.as_str() == wanted).or_else(|| {
+            self.peers
+                .iter()
+                .position(|p| p.display_name.to_lowercase() == wanted.to_lowercase())
+        });
+        if let Some(idx) = found {
+            let prev = self.selected_peer_id().cloned();
+            self.selected_peer_idx = Some(idx);
+            self.switched_peer(prev);
+            self.focused = FocusedPanel::Input;
+        }
+        found.is_some()
+    }
+
     /// Returns the messages for the currently selected peer.
     pub fn current_messages(&self) -> &[Message] {
         self.selected_peer_id()
@@ -146,52 +577,256 @@ impl TuiApp {
             .unwrap_or(&[])
     }
 
+    /// Returns the message that actions like copying (y) apply to: the
+    /// current search match, or else the newest message.
+    pub fn selected_message(&self) -> Option<&Message> {
+        let messages = self.current_messages();
+        self.search
+            .as_ref()
+            .filter(|s| self.selected_peer_id() == Some(&s.peer_id))
+            .and_then(|s| s.current.as_ref())
+            .and_then(|id| messages.iter().find(|m| &m.id == id))
+            .or_else(|| messages.last())
+    }
+
+    /// The message a retry (t) sends again: the selected one if it failed,
+    /// else the newest that did in the open conversation.
+    pub fn retry_target(&self) -> Option<&Message> {
+        self.selected_message()
+            .filter(|m| self.failed.contains(&m.id))
+            .or_else(|| self.current_messages().iter().rev().find(|m| self.failed.contains(&m.id)))
+    }
+
+    /// Returns the message the note being written is for, if any.
+    pub fn note_target_message(&self) -> Option<&Message> {
+        self.note_target.and_then(|idx| self.current_messages().get(idx))
+    }
+
+    /// Updates the conversation summary of `message`'s peer so the peer
+    /// list preview follows new messages without re-querying the daemon.
+    pub fn note_last_message(&mut self, message: &Message) {
+        let is_open = self.selected_peer_id() == Some(&message.peer_id);
+        let summary = self
+            .conversations
+            .entry(message.peer_id.clone())
+            .or_insert_with(|| ConversationSummary {
+                peer_id: message.peer_id.clone(),
+                last_message_preview: String::new(),
+                last_direction: message.direction,
+                last_timestamp: message.timestamp,
+                unread_count: 0,
+                read_by_peer: None,
+            });
+        summary.last_message_preview = ConversationSummary::preview(&message.content);
+        summary.last_direction = message.direction;
+        summary.last_timestamp = message.timestamp;
+        if message.direction == Direction::Received && !is_open {
+            summary.unread_count += 1;
+        }
+    }
+
+    /// Adds new messages to a peer's history. While that conversation is
+    /// open and scrolled up, the view stays where it is and they're counted
+    /// as unseen below it; otherwise it follows them to the bottom, as it
+    /// always does for what we sent.
+    fn append_messages(&mut self, peer_id: PeerId, messages: Vec<Message>) {
+        let Some(first) = messages.first().map(|m| m.id.clone()) else {
+            return;
+        };
+        if self.selected_peer_id() != Some(&peer_id) {
+            self.messages.entry(peer_id).or_default().extend(messages);
+            return;
+        }
+        let ours = messages.iter().any(|m| m.direction == Direction::Sent);
+        if ours {
+            self.unseen = None;
+        }
+        if self.messages_scroll == 0 || ours {
+            self.messages.entry(peer_id).or_default().extend(messages);
+            self.messages_scroll = 0;
+            return;
+        }
+        let count = messages.len();
+        let before = self.message_line(self.current_messages().len());
+        self.messages.entry(peer_id.clone()).or_default().extend(messages);
+        match self.unseen.as_mut().filter(|u| u.peer_id == peer_id) {
+            Some(unseen) => unseen.count += count,
+            None => self.unseen = Some(Unseen { peer_id, first, count }),
+        }
+        // The offset counts from the bottom, so it grows by what went below
+        let after = self.message_line(self.current_messages().len());
+        self.messages_scroll = self.messages_scroll.saturating_add(after - before);
+    }
+
+    /// After the selection moved away from `prev`: starts the newly selected
+    /// conversation at the bottom with no search, and swaps what was being
+    /// typed to `prev` for the draft kept for this one. A half-written note
+    /// is dropped.
+    fn switched_peer(&mut self, prev: Option<PeerId>) {
+        if self.selected_peer_id() == prev.as_ref() {
+            return;
+        }
+        self.messages_scroll = 0;
+        self.search = None;
+        self.unseen = None;
+        self.copy = None;
+        self.message_menu = None;
+        self.reply_to = None;
+
+        if self.note_target.take().is_some() {
+            self.take_input();
+        }
+        if let Some(prev) = prev.filter(|_| !self.input.is_empty()) {
+            let typed = self.take_input();
+            self.drafts.insert(prev, typed);
+        }
+        if let Some(draft) = self.selected_peer_id().cloned().and_then(|id| self.drafts.remove(&id)) {
+            self.input_cursor = draft.len();
+            self.input = draft;
+        }
+    }
+
+    /// Clears the "N nuevos" count once the bottom is on screen. The divider
+    /// stays until the conversation is left.
+    fn note_bottom_reached(&mut self) {
+        if let Some(unseen) = self.unseen.as_mut().filter(|_| self.messages_scroll == 0) {
+            unseen.count = 0;
+        }
+    }
+
+    /// Whether this peer is writing to us, i.e. sent `PeerTyping` less
+    /// than `TYPING_TIMEOUT` ago and no message since.
+    pub fn is_typing(&self, peer_id: &PeerId) -> bool {
+        self.typing.get(peer_id).is_some_and(|at| at.elapsed() < TYPING_TIMEOUT)
+    }
+
+    /// The message the "mensajes nuevos" divider goes above, if it's in
+    /// the open conversation.
+    pub fn unseen_first(&self) -> Option<&MessageId> {
+        self.unseen
+            .as_ref()
+            .filter(|u| self.selected_peer_id() == Some(&u.peer_id))
+            .map(|u| &u.first)
+    }
+
+    /// Unread received messages across all conversations.
+    pub fn total_unread(&self) -> u32 {
+        self.conversations.values().map(|c| c.unread_count).sum()
+    }
+
+    /// Clears the unread badge of the selected conversation (after the
+    /// daemon was asked to mark it as read).
+    pub fn clear_selected_unread(&mut self) {
+        if let Some(peer_id) = self.selected_peer_id().cloned() {
+            if let Some(summary) = self.conversations.get_mut(&peer_id) {
+                summary.unread_count = 0;
+            }
+        }
+    }
+
     /// Processes an action and updates the state accordingly.
     pub fn handle_action(&mut self, action: Action) {
+        // Any other key drops a pending `g`
+        if !matches!(action, Action::ServerMessage(_) | Action::ReplayTick) {
+            self.pending_g = matches!(action, Action::PendingG);
+        }
+
         match action {
-            Action::Quit => {
+            // Asked already: a second Ctrl+C quits anyway
+            Action::Quit if !self.input.trim().is_empty() && !self.confirm_quit => {
+                self.confirm_quit = true;
+            }
+
+            Action::Quit | Action::ConfirmQuit => {
                 self.should_quit = true;
             }
 
+            Action::CancelQuit => {
+                self.confirm_quit = false;
+            }
+
             Action::NextFocus => {
-                // Cycle through: PeerList -> Messages -> Input -> PeerList
+                // Cycle through: PeerList -> Messages -> Input -> PeerList,
+                // leaving out the peer list while it's hidden
                 self.focused = match self.focused {
                     FocusedPanel::PeerList => FocusedPanel::Messages,
                     FocusedPanel::Messages => FocusedPanel::Input,
+                    FocusedPanel::Input if self.config.peer_list_hidden => FocusedPanel::Messages,
                     FocusedPanel::Input => FocusedPanel::PeerList,
                 };
+                // A note is only being written while the input has focus
+                self.note_target = None;
+                self.copy = None;
+                self.message_menu = None;
             }
 
             Action::NextPeer => {
                 if self.peers.is_empty() {
                     return;
                 }
+                let prev = self.selected_peer_id().cloned();
                 self.selected_peer_idx = Some(match self.selected_peer_idx {
                     Some(idx) => (idx + 1).min(self.peers.len() - 1),
                     None => 0,
                 });
-                // Reset scroll when switching peers
-                self.messages_scroll = 0;
+                self.switched_peer(prev);
             }
 
             Action::PrevPeer => {
                 if self.peers.is_empty() {
                     return;
                 }
+                let prev = self.selected_peer_id().cloned();
                 self.selected_peer_idx = Some(match self.selected_peer_idx {
                     Some(idx) => idx.saturating_sub(1),
                     None => 0,
                 });
-                self.messages_scroll = 0;
+                self.switched_peer(prev);
             }
 
-            Action::ScrollUp => {
-                self.messages_scroll = self.messages_scroll.saturating_add(3);
-            }
+            Action::ScrollUp => match self.view {
+                View::Chat => {
+                    self.messages_scroll = self.messages_scroll.saturating_add(3).min(self.max_messages_scroll())
+                }
+                View::Activity => self.activity_scroll = self.activity_scroll.saturating_add(3),
+                // The replay always follows the newest message shown
+                View::Replay => {}
+            },
 
-            Action::ScrollDown => {
-                self.messages_scroll = self.messages_scroll.saturating_sub(3);
-            }
+            Action::ScrollDown => match self.view {
+                View::Chat => {
+                    self.messages_scroll = self.messages_scroll.saturating_sub(3);
+                    self.note_bottom_reached();
+                }
+                View::Activity => self.activity_scroll = self.activity_scroll.saturating_sub(3),
+                View::Replay => {}
+            },
+
+            Action::PendingG => {}
+
+            Action::JumpTop => match self.focused {
+                FocusedPanel::PeerList if !self.peers.is_empty() => {
+                    let prev = self.selected_peer_id().cloned();
+                    self.selected_peer_idx = Some(0);
+                    self.switched_peer(prev);
+                }
+                // The top of what's loaded; the page before follows
+                FocusedPanel::Messages => self.messages_scroll = self.max_messages_scroll(),
+                _ => {}
+            },
+
+            Action::JumpBottom => match self.focused {
+                FocusedPanel::PeerList if !self.peers.is_empty() => {
+                    let prev = self.selected_peer_id().cloned();
+                    self.selected_peer_idx = Some(self.peers.len() - 1);
+                    self.switched_peer(prev);
+                }
+                FocusedPanel::Messages => {
+                    self.messages_scroll = 0;
+                    self.note_bottom_reached();
+                }
+                _ => {}
+            },
 
             Action::InputChar(ch) => {
                 self.input.insert(self.input_cursor, ch);
@@ -260,26 +895,824 @@ impl TuiApp {
 
             Action::FocusPanel(panel) => {
                 self.focused = panel;
+                if panel != FocusedPanel::Input {
+                    self.note_target = None;
+                }
+                if panel != FocusedPanel::Messages {
+                    self.copy = None;
+                    self.message_menu = None;
+                }
             }
 
             Action::SelectPeer(idx) => {
                 if !self.peers.is_empty() {
+                    let prev = self.selected_peer_id().cloned();
                     self.selected_peer_idx = Some(idx.min(self.peers.len() - 1));
                     self.focused = FocusedPanel::PeerList;
-                    self.messages_scroll = 0;
+                    self.switched_peer(prev);
+                    self.note_target = None;
+                }
+            }
+
+            Action::ToggleActivity => {
+                self.view = match self.view {
+                    View::Chat | View::Replay => View::Activity,
+                    View::Activity => View::Chat,
+                };
+                self.activity_scroll = 0;
+                self.replay = None;
+            }
+
+            Action::TogglePeerList => {
+                self.config.peer_list_hidden = !self.config.peer_list_hidden;
+                if self.config.peer_list_hidden && self.focused == FocusedPanel::PeerList {
+                    self.focused = FocusedPanel::Messages;
+                }
+            }
+
+            Action::NarrowPeerList => {
+                let narrowest = *TuiConfig::PEER_LIST_WIDTHS.start();
+                self.config.peer_list_width = self
+                    .config
+                    .peer_list_width
+                    .saturating_sub(PEER_LIST_WIDTH_STEP)
+                    .max(narrowest);
+            }
+
+            Action::WidenPeerList => {
+                if self.config.peer_list_hidden {
+                    self.config.peer_list_hidden = false;
+                } else {
+                    let widest = *TuiConfig::PEER_LIST_WIDTHS.end();
+                    self.config.peer_list_width = (self.config.peer_list_width + PEER_LIST_WIDTH_STEP).min(widest);
+                }
+            }
+
+            Action::ExportConversation => {
+                // Handled externally (sends the request to the daemon)
+            }
+
+            Action::CopyMessage => {
+                // Handled externally (needs the system clipboard)
+            }
+
+            Action::RetryMessage => {
+                // Handled externally (sends the request to the daemon)
+            }
+
+            Action::StartNote => {
+                let Some(last) = self.current_messages().len().checked_sub(1) else {
+                    self.status = "No hay mensajes para anotar".to_string();
+                    return;
+                };
+                self.start_note(last);
+            }
+
+            Action::NoteTargetPrev => {
+                if let Some(idx) = self.note_target {
+                    self.note_target = Some(idx.saturating_sub(1));
+                }
+            }
+
+            Action::NoteTargetNext => {
+                if let Some(idx) = self.note_target {
+                    let last = self.current_messages().len().saturating_sub(1);
+                    self.note_target = Some((idx + 1).min(last));
+                }
+            }
+
+            Action::CancelNote => {
+                self.note_target = None;
+                self.take_input();
+            }
+
+            Action::StartSearch => {
+                let Some(peer_id) = self.selected_peer_id().cloned() else {
+                    self.status = "Selecciona un peer para buscar en su conversacion".to_string();
+                    return;
+                };
+                match &mut self.search {
+                    // Searching again starts from the last query
+                    Some(search) if search.peer_id == peer_id => search.editing = true,
+                    _ => {
+                        self.search = Some(Search {
+                            peer_id,
+                            query: String::new(),
+                            editing: true,
+                            matches: HashSet::new(),
+                            current: None,
+                        })
+                    }
+                }
+                self.focused = FocusedPanel::Messages;
+                self.note_target = None;
+            }
+
+            Action::SearchChar(ch) => {
+                if let Some(search) = self.search.as_mut().filter(|s| s.editing) {
+                    search.query.push(ch);
+                }
+            }
+
+            Action::SearchBackspace => {
+                if let Some(search) = self.search.as_mut().filter(|s| s.editing) {
+                    search.query.pop();
+                }
+            }
+
+            Action::SubmitSearch => {
+                // Handled externally (sends the request to the daemon)
+            }
+
+            Action::MarkAllRead => {
+                // Handled externally (sends the requests to the daemon)
+            }
+
+            Action::ToggleMute => {
+                // Handled externally (sends the request to the daemon)
+            }
+
+            Action::SearchOlder => self.jump_to_match(true),
+
+            Action::SearchNewer => self.jump_to_match(false),
+
+            Action::CancelSearch => {
+                self.search = None;
+            }
+
+            Action::OpenSwitcher => {
+                self.switcher = Some(Switcher::default());
+            }
+
+            Action::SwitcherChar(c) => {
+                if let Some(switcher) = &mut self.switcher {
+                    switcher.query.push(c);
+                    switcher.selected = 0;
+                }
+            }
+
+            Action::SwitcherBackspace => {
+                if let Some(switcher) = &mut self.switcher {
+                    switcher.query.pop();
+                    switcher.selected = 0;
+                }
+            }
+
+            Action::SwitcherUp => {
+                if let Some(switcher) = &mut self.switcher {
+                    switcher.selected = switcher.selected.saturating_sub(1);
+                }
+            }
+
+            Action::SwitcherDown => {
+                let last = self.switcher_matches().len().saturating_sub(1);
+                if let Some(switcher) = &mut self.switcher {
+                    switcher.selected = (switcher.selected + 1).min(last);
+                }
+            }
+
+            Action::SubmitSwitcher => {
+                let selected = self.switcher.as_ref().map_or(0, |s| s.selected);
+                let Some(&idx) = self.switcher_matches().get(selected) else {
+                    self.status = "Ningun peer coincide".to_string();
+                    return;
+                };
+                self.switcher = None;
+                let prev = self.selected_peer_id().cloned();
+                self.selected_peer_idx = Some(idx);
+                self.switched_peer(prev);
+                self.focused = FocusedPanel::Input;
+            }
+
+            Action::CloseSwitcher => {
+                self.switcher = None;
+            }
+
+            Action::SelectMessage => {
+                let Some(message) = self.selected_message() else {
+                    self.status = "No hay mensajes".to_string();
+                    return;
+                };
+                self.message_menu = Some(MessageMenu {
+                    message_id: message.id.clone(),
+                    item: None,
+                });
+                self.status = "Elige un mensaje con las flechas y pulsa Enter (Esc sale)".to_string();
+            }
+
+            Action::MessageMenuUp => self.move_message_menu(false),
+
+            Action::MessageMenuDown => self.move_message_menu(true),
+
+            Action::SubmitMessageMenu => {
+                // Opening the popup; doing an entry is handled externally
+                if let Some(menu) = self.message_menu.as_mut().filter(|m| m.item.is_none()) {
+                    menu.item = Some(0);
+                }
+            }
+
+            Action::CloseMessageMenu => {
+                match &mut self.message_menu {
+                    Some(MessageMenu { item: item @ Some(_), .. }) => *item = None,
+                    _ => {
+                        self.message_menu = None;
+                        self.status.clear();
+                    }
+                }
+            }
+
+            Action::CancelReply => {
+                self.reply_to = None;
+            }
+
+            Action::StartCopy => self.start_copy(),
+
+            Action::CopyUp => self.move_copy_cursor(|copy, _| copy.line = copy.line.saturating_sub(1)),
+
+            Action::CopyDown => self.move_copy_cursor(|copy, _| copy.line += 1),
+
+            Action::CopyLeft => self.move_copy_cursor(|copy, _| copy.col = copy.col.saturating_sub(1)),
+
+            Action::CopyRight => self.move_copy_cursor(|copy, len| copy.col = (copy.col + 1).min(len)),
+
+            Action::CopyLineStart => self.move_copy_cursor(|copy, _| copy.col = 0),
+
+            Action::CopyLineEnd => self.move_copy_cursor(|copy, len| copy.col = len),
+
+            Action::CopyToggleSelection => {
+                if let Some(copy) = &mut self.copy {
+                    copy.anchor = match copy.anchor {
+                        Some(_) => None,
+                        None => Some((copy.line, copy.col)),
+                    };
+                }
+            }
+
+            Action::YankCopy => {
+                // Handled externally (needs the system clipboard)
+            }
+
+            Action::CancelCopy => {
+                self.copy = None;
+                self.status.clear();
+            }
+
+            Action::StartReplay => {
+                let Some(peer_id) = self.selected_peer_id().cloned() else {
+                    self.status = "Selecciona un peer para ver su historia".to_string();
+                    return;
+                };
+                self.replay = Some(Replay::new(peer_id));
+                self.view = View::Replay;
+                self.note_target = None;
+            }
+
+            Action::StopReplay => {
+                self.replay = None;
+                self.view = View::Chat;
+            }
+
+            Action::ReplayTogglePause => {
+                if let Some(replay) = &mut self.replay {
+                    replay.paused = !replay.paused;
+                    // Resuming waits the gap again rather than jumping ahead
+                    replay.next_at = None;
+                }
+            }
+
+            Action::ReplayFaster => {
+                if let Some(replay) = &mut self.replay {
+                    replay.pacing = replay.pacing.faster();
+                }
+            }
+
+            Action::ReplaySlower => {
+                if let Some(replay) = &mut self.replay {
+                    replay.pacing = replay.pacing.slower();
+                }
+            }
+
+            Action::ReplaySkip => {
+                if let Some(replay) = &mut self.replay {
+                    replay.advance(Instant::now(), true);
+                }
+            }
+
+            Action::ReplayTick => {
+                if let Some(replay) = self.replay.as_mut().filter(|r| !r.paused) {
+                    replay.advance(Instant::now(), false);
                 }
             }
 
             Action::ServerMessage(msg) => {
+                self.record_activity(&msg);
                 self.handle_server_message(msg);
             }
         }
     }
 
+    /// The next story-mode page to ask the daemon for, once the replay is
+    /// running low on fetched messages. Marks the page as requested.
+    pub fn replay_page_request(&mut self) -> Option<ClientRequest> {
+        let replay = self.replay.as_mut()?;
+        let buffered = replay.messages.len() - replay.shown;
+        if replay.loading || replay.complete || buffered >= REPLAY_PAGE as usize / 4 {
+            return None;
+        }
+        replay.loading = true;
+        Some(ClientRequest::GetTimeline {
+            peer_id: replay.peer_id.clone(),
+            after: replay.messages.last().map(|m| m.id.clone()),
+            limit: REPLAY_PAGE,
+        })
+    }
+
+    /// Stops editing the search query and returns the request for its
+    /// matches. A blank query leaves the search instead.
+    pub fn submit_search(&mut self) -> Option<ClientRequest> {
+        let search = self.search.as_mut()?;
+        let query = search.query.trim();
+        if query.is_empty() {
+            self.search = None;
+            return None;
+        }
+        search.editing = false;
+        self.status = format!("Buscando \"{query}\"...");
+        Some(ClientRequest::SearchMessages {
+            peer_id: search.peer_id.clone(),
+            query: search.query.clone(),
+            limit: SEARCH_LIMIT,
+        })
+    }
+
+    /// Clears the unread badges of every conversation and returns the
+    /// requests that mark them as read in the daemon, so its watermarks
+    /// (and the peers' read receipts) follow.
+    pub fn mark_all_read(&mut self) -> Vec<ClientRequest> {
+        let mut requests = Vec::new();
+        for (peer_id, summary) in &mut self.conversations {
+            if summary.unread_count > 0 {
+                summary.unread_count = 0;
+                requests.push(ClientRequest::MarkRead {
+                    peer_id: peer_id.clone(),
+                    up_to: None,
+                });
+            }
+        }
+        self.status = match requests.len() {
+            0 => "No hay mensajes sin leer".to_string(),
+            1 => "1 conversación marcada como leída".to_string(),
+            n => format!("{n} conversaciones marcadas como leídas"),
+        };
+        requests
+    }
+
+    /// Mutes the selected peer's notifications, or unmutes them, and returns
+    /// the request that tells the daemon. The daemon just answers `Ok`, so
+    /// the peer list shows the change right away; the sound setting is
+    /// kept as it was.
+    pub fn toggle_mute(&mut self) -> Option<ClientRequest> {
+        let idx = self.selected_peer_idx?;
+        let peer = self.peers.get_mut(idx)?;
+        peer.notifications.muted = !peer.notifications.muted;
+        self.status = if peer.notifications.muted {
+            format!("{} silenciado", peer.display_name)
+        } else {
+            format!("{} ya no está silenciado", peer.display_name)
+        };
+        Some(ClientRequest::SetPeerNotifications {
+            peer_id: peer.id.clone(),
+            muted: peer.notifications.muted,
+            sound: peer.notifications.sound,
+        })
+    }
+
+    /// Indexes (into `peers`) of the peers whose names match the
+    /// quick-switcher's query, best match first. Every peer matches an
+    /// empty query, in the peer list's order.
+    pub fn switcher_matches(&self) -> Vec<usize> {
+        let Some(switcher) = &self.switcher else {
+            return Vec::new();
+        };
+        let mut scored: Vec<(usize, usize)> = self
+            .peers
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, peer)| fuzzy_score(&peer.display_name, &switcher.query).map(|score| (score, idx)))
+            .collect();
+        scored.sort();
+        scored.into_iter().map(|(_, idx)| idx).collect()
+    }
+
+    /// Indexes (into `current_messages()`) of the loaded messages that
+    /// match the search, oldest first.
+    pub fn search_hits(&self) -> Vec<usize> {
+        let Some(search) = self.search.as_ref().filter(|s| self.selected_peer_id() == Some(&s.peer_id)) else {
+            return Vec::new();
+        };
+        self.current_messages()
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| search.matches.contains(&msg.id))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Jumps to the next older (or newer) match in the loaded history,
+    /// wrapping around at either end.
+    fn jump_to_match(&mut self, older: bool) {
+        let hits = self.search_hits();
+        if hits.is_empty() {
+            self.status = "Sin resultados en los mensajes cargados".to_string();
+            return;
+        }
+        let current = self
+            .search
+            .as_ref()
+            .and_then(|s| s.current.as_ref())
+            .and_then(|id| self.current_messages().iter().position(|m| &m.id == id));
+        let next = if older {
+            current.and_then(|c| hits.iter().rposition(|&h| h < c)).unwrap_or(hits.len() - 1)
+        } else {
+            current.and_then(|c| hits.iter().position(|&h| h > c)).unwrap_or(0)
+        };
+        self.show_match(&hits, next);
+    }
+
+    /// Marks `hits[pos]` as the current match and scrolls to it.
+    fn show_match(&mut self, hits: &[usize], pos: usize) {
+        let idx = hits[pos];
+        let id = self.current_messages()[idx].id.clone();
+        if let Some(search) = &mut self.search {
+            search.current = Some(id);
+        }
+        // With the match at the top of the panel
+        let below = self.message_line(self.current_messages().len()) - self.message_line(idx);
+        self.messages_scroll = below.saturating_sub(self.visible_message_lines()).min(self.max_messages_scroll());
+        // Counted from the newest, where the search starts
+        self.status = format!("Resultado {} de {} (n: anterior, N: siguiente)", hits.len() - pos, hits.len());
+    }
+
+    /// The row the message at `idx` starts on in the messages panel, laid
+    /// out and wrapped as `ui::messages` does it at the width last drawn.
+    /// `idx` may be the number of messages, for the total.
+    fn message_line(&self, idx: usize) -> u16 {
+        let (lines, starts) = crate::ui::messages::conversation_lines(self);
+        self.line_row(&lines, starts.get(idx).copied().unwrap_or(lines.len()))
+    }
+
+    /// The row `lines[line]` starts on, once the lines before it are
+    /// wrapped to the messages panel's width.
+    fn line_row(&self, lines: &[Line], line: usize) -> u16 {
+        let width = self.panel_rects.messages.width.saturating_sub(2);
+        crate::ui::messages::wrapped_height(&lines[..line.min(lines.len())], width).min(u16::MAX as usize) as u16
+    }
+
+    /// Starts writing a note on the message at `idx` of the open
+    /// conversation.
+    fn start_note(&mut self, idx: usize) {
+        self.note_target = Some(idx);
+        self.focused = FocusedPanel::Input;
+        // Start from the existing note, unless a message is being typed
+        if self.input.is_empty() {
+            self.load_note_into_input();
+        }
+    }
+
+    /// The message selected for the popup and its index in the open
+    /// conversation.
+    pub fn menu_message(&self) -> Option<(usize, &Message)> {
+        let menu = self.message_menu.as_ref()?;
+        self.current_messages().iter().enumerate().find(|(_, m)| m.id == menu.message_id)
+    }
+
+    /// What the popup offers for the selected message, in order.
+    pub fn message_menu_items(&self) -> Vec<MessageAction> {
+        let Some((_, message)) = self.menu_message() else {
+            return Vec::new();
+        };
+        let mut items = Vec::new();
+        if message.direction != Direction::System {
+            items.push(MessageAction::Reply);
+        }
+        items.extend([MessageAction::Copy, MessageAction::Note]);
+        if self.failed.contains(&message.id) {
+            items.push(MessageAction::Retry);
+        }
+        if message.direction != Direction::System {
+            items.push(MessageAction::Forward);
+        }
+        items.push(MessageAction::Delete);
+        if message.direction == Direction::Sent {
+            items.push(MessageAction::Retract);
+        }
+        items
+    }
+
+    /// Moves the message selection, or the popup's highlight once it's
+    /// open, and keeps the selected message on screen.
+    fn move_message_menu(&mut self, down: bool) {
+        let items = self.message_menu_items().len();
+        let Some(idx) = self.menu_message().map(|(idx, _)| idx) else {
+            self.message_menu = None;
+            return;
+        };
+        let last = self.current_messages().len() - 1;
+        let next = if down { (idx + 1).min(last) } else { idx.saturating_sub(1) };
+        let next_id = self.current_messages()[next].id.clone();
+        let Some(menu) = self.message_menu.as_mut() else {
+            return;
+        };
+        if let Some(item) = &mut menu.item {
+            *item = if down { (*item + 1).min(items.saturating_sub(1)) } else { item.saturating_sub(1) };
+            return;
+        }
+        menu.message_id = next_id;
+        let idx = next;
+
+        let total = self.message_line(self.current_messages().len());
+        let above = total - self.message_line(idx);
+        let below = total - self.message_line(idx + 1);
+        self.scroll_into_view(above, below);
+    }
+
+    /// Closes the popup and returns its highlighted entry, with the message
+    /// it's for, for `main.rs` to carry out. A reply or a note is started
+    /// here.
+    pub fn take_message_action(&mut self) -> Option<(MessageAction, Message)> {
+        let item = self.message_menu.as_ref()?.item?;
+        let action = *self.message_menu_items().get(item)?;
+        let (idx, message) = self.menu_message().map(|(idx, m)| (idx, m.clone()))?;
+        self.message_menu = None;
+        self.status.clear();
+        match action {
+            MessageAction::Reply => {
+                self.reply_to = Some(message.id.clone());
+                self.focused = FocusedPanel::Input;
+            }
+            MessageAction::Note => self.start_note(idx),
+            MessageAction::Forward => {
+                self.switcher = Some(Switcher {
+                    forward: Some(message.content.clone()),
+                    ..Switcher::default()
+                });
+            }
+            _ => {}
+        }
+        Some((action, message))
+    }
+
+    /// Sends the message being forwarded to the peer highlighted in the
+    /// quick-switcher: returns the request and closes the switcher. The
+    /// open conversation stays as it was.
+    pub fn submit_forward(&mut self) -> Option<ClientRequest> {
+        let selected = self.switcher.as_ref()?.selected;
+        let Some(&idx) = self.switcher_matches().get(selected) else {
+            self.status = "Ningun peer coincide".to_string();
+            return None;
+        };
+        let content = self.switcher.take()?.forward?;
+        let peer = &self.peers[idx];
+        self.status = format!("Mensaje reenviado a {}", peer.display_name);
+        // Sent as it reads, not as a slash command
+        let content = if content.starts_with('/') { format!("/{content}") } else { content };
+        Some(ClientRequest::SendMessage {
+            peer_id: peer.id.clone(),
+            content,
+            reply_to: None,
+        })
+    }
+
+    /// Enters copy mode with the cursor on the last line with text at the
+    /// bottom of the panel.
+    fn start_copy(&mut self) {
+        let (lines, _) = crate::ui::messages::conversation_lines(self);
+        if lines.is_empty() {
+            self.status = "No hay mensajes para copiar".to_string();
+            return;
+        }
+        let total = self.line_row(&lines, lines.len());
+        let bottom = total.saturating_sub(self.messages_scroll);
+        let mut line = (0..lines.len()).rposition(|l| self.line_row(&lines, l) < bottom).unwrap_or(0);
+        while line > 0 && lines[line].width() == 0 {
+            line -= 1;
+        }
+        self.copy = Some(CopyMode { line, ..CopyMode::default() });
+        self.status = "Modo copia: flechas/hjkl mueven, v selecciona, y copia, Esc sale".to_string();
+    }
+
+    /// Moves the copy mode cursor with `step`, which gets the length of the
+    /// cursor's line, and scrolls the panel to keep it on screen.
+    fn move_copy_cursor(&mut self, step: impl FnOnce(&mut CopyMode, usize)) {
+        let Some(mut copy) = self.copy.take() else {
+            return;
+        };
+        // Rows above and below the cursor's line, counted up from the
+        // bottom like `messages_scroll`
+        let (above, below) = {
+            let (lines, _) = crate::ui::messages::conversation_lines(self);
+            let len = |line: usize| lines.get(line).map_or(0, |l| crate::ui::messages::line_text(l).chars().count());
+            let last_col = len(copy.line).saturating_sub(1);
+            step(&mut copy, last_col);
+            copy.line = copy.line.min(lines.len().saturating_sub(1));
+            copy.col = copy.col.min(len(copy.line).saturating_sub(1));
+            let total = self.line_row(&lines, lines.len());
+            (total - self.line_row(&lines, copy.line), total - self.line_row(&lines, copy.line + 1))
+        };
+        self.scroll_into_view(above, below);
+        self.copy = Some(copy);
+    }
+
+    /// Scrolls the messages panel just enough to show rows that have
+    /// `above` rows from their top to the bottom of the conversation and
+    /// `below` under them.
+    fn scroll_into_view(&mut self, above: u16, below: u16) {
+        let visible = self.visible_message_lines();
+        if above > self.messages_scroll.saturating_add(visible) {
+            self.messages_scroll = above.saturating_sub(visible).min(self.max_messages_scroll());
+        } else if below < self.messages_scroll {
+            self.messages_scroll = below;
+            self.note_bottom_reached();
+        }
+    }
+
+    /// The text copy mode copies: the selection, from the anchor to the
+    /// cursor (both included), or the cursor's whole line without one.
+    /// Trailing spaces are left out of every line.
+    pub fn copy_selection(&self) -> Option<String> {
+        let copy = self.copy.as_ref()?;
+        let (lines, _) = crate::ui::messages::conversation_lines(self);
+        let ((first, from), (last, to)) = match copy.anchor {
+            Some(anchor) => (anchor.min((copy.line, copy.col)), anchor.max((copy.line, copy.col))),
+            None => ((copy.line, 0), (copy.line, usize::MAX - 1)),
+        };
+        let text: Vec<String> = (first..=last.min(lines.len().saturating_sub(1)))
+            .map(|line| {
+                let text = crate::ui::messages::line_text(&lines[line]);
+                let from = if line == first { from } else { 0 };
+                let to = if line == last { to + 1 } else { usize::MAX };
+                let selected: String = text.chars().take(to).skip(from).collect();
+                selected.trim_end().to_string()
+            })
+            .collect();
+        Some(text.join("\n"))
+    }
+
+    /// Lines of the messages panel inside its borders, as last drawn.
+    fn visible_message_lines(&self) -> u16 {
+        self.panel_rects.messages.height.saturating_sub(2)
+    }
+
+    /// The highest `messages_scroll`: the top of the loaded history.
+    pub fn max_messages_scroll(&self) -> u16 {
+        self.message_line(self.current_messages().len())
+            .saturating_sub(self.visible_message_lines())
+    }
+
+    /// The request for the latest page of a conversation, to show it when
+    /// it's opened.
+    pub fn latest_page_request(&mut self, peer_id: PeerId) -> ClientRequest {
+        self.page_requests.push_back(PageRequest {
+            peer_id: peer_id.clone(),
+            older: false,
+        });
+        ClientRequest::GetMessages {
+            peer_id,
+            limit: HISTORY_PAGE,
+            before: None,
+            cursor: None,
+        }
+    }
+
+    /// The page before the loaded history to ask the daemon for, once the
+    /// messages panel is scrolled to its top. Marks the page as requested.
+    pub fn older_page_request(&mut self) -> Option<ClientRequest> {
+        if self.view != View::Chat || self.messages_scroll < self.max_messages_scroll() {
+            return None;
+        }
+        let peer_id = self.selected_peer_id()?.clone();
+        // Wait for a page on its way (its cursor may be about to change)
+        if self.page_requests.iter().any(|r| r.peer_id == peer_id) {
+            return None;
+        }
+        let cursor = self.older_cursors.get(&peer_id)?.clone()?;
+        self.page_requests.push_back(PageRequest {
+            peer_id: peer_id.clone(),
+            older: true,
+        });
+        self.status = "Cargando mensajes anteriores...".to_string();
+        Some(ClientRequest::GetMessages {
+            peer_id,
+            limit: HISTORY_PAGE,
+            before: None,
+            cursor: Some(cursor),
+        })
+    }
+
+    /// Forgets the history pages asked for: after losing the connection
+    /// to the daemon they'll never be answered.
+    pub fn connection_lost(&mut self) {
+        self.page_requests.clear();
+    }
+
+    /// Replaces the input with the current note of the targeted message.
+    fn load_note_into_input(&mut self) {
+        let note = self
+            .note_target_message()
+            .and_then(|msg| self.notes.get(&msg.id))
+            .cloned()
+            .unwrap_or_default();
+        self.input_cursor = note.len();
+        self.input = note;
+    }
+
+    /// Returns the display name for a peer ID, or the raw ID if unknown.
+    fn peer_name(&self, peer_id: &PeerId) -> String {
+        self.peers
+            .iter()
+            .find(|p| &p.id == peer_id)
+            .map(|p| p.display_name.clone())
+            .unwrap_or_else(|| peer_id.to_string())
+    }
+
+    /// Adds a line to the activity feed for daemon events worth seeing.
+    ///
+    /// Runs before the event is applied, so e.g. a `PeerOffline` can still
+    /// be resolved to the peer's name.
+    fn record_activity(&mut self, msg: &ServerMessage) {
+        let (level, text) = match msg {
+            ServerMessage::PeerOnline { peer } => (
+                ActivityLevel::Info,
+                format!("{} en linea ({})", peer.display_name, peer.addresses.join(", ")),
+            ),
+            ServerMessage::PeerOffline { peer_id } => (
+                ActivityLevel::Warning,
+                format!("{} desconectado", self.peer_name(peer_id)),
+            ),
+            ServerMessage::NewMessage { message } => {
+                let name = self.peer_name(&message.peer_id);
+                match message.direction {
+                    Direction::Received if message.announcement => {
+                        (ActivityLevel::Warning, format!("Anuncio de {name}: {}", message.content))
+                    }
+                    Direction::Received => (ActivityLevel::Info, format!("Mensaje recibido de {name}")),
+                    Direction::Sent => (ActivityLevel::Info, format!("Mensaje enviado a {name}")),
+                    Direction::System => (ActivityLevel::Info, format!("Aviso del sistema para {name}")),
+                }
+            }
+            ServerMessage::NewMessages { peer_id, messages } => (
+                ActivityLevel::Info,
+                format!("{} mensajes recibidos de {}", messages.len(), self.peer_name(peer_id)),
+            ),
+            ServerMessage::MessageDelivered { message_id } => {
+                let peer = self
+                    .messages
+                    .iter()
+                    .find(|(_, msgs)| msgs.iter().any(|m| &m.id == message_id))
+                    .map(|(peer_id, _)| self.peer_name(peer_id));
+                let text = match peer {
+                    Some(name) => format!("Mensaje entregado a {name}"),
+                    None => format!("Mensaje {message_id} entregado"),
+                };
+                (ActivityLevel::Info, text)
+            }
+            ServerMessage::MessageFailed { message, reason } => (
+                ActivityLevel::Warning,
+                format!("No se pudo entregar un mensaje a {} ({reason})", self.peer_name(&message.peer_id)),
+            ),
+            ServerMessage::Error { code, message } => {
+                (ActivityLevel::Error, format!("Error [{code}]: {message}"))
+            }
+            ServerMessage::Exported { peer_id, path, .. } => (
+                ActivityLevel::Info,
+                format!("Conversacion con {} exportada a {path}", self.peer_name(peer_id)),
+            ),
+            ServerMessage::SubsystemRestarted { name, reason, .. } => (
+                ActivityLevel::Warning,
+                format!("El daemon reinicio {name} ({reason})"),
+            ),
+            _ => return,
+        };
+
+        if self.activity.len() == MAX_ACTIVITY_ENTRIES {
+            self.activity.pop_front();
+        }
+        self.activity.push_back(ActivityEntry {
+            at: Timestamp::now(),
+            level,
+            text,
+        });
+        // Keep the user's place if they've scrolled back through the log
+        if self.activity_scroll > 0 {
+            self.activity_scroll = self.activity_scroll.saturating_add(1);
+        }
+    }
+
     /// Processes a message from the daemon.
     fn handle_server_message(&mut self, msg: ServerMessage) {
         match msg {
             ServerMessage::PeerList { peers } => {
+                let prev = self.selected_peer_id().cloned();
                 self.peers = peers;
                 // Ensure selected index is still valid
                 if let Some(idx) = self.selected_peer_idx {
@@ -293,29 +1726,152 @@ impl TuiApp {
                 } else if !self.peers.is_empty() {
                     self.selected_peer_idx = Some(0);
                 }
+                self.switched_peer(prev);
                 let n = self.peers.len();
                 self.status = format!("{n} peer{}", if n == 1 { "" } else { "s" });
             }
 
-            ServerMessage::Messages { messages } => {
+            ServerMessage::Messages { messages, next_cursor, .. } => {
+                // A page of another conversation means an answer went
+                // missing (an error came instead): start matching afresh
+                let page = self
+                    .page_requests
+                    .pop_front()
+                    .filter(|p| messages.first().is_none_or(|m| m.peer_id == p.peer_id));
+                if page.is_none() {
+                    self.page_requests.clear();
+                }
+                let Some(peer_id) = page
+                    .as_ref()
+                    .map(|p| p.peer_id.clone())
+                    .or_else(|| messages.first().map(|m| m.peer_id.clone()))
+                else {
+                    return;
+                };
+                self.older_cursors.insert(peer_id.clone(), next_cursor);
                 // Messages come newest-first from the DB. Reverse them
                 // for display (oldest-first, chronological order).
-                if let Some(peer_id) = messages.first().map(|m| m.peer_id.clone()) {
-                    let mut msgs = messages;
-                    msgs.reverse();
+                let mut msgs = messages;
+                msgs.reverse();
+                if page.is_some_and(|p| p.older) {
+                    // The scroll offset counts from the bottom, so the
+                    // lines on screen stay put as the page goes above them
+                    let loaded = msgs.len();
+                    let lines_before = self.copy.as_ref().map(|_| crate::ui::messages::conversation_lines(self).0.len());
+                    self.messages.entry(peer_id.clone()).or_default().splice(0..0, msgs);
+                    if self.selected_peer_id() == Some(&peer_id) {
+                        // Indexes into the history moved along
+                        self.note_target = self.note_target.map(|idx| idx + loaded);
+                        if let Some(before) = lines_before {
+                            let added = crate::ui::messages::conversation_lines(self).0.len().saturating_sub(before);
+                            if let Some(copy) = &mut self.copy {
+                                copy.line += added;
+                                if let Some(anchor) = &mut copy.anchor {
+                                    anchor.0 += added;
+                                }
+                            }
+                        }
+                        self.status = match loaded {
+                            0 => "No hay mensajes anteriores".to_string(),
+                            n => format!("{n} mensajes anteriores cargados"),
+                        };
+                    }
+                } else {
                     self.messages.insert(peer_id, msgs);
                 }
             }
 
+            ServerMessage::Timeline { peer_id, messages } => {
+                let Some(replay) = self.replay.as_mut().filter(|r| r.peer_id == peer_id) else {
+                    return;
+                };
+                replay.loading = false;
+                replay.complete = messages.len() < REPLAY_PAGE as usize;
+                replay.messages.extend(messages);
+                if replay.messages.is_empty() {
+                    self.replay = None;
+                    self.view = View::Chat;
+                    self.status = "No hay mensajes para reproducir".to_string();
+                }
+            }
+
+            ServerMessage::SearchResults { peer_id, query, messages } => {
+                // Results of an older query, or another conversation's, are stale
+                let Some(search) = self
+                    .search
+                    .as_mut()
+                    .filter(|s| s.peer_id == peer_id && s.query == query)
+                else {
+                    return;
+                };
+                search.matches = messages.iter().map(|m| m.id.clone()).collect();
+                search.current = None;
+                let hits = self.search_hits();
+                if messages.is_empty() {
+                    self.status = format!("Sin resultados para \"{}\"", query.trim());
+                } else if hits.is_empty() {
+                    self.status = format!("{} resultados, ninguno en los mensajes cargados", messages.len());
+                } else {
+                    self.show_match(&hits, hits.len() - 1);
+                    if messages.len() > hits.len() {
+                        let older = messages.len() - hits.len();
+                        self.status.push_str(&format!(" - {older} mas en mensajes antiguos"));
+                    }
+                }
+            }
+
+            ServerMessage::MessageDeleted { peer_id, message_id } => {
+                let is_open = self.selected_peer_id() == Some(&peer_id);
+                let Some(msgs) = self.messages.get_mut(&peer_id) else {
+                    return;
+                };
+                let Some(idx) = msgs.iter().position(|m| m.id == message_id) else {
+                    return;
+                };
+                msgs.remove(idx);
+                let remaining = msgs.len();
+                self.notes.remove(&message_id);
+
+                // Keep a note being written on the same message
+                if let Some(target) = self.note_target.filter(|_| is_open) {
+                    self.note_target = match target.cmp(&idx) {
+                        std::cmp::Ordering::Less => Some(target),
+                        std::cmp::Ordering::Greater => Some(target - 1),
+                        // Its message is gone: fall back to the nearest one
+                        std::cmp::Ordering::Equal => remaining.checked_sub(1).map(|last| target.min(last)),
+                    };
+                    // Nothing left to annotate; don't let Enter send the note as a message
+                    if self.note_target.is_none() {
+                        self.take_input();
+                    }
+                }
+            }
+
             ServerMessage::NewMessage { message } => {
-                // Add the new message to the correct peer's history
+                self.note_last_message(&message);
+                // What they were typing has arrived
+                if message.direction == Direction::Received {
+                    self.typing.remove(&message.peer_id);
+                }
                 let peer_id = message.peer_id.clone();
-                self.messages
-                    .entry(peer_id)
-                    .or_default()
-                    .push(message);
-                // Reset scroll to show the newest message
-                self.messages_scroll = 0;
+                self.append_messages(peer_id, vec![message]);
+            }
+
+            ServerMessage::NewMessages { peer_id, messages } => {
+                for message in &messages {
+                    self.note_last_message(message);
+                }
+                self.typing.remove(&peer_id);
+                self.append_messages(peer_id, messages);
+            }
+
+            ServerMessage::Conversations { conversations } => {
+                self.conversations = conversations
+                    .into_iter()
+                    .map(|c| (c.peer_id.clone(), c))
+                    .collect();
+                // The open conversation is being read, whatever the snapshot says
+                self.clear_selected_unread();
             }
 
             ServerMessage::MessageSent { message_id: _ } => {
@@ -329,6 +1885,10 @@ impl TuiApp {
                     existing.online = true;
                     existing.display_name = peer.display_name;
                     existing.addresses = peer.addresses;
+                    existing.avatar = peer.avatar;
+                    existing.accent_color = peer.accent_color;
+                    existing.capabilities = peer.capabilities;
+                    existing.interface_addresses = peer.interface_addresses;
                 } else {
                     self.peers.push(peer);
                 }
@@ -337,12 +1897,44 @@ impl TuiApp {
             }
 
             ServerMessage::PeerOffline { peer_id } => {
+                self.typing.remove(&peer_id);
                 if let Some(peer) = self.peers.iter_mut().find(|p| p.id == peer_id) {
                     peer.online = false;
                 }
             }
 
+            ServerMessage::MessagesRead { peer_id, up_to } => {
+                if let Some(summary) = self.conversations.get_mut(&peer_id) {
+                    summary.read_by_peer = summary.read_by_peer.max(Some(up_to));
+                }
+            }
+
+            ServerMessage::PeerTyping { peer_id } => {
+                self.typing.insert(peer_id, Instant::now());
+            }
+
+            ServerMessage::MessageFailed { message, reason } => {
+                let name = self.peer_name(&message.peer_id);
+                // What we just sent shows with an ID of our own until the
+                // history is reloaded; it takes the daemon's, so a retry
+                // names the right message
+                if let Some(messages) = self.messages.get_mut(&message.peer_id) {
+                    if !messages.iter().any(|m| m.id == message.id) {
+                        if let Some(shown) = messages
+                            .iter_mut()
+                            .rev()
+                            .find(|m| m.direction == Direction::Sent && !m.delivered && m.content == message.content)
+                        {
+                            shown.id = message.id.clone();
+                        }
+                    }
+                }
+                self.failed.insert(message.id);
+                self.status = format!("No se pudo entregar el mensaje a {name}: {reason} (t: reintentar)");
+            }
+
             ServerMessage::MessageDelivered { message_id } => {
+                self.failed.remove(&message_id);
                 // Mark the message as delivered in our local state
                 for messages in self.messages.values_mut() {
                     if let Some(msg) = messages.iter_mut().find(|m| m.id == message_id) {
@@ -364,6 +1956,50 @@ impl TuiApp {
                 self.status = format!("Error [{code}]: {message}");
             }
 
+            ServerMessage::SubsystemRestarted { name, .. } => {
+                self.status = format!("El daemon reinicio {name} tras un fallo");
+            }
+
+            ServerMessage::ExportProgress { exported, total, .. } => {
+                self.status = format!("Exportando conversacion... {exported}/{total}");
+            }
+
+            ServerMessage::Exported { path, messages, .. } => {
+                self.status = format!("Conversacion exportada a {path} ({messages} mensajes)");
+            }
+
+            ServerMessage::MessageNotes { peer_id, notes } => {
+                // Replace what we had for this conversation (notes removed
+                // elsewhere must disappear too)
+                if let Some(messages) = self.messages.get(&peer_id) {
+                    for msg in messages {
+                        self.notes.remove(&msg.id);
+                    }
+                }
+                self.notes
+                    .extend(notes.into_iter().map(|n| (n.message_id, n.note)));
+            }
+
+            // The TUI doesn't request these yet; `socat` users do.
+            // (`familycom notes` searches notes and `familycom --announce`
+            // announces on their own connections.)
+            ServerMessage::Status { .. }
+            | ServerMessage::Announced { .. }
+            | ServerMessage::Pong { .. }
+            | ServerMessage::ConfigReloaded { .. }
+            | ServerMessage::ShuttingDown { .. }
+            | ServerMessage::Summary { .. }
+            | ServerMessage::AuditLog { .. }
+            | ServerMessage::NoteSearchResults { .. }
+            | ServerMessage::Subscriptions { .. }
+            | ServerMessage::DoNotDisturb { .. } => {}
+
+            // Reassembled into `Messages` by the IPC client
+            ServerMessage::MessagesChunk { .. } | ServerMessage::MessagesEnd { .. } => {}
+
+            // Consumed by `IpcClient::reconnect`
+            ServerMessage::Resumed { .. } => {}
+
             ServerMessage::Ok => {}
         }
     }
@@ -377,3 +2013,32 @@ impl TuiApp {
         content
     }
 }
+
+/// How well `name` matches a quick-switcher query, lower being better, or
+/// `None` if it doesn't. The query's letters have to appear in the name in
+/// order ("hmam" matches "Habitación de Mamá"); case and accents are
+/// ignored. Letters closer to the start and to each other score better.
+fn fuzzy_score(name: &str, query: &str) -> Option<usize> {
+    let name: Vec<char> = name.chars().map(fold_letter).collect();
+    let mut score = 0;
+    let mut pos = 0;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()).map(fold_letter) {
+        let found = name[pos..].iter().position(|&c| c == wanted)?;
+        score += found;
+        pos += found + 1;
+    }
+    Some(score)
+}
+
+/// Lowercases a letter and drops its accent, for matching names.
+fn fold_letter(c: char) -> char {
+    match c.to_lowercase().next().unwrap_or(c) {
+        'á' | 'à' | 'ä' | 'â' => 'a',
+        'é' | 'è' | 'ë' | 'ê' => 'e',
+        'í' | 'ì' | 'ï' | 'î' => 'i',
+        'ó' | 'ò' | 'ö' | 'ô' => 'o',
+        'ú' | 'ù' | 'ü' | 'û' => 'u',
+        'ñ' => 'n',
+        other => other,
+    }
+}
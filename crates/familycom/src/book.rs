@@ -0,0 +1,189 @@
+//! Keepsake book export (`familycom book`).
+//!
+//! Fetches a date range of one conversation from the daemon and writes it
+//! as an EPUB with a chapter per month (see `familycom_core::book`):
+//!
+//! ```bash
+//! familycom book --peer Abuela --since 2026-01-01 --until 2026-12-31 \
+//!     --out familia-2026.epub --pdf familia-2026.pdf
+//! ```
+//!
+//! # Images
+//!
+//! Pictures received from the peer are taken from its downloads folder
+//! (`[downloads]`, which needs `per_peer_folders = true` to know whose
+//! files are whose). Each one goes into the book at the time the file was
+//! written, so it lands next to the messages it arrived with.
+//!
+//! # PDF
+//!
+//! EPUB is built in; PDF is delegated to an external converter, by default
+//! Calibre's `ebook-convert`. `--pdf-command` swaps it for any program,
+//! with `{epub}` and `{pdf}` replaced by the two paths.
+
+use crate::ipc_client::IpcClient;
+use crate::print;
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate};
+use familycom_core::book::{self, Book, BookImage};
+use familycom_core::config::AppConfig;
+use familycom_core::files::sanitize_component;
+use familycom_core::ipc::{ClientRequest, ServerMessage};
+use familycom_core::types::Timestamp;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Converter used for `--pdf` when no `--pdf-command` is given.
+pub const DEFAULT_PDF_COMMAND: &str = "ebook-convert {epub} {pdf}";
+
+/// What `familycom book` was asked to make.
+pub struct BookRequest {
+    pub peer: String,
+    pub since: Option<NaiveDate>,
+    /// Last day included.
+    pub until: Option<NaiveDate>,
+    pub title: Option<String>,
+    pub out: PathBuf,
+    pub pdf: Option<PathBuf>,
+    pub pdf_command: String,
+    pub images: bool,
+}
+
+/// Builds the book and writes the EPUB (and the PDF, if asked).
+pub async fn run(socket_path: &PathBuf, request: &BookRequest) -> Result<()> {
+    let mut client = IpcClient::connect_to(socket_path)
+        .await
+        .context("could not connect to daemon")?;
+
+    client.send(&ClientRequest::ListPeers).await?;
+    let peers = match client.recv().await? {
+        ServerMessage::PeerList { peers } => peers,
+        ServerMessage::Error { message, .. } => bail!("{message}"),
+        other => bail!("unexpected response from daemon: {other:?}"),
+    };
+    let peer = print::find_peer(&peers, &request.peer)?;
+
+    let since = request.since.map(print::local_midnight);
+    let until = request.until.and_then(|d| d.succ_opt()).map(print::local_midnight);
+    let mut messages = print::fetch_since(&mut client, peer, since).await?;
+    if let Some(until) = until {
+        messages.retain(|m| m.timestamp < until);
+    }
+    // The daemon returns newest first; books read front to back
+    messages.reverse();
+
+    let images = if request.images {
+        received_images(&peer.display_name, since, until)
+    } else {
+        Vec::new()
+    };
+
+    let title = request.title.clone().unwrap_or_else(|| default_title(&peer.display_name, request));
+    let book = Book {
+        title,
+        peer_name: peer.display_name.clone(),
+        created_at: Timestamp::now(),
+        messages,
+        images,
+    };
+    let message_count = book.messages.len();
+    let image_count = book.images.len();
+
+    let file = File::create(&request.out)
+        .with_context(|| format!("could not create {}", request.out.display()))?;
+    book.write_epub(BufWriter::new(file), &Local)
+        .with_context(|| format!("could not write {}", request.out.display()))?;
+    println!(
+        "Libro guardado en {} ({message_count} mensajes, {image_count} imagenes)",
+        request.out.display()
+    );
+
+    if let Some(pdf) = &request.pdf {
+        convert_to_pdf(&request.pdf_command, &request.out, pdf)?;
+        println!("PDF guardado en {}", pdf.display());
+    }
+    Ok(())
+}
+
+/// "Abuela 2026" for a single year, otherwise "Conversacion con Abuela".
+fn default_title(peer_name: &str, request: &BookRequest) -> String {
+    use chrono::Datelike;
+    match (request.since, request.until) {
+        (Some(since), Some(until)) if since.year() == until.year() => format!("{peer_name} {}", since.year()),
+        _ => format!("Conversacion con {peer_name}"),
+    }
+}
+
+/// Images in the peer's downloads folder written within `[since, until)`.
+///
+/// Best effort: a missing folder or unreadable file just means fewer
+/// pictures in the book.
+fn received_images(peer_name: &str, since: Option<Timestamp>, until: Option<Timestamp>) -> Vec<BookImage> {
+    let Ok(Some(config)) = AppConfig::load() else {
+        return Vec::new();
+    };
+    if !config.downloads.per_peer_folders {
+        eprintln!("Aviso: sin per_peer_folders no se sabe de quien es cada archivo; el libro va sin imagenes");
+        return Vec::new();
+    }
+    let (Some(root), Some(folder)) = (config.downloads.root_dir(), sanitize_component(peer_name)) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(root.join(folder)) else {
+        return Vec::new();
+    };
+
+    let mut images = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !book::is_image(&file_name) {
+            continue;
+        }
+        let Some(received_at) = modified_at(&entry.path()) else {
+            continue;
+        };
+        if since.is_some_and(|s| received_at < s) || until.is_some_and(|u| received_at >= u) {
+            continue;
+        }
+        if let Ok(data) = std::fs::read(entry.path()) {
+            images.push(BookImage {
+                file_name,
+                received_at,
+                data,
+            });
+        }
+    }
+    images
+}
+
+/// When the file was last written, as a `Timestamp`.
+fn modified_at(path: &Path) -> Option<Timestamp> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let millis = modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_millis();
+    Some(Timestamp::from_millis(i64::try_from(millis).ok()?))
+}
+
+/// Runs the PDF converter. The command is split on whitespace (no shell),
+/// and `{epub}` / `{pdf}` in any argument are replaced by the paths.
+fn convert_to_pdf(command: &str, epub: &Path, pdf: &Path) -> Result<()> {
+    let args: Vec<String> = command
+        .split_whitespace()
+        .map(|arg| {
+            arg.replace("{epub}", &epub.to_string_lossy())
+                .replace("{pdf}", &pdf.to_string_lossy())
+        })
+        .collect();
+    let Some((program, args)) = args.split_first() else {
+        bail!("--pdf-command esta vacio");
+    };
+
+    let status = Command::new(program).args(args).status().with_context(|| {
+        format!("no se pudo ejecutar '{program}' (instala Calibre o indica otro con --pdf-command)")
+    })?;
+    if !status.success() {
+        bail!("'{program}' fallo ({status}); el EPUB quedo en {}", epub.display());
+    }
+    Ok(())
+}
@@ -8,20 +8,75 @@
 //!
 //! | Key          | Context     | Action                    |
 //! |--------------|-------------|---------------------------|
+//! | F2           | Any         | Toggle the activity feed  |
+//! | F4           | Chat        | Hide / show the peer list |
+//! | Ctrl+Left/Right | Chat     | Narrow / widen the peer list |
+//! | Ctrl+K       | Chat        | Quick-switch to a peer    |
 //! | Tab          | Any         | Switch focus to next panel |
 //! | Esc / q      | Not input   | Quit the TUI              |
 //! | Up / k       | Peer list   | Select previous peer      |
 //! | Down / j     | Peer list   | Select next peer          |
+//! | m            | Peer list   | Mute / unmute the peer    |
+//! | /            | Not input   | Search the conversation   |
+//! | R            | Not input   | Mark everything as read   |
 //! | PageUp       | Messages    | Scroll up (older)         |
 //! | PageDown     | Messages    | Scroll down (newer)       |
+//! | End          | Messages    | Jump to the newest        |
+//! | n            | Messages    | Write a private note      |
+//! | n / N        | Messages    | Older / newer search match |
+//! | r            | Messages    | Replay the conversation   |
+//! | e            | Messages    | Export the conversation   |
+//! | y            | Messages    | Copy the message's text   |
+//! | v            | Messages    | Copy mode (select text)   |
+//! | Enter        | Messages    | Select a message to act on |
+//! | t            | Messages    | Retry an undelivered message |
 //! | Enter        | Input       | Send message              |
 //! | Backspace    | Input       | Delete char before cursor |
 //! | Delete       | Input       | Delete char after cursor  |
 //! | Left/Right   | Input       | Move cursor               |
 //! | Home/End     | Input       | Jump to start/end         |
 //! | Any char     | Input       | Type that character       |
+//!
+//! Quitting with something typed in the input asks first: s (or Enter, or
+//! q again) quits and n (or Esc) goes back.
+//!
+//! While the activity feed is shown, Up/Down/PageUp/PageDown scroll it and
+//! Esc returns to the chat.
+//!
+//! While typing a search, Enter looks it up and Esc cancels. Once it's
+//! done, n and N go through the matches instead of starting a note, and
+//! Esc drops the search.
+//!
+//! While selecting a message, Up/Down (or j/k) pick it and Enter opens a
+//! popup of what to do with it (reply, copy, note, retry, forward,
+//! delete); in the popup, Up/Down choose and Enter does it. Esc closes the
+//! popup, and then stops selecting.
+//!
+//! In copy mode, arrows or h/j/k/l move the cursor through the messages as
+//! drawn, 0 and $ go to the start and end of the line, v (or Space) starts
+//! selecting, y (or Enter) copies the selection (or the cursor's line) and
+//! Esc leaves.
+//!
+//! In the quick-switcher, typing filters the peers by name, Up/Down pick
+//! one, Enter opens their conversation and Esc closes it.
+//!
+//! While replying to a message, Esc in the input stops replying (the text
+//! stays).
+//!
+//! While writing a note, Up/Down pick the message it's for, Enter saves it
+//! and Esc cancels.
+//!
+//! With `vim_mode = true` in tui.toml, the peer list and the messages are
+//! normal mode and the input is insert mode: `i` starts typing and Esc
+//! stops, instead of quitting (only q quits). In normal mode, besides j/k
+//! and /, gg and G go to the first and last peer or the oldest and newest
+//! message, and h/l move between the peer list and the messages.
+//!
+//! In story mode, Space pauses, +/- change the speed, Enter shows the next
+//! message right away and Esc returns to the chat.
 
-use crate::app::{Action, FocusedPanel, TuiApp};
+use crate::app::{Action, FocusedPanel, TuiApp, View};
+use crate::ui;
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
 
 /// Converts a crossterm `Event` into an optional `Action`.
@@ -39,11 +94,59 @@ pub fn handle_event(event: &Event, app: &TuiApp) -> Option<Action> {
 
 /// Converts a key event into an action based on the current focus.
 fn handle_key_event(key: &KeyEvent, app: &TuiApp) -> Option<Action> {
-    // Ctrl+C always quits, regardless of focus
+    // Ctrl+C always quits, regardless of focus (at the quit prompt too)
     if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
         return Some(Action::Quit);
     }
 
+    // The quit prompt takes every key until it's answered
+    if app.confirm_quit {
+        return handle_confirm_quit_key(key);
+    }
+
+    // F2 toggles the activity feed from anywhere
+    if key.code == KeyCode::F(2) {
+        return Some(Action::ToggleActivity);
+    }
+
+    // The activity feed has no panels, just a scrollable log
+    if app.view == View::Activity {
+        return handle_activity_key(key);
+    }
+
+    if app.view == View::Replay {
+        return handle_replay_key(key);
+    }
+
+    // The quick-switcher takes every key while it's open
+    if app.switcher.is_some() {
+        return handle_switcher_key(key);
+    }
+
+    // The peer list's size and the quick-switcher, from anywhere in the chat
+    match key.code {
+        KeyCode::F(4) => return Some(Action::TogglePeerList),
+        KeyCode::Char('k') if key.modifiers.contains(KeyModifiers::CONTROL) => return Some(Action::OpenSwitcher),
+        KeyCode::Left if key.modifiers.contains(KeyModifiers::CONTROL) => return Some(Action::NarrowPeerList),
+        KeyCode::Right if key.modifiers.contains(KeyModifiers::CONTROL) => return Some(Action::WidenPeerList),
+        _ => {}
+    }
+
+    // Copy mode takes every key until it's left
+    if app.copy.is_some() {
+        return handle_copy_key(key);
+    }
+
+    // So does selecting a message
+    if app.message_menu.is_some() {
+        return handle_message_menu_key(key);
+    }
+
+    // The search query takes every key until it's done
+    if app.search.as_ref().is_some_and(|s| s.editing) {
+        return handle_search_key(key);
+    }
+
     // Tab always switches focus
     if key.code == KeyCode::Tab {
         return Some(Action::NextFocus);
@@ -56,32 +159,159 @@ fn handle_key_event(key: &KeyEvent, app: &TuiApp) -> Option<Action> {
     }
 
     match app.focused {
+        FocusedPanel::PeerList | FocusedPanel::Messages if app.config.vim_mode => handle_vim_normal_key(key, app),
         FocusedPanel::PeerList => handle_peer_list_key(key),
-        FocusedPanel::Messages => handle_messages_key(key),
+        FocusedPanel::Messages => handle_messages_key(key, app.search.is_some()),
+        FocusedPanel::Input if app.note_target.is_some() => handle_note_key(key),
+        // Dropping the quote of the message being answered
+        FocusedPanel::Input if app.reply_to.is_some() && key.code == KeyCode::Esc => Some(Action::CancelReply),
+        // Leaving insert mode
+        FocusedPanel::Input if app.config.vim_mode && key.code == KeyCode::Esc => {
+            Some(Action::FocusPanel(FocusedPanel::Messages))
+        }
         FocusedPanel::Input => handle_input_key(key),
     }
 }
 
+/// Key handling in vim mode's normal mode (the peer list or the messages
+/// focused). Keys vim doesn't claim work as without vim mode.
+fn handle_vim_normal_key(key: &KeyEvent, app: &TuiApp) -> Option<Action> {
+    let searching = app.search.is_some();
+    match key.code {
+        KeyCode::Char('i') => Some(Action::FocusPanel(FocusedPanel::Input)),
+        KeyCode::Char('g') if app.pending_g => Some(Action::JumpTop),
+        KeyCode::Char('g') => Some(Action::PendingG),
+        KeyCode::Char('G') => Some(Action::JumpBottom),
+        KeyCode::Char('h') if !app.config.peer_list_hidden => Some(Action::FocusPanel(FocusedPanel::PeerList)),
+        KeyCode::Char('l') => Some(Action::FocusPanel(FocusedPanel::Messages)),
+        // Pressed out of habit to get back to normal mode: never quits
+        KeyCode::Esc if !searching => None,
+        _ if app.focused == FocusedPanel::PeerList => handle_peer_list_key(key),
+        _ => handle_messages_key(key, searching),
+    }
+}
+
 /// Key handling when the peer list panel is focused.
 fn handle_peer_list_key(key: &KeyEvent) -> Option<Action> {
     match key.code {
         KeyCode::Up | KeyCode::Char('k') => Some(Action::PrevPeer),
         KeyCode::Down | KeyCode::Char('j') => Some(Action::NextPeer),
+        KeyCode::Char('/') => Some(Action::StartSearch),
+        KeyCode::Char('R') => Some(Action::MarkAllRead),
+        KeyCode::Char('m') => Some(Action::ToggleMute),
         KeyCode::Esc | KeyCode::Char('q') => Some(Action::Quit),
         _ => None,
     }
 }
 
-/// Key handling when the messages panel is focused.
-fn handle_messages_key(key: &KeyEvent) -> Option<Action> {
+/// Key handling when the messages panel is focused. `searching` is
+/// whether a search's matches are highlighted.
+fn handle_messages_key(key: &KeyEvent, searching: bool) -> Option<Action> {
     match key.code {
         KeyCode::PageUp | KeyCode::Up | KeyCode::Char('k') => Some(Action::ScrollUp),
         KeyCode::PageDown | KeyCode::Down | KeyCode::Char('j') => Some(Action::ScrollDown),
+        KeyCode::End => Some(Action::JumpBottom),
+        KeyCode::Char('/') => Some(Action::StartSearch),
+        KeyCode::Char('n') if searching => Some(Action::SearchOlder),
+        KeyCode::Char('N') if searching => Some(Action::SearchNewer),
+        KeyCode::Esc if searching => Some(Action::CancelSearch),
+        KeyCode::Char('n') => Some(Action::StartNote),
+        KeyCode::Char('r') => Some(Action::StartReplay),
+        KeyCode::Char('e') => Some(Action::ExportConversation),
+        KeyCode::Char('y') => Some(Action::CopyMessage),
+        KeyCode::Char('v') => Some(Action::StartCopy),
+        KeyCode::Enter => Some(Action::SelectMessage),
+        KeyCode::Char('t') => Some(Action::RetryMessage),
+        KeyCode::Char('R') => Some(Action::MarkAllRead),
         KeyCode::Esc | KeyCode::Char('q') => Some(Action::Quit),
         _ => None,
     }
 }
 
+/// Key handling while asking whether to quit and discard the input.
+fn handle_confirm_quit_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Char('s') | KeyCode::Char('S') | KeyCode::Char('q') | KeyCode::Enter => Some(Action::ConfirmQuit),
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => Some(Action::CancelQuit),
+        _ => None,
+    }
+}
+
+/// Key handling while the activity feed is shown.
+fn handle_activity_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::PageUp | KeyCode::Up | KeyCode::Char('k') => Some(Action::ScrollUp),
+        KeyCode::PageDown | KeyCode::Down | KeyCode::Char('j') => Some(Action::ScrollDown),
+        KeyCode::Esc => Some(Action::ToggleActivity),
+        KeyCode::Char('q') => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+/// Key handling in story mode.
+fn handle_replay_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Char(' ') => Some(Action::ReplayTogglePause),
+        KeyCode::Char('+') | KeyCode::Char('=') => Some(Action::ReplayFaster),
+        KeyCode::Char('-') => Some(Action::ReplaySlower),
+        KeyCode::Enter | KeyCode::Right => Some(Action::ReplaySkip),
+        KeyCode::Esc => Some(Action::StopReplay),
+        KeyCode::Char('q') => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+/// Key handling while typing a search query.
+fn handle_search_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Enter => Some(Action::SubmitSearch),
+        KeyCode::Backspace => Some(Action::SearchBackspace),
+        KeyCode::Esc => Some(Action::CancelSearch),
+        KeyCode::Char(c) => Some(Action::SearchChar(c)),
+        _ => None,
+    }
+}
+
+/// Key handling while selecting a message or choosing in its popup.
+fn handle_message_menu_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Up | KeyCode::Char('k') => Some(Action::MessageMenuUp),
+        KeyCode::Down | KeyCode::Char('j') => Some(Action::MessageMenuDown),
+        KeyCode::Enter => Some(Action::SubmitMessageMenu),
+        KeyCode::Esc | KeyCode::Char('q') => Some(Action::CloseMessageMenu),
+        _ => None,
+    }
+}
+
+/// Key handling in copy mode.
+fn handle_copy_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Up | KeyCode::Char('k') => Some(Action::CopyUp),
+        KeyCode::Down | KeyCode::Char('j') => Some(Action::CopyDown),
+        KeyCode::Left | KeyCode::Char('h') => Some(Action::CopyLeft),
+        KeyCode::Right | KeyCode::Char('l') => Some(Action::CopyRight),
+        KeyCode::Home | KeyCode::Char('0') => Some(Action::CopyLineStart),
+        KeyCode::End | KeyCode::Char('$') => Some(Action::CopyLineEnd),
+        KeyCode::Char('v') | KeyCode::Char(' ') => Some(Action::CopyToggleSelection),
+        KeyCode::Char('y') | KeyCode::Enter => Some(Action::YankCopy),
+        KeyCode::Esc | KeyCode::Char('q') => Some(Action::CancelCopy),
+        _ => None,
+    }
+}
+
+/// Key handling while the quick-switcher is open.
+fn handle_switcher_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Enter => Some(Action::SubmitSwitcher),
+        KeyCode::Backspace => Some(Action::SwitcherBackspace),
+        KeyCode::Up => Some(Action::SwitcherUp),
+        KeyCode::Down => Some(Action::SwitcherDown),
+        KeyCode::Esc => Some(Action::CloseSwitcher),
+        KeyCode::Char(c) => Some(Action::SwitcherChar(c)),
+        _ => None,
+    }
+}
+
 /// Key handling when the text input is focused.
 ///
 /// In input mode, most keys produce text input rather than navigation.
@@ -101,6 +331,17 @@ fn handle_input_key(key: &KeyEvent) -> Option<Action> {
     }
 }
 
+/// Key handling while writing a note: like the input, except Up/Down choose
+/// the message and Esc cancels the note instead of quitting.
+fn handle_note_key(key: &KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Up => Some(Action::NoteTargetPrev),
+        KeyCode::Down => Some(Action::NoteTargetNext),
+        KeyCode::Esc => Some(Action::CancelNote),
+        _ => handle_input_key(key),
+    }
+}
+
 /// Converts a mouse event into an action using the saved panel rectangles.
 ///
 /// Supports:
@@ -115,13 +356,29 @@ fn handle_mouse_event(
     let row = mouse.row;
     let rects = &app.panel_rects;
 
+    // Story mode is keyboard-only
+    if app.view == View::Replay {
+        return None;
+    }
+
+    if app.view == View::Activity {
+        return match mouse.kind {
+            MouseEventKind::ScrollUp if rect_contains(rects.activity, col, row) => Some(Action::ScrollUp),
+            MouseEventKind::ScrollDown if rect_contains(rects.activity, col, row) => {
+                Some(Action::ScrollDown)
+            }
+            _ => None,
+        };
+    }
+
     match mouse.kind {
         MouseEventKind::Down(MouseButton::Left) => {
             if rect_contains(rects.peers, col, row) {
-                // Clicked inside the peers panel. Compute which row was clicked.
-                // Subtract 1 for the top border of the block.
+                // Clicked inside the peers panel. Compute which entry was clicked.
+                // Subtract 1 for the top border of the block; each entry spans
+                // several lines (name + message preview).
                 let inner_y = row.saturating_sub(rects.peers.y + 1);
-                Some(Action::SelectPeer(inner_y as usize))
+                Some(Action::SelectPeer((inner_y / ui::peer_list::ITEM_HEIGHT) as usize))
             } else if rect_contains(rects.messages, col, row) {
                 Some(Action::FocusPanel(FocusedPanel::Messages))
             } else if rect_contains(rects.input, col, row) {
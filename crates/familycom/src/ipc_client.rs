@@ -19,7 +19,8 @@
 //! ```
 
 use familycom_core::config::AppConfig;
-use familycom_core::ipc::{self, ClientRequest, ServerMessage};
+use familycom_core::ipc::{self, ClientRequest, EventFilter, ServerMessage};
+use familycom_core::types::Message;
 use std::path::PathBuf;
 use thiserror::Error;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
@@ -63,6 +64,14 @@ pub struct IpcClient {
     writer: WriteHalf<UnixStream>,
     /// Buffer reused for reading lines (avoids repeated allocation).
     line_buf: String,
+    /// Messages of a chunked `GetMessages` response received so far.
+    chunked: Vec<Message>,
+    /// Socket path, for `reconnect`.
+    path: PathBuf,
+    /// Whether `subscribe` was called, so `reconnect` subscribes again.
+    subscribed: bool,
+    /// `seq` of the last pushed event, to resume from after reconnecting.
+    last_seq: Option<String>,
 }
 
 impl IpcClient {
@@ -81,6 +90,22 @@ impl IpcClient {
             return Err(IpcClientError::DaemonNotRunning(path.clone()));
         }
 
+        let (reader, writer) = Self::open(path).await?;
+        Ok(Self {
+            reader,
+            writer,
+            line_buf: String::with_capacity(4096),
+            chunked: Vec::new(),
+            path: path.clone(),
+            subscribed: false,
+            last_seq: None,
+        })
+    }
+
+    /// Opens a new connection to the socket at `path`.
+    async fn open(
+        path: &PathBuf,
+    ) -> Result<(BufReader<ReadHalf<UnixStream>>, WriteHalf<UnixStream>), IpcClientError> {
         let stream = UnixStream::connect(path).await.map_err(|e| {
             IpcClientError::Connect {
                 path: path.clone(),
@@ -89,15 +114,50 @@ impl IpcClient {
         })?;
 
         let (reader, writer) = tokio::io::split(stream);
-        let reader = BufReader::new(reader);
-
         debug!(path = %path.display(), "connected to daemon");
+        Ok((BufReader::new(reader), writer))
+    }
 
-        Ok(Self {
-            reader,
-            writer,
-            line_buf: String::with_capacity(4096),
+    /// Connects again after `recv` returned `Disconnected`, and resubscribes
+    /// if `subscribe` had been called. Events sent while disconnected are
+    /// replayed and come through `recv` as usual.
+    ///
+    /// Returns false if the daemon could not replay everything (it
+    /// restarted, or too much happened meanwhile); the caller should then
+    /// fetch its state again. Responses to requests sent before the
+    /// connection dropped are lost either way.
+    pub async fn reconnect(&mut self) -> Result<bool, IpcClientError> {
+        if !self.path.exists() {
+            return Err(IpcClientError::DaemonNotRunning(self.path.clone()));
+        }
+        let (reader, writer) = Self::open(&self.path).await?;
+        self.reader = reader;
+        self.writer = writer;
+        self.chunked.clear();
+        if !self.subscribed {
+            return Ok(false);
+        }
+
+        self.send(&ClientRequest::Subscribe {
+            name: None,
+            filter: EventFilter::default(),
+            since: self.last_seq.clone(),
         })
+        .await?;
+        match self.recv().await? {
+            ServerMessage::Resumed { replayed, complete } => {
+                debug!(replayed, complete, "resumed event stream");
+                Ok(complete)
+            }
+            // Nothing to resume from
+            ServerMessage::Ok => Ok(false),
+            ServerMessage::Error { code, message } => {
+                Err(IpcClientError::Protocol(format!("{code}: {message}")))
+            }
+            _ => Err(IpcClientError::Protocol(
+                "unexpected response to Subscribe".to_string(),
+            )),
+        }
     }
 
     /// Sends a request to the daemon.
@@ -114,15 +174,38 @@ impl IpcClient {
     /// This can be either a response to a previous request, or a pushed
     /// event (if subscribed). Returns `Err(Disconnected)` if the daemon
     /// closes the connection.
+    ///
+    /// Chunked history (`MessagesChunk` ... `MessagesEnd`) is put back
+    /// together and returned as a single `Messages`; events arriving in
+    /// between are returned as they come.
     pub async fn recv(&mut self) -> Result<ServerMessage, IpcClientError> {
-        self.line_buf.clear();
-        let bytes_read = self.reader.read_line(&mut self.line_buf).await?;
-        if bytes_read == 0 {
-            return Err(IpcClientError::Disconnected);
+        loop {
+            self.line_buf.clear();
+            let bytes_read = self.reader.read_line(&mut self.line_buf).await?;
+            if bytes_read == 0 {
+                return Err(IpcClientError::Disconnected);
+            }
+            let (msg, seq) = ipc::decode_frame(&self.line_buf)
+                .map_err(|e| IpcClientError::Protocol(e.to_string()))?;
+            if seq.is_some() {
+                self.last_seq = seq;
+            }
+            match msg {
+                ServerMessage::MessagesChunk { messages } => self.chunked.extend(messages),
+                ServerMessage::MessagesEnd {
+                    next_cursor,
+                    prev_cursor,
+                    ..
+                } => {
+                    return Ok(ServerMessage::Messages {
+                        messages: std::mem::take(&mut self.chunked),
+                        next_cursor,
+                        prev_cursor,
+                    });
+                }
+                other => return Ok(other),
+            }
         }
-        let msg = ipc::decode_response(&self.line_buf)
-            .map_err(|e| IpcClientError::Protocol(e.to_string()))?;
-        Ok(msg)
     }
 
     /// Subscribes to real-time events from the daemon.
@@ -131,11 +214,19 @@ impl IpcClient {
     /// (NewMessage, PeerOnline, PeerOffline, etc.) in addition to
     /// request responses.
     pub async fn subscribe(&mut self) -> Result<(), IpcClientError> {
-        self.send(&ClientRequest::Subscribe).await?;
+        self.send(&ClientRequest::Subscribe {
+            name: None,
+            filter: EventFilter::default(),
+            since: None,
+        })
+        .await?;
         // Wait for the Ok acknowledgment
         let response = self.recv().await?;
         match response {
-            ServerMessage::Ok => Ok(()),
+            ServerMessage::Ok => {
+                self.subscribed = true;
+                Ok(())
+            }
             ServerMessage::Error { code, message } => {
                 Err(IpcClientError::Protocol(format!("{code}: {message}")))
             }
@@ -8,43 +8,165 @@
 //! ```bash
 //! familycom                      # Connect to daemon and open TUI
 //! familycom --set-name "Nuevo"   # Change display name and exit
+//! familycom --transcript chat.txt
+//!                                # Also append the session to a text file
+//! familycom completions fish     # Print shell completions to stdout
+//! familycom man                  # Print the man page (roff) to stdout
+//! familycom print --peer PC-Sala --since 2026-02-01 | lp
+//!                                # Print a conversation for paper
+//! familycom notes dentista       # Search your private message notes
+//! familycom book --peer Abuela --since 2026-01-01 --until 2026-12-31 --out familia.epub
+//!                                # Make a keepsake EPUB (add --pdf for a PDF)
+//! familycom stop                 # Stop the daemon (familycom restart: restart it)
+//! familycom statusline           # "✉ 3 ● 2" for a tmux/zellij status bar
 //! ```
 //!
 //! The daemon must be running before starting the TUI. If it's not,
 //! you'll see a helpful error message with instructions.
 
+mod alerts;
 mod app;
+mod book;
 mod event;
 mod ipc_client;
+mod print;
+mod statusline;
+mod transcript;
 mod ui;
 
-use anyhow::{Context, Result};
-use app::{Action, TuiApp};
-use clap::Parser;
+use alerts::Alerts;
+use anyhow::{bail, Context, Result};
+use app::{Action, MessageAction, TuiApp, View};
+use clap::{CommandFactory, Parser, Subcommand};
 use crossterm::{
-    event::EventStream,
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture, Event, EventStream},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
+use familycom_core::commands::Input;
+use familycom_core::config::TuiConfig;
+use familycom_core::export::ExportFormat;
+use familycom_core::files::sanitize_component;
 use familycom_core::ipc::ClientRequest;
+use familycom_core::types::{MessageId, PeerId};
 use ipc_client::IpcClient;
 use ratatui::prelude::*;
 use std::io::stdout;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio_stream::StreamExt;
+use transcript::Transcript;
+
+/// How long the TUI waits between attempts to reconnect to the daemon.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Key presses are reported to the daemon (for automatic away) at most
+/// this often.
+const ACTIVITY_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// While the user types, the peer is told so at most this often. Shorter
+/// than `app::TYPING_TIMEOUT`, so its indicator doesn't flicker.
+const TYPING_REPORT_INTERVAL: Duration = Duration::from_secs(3);
 
 /// FamilyCom TUI client — chat with peers on your local network.
 #[derive(Parser, Debug)]
 #[command(name = "familycom", about = "FamilyCom LAN messenger TUI client")]
 struct Cli {
+    /// Subcommand to run (completions, man, print, notes, book, stop, restart, statusline).
+    /// If omitted, opens the TUI.
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Change this machine's display name and exit.
     #[arg(long)]
     set_name: Option<String>,
 
+    /// Send a house-wide announcement (e.g. "A cenar!") to everyone online
+    /// and exit. It shows as a banner and is notified even in do-not-disturb.
+    #[arg(long, value_name = "TEXT")]
+    announce: Option<String>,
+
     /// Path to the daemon's Unix socket.
     #[arg(long)]
     socket: Option<std::path::PathBuf>,
+
+    /// Append every message shown and every status change to this file,
+    /// as plain text, while the TUI runs.
+    #[arg(long, value_name = "FILE")]
+    transcript: Option<std::path::PathBuf>,
+
+    /// Open the conversation with this peer (display name or peer ID).
+    #[arg(long)]
+    peer: Option<String>,
+}
+
+/// Auxiliary subcommands that don't open the TUI.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print shell completions for familycom to stdout.
+    Completions {
+        /// The shell to generate completions for.
+        shell: clap_complete::Shell,
+    },
+    /// Print the familycom man page (roff format) to stdout.
+    Man,
+    /// Print a conversation as plain text, ready to pipe to `lp`.
+    Print {
+        /// Display name of the peer whose conversation to print.
+        #[arg(long)]
+        peer: String,
+        /// Only include messages from this day on (AAAA-MM-DD or DD/MM/AAAA).
+        #[arg(long, value_parser = print::parse_date)]
+        since: Option<chrono::NaiveDate>,
+        /// Page width in characters.
+        #[arg(long, default_value_t = 80)]
+        width: usize,
+    },
+    /// Search the private notes attached to messages.
+    Notes {
+        /// Text to look for in the notes.
+        query: String,
+    },
+    /// Export a conversation as an EPUB book, one chapter per month.
+    Book {
+        /// Display name of the peer whose conversation to export.
+        #[arg(long)]
+        peer: String,
+        /// First day included (AAAA-MM-DD or DD/MM/AAAA).
+        #[arg(long, value_parser = print::parse_date)]
+        since: Option<chrono::NaiveDate>,
+        /// Last day included (AAAA-MM-DD or DD/MM/AAAA).
+        #[arg(long, value_parser = print::parse_date)]
+        until: Option<chrono::NaiveDate>,
+        /// Book title. Defaults to "<peer> <year>" for a single year.
+        #[arg(long)]
+        title: Option<String>,
+        /// Where to write the EPUB.
+        #[arg(long, value_name = "FILE")]
+        out: std::path::PathBuf,
+        /// Also convert the book to PDF at this path.
+        #[arg(long, value_name = "FILE")]
+        pdf: Option<std::path::PathBuf>,
+        /// Converter used for --pdf; {epub} and {pdf} are replaced by the paths.
+        #[arg(long, default_value = book::DEFAULT_PDF_COMMAND)]
+        pdf_command: String,
+        /// Leave out pictures from the peer's downloads folder.
+        #[arg(long)]
+        no_images: bool,
+    },
+    /// Stop the daemon.
+    Stop,
+    /// Restart the daemon (e.g. after changing tcp_port in config.toml).
+    Restart,
+    /// Print unread messages and online peers on one line, for tmux or
+    /// zellij status bars. Prints nothing if the daemon isn't running.
+    Statusline {
+        /// Output format; {unread} and {online} are replaced by the counts.
+        #[arg(long, default_value = statusline::DEFAULT_FORMAT)]
+        format: String,
+        /// Reuse the last answer for this many seconds before asking the daemon again.
+        #[arg(long, default_value_t = 5)]
+        max_age: u64,
+    },
 }
 
 #[tokio::main]
@@ -64,11 +186,79 @@ async fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    // Completions and man pages are generated at runtime so packagers can
+    // just call the installed binary instead of needing a build script.
+    match &cli.command {
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Cli::command(), "familycom", &mut std::io::stdout());
+            return Ok(());
+        }
+        Some(Command::Man) => {
+            clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Print { peer, since, width }) => {
+            let socket_path = cli
+                .socket
+                .clone()
+                .unwrap_or_else(familycom_core::config::AppConfig::default_socket_path);
+            return print::run(&socket_path, peer, *since, *width).await;
+        }
+        Some(Command::Notes { query }) => {
+            return search_notes(query, &cli.socket).await;
+        }
+        Some(Command::Book {
+            peer,
+            since,
+            until,
+            title,
+            out,
+            pdf,
+            pdf_command,
+            no_images,
+        }) => {
+            let socket_path = cli
+                .socket
+                .clone()
+                .unwrap_or_else(familycom_core::config::AppConfig::default_socket_path);
+            let request = book::BookRequest {
+                peer: peer.clone(),
+                since: *since,
+                until: *until,
+                title: title.clone(),
+                out: out.clone(),
+                pdf: pdf.clone(),
+                pdf_command: pdf_command.clone(),
+                images: !no_images,
+            };
+            return book::run(&socket_path, &request).await;
+        }
+        Some(Command::Stop) => {
+            return stop_daemon(ClientRequest::Shutdown, &cli.socket).await;
+        }
+        Some(Command::Restart) => {
+            return stop_daemon(ClientRequest::Restart, &cli.socket).await;
+        }
+        Some(Command::Statusline { format, max_age }) => {
+            let socket_path = cli
+                .socket
+                .clone()
+                .unwrap_or_else(familycom_core::config::AppConfig::default_socket_path);
+            return statusline::run(&socket_path, format, *max_age).await;
+        }
+        None => {}
+    }
+
     // Handle --set-name: change name and exit without opening TUI
     if let Some(name) = &cli.set_name {
         return set_display_name(name, &cli.socket).await;
     }
 
+    // Handle --announce: send to everyone and exit without opening TUI
+    if let Some(content) = &cli.announce {
+        return announce(content, &cli.socket).await;
+    }
+
     // Connect to the daemon
     let socket_path = cli
         .socket
@@ -93,12 +283,31 @@ async fn main() -> Result<()> {
     // Subscribe to real-time events
     client.subscribe().await.context("failed to subscribe")?;
 
-    // Request initial data
-    client.send(&ClientRequest::GetConfig).await?;
-    client.send(&ClientRequest::ListPeers).await?;
+    request_initial_data(&mut client).await?;
+
+    // Opened before the TUI takes over the terminal so errors are readable
+    let transcript = match &cli.transcript {
+        Some(path) => Some(
+            Transcript::open(path)
+                .with_context(|| format!("could not open transcript file {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    // A missing or unreadable config just means the default title and bell
+    let alerts = Alerts::new(
+        familycom_core::config::AppConfig::load()
+            .ok()
+            .flatten()
+            .map(|config| config.terminal)
+            .unwrap_or_default(),
+    );
+
+    // Likewise for the TUI's own preferences
+    let tui_config = TuiConfig::load().unwrap_or_default();
 
     // Run the TUI
-    run_tui(client).await
+    run_tui(client, transcript, alerts, tui_config, cli.peer.as_deref()).await
 }
 
 /// Runs the interactive TUI main loop.
@@ -108,7 +317,18 @@ async fn main() -> Result<()> {
 /// - Terminal events (keyboard input)
 /// - IPC messages from the daemon (peer updates, new messages)
 /// - Periodic screen refresh
-async fn run_tui(mut client: IpcClient) -> Result<()> {
+///
+/// With a `transcript`, new messages and status changes are also appended
+/// to it as they appear. `alerts` keeps the terminal title and bell in step
+/// with unread messages. `tui_config` is saved back to tui.toml whenever
+/// the user changes it. `open_peer` is the conversation to start on.
+async fn run_tui(
+    mut client: IpcClient,
+    mut transcript: Option<Transcript>,
+    mut alerts: Alerts,
+    tui_config: TuiConfig,
+    open_peer: Option<&str>,
+) -> Result<()> {
     // Set up terminal for TUI rendering.
     // Raw mode: disables line buffering and echo, so we get each keypress.
     // Alternate screen: switches to a separate screen buffer, so our TUI
@@ -116,12 +336,16 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
     enable_raw_mode()?;
     stdout().execute(EnterAlternateScreen)?;
     stdout().execute(EnableMouseCapture)?;
+    // Focus reports tell the bell whether anyone is looking
+    stdout().execute(EnableFocusChange)?;
+    alerts.start(&mut stdout())?;
 
     // Set up a panic hook that restores the terminal before printing
     // the panic message. Without this, a panic would leave the terminal
     // in raw mode with the alternate screen active — very confusing.
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
+        let _ = stdout().execute(DisableFocusChange);
         let _ = stdout().execute(DisableMouseCapture);
         let _ = disable_raw_mode();
         let _ = stdout().execute(LeaveAlternateScreen);
@@ -130,6 +354,7 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
 
     let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
     let mut app = TuiApp::new();
+    app.config = tui_config;
 
     // Event stream from crossterm — delivers keyboard/mouse events asynchronously
     let mut event_stream = EventStream::new();
@@ -137,6 +362,21 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
     // Tick interval for periodic UI refresh (e.g., updating timestamps)
     let mut tick = tokio::time::interval(Duration::from_millis(250));
 
+    // Set while disconnected from the daemon: when to try reconnecting
+    let mut reconnect_at: Option<tokio::time::Instant> = None;
+
+    // When a key press was last reported to the daemon
+    let mut activity_reported: Option<Instant> = None;
+
+    // Who we last told we're writing to, and when
+    let mut typing_reported: Option<(PeerId, Instant)> = None;
+
+    // The conversation shown and terminal focus last told to the daemon
+    let mut viewing_sent: Option<(Option<PeerId>, bool)> = None;
+
+    // The system clipboard, opened the first time a message is copied
+    let mut clipboard: Option<arboard::Clipboard> = None;
+
     // Read initial responses from daemon (Config and PeerList)
     for _ in 0..2 {
         if let Ok(Ok(msg)) = tokio::time::timeout(Duration::from_secs(2), client.recv()).await {
@@ -145,34 +385,125 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
     }
 
     app.status = "Conectado".to_string();
+    if let Some(wanted) = open_peer {
+        if app.select_peer(wanted) {
+            fetch_selected_peer_messages(&mut app, &mut client).await;
+        } else {
+            app.status = format!("No hay ningun peer '{wanted}'");
+        }
+    }
 
     // Main event loop
     loop {
+        if let Some(transcript) = &mut transcript {
+            transcript.record_status(&app.status);
+        }
+
         // Render the current state (mutable borrow so layout can save panel Rects)
         terminal.draw(|frame| ui::layout::render(frame, &mut app))?;
+        alerts.update_title(&mut stdout(), app.total_unread())?;
 
         // Wait for the next event (terminal input, daemon message, or tick)
         tokio::select! {
             // Terminal input events
             maybe_event = event_stream.next() => {
                 match maybe_event {
+                    Some(Ok(Event::FocusGained)) => {
+                        alerts.set_focused(true);
+                        // Whatever arrived while away is in front of the user now
+                        mark_shown_read(&app, &mut client, None).await;
+                    }
+                    Some(Ok(Event::FocusLost)) => alerts.set_focused(false),
                     Some(Ok(evt)) => {
+                        // Someone is at the keyboard: not away
+                        if matches!(evt, Event::Key(_))
+                            && reconnect_at.is_none()
+                            && activity_reported.is_none_or(|at| at.elapsed() >= ACTIVITY_REPORT_INTERVAL)
+                        {
+                            activity_reported = Some(Instant::now());
+                            let _ = client.send(&ClientRequest::ReportActivity).await;
+                        }
                         if let Some(action) = event::handle_event(&evt, &app) {
                             match action {
+                                Action::SendMessage if app.note_target.is_some() => {
+                                    handle_save_note(&mut app, &mut client).await;
+                                }
                                 Action::SendMessage => {
-                                    handle_send_message(&mut app, &mut client).await;
+                                    let sent = handle_send_message(&mut app, &mut client).await;
+                                    if let (Some(transcript), Some(sent)) = (&mut transcript, sent) {
+                                        transcript.record_message(&app, &sent);
+                                    }
+                                }
+                                Action::ExportConversation => {
+                                    export_conversation(&mut app, &mut client).await;
+                                }
+                                Action::CopyMessage => {
+                                    copy_message(&mut app, &mut clipboard);
+                                }
+                                Action::YankCopy => {
+                                    yank_copy(&mut app, &mut clipboard);
+                                }
+                                Action::RetryMessage => {
+                                    retry_message(&mut app, &mut client).await;
+                                }
+                                Action::SubmitMessageMenu
+                                    if app.message_menu.as_ref().is_some_and(|m| m.item.is_some()) =>
+                                {
+                                    message_action(&mut app, &mut client, &mut clipboard).await;
+                                }
+                                Action::SubmitSwitcher
+                                    if app.switcher.as_ref().is_some_and(|s| s.forward.is_some()) =>
+                                {
+                                    if let Some(request) = app.submit_forward() {
+                                        if let Err(e) = client.send(&request).await {
+                                            app.status = format!("Error enviando: {e}");
+                                        }
+                                    }
+                                }
+                                Action::MarkAllRead => {
+                                    for request in app.mark_all_read() {
+                                        if let Err(e) = client.send(&request).await {
+                                            app.status = format!("Error: {e}");
+                                        }
+                                    }
+                                }
+                                Action::ToggleMute => {
+                                    if let Some(request) = app.toggle_mute() {
+                                        if let Err(e) = client.send(&request).await {
+                                            app.status = format!("Error: {e}");
+                                        }
+                                    }
+                                }
+                                Action::SubmitSearch => {
+                                    if let Some(request) = app.submit_search() {
+                                        if let Err(e) = client.send(&request).await {
+                                            app.status = format!("Error: {e}");
+                                        }
+                                    }
                                 }
                                 other => {
+                                    let typed = matches!(other, Action::InputChar(_));
                                     // Track the selected peer before the action so we
                                     // can detect peer switches (NextPeer, PrevPeer, etc.)
                                     let prev_peer = app.selected_peer_id().cloned();
+                                    let prev_config = app.config.clone();
                                     app.handle_action(other);
                                     let new_peer = app.selected_peer_id().cloned();
 
+                                    if app.config != prev_config {
+                                        if let Err(e) = app.config.save() {
+                                            app.status = format!("No se pudo guardar tui.toml: {e}");
+                                        }
+                                    }
+
+                                    if typed && reconnect_at.is_none() {
+                                        report_typing(&app, &mut client, &mut typing_reported).await;
+                                    }
+
                                     // If the user switched to a different peer, fetch
                                     // that peer's message history from the daemon/DB.
                                     if new_peer != prev_peer {
-                                        fetch_selected_peer_messages(&app, &mut client).await;
+                                        fetch_selected_peer_messages(&mut app, &mut client).await;
                                     }
                                 }
                             }
@@ -184,23 +515,50 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
             }
 
             // Messages from the daemon (responses and pushed events)
-            result = client.recv() => {
+            result = client.recv(), if reconnect_at.is_none() => {
                 match result {
                     Ok(msg) => {
                         // If we got a PeerList, also request messages for selected peer
                         let should_fetch = matches!(&msg,
                             familycom_core::ipc::ServerMessage::PeerList { .. }
                         );
+                        let (new_messages, received) = match &msg {
+                            familycom_core::ipc::ServerMessage::NewMessage { message } => (
+                                std::slice::from_ref(message),
+                                message.direction == familycom_core::types::Direction::Received,
+                            ),
+                            // A flood from one peer: one alert for the whole batch
+                            familycom_core::ipc::ServerMessage::NewMessages { messages, .. } => {
+                                (messages.as_slice(), !messages.is_empty())
+                            }
+                            _ => (&[][..], false),
+                        };
+                        if let Some(transcript) = &mut transcript {
+                            for message in new_messages {
+                                transcript.record_message(&app, message);
+                            }
+                        }
+                        if received {
+                            alerts.message_received(&mut stdout())?;
+                        }
+                        let received_from = new_messages.first().filter(|_| received).map(|m| m.peer_id.clone());
 
                         app.handle_action(Action::ServerMessage(msg));
 
+                        if let Some(peer_id) = received_from {
+                            if alerts.focused() {
+                                mark_shown_read(&app, &mut client, Some(&peer_id)).await;
+                            }
+                        }
+
                         if should_fetch {
-                            fetch_selected_peer_messages(&app, &mut client).await;
+                            fetch_selected_peer_messages(&mut app, &mut client).await;
                         }
                     }
                     Err(ipc_client::IpcClientError::Disconnected) => {
-                        app.status = "Desconectado del daemon".to_string();
-                        // Could implement reconnection logic here
+                        app.status = "Desconectado del daemon, reconectando...".to_string();
+                        app.connection_lost();
+                        reconnect_at = Some(tokio::time::Instant::now() + RECONNECT_DELAY);
                     }
                     Err(e) => {
                         app.status = format!("Error: {e}");
@@ -210,7 +568,58 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
 
             // Periodic tick for UI refresh
             _ = tick.tick() => {
-                // Just triggers a redraw
+                if reconnect_at.is_some_and(|at| at <= tokio::time::Instant::now()) {
+                    match client.reconnect().await {
+                        Ok(complete) => {
+                            reconnect_at = None;
+                            // A new connection knows nothing of what we show
+                            viewing_sent = None;
+                            app.status = "Reconectado al daemon".to_string();
+                            // Missed events were replayed unless the daemon
+                            // couldn't; then start over from a fresh state
+                            if !complete {
+                                if let Err(e) = request_initial_data(&mut client).await {
+                                    app.status = format!("Error: {e}");
+                                }
+                            }
+                        }
+                        Err(_) => reconnect_at = Some(tokio::time::Instant::now() + RECONNECT_DELAY),
+                    }
+                }
+
+                // Story mode shows its next message when it's due
+                if app.view == View::Replay {
+                    app.handle_action(Action::ReplayTick);
+                }
+            }
+        }
+
+        // Keep story mode fed with messages as it catches up
+        if let Some(request) = app.replay_page_request() {
+            if let Err(e) = client.send(&request).await {
+                app.status = format!("Error: {e}");
+            }
+        }
+
+        // Tell the daemon what's on screen, so it doesn't notify it
+        let showing = (app.view == View::Chat).then(|| app.selected_peer_id().cloned()).flatten();
+        let viewing = (showing, alerts.focused());
+        if reconnect_at.is_none() && viewing_sent.as_ref() != Some(&viewing) {
+            let request = ClientRequest::SetViewing {
+                peer_id: viewing.0.clone(),
+                focused: viewing.1,
+            };
+            if client.send(&request).await.is_ok() {
+                viewing_sent = Some(viewing);
+            }
+        }
+
+        // Scrolled to the top of the history: load the page before
+        if reconnect_at.is_none() {
+            if let Some(request) = app.older_page_request() {
+                if let Err(e) = client.send(&request).await {
+                    app.status = format!("Error: {e}");
+                }
             }
         }
 
@@ -220,6 +629,8 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
     }
 
     // Restore terminal
+    alerts.restore(&mut stdout())?;
+    stdout().execute(DisableFocusChange)?;
     stdout().execute(DisableMouseCapture)?;
     disable_raw_mode()?;
     stdout().execute(LeaveAlternateScreen)?;
@@ -228,58 +639,310 @@ async fn run_tui(mut client: IpcClient) -> Result<()> {
 }
 
 /// Handles the SendMessage action: sends the input text to the selected peer.
-async fn handle_send_message(app: &mut TuiApp, client: &mut IpcClient) {
+///
+/// Returns the message as shown locally, if one was sent.
+async fn handle_send_message(
+    app: &mut TuiApp,
+    client: &mut IpcClient,
+) -> Option<familycom_core::types::Message> {
     let content = app.input.trim().to_string();
     if content.is_empty() {
-        return;
+        return None;
     }
 
     let peer_id = match app.selected_peer_id() {
         Some(id) => id.clone(),
         None => {
             app.status = "No hay peer seleccionado".to_string();
-            return;
+            return None;
         }
     };
 
-    // Clear the input buffer
+    // Clear the input buffer and the quote above it; a command isn't a
+    // reply, so its quote is dropped rather than kept for the next message
     app.take_input();
+    let reply_to = app.reply_to.take();
+
+    // A slash command's answer comes back from the daemon as a system message
+    let shown = match Input::parse(&content) {
+        Input::Command(_) => {
+            if let Err(e) = client.send(&ClientRequest::SendMessage { peer_id, content, reply_to: None }).await {
+                app.status = format!("Error enviando: {e}");
+            }
+            return None;
+        }
+        Input::Message(text) => text.to_string(),
+    };
 
     // Add the message to local display immediately (optimistic update)
     let message = familycom_core::types::Message {
         id: familycom_core::types::MessageId::generate(),
         peer_id: peer_id.clone(),
         direction: familycom_core::types::Direction::Sent,
-        content: content.clone(),
+        content: shown,
         timestamp: familycom_core::types::Timestamp::now(),
         delivered: false,
+        fire_and_forget: false,
+        announcement: false,
+        reply_to: reply_to.clone(),
     };
-    app.messages.entry(peer_id.clone()).or_default().push(message);
+    app.note_last_message(&message);
+    app.messages.entry(peer_id.clone()).or_default().push(message.clone());
     app.messages_scroll = 0;
+    app.unseen = None;
 
     // Send via IPC to daemon
     if let Err(e) = client
         .send(&ClientRequest::SendMessage {
             peer_id,
             content,
+            reply_to,
         })
         .await
     {
         app.status = format!("Error enviando: {e}");
     }
+    Some(message)
 }
 
-/// Requests message history for the currently selected peer.
-async fn fetch_selected_peer_messages(app: &TuiApp, client: &mut IpcClient) {
-    if let Some(peer_id) = app.selected_peer_id() {
-        let _ = client
-            .send(&ClientRequest::GetMessages {
-                peer_id: peer_id.clone(),
-                limit: 100,
-                before: None,
-            })
-            .await;
+/// Handles Enter while writing a note: saves the input as the private
+/// note of the targeted message (an empty input removes the note).
+async fn handle_save_note(app: &mut TuiApp, client: &mut IpcClient) {
+    let Some((message_id, peer_id)) = app
+        .note_target_message()
+        .map(|m| (m.id.clone(), m.peer_id.clone()))
+    else {
+        app.note_target = None;
+        return;
+    };
+    let note = app.take_input().trim().to_string();
+    app.note_target = None;
+
+    if let Err(e) = client
+        .send(&ClientRequest::SetMessageNote {
+            message_id: message_id.clone(),
+            note: note.clone(),
+        })
+        .await
+    {
+        app.status = format!("Error guardando la nota: {e}");
+        return;
+    }
+    if note.is_empty() {
+        app.notes.remove(&message_id);
+        app.status = "Nota eliminada".to_string();
+    } else {
+        app.notes.insert(message_id, note);
+        app.status = "Nota guardada".to_string();
+    }
+    // Re-read the conversation's notes so one the daemon rejected (e.g. on
+    // a message it hasn't stored yet) doesn't linger on screen
+    let _ = client.send(&ClientRequest::GetMessageNotes { peer_id }).await;
+}
+
+/// Marks the conversation on screen as read, so its peer's read receipt
+/// follows what the user sees. With `only`, just if it's that peer's.
+async fn mark_shown_read(app: &TuiApp, client: &mut IpcClient, only: Option<&PeerId>) {
+    if app.view != View::Chat {
+        return;
+    }
+    let Some(peer_id) = app.selected_peer_id() else {
+        return;
+    };
+    if only.is_some_and(|only| only != peer_id) {
+        return;
     }
+    let _ = client
+        .send(&ClientRequest::MarkRead {
+            peer_id: peer_id.clone(),
+            up_to: None,
+        })
+        .await;
+}
+
+/// Tells the selected peer we're writing to them, unless they were told
+/// less than `TYPING_REPORT_INTERVAL` ago. Notes and slash commands never
+/// reach the peer, so typing them isn't reported.
+async fn report_typing(app: &TuiApp, client: &mut IpcClient, reported: &mut Option<(PeerId, Instant)>) {
+    if app.note_target.is_some() || app.input.starts_with('/') {
+        return;
+    }
+    let Some(peer_id) = app.selected_peer_id() else {
+        return;
+    };
+    if reported
+        .as_ref()
+        .is_some_and(|(to, at)| to == peer_id && at.elapsed() < TYPING_REPORT_INTERVAL)
+    {
+        return;
+    }
+    let request = ClientRequest::ReportTyping {
+        peer_id: peer_id.clone(),
+    };
+    if client.send(&request).await.is_ok() {
+        *reported = Some((peer_id.clone(), Instant::now()));
+    }
+}
+
+/// Copies the selected message's text to the system clipboard.
+fn copy_message(app: &mut TuiApp, clipboard: &mut Option<arboard::Clipboard>) {
+    let Some(content) = app.selected_message().map(|m| m.content.clone()) else {
+        app.status = "No hay mensajes para copiar".to_string();
+        return;
+    };
+    set_clipboard(app, clipboard, content, "Mensaje copiado al portapapeles");
+}
+
+/// Copies copy mode's selection to the clipboard and leaves copy mode.
+fn yank_copy(app: &mut TuiApp, clipboard: &mut Option<arboard::Clipboard>) {
+    let Some(text) = app.copy_selection() else {
+        return;
+    };
+    app.copy = None;
+    set_clipboard(app, clipboard, text, "Selección copiada al portapapeles");
+}
+
+/// Puts `text` in the system clipboard, opening it the first time, and
+/// says `done` in the status bar.
+///
+/// The clipboard is kept open afterwards: on X11, copied text is only
+/// there for pasting while the program that copied it is running.
+fn set_clipboard(app: &mut TuiApp, clipboard: &mut Option<arboard::Clipboard>, text: String, done: &str) {
+    if clipboard.is_none() {
+        match arboard::Clipboard::new() {
+            Ok(opened) => *clipboard = Some(opened),
+            Err(e) => {
+                app.status = format!("No se pudo abrir el portapapeles: {e}");
+                return;
+            }
+        }
+    }
+    let Some(clipboard) = clipboard.as_mut() else {
+        return;
+    };
+    app.status = match clipboard.set_text(text) {
+        Ok(()) => done.to_string(),
+        Err(e) => format!("No se pudo copiar: {e}"),
+    };
+}
+
+/// Asks the daemon to send an undelivered message again. It shows as
+/// pending meanwhile; `MessageDelivered` or another `MessageFailed` says
+/// how it went.
+async fn retry_message(app: &mut TuiApp, client: &mut IpcClient) {
+    let Some(message_id) = app.retry_target().map(|m| m.id.clone()) else {
+        app.status = "No hay mensajes sin entregar".to_string();
+        return;
+    };
+    send_retry(app, client, message_id).await;
+}
+
+/// Does the entry chosen in a message's popup. Deleting waits for the
+/// daemon's `MessageDeleted` to take the message off the screen.
+async fn message_action(app: &mut TuiApp, client: &mut IpcClient, clipboard: &mut Option<arboard::Clipboard>) {
+    let Some((action, message)) = app.take_message_action() else {
+        return;
+    };
+    let retract = match action {
+        MessageAction::Copy => {
+            set_clipboard(app, clipboard, message.content, "Mensaje copiado al portapapeles");
+            return;
+        }
+        MessageAction::Retry => {
+            send_retry(app, client, message.id).await;
+            return;
+        }
+        // Started by `take_message_action`
+        MessageAction::Reply | MessageAction::Note | MessageAction::Forward => return,
+        MessageAction::Delete => false,
+        MessageAction::Retract => true,
+    };
+    let request = ClientRequest::DeleteMessage {
+        message_id: message.id,
+        retract,
+    };
+    if let Err(e) = client.send(&request).await {
+        app.status = format!("Error: {e}");
+    }
+}
+
+/// Sends `RetryMessage` for a message the daemon couldn't deliver.
+async fn send_retry(app: &mut TuiApp, client: &mut IpcClient, message_id: MessageId) {
+    app.failed.remove(&message_id);
+    app.status = match client.send(&ClientRequest::RetryMessage { message_id }).await {
+        Ok(()) => "Reintentando...".to_string(),
+        Err(e) => format!("Error: {e}"),
+    };
+}
+
+/// Asks the daemon to export the selected conversation as a text file in
+/// the documents folder (the home folder if there's none). Progress and
+/// the result arrive as `ExportProgress` and `Exported`.
+async fn export_conversation(app: &mut TuiApp, client: &mut IpcClient) {
+    let Some(peer) = app.selected_peer().cloned() else {
+        app.status = "Selecciona un peer para exportar su conversacion".to_string();
+        return;
+    };
+    let Some(dir) = dirs::document_dir().or_else(dirs::home_dir) else {
+        app.status = "No se encontro una carpeta donde guardar".to_string();
+        return;
+    };
+    let format = ExportFormat::Text;
+    let path = export_path(&dir, &peer.display_name, format);
+    app.status = format!("Exportando conversacion con {}...", peer.display_name);
+    let request = ClientRequest::ExportConversation {
+        peer_id: peer.id,
+        format,
+        path: path.to_string_lossy().into_owned(),
+    };
+    if let Err(e) = client.send(&request).await {
+        app.status = format!("Error: {e}");
+    }
+}
+
+/// `<dir>/familycom-<peer>-<date>.<ext>`, numbered if that file exists
+/// (the daemon never overwrites one).
+fn export_path(dir: &std::path::Path, peer_name: &str, format: ExportFormat) -> std::path::PathBuf {
+    let peer = sanitize_component(peer_name).unwrap_or_else(|| "conversacion".to_string());
+    let stem = format!("familycom-{peer}-{}", chrono::Local::now().format("%Y-%m-%d"));
+    let extension = format.extension();
+    let mut path = dir.join(format!("{stem}.{extension}"));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{stem}-{n}.{extension}"));
+        n += 1;
+    }
+    path
+}
+
+/// Asks the daemon for what the TUI shows on startup: our config, the
+/// peer list and the conversation summaries.
+async fn request_initial_data(client: &mut IpcClient) -> Result<(), ipc_client::IpcClientError> {
+    client.send(&ClientRequest::GetConfig).await?;
+    client.send(&ClientRequest::ListPeers).await?;
+    client.send(&ClientRequest::GetConversations).await
+}
+
+/// Requests message history for the currently selected peer.
+///
+/// Opening a conversation also marks it as read up to now.
+async fn fetch_selected_peer_messages(app: &mut TuiApp, client: &mut IpcClient) {
+    let Some(peer_id) = app.selected_peer_id().cloned() else {
+        return;
+    };
+    let _ = client.send(&app.latest_page_request(peer_id.clone())).await;
+    let _ = client
+        .send(&ClientRequest::GetMessageNotes {
+            peer_id: peer_id.clone(),
+        })
+        .await;
+    let _ = client
+        .send(&ClientRequest::MarkRead {
+            peer_id,
+            up_to: None,
+        })
+        .await;
+    app.clear_selected_unread();
 }
 
 /// Handles the --set-name CLI option.
@@ -315,6 +978,110 @@ async fn set_display_name(name: &str, socket: &Option<std::path::PathBuf>) -> Re
     }
 }
 
+/// Handles `familycom --announce`: sends an announcement to every online
+/// peer.
+async fn announce(content: &str, socket: &Option<std::path::PathBuf>) -> Result<()> {
+    use familycom_core::ipc::ServerMessage;
+
+    let socket_path = socket
+        .clone()
+        .unwrap_or_else(familycom_core::config::AppConfig::default_socket_path);
+    let mut client = IpcClient::connect_to(&socket_path)
+        .await
+        .context("could not connect to daemon")?;
+
+    client
+        .send(&ClientRequest::Announce {
+            content: content.to_string(),
+        })
+        .await?;
+    match client.recv().await? {
+        ServerMessage::Announced { message_ids } if message_ids.is_empty() => {
+            println!("No hay nadie en linea para recibir el anuncio")
+        }
+        ServerMessage::Announced { message_ids } => match message_ids.len() {
+            1 => println!("Anuncio enviado a 1 equipo"),
+            n => println!("Anuncio enviado a {n} equipos"),
+        },
+        ServerMessage::Error { message, .. } => bail!("{message}"),
+        other => bail!("unexpected response from daemon: {other:?}"),
+    }
+    Ok(())
+}
+
+/// Handles `familycom stop` and `familycom restart`.
+async fn stop_daemon(request: ClientRequest, socket: &Option<std::path::PathBuf>) -> Result<()> {
+    use familycom_core::ipc::ServerMessage;
+
+    let socket_path = socket
+        .clone()
+        .unwrap_or_else(familycom_core::config::AppConfig::default_socket_path);
+    let mut client = IpcClient::connect_to(&socket_path)
+        .await
+        .context("could not connect to daemon")?;
+
+    client.send(&request).await?;
+    match client.recv().await? {
+        ServerMessage::ShuttingDown { restart: false } => println!("Deteniendo el daemon"),
+        ServerMessage::ShuttingDown { restart: true } => println!("Reiniciando el daemon"),
+        ServerMessage::Error { message, .. } => bail!("{message}"),
+        other => bail!("unexpected response from daemon: {other:?}"),
+    }
+    Ok(())
+}
+
+/// Handles `familycom notes`: prints the notes matching `query`, each
+/// under the message it annotates.
+async fn search_notes(query: &str, socket: &Option<std::path::PathBuf>) -> Result<()> {
+    use familycom_core::ipc::ServerMessage;
+
+    let socket_path = socket
+        .clone()
+        .unwrap_or_else(familycom_core::config::AppConfig::default_socket_path);
+    let mut client = IpcClient::connect_to(&socket_path)
+        .await
+        .context("could not connect to daemon")?;
+
+    client.send(&ClientRequest::ListPeers).await?;
+    let peers = match client.recv().await? {
+        ServerMessage::PeerList { peers } => peers,
+        ServerMessage::Error { message, .. } => bail!("{message}"),
+        other => bail!("unexpected response from daemon: {other:?}"),
+    };
+
+    client
+        .send(&ClientRequest::SearchNotes {
+            query: query.to_string(),
+        })
+        .await?;
+    let results = match client.recv().await? {
+        ServerMessage::NoteSearchResults { results } => results,
+        ServerMessage::Error { message, .. } => bail!("{message}"),
+        other => bail!("unexpected response from daemon: {other:?}"),
+    };
+
+    if results.is_empty() {
+        println!("No hay notas con \"{query}\"");
+        return Ok(());
+    }
+    for found in results {
+        let msg = &found.message;
+        let peer_name = peers
+            .iter()
+            .find(|p| p.id == msg.peer_id)
+            .map(|p| p.display_name.as_str())
+            .unwrap_or("???");
+        let speaker = match msg.direction {
+            familycom_core::types::Direction::Received => peer_name.to_string(),
+            familycom_core::types::Direction::Sent => format!("Yo -> {peer_name}"),
+            familycom_core::types::Direction::System => format!("FamilyCom ({peer_name})"),
+        };
+        println!("{}  {speaker}: {}", msg.timestamp.format_local_datetime(), msg.content);
+        println!("    ✎ {}", found.note.note);
+    }
+    Ok(())
+}
+
 /// Opens a log file in the FamilyCom data directory for append-mode writing.
 ///
 /// Returns `None` if the data directory can't be determined or the file
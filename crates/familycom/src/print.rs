@@ -0,0 +1,243 @@
+//! Conversation printing (`familycom print`).
+//!
+//! Renders one conversation as plain text on stdout, meant to be piped to
+//! `lp` for a paper copy:
+//!
+//! ```text
+//! Conversacion con PC-Sala
+//! Desde el 01/02/2026
+//! Impreso el 16/02/2026 18:05
+//! ================================================================
+//!
+//! --- 13/02/2026 ---
+//!
+//! 10:30  PC-Sala  Hola abuela! Te mando un abrazo grande desde el
+//!                 colegio.
+//! 10:31  Yo       Gracias mi amor!
+//! ```
+//!
+//! Times and names are in fixed-width columns and message text is
+//! word-wrapped under itself, so the output stays readable on paper. No
+//! colors or escape codes are emitted.
+
+use crate::ipc_client::IpcClient;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use familycom_core::ipc::{ClientRequest, ServerMessage};
+use familycom_core::types::{Direction, Message, PeerInfo, Timestamp};
+use std::path::PathBuf;
+
+/// Messages fetched per `GetMessages` request while walking back in history.
+const PAGE_SIZE: u32 = 500;
+
+/// Narrowest text column we wrap to, however small `--width` is.
+const MIN_TEXT_WIDTH: usize = 20;
+
+/// Label used for our own messages, same as in the TUI.
+const OWN_NAME: &str = "Yo";
+
+/// Label used for daemon-generated messages (e.g. the weekly recap).
+const SYSTEM_NAME: &str = "FamilyCom";
+
+/// Parses a `--since` date, accepting `2026-02-13` or `13/02/2026`.
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(s, "%d/%m/%Y"))
+        .map_err(|_| format!("fecha invalida '{s}', usa AAAA-MM-DD o DD/MM/AAAA"))
+}
+
+/// Fetches the conversation with `peer_name` and prints it to stdout.
+pub async fn run(socket_path: &PathBuf, peer_name: &str, since: Option<NaiveDate>, width: usize) -> Result<()> {
+    let mut client = IpcClient::connect_to(socket_path)
+        .await
+        .context("could not connect to daemon")?;
+
+    client.send(&ClientRequest::ListPeers).await?;
+    let peers = match client.recv().await? {
+        ServerMessage::PeerList { peers } => peers,
+        ServerMessage::Error { message, .. } => bail!("{message}"),
+        other => bail!("unexpected response from daemon: {other:?}"),
+    };
+    let peer = find_peer(&peers, peer_name)?;
+
+    let since_ts = since.map(local_midnight);
+    let mut messages = fetch_since(&mut client, peer, since_ts).await?;
+    // The daemon returns newest first; paper reads top to bottom
+    messages.reverse();
+
+    print!("{}", render(&peer.display_name, &messages, since, width));
+    Ok(())
+}
+
+/// Finds a peer by display name, ignoring case and surrounding spaces.
+pub fn find_peer<'a>(peers: &'a [PeerInfo], name: &str) -> Result<&'a PeerInfo> {
+    let wanted = name.trim().to_lowercase();
+    let matches: Vec<&PeerInfo> = peers
+        .iter()
+        .filter(|p| p.display_name.to_lowercase() == wanted)
+        .collect();
+    match matches.as_slice() {
+        [peer] => Ok(peer),
+        [] => {
+            let known: Vec<&str> = peers.iter().map(|p| p.display_name.as_str()).collect();
+            bail!(
+                "no hay ningun peer llamado '{name}' (conocidos: {})",
+                known.join(", ")
+            )
+        }
+        _ => bail!("hay varios peers llamados '{name}'"),
+    }
+}
+
+/// Pages back through history until `since` (or the first message).
+///
+/// Returns messages newest first, all at or after `since`.
+pub async fn fetch_since(client: &mut IpcClient, peer: &PeerInfo, since: Option<Timestamp>) -> Result<Vec<Message>> {
+    let mut all = Vec::new();
+    let mut cursor = None;
+    loop {
+        client
+            .send(&ClientRequest::GetMessages {
+                peer_id: peer.id.clone(),
+                limit: PAGE_SIZE,
+                before: None,
+                cursor,
+            })
+            .await?;
+        let (page, next_cursor) = match client.recv().await? {
+            ServerMessage::Messages {
+                messages, next_cursor, ..
+            } => (messages, next_cursor),
+            ServerMessage::Error { message, .. } => bail!("{message}"),
+            other => bail!("unexpected response from daemon: {other:?}"),
+        };
+
+        let Some(oldest) = page.last().map(|m| m.timestamp) else {
+            break;
+        };
+        all.extend(page);
+
+        let reached_since = since.is_some_and(|s| oldest < s);
+        if next_cursor.is_none() || reached_since {
+            break;
+        }
+        cursor = next_cursor;
+    }
+
+    if let Some(since) = since {
+        all.retain(|m| m.timestamp >= since);
+    }
+    Ok(all)
+}
+
+/// Renders the printable text for a conversation (messages oldest first).
+fn render(peer_name: &str, messages: &[Message], since: Option<NaiveDate>, width: usize) -> String {
+    let mut name_width = peer_name.chars().count().max(OWN_NAME.chars().count());
+    if messages.iter().any(|m| m.direction == Direction::System) {
+        name_width = name_width.max(SYSTEM_NAME.chars().count());
+    }
+    // "HH:MM  " + name column + two spaces
+    let indent = 7 + name_width + 2;
+    let text_width = width.saturating_sub(indent).max(MIN_TEXT_WIDTH);
+
+    let mut out = String::new();
+    out.push_str(&format!("Conversacion con {peer_name}\n"));
+    if let Some(since) = since {
+        out.push_str(&format!("Desde el {}\n", since.format("%d/%m/%Y")));
+    }
+    out.push_str(&format!("Impreso el {}\n", Local::now().format("%d/%m/%Y %H:%M")));
+    out.push_str(&"=".repeat(width.max(indent + MIN_TEXT_WIDTH)));
+    out.push('\n');
+
+    if messages.is_empty() {
+        out.push_str("\n(sin mensajes)\n");
+        return out;
+    }
+
+    let mut current_day = None;
+    for msg in messages {
+        let at = local_time(msg.timestamp);
+        let day = at.map(|t| t.date_naive());
+        if day != current_day {
+            current_day = day;
+            let label = day.map(|d| d.format("%d/%m/%Y").to_string()).unwrap_or_else(|| "??/??/????".to_string());
+            out.push_str(&format!("\n--- {label} ---\n\n"));
+        }
+
+        let time = at.map(|t| t.format("%H:%M").to_string()).unwrap_or_else(|| "??:??".to_string());
+        let name = match msg.direction {
+            Direction::Sent => OWN_NAME,
+            Direction::Received => peer_name,
+            Direction::System => SYSTEM_NAME,
+        };
+
+        for (i, line) in wrap(&msg.content, text_width).iter().enumerate() {
+            if i == 0 {
+                out.push_str(&format!("{time}  {name:<name_width$}  {line}\n"));
+            } else {
+                out.push_str(&format!("{:indent$}{line}\n", ""));
+            }
+        }
+    }
+    out
+}
+
+/// Word-wraps `text` to `width` characters, keeping its own line breaks.
+///
+/// Words longer than a whole line are split so nothing runs off the page.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            // Hard-split words that can never fit
+            while word.len() > width {
+                if line_len > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    line_len = 0;
+                }
+                lines.push(word.drain(..width).collect());
+            }
+            if word.is_empty() {
+                continue;
+            }
+            if line_len > 0 && line_len + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+                line_len = 0;
+            }
+            if line_len > 0 {
+                line.push(' ');
+                line_len += 1;
+            }
+            line_len += word.len();
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// Converts a timestamp to local time, if it's representable.
+fn local_time(ts: Timestamp) -> Option<DateTime<Local>> {
+    match Local.timestamp_millis_opt(ts.as_millis()) {
+        chrono::LocalResult::Single(dt) => Some(dt),
+        chrono::LocalResult::Ambiguous(dt, _) => Some(dt),
+        chrono::LocalResult::None => None,
+    }
+}
+
+/// Start of `date` in local time, as a `Timestamp`.
+pub fn local_midnight(date: NaiveDate) -> Timestamp {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let millis = match Local.from_local_datetime(&midnight) {
+        chrono::LocalResult::Single(dt) | chrono::LocalResult::Ambiguous(dt, _) => dt.timestamp_millis(),
+        // Midnight skipped by a DST change: UTC is close enough
+        chrono::LocalResult::None => midnight.and_utc().timestamp_millis(),
+    };
+    Timestamp::from_millis(millis)
+}
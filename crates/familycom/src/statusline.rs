@@ -0,0 +1,93 @@
+//! One-line summary for terminal status bars (`familycom statusline`).
+//!
+//! Prints the unread total and how many peers are online, e.g. for tmux:
+//!
+//! ```text
+//! set -g status-right '#(familycom statusline) %H:%M'
+//! ```
+//!
+//! or zellij's `zjstatus` command widget. Status bars re-run the command
+//! every few seconds, so the answer is cached in a small file next to the
+//! daemon socket and reused for `--max-age` seconds instead of asking the
+//! daemon each time. When the daemon isn't running, nothing is printed so
+//! the status bar just leaves the space empty.
+
+use crate::ipc_client::IpcClient;
+use anyhow::{bail, Context, Result};
+use familycom_core::ipc::{ClientRequest, ServerMessage};
+use familycom_core::types::Timestamp;
+use std::path::{Path, PathBuf};
+
+/// Output used when no `--format` is given.
+pub const DEFAULT_FORMAT: &str = "✉ {unread} ● {online}";
+
+/// What the status line shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Summary {
+    unread: u32,
+    online: u32,
+}
+
+/// Prints the status line, from the cache if it's at most `max_age_secs` old.
+pub async fn run(socket_path: &Path, format: &str, max_age_secs: u64) -> Result<()> {
+    let cache = cache_path(socket_path);
+    let now = Timestamp::now();
+    let summary = match read_cache(&cache, now, max_age_secs) {
+        Some(summary) => summary,
+        None => match fetch(socket_path).await {
+            Ok(summary) => {
+                // Best effort: without a cache we just ask every time
+                let line = format!("{} {} {}", now.as_millis(), summary.unread, summary.online);
+                let _ = std::fs::write(&cache, line);
+                summary
+            }
+            // Daemon not running: leave the status bar slot empty
+            Err(_) => return Ok(()),
+        },
+    };
+    println!("{}", render(format, summary));
+    Ok(())
+}
+
+/// Asks the daemon for the summary.
+async fn fetch(socket_path: &Path) -> Result<Summary> {
+    let mut client = IpcClient::connect_to(&socket_path.to_path_buf())
+        .await
+        .context("could not connect to daemon")?;
+    client.send(&ClientRequest::GetSummary).await?;
+    match client.recv().await? {
+        ServerMessage::Summary { unread, online_peers } => Ok(Summary {
+            unread,
+            online: online_peers,
+        }),
+        ServerMessage::Error { message, .. } => bail!("{message}"),
+        other => bail!("unexpected response from daemon: {other:?}"),
+    }
+}
+
+/// The cache lives next to the socket, so each daemon (`--socket`) has its own.
+fn cache_path(socket_path: &Path) -> PathBuf {
+    let mut name = socket_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".statusline");
+    socket_path.with_file_name(name)
+}
+
+/// The cached summary, if the file exists and is fresh enough.
+fn read_cache(path: &Path, now: Timestamp, max_age_secs: u64) -> Option<Summary> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let mut fields = content.split_whitespace();
+    let written_at: i64 = fields.next()?.parse().ok()?;
+    let summary = Summary {
+        unread: fields.next()?.parse().ok()?,
+        online: fields.next()?.parse().ok()?,
+    };
+    let age_ms = now.as_millis().checked_sub(written_at)?;
+    (0..=max_age_secs as i64 * 1000).contains(&age_ms).then_some(summary)
+}
+
+/// Fills `{unread}` and `{online}` into the format.
+fn render(format: &str, summary: Summary) -> String {
+    format
+        .replace("{unread}", &summary.unread.to_string())
+        .replace("{online}", &summary.online.to_string())
+}
@@ -0,0 +1,105 @@
+//! Plain-text session transcript (`familycom --transcript <file>`).
+//!
+//! While the TUI runs, every message shown (received, sent, or posted by
+//! the daemon) and every status bar change is appended to the file, one
+//! line each:
+//!
+//! ```text
+//! 2026-02-13 10:30:12  PC-Sala: Hola, como estas?
+//! 2026-02-13 10:31:02  Yo -> PC-Sala: Bien! Aqui trabajando
+//! 2026-02-13 10:31:03  * Desconectado del daemon
+//! ```
+//!
+//! Screen readers and other accessibility tools can follow the file with
+//! `tail -f`, and it doubles as a local record of important conversations.
+//! Message history loaded when switching conversations isn't repeated;
+//! only messages that appear while the session runs are written.
+
+use crate::app::TuiApp;
+use chrono::{Local, TimeZone};
+use familycom_core::types::{Direction, Message, Timestamp};
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use tracing::warn;
+
+/// Appends the session to a transcript file.
+pub struct Transcript {
+    out: LineWriter<File>,
+    /// Last status written, so redraws don't repeat it.
+    last_status: String,
+    /// Set after a write error; the TUI keeps running without a transcript.
+    failed: bool,
+}
+
+impl Transcript {
+    /// Opens `path` for appending, creating it if needed, and writes a
+    /// session header.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut transcript = Self {
+            out: LineWriter::new(file),
+            last_status: String::new(),
+            failed: false,
+        };
+        transcript.write_line(&format!("=== Sesion iniciada {} ===", now()));
+        Ok(transcript)
+    }
+
+    /// Records a message that is now on screen.
+    pub fn record_message(&mut self, app: &TuiApp, msg: &Message) {
+        let peer_name = app
+            .peers
+            .iter()
+            .find(|p| p.id == msg.peer_id)
+            .map(|p| p.display_name.as_str())
+            .unwrap_or("???");
+        let speaker = match msg.direction {
+            Direction::Received => peer_name.to_string(),
+            Direction::Sent => format!("Yo -> {peer_name}"),
+            Direction::System => format!("FamilyCom ({peer_name})"),
+        };
+        // Continuation lines are indented so each message stays one block
+        let content = msg.content.replace('\n', "\n    ");
+        self.write_line(&format!("{}  {speaker}: {content}", format_time(msg.timestamp)));
+    }
+
+    /// Records the status bar text, if it changed since the last call.
+    pub fn record_status(&mut self, status: &str) {
+        if status == self.last_status {
+            return;
+        }
+        self.last_status = status.to_string();
+        self.write_line(&format!("{}  * {status}", now()));
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.failed {
+            return;
+        }
+        if let Err(e) = writeln!(self.out, "{line}") {
+            warn!(error = %e, "failed to write transcript, disabling it");
+            self.failed = true;
+        }
+    }
+}
+
+impl Drop for Transcript {
+    fn drop(&mut self) {
+        self.write_line(&format!("=== Sesion terminada {} ===", now()));
+    }
+}
+
+fn now() -> String {
+    format_time(Timestamp::now())
+}
+
+/// Formats a timestamp as local "AAAA-MM-DD HH:MM:SS".
+fn format_time(ts: Timestamp) -> String {
+    match Local.timestamp_millis_opt(ts.as_millis()) {
+        chrono::LocalResult::Single(dt) | chrono::LocalResult::Ambiguous(dt, _) => {
+            dt.format("%Y-%m-%d %H:%M:%S").to_string()
+        }
+        chrono::LocalResult::None => "????-??-?? ??:??:??".to_string(),
+    }
+}
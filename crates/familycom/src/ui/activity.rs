@@ -0,0 +1,67 @@
+//! Activity feed view (toggled with F2).
+//!
+//! Replaces the peer list and conversation with a log of recent daemon
+//! events, newest at the bottom. Handy for diagnosing connectivity
+//! problems without tailing `daemon.log`.
+//!
+//! ```text
+//! +-- Actividad (F2/Esc: volver) ----------------------+
+//! | [10:30] PC-Sala en linea (192.168.1.20:9876)       |
+//! | [10:31] Mensaje recibido de PC-Sala                |
+//! | [10:32] Laptop desconectado                        |
+//! +----------------------------------------------------+
+//! ```
+
+use crate::app::{ActivityLevel, TuiApp};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::Frame;
+
+/// Renders the activity feed into `area`.
+pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let block = Block::default()
+        .title(" Actividad (F2/Esc: volver) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    if app.activity.is_empty() {
+        let empty = Paragraph::new("Sin actividad todavia")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(block);
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let lines: Vec<Line> = app
+        .activity
+        .iter()
+        .map(|entry| {
+            let color = match entry.level {
+                ActivityLevel::Info => Color::White,
+                ActivityLevel::Warning => Color::Yellow,
+                ActivityLevel::Error => Color::Red,
+            };
+            Line::from(vec![
+                Span::styled(
+                    format!("[{}] ", entry.at.format_local_time()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(entry.text.as_str(), Style::default().fg(color)),
+            ])
+        })
+        .collect();
+
+    // One entry per line (no wrapping), so the offset that pins the newest
+    // entry to the bottom is simply "lines that don't fit", minus how far
+    // the user has scrolled back.
+    let visible = area.height.saturating_sub(2) as usize;
+    let bottom = lines.len().saturating_sub(visible);
+    let offset = bottom.saturating_sub(app.activity_scroll as usize);
+
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .scroll((offset.min(u16::MAX as usize) as u16, 0));
+    frame.render_widget(paragraph, area);
+}
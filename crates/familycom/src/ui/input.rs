@@ -10,6 +10,13 @@
 //! ```
 //!
 //! The cursor is shown as a blinking block when the input is focused.
+//! In vim mode the title says which mode is on, NORMAL or INSERTAR.
+//!
+//! While replying, the title quotes the message being answered
+//! ("↪ respondiendo a: Mamá: ¿Cenamos…").
+//!
+//! While a search query is typed (/), the box shows it instead, after a
+//! `/` prompt; the message being written is kept for later.
 
 use crate::app::{FocusedPanel, TuiApp};
 use ratatui::layout::Rect;
@@ -19,6 +26,11 @@ use ratatui::Frame;
 
 /// Renders the text input panel.
 pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    if let Some(search) = app.search.as_ref().filter(|s| s.editing) {
+        render_search(frame, &search.query, area);
+        return;
+    }
+
     let is_focused = app.focused == FocusedPanel::Input;
 
     let border_style = if is_focused {
@@ -27,10 +39,18 @@ pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
         Style::default().fg(Color::DarkGray)
     };
 
-    let title = if is_focused {
-        " Escribe un mensaje (Enter para enviar) "
+    let title = if app.note_target.is_some() {
+        " Nota privada (↑↓ elegir mensaje, Enter guardar, Esc cancelar) ".to_string()
+    } else if let Some(reply_to) = &app.reply_to {
+        format!(" ↪ respondiendo a: {} (Esc cancela) ", super::messages::quote(app, reply_to))
+    } else if app.config.vim_mode && is_focused {
+        " -- INSERTAR -- (Enter enviar, Esc salir) ".to_string()
+    } else if app.config.vim_mode {
+        " -- NORMAL -- (i para escribir) ".to_string()
+    } else if is_focused {
+        " Escribe un mensaje (Enter para enviar) ".to_string()
     } else {
-        " Escribe un mensaje... "
+        " Escribe un mensaje... ".to_string()
     };
 
     let block = Block::default()
@@ -58,12 +78,34 @@ pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
         // +2 for the border (1) and "> " prefix (2), -1 for 0-indexing
         // The cursor_x offset accounts for the "> " prefix (2 chars)
         // plus the current cursor position in the input text.
-        let cursor_x = area.x + 1 + 2 + visual_cursor_offset(&app.input, app.input_cursor) as u16;
-        let cursor_y = area.y + 1; // +1 for the top border
+        // Clamped to the inside of the box: long input scrolls out of view
+        // rather than pushing the cursor past the panel (or overflowing).
+        let offset = visual_cursor_offset(&app.input, app.input_cursor).min(u16::MAX as usize) as u16;
+        let last_col = area.right().saturating_sub(2).max(area.x);
+        let cursor_x = area.x.saturating_add(3).saturating_add(offset).min(last_col);
+        let cursor_y = area.y.saturating_add(1).min(area.bottom().saturating_sub(1)); // +1 for the top border
         frame.set_cursor_position((cursor_x, cursor_y));
     }
 }
 
+/// Renders the search query being typed, with the cursor at its end.
+fn render_search(frame: &mut Frame, query: &str, area: Rect) {
+    let block = Block::default()
+        .title(" Buscar en la conversacion (Enter buscar, Esc cancelar) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let search_widget = Paragraph::new(format!("/ {query}"))
+        .style(Style::default().fg(Color::White))
+        .block(block);
+    frame.render_widget(search_widget, area);
+
+    let offset = visual_cursor_offset(query, query.len()).min(u16::MAX as usize) as u16;
+    let last_col = area.right().saturating_sub(2).max(area.x);
+    let cursor_x = area.x.saturating_add(3).saturating_add(offset).min(last_col);
+    let cursor_y = area.y.saturating_add(1).min(area.bottom().saturating_sub(1));
+    frame.set_cursor_position((cursor_x, cursor_y));
+}
+
 /// Calculates the visual column offset for the cursor.
 ///
 /// Because we're dealing with UTF-8 strings, the byte offset (input_cursor)
@@ -17,16 +17,30 @@
 //! ```
 //!
 //! Uses ratatui's `Layout` with `Constraint`s to define proportional
-//! and fixed-size regions.
+//! and fixed-size regions. In the activity view (F2) and story mode (r)
+//! the peers and messages panels are replaced by a single full-width view.
+//! The peer list's width comes from `TuiConfig`, and it can be hidden
+//! (F4) to give the messages the whole width. The quick-switcher (Ctrl+K)
+//! is drawn over the chat, and a selected message's popup over the
+//! messages.
+//!
+//! Below `MIN_WIDTH` x `MIN_HEIGHT` the panels don't fit; a "ventana
+//! demasiado pequeña" placeholder is drawn instead.
 
-use crate::app::TuiApp;
-use crate::ui::{input, messages, peer_list};
+use crate::app::{TuiApp, View};
+use crate::ui::{activity, input, message_menu, messages, peer_list, replay, switcher};
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::Paragraph;
+use ratatui::widgets::{Paragraph, Wrap};
 use ratatui::Frame;
 
+/// Smallest terminal width the full layout is drawn at.
+pub const MIN_WIDTH: u16 = 30;
+/// Smallest terminal height the full layout is drawn at: a 5-line content
+/// area, the 3-line input box, and the status bar, plus one spare line.
+pub const MIN_HEIGHT: u16 = 10;
+
 /// Renders the complete TUI to the given frame.
 ///
 /// This is the top-level render function called on every frame.
@@ -34,6 +48,13 @@ use ratatui::Frame;
 pub fn render(frame: &mut Frame, app: &mut TuiApp) {
     let size = frame.area();
 
+    if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+        // Nothing is clickable while the placeholder is shown
+        app.panel_rects = Default::default();
+        render_too_small(frame, size);
+        return;
+    }
+
     // Main vertical layout: content area + input + status bar
     let vertical = Layout::default()
         .direction(Direction::Vertical)
@@ -48,32 +69,81 @@ pub fn render(frame: &mut Frame, app: &mut TuiApp) {
     let input_area = vertical[1];
     let status_area = vertical[2];
 
-    // Horizontal split for content: peers list | messages
-    let horizontal = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(25), // Peer list (25% width)
-            Constraint::Percentage(75), // Messages (75% width)
-        ])
-        .split(content_area);
-
-    let peers_area = horizontal[0];
-    let messages_area = horizontal[1];
-
-    // Save panel rectangles for mouse hit-testing
-    app.panel_rects.peers = peers_area;
-    app.panel_rects.messages = messages_area;
     app.panel_rects.input = input_area;
-
-    // Render each panel
-    peer_list::render(frame, app, peers_area);
-    messages::render(frame, app, messages_area);
     input::render(frame, app, input_area);
     render_status_bar(frame, app, status_area);
+
+    if app.view == View::Activity {
+        app.panel_rects.peers = Rect::default();
+        app.panel_rects.messages = Rect::default();
+        app.panel_rects.activity = content_area;
+        activity::render(frame, app, content_area);
+        return;
+    }
+    if app.view == View::Replay {
+        app.panel_rects.peers = Rect::default();
+        app.panel_rects.messages = Rect::default();
+        app.panel_rects.activity = Rect::default();
+        replay::render(frame, app, content_area);
+        return;
+    }
+    app.panel_rects.activity = Rect::default();
+
+    if app.config.peer_list_hidden {
+        app.panel_rects.peers = Rect::default();
+        app.panel_rects.messages = content_area;
+        messages::render(frame, app, content_area);
+    } else {
+        // Horizontal split for content: peers list | messages
+        let peer_list_width = app.config.peer_list_width;
+        let horizontal = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(peer_list_width),       // Peer list (25% by default)
+                Constraint::Percentage(100 - peer_list_width), // Messages
+            ])
+            .split(content_area);
+
+        let peers_area = horizontal[0];
+        let messages_area = horizontal[1];
+
+        // Save panel rectangles for mouse hit-testing
+        app.panel_rects.peers = peers_area;
+        app.panel_rects.messages = messages_area;
+
+        // Render each panel
+        peer_list::render(frame, app, peers_area);
+        messages::render(frame, app, messages_area);
+    }
+
+    if app.message_menu.as_ref().is_some_and(|m| m.item.is_some()) {
+        message_menu::render(frame, app, app.panel_rects.messages);
+    }
+    if app.switcher.is_some() {
+        switcher::render(frame, app, content_area);
+    }
+}
+
+/// Renders the placeholder shown when the terminal is below the minimum
+/// size. Wraps, so it degrades gracefully down to a 1x1 terminal.
+fn render_too_small(frame: &mut Frame, area: Rect) {
+    let text = format!("Ventana demasiado pequeña (mínimo {MIN_WIDTH}x{MIN_HEIGHT})");
+    let placeholder = Paragraph::new(text)
+        .style(Style::default().fg(Color::Yellow))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(placeholder, area);
 }
 
 /// Renders the status bar at the bottom of the screen.
 fn render_status_bar(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    // The quit prompt takes the whole bar until it's answered
+    if app.confirm_quit {
+        let prompt = Paragraph::new(" ¿Salir y descartar el mensaje? (s: salir, n: seguir escribiendo) ")
+            .style(Style::default().bg(Color::Yellow).fg(Color::Black).add_modifier(Modifier::BOLD));
+        frame.render_widget(prompt, area);
+        return;
+    }
+
     let online_count = app.peers.iter().filter(|p| p.online).count();
     let total_count = app.peers.len();
 
@@ -98,6 +168,15 @@ fn render_status_bar(frame: &mut Frame, app: &TuiApp, area: Rect) {
             app.our_name.to_string(),
             Style::default().fg(Color::Yellow),
         ),
+        Span::raw(" | "),
+        Span::styled(
+            match app.view {
+                View::Chat => "F2: actividad",
+                View::Activity => "F2: chat",
+                View::Replay => "Esc: chat",
+            },
+            Style::default().fg(Color::Gray),
+        ),
     ]);
 
     let status_bar = Paragraph::new(status_text)
@@ -105,3 +184,815 @@ fn render_status_bar(frame: &mut Frame, app: &TuiApp, area: Rect) {
 
     frame.render_widget(status_bar, area);
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{Action, FocusedPanel};
+    use familycom_core::ipc::ClientRequest;
+    use familycom_core::types::{Direction, Message, MessageId, NotificationPrefs, PeerId, PeerInfo, Timestamp};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    /// An app with enough state that every panel has something to draw.
+    fn busy_app() -> TuiApp {
+        let mut app = TuiApp::new();
+        let peer_id = PeerId::new("peer-1");
+        app.peers.push(PeerInfo {
+            id: peer_id.clone(),
+            display_name: "Habitación de Mamá".to_string(),
+            addresses: vec!["192.168.1.20:9876".to_string()],
+            last_seen_at: Timestamp::from_millis(0),
+            online: true,
+            do_not_disturb: false,
+            away: false,
+            avatar: None,
+            accent_color: None,
+            capabilities: Vec::new(),
+            notifications: NotificationPrefs::default(),
+            interface_addresses: Default::default(),
+        });
+        app.selected_peer_idx = Some(0);
+        let message = Message {
+            id: MessageId::new("m1"),
+            peer_id: peer_id.clone(),
+            direction: Direction::Received,
+            content: "¡Hola! ¿Cenamos juntos esta noche?".to_string(),
+            timestamp: Timestamp::from_millis(0),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        app.note_last_message(&message);
+        app.messages.insert(peer_id, vec![message]);
+        for c in "un mensaje bastante largo para el cuadro".chars() {
+            app.handle_action(Action::InputChar(c));
+        }
+        app.focused = FocusedPanel::Input;
+        app
+    }
+
+    fn draw(app: &mut TuiApp, width: u16, height: u16) -> Terminal<TestBackend> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| render(frame, app)).unwrap();
+        terminal
+    }
+
+    #[test]
+    fn tiny_sizes_do_not_panic() {
+        for view in [View::Chat, View::Activity, View::Replay] {
+            let mut app = busy_app();
+            app.view = view;
+            for width in 1..=20 {
+                for height in 1..=5 {
+                    draw(&mut app, width, height);
+                }
+            }
+        }
+    }
+
+    /// All the text drawn on the screen, row by row.
+    fn screen_text(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect::<String>() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn replay_shows_only_messages_reached_so_far() {
+        use familycom_core::ipc::ServerMessage;
+
+        let mut app = busy_app();
+        app.handle_action(Action::StartReplay);
+        assert_eq!(app.view, View::Replay);
+        assert!(app.replay_page_request().is_some());
+        assert!(app.replay_page_request().is_none(), "one page at a time");
+
+        let peer_id = PeerId::new("peer-1");
+        let messages = ["primero", "segundo"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Message {
+                id: MessageId::new(format!("m{i}")),
+                peer_id: peer_id.clone(),
+                direction: Direction::Received,
+                content: text.to_string(),
+                timestamp: Timestamp::from_millis(i as i64 * 3_600_000),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .collect();
+        app.handle_action(Action::ServerMessage(ServerMessage::Timeline { peer_id, messages }));
+        app.handle_action(Action::ReplaySkip);
+
+        let screen = screen_text(&draw(&mut app, 60, 20));
+        assert!(screen.contains("Historia"), "got {screen}");
+        assert!(screen.contains("primero"));
+        assert!(!screen.contains("segundo"));
+
+        app.handle_action(Action::ReplaySkip);
+        assert!(app.replay.as_ref().unwrap().finished());
+        assert!(screen_text(&draw(&mut app, 60, 20)).contains("segundo"));
+    }
+
+    #[test]
+    fn scrolling_to_the_top_loads_older_messages_in_place() {
+        use chrono::TimeZone;
+        use familycom_core::ipc::ServerMessage;
+
+        // The older page is from the day before
+        let noon = |day| chrono::Local.with_ymd_and_hms(2020, 2, day, 12, 0, 0).unwrap().timestamp_millis();
+        let peer_id = PeerId::new("peer-1");
+        let page = |range: std::ops::Range<i64>| -> Vec<Message> {
+            // Newest first, as the daemon sends them
+            range
+                .rev()
+                .map(|i| Message {
+                    id: MessageId::new(format!("m{i}")),
+                    peer_id: peer_id.clone(),
+                    direction: Direction::Received,
+                    content: format!("mensaje {i}"),
+                    timestamp: Timestamp::from_millis(noon(if i < 10 { 13 } else { 14 }) + i * 60_000),
+                    delivered: true,
+                    fire_and_forget: false,
+                    announcement: false,
+                    reply_to: None,
+                })
+                .collect()
+        };
+
+        let mut app = busy_app();
+        app.latest_page_request(peer_id.clone());
+        app.handle_action(Action::ServerMessage(ServerMessage::Messages {
+            messages: page(10..30),
+            next_cursor: Some("older".to_string()),
+            prev_cursor: None,
+        }));
+        let screen = screen_text(&draw(&mut app, 60, 20));
+        assert!(screen.contains("mensaje 29"), "newest at the bottom, got {screen}");
+        assert!(!screen.contains("mensaje 10 "));
+        assert!(app.older_page_request().is_none(), "not at the top yet");
+
+        for _ in 0..30 {
+            app.handle_action(Action::ScrollUp);
+        }
+        assert_eq!(app.messages_scroll, app.max_messages_scroll());
+        app.status.clear();
+        let before = screen_text(&draw(&mut app, 60, 20));
+        assert!(before.contains("mensaje 10"));
+        assert!(before.contains("── 14 de febrero de 2020 ──"), "got {before}");
+        match app.older_page_request() {
+            Some(ClientRequest::GetMessages { cursor, .. }) => assert_eq!(cursor.as_deref(), Some("older")),
+            other => panic!("expected GetMessages, got {other:?}"),
+        }
+        assert!(app.older_page_request().is_none(), "one page at a time");
+
+        app.handle_action(Action::ServerMessage(ServerMessage::Messages {
+            messages: page(0..10),
+            next_cursor: None,
+            prev_cursor: None,
+        }));
+        assert_eq!(app.current_messages().len(), 30);
+        assert_eq!(app.current_messages()[0].content, "mensaje 0");
+        app.status.clear();
+        assert_eq!(screen_text(&draw(&mut app, 60, 20)), before, "the view stays put");
+
+        for _ in 0..30 {
+            app.handle_action(Action::ScrollUp);
+        }
+        assert!(app.older_page_request().is_none(), "no more pages");
+        assert!(screen_text(&draw(&mut app, 60, 20)).contains("── 13 de febrero de 2020 ──"));
+    }
+
+    #[test]
+    fn typing_shows_in_the_title_until_the_message_arrives() {
+        use familycom_core::ipc::ServerMessage;
+
+        let mut app = busy_app();
+        let peer_id = PeerId::new("peer-1");
+        let typing = "Habitación de Mamá está escribiendo…";
+        assert!(!screen_text(&draw(&mut app, 100, 20)).contains(typing));
+
+        app.handle_action(Action::ServerMessage(ServerMessage::PeerTyping {
+            peer_id: peer_id.clone(),
+        }));
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains(typing), "got {screen}");
+
+        app.handle_action(Action::ServerMessage(ServerMessage::NewMessage {
+            message: Message {
+                id: MessageId::new("m2"),
+                peer_id,
+                direction: Direction::Received,
+                content: "¿A las nueve?".to_string(),
+                timestamp: Timestamp::from_millis(60_000),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            },
+        }));
+        assert!(!screen_text(&draw(&mut app, 100, 20)).contains(typing));
+    }
+
+    #[test]
+    fn sent_messages_show_delivery_and_read_marks() {
+        use familycom_core::ipc::ServerMessage;
+
+        let mut app = busy_app();
+        let peer_id = PeerId::new("peer-1");
+        let sent = Message {
+            id: MessageId::new("m2"),
+            peer_id: peer_id.clone(),
+            direction: Direction::Sent,
+            content: "Sí, a las nueve".to_string(),
+            timestamp: Timestamp::from_millis(60_000),
+            delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        app.handle_action(Action::ServerMessage(ServerMessage::NewMessage { message: sent }));
+        let header = |app: &mut TuiApp| {
+            let screen = screen_text(&draw(app, 100, 20));
+            screen.lines().find(|line| line.contains("Yo:")).unwrap_or_default().to_string()
+        };
+        assert!(header(&mut app).contains("Yo: …"), "got {}", header(&mut app));
+
+        app.handle_action(Action::ServerMessage(ServerMessage::MessageDelivered {
+            message_id: MessageId::new("m2"),
+        }));
+        assert!(header(&mut app).contains("Yo: ✓ "));
+
+        // A receipt for something older doesn't cover it
+        for up_to in [60_000, 0] {
+            app.handle_action(Action::ServerMessage(ServerMessage::MessagesRead {
+                peer_id: peer_id.clone(),
+                up_to: Timestamp::from_millis(up_to),
+            }));
+        }
+        assert!(header(&mut app).contains("Yo: ✓✓"));
+    }
+
+    #[test]
+    fn hidden_peer_list_leaves_the_width_to_the_messages() {
+        let mut app = busy_app();
+        app.focused = FocusedPanel::PeerList;
+        draw(&mut app, 80, 20);
+        assert_eq!(app.panel_rects.peers.width, 20);
+
+        app.handle_action(Action::WidenPeerList);
+        draw(&mut app, 80, 20);
+        assert_eq!(app.panel_rects.peers.width, 24);
+
+        app.handle_action(Action::TogglePeerList);
+        assert_eq!(app.focused, FocusedPanel::Messages);
+        let screen = screen_text(&draw(&mut app, 80, 20));
+        assert_eq!(app.panel_rects.peers, Rect::default());
+        assert_eq!(app.panel_rects.messages.width, 80);
+        assert!(!screen.contains("Peers"), "got {screen}");
+
+        // Tab skips it while hidden
+        app.handle_action(Action::NextFocus);
+        app.handle_action(Action::NextFocus);
+        assert_eq!(app.focused, FocusedPanel::Messages);
+
+        // Widening brings it back at the width it had
+        app.handle_action(Action::WidenPeerList);
+        draw(&mut app, 80, 20);
+        assert_eq!(app.panel_rects.peers.width, 24);
+    }
+
+    #[test]
+    fn vim_mode_moves_with_keys_and_types_after_i() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = busy_app();
+        app.config.vim_mode = true;
+        app.focused = FocusedPanel::Messages;
+        let press = |app: &mut TuiApp, code| {
+            let key = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+            if let Some(action) = crate::event::handle_event(&key, app) {
+                app.handle_action(action);
+            }
+        };
+        for n in 0..30 {
+            app.messages.get_mut(&PeerId::new("peer-1")).unwrap().push(Message {
+                id: MessageId::new(format!("v{n}")),
+                peer_id: PeerId::new("peer-1"),
+                direction: Direction::Received,
+                content: format!("mensaje {n}"),
+                timestamp: Timestamp::from_millis(n),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            });
+        }
+        assert!(screen_text(&draw(&mut app, 80, 20)).contains("-- NORMAL --"));
+
+        press(&mut app, KeyCode::Char('g'));
+        press(&mut app, KeyCode::Char('g'));
+        assert_eq!(app.messages_scroll, app.max_messages_scroll());
+        assert!(app.messages_scroll > 0);
+        press(&mut app, KeyCode::Char('G'));
+        assert_eq!(app.messages_scroll, 0);
+
+        // Esc doesn't quit in normal mode, and i starts typing
+        press(&mut app, KeyCode::Esc);
+        assert!(!app.should_quit);
+        let typed = app.input.clone();
+        press(&mut app, KeyCode::Char('i'));
+        assert_eq!(app.input, typed);
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.input, typed + "j");
+        assert!(screen_text(&draw(&mut app, 80, 20)).contains("-- INSERTAR --"));
+
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.focused, FocusedPanel::Messages);
+        assert!(!app.should_quit);
+    }
+
+    #[test]
+    fn placeholder_below_minimum_size() {
+        let mut app = busy_app();
+        let terminal = draw(&mut app, MIN_WIDTH - 1, MIN_HEIGHT);
+        let first_row: String = (0..terminal.backend().buffer().area.width)
+            .map(|x| terminal.backend().buffer()[(x, 0)].symbol().to_string())
+            .collect();
+        assert!(first_row.starts_with("Ventana"), "got {first_row:?}");
+        assert_eq!(app.panel_rects.peers, Rect::default());
+    }
+
+    #[test]
+    fn full_layout_at_minimum_size() {
+        let mut app = busy_app();
+        draw(&mut app, MIN_WIDTH, MIN_HEIGHT);
+        assert!(app.panel_rects.peers.width > 0);
+        assert!(app.panel_rects.input.height > 0);
+    }
+
+    #[test]
+    fn new_messages_while_scrolled_up_keep_the_view() {
+        use familycom_core::ipc::ServerMessage;
+
+        let mut app = busy_app();
+        app.focused = FocusedPanel::Messages;
+        let peer_id = PeerId::new("peer-1");
+        let received = |n: i64| Message {
+            id: MessageId::new(format!("n{n}")),
+            peer_id: peer_id.clone(),
+            direction: Direction::Received,
+            content: format!("mensaje {n}"),
+            timestamp: Timestamp::from_millis(n),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        app.handle_action(Action::ServerMessage(ServerMessage::NewMessages {
+            peer_id: peer_id.clone(),
+            messages: (1..20).map(received).collect(),
+        }));
+        assert_eq!(app.messages_scroll, 0);
+        draw(&mut app, 60, 20);
+
+        app.handle_action(Action::ScrollUp);
+        app.status.clear();
+        let before = screen_text(&draw(&mut app, 60, 20));
+        app.handle_action(Action::ServerMessage(ServerMessage::NewMessages {
+            peer_id: peer_id.clone(),
+            messages: (20..22).map(received).collect(),
+        }));
+        let after = screen_text(&draw(&mut app, 60, 20));
+        assert!(after.contains("2 nuevos ↓"), "got {after}");
+        let body = |screen: &str| screen.lines().skip(1).take(10).collect::<Vec<_>>().join("\n");
+        assert_eq!(body(&after), body(&before), "the view stays put");
+
+        app.handle_action(Action::JumpBottom);
+        let screen = screen_text(&draw(&mut app, 60, 20));
+        assert!(screen.contains("mensajes nuevos"), "got {screen}");
+        assert!(screen.contains("mensaje 21"));
+        assert!(!screen.contains("nuevos ↓"));
+    }
+
+    #[test]
+    fn relative_times_replace_the_clock_time() {
+        let mut app = busy_app();
+        let now = Timestamp::now().as_millis();
+        let message = app.messages.get_mut(&PeerId::new("peer-1")).unwrap();
+        message[0].timestamp = Timestamp::from_millis(now - 5 * 60_000 - 1_000);
+        let clock = message[0].timestamp.format_local_time();
+        assert!(screen_text(&draw(&mut app, 100, 20)).contains(&format!("[{clock}]")));
+
+        app.config.relative_times = true;
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains("[hace 5 min]"), "got {screen}");
+    }
+
+    #[test]
+    fn drafts_stay_with_their_conversation() {
+        let mut app = busy_app();
+        let mut papa = app.peers[0].clone();
+        papa.id = PeerId::new("peer-2");
+        papa.display_name = "PC-Papá".to_string();
+        app.peers.push(papa);
+        let typed = app.input.clone();
+
+        app.handle_action(Action::SelectPeer(1));
+        assert!(app.input.is_empty());
+        app.handle_action(Action::InputChar('¿'));
+        app.handle_action(Action::SelectPeer(0));
+        assert_eq!(app.input, typed);
+        assert_eq!(app.input_cursor, typed.len());
+        assert!(screen_text(&draw(&mut app, 100, 20)).contains("bastante largo"));
+
+        app.handle_action(Action::NextPeer);
+        assert_eq!(app.input, "¿");
+        assert_eq!(app.drafts.len(), 1);
+    }
+
+    #[test]
+    fn quick_switcher_jumps_to_a_matching_peer() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = busy_app();
+        for (id, name) in [("peer-2", "Móvil de Papá"), ("peer-3", "Portátil de Mamá")] {
+            let mut peer = app.peers[0].clone();
+            peer.id = PeerId::new(id);
+            peer.display_name = name.to_string();
+            app.peers.push(peer);
+        }
+        let press = |app: &mut TuiApp, code, modifiers| {
+            let key = Event::Key(KeyEvent::new(code, modifiers));
+            if let Some(action) = crate::event::handle_event(&key, app) {
+                app.handle_action(action);
+            }
+        };
+        press(&mut app, KeyCode::Char('k'), KeyModifiers::CONTROL);
+        for c in "mama".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains("> mama"), "got {screen}");
+        // The closer match first
+        assert_eq!(app.switcher_matches(), vec![2, 0]);
+
+        press(&mut app, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert!(app.switcher.is_none());
+        assert_eq!(app.selected_peer_idx, Some(0));
+        assert_eq!(app.focused, FocusedPanel::Input);
+        assert!(app.input.contains("bastante largo"), "typing went to the query");
+    }
+
+    #[test]
+    fn failed_messages_are_marked_until_delivered() {
+        use familycom_core::ipc::ServerMessage;
+
+        let mut app = busy_app();
+        app.focused = FocusedPanel::Messages;
+        let peer_id = PeerId::new("peer-1");
+        // Our own copy, as `handle_send_message` shows it
+        let shown = Message {
+            id: MessageId::new("local"),
+            peer_id: peer_id.clone(),
+            direction: Direction::Sent,
+            content: "Llego tarde".to_string(),
+            timestamp: Timestamp::from_millis(60_000),
+            delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        app.messages.get_mut(&peer_id).unwrap().push(shown.clone());
+        assert!(app.retry_target().is_none());
+
+        app.handle_action(Action::ServerMessage(ServerMessage::MessageFailed {
+            message: Message {
+                id: MessageId::new("daemon"),
+                ..shown
+            },
+            reason: "connection refused".to_string(),
+        }));
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains("Yo: ✗ no entregado"), "got {screen}");
+        assert_eq!(app.retry_target().map(|m| m.id.as_str()), Some("daemon"));
+
+        app.handle_action(Action::ServerMessage(ServerMessage::MessageDelivered {
+            message_id: MessageId::new("daemon"),
+        }));
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains("Yo: ✓"), "got {screen}");
+        assert!(app.retry_target().is_none());
+    }
+
+    #[test]
+    fn scrolling_counts_wrapped_rows() {
+        let mut app = busy_app();
+        app.focused = FocusedPanel::Messages;
+        app.config.peer_list_hidden = true;
+        let peer_id = PeerId::new("peer-1");
+        for n in 0..3 {
+            app.messages.get_mut(&peer_id).unwrap().push(Message {
+                id: MessageId::new(format!("w{n}")),
+                peer_id: peer_id.clone(),
+                direction: Direction::Received,
+                content: format!("mensaje {n} {}", "muy largo ".repeat(12)),
+                timestamp: Timestamp::from_millis(n),
+                delivered: true,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            });
+        }
+        draw(&mut app, 40, 12);
+
+        // Each long message takes several rows at this width, so the top
+        // is further up than its raw lines would say
+        let raw = messages::conversation_lines(&app).0.len();
+        assert!(app.max_messages_scroll() as usize > raw - 10, "got {}", app.max_messages_scroll());
+
+        for _ in 0..30 {
+            app.handle_action(Action::ScrollUp);
+        }
+        assert_eq!(app.messages_scroll, app.max_messages_scroll());
+        let screen = screen_text(&draw(&mut app, 40, 12));
+        assert!(screen.contains("¡Hola! ¿Cenamos"), "the oldest message at the top, got {screen}");
+
+        app.handle_action(Action::JumpBottom);
+        let screen = screen_text(&draw(&mut app, 40, 12));
+        assert!(screen.contains("muy largo"), "got {screen}");
+        assert!(!screen.contains("¡Hola!"));
+    }
+
+    #[test]
+    fn copy_mode_selects_text_of_the_messages() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = busy_app();
+        app.focused = FocusedPanel::Messages;
+        draw(&mut app, 100, 20);
+        let press = |app: &mut TuiApp, code| {
+            let key = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+            crate::event::handle_event(&key, app)
+        };
+        for code in [KeyCode::Char('v'), KeyCode::Char('l'), KeyCode::Char('l'), KeyCode::Char('v')] {
+            let action = press(&mut app, code).unwrap();
+            app.handle_action(action);
+        }
+        // Without moving, the selection is the cursor's character
+        assert_eq!(app.copy_selection().as_deref(), Some("¡"));
+
+        for code in [KeyCode::Char('$'), KeyCode::Char('k')] {
+            let action = press(&mut app, code).unwrap();
+            app.handle_action(action);
+        }
+        // From the header line's end back down to the start of the text
+        let selection = app.copy_selection().unwrap();
+        assert!(selection.ends_with(":\n  ¡"), "got {selection:?}");
+        draw(&mut app, 100, 20);
+
+        let action = press(&mut app, KeyCode::Char('j')).unwrap();
+        app.handle_action(action);
+        let action = press(&mut app, KeyCode::Char('$')).unwrap();
+        app.handle_action(action);
+        assert_eq!(app.copy_selection().as_deref(), Some("¡Hola! ¿Cenamos juntos esta noche?"));
+        assert!(matches!(press(&mut app, KeyCode::Char('y')), Some(Action::YankCopy)));
+
+        // Esc leaves copy mode instead of quitting
+        let action = press(&mut app, KeyCode::Esc).unwrap();
+        app.handle_action(action);
+        assert!(app.copy.is_none());
+        assert!(!app.should_quit);
+    }
+
+    #[test]
+    fn mark_all_read_clears_every_badge() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+        use familycom_core::ipc::ClientRequest;
+        use familycom_core::types::ConversationSummary;
+
+        let mut app = busy_app();
+        for (id, unread) in [("peer-1", 0), ("peer-2", 3), ("peer-3", 1)] {
+            app.conversations.insert(
+                PeerId::new(id),
+                ConversationSummary {
+                    peer_id: PeerId::new(id),
+                    last_message_preview: "hola".to_string(),
+                    last_direction: Direction::Received,
+                    last_timestamp: Timestamp::from_millis(0),
+                    unread_count: unread,
+                    read_by_peer: None,
+                },
+            );
+        }
+        app.focused = FocusedPanel::PeerList;
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('R'), KeyModifiers::SHIFT));
+        assert!(matches!(crate::event::handle_event(&key, &app), Some(Action::MarkAllRead)));
+
+        let mut marked: Vec<String> = app
+            .mark_all_read()
+            .into_iter()
+            .map(|request| match request {
+                ClientRequest::MarkRead { peer_id, up_to: None } => peer_id.as_str().to_string(),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        marked.sort();
+        assert_eq!(marked, ["peer-2", "peer-3"]);
+        assert_eq!(app.total_unread(), 0);
+        assert!(app.mark_all_read().is_empty());
+    }
+
+    #[test]
+    fn muting_a_peer_shows_in_the_peer_list() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+        use familycom_core::ipc::ClientRequest;
+
+        let mut app = busy_app();
+        app.focused = FocusedPanel::PeerList;
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('m'), KeyModifiers::NONE));
+        assert!(matches!(crate::event::handle_event(&key, &app), Some(Action::ToggleMute)));
+
+        match app.toggle_mute() {
+            Some(ClientRequest::SetPeerNotifications { peer_id, muted: true, sound: true }) => {
+                assert_eq!(peer_id.as_str(), "peer-1");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains("🔕"), "got {screen}");
+
+        assert!(matches!(
+            app.toggle_mute(),
+            Some(ClientRequest::SetPeerNotifications { muted: false, .. })
+        ));
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(!screen.contains("🔕"));
+    }
+
+    #[test]
+    fn quitting_with_typed_text_asks_first() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = busy_app();
+        let press = |app: &mut TuiApp, code| {
+            let key = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+            if let Some(action) = crate::event::handle_event(&key, app) {
+                app.handle_action(action);
+            }
+        };
+        press(&mut app, KeyCode::Esc);
+        assert!(!app.should_quit);
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains("¿Salir y descartar el mensaje?"), "got {screen}");
+
+        // Other keys wait for an answer; n goes back to typing
+        press(&mut app, KeyCode::Char('x'));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(!app.confirm_quit && !app.should_quit);
+        assert_eq!(app.input, "un mensaje bastante largo para el cuadro");
+
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('s'));
+        assert!(app.should_quit);
+
+        // With nothing typed it just quits
+        let mut app = busy_app();
+        app.take_input();
+        press(&mut app, KeyCode::Esc);
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn message_popup_forwards_the_selected_message() {
+        use crate::app::MessageAction;
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = busy_app();
+        let mut peer = app.peers[0].clone();
+        peer.id = PeerId::new("peer-2");
+        peer.display_name = "Móvil de Papá".to_string();
+        app.peers.push(peer);
+        let peer_id = PeerId::new("peer-1");
+        app.messages.get_mut(&peer_id).unwrap().push(Message {
+            id: MessageId::new("m2"),
+            peer_id: peer_id.clone(),
+            direction: Direction::Sent,
+            content: "Claro que sí".to_string(),
+            timestamp: Timestamp::from_millis(1),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        });
+        app.focused = FocusedPanel::Messages;
+        draw(&mut app, 100, 20);
+        let press = |app: &mut TuiApp, code| {
+            let key = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+            if let Some(action) = crate::event::handle_event(&key, app) {
+                app.handle_action(action);
+            }
+        };
+
+        // Starts on the newest; ours can be deleted for everyone
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.menu_message().map(|(idx, _)| idx), Some(1));
+        assert!(app.message_menu_items().contains(&MessageAction::Retract));
+
+        press(&mut app, KeyCode::Char('k'));
+        press(&mut app, KeyCode::Enter);
+        let screen = screen_text(&draw(&mut app, 100, 20));
+        assert!(screen.contains("Copiar texto"), "got {screen}");
+        assert_eq!(
+            app.message_menu_items(),
+            [
+                MessageAction::Reply,
+                MessageAction::Copy,
+                MessageAction::Note,
+                MessageAction::Forward,
+                MessageAction::Delete
+            ]
+        );
+
+        // Esc closes the popup but keeps the selection
+        press(&mut app, KeyCode::Esc);
+        assert!(app.message_menu.as_ref().is_some_and(|m| m.item.is_none()));
+        press(&mut app, KeyCode::Enter);
+        for _ in 0..3 {
+            press(&mut app, KeyCode::Down);
+        }
+        let (action, message) = app.take_message_action().unwrap();
+        assert_eq!(action, MessageAction::Forward);
+        assert_eq!(message.id, MessageId::new("m1"));
+        assert!(app.message_menu.is_none());
+
+        for c in "papa".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        match app.submit_forward() {
+            Some(ClientRequest::SendMessage { peer_id, content, .. }) => {
+                assert_eq!(peer_id.as_str(), "peer-2");
+                assert_eq!(content, "¡Hola! ¿Cenamos juntos esta noche?");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(app.switcher.is_none());
+        assert_eq!(app.selected_peer_idx, Some(0), "the open conversation stays");
+    }
+
+    #[test]
+    fn replies_are_quoted_and_indented_under_their_parent() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = busy_app();
+        app.focused = FocusedPanel::Messages;
+        let press = |app: &mut TuiApp, code| {
+            let key = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+            if let Some(action) = crate::event::handle_event(&key, app) {
+                app.handle_action(action);
+            }
+        };
+        // Enter, Enter: "Responder" on the newest message
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Enter);
+        assert!(app.take_message_action().is_some());
+        assert_eq!(app.reply_to, Some(MessageId::new("m1")));
+        assert_eq!(app.focused, FocusedPanel::Input);
+        let screen = screen_text(&draw(&mut app, 120, 20));
+        assert!(
+            screen.contains("↪ respondiendo a: Habitación de Mamá: ¡Hola! ¿Cenamos"),
+            "got {screen}"
+        );
+
+        // Esc drops the quote but not the text, and doesn't quit
+        press(&mut app, KeyCode::Esc);
+        assert!(app.reply_to.is_none() && !app.should_quit && !app.confirm_quit);
+        assert!(!app.input.is_empty());
+
+        let peer_id = PeerId::new("peer-1");
+        app.messages.get_mut(&peer_id).unwrap().push(Message {
+            id: MessageId::new("m2"),
+            peer_id: peer_id.clone(),
+            direction: Direction::Sent,
+            content: "Claro que sí".to_string(),
+            timestamp: Timestamp::from_millis(1),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: Some(MessageId::new("m1")),
+        });
+        let screen = screen_text(&draw(&mut app, 120, 20));
+        assert!(screen.contains("    ┆ Habitación de Mamá: ¡Hola!"), "got {screen}");
+        assert!(screen.contains("      Claro que sí"), "got {screen}");
+    }
+}
@@ -0,0 +1,64 @@
+//! Message popup (Enter on a selected message).
+//!
+//! Drawn over the messages panel: what can be done with the message
+//! marked with ▶, the highlighted entry done with Enter. Retrying only
+//! shows for a message that couldn't be delivered, and deleting for
+//! everyone only for ours.
+//!
+//! ```text
+//!        +-- Mensaje (Enter, Esc) ---+
+//!        | >> Responder              |
+//!        |    Copiar texto           |
+//!        |    Nota privada           |
+//!        |    Reenviar a…            |
+//!        |    Borrar (solo aquí)     |
+//!        |    Borrar para todos      |
+//!        +---------------------------+
+//! ```
+
+use crate::app::{MessageAction, TuiApp};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+/// Renders the popup centered in `area` (the messages panel).
+pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let Some(selected) = app.message_menu.as_ref().and_then(|m| m.item) else {
+        return;
+    };
+
+    let lines: Vec<Line> = app
+        .message_menu_items()
+        .into_iter()
+        .enumerate()
+        .map(|(pos, action)| {
+            let style = if pos == selected {
+                Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD)
+            } else if action == MessageAction::Retract {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let prefix = if pos == selected { ">> " } else { "   " };
+            Line::from(Span::styled(format!("{prefix}{}", action.label()), style))
+        })
+        .collect();
+
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let width = 29.min(area.width);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 3,
+        width,
+        height,
+    };
+
+    let block = Block::default()
+        .title(" Mensaje (Enter, Esc) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(Paragraph::new(lines).block(block), popup);
+}
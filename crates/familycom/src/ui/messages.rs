@@ -5,22 +5,76 @@
 //!
 //! ```text
 //! +-- Messages (PC-Sala) -------------------------+
+//! |               ── 13 de febrero ──              |  <- first message of a day
 //! | [10:30] PC-Sala:                               |
 //! | Hola, como estas?                              |
 //! |                                                |
-//! | [10:31] Yo:                                    |
+//! | [10:31] Yo: ✓✓                                 |  <- delivered (✓), read (✓✓)
 //! | Bien! Aqui trabajando en algo chevere          |
+//! |   ✎ preguntar por el proyecto                  |  <- private note (dim)
+//! |                                                |
+//! | [21:00] PC-Cocina: ANUNCIO                     |
+//! |  A cenar!                                      |  <- announcement banner
 //! +------------------------------------------------+
 //! ```
+//!
+//! A reply is indented, with the start of the message it answers quoted
+//! under its header:
+//!
+//! ```text
+//! |     [10:32] Yo:                                |
+//! |     ┆ PC-Sala: Hola, como estas?               |
+//! |       Muy bien!                                |
+//! ```
+//!
+//! A message the daemon couldn't deliver says "✗ no entregado" in red
+//! after the name, until t sends it again and it gets through.
+//!
+//! Messages that arrive while scrolled up don't move the view: a
+//! "── mensajes nuevos ──" divider goes above the first of them and the
+//! bottom border counts them ("2 nuevos ↓") until End jumps down.
+//!
+//! While the peer is writing to us, the title says so ("Mensajes -
+//! PC-Sala está escribiendo…") until they send it or stop for a while.
+//!
+//! With `relative_times = true` in tui.toml, the headers say "hace 5 min"
+//! or "ayer 21:03" instead of the clock time; the TUI redraws on its tick,
+//! so they keep up.
+//!
+//! In copy mode (v), the cursor is a cyan cell and the selection is shown
+//! in reverse video.
+//!
+//! After a search (/), the query is highlighted in the messages that
+//! match it, the current match (n/N) in a different color.
 
-use crate::app::{FocusedPanel, TuiApp};
-use familycom_core::types::Direction;
+use crate::app::{CopyMode, FocusedPanel, Search, TuiApp};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone};
+use familycom_core::types::{Direction, Message, MessageId, MessageStatus, Timestamp};
 use ratatui::layout::Rect;
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
 use ratatui::Frame;
 
+/// Characters of a quoted message shown before cutting it off.
+const QUOTE_CHARS: usize = 40;
+
+/// Month names for the day separators.
+const MONTHS: [&str; 12] = [
+    "enero",
+    "febrero",
+    "marzo",
+    "abril",
+    "mayo",
+    "junio",
+    "julio",
+    "agosto",
+    "septiembre",
+    "octubre",
+    "noviembre",
+    "diciembre",
+];
+
 /// Renders the message history panel.
 pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
     let is_focused = app.focused == FocusedPanel::Messages;
@@ -33,15 +87,35 @@ pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
 
     // Panel title includes the selected peer's name
     let title = match app.selected_peer() {
+        Some(peer) if app.is_typing(&peer.id) => {
+            format!(" Mensajes - {} está escribiendo… ", peer.display_name)
+        }
         Some(peer) => format!(" Mensajes - {} ", peer.display_name),
         None => " Mensajes ".to_string(),
     };
 
-    let block = Block::default()
+    let mut block = Block::default()
         .title(title)
         .borders(Borders::ALL)
         .border_style(border_style);
 
+    // Messages that arrived below the view while scrolled up
+    let unseen = app
+        .unseen
+        .as_ref()
+        .filter(|u| app.messages_scroll > 0 && app.selected_peer_id() == Some(&u.peer_id))
+        .map_or(0, |u| u.count);
+    if unseen > 0 {
+        let label = if unseen == 1 { "nuevo" } else { "nuevos" };
+        block = block.title_bottom(
+            Line::from(Span::styled(
+                format!(" {unseen} {label} ↓ (Fin) "),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ))
+            .right_aligned(),
+        );
+    }
+
     let messages = app.current_messages();
 
     if messages.is_empty() {
@@ -57,33 +131,115 @@ pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
         return;
     }
 
-    // Build the message lines.
+    let (mut lines, _) = conversation_lines(app);
+    if let Some(copy) = &app.copy {
+        mark_copy(&mut lines, copy);
+    }
+
+    // `messages_scroll` counts rows up from the bottom, as wrapped to the
+    // panel's width; the paragraph scrolls down from the top
+    let total = wrapped_height(&lines, area.width.saturating_sub(2)).min(u16::MAX as usize) as u16;
+    let visible = area.height.saturating_sub(2);
+    let top = total.saturating_sub(visible).saturating_sub(app.messages_scroll);
+
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((top, 0));
+
+    frame.render_widget(paragraph, area);
+}
+
+/// The lines of the open conversation as the panel shows them (before
+/// wrapping), and the index of each message's first line among them.
+pub fn conversation_lines(app: &TuiApp) -> (Vec<Line<'_>>, Vec<usize>) {
+    let messages = app.current_messages();
+
     // Each message becomes 2+ lines: header (time + name) + content.
     let mut lines: Vec<Line> = Vec::new();
+    let mut starts = Vec::with_capacity(messages.len());
+
+    // Highlights are shown once the query has been looked up
+    let search = app
+        .search
+        .as_ref()
+        .filter(|s| !s.editing && app.selected_peer_id() == Some(&s.peer_id));
+
+    // How far the peer has read what we sent, for the ✓✓ marks
+    let read_by_peer = app
+        .selected_peer_id()
+        .and_then(|id| app.conversations.get(id))
+        .and_then(|c| c.read_by_peer);
 
-    for msg in messages {
-        let time = msg.timestamp.format_local_time();
+    let now = Local::now();
+    let today = now.date_naive();
+    let unseen_first = app.unseen_first();
+    for (idx, msg) in messages.iter().enumerate() {
+        starts.push(lines.len());
+        if unseen_first == Some(&msg.id) {
+            lines.push(
+                Line::from(Span::styled("── mensajes nuevos ──", Style::default().fg(Color::Yellow))).centered(),
+            );
+        }
+        if let Some(day) = local_day(msg.timestamp).filter(|_| starts_day(messages, idx)) {
+            lines.push(
+                Line::from(Span::styled(
+                    format!("── {} ──", day_label(day, today)),
+                    Style::default().fg(Color::DarkGray),
+                ))
+                .centered(),
+            );
+        }
+
+        let time = if app.config.relative_times {
+            relative_time(msg.timestamp, now)
+        } else {
+            msg.timestamp.format_local_time()
+        };
 
         let (name, name_color) = match msg.direction {
             Direction::Sent => ("Yo".to_string(), Color::Cyan),
-            Direction::Received => {
-                let peer_name = app
-                    .selected_peer()
-                    .map(|p| p.display_name.clone())
-                    .unwrap_or_else(|| "???".to_string());
-                (peer_name, Color::Yellow)
-            }
+            Direction::System => ("FamilyCom".to_string(), Color::Magenta),
+            Direction::Received => match app.selected_peer() {
+                Some(peer) => {
+                    let name = match &peer.avatar {
+                        Some(avatar) => format!("{avatar} {}", peer.display_name),
+                        None => peer.display_name.clone(),
+                    };
+                    (name, super::peer_accent(peer).unwrap_or(Color::Yellow))
+                }
+                None => ("???".to_string(), Color::Yellow),
+            },
         };
 
         // Delivery indicator for sent messages
-        let delivery_indicator = match msg.direction {
-            Direction::Sent if msg.delivered => " [ok]",
-            Direction::Sent => " [...]",
-            Direction::Received => "",
+        let (delivery_indicator, delivery_color) = match msg.status(read_by_peer) {
+            // The daemon gave up on it; t sends it again
+            Some(MessageStatus::Pending | MessageStatus::Sent) if app.failed.contains(&msg.id) => (" ✗ no entregado", Color::Red),
+            Some(MessageStatus::Read) => (" ✓✓", Color::Cyan),
+            Some(MessageStatus::Delivered) => (" ✓", Color::DarkGray),
+            // Sent, but the peer isn't expected to ACK it
+            Some(MessageStatus::Sent) => (" enviado", Color::DarkGray),
+            Some(MessageStatus::Pending) => (" …", Color::DarkGray),
+            None => ("", Color::DarkGray),
         };
 
+        // The message a note is being written for, or the one selected to
+        // act on, is marked with an arrow
+        let selected = app.message_menu.as_ref().is_some_and(|m| m.message_id == msg.id);
+        let marker = if app.note_target == Some(idx) || selected {
+            Span::styled("▶ ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        } else {
+            Span::raw("")
+        };
+
+        // Replies are indented under the header of what they answer
+        let indent = if msg.reply_to.is_some() { "    " } else { "" };
+
         // Header line: [HH:MM] Name: [delivery]
         lines.push(Line::from(vec![
+            marker,
+            Span::raw(indent),
             Span::styled(
                 format!("[{time}] "),
                 Style::default().fg(Color::DarkGray),
@@ -96,26 +252,208 @@ pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
             ),
             Span::styled(
                 delivery_indicator,
-                Style::default().fg(Color::DarkGray),
+                Style::default().fg(delivery_color),
             ),
+            if msg.announcement {
+                Span::styled(" ANUNCIO", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            } else {
+                Span::raw("")
+            },
         ]));
 
-        // Content line(s)
-        for content_line in msg.content.lines() {
+        if let Some(parent) = &msg.reply_to {
             lines.push(Line::from(Span::styled(
-                format!("  {content_line}"),
-                Style::default().fg(Color::White),
+                format!("{indent}┆ {}", quote(app, parent)),
+                Style::default().fg(Color::DarkGray),
             )));
         }
 
+        // Content line(s); an announcement is a banner, black on yellow
+        let content_style = if msg.announcement {
+            Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let search_match = search.filter(|s| s.matches.contains(&msg.id));
+        for content_line in msg.content.lines() {
+            let mut spans = vec![Span::raw(indent), Span::styled("  ", content_style)];
+            match search_match {
+                Some(search) => spans.extend(highlight(content_line, search, &msg.id, content_style)),
+                None => spans.push(Span::styled(content_line, content_style)),
+            }
+            lines.push(Line::from(spans));
+        }
+
+        // Private note, if any (local only, never sent)
+        if let Some(note) = app.notes.get(&msg.id) {
+            for note_line in note.lines() {
+                lines.push(Line::from(Span::styled(
+                    format!("{indent}    ✎ {note_line}"),
+                    Style::default()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::ITALIC),
+                )));
+            }
+        }
+
         // Empty line between messages for readability
         lines.push(Line::from(""));
     }
 
-    let paragraph = Paragraph::new(lines)
-        .block(block)
-        .wrap(Wrap { trim: false })
-        .scroll((app.messages_scroll, 0));
+    (lines, starts)
+}
 
-    frame.render_widget(paragraph, area);
+/// "Name: start of the text" of a message of the open conversation, for
+/// quoting it in a reply; just "un mensaje anterior" if it isn't loaded
+/// (or was deleted).
+pub fn quote(app: &TuiApp, message_id: &MessageId) -> String {
+    let Some(message) = app.current_messages().iter().find(|m| &m.id == message_id) else {
+        return "un mensaje anterior".to_string();
+    };
+    let name = match message.direction {
+        Direction::Sent => "Yo",
+        Direction::System => "FamilyCom",
+        Direction::Received => app.selected_peer().map_or("???", |p| p.display_name.as_str()),
+    };
+    let first_line = message.content.lines().next().unwrap_or_default();
+    let mut text: String = first_line.chars().take(QUOTE_CHARS).collect();
+    if text.len() < message.content.len() {
+        text.push('…');
+    }
+    format!("{name}: {text}")
+}
+
+/// Rows `lines` take once wrapped to `width` columns. With no width yet
+/// (the panel hasn't been drawn), one each.
+pub fn wrapped_height(lines: &[Line], width: u16) -> usize {
+    if width == 0 {
+        return lines.len();
+    }
+    Paragraph::new(lines.to_vec()).wrap(Wrap { trim: false }).line_count(width)
+}
+
+/// Shows copy mode's selection and cursor on the lines.
+fn mark_copy(lines: &mut [Line], copy: &CopyMode) {
+    let cursor = (copy.line, copy.col);
+    if let Some(anchor) = copy.anchor {
+        let ((first, from), (last, to)) = (anchor.min(cursor), anchor.max(cursor));
+        for (idx, line) in lines.iter_mut().enumerate().take(last + 1).skip(first) {
+            let from = if idx == first { from } else { 0 };
+            let to = if idx == last { to + 1 } else { usize::MAX };
+            restyle(line, from, to, Style::default().add_modifier(Modifier::REVERSED));
+        }
+    }
+    if let Some(line) = lines.get_mut(copy.line) {
+        // An empty line still gets a cell for the cursor
+        if line.width() == 0 {
+            line.spans.push(Span::raw(" "));
+        }
+        restyle(line, copy.col, copy.col + 1, Style::default().fg(Color::Black).bg(Color::Cyan));
+    }
+}
+
+/// Patches `style` onto the characters `from..to` of `line`, splitting
+/// its spans where needed.
+fn restyle(line: &mut Line, from: usize, to: usize, style: Style) {
+    let mut spans = Vec::with_capacity(line.spans.len() + 2);
+    let mut pos = 0;
+    for span in line.spans.drain(..) {
+        let len = span.content.chars().count();
+        let start = from.clamp(pos, pos + len) - pos;
+        let end = to.clamp(pos, pos + len) - pos;
+        pos += len;
+        if start == end {
+            spans.push(span);
+            continue;
+        }
+        let text = span.content.as_ref();
+        let byte = |n: usize| text.char_indices().nth(n).map_or(text.len(), |(i, _)| i);
+        let (start, end) = (byte(start), byte(end));
+        for (part, part_style) in [
+            (&text[..start], span.style),
+            (&text[start..end], span.style.patch(style)),
+            (&text[end..], span.style),
+        ] {
+            if !part.is_empty() {
+                spans.push(Span::styled(part.to_string(), part_style));
+            }
+        }
+    }
+    line.spans = spans;
+}
+
+/// The text of a line as drawn, without its styles.
+pub fn line_text(line: &Line) -> String {
+    line.spans.iter().map(|span| span.content.as_ref()).collect()
+}
+
+/// The calendar day `timestamp` falls on in the local timezone.
+fn local_day(timestamp: Timestamp) -> Option<NaiveDate> {
+    Local
+        .timestamp_millis_opt(timestamp.as_millis())
+        .single()
+        .map(|t| t.date_naive())
+}
+
+/// Whether `messages[idx]` is the first of its day, so a separator line
+/// goes above it.
+fn starts_day(messages: &[Message], idx: usize) -> bool {
+    let day = local_day(messages[idx].timestamp);
+    day.is_some() && (idx == 0 || local_day(messages[idx - 1].timestamp) != day)
+}
+
+/// "13 de febrero", with the year unless it's this one.
+fn day_label(day: NaiveDate, today: NaiveDate) -> String {
+    let month = MONTHS[day.month0() as usize];
+    if day.year() == today.year() {
+        format!("{} de {month}", day.day())
+    } else {
+        format!("{} de {month} de {}", day.day(), day.year())
+    }
+}
+
+/// How long before `now` the message was sent: "ahora", "hace 5 min",
+/// "hace 2 h" today, "ayer 21:03", and the clock time before that (the day
+/// separator has the date).
+fn relative_time(timestamp: Timestamp, now: DateTime<Local>) -> String {
+    let Some(then) = Local.timestamp_millis_opt(timestamp.as_millis()).single() else {
+        return timestamp.format_local_time();
+    };
+    // A peer's clock may be a little ahead of ours
+    let minutes = (now - then).num_minutes().max(0);
+    let days = (now.date_naive() - then.date_naive()).num_days();
+    match (days, minutes) {
+        (_, 0) => "ahora".to_string(),
+        (_, 1..=59) => format!("hace {minutes} min"),
+        (0, _) => format!("hace {} h", minutes / 60),
+        (1, _) => format!("ayer {}", then.format("%H:%M")),
+        _ => timestamp.format_local_time(),
+    }
+}
+
+/// Splits a line of a matching message into spans, with each occurrence of
+/// the query highlighted. Case is ignored for ASCII letters, as the
+/// daemon's search does.
+fn highlight<'a>(text: &'a str, search: &Search, id: &MessageId, style: Style) -> Vec<Span<'a>> {
+    let hit_style = if search.current.as_ref() == Some(id) {
+        Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::Black).bg(Color::Yellow)
+    };
+    // ASCII lowercasing keeps byte offsets, so they apply to `text` too
+    let haystack = text.to_ascii_lowercase();
+    let needle = search.query.trim().to_ascii_lowercase();
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    if !needle.is_empty() {
+        while let Some(found) = haystack[pos..].find(&needle) {
+            let start = pos + found;
+            let end = start + needle.len();
+            spans.push(Span::styled(&text[pos..start], style));
+            spans.push(Span::styled(&text[start..end], hit_style));
+            pos = end;
+        }
+    }
+    spans.push(Span::styled(&text[pos..], style));
+    spans
 }
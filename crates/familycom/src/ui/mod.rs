@@ -5,8 +5,27 @@
 //! - `peer_list`: Left panel showing discovered peers
 //! - `messages`: Right panel showing message history
 //! - `input`: Bottom panel for text input
+//! - `activity`: Daemon event log shown instead of the chat (F2)
+//! - `replay`: Story mode, a conversation replayed from the start (r)
+//! - `switcher`: Quick-switcher popup over the chat (Ctrl+K)
+//! - `message_menu`: Popup of what to do with a selected message
 
+pub mod activity;
 pub mod input;
 pub mod layout;
+pub mod message_menu;
 pub mod messages;
 pub mod peer_list;
+pub mod replay;
+pub mod switcher;
+
+use familycom_core::types::PeerInfo;
+use ratatui::style::Color;
+
+/// Returns the terminal color for a peer's chosen accent color, if any.
+///
+/// Shared by the peer list and message panels so a peer's name looks the
+/// same everywhere.
+pub fn peer_accent(peer: &PeerInfo) -> Option<Color> {
+    peer.accent_color.map(|c| Color::Rgb(c.r, c.g, c.b))
+}
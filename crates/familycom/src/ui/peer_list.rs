@@ -1,23 +1,32 @@
 //! Peer list panel (left side).
 //!
-//! Shows all discovered peers with their online status.
+//! Shows all discovered peers with their online status, unread count,
+//! and a preview of the last message exchanged.
 //! The selected peer is highlighted, and arrow keys navigate the list.
 //!
 //! ```text
-//! +-- Peers --------+
-//! | * PC-Sala       |  <- * = online, selected (highlighted)
-//! |   Laptop-Ign    |  <- no *, offline
-//! |                 |
-//! +-----------------+
+//! +-- Peers -----------+
+//! | * 🐱 PC-Sala   (2) |  <- * = online, selected, 2 unread
+//! |     ¿Cenamos?      |  <- last message preview
+//! | - Laptop-Ign       |  <- offline
+//! |     Tú: Genial     |
+//! | z 🔕 Abuela        |  <- online, do not disturb, muted (m)
+//! |                    |
+//! +--------------------+
 //! ```
 
 use crate::app::{FocusedPanel, TuiApp};
+use familycom_core::types::Direction;
 use ratatui::layout::Rect;
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, List, ListItem, ListState};
 use ratatui::Frame;
 
+/// Lines per peer entry (name line + preview line). Used by mouse
+/// hit-testing in `event.rs`.
+pub const ITEM_HEIGHT: u16 = 2;
+
 /// Renders the peer list panel.
 pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
     let is_focused = app.focused == FocusedPanel::PeerList;
@@ -48,25 +57,63 @@ pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
         .peers
         .iter()
         .map(|peer| {
-            // Online indicator: green * for online, dim - for offline
-            let (indicator, indicator_color) = if peer.online {
+            // Online indicator: green * for online, yellow z for do not
+            // disturb, yellow ~ for away, dim - for offline
+            let (indicator, indicator_color) = if peer.online && peer.do_not_disturb {
+                ("z", Color::Yellow)
+            } else if peer.online && peer.away {
+                ("~", Color::Yellow)
+            } else if peer.online {
                 ("*", Color::Green)
             } else {
                 ("-", Color::DarkGray)
             };
 
+            // Offline peers are always dimmed; online peers use their
+            // accent color if they advertised one.
             let name_color = if peer.online {
-                Color::White
+                super::peer_accent(peer).unwrap_or(Color::White)
             } else {
                 Color::DarkGray
             };
 
-            let line = Line::from(vec![
-                Span::styled(format!(" {indicator} "), Style::default().fg(indicator_color)),
-                Span::styled(&peer.display_name, Style::default().fg(name_color)),
-            ]);
+            let mut spans = vec![Span::styled(
+                format!(" {indicator} "),
+                Style::default().fg(indicator_color),
+            )];
+            // Before the name, which may not fit
+            if peer.notifications.muted {
+                spans.push(Span::raw("🔕 "));
+            }
+            if let Some(avatar) = &peer.avatar {
+                spans.push(Span::raw(format!("{avatar} ")));
+            }
+            spans.push(Span::styled(&peer.display_name, Style::default().fg(name_color)));
+
+            let summary = app.conversations.get(&peer.id);
+            if let Some(unread) = summary.map(|s| s.unread_count).filter(|&n| n > 0) {
+                spans.push(Span::styled(
+                    format!(" ({unread})"),
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                ));
+            }
+
+            // Always two lines, even without a preview, so rows stay aligned
+            // with mouse hit-testing.
+            let preview = match summary {
+                Some(s) if s.last_direction == Direction::Sent => {
+                    format!("Tú: {}", s.last_message_preview)
+                }
+                Some(s) => s.last_message_preview.clone(),
+                None => String::new(),
+            };
+            let preview_line = Line::from(Span::styled(
+                format!("     {preview}"),
+                // Not DarkGray: it would vanish on the highlighted row
+                Style::default().fg(Color::Gray),
+            ));
 
-            ListItem::new(line)
+            ListItem::new(vec![Line::from(spans), preview_line])
         })
         .collect();
 
@@ -0,0 +1,103 @@
+//! Story mode view (r in the messages panel).
+//!
+//! Replaces the peer list and conversation with the selected conversation
+//! replayed from its first message, one message at a time, with a line
+//! marking each new day. The newest message shown stays at the bottom.
+//!
+//! ```text
+//! +-- Historia - PC-Sala (x60) ------------------------+
+//! | ── 14/02/2026 ──                                   |
+//! | [10:30] PC-Sala:                                   |
+//! |   ¿Quién quiere panqueques?                        |
+//! |                                                    |
+//! | [10:31] Yo:                                        |
+//! |   ¡Yo! ¡Yo!                                        |
+//! +-- Espacio: pausa  +/-: velocidad  Esc: salir ------+
+//! ```
+
+use crate::app::TuiApp;
+use chrono::{Local, NaiveDate, TimeZone};
+use familycom_core::types::{Direction, Timestamp};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::Frame;
+
+/// Renders the replay into `area`.
+pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let Some(replay) = &app.replay else {
+        return;
+    };
+    let peer = app.peers.iter().find(|p| p.id == replay.peer_id);
+    let peer_name = peer.map_or(replay.peer_id.as_str(), |p| p.display_name.as_str());
+
+    let state = if replay.finished() {
+        " - fin"
+    } else if replay.paused {
+        " - pausa"
+    } else if replay.shown == 0 {
+        " - cargando..."
+    } else {
+        ""
+    };
+    let block = Block::default()
+        .title(format!(" Historia - {peer_name} (x{}){state} ", replay.pacing.speed))
+        .title_bottom(" Espacio: pausa  +/-: velocidad  Enter: siguiente  Esc: salir ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    let mut lines: Vec<Line> = Vec::new();
+    let mut last_day: Option<NaiveDate> = None;
+    for msg in &replay.messages[..replay.shown] {
+        if let Some(day) = local_date(msg.timestamp).filter(|d| Some(*d) != last_day) {
+            lines.push(Line::from(Span::styled(
+                format!("── {} ──", day.format("%d/%m/%Y")),
+                Style::default().fg(Color::DarkGray).add_modifier(Modifier::BOLD),
+            )));
+            last_day = Some(day);
+        }
+
+        let (name, name_color) = match msg.direction {
+            Direction::Sent => ("Yo".to_string(), Color::Cyan),
+            Direction::System => ("FamilyCom".to_string(), Color::Magenta),
+            Direction::Received => (
+                peer_name.to_string(),
+                peer.and_then(super::peer_accent).unwrap_or(Color::Yellow),
+            ),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("[{}] ", msg.timestamp.format_local_time()),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::styled(format!("{name}:"), Style::default().fg(name_color).add_modifier(Modifier::BOLD)),
+        ]));
+        for content_line in msg.content.lines() {
+            lines.push(Line::from(Span::styled(
+                format!("  {content_line}"),
+                Style::default().fg(Color::White),
+            )));
+        }
+        lines.push(Line::from(""));
+    }
+
+    // Pin the newest message to the bottom. Wrapping is estimated from the
+    // line widths, which is exact for the whitespace-free overflow and close
+    // enough for word-wrapped text.
+    let inner_width = area.width.saturating_sub(2).max(1) as usize;
+    let rows: usize = lines.iter().map(|l| l.width().div_ceil(inner_width).max(1)).sum();
+    let visible = area.height.saturating_sub(2) as usize;
+    let offset = rows.saturating_sub(visible).min(u16::MAX as usize) as u16;
+
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((offset, 0));
+    frame.render_widget(paragraph, area);
+}
+
+/// The local calendar day of `timestamp`, if it's representable.
+fn local_date(timestamp: Timestamp) -> Option<NaiveDate> {
+    Local.timestamp_millis_opt(timestamp.as_millis()).single().map(|dt| dt.date_naive())
+}
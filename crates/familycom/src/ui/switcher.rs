@@ -0,0 +1,83 @@
+//! Quick-switcher popup (Ctrl+K).
+//!
+//! Drawn over the middle of the chat: what's been typed, and below it the
+//! peers whose names match it, best first. Enter opens the highlighted one,
+//! or sends it the message being forwarded.
+//!
+//! ```text
+//!      +-- Ir a (Enter abrir, Esc cerrar) ---+
+//!      | > mam                               |
+//!      | >> Habitación de Mamá (2)           |
+//!      |    Móvil de Mamá                    |
+//!      +-------------------------------------+
+//! ```
+
+use crate::app::TuiApp;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+/// Most matches listed at once.
+const MAX_ROWS: usize = 8;
+
+/// Renders the quick-switcher centered in `area`.
+pub fn render(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let Some(switcher) = &app.switcher else {
+        return;
+    };
+    let matches = app.switcher_matches();
+
+    let mut lines = vec![Line::from(format!("> {}", switcher.query))];
+    if matches.is_empty() {
+        lines.push(Line::from(Span::styled("  Ningun peer coincide", Style::default().fg(Color::DarkGray))));
+    }
+    // Scrolled so the highlighted match stays in view
+    let first = switcher.selected.saturating_sub(MAX_ROWS - 1);
+    for (pos, &idx) in matches.iter().enumerate().skip(first).take(MAX_ROWS) {
+        let peer = &app.peers[idx];
+        let selected = pos == switcher.selected;
+        let style = if selected {
+            Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD)
+        } else if peer.online {
+            Style::default().fg(Color::White)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        let mut spans = vec![Span::styled(
+            format!("{}{}", if selected { ">> " } else { "   " }, peer.display_name),
+            style,
+        )];
+        if let Some(unread) = app.conversations.get(&peer.id).map(|c| c.unread_count).filter(|&n| n > 0) {
+            spans.push(Span::styled(format!(" ({unread})"), Style::default().fg(Color::Yellow)));
+        }
+        lines.push(Line::from(spans));
+    }
+
+    // Borders, the query and up to MAX_ROWS matches, within the chat
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let width = (area.width * 3 / 5).max(30).min(area.width);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 3,
+        width,
+        height,
+    };
+
+    let title = match switcher.forward {
+        Some(_) => " Reenviar a (Enter enviar, Esc cancelar) ",
+        None => " Ir a (Enter abrir, Esc cerrar) ",
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(Paragraph::new(lines).block(block), popup);
+
+    // The cursor after the query, as in the input box
+    let offset = switcher.query.chars().count().min(u16::MAX as usize) as u16;
+    let cursor_x = popup.x.saturating_add(3).saturating_add(offset).min(popup.right().saturating_sub(2));
+    frame.set_cursor_position((cursor_x, popup.y + 1));
+}
@@ -21,24 +21,163 @@
 //!         discovery_event => update peers, notify TUI clients
 //!         incoming_message => save to DB, notify TUI clients
 //!         ipc_request => handle and respond
+//!         recap_tick => post the weekly recap if a new week has started
+//!         schedule_tick => send scheduled messages that are due
+//!         sweep_tick => ping online peers in the background
+//!         swept => mark the ones that didn't answer offline
+//!         resend => a message resent to a peer that came back was ACKed
+//!         dnd_tick => enter or leave quiet hours
+//!         flood_tick => push chats held back from a flooding peer, as one batch
+//!         storage_tick => low-storage mode: copy history to the archive in the background
+//!         archived => mark what the archive took, trim it here
+//!         email_tick => email messages a peer hasn't picked up in hours
+//!         emailed => remember which ones went out
 //!     }
 //! }
 //! ```
 
 use crate::client;
 use crate::discovery::DiscoveryEvent;
+use crate::email::{self, Email};
+use crate::events::EventBus;
+use crate::idle;
 use crate::ipc_server::IpcRequest;
+use crate::notifications;
+use crate::ratelimit::TokenBucket;
+use crate::send;
 use crate::server::IncomingMessage;
-use familycom_core::config::AppConfig;
-use familycom_core::db::Database;
-use familycom_core::ipc::{ClientRequest, ServerMessage};
+use crate::supervisor::HealthRegistry;
+use crate::tray::{TrayPeer, Unread};
+use familycom_core::commands::{self, Input, SlashCommand};
+use familycom_core::config::{AppConfig, ConfigError};
+use familycom_core::db::{Database, DatabaseError, RecoveryReport};
+use familycom_core::export::{Export, ExportFormat};
+use familycom_core::ipc::{self, ClientRequest, DatabaseRecovery, ErrorCode, ServerMessage, IPC_PROTOCOL_VERSION};
 use familycom_core::protocol::PeerMessage;
-use familycom_core::types::{Direction, Message, MessageContent, MessageId, PeerId, PeerInfo, Timestamp};
-use std::collections::HashMap;
+use familycom_core::recap::{self, WeeklyRecap};
+use familycom_core::types::{
+    AuditAction, AuditEntry, Capability, DeliveryMode, Direction, Message, MessageContent, MessageCursor, MessageId,
+    NotificationPrefs, PageDirection, PeerId, PeerInfo, Timestamp,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use tokio::sync::{broadcast, mpsc};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
+/// How often the main loop checks whether the weekly recap is due.
+const RECAP_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often the main loop sends scheduled messages that are due.
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often peer sightings (`last_seen_at`) are flushed to the database.
+const PRESENCE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often online peers are pinged. A machine that crashes or loses
+/// power never sends an mDNS goodbye, so without this it stays "online"
+/// until its mDNS record expires, which can take over an hour.
+const PRESENCE_SWEEP_INTERVAL: Duration = Duration::from_secs(90);
+
+/// How often the main loop checks whether quiet hours started or ended.
+const QUIET_HOURS_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the desktop's idle time is checked for automatic away. Also
+/// how long it can take to show as available again after using the
+/// desktop (a key in the TUI counts straight away).
+const AWAY_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A peer's chats are pushed to clients (and notified) one by one at up
+/// to `CHAT_RATE` per second, with bursts up to `CHAT_BURST`. Beyond that
+/// (a kid holding down Enter) they're held back and pushed together.
+const CHAT_RATE: f64 = 1.0;
+const CHAT_BURST: u32 = 10;
+
+/// How often chats held back from a flooding peer are pushed, as one
+/// `NewMessages` event per peer.
+const FLOOD_FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often low-storage mode copies new messages to the archive peer and
+/// trims local history.
+const STORAGE_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How often the email fallback looks for messages undelivered for too
+/// long.
+const EMAIL_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Messages per `HistoryPush`. At the 10 000-character message limit this
+/// stays well under the 1 MB frame cap.
+const ARCHIVE_BATCH: u32 = 50;
+
+/// Most `HistoryPush` batches per storage check, so a large backlog (mode
+/// just turned on) is loaded and copied a bit at a time.
+const ARCHIVE_BATCHES_PER_CHECK: u32 = 20;
+
+/// Messages dropped at a time while the database is over `max_db_mb`.
+const PRUNE_BATCH: u32 = 500;
+
+/// Budget for the message text in one `HistoryPage` (archiver role), with
+/// `HISTORY_MESSAGE_OVERHEAD` per message for IDs and framing. Keeps pages
+/// under the 1 MB frame cap whatever `limit` was asked for.
+const HISTORY_PAGE_BYTES: usize = 512 * 1024;
+const HISTORY_MESSAGE_OVERHEAD: usize = 200;
+
+/// Retractions remembered for chats that haven't arrived yet (see
+/// `DaemonApp::early_retractions`); the oldest are forgotten past this.
+const EARLY_RETRACTION_LIMIT: usize = 64;
+
+/// Chat IDs remembered by `DaemonApp::seen_chats`; the oldest are
+/// forgotten past this. Retries come within seconds, so this is plenty.
+const SEEN_CHAT_LIMIT: usize = 1024;
+
+/// Most results returned for a `SearchNotes` request.
+const NOTE_SEARCH_LIMIT: u32 = 100;
+
+/// Most results returned for a `SearchMessages` request, whatever its
+/// `limit` asks for.
+const MESSAGE_SEARCH_LIMIT: u32 = 500;
+
+/// Messages read from the database at a time by `ExportConversation`,
+/// with an `ExportProgress` sent after each batch.
+const EXPORT_BATCH: u32 = 500;
+
+/// DB config key holding the week (e.g. "2026-W07") of the last posted recap.
+const LAST_RECAP_KEY: &str = "last_recap_week";
+
+/// What the daemon does once its main loop has stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExitAction {
+    /// Exit the process (Ctrl+C, the tray's Quit, or `Shutdown`).
+    #[default]
+    Exit,
+    /// Start again with the same arguments (`Restart`).
+    Restart,
+}
+
+/// A peer's chat budget, and the chats it sent over budget since the last
+/// flush. They're already stored; only pushing them to clients waits.
+struct Flood {
+    budget: TokenBucket,
+    held: Vec<Message>,
+}
+
+/// Progress of a background resend (see `DaemonApp::resend_pending`).
+#[derive(Debug)]
+enum Resend {
+    /// The peer ACKed this message.
+    Delivered(MessageId),
+    /// This fire-and-forget message went out; there is no ACK to wait for.
+    Sent(MessageId),
+    /// The resend to this peer is over, whether or not everything got
+    /// through.
+    Finished(PeerId),
+}
+
 /// The main daemon application.
 ///
 /// Holds all shared state and coordinates the subsystems. The `Database`
@@ -51,331 +190,2207 @@ pub struct DaemonApp {
     db: Mutex<Database>,
     /// Our configuration (peer_id, display_name, etc.).
     config: AppConfig,
+    /// Publishes `config` to subsystems that follow changes to it
+    /// (discovery, notifications).
+    config_tx: watch::Sender<AppConfig>,
+    /// The file `ReloadConfig` re-reads; the default location if unset.
+    config_path: Option<PathBuf>,
     /// Currently known online peers (keyed by PeerId).
     /// This is the authoritative source for online status — the DB
     /// stores all known peers, but online status is managed here.
     online_peers: HashMap<PeerId, PeerInfo>,
-    /// Broadcast channel for pushing events to subscribed TUI clients.
-    event_tx: broadcast::Sender<ServerMessage>,
+    /// Peer details as last written to the DB. Survives a peer going
+    /// offline, so a flappy Wi-Fi link re-announcing the same peer doesn't
+    /// rewrite its row each time.
+    persisted_peers: HashMap<PeerId, PeerInfo>,
+    /// Sightings of unchanged peers not yet written, flushed in one
+    /// transaction every `PRESENCE_FLUSH_INTERVAL`.
+    pending_last_seen: HashMap<PeerId, Timestamp>,
+    /// Peers whose undelivered messages are being resent in the background.
+    resending: HashSet<PeerId>,
+    /// Reports from background resends, and their receiving end, which the
+    /// main loop takes when it starts.
+    resend_tx: mpsc::UnboundedSender<Resend>,
+    resend_rx: Option<mpsc::UnboundedReceiver<Resend>>,
+    /// Per-peer chat budgets, and the chats held back from peers over
+    /// theirs (see `CHAT_RATE`).
+    floods: HashMap<PeerId, Flood>,
+    /// Retractions that arrived before the chat they take back. Each
+    /// message travels on its own connection, so a `Retract` sent right
+    /// after its `Chat` can overtake it; the chat is dropped when it lands.
+    early_retractions: VecDeque<(MessageId, PeerId)>,
+    /// IDs of the chats received lately, stored or not. A sender retries
+    /// a chat whose ACK it lost; this catches the retry even when the
+    /// first copy is no longer in the database (retracted or deleted).
+    seen_chats: VecDeque<MessageId>,
+    /// Per-peer notification settings (peers without an entry use the
+    /// defaults), published to the notification handler.
+    notification_prefs_tx: watch::Sender<HashMap<PeerId, NotificationPrefs>>,
+    /// How each known peer is named in notifications, kept in step with
+    /// the peer table and published to the notification handler.
+    peer_names_tx: watch::Sender<HashMap<PeerId, String>>,
+    /// Whether we're in do-not-disturb, published to the notification
+    /// handler and to discovery (which advertises it).
+    do_not_disturb_tx: watch::Sender<bool>,
+    /// Do-not-disturb set by hand with `SetDoNotDisturb`; `None` follows
+    /// `quiet_hours`.
+    do_not_disturb_override: Option<bool>,
+    /// Whether nobody is at the keyboard (`[away]`), published to
+    /// discovery (which advertises it).
+    away_tx: watch::Sender<bool>,
+    /// Clients showing a conversation in a focused window (`SetViewing`),
+    /// published to the notification handler.
+    viewers_tx: watch::Sender<Vec<notifications::Viewer>>,
+    /// When a TUI last reported a key press (`ReportActivity`). `None`
+    /// until one does.
+    last_input: Option<Instant>,
+    /// Unread received messages, published to the tray icon.
+    unread_tx: watch::Sender<Unread>,
+    /// `online_peers` by name, published to the tray menu.
+    tray_peers_tx: watch::Sender<Vec<TrayPeer>>,
+    /// Numbered stream of the events pushed to subscribed TUI clients.
+    events: EventBus,
+    /// Health of supervised subsystems, reported via `GetStatus`.
+    health: HealthRegistry,
+    /// Set if the database had to be rebuilt at startup, reported via `GetStatus`.
+    db_recovery: Option<DatabaseRecovery>,
+    /// Set by a `Shutdown` or `Restart` request; the main loop then
+    /// cancels the shutdown token.
+    exit_request: Option<ExitAction>,
 }
 
 impl DaemonApp {
     /// Creates a new daemon app with the given database and config.
     pub fn new(db: Database, config: AppConfig) -> Self {
-        // Broadcast channel with a buffer of 256 events.
-        // If a TUI client falls behind by more than 256 events,
-        // it will receive a Lagged error and miss some events.
-        let (event_tx, _) = broadcast::channel(256);
+        let (config_tx, _) = watch::channel(config.clone());
+        let notification_prefs = db.peer_notifications().unwrap_or_else(|e| {
+            error!(error = %e, "failed to load peer notification settings");
+            HashMap::new()
+        });
+        let (notification_prefs_tx, _) = watch::channel(notification_prefs);
+        let peer_names = db
+            .get_peers()
+            .unwrap_or_else(|e| {
+                error!(error = %e, "failed to load peers");
+                Vec::new()
+            })
+            .iter()
+            .map(|peer| (peer.id.clone(), notifications::sender_label(peer)))
+            .collect();
+        let unread = count_unread(&db, &peer_names).unwrap_or_else(|e| {
+            error!(error = %e, "failed to count unread messages");
+            Unread::default()
+        });
+        let (unread_tx, _) = watch::channel(unread);
+        let (tray_peers_tx, _) = watch::channel(Vec::new());
+        let (peer_names_tx, _) = watch::channel(peer_names);
+        let (resend_tx, resend_rx) = mpsc::unbounded_channel();
+        let (do_not_disturb_tx, _) = watch::channel(in_quiet_hours(&config));
+        let (away_tx, _) = watch::channel(false);
+        let (viewers_tx, _) = watch::channel(Vec::new());
+        let events = EventBus::new();
 
         Self {
             db: Mutex::new(db),
             config,
+            config_tx,
+            config_path: None,
             online_peers: HashMap::new(),
-            event_tx,
+            persisted_peers: HashMap::new(),
+            pending_last_seen: HashMap::new(),
+            resending: HashSet::new(),
+            resend_tx,
+            resend_rx: Some(resend_rx),
+            floods: HashMap::new(),
+            early_retractions: VecDeque::new(),
+            seen_chats: VecDeque::new(),
+            notification_prefs_tx,
+            peer_names_tx,
+            do_not_disturb_tx,
+            do_not_disturb_override: None,
+            away_tx,
+            viewers_tx,
+            last_input: None,
+            unread_tx,
+            tray_peers_tx,
+            health: HealthRegistry::with_events(events.clone()),
+            events,
+            db_recovery: None,
+            exit_request: None,
         }
     }
 
-    /// Returns a clone of the broadcast sender (for the IPC server to use).
-    pub fn event_sender(&self) -> broadcast::Sender<ServerMessage> {
-        self.event_tx.clone()
+    /// Records that the database was rebuilt from a corrupt file at startup,
+    /// so TUI clients can tell the user via `GetStatus`.
+    pub fn set_db_recovery(&mut self, report: RecoveryReport) {
+        self.db_recovery = Some(DatabaseRecovery {
+            corrupt_path: report.corrupt_path.display().to_string(),
+            reason: report.reason,
+            salvaged_rows: report.salvaged_rows,
+        });
+    }
+
+    /// Sets the config file the daemon was started with (`--config`), for
+    /// `ReloadConfig` and for saving settings changed over IPC.
+    pub fn set_config_path(&mut self, path: PathBuf) {
+        self.config_path = Some(path);
+    }
+
+    /// Returns a receiver that sees every config change (for subsystems
+    /// that apply settings while running).
+    pub fn config_watch(&self) -> watch::Receiver<AppConfig> {
+        self.config_tx.subscribe()
+    }
+
+    /// Returns a receiver that sees every change to the per-peer
+    /// notification settings (for the notification handler).
+    pub fn notification_prefs_watch(&self) -> watch::Receiver<HashMap<PeerId, NotificationPrefs>> {
+        self.notification_prefs_tx.subscribe()
+    }
+
+    /// Returns a receiver that sees every change to how peers are named in
+    /// notifications (for the notification handler).
+    pub fn peer_names_watch(&self) -> watch::Receiver<HashMap<PeerId, String>> {
+        self.peer_names_tx.subscribe()
+    }
+
+    /// Returns a receiver that sees do-not-disturb turn on and off (for the
+    /// notification handler and discovery).
+    pub fn do_not_disturb_watch(&self) -> watch::Receiver<bool> {
+        self.do_not_disturb_tx.subscribe()
+    }
+
+    /// Returns a receiver that sees automatic away start and end (for
+    /// discovery).
+    pub fn away_watch(&self) -> watch::Receiver<bool> {
+        self.away_tx.subscribe()
+    }
+
+    /// Returns a receiver that sees which conversations are open in a
+    /// focused client (for the notification handler).
+    pub fn viewers_watch(&self) -> watch::Receiver<Vec<notifications::Viewer>> {
+        self.viewers_tx.subscribe()
+    }
+
+    /// Returns a receiver that sees the unread count and senders change
+    /// (for the tray icon).
+    pub fn unread_watch(&self) -> watch::Receiver<Unread> {
+        self.unread_tx.subscribe()
+    }
+
+    /// Returns a receiver that sees peers come online and go offline (for
+    /// the tray menu).
+    pub fn tray_peers_watch(&self) -> watch::Receiver<Vec<TrayPeer>> {
+        self.tray_peers_tx.subscribe()
+    }
+
+    /// Returns a handle to the event stream (for the IPC server and the
+    /// notification handler to subscribe to).
+    pub fn event_bus(&self) -> EventBus {
+        self.events.clone()
+    }
+
+    /// Returns a handle to the subsystem health registry (for the supervisor to update).
+    pub fn health_registry(&self) -> HealthRegistry {
+        self.health.clone()
     }
 
     /// Runs the main event loop.
     ///
     /// This is the daemon's core — it processes events from all subsystems
-    /// until a shutdown signal is received.
+    /// until shutdown. Once `shutdown` is cancelled the TCP and IPC servers
+    /// start draining; the loop keeps handling what they forward until both
+    /// channels close (every sender gone), so a message a peer has already
+    /// been ACKed for is still saved.
     ///
     /// # Arguments
     ///
     /// * `discovery_rx` - Channel receiving mDNS discovery events
     /// * `message_rx` - Channel receiving incoming TCP messages
     /// * `ipc_rx` - Channel receiving IPC requests from TUI clients
-    /// * `shutdown_rx` - Signal to stop the daemon
+    /// * `shutdown` - Cancelled to stop the daemon
+    ///
+    /// Returns what to do next: exit, or restart if a client asked for it.
     pub async fn run(
         &mut self,
         mut discovery_rx: mpsc::Receiver<DiscoveryEvent>,
         mut message_rx: mpsc::Receiver<IncomingMessage>,
         mut ipc_rx: mpsc::Receiver<IpcRequest>,
-        mut shutdown_rx: mpsc::Receiver<()>,
-    ) {
+        shutdown: CancellationToken,
+    ) -> ExitAction {
         info!(
             peer_id = %self.config.peer_id,
             display_name = %self.config.display_name,
             "daemon main loop started"
         );
+        self.replay_journal();
+        self.check_email_relay();
 
-        loop {
+        let mut messages_open = true;
+        let mut ipc_open = true;
+        let mut draining = false;
+        // First tick fires immediately, so a recap missed while the
+        // machine was off is posted at startup.
+        let mut recap_tick = tokio::time::interval(RECAP_CHECK_INTERVAL);
+        let mut schedule_tick = tokio::time::interval(SCHEDULE_CHECK_INTERVAL);
+        let mut presence_tick = tokio::time::interval(PRESENCE_FLUSH_INTERVAL);
+        let mut flood_tick = tokio::time::interval(FLOOD_FLUSH_INTERVAL);
+        let mut sweep_tick = tokio::time::interval(PRESENCE_SWEEP_INTERVAL);
+        let (swept_tx, mut swept_rx) = mpsc::channel(1);
+        let mut sweeping = false;
+        let mut resend_rx = self.resend_rx.take().expect("the main loop runs once");
+        let mut dnd_tick = tokio::time::interval(QUIET_HOURS_CHECK_INTERVAL);
+        let mut away_tick = tokio::time::interval(AWAY_CHECK_INTERVAL);
+        let (idle_tx, mut idle_rx) = mpsc::channel(1);
+        let mut probing_idle = false;
+        let mut storage_tick = tokio::time::interval(STORAGE_CHECK_INTERVAL);
+        let (archived_tx, mut archived_rx) = mpsc::channel(1);
+        let mut archiving = false;
+        let mut email_tick = tokio::time::interval(EMAIL_CHECK_INTERVAL);
+        let (emailed_tx, mut emailed_rx) = mpsc::channel(1);
+        let mut emailing = false;
+
+        while messages_open || ipc_open {
             tokio::select! {
                 // Handle mDNS discovery events
-                Some(event) = discovery_rx.recv() => {
+                Some(event) = discovery_rx.recv(), if !draining => {
                     self.handle_discovery_event(event);
                 }
 
                 // Handle incoming TCP messages from peers
-                Some(incoming) = message_rx.recv() => {
-                    self.handle_incoming_message(incoming);
-                }
+                incoming = message_rx.recv(), if messages_open => match incoming {
+                    Some(incoming) => self.handle_incoming_message(incoming),
+                    None => messages_open = false,
+                },
 
                 // Handle IPC requests from TUI clients
-                Some(ipc_req) = ipc_rx.recv() => {
-                    self.handle_ipc_request(ipc_req).await;
-                }
+                ipc_req = ipc_rx.recv(), if ipc_open => match ipc_req {
+                    Some(ipc_req) => {
+                        self.handle_ipc_request(ipc_req).await;
+                        // The response is already queued for the client,
+                        // which writes it out before closing
+                        if self.exit_request.is_some() && !shutdown.is_cancelled() {
+                            info!(action = ?self.exit_request, "stop requested over IPC");
+                            shutdown.cancel();
+                        }
+                    }
+                    None => ipc_open = false,
+                },
 
-                // Shutdown signal
-                _ = shutdown_rx.recv() => {
-                    info!("shutdown signal received, stopping daemon");
-                    break;
+                // Periodic check for the weekly recap
+                _ = recap_tick.tick(), if !draining => {
+                    self.post_weekly_recaps_if_due();
                 }
-            }
-        }
-    }
 
-    /// Processes an mDNS discovery event (peer found or lost).
-    fn handle_discovery_event(&mut self, event: DiscoveryEvent) {
-        match event {
-            DiscoveryEvent::PeerFound(peer_info) => {
-                info!(
-                    peer_id = %peer_info.id,
-                    name = %peer_info.display_name,
-                    addresses = ?peer_info.addresses,
-                    "peer came online"
-                );
+                // Scheduled messages whose time has come
+                _ = schedule_tick.tick(), if !draining => {
+                    self.dispatch_scheduled().await;
+                }
 
-                // Update our in-memory peer list
-                self.online_peers
-                    .insert(peer_info.id.clone(), peer_info.clone());
+                // Periodic write of batched peer sightings
+                _ = presence_tick.tick(), if !draining => {
+                    self.flush_last_seen();
+                }
 
-                // Persist to database
-                if let Ok(db) = self.db.lock() {
-                    if let Err(e) = db.upsert_peer(&peer_info) {
-                        error!(error = %e, "failed to save peer to database");
-                    }
+                // Ping online peers, one sweep at a time
+                _ = sweep_tick.tick(), if !draining && !sweeping => {
+                    sweeping = self.start_presence_sweep(swept_tx.clone());
                 }
 
-                // Notify subscribed TUI clients
-                let _ = self.event_tx.send(ServerMessage::PeerOnline {
-                    peer: peer_info,
-                });
-            }
+                // Peers that didn't answer the sweep
+                Some(unreachable) = swept_rx.recv() => {
+                    sweeping = false;
+                    self.demote_unreachable(unreachable);
+                }
 
-            DiscoveryEvent::PeerLost(peer_id) => {
-                // The discovery module now maps mDNS fullnames to UUID-based
-                // PeerIds, so we can look up directly by key.
-                if self.online_peers.remove(&peer_id).is_some() {
-                    info!(peer_id = %peer_id, "peer went offline");
-                    let _ = self.event_tx.send(ServerMessage::PeerOffline {
-                        peer_id,
-                    });
-                } else {
-                    debug!(peer_id = %peer_id, "received PeerLost for unknown peer");
+                // A message resent to a peer that came back got through
+                Some(resend) = resend_rx.recv() => {
+                    self.handle_resend(resend);
                 }
-            }
-        }
-    }
 
-    /// Processes an incoming message received over TCP from a peer.
-    fn handle_incoming_message(&mut self, incoming: IncomingMessage) {
-        match incoming.message {
-            PeerMessage::Chat {
-                id,
-                sender_id,
-                sender_name,
-                content,
-                timestamp,
-            } => {
-                info!(
-                    message_id = %id,
-                    from = %sender_name,
-                    "received chat message"
-                );
+                // Quiet hours starting or ending
+                _ = dnd_tick.tick(), if !draining => {
+                    self.update_do_not_disturb();
+                }
 
-                // Build the message struct
-                let message = Message {
-                    id: id.clone(),
-                    peer_id: sender_id.clone(),
-                    direction: Direction::Received,
-                    content: content.clone(),
-                    timestamp,
-                    delivered: true, // We already sent an ACK in the TCP handler
-                };
+                // Automatic away: ask the desktop how long it's been idle
+                _ = away_tick.tick(), if !draining && !probing_idle => {
+                    probing_idle = self.start_idle_probe(idle_tx.clone());
+                }
 
-                // Save to database
-                if let Ok(db) = self.db.lock() {
-                    // Ensure the peer exists in our DB
-                    // (they should from mDNS, but just in case)
-                    let peer_exists = db.get_peers().ok()
-                        .map(|peers| peers.iter().any(|p| p.id == sender_id))
-                        .unwrap_or(false);
+                Some(system_idle) = idle_rx.recv() => {
+                    probing_idle = false;
+                    self.update_away(system_idle);
+                }
 
-                    if !peer_exists {
-                        let peer_info = PeerInfo {
-                            id: sender_id.clone(),
-                            display_name: sender_name.clone(),
-                            addresses: vec![incoming.from_addr.to_string()],
-                            last_seen_at: Timestamp::now(),
-                            online: true,
-                        };
-                        if let Err(e) = db.upsert_peer(&peer_info) {
-                            error!(error = %e, "failed to save peer");
-                        }
-                    }
+                // Push chats held back from flooding peers
+                _ = flood_tick.tick(), if !draining => {
+                    self.flush_floods();
+                }
 
-                    if let Err(e) = db.save_message(&message) {
-                        error!(error = %e, "failed to save message to database");
+                // Low-storage mode: copy history to the archive peer, one
+                // copy at a time, and trim what it already has
+                _ = storage_tick.tick(), if !draining && !archiving => {
+                    archiving = self.start_archive_push(archived_tx.clone());
+                    if !archiving {
+                        self.trim_storage();
                     }
                 }
 
-                // Notify subscribed TUI clients about the new message
-                let _ = self.event_tx.send(ServerMessage::NewMessage { message });
-            }
+                // The messages the archive ACKed
+                Some(archived) = archived_rx.recv() => {
+                    archiving = false;
+                    self.record_archived(archived);
+                    self.trim_storage();
+                }
 
-            PeerMessage::Ack { message_id } => {
-                debug!(message_id = %message_id, "received delivery ACK");
+                // Email fallback: one batch of emails at a time
+                _ = email_tick.tick(), if !draining && !emailing => {
+                    emailing = self.start_email_fallback(emailed_tx.clone());
+                }
 
-                // Mark the message as delivered in our database
-                if let Ok(db) = self.db.lock() {
-                    if let Err(e) = db.mark_delivered(&message_id) {
-                        error!(error = %e, "failed to mark message as delivered");
-                    }
+                // The messages that were emailed
+                Some(emailed) = emailed_rx.recv() => {
+                    emailing = false;
+                    self.record_emailed(emailed);
                 }
 
-                // Notify TUI clients
-                let _ = self.event_tx.send(ServerMessage::MessageDelivered { message_id });
+                // Shutdown signal
+                _ = shutdown.cancelled(), if !draining => {
+                    info!("shutdown signal received, draining connections");
+                    draining = true;
+                }
             }
-
-            // Ping/Pong are handled at the TCP connection level, not here
-            PeerMessage::Ping | PeerMessage::Pong => {}
         }
+        self.flush_last_seen();
+        self.flush_floods();
+        info!("daemon main loop stopped");
+        self.exit_request.unwrap_or_default()
     }
 
-    /// Processes an IPC request from a TUI client.
-    async fn handle_ipc_request(&mut self, ipc_req: IpcRequest) {
-        let IpcRequest {
-            request,
-            response_tx,
-        } = ipc_req;
-
-        let response = match request {
-            ClientRequest::ListPeers => self.handle_list_peers(),
-
-            ClientRequest::GetMessages {
-                peer_id,
-                limit,
-                before,
-            } => self.handle_get_messages(&peer_id, limit, before),
-
-            ClientRequest::SendMessage { peer_id, content } => {
-                self.handle_send_message(&peer_id, &content).await
+    /// Writes the batched `last_seen_at` updates, if any.
+    fn flush_last_seen(&mut self) {
+        if self.pending_last_seen.is_empty() {
+            return;
+        }
+        let seen: Vec<(PeerId, Timestamp)> = self.pending_last_seen.drain().collect();
+        if let Ok(db) = self.db.lock() {
+            match db.touch_peers(&seen) {
+                Ok(updated) => debug!(peers = updated, "flushed peer sightings"),
+                Err(e) => error!(error = %e, "failed to update peer last_seen_at"),
             }
+        }
+    }
 
-            ClientRequest::GetConfig => self.handle_get_config(),
-
-            ClientRequest::SetDisplayName { name } => self.handle_set_display_name(&name),
+    /// Pings every online peer in the background. The ones that don't
+    /// answer, with the `last_seen_at` they had, are sent on `swept_tx`.
+    /// Returns whether a sweep was started.
+    fn start_presence_sweep(&self, swept_tx: mpsc::Sender<Vec<(PeerId, Timestamp)>>) -> bool {
+        let peers: Vec<PeerInfo> = self.online_peers.values().cloned().collect();
+        if peers.is_empty() {
+            return false;
+        }
+        debug!(peers = peers.len(), "pinging online peers");
+        tokio::spawn(async move {
+            let mut pings = tokio::task::JoinSet::new();
+            for peer in peers {
+                pings.spawn(async move {
+                    let answered = client::ping_any(&peer.addresses).await.is_ok();
+                    (peer, answered)
+                });
+            }
+            let mut unreachable = Vec::new();
+            while let Some(ping) = pings.join_next().await {
+                if let Ok((peer, false)) = ping {
+                    unreachable.push((peer.id, peer.last_seen_at));
+                }
+            }
+            let _ = swept_tx.send(unreachable).await;
+        });
+        true
+    }
 
-            // Subscribe is handled in the IPC server itself
-            ClientRequest::Subscribe => ServerMessage::Ok,
+    /// Emails, in the background, the messages undelivered for longer than
+    /// `[email] after_hours` to the peers that have an address. Returns
+    /// whether anything is being emailed; the IDs of what went out come
+    /// back on `emailed_tx`.
+    fn start_email_fallback(&self, emailed_tx: mpsc::Sender<Vec<MessageId>>) -> bool {
+        let settings = &self.config.email;
+        let Some(relay) = settings.smtp_relay.clone() else {
+            return false;
         };
+        if !settings.relay_allowed() {
+            return false;
+        }
+        let after = i64::from(settings.after_hours) * 60 * 60 * 1000;
+        let sent_before = Timestamp::from_millis(Timestamp::now().as_millis() - after);
 
-        if response_tx.send(response).await.is_err() {
-            debug!("IPC client disconnected before receiving response");
+        // One email per peer, with all its overdue messages
+        let mut emails: Vec<(Email, Vec<MessageId>)> = Vec::new();
+        {
+            let Ok(db) = self.db.lock() else {
+                return false;
+            };
+            let overdue = match db.get_overdue_undelivered(sent_before) {
+                Ok(overdue) => overdue,
+                Err(e) => {
+                    error!(error = %e, "failed to look up undelivered messages");
+                    return false;
+                }
+            };
+            let mut by_peer: Vec<(PeerId, Vec<Message>)> = Vec::new();
+            for message in overdue {
+                match by_peer.iter_mut().find(|(peer_id, _)| *peer_id == message.peer_id) {
+                    Some((_, messages)) => messages.push(message),
+                    None => by_peer.push((message.peer_id.clone(), vec![message])),
+                }
+            }
+            for (peer_id, messages) in by_peer {
+                let name = db.peer_display_name(&peer_id).ok().flatten().unwrap_or_default();
+                let Some(address) = settings.address_for(&peer_id, &name) else {
+                    continue;
+                };
+                let email = Email::compose(&settings.from, address, &self.config.display_name, &messages);
+                emails.push((email, messages.into_iter().map(|m| m.id).collect()));
+            }
+        }
+        if emails.is_empty() {
+            return false;
         }
-    }
 
-    /// Handles ListPeers: returns all known peers with their online status.
-    fn handle_list_peers(&self) -> ServerMessage {
-        match self.db.lock() {
-            Ok(db) => match db.get_peers() {
-                Ok(mut peers) => {
-                    // Update online status from our in-memory state
-                    for peer in &mut peers {
-                        peer.online = self.online_peers.contains_key(&peer.id);
+        tokio::spawn(async move {
+            let mut emailed = Vec::new();
+            for (email, ids) in emails {
+                match tokio::time::timeout(email::SEND_TIMEOUT, email::send(&relay, &email)).await {
+                    Ok(Ok(())) => {
+                        info!(to = %email.to, messages = ids.len(), "emailed undelivered messages");
+                        emailed.extend(ids);
                     }
-                    ServerMessage::PeerList { peers }
+                    Ok(Err(e)) => warn!(to = %email.to, error = %e, "failed to email undelivered messages"),
+                    Err(_) => warn!(to = %email.to, %relay, "SMTP relay did not answer in time"),
                 }
-                Err(e) => ServerMessage::Error {
-                    code: "db_error".to_string(),
-                    message: format!("failed to fetch peers: {e}"),
-                },
-            },
-            Err(e) => ServerMessage::Error {
-                code: "internal_error".to_string(),
-                message: format!("database lock poisoned: {e}"),
-            },
-        }
+            }
+            let _ = emailed_tx.send(emailed).await;
+        });
+        true
     }
 
-    /// Handles GetMessages: returns message history with a peer.
-    fn handle_get_messages(
-        &self,
-        peer_id: &PeerId,
-        limit: u32,
-        before: Option<Timestamp>,
-    ) -> ServerMessage {
-        match self.db.lock() {
-            Ok(db) => match db.get_messages(peer_id, limit, before) {
-                Ok(messages) => ServerMessage::Messages { messages },
-                Err(e) => ServerMessage::Error {
-                    code: "db_error".to_string(),
-                    message: format!("failed to fetch messages: {e}"),
-                },
-            },
-            Err(e) => ServerMessage::Error {
-                code: "internal_error".to_string(),
-                message: format!("database lock poisoned: {e}"),
-            },
+    /// Logs why the email fallback stays off when `[email]` names a relay
+    /// it may not use (see `EmailConfig::relay_allowed`).
+    fn check_email_relay(&self) {
+        let settings = &self.config.email;
+        if settings.enabled() && !settings.relay_allowed() {
+            error!(
+                relay = settings.smtp_relay.as_deref().unwrap_or_default(),
+                "email fallback off: the SMTP relay isn't on this machine and emails would cross the network \
+                 unencrypted; set `allow_remote_relay = true` in [email] to use it anyway"
+            );
         }
     }
 
-    /// Handles SendMessage: saves the message locally and sends it to the peer via TCP.
-    async fn handle_send_message(&mut self, peer_id: &PeerId, content: &str) -> ServerMessage {
-        // Validate the message content
-        if let Err(e) = MessageContent::new(content) {
-            return ServerMessage::Error {
-                code: "invalid_content".to_string(),
-                message: e.to_string(),
-            };
+    /// Records which overdue messages were emailed, so they aren't again.
+    fn record_emailed(&self, emailed: Vec<MessageId>) {
+        if emailed.is_empty() {
+            return;
+        }
+        if let Ok(db) = self.db.lock() {
+            if let Err(e) = db.mark_emailed(&emailed) {
+                error!(error = %e, "failed to record emailed messages");
+            }
         }
+    }
 
-        // Find the peer's addresses
-        let peer_info = self.online_peers.get(peer_id).cloned();
-        let addresses = match &peer_info {
-            Some(info) => info.addresses.clone(),
-            None => {
-                // Peer might be offline — try to get their last known addresses from DB
-                match self.db.lock() {
-                    Ok(db) => match db.get_peers() {
-                        Ok(peers) => peers
-                            .into_iter()
-                            .find(|p| p.id == *peer_id)
-                            .map(|p| p.addresses)
-                            .unwrap_or_default(),
-                        Err(_) => vec![],
-                    },
-                    Err(_) => vec![],
-                }
+    /// Marks peers that didn't answer a presence sweep offline, unless mDNS
+    /// saw them again while the sweep ran.
+    fn demote_unreachable(&mut self, unreachable: Vec<(PeerId, Timestamp)>) {
+        for (peer_id, seen_at) in unreachable {
+            let stale = self
+                .online_peers
+                .get(&peer_id)
+                .is_some_and(|peer| peer.last_seen_at == seen_at);
+            if !stale {
+                continue;
             }
-        };
+            self.online_peers.remove(&peer_id);
+            info!(peer_id = %peer_id, "peer stopped answering pings, marking it offline");
+            let _ = self.events.send(ServerMessage::PeerOffline { peer_id });
+        }
+        self.publish_tray_peers();
+    }
 
-        if addresses.is_empty() {
-            return ServerMessage::Error {
-                code: "peer_not_found".to_string(),
-                message: format!("no known addresses for peer {peer_id}"),
-            };
+    /// Re-evaluates do-not-disturb (the manual setting, else quiet hours)
+    /// and publishes it if it changed.
+    fn update_do_not_disturb(&mut self) {
+        let active = self
+            .do_not_disturb_override
+            .unwrap_or_else(|| in_quiet_hours(&self.config));
+        let changed = self.do_not_disturb_tx.send_if_modified(|current| {
+            std::mem::replace(current, active) != active
+        });
+        if changed {
+            info!(active, manual = ?self.do_not_disturb_override, "do not disturb changed");
         }
+    }
 
-        // Create the message
-        let message_id = MessageId::generate();
-        let timestamp = Timestamp::now();
+    /// Reads the desktop's idle time in the background and sends it on
+    /// `idle_tx`. Returns whether a probe was started: not with automatic
+    /// away off, which also ends any away status.
+    fn start_idle_probe(&mut self, idle_tx: mpsc::Sender<Option<Duration>>) -> bool {
+        if self.config.away.idle_timeout().is_none() {
+            self.update_away(None);
+            return false;
+        }
+        tokio::spawn(async move {
+            let system_idle = tokio::task::spawn_blocking(idle::system_idle).await.unwrap_or(None);
+            let _ = idle_tx.send(system_idle).await;
+        });
+        true
+    }
 
-        let peer_message = PeerMessage::Chat {
-            id: message_id.clone(),
-            sender_id: PeerId::new(&self.config.peer_id),
-            sender_name: self.config.display_name.clone(),
-            content: content.to_string(),
-            timestamp,
+    /// Re-evaluates automatic away from the desktop's idle time, when it
+    /// could be read, and the last key pressed in a TUI, and publishes it
+    /// if it changed. Knowing neither, we're not away.
+    fn update_away(&mut self, system_idle: Option<Duration>) {
+        let tui_idle = self.last_input.map(|at| at.elapsed());
+        let idle = match (system_idle, tui_idle) {
+            (Some(system_idle), Some(tui_idle)) => Some(system_idle.min(tui_idle)),
+            (system_idle, tui_idle) => system_idle.or(tui_idle),
         };
-
-        // Save to our local database first
+        let away = match (idle, self.config.away.idle_timeout()) {
+            (Some(idle), Some(timeout)) => idle >= timeout,
+            _ => false,
+        };
+        let changed = self.away_tx.send_if_modified(|current| std::mem::replace(current, away) != away);
+        if changed {
+            info!(away, idle_secs = ?idle.map(|idle| idle.as_secs()), "away status changed");
+        }
+    }
+
+    /// Handles ReportActivity: someone is at the keyboard, so we're not
+    /// away (any more).
+    fn handle_report_activity(&mut self) -> ServerMessage {
+        self.last_input = Some(Instant::now());
+        self.update_away(None);
+        ServerMessage::Ok
+    }
+
+    /// Handles SetViewing: records what `client` shows, replacing what it
+    /// said before. Clients that have disconnected are dropped on the way.
+    fn handle_set_viewing(
+        &mut self,
+        client: &mpsc::Sender<ServerMessage>,
+        peer_id: Option<PeerId>,
+        focused: bool,
+    ) -> ServerMessage {
+        self.viewers_tx.send_modify(|viewers| {
+            viewers.retain(|v| !v.client.same_channel(client) && !v.client.is_closed());
+            if let Some(peer_id) = peer_id.filter(|_| focused) {
+                viewers.push(notifications::Viewer {
+                    peer_id,
+                    client: client.clone(),
+                });
+            }
+        });
+        ServerMessage::Ok
+    }
+
+    /// Handles ReportTyping: tells the peer we're writing to them, if it's
+    /// online and understands it. Best effort, so always `Ok`: a lost
+    /// notice only means the indicator doesn't show.
+    fn handle_report_typing(&self, peer_id: &PeerId) -> ServerMessage {
+        let Some(peer) = self.find_peer_info(peer_id) else {
+            return ServerMessage::Ok;
+        };
+        if !peer.online || !peer.supports(Capability::Typing) || !self.config.kid_mode.allows(peer_id) {
+            return ServerMessage::Ok;
+        }
+        let typing = PeerMessage::Typing {
+            sender_id: PeerId::new(&self.config.peer_id),
+        };
+        let peer_id = peer_id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client::send_to_any(&peer.addresses, &typing, DeliveryMode::FireAndForget).await {
+                debug!(peer_id = %peer_id, error = %e, "failed to send typing notice");
+            }
+        });
+        ServerMessage::Ok
+    }
+
+    /// Handles SetDoNotDisturb: sets or clears the manual override.
+    fn handle_set_do_not_disturb(&mut self, enabled: Option<bool>) -> ServerMessage {
+        self.do_not_disturb_override = enabled;
+        self.update_do_not_disturb();
+        self.do_not_disturb_status()
+    }
+
+    /// The `DoNotDisturb` response.
+    fn do_not_disturb_status(&self) -> ServerMessage {
+        ServerMessage::DoNotDisturb {
+            active: *self.do_not_disturb_tx.borrow(),
+            manual: self.do_not_disturb_override,
+        }
+    }
+
+    /// Pushes a received chat to clients, unless its sender is over its
+    /// chat budget: then it's held back until the next `flush_floods`,
+    /// along with whatever else the peer sends until then.
+    fn push_received(&mut self, message: Message) {
+        let now = tokio::time::Instant::now();
+        let flood = self.floods.entry(message.peer_id.clone()).or_insert_with(|| Flood {
+            budget: TokenBucket::new(CHAT_RATE, CHAT_BURST),
+            held: Vec::new(),
+        });
+        // Once a burst is being held, later chats join it so clients
+        // still get them in order
+        if flood.held.is_empty() && flood.budget.try_take(now) {
+            let _ = self.events.send(ServerMessage::NewMessage { message });
+            return;
+        }
+        if flood.held.is_empty() {
+            info!(peer_id = %message.peer_id, "peer is flooding, batching its messages");
+        }
+        flood.held.push(message);
+    }
+
+    /// Pushes the chats held back from each flooding peer as one
+    /// `NewMessages`, and forgets peers that have gone quiet.
+    fn flush_floods(&mut self) {
+        let now = tokio::time::Instant::now();
+        for (peer_id, flood) in &mut self.floods {
+            if flood.held.is_empty() {
+                continue;
+            }
+            let messages = std::mem::take(&mut flood.held);
+            debug!(peer_id = %peer_id, count = messages.len(), "pushing held-back messages");
+            let _ = self.events.send(ServerMessage::NewMessages {
+                peer_id: peer_id.clone(),
+                messages,
+            });
+        }
+        self.floods.retain(|_, flood| !flood.budget.is_full(now));
+    }
+
+    /// Posts last week's recap into each conversation that had activity,
+    /// unless it was already posted.
+    ///
+    /// Recaps are stored as `Direction::System` messages and pushed to TUI
+    /// clients like any new message.
+    fn post_weekly_recaps_if_due(&mut self) {
+        let (week_start, from, to) = recap::previous_week(&chrono::Local::now());
+        let key = recap::week_key(week_start);
+
+        let posted = {
+            let Ok(db) = self.db.lock() else {
+                return;
+            };
+            match db.get_config(LAST_RECAP_KEY) {
+                Ok(Some(last)) if last == key => return,
+                Ok(_) => {}
+                Err(e) => {
+                    error!(error = %e, "failed to read last recap week");
+                    return;
+                }
+            }
+
+            let (messages, peers) = match (db.get_messages_between(from, to), db.get_peers()) {
+                (Ok(messages), Ok(peers)) => (messages, peers),
+                (Err(e), _) | (_, Err(e)) => {
+                    error!(error = %e, "failed to load data for weekly recap");
+                    return;
+                }
+            };
+
+            let mut by_peer: HashMap<PeerId, Vec<Message>> = HashMap::new();
+            for msg in messages {
+                by_peer.entry(msg.peer_id.clone()).or_default().push(msg);
+            }
+
+            let mut posted = Vec::new();
+            for peer in &peers {
+                let Some(history) = by_peer.get(&peer.id) else {
+                    continue;
+                };
+                let summary = WeeklyRecap::compose(week_start, history, &chrono::Local);
+                if summary.is_empty() {
+                    continue;
+                }
+                let message = Message {
+                    id: MessageId::generate(),
+                    peer_id: peer.id.clone(),
+                    direction: Direction::System,
+                    content: summary.render(&peer.display_name),
+                    timestamp: Timestamp::now(),
+                    delivered: true,
+                    fire_and_forget: false,
+                    announcement: false,
+                    reply_to: None,
+                };
+                match db.save_message(&message) {
+                    Ok(()) => posted.push(message),
+                    Err(e) => error!(peer_id = %peer.id, error = %e, "failed to save weekly recap"),
+                }
+            }
+
+            if let Err(e) = db.set_config(LAST_RECAP_KEY, &key) {
+                error!(error = %e, "failed to remember last recap week");
+            }
+            posted
+        };
+
+        if !posted.is_empty() {
+            info!(week = %key, conversations = posted.len(), "posted weekly recap");
+        }
+        for message in posted {
+            let _ = self.events.send(ServerMessage::NewMessage { message });
+        }
+    }
+
+    /// Low-storage mode: drops or shortens the local copies of messages
+    /// the archive peer already has.
+    fn trim_storage(&self) {
+        let storage = &self.config.storage;
+        if !storage.low_storage() {
+            return;
+        }
+        let Ok(db) = self.db.lock() else {
+            return;
+        };
+        if let Some(days) = storage.keep_days {
+            let cutoff = Timestamp::from_millis(Timestamp::now().as_millis() - i64::from(days) * 24 * 60 * 60 * 1000);
+            match db.prune_archived(Some(cutoff), u32::MAX) {
+                Ok(0) => {}
+                Ok(dropped) => info!(messages = dropped, keep_days = days, "dropped archived history"),
+                Err(e) => error!(error = %e, "failed to drop old archived messages"),
+            }
+        }
+        if let Some(max_bytes) = storage.max_db_bytes() {
+            let mut dropped = 0;
+            loop {
+                match db.used_bytes() {
+                    Ok(used) if used > max_bytes => {}
+                    Ok(_) => break,
+                    Err(e) => {
+                        error!(error = %e, "failed to read database size");
+                        break;
+                    }
+                }
+                match db.prune_archived(None, PRUNE_BATCH) {
+                    Ok(0) => {
+                        warn!("database is over max_db_mb but has no archived messages left to drop");
+                        break;
+                    }
+                    Ok(n) => dropped += n,
+                    Err(e) => {
+                        error!(error = %e, "failed to drop archived messages");
+                        break;
+                    }
+                }
+            }
+            if dropped > 0 {
+                info!(messages = dropped, "dropped archived history to stay under max_db_mb");
+            }
+        }
+        if let Some(max_chars) = storage.truncate_chars {
+            if let Err(e) = db.truncate_archived(max_chars) {
+                error!(error = %e, "failed to shorten archived messages");
+            }
+        }
+    }
+
+    /// Low-storage mode: copies, in the background and in batches,
+    /// messages the archive peer doesn't have yet. Stops at the first batch
+    /// it doesn't ACK. Returns whether a copy was started; the IDs of what
+    /// got through come back on `archived_tx`.
+    fn start_archive_push(&self, archived_tx: mpsc::Sender<Vec<MessageId>>) -> bool {
+        if !self.config.storage.low_storage() {
+            return false;
+        }
+        let Some(archive) = self.archive_peer() else {
+            debug!("archive peer offline, not copying history");
+            return false;
+        };
+        let unarchived = match self.db.lock() {
+            Ok(db) => match db.unarchived_messages(ARCHIVE_BATCH * ARCHIVE_BATCHES_PER_CHECK) {
+                Ok(unarchived) => unarchived,
+                Err(e) => {
+                    error!(error = %e, "failed to load messages to archive");
+                    return false;
+                }
+            },
+            Err(_) => return false,
+        };
+        if unarchived.is_empty() {
+            return false;
+        }
+
+        let owner_id = PeerId::new(&self.config.peer_id);
+        tokio::spawn(async move {
+            let mut archived = Vec::new();
+            for batch in unarchived.chunks(ARCHIVE_BATCH as usize) {
+                let push = PeerMessage::HistoryPush {
+                    batch_id: MessageId::generate(),
+                    owner_id: owner_id.clone(),
+                    messages: batch.to_vec(),
+                };
+                if let Err(e) = client::send_to_any(&archive.addresses, &push, DeliveryMode::AckRequired).await {
+                    warn!(archive = %archive.display_name, error = %e, "could not copy history to archive");
+                    break;
+                }
+                debug!(messages = batch.len(), archive = %archive.display_name, "copied history to archive");
+                archived.extend(batch.iter().map(|m| m.id.clone()));
+            }
+            let _ = archived_tx.send(archived).await;
+        });
+        true
+    }
+
+    /// Records which messages the archive has, so they can be trimmed here
+    /// and aren't copied again.
+    fn record_archived(&self, archived: Vec<MessageId>) {
+        if archived.is_empty() {
+            return;
+        }
+        if let Ok(db) = self.db.lock() {
+            if let Err(e) = db.mark_archived(&archived) {
+                error!(error = %e, "failed to mark messages archived");
+            }
+        }
+    }
+
+    /// The online peer configured as `[storage] archive_peer`, if any.
+    fn archive_peer(&self) -> Option<PeerInfo> {
+        self.online_peers
+            .values()
+            .find(|peer| self.config.storage.is_archive(&peer.id, &peer.display_name))
+            .cloned()
+    }
+
+    /// Resends, in the background and oldest first, the messages `peer`
+    /// never ACKed: it was offline or unreachable when they were sent. Stops
+    /// at the first failure; the rest wait until the peer is seen again.
+    fn resend_pending(&mut self, peer: &PeerInfo) {
+        // Kept, not dropped, in case kid mode is turned off again
+        if self.resending.contains(&peer.id) || !self.config.kid_mode.allows(&peer.id) {
+            return;
+        }
+        let pending = match self.db.lock() {
+            Ok(db) => db.get_undelivered(&peer.id).unwrap_or_else(|e| {
+                error!(error = %e, "failed to look up undelivered messages");
+                Vec::new()
+            }),
+            Err(_) => return,
+        };
+        if pending.is_empty() {
+            return;
+        }
+        info!(peer_id = %peer.id, count = pending.len(), "resending undelivered messages");
+        self.resending.insert(peer.id.clone());
+
+        let peer_id = peer.id.clone();
+        let addresses = peer.addresses.clone();
+        let sender_id = PeerId::new(&self.config.peer_id);
+        let sender_name = self.config.display_name.clone();
+        let resend_tx = self.resend_tx.clone();
+        tokio::spawn(async move {
+            for message in pending {
+                let chat = PeerMessage::Chat {
+                    id: message.id.clone(),
+                    sender_id: sender_id.clone(),
+                    sender_name: sender_name.clone(),
+                    content: message.content,
+                    timestamp: message.timestamp,
+                    announcement: message.announcement,
+                    reply_to: message.reply_to.clone(),
+                };
+                // Only journaled fire-and-forget messages show up here, and
+                // they are resent the way they were meant to go out
+                let mode = if message.fire_and_forget {
+                    DeliveryMode::FireAndForget
+                } else {
+                    DeliveryMode::AckRequired
+                };
+                match client::send_to_any(&addresses, &chat, mode).await {
+                    Ok(()) if message.fire_and_forget => {
+                        let _ = resend_tx.send(Resend::Sent(message.id));
+                    }
+                    Ok(()) => {
+                        let _ = resend_tx.send(Resend::Delivered(message.id));
+                    }
+                    Err(e) => {
+                        warn!(peer_id = %peer_id, error = %e, "resend failed, keeping the rest for later");
+                        break;
+                    }
+                }
+            }
+            let _ = resend_tx.send(Resend::Finished(peer_id));
+        });
+    }
+
+    /// Replays the outbound journal: messages saved as pending whose send
+    /// never finished because the daemon stopped or crashed in between.
+    /// Each peer with any is resent to at its last known addresses, the
+    /// same way as when it comes back online.
+    fn replay_journal(&mut self) {
+        let peer_ids = match self.db.lock() {
+            Ok(db) => db.get_pending_peers().unwrap_or_else(|e| {
+                error!(error = %e, "failed to read the outbound journal");
+                Vec::new()
+            }),
+            Err(_) => return,
+        };
+        for peer_id in peer_ids {
+            match self.find_peer_info(&peer_id) {
+                Some(peer) if !peer.addresses.is_empty() => {
+                    info!(peer_id = %peer_id, "replaying journaled messages");
+                    self.resend_pending(&peer);
+                }
+                _ => warn!(peer_id = %peer_id, "journaled messages for a peer with no known address"),
+            }
+        }
+    }
+
+    /// Records the progress of a background resend.
+    fn handle_resend(&mut self, resend: Resend) {
+        match resend {
+            Resend::Delivered(message_id) => {
+                debug!(message_id = %message_id, "resent message acknowledged");
+                if let Ok(db) = self.db.lock() {
+                    if let Err(e) = db.mark_delivered(&message_id) {
+                        error!(error = %e, "failed to mark message as delivered");
+                    }
+                }
+                let _ = self.events.send(ServerMessage::MessageDelivered { message_id });
+            }
+            Resend::Sent(message_id) => {
+                debug!(message_id = %message_id, "journaled message resent");
+                self.clear_pending(&message_id);
+            }
+            Resend::Finished(peer_id) => {
+                self.resending.remove(&peer_id);
+            }
+        }
+    }
+
+    /// Processes an mDNS discovery event (peer found or lost).
+    fn handle_discovery_event(&mut self, event: DiscoveryEvent) {
+        match event {
+            DiscoveryEvent::PeerFound(mut peer_info) => {
+                peer_info.notifications = self.notification_prefs(&peer_info.id);
+                let previous = self.online_peers.get(&peer_info.id);
+                let was_online = previous.is_some();
+                // Presence, so not stored, but clients still need to hear of it
+                let presence_changed = previous.is_some_and(|p| {
+                    p.do_not_disturb != peer_info.do_not_disturb
+                        || p.away != peer_info.away
+                        || p.interface_addresses != peer_info.interface_addresses
+                });
+                let changed = !self
+                    .persisted_peers
+                    .get(&peer_info.id)
+                    .is_some_and(|known| known.same_details(&peer_info));
+
+                // Update our in-memory peer list
+                self.online_peers
+                    .insert(peer_info.id.clone(), peer_info.clone());
+
+                if changed {
+                    self.persist_peer(&peer_info);
+                } else {
+                    // Same peer re-announced (or back after a Wi-Fi blip):
+                    // only its last_seen_at moved, which is written in batches.
+                    self.pending_last_seen
+                        .insert(peer_info.id.clone(), peer_info.last_seen_at);
+                    if was_online && !presence_changed {
+                        debug!(peer_id = %peer_info.id, "peer re-announced, nothing changed");
+                        return;
+                    }
+                }
+
+                info!(
+                    peer_id = %peer_info.id,
+                    name = %peer_info.display_name,
+                    addresses = ?peer_info.addresses,
+                    "{}",
+                    if was_online { "peer details changed" } else { "peer came online" }
+                );
+
+                if !was_online {
+                    self.resend_pending(&peer_info);
+                }
+                self.publish_tray_peers();
+
+                // Notify subscribed TUI clients
+                let _ = self.events.send(ServerMessage::PeerOnline {
+                    peer: peer_info,
+                });
+            }
+
+            DiscoveryEvent::PeerLost(peer_id) => {
+                // The discovery module now maps mDNS fullnames to UUID-based
+                // PeerIds, so we can look up directly by key.
+                if self.online_peers.remove(&peer_id).is_some() {
+                    info!(peer_id = %peer_id, "peer went offline");
+                    let _ = self.events.send(ServerMessage::PeerOffline {
+                        peer_id,
+                    });
+                    self.publish_tray_peers();
+                } else {
+                    debug!(peer_id = %peer_id, "received PeerLost for unknown peer");
+                }
+            }
+        }
+    }
+
+    /// Writes a new or changed peer to the database, noting renames in the
+    /// audit log first.
+    fn persist_peer(&mut self, peer_info: &PeerInfo) {
+        let Ok(db) = self.db.lock() else {
+            return;
+        };
+        match db.peer_display_name(&peer_info.id) {
+            Ok(Some(old)) if old != peer_info.display_name => {
+                let entry = AuditEntry {
+                    timestamp: Timestamp::now(),
+                    action: AuditAction::PeerRenamed,
+                    peer_id: Some(peer_info.id.clone()),
+                    detail: format!("'{old}' -> '{}'", peer_info.display_name),
+                };
+                if let Err(e) = db.record_audit(&entry) {
+                    error!(error = %e, "failed to record peer rename");
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!(error = %e, "failed to look up previous peer name"),
+        }
+        match db.upsert_peer(peer_info) {
+            Ok(()) => {
+                // The full row includes last_seen_at, so nothing is pending
+                self.pending_last_seen.remove(&peer_info.id);
+                self.persisted_peers
+                    .insert(peer_info.id.clone(), peer_info.clone());
+                self.publish_peer_name(peer_info);
+            }
+            Err(e) => error!(error = %e, "failed to save peer to database"),
+        }
+    }
+
+    /// Recounts unread messages for the tray, after anything that can
+    /// change them: a chat arriving, a deletion, `MarkRead`.
+    fn refresh_unread(&self) {
+        let counted = match self.db.lock() {
+            Ok(db) => count_unread(&db, &self.peer_names_tx.borrow()),
+            Err(_) => return,
+        };
+        match counted {
+            Ok(unread) => {
+                self.unread_tx.send_if_modified(|current| {
+                    let changed = *current != unread;
+                    *current = unread;
+                    changed
+                });
+            }
+            Err(e) => error!(error = %e, "failed to count unread messages"),
+        }
+    }
+
+    /// Lists the online peers in the tray menu, sorted by name.
+    fn publish_tray_peers(&self) {
+        let mut peers: Vec<TrayPeer> = self
+            .online_peers
+            .values()
+            .map(|peer| TrayPeer {
+                id: peer.id.clone(),
+                name: peer.display_name.clone(),
+            })
+            .collect();
+        peers.sort_by_cached_key(|peer| (peer.name.to_lowercase(), peer.id.to_string()));
+        self.tray_peers_tx.send_if_modified(|current| {
+            let changed = *current != peers;
+            *current = peers;
+            changed
+        });
+    }
+
+    /// Updates how `peer` is named in notifications.
+    fn publish_peer_name(&self, peer: &PeerInfo) {
+        let label = notifications::sender_label(peer);
+        self.peer_names_tx.send_if_modified(|names| {
+            names.insert(peer.id.clone(), label.clone()).as_ref() != Some(&label)
+        });
+    }
+
+    /// Whether to take part in history sync for `owner_id` on a connection
+    /// from `from`: only with the archiver role, and only with the owner
+    /// itself (an address it advertises over mDNS), so one family member's
+    /// daemon can't read or overwrite another's history.
+    fn accepts_history_sync(&self, owner_id: &PeerId, from: SocketAddr) -> bool {
+        if !self.config.storage.archiver {
+            debug!(owner_id = %owner_id, "refusing history sync, this daemon is not an archive");
+            return false;
+        }
+        let is_owner = self.online_peers.get(owner_id).is_some_and(|peer| {
+            peer.addresses
+                .iter()
+                .filter_map(|addr| addr.parse::<SocketAddr>().ok())
+                .any(|addr| addr.ip() == from.ip())
+        });
+        if !is_owner {
+            warn!(owner_id = %owner_id, from = %from, "refusing history sync from an address the owner doesn't advertise");
+        }
+        is_owner
+    }
+
+    /// Processes an incoming message received over TCP from a peer.
+    fn handle_incoming_message(&mut self, incoming: IncomingMessage) {
+        match incoming.message {
+            PeerMessage::Chat {
+                id,
+                sender_id,
+                sender_name,
+                content,
+                timestamp,
+                announcement,
+                reply_to,
+            } => {
+                // ACKed like any other, so the sender doesn't keep retrying
+                if !self.kid_mode_allows(&sender_id, &format!("ignored a message from '{sender_name}'")) {
+                    return;
+                }
+                // The TCP handler ACKs every copy, so a retry only needs
+                // to be dropped here: not stored, pushed or notified again
+                if self.is_duplicate_chat(&id) {
+                    debug!(message_id = %id, from = %sender_name, "chat message received twice, ignoring");
+                    return;
+                }
+                if self.seen_chats.len() == SEEN_CHAT_LIMIT {
+                    self.seen_chats.pop_front();
+                }
+                self.seen_chats.push_back(id.clone());
+
+                let retracted = self
+                    .early_retractions
+                    .iter()
+                    .position(|(message_id, retracted_by)| *message_id == id && *retracted_by == sender_id);
+                if let Some(index) = retracted {
+                    self.early_retractions.remove(index);
+                    info!(message_id = %id, from = %sender_name, "dropping chat message retracted before it arrived");
+                    return;
+                }
+
+                info!(
+                    message_id = %id,
+                    from = %sender_name,
+                    "received chat message"
+                );
+
+                // Build the message struct
+                let message = Message {
+                    id: id.clone(),
+                    peer_id: sender_id.clone(),
+                    direction: Direction::Received,
+                    content: content.clone(),
+                    timestamp,
+                    delivered: true, // We already sent an ACK in the TCP handler
+                    fire_and_forget: false,
+                    announcement,
+                    reply_to,
+                };
+
+                // Save to database
+                if let Ok(db) = self.db.lock() {
+                    // Ensure the peer exists in our DB
+                    // (they should from mDNS, but just in case)
+                    let peer_exists = db.get_peers().ok()
+                        .map(|peers| peers.iter().any(|p| p.id == sender_id))
+                        .unwrap_or(false);
+
+                    if !peer_exists {
+                        let peer_info = PeerInfo {
+                            id: sender_id.clone(),
+                            display_name: sender_name.clone(),
+                            addresses: vec![incoming.from_addr.to_string()],
+                            last_seen_at: Timestamp::now(),
+                            online: true,
+                            do_not_disturb: false,
+                            away: false,
+                            avatar: None,
+                            accent_color: None,
+                            capabilities: Vec::new(),
+                            notifications: NotificationPrefs::default(),
+                            interface_addresses: Default::default(),
+                        };
+                        match db.upsert_peer(&peer_info) {
+                            Ok(()) => self.publish_peer_name(&peer_info),
+                            Err(e) => error!(error = %e, "failed to save peer"),
+                        }
+                    }
+
+                    match db.save_message(&message) {
+                        Ok(()) => {}
+                        // Ruled out above, but never push a row the database
+                        // already had: clients have it
+                        Err(e) if e.is_duplicate() => {
+                            debug!(message_id = %message.id, "chat message received twice, ignoring");
+                            return;
+                        }
+                        Err(e) => error!(error = %e, "failed to save message to database"),
+                    }
+                }
+
+                // Notify subscribed TUI clients about the new message
+                self.push_received(message);
+                self.refresh_unread();
+            }
+
+            PeerMessage::Ack { message_id } => {
+                debug!(message_id = %message_id, "received delivery ACK");
+
+                // Mark the message as delivered in our database
+                if let Ok(db) = self.db.lock() {
+                    if let Err(e) = db.mark_delivered(&message_id) {
+                        error!(error = %e, "failed to mark message as delivered");
+                    }
+                }
+
+                // Notify TUI clients
+                let _ = self.events.send(ServerMessage::MessageDelivered { message_id });
+            }
+
+            PeerMessage::Retract { message_id, sender_id } => {
+                let message = match self.db.lock() {
+                    Ok(db) => db.get_message(&message_id).unwrap_or_else(|e| {
+                        error!(error = %e, "failed to look up retracted message");
+                        None
+                    }),
+                    Err(_) => None,
+                };
+                let Some(message) = message else {
+                    debug!(message_id = %message_id, sender = %sender_id, "retraction arrived before its message");
+                    if self.early_retractions.len() == EARLY_RETRACTION_LIMIT {
+                        self.early_retractions.pop_front();
+                    }
+                    self.early_retractions.push_back((message_id, sender_id));
+                    return;
+                };
+                // A peer can only take back what it sent us
+                if message.peer_id != sender_id || message.direction != Direction::Received {
+                    warn!(
+                        message_id = %message_id,
+                        sender = %sender_id,
+                        "ignoring retraction of a message not received from that peer"
+                    );
+                    return;
+                }
+
+                info!(message_id = %message_id, sender = %sender_id, "peer retracted a message");
+                // Clients haven't seen it yet if it was held back from a flood
+                if let Some(flood) = self.floods.get_mut(&sender_id) {
+                    flood.held.retain(|held| held.id != message_id);
+                }
+                self.delete_and_broadcast(&message);
+            }
+
+            PeerMessage::HistoryPush {
+                batch_id,
+                owner_id,
+                messages,
+            } => {
+                if !self.accepts_history_sync(&owner_id, incoming.from_addr) {
+                    return;
+                }
+                let stored = match self.db.lock() {
+                    Ok(db) => db.store_archived(&owner_id, &messages),
+                    Err(_) => return,
+                };
+                match stored {
+                    Ok(_) => {
+                        debug!(owner_id = %owner_id, messages = messages.len(), "archived pushed history");
+                        if let Some(reply) = incoming.reply {
+                            let _ = reply.send(PeerMessage::Ack { message_id: batch_id });
+                        }
+                    }
+                    // No ACK: the owner keeps its copies and pushes again later
+                    Err(e) => error!(owner_id = %owner_id, error = %e, "failed to archive pushed history"),
+                }
+            }
+
+            PeerMessage::HistoryQuery {
+                owner_id,
+                peer_id,
+                before,
+                limit,
+            } => {
+                if !self.accepts_history_sync(&owner_id, incoming.from_addr) {
+                    return;
+                }
+                let page = match self.db.lock() {
+                    Ok(db) => db.get_archived(&owner_id, &peer_id, before, limit),
+                    Err(_) => return,
+                };
+                match page {
+                    Ok(mut messages) => {
+                        let mut budget = HISTORY_PAGE_BYTES;
+                        let fits = messages
+                            .iter()
+                            .take_while(|m| {
+                                budget = budget.saturating_sub(m.content.len() + HISTORY_MESSAGE_OVERHEAD);
+                                budget > 0
+                            })
+                            .count();
+                        messages.truncate(fits.max(1));
+                        if let Some(reply) = incoming.reply {
+                            let _ = reply.send(PeerMessage::HistoryPage { messages });
+                        }
+                    }
+                    Err(e) => error!(owner_id = %owner_id, error = %e, "failed to read archived history"),
+                }
+            }
+
+            PeerMessage::Read { sender_id, up_to } => {
+                if !self.config.kid_mode.allows(&sender_id) {
+                    return;
+                }
+                let saved = match self.db.lock() {
+                    Ok(db) => db.mark_read_by_peer(&sender_id, up_to),
+                    Err(_) => return,
+                };
+                match saved {
+                    Ok(()) => {
+                        let _ = self.events.send(ServerMessage::MessagesRead {
+                            peer_id: sender_id,
+                            up_to,
+                        });
+                    }
+                    Err(e) => error!(peer_id = %sender_id, error = %e, "failed to save read receipt"),
+                }
+            }
+
+            // Not audited in kid mode: one arrives every few seconds
+            PeerMessage::Typing { sender_id } => {
+                if self.config.kid_mode.allows(&sender_id) {
+                    let _ = self.events.send(ServerMessage::PeerTyping { peer_id: sender_id });
+                }
+            }
+
+            // Handled at the TCP connection level, never forwarded here
+            PeerMessage::Ping | PeerMessage::Pong | PeerMessage::HistoryPage { .. } => {}
+        }
+    }
+
+    /// Whether a chat with this ID was already received, i.e. this is the
+    /// sender retrying after losing our ACK.
+    fn is_duplicate_chat(&self, id: &MessageId) -> bool {
+        if self.seen_chats.contains(id) {
+            return true;
+        }
+        let stored = match self.db.lock() {
+            Ok(db) => db.get_message(id).unwrap_or_else(|e| {
+                error!(error = %e, "failed to check for a duplicate message");
+                None
+            }),
+            Err(_) => None,
+        };
+        stored.is_some()
+    }
+
+    /// Deletes `message` from the database and tells subscribed clients.
+    fn delete_and_broadcast(&self, message: &Message) -> bool {
+        let deleted = match self.db.lock() {
+            Ok(db) => match db.delete_message(&message.id) {
+                Ok(deleted) => deleted,
+                Err(e) => {
+                    error!(error = %e, "failed to delete message");
+                    false
+                }
+            },
+            Err(_) => false,
+        };
+        if deleted {
+            let _ = self.events.send(ServerMessage::MessageDeleted {
+                peer_id: message.peer_id.clone(),
+                message_id: message.id.clone(),
+            });
+            self.refresh_unread();
+        }
+        deleted
+    }
+
+    /// Processes an IPC request from a TUI client.
+    async fn handle_ipc_request(&mut self, ipc_req: IpcRequest) {
+        let IpcRequest {
+            request,
+            response_tx,
+        } = ipc_req;
+
+        let response = match request {
+            ClientRequest::ListPeers => self.handle_list_peers(),
+
+            ClientRequest::GetMessages {
+                peer_id,
+                limit,
+                before,
+                cursor,
+            } => match self.handle_get_messages(&peer_id, limit, before, cursor.as_deref(), &response_tx) {
+                Some(response) => response,
+                // Answered once the archive peer has been asked
+                None => return,
+            },
+
+            ClientRequest::GetTimeline { peer_id, after, limit } => {
+                self.handle_get_timeline(peer_id, after.as_ref(), limit)
+            }
+
+            ClientRequest::SendMessage {
+                peer_id,
+                content,
+                reply_to,
+            } => self.handle_send_message(&peer_id, &content, reply_to).await,
+
+            ClientRequest::ScheduleMessage {
+                peer_id,
+                content,
+                send_at,
+            } => self.handle_schedule_message(&peer_id, &content, send_at),
+
+            ClientRequest::Announce { content } => self.handle_announce(&content).await,
+
+            ClientRequest::DeleteMessage { message_id, retract } => {
+                self.handle_delete_message(&message_id, retract).await
+            }
+
+            ClientRequest::RetryMessage { message_id } => self.handle_retry_message(&message_id).await,
+
+            ClientRequest::MarkRead { peer_id, up_to } => {
+                self.handle_mark_read(&peer_id, up_to)
+            }
+
+            ClientRequest::SetPeerNotifications { peer_id, muted, sound } => {
+                self.handle_set_peer_notifications(&peer_id, NotificationPrefs { muted, sound })
+            }
+
+            ClientRequest::GetConfig => self.handle_get_config(),
+
+            ClientRequest::SetDisplayName { name } => self.handle_set_display_name(&name),
+
+            ClientRequest::ReloadConfig => self.handle_reload_config(),
+
+            ClientRequest::Shutdown => {
+                self.exit_request = Some(ExitAction::Exit);
+                ServerMessage::ShuttingDown { restart: false }
+            }
+
+            ClientRequest::Restart => {
+                self.exit_request = Some(ExitAction::Restart);
+                ServerMessage::ShuttingDown { restart: true }
+            }
+
+            ClientRequest::GetConversations => self.handle_get_conversations(),
+
+            ClientRequest::ExportConversation { peer_id, format, path } => {
+                self.handle_export_conversation(peer_id, format, &path, &response_tx).await
+            }
+
+            ClientRequest::GetSummary => self.handle_get_summary(),
+
+            ClientRequest::SetMessageNote { message_id, note } => {
+                self.handle_set_message_note(&message_id, &note)
+            }
+
+            ClientRequest::GetMessageNotes { peer_id } => self.handle_get_message_notes(peer_id),
+
+            ClientRequest::SearchNotes { query } => self.handle_search_notes(&query),
+
+            ClientRequest::SearchMessages { peer_id, query, limit } => {
+                self.handle_search_messages(peer_id, query, limit)
+            }
+
+            ClientRequest::GetAuditLog { limit, before } => self.handle_get_audit_log(limit, before),
+
+            ClientRequest::SetDoNotDisturb { enabled } => self.handle_set_do_not_disturb(enabled),
+
+            ClientRequest::GetDoNotDisturb => self.do_not_disturb_status(),
+
+            ClientRequest::ReportActivity => self.handle_report_activity(),
+
+            ClientRequest::SetViewing { peer_id, focused } => {
+                self.handle_set_viewing(&response_tx, peer_id, focused)
+            }
+
+            ClientRequest::ReportTyping { peer_id } => self.handle_report_typing(&peer_id),
+
+            ClientRequest::SetNotificationsEnabled { enabled } => self.handle_set_notifications_enabled(enabled),
+
+            ClientRequest::GetStatus => ServerMessage::Status {
+                subsystems: self.health.snapshot(),
+                database_recovery: self.db_recovery.clone(),
+            },
+
+            ClientRequest::Ping { sent_at } => ServerMessage::Pong {
+                sent_at,
+                daemon_time: Timestamp::now(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                protocol_version: IPC_PROTOCOL_VERSION,
+            },
+
+            // Subscriptions are handled in the IPC server itself
+            ClientRequest::Subscribe { .. } | ClientRequest::Unsubscribe { .. } | ClientRequest::ListSubscriptions => {
+                ServerMessage::Ok
+            }
+        };
+
+        send_response(&response_tx, response).await;
+    }
+
+    /// Handles ListPeers: returns all known peers with their online status.
+    fn handle_list_peers(&self) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.get_peers() {
+                Ok(mut peers) => {
+                    // Update online status from our in-memory state
+                    for peer in &mut peers {
+                        let online = self.online_peers.get(&peer.id);
+                        peer.online = online.is_some();
+                        peer.do_not_disturb = online.is_some_and(|p| p.do_not_disturb);
+                        peer.away = online.is_some_and(|p| p.away);
+                    }
+                    ServerMessage::PeerList { peers }
+                }
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to fetch peers: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles GetMessages: returns message history with a peer, with
+    /// cursors for the pages on either side.
+    ///
+    /// In low-storage mode, a page that runs out of local history is
+    /// filled in from the archive peer. That waits on the network, so it
+    /// happens in the background, which answers on `response_tx` itself;
+    /// `None` is returned then.
+    fn handle_get_messages(
+        &self,
+        peer_id: &PeerId,
+        limit: u32,
+        before: Option<Timestamp>,
+        cursor: Option<&str>,
+        response_tx: &mpsc::Sender<ServerMessage>,
+    ) -> Option<ServerMessage> {
+        let cursor = match cursor.map(MessageCursor::decode) {
+            None => None,
+            Some(Some(cursor)) => Some(cursor),
+            Some(None) => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::InvalidRequest,
+                    message: "unknown cursor (pass next_cursor or prev_cursor unchanged)".to_string(),
+                })
+            }
+        };
+
+        let fetched = match self.db.lock() {
+            Ok(db) => match &cursor {
+                Some(cursor) => db.get_messages_at(peer_id, limit, cursor),
+                None => db.get_messages(peer_id, limit, before),
+            },
+            Err(e) => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("database lock poisoned: {e}"),
+                })
+            }
+        };
+        let mut messages = match fetched {
+            Ok(messages) => messages,
+            Err(e) => {
+                return Some(ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to fetch messages: {e}"),
+                })
+            }
+        };
+
+        let going_older = cursor.as_ref().is_none_or(|c| c.direction == PageDirection::Older);
+        let short_page = going_older && messages.len() < limit as usize;
+        let archive = if short_page && self.config.storage.low_storage() {
+            self.archive_peer()
+        } else {
+            None
+        };
+        let Some(archive) = archive else {
+            return Some(messages_page(messages, limit, cursor.as_ref()));
+        };
+
+        // The archive pages by timestamp only: ask from just past the
+        // cursor and drop what's on this side of it
+        let archive_before = cursor
+            .as_ref()
+            .map(|c| Timestamp::from_millis(c.timestamp.as_millis() + 1))
+            .or(before);
+        let query = PeerMessage::HistoryQuery {
+            owner_id: PeerId::new(&self.config.peer_id),
+            peer_id: peer_id.clone(),
+            before: archive_before,
+            limit,
+        };
+        let response_tx = response_tx.clone();
+        tokio::spawn(async move {
+            let mut archived = fetch_from_archive(&archive, &query).await;
+            if let Some(c) = &cursor {
+                archived.retain(|m| (m.timestamp, m.id.as_str()) < (c.timestamp, c.id.as_str()));
+            }
+            // The archive also has most of what's still here; its copy
+            // wins, since local ones may have been shortened
+            let archived_ids: HashSet<MessageId> = archived.iter().map(|m| m.id.clone()).collect();
+            messages.retain(|m| !archived_ids.contains(&m.id));
+            messages.extend(archived);
+            messages.sort_by(|a, b| (b.timestamp, b.id.as_str()).cmp(&(a.timestamp, a.id.as_str())));
+            messages.truncate(limit as usize);
+            send_response(&response_tx, messages_page(messages, limit, cursor.as_ref())).await;
+        });
+        None
+    }
+
+    /// Handles GetTimeline: returns the next page of a conversation,
+    /// oldest first.
+    fn handle_get_timeline(&self, peer_id: PeerId, after: Option<&MessageId>, limit: u32) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.get_timeline(&peer_id, after, limit) {
+                Ok(messages) => ServerMessage::Timeline { peer_id, messages },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to fetch timeline: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles GetConversations: returns the last message and unread
+    /// count of every conversation.
+    fn handle_get_conversations(&self) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.get_conversation_summaries() {
+                Ok(conversations) => ServerMessage::Conversations { conversations },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to fetch conversations: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles ExportConversation: reads the whole conversation, telling
+    /// the client how far along it is, and writes it to `path`.
+    async fn handle_export_conversation(
+        &self,
+        peer_id: PeerId,
+        format: ExportFormat,
+        path: &str,
+        response_tx: &mpsc::Sender<ServerMessage>,
+    ) -> ServerMessage {
+        // Relative to what? The daemon's working directory means nothing
+        // to the client
+        if !Path::new(path).is_absolute() {
+            return ServerMessage::Error {
+                code: ErrorCode::InvalidRequest,
+                message: format!("export path must be absolute: {path}"),
+            };
+        }
+        let Some(peer) = self.find_peer_info(&peer_id) else {
+            return ServerMessage::Error {
+                code: ErrorCode::PeerNotFound,
+                message: format!("unknown peer {peer_id}"),
+            };
+        };
+
+        let total = match self.db.lock() {
+            Ok(db) => db.message_count(&peer_id),
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("database lock poisoned: {e}"),
+                }
+            }
+        };
+        let total = match total {
+            Ok(total) => total,
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to count messages: {e}"),
+                }
+            }
+        };
+
+        let mut messages: Vec<Message> = Vec::new();
+        loop {
+            // The lock is released before the progress send below
+            let batch = match self.db.lock() {
+                Ok(db) => db.get_timeline(&peer_id, messages.last().map(|m| &m.id), EXPORT_BATCH),
+                Err(e) => {
+                    return ServerMessage::Error {
+                        code: ErrorCode::InternalError,
+                        message: format!("database lock poisoned: {e}"),
+                    }
+                }
+            };
+            let batch = match batch {
+                Ok(batch) => batch,
+                Err(e) => {
+                    return ServerMessage::Error {
+                        code: ErrorCode::DbError,
+                        message: format!("failed to fetch messages: {e}"),
+                    }
+                }
+            };
+            let done = batch.len() < EXPORT_BATCH as usize;
+            messages.extend(batch);
+            if done {
+                break;
+            }
+            let progress = ServerMessage::ExportProgress {
+                peer_id: peer_id.clone(),
+                exported: messages.len() as u32,
+                // Messages may have arrived since the count
+                total: total.max(messages.len() as u32),
+            };
+            // A client that went away still gets its file
+            let _ = response_tx.send(progress).await;
+        }
+
+        let export = Export {
+            peer_id: peer_id.clone(),
+            peer_name: peer.display_name,
+            exported_at: Timestamp::now(),
+            messages,
+        };
+        if let Err(e) = write_export(&export, format, Path::new(path)) {
+            warn!(%peer_id, path, error = %e, "conversation export failed");
+            return ServerMessage::Error {
+                code: ErrorCode::ExportFailed,
+                message: format!("could not write {path}: {e}"),
+            };
+        }
+        info!(%peer_id, %format, path, messages = export.messages.len(), "conversation exported");
+        ServerMessage::Exported {
+            peer_id,
+            path: path.to_string(),
+            messages: export.messages.len() as u32,
+        }
+    }
+
+    /// Handles GetSummary: the unread total and the online peer count.
+    fn handle_get_summary(&self) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.total_unread_count() {
+                Ok(unread) => ServerMessage::Summary {
+                    unread,
+                    online_peers: self.online_peers.len() as u32,
+                },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to count unread messages: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles SetMessageNote: stores (or removes) a private note.
+    fn handle_set_message_note(&self, message_id: &MessageId, note: &str) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.set_message_note(message_id, note, Timestamp::now()) {
+                Ok(true) => ServerMessage::Ok,
+                Ok(false) => ServerMessage::Error {
+                    code: ErrorCode::MessageNotFound,
+                    message: format!("no message with ID {message_id}"),
+                },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to save note: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles GetMessageNotes: returns the notes on a conversation.
+    fn handle_get_message_notes(&self, peer_id: PeerId) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.get_message_notes(&peer_id) {
+                Ok(notes) => ServerMessage::MessageNotes { peer_id, notes },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to fetch notes: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles SearchNotes: finds notes containing the query.
+    fn handle_search_notes(&self, query: &str) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.search_message_notes(query, NOTE_SEARCH_LIMIT) {
+                Ok(results) => ServerMessage::NoteSearchResults { results },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to search notes: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles SearchMessages: finds messages of one conversation
+    /// containing the query.
+    fn handle_search_messages(&self, peer_id: PeerId, query: String, limit: u32) -> ServerMessage {
+        if query.trim().is_empty() {
+            return ServerMessage::Error {
+                code: ErrorCode::InvalidRequest,
+                message: "search query is empty".to_string(),
+            };
+        }
+        match self.db.lock() {
+            Ok(db) => match db.search_messages(&peer_id, &query, limit.min(MESSAGE_SEARCH_LIMIT)) {
+                Ok(messages) => ServerMessage::SearchResults { peer_id, query, messages },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to search messages: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Handles GetAuditLog: returns a page of the settings audit log.
+    fn handle_get_audit_log(&self, limit: u32, before: Option<Timestamp>) -> ServerMessage {
+        match self.db.lock() {
+            Ok(db) => match db.get_audit_log(limit, before) {
+                Ok(entries) => ServerMessage::AuditLog { entries },
+                Err(e) => ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to fetch audit log: {e}"),
+                },
+            },
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::InternalError,
+                message: format!("database lock poisoned: {e}"),
+            },
+        }
+    }
+
+    /// Appends to the audit log. Failures are logged, not reported: the
+    /// change itself already succeeded.
+    fn record_audit(&self, entry: AuditEntry) {
+        if let Ok(db) = self.db.lock() {
+            if let Err(e) = db.record_audit(&entry) {
+                error!(error = %e, "failed to record audit entry");
+            }
+        }
+    }
+
+    /// Whether kid mode lets this machine exchange messages with `peer_id`.
+    /// If not, the attempt (`detail`) is flagged in the audit log for the
+    /// parents.
+    fn kid_mode_allows(&self, peer_id: &PeerId, detail: &str) -> bool {
+        if self.config.kid_mode.allows(peer_id) {
+            return true;
+        }
+        warn!(peer_id = %peer_id, "{detail} (kid mode)");
+        self.record_audit(AuditEntry {
+            timestamp: Timestamp::now(),
+            action: AuditAction::KidModeBlocked,
+            peer_id: Some(peer_id.clone()),
+            detail: detail.to_string(),
+        });
+        false
+    }
+
+    /// Takes a message out of the outbound journal once its send attempt is
+    /// over. Messages that need an ACK leave it through `mark_delivered`
+    /// instead, or stay undelivered for `resend_pending`.
+    fn clear_pending(&self, message_id: &MessageId) {
+        if let Ok(db) = self.db.lock() {
+            if let Err(e) = db.clear_pending(message_id) {
+                error!(message_id = %message_id, error = %e, "failed to clear journaled message");
+            }
+        }
+    }
+
+    /// Returns what we know about a peer: the live mDNS entry if it's
+    /// online, otherwise its last known details from the database.
+    fn find_peer_info(&self, peer_id: &PeerId) -> Option<PeerInfo> {
+        self.online_peers.get(peer_id).cloned().or_else(|| {
+            // Peer might be offline — try to get their last known addresses from DB
+            match self.db.lock() {
+                Ok(db) => match db.get_peers() {
+                    Ok(peers) => peers.into_iter().find(|p| p.id == *peer_id),
+                    Err(_) => None,
+                },
+                Err(_) => None,
+            }
+        })
+    }
+
+    /// Handles DeleteMessage: deletes the message locally and, with
+    /// `retract`, asks the peer to delete its copy too.
+    async fn handle_delete_message(&self, message_id: &MessageId, retract: bool) -> ServerMessage {
+        let message = match self.db.lock() {
+            Ok(db) => match db.get_message(message_id) {
+                Ok(message) => message,
+                Err(e) => {
+                    return ServerMessage::Error {
+                        code: ErrorCode::DbError,
+                        message: format!("failed to look up message: {e}"),
+                    }
+                }
+            },
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("database lock poisoned: {e}"),
+                }
+            }
+        };
+        let Some(message) = message else {
+            return ServerMessage::Error {
+                code: ErrorCode::MessageNotFound,
+                message: format!("no message with ID {message_id}"),
+            };
+        };
+        if retract && message.direction != Direction::Sent {
+            return ServerMessage::Error {
+                code: ErrorCode::NotOwner,
+                message: "only messages you sent can be retracted".to_string(),
+            };
+        }
+        // Check before deleting anything: an older peer would just drop the
+        // retraction, leaving the message there but gone from our side
+        let peer = if retract { self.find_peer_info(&message.peer_id) } else { None };
+        if peer.as_ref().is_some_and(|p| !p.supports(Capability::Retract)) {
+            return ServerMessage::Error {
+                code: ErrorCode::NotSupported,
+                message: "this peer's FamilyCom can't delete messages; update it first".to_string(),
+            };
+        }
+
+        if !self.delete_and_broadcast(&message) {
+            return ServerMessage::Error {
+                code: ErrorCode::DbError,
+                message: format!("failed to delete message {message_id}"),
+            };
+        }
+        info!(message_id = %message_id, retract, "message deleted");
+        if !retract {
+            return ServerMessage::Ok;
+        }
+
+        let addresses = peer.map(|info| info.addresses).unwrap_or_default();
+        let retraction = PeerMessage::Retract {
+            message_id: message_id.clone(),
+            sender_id: PeerId::new(&self.config.peer_id),
+        };
+        match client::send_to_any(&addresses, &retraction, DeliveryMode::AckRequired).await {
+            Ok(()) => ServerMessage::Ok,
+            Err(e) => {
+                warn!(message_id = %message_id, error = %e, "failed to retract message from peer");
+                ServerMessage::Error {
+                    code: ErrorCode::RetractFailed,
+                    message: format!("deleted here, but the peer could not be reached: {e}"),
+                }
+            }
+        }
+    }
+
+    /// Handles RetryMessage: sends an undelivered message of ours again,
+    /// with its original ID and timestamp so the peer keeps one copy.
+    async fn handle_retry_message(&self, message_id: &MessageId) -> ServerMessage {
+        let message = match self.db.lock() {
+            Ok(db) => match db.get_message(message_id) {
+                Ok(message) => message,
+                Err(e) => {
+                    return ServerMessage::Error {
+                        code: ErrorCode::DbError,
+                        message: format!("failed to look up message: {e}"),
+                    }
+                }
+            },
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("database lock poisoned: {e}"),
+                }
+            }
+        };
+        let Some(message) = message else {
+            return ServerMessage::Error {
+                code: ErrorCode::MessageNotFound,
+                message: format!("no message with ID {message_id}"),
+            };
+        };
+        if message.direction != Direction::Sent {
+            return ServerMessage::Error {
+                code: ErrorCode::NotOwner,
+                message: "only messages you sent can be retried".to_string(),
+            };
+        }
+        if message.delivered {
+            return ServerMessage::MessageSent {
+                message_id: message.id,
+            };
+        }
+        if !self.kid_mode_allows(&message.peer_id, "refused to retry a message") {
+            return ServerMessage::Error {
+                code: ErrorCode::PeerNotAllowed,
+                message: format!("kid mode is on and {} is not an allowed peer", message.peer_id),
+            };
+        }
+        let addresses = self
+            .find_peer_info(&message.peer_id)
+            .map(|info| info.addresses)
+            .unwrap_or_default();
+        if addresses.is_empty() {
+            return ServerMessage::Error {
+                code: ErrorCode::PeerNotFound,
+                message: format!("no known addresses for peer {}", message.peer_id),
+            };
+        }
+
+        let chat = PeerMessage::Chat {
+            id: message.id.clone(),
+            sender_id: PeerId::new(&self.config.peer_id),
+            sender_name: self.config.display_name.clone(),
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+            announcement: message.announcement,
+            reply_to: message.reply_to.clone(),
+        };
+        let mode = if message.fire_and_forget {
+            DeliveryMode::FireAndForget
+        } else {
+            DeliveryMode::AckRequired
+        };
+        match client::send_to_any(&addresses, &chat, mode).await {
+            Ok(()) => {
+                info!(message_id = %message_id, peer_id = %message.peer_id, "retried message sent");
+                if mode == DeliveryMode::AckRequired {
+                    if let Ok(db) = self.db.lock() {
+                        let _ = db.mark_delivered(message_id);
+                    }
+                    let _ = self.events.send(ServerMessage::MessageDelivered {
+                        message_id: message_id.clone(),
+                    });
+                } else {
+                    self.clear_pending(message_id);
+                }
+                ServerMessage::MessageSent {
+                    message_id: message_id.clone(),
+                }
+            }
+            Err(e) => {
+                warn!(message_id = %message_id, peer_id = %message.peer_id, error = %e, "retry failed");
+                let reason = e.to_string();
+                let _ = self.events.send(ServerMessage::MessageFailed {
+                    message,
+                    reason: reason.clone(),
+                });
+                ServerMessage::Error {
+                    code: ErrorCode::DeliveryFailed,
+                    message: format!("the peer could not be reached: {reason}"),
+                }
+            }
+        }
+    }
+
+    /// Handles SendMessage: saves the message locally and sends it to the peer via TCP.
+    ///
+    /// Slash commands (see `familycom_core::commands`) are run here instead.
+    async fn handle_send_message(&mut self, peer_id: &PeerId, content: &str, reply_to: Option<MessageId>) -> ServerMessage {
+        let content = match Input::parse(content) {
+            Input::Message(content) => content,
+            Input::Command(command) => return self.handle_slash_command(peer_id, command).await,
+        };
+
+        // Validate the message content
+        if let Err(e) = MessageContent::new(content) {
+            return ServerMessage::Error {
+                code: ErrorCode::InvalidContent,
+                message: e.to_string(),
+            };
+        }
+
+        if !self.kid_mode_allows(peer_id, "refused to send a message") {
+            return ServerMessage::Error {
+                code: ErrorCode::PeerNotAllowed,
+                message: format!("kid mode is on and {peer_id} is not an allowed peer"),
+            };
+        }
+
+        // Find the peer's addresses
+        let peer_info = self.find_peer_info(peer_id);
+        let addresses = peer_info
+            .as_ref()
+            .map(|info| info.addresses.clone())
+            .unwrap_or_default();
+
+        if addresses.is_empty() {
+            return ServerMessage::Error {
+                code: ErrorCode::PeerNotFound,
+                message: format!("no known addresses for peer {peer_id}"),
+            };
+        }
+
+        let mode = self.config.delivery.mode_for(
+            peer_id,
+            peer_info.as_ref().map(|info| info.display_name.as_str()),
+        );
+        let fire_and_forget = mode == DeliveryMode::FireAndForget;
+
+        // Create the message
+        let message_id = MessageId::generate();
+        let timestamp = Timestamp::now();
+
+        let peer_message = PeerMessage::Chat {
+            id: message_id.clone(),
+            sender_id: PeerId::new(&self.config.peer_id),
+            sender_name: self.config.display_name.clone(),
+            content: content.to_string(),
+            timestamp,
+            announcement: false,
+            reply_to: reply_to.clone(),
+        };
+
+        // Journal it in our local database first, so a crash before the
+        // send finishes leaves it to be replayed at the next start
         let message = Message {
             id: message_id.clone(),
             peer_id: peer_id.clone(),
@@ -383,20 +2398,32 @@ impl DaemonApp {
             content: content.to_string(),
             timestamp,
             delivered: false,
+            fire_and_forget,
+            announcement: false,
+            reply_to,
         };
 
         if let Ok(db) = self.db.lock() {
-            if let Err(e) = db.save_message(&message) {
+            if let Err(e) = db.save_pending(&message) {
                 error!(error = %e, "failed to save outgoing message");
                 return ServerMessage::Error {
-                    code: "db_error".to_string(),
+                    code: ErrorCode::DbError,
                     message: format!("failed to save message: {e}"),
                 };
             }
         }
 
         // Send the message to the peer via TCP
-        match client::send_to_any(&addresses, &peer_message).await {
+        match client::send_to_any(&addresses, &peer_message, mode).await {
+            Ok(()) if fire_and_forget => {
+                info!(
+                    message_id = %message_id,
+                    peer_id = %peer_id,
+                    "message sent (no ACK expected)"
+                );
+                self.clear_pending(&message_id);
+                ServerMessage::MessageSent { message_id }
+            }
             Ok(()) => {
                 info!(
                     message_id = %message_id,
@@ -409,22 +2436,416 @@ impl DaemonApp {
                     let _ = db.mark_delivered(&message_id);
                 }
 
-                ServerMessage::MessageSent { message_id }
+                ServerMessage::MessageSent { message_id }
+            }
+            Err(e) => {
+                warn!(
+                    message_id = %message_id,
+                    peer_id = %peer_id,
+                    error = %e,
+                    "failed to deliver message"
+                );
+                self.clear_pending(&message_id);
+
+                // Message is saved locally but not delivered.
+                // We still return MessageSent so the TUI shows it,
+                // but with delivered=false; clients can offer a retry.
+                let _ = self.events.send(ServerMessage::MessageFailed {
+                    message,
+                    reason: e.to_string(),
+                });
+                ServerMessage::MessageSent { message_id }
+            }
+        }
+    }
+
+    /// Runs a slash command typed in `peer_id`'s conversation and posts the
+    /// answer there as a `Direction::System` message. Nothing is sent.
+    async fn handle_slash_command(&self, peer_id: &PeerId, command: SlashCommand) -> ServerMessage {
+        debug!(peer_id = %peer_id, ?command, "running slash command");
+        let content = match command {
+            SlashCommand::Ping(target) => self.ping_reply(peer_id, target.as_deref()).await,
+            SlashCommand::Who => self.who_reply(),
+            SlashCommand::Status => self.status_reply(),
+            SlashCommand::Help => commands::HELP.to_string(),
+            SlashCommand::Unknown(name) => format!("Comando desconocido: /{name}. {}", commands::HELP),
+        };
+        let message_id = self.post_system_message(peer_id, content);
+        ServerMessage::MessageSent { message_id }
+    }
+
+    /// Stores a `Direction::System` message in `peer_id`'s conversation and
+    /// pushes it to clients.
+    fn post_system_message(&self, peer_id: &PeerId, content: String) -> MessageId {
+        let message = Message {
+            id: MessageId::generate(),
+            peer_id: peer_id.clone(),
+            direction: Direction::System,
+            content,
+            timestamp: Timestamp::now(),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        if let Ok(db) = self.db.lock() {
+            if let Err(e) = db.save_message(&message) {
+                error!(error = %e, "failed to save system message");
+            }
+        }
+        let message_id = message.id.clone();
+        let _ = self.events.send(ServerMessage::NewMessage { message });
+        message_id
+    }
+
+    /// `/ping`: pings the named peer, or the conversation's.
+    async fn ping_reply(&self, peer_id: &PeerId, target: Option<&str>) -> String {
+        let peer = match target {
+            None => self.find_peer_info(peer_id),
+            Some(target) => {
+                let mut peers: Vec<PeerInfo> = self.online_peers.values().cloned().collect();
+                if let Ok(db) = self.db.lock() {
+                    let known = db.get_peers().unwrap_or_default();
+                    peers.extend(known.into_iter().filter(|p| !self.online_peers.contains_key(&p.id)));
+                }
+                match send::find_peer(&peers, target) {
+                    Ok(peer) => Some(peer.clone()),
+                    Err(_) => return format!("No hay ningun equipo llamado \"{target}\"."),
+                }
+            }
+        };
+        let Some(peer) = peer else {
+            return format!("No se conoce la direccion de {peer_id}.");
+        };
+        let started = std::time::Instant::now();
+        match client::ping_any(&peer.addresses).await {
+            Ok(()) => format!("{} respondio en {} ms.", peer.display_name, started.elapsed().as_millis()),
+            Err(e) => format!("{} no respondio ({e}).", peer.display_name),
+        }
+    }
+
+    /// `/who`: the peers online now.
+    fn who_reply(&self) -> String {
+        let mut names: Vec<&str> = self.online_peers.values().map(|p| p.display_name.as_str()).collect();
+        if names.is_empty() {
+            return "No hay nadie mas en linea.".to_string();
+        }
+        names.sort_by_key(|name| name.to_lowercase());
+        format!("En linea ({}): {}.", names.len(), names.join(", "))
+    }
+
+    /// `/status`: a one-line summary of this daemon.
+    fn status_reply(&self) -> String {
+        let unread = match self.db.lock() {
+            Ok(db) => db.total_unread_count().unwrap_or(0),
+            Err(_) => 0,
+        };
+        let mut parts = vec![
+            format!("{} (familycomd {})", self.config.display_name, env!("CARGO_PKG_VERSION")),
+            format!("{} en linea", self.online_peers.len()),
+            format!("{unread} sin leer"),
+        ];
+        if !self.config.notifications_enabled {
+            parts.push("notificaciones silenciadas".to_string());
+        }
+        if *self.do_not_disturb_tx.borrow() {
+            parts.push("no molestar".to_string());
+        }
+        let restarting: Vec<String> = self
+            .health
+            .snapshot()
+            .into_iter()
+            .filter(|subsystem| subsystem.state == ipc::SubsystemState::Restarting)
+            .map(|subsystem| subsystem.name)
+            .collect();
+        if restarting.is_empty() {
+            parts.push("todo funcionando".to_string());
+        } else {
+            parts.push(format!("reiniciando: {}", restarting.join(", ")));
+        }
+        format!("{}.", parts.join(", "))
+    }
+
+    /// Handles Announce: sends `content` to every online peer at once, each
+    /// copy stored as its own announcement message. Every copy is pushed as
+    /// `NewMessage`, since announcements usually come from the tray or the
+    /// command line rather than a TUI that shows them itself.
+    async fn handle_announce(&mut self, content: &str) -> ServerMessage {
+        if let Err(e) = MessageContent::new(content) {
+            return ServerMessage::Error {
+                code: ErrorCode::InvalidContent,
+                message: e.to_string(),
+            };
+        }
+
+        // In kid mode, the house is only the allowed peers
+        let peers: Vec<PeerInfo> = self
+            .online_peers
+            .values()
+            .filter(|peer| self.config.kid_mode.allows(&peer.id))
+            .cloned()
+            .collect();
+        let sender_id = PeerId::new(&self.config.peer_id);
+        let timestamp = Timestamp::now();
+        let mut sends = tokio::task::JoinSet::new();
+        let mut message_ids = Vec::new();
+        for peer in peers {
+            let mode = self.config.delivery.mode_for(&peer.id, Some(peer.display_name.as_str()));
+            let message = Message {
+                id: MessageId::generate(),
+                peer_id: peer.id.clone(),
+                direction: Direction::Sent,
+                content: content.to_string(),
+                timestamp,
+                delivered: false,
+                fire_and_forget: mode == DeliveryMode::FireAndForget,
+                announcement: true,
+                reply_to: None,
+            };
+            if let Ok(db) = self.db.lock() {
+                if let Err(e) = db.save_pending(&message) {
+                    error!(peer_id = %peer.id, error = %e, "failed to save announcement");
+                    continue;
+                }
+            }
+            let chat = PeerMessage::Chat {
+                id: message.id.clone(),
+                sender_id: sender_id.clone(),
+                sender_name: self.config.display_name.clone(),
+                content: content.to_string(),
+                timestamp,
+                announcement: true,
+                reply_to: None,
+            };
+            let message_id = message.id.clone();
+            message_ids.push(message_id.clone());
+            let _ = self.events.send(ServerMessage::NewMessage { message });
+
+            // All at once, so one unreachable peer doesn't hold up the rest
+            sends.spawn(async move {
+                let result = client::send_to_any(&peer.addresses, &chat, mode).await;
+                (peer.id, message_id, mode, result)
+            });
+        }
+
+        while let Some(sent) = sends.join_next().await {
+            let Ok((peer_id, message_id, mode, result)) = sent else {
+                continue;
+            };
+            match result {
+                Ok(()) if mode == DeliveryMode::AckRequired => {
+                    if let Ok(db) = self.db.lock() {
+                        let _ = db.mark_delivered(&message_id);
+                    }
+                    let _ = self.events.send(ServerMessage::MessageDelivered { message_id });
+                }
+                Ok(()) => self.clear_pending(&message_id),
+                Err(e) => {
+                    warn!(message_id = %message_id, peer_id = %peer_id, error = %e, "failed to deliver announcement");
+                    self.clear_pending(&message_id);
+                }
+            }
+        }
+
+        info!(peers = message_ids.len(), "announcement sent");
+        ServerMessage::Announced { message_ids }
+    }
+
+    /// Handles ScheduleMessage: stores the message for `dispatch_scheduled`.
+    fn handle_schedule_message(&self, peer_id: &PeerId, content: &str, send_at: Timestamp) -> ServerMessage {
+        if let Err(e) = MessageContent::new(content) {
+            return ServerMessage::Error {
+                code: ErrorCode::InvalidContent,
+                message: e.to_string(),
+            };
+        }
+        let db = match self.db.lock() {
+            Ok(db) => db,
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("database lock poisoned: {e}"),
+                }
+            }
+        };
+        match db.peer_display_name(peer_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::PeerNotFound,
+                    message: format!("unknown peer {peer_id}"),
+                }
+            }
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to look up peer: {e}"),
+                }
+            }
+        }
+        match db.schedule_message(peer_id, content, send_at) {
+            Ok(id) => {
+                info!(peer_id = %peer_id, id, send_at = %send_at.format_local_datetime(), "message scheduled");
+                ServerMessage::Ok
+            }
+            Err(e) => ServerMessage::Error {
+                code: ErrorCode::DbError,
+                message: format!("failed to schedule message: {e}"),
+            },
+        }
+    }
+
+    /// Sends the scheduled messages that are due, oldest first, as if each
+    /// was a `SendMessage` just now, and pushes them to clients (which
+    /// didn't add them on their own, as they do when the user hits Enter).
+    ///
+    /// Each is removed from the schedule before it's sent: one that can't
+    /// get through stays undelivered and is resent when the peer comes
+    /// back, like any other. One that can't be sent at all is reported in
+    /// the conversation.
+    async fn dispatch_scheduled(&mut self) {
+        let due = match self.db.lock() {
+            Ok(db) => db.due_scheduled_messages(Timestamp::now()).unwrap_or_else(|e| {
+                error!(error = %e, "failed to look up scheduled messages");
+                Vec::new()
+            }),
+            Err(_) => return,
+        };
+        for scheduled in due {
+            if let Ok(db) = self.db.lock() {
+                if let Err(e) = db.remove_scheduled_message(scheduled.id) {
+                    error!(error = %e, "failed to remove dispatched scheduled message");
+                    continue;
+                }
+            }
+            match self.handle_send_message(&scheduled.peer_id, &scheduled.content, None).await {
+                ServerMessage::MessageSent { message_id } => {
+                    let message = match self.db.lock() {
+                        Ok(db) => db.get_message(&message_id).ok().flatten(),
+                        Err(_) => None,
+                    };
+                    // Slash command replies are pushed already
+                    if let Some(message) = message.filter(|m| m.direction == Direction::Sent) {
+                        info!(message_id = %message_id, peer_id = %scheduled.peer_id, "sent scheduled message");
+                        let _ = self.events.send(ServerMessage::NewMessage { message });
+                    }
+                }
+                ServerMessage::Error { message, .. } => {
+                    warn!(peer_id = %scheduled.peer_id, error = %message, "failed to send scheduled message");
+                    self.post_system_message(
+                        &scheduled.peer_id,
+                        format!("No se pudo enviar el mensaje programado \"{}\": {message}", scheduled.content),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Handles MarkRead: advances the read watermark for a conversation and
+    /// sends the peer a read receipt.
+    fn handle_mark_read(&self, peer_id: &PeerId, up_to: Option<Timestamp>) -> ServerMessage {
+        let db = match self.db.lock() {
+            Ok(db) => db,
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("database lock poisoned: {e}"),
+                }
+            }
+        };
+        let last_read = match db.last_received_at(peer_id, up_to) {
+            Ok(last_read) => last_read,
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::DbError,
+                    message: format!("failed to look up the last message read: {e}"),
+                }
+            }
+        };
+        // Message timestamps come from the sender's clock, so without
+        // `up_to` the watermark is the newest message received, not our
+        // time: a peer whose clock is ahead would stay unread
+        let Some(watermark) = up_to.or(last_read) else {
+            return ServerMessage::Ok;
+        };
+        if let Err(e) = db.mark_read(peer_id, watermark) {
+            return ServerMessage::Error {
+                code: ErrorCode::DbError,
+                message: format!("failed to mark conversation as read: {e}"),
+            };
+        }
+        drop(db);
+        self.refresh_unread();
+        if let Some(last_read) = last_read {
+            self.send_read_receipt(peer_id, last_read);
+        }
+        ServerMessage::Ok
+    }
+
+    /// Tells a peer we've read their messages up to `up_to`, if it's online
+    /// and understands read receipts. Not retried: the next one says the
+    /// same and more.
+    fn send_read_receipt(&self, peer_id: &PeerId, up_to: Timestamp) {
+        let Some(peer) = self.find_peer_info(peer_id) else {
+            return;
+        };
+        if !peer.online || !peer.supports(Capability::ReadReceipts) || !self.config.kid_mode.allows(peer_id) {
+            return;
+        }
+        let receipt = PeerMessage::Read {
+            sender_id: PeerId::new(&self.config.peer_id),
+            up_to,
+        };
+        let peer_id = peer_id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client::send_to_any(&peer.addresses, &receipt, DeliveryMode::FireAndForget).await {
+                debug!(peer_id = %peer_id, error = %e, "failed to send read receipt");
             }
-            Err(e) => {
-                warn!(
-                    message_id = %message_id,
-                    peer_id = %peer_id,
-                    error = %e,
-                    "failed to deliver message"
-                );
+        });
+    }
 
-                // Message is saved locally but not delivered.
-                // We still return MessageSent so the TUI shows it,
-                // but with delivered=false.
-                ServerMessage::MessageSent { message_id }
+    /// Handles SetPeerNotifications: stores the settings and hands them to
+    /// the notification handler.
+    fn handle_set_peer_notifications(&mut self, peer_id: &PeerId, prefs: NotificationPrefs) -> ServerMessage {
+        let saved = match self.db.lock() {
+            Ok(db) => match db.peer_display_name(peer_id) {
+                Ok(None) => {
+                    return ServerMessage::Error {
+                        code: ErrorCode::PeerNotFound,
+                        message: format!("unknown peer {peer_id}"),
+                    };
+                }
+                Ok(Some(_)) => db.set_peer_notifications(peer_id, prefs),
+                Err(e) => Err(e),
+            },
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("database lock poisoned: {e}"),
+                };
             }
+        };
+        if let Err(e) = saved {
+            return ServerMessage::Error {
+                code: ErrorCode::DbError,
+                message: format!("failed to save notification settings: {e}"),
+            };
         }
+
+        info!(peer_id = %peer_id, muted = prefs.muted, sound = prefs.sound, "peer notification settings changed");
+        self.notification_prefs_tx.send_modify(|all| {
+            all.insert(peer_id.clone(), prefs);
+        });
+        if let Some(peer) = self.online_peers.get_mut(peer_id) {
+            peer.notifications = prefs;
+        }
+        ServerMessage::Ok
+    }
+
+    /// The notification settings for `peer_id`, or the defaults.
+    fn notification_prefs(&self, peer_id: &PeerId) -> NotificationPrefs {
+        self.notification_prefs_tx.borrow().get(peer_id).copied().unwrap_or_default()
     }
 
     /// Handles GetConfig: returns the current configuration.
@@ -435,28 +2856,1054 @@ impl DaemonApp {
         }
     }
 
+    /// Saves the config to the file the daemon was started with, or the
+    /// default one.
+    fn save_config(&self) -> Result<(), ConfigError> {
+        match &self.config_path {
+            Some(path) => self.config.save_to(path),
+            None => self.config.save(),
+        }
+    }
+
     /// Handles SetDisplayName: updates the display name.
     fn handle_set_display_name(&mut self, name: &str) -> ServerMessage {
         // Validate
         if name.trim().is_empty() || name.len() > 50 {
             return ServerMessage::Error {
-                code: "invalid_name".to_string(),
+                code: ErrorCode::InvalidName,
                 message: "display name must be 1-50 characters".to_string(),
             };
         }
 
-        self.config.display_name = name.trim().to_string();
+        let old_name = std::mem::replace(&mut self.config.display_name, name.trim().to_string());
 
         // Save to config file
-        if let Err(e) = self.config.save() {
+        if let Err(e) = self.save_config() {
             error!(error = %e, "failed to save config");
+            self.config.display_name = old_name;
             return ServerMessage::Error {
-                code: "config_error".to_string(),
+                code: ErrorCode::ConfigError,
                 message: format!("failed to save config: {e}"),
             };
         }
 
+        if old_name != self.config.display_name {
+            self.record_audit(AuditEntry {
+                timestamp: Timestamp::now(),
+                action: AuditAction::DisplayNameChanged,
+                peer_id: None,
+                detail: format!("'{old_name}' -> '{}'", self.config.display_name),
+            });
+        }
+
+        self.config_tx.send_replace(self.config.clone());
         info!(new_name = %self.config.display_name, "display name updated");
         ServerMessage::Ok
     }
+
+    /// Handles SetNotificationsEnabled: saves the setting and hands it to
+    /// the notification handler (through the config watch).
+    fn handle_set_notifications_enabled(&mut self, enabled: bool) -> ServerMessage {
+        if self.config.notifications_enabled == enabled {
+            return ServerMessage::Ok;
+        }
+        self.config.notifications_enabled = enabled;
+        if let Err(e) = self.save_config() {
+            error!(error = %e, "failed to save config");
+            self.config.notifications_enabled = !enabled;
+            return ServerMessage::Error {
+                code: ErrorCode::ConfigError,
+                message: format!("failed to save config: {e}"),
+            };
+        }
+        self.config_tx.send_replace(self.config.clone());
+        info!(enabled, "desktop notifications {}", if enabled { "enabled" } else { "paused" });
+        ServerMessage::Ok
+    }
+
+    /// Handles ReloadConfig: re-reads the config file and applies the
+    /// settings that can change while running.
+    fn handle_reload_config(&mut self) -> ServerMessage {
+        let path = match &self.config_path {
+            Some(path) => path.clone(),
+            None => match AppConfig::config_file_path() {
+                Ok(path) => path,
+                Err(e) => {
+                    return ServerMessage::Error {
+                        code: ErrorCode::ConfigError,
+                        message: e.to_string(),
+                    }
+                }
+            },
+        };
+        let new = match AppConfig::load_from(&path) {
+            Ok(Some(config)) => config,
+            Ok(None) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::ConfigError,
+                    message: format!("config file not found at {}", path.display()),
+                }
+            }
+            Err(e) => {
+                return ServerMessage::Error {
+                    code: ErrorCode::ConfigError,
+                    message: e.to_string(),
+                }
+            }
+        };
+
+        let reload = self.config.reload(new);
+        let old_name = std::mem::replace(&mut self.config, reload.config).display_name;
+        if old_name != self.config.display_name {
+            self.record_audit(AuditEntry {
+                timestamp: Timestamp::now(),
+                action: AuditAction::DisplayNameChanged,
+                peer_id: None,
+                detail: format!("'{old_name}' -> '{}'", self.config.display_name),
+            });
+        }
+        if !reload.applied.is_empty() {
+            self.config_tx.send_replace(self.config.clone());
+        }
+        self.update_do_not_disturb();
+        if reload.applied.contains(&"email") {
+            self.check_email_relay();
+        }
+        info!(
+            applied = ?reload.applied,
+            restart_required = ?reload.restart_required,
+            "config reloaded"
+        );
+
+        ServerMessage::ConfigReloaded {
+            applied: reload.applied.into_iter().map(String::from).collect(),
+            restart_required: reload.restart_required.into_iter().map(String::from).collect(),
+        }
+    }
+}
+
+/// Sends a response to an IPC client. Large histories go out as several
+/// lines (see `ipc::into_frames`).
+async fn send_response(response_tx: &mpsc::Sender<ServerMessage>, response: ServerMessage) {
+    for frame in ipc::into_frames(response) {
+        if response_tx.send(frame).await.is_err() {
+            debug!("IPC client disconnected before receiving response");
+            break;
+        }
+    }
+}
+
+/// A `Messages` response for a page of `limit` messages, newest first.
+fn messages_page(messages: Vec<Message>, limit: u32, cursor: Option<&MessageCursor>) -> ServerMessage {
+    let (next, prev) = MessageCursor::for_page(&messages, limit, cursor);
+    ServerMessage::Messages {
+        messages,
+        next_cursor: next.map(|c| c.encode()),
+        prev_cursor: prev.map(|c| c.encode()),
+    }
+}
+
+/// Low-storage mode: sends `query` (a `HistoryQuery`) to the archive peer
+/// and returns the messages it answers with, newest first. Empty if it
+/// doesn't answer.
+async fn fetch_from_archive(archive: &PeerInfo, query: &PeerMessage) -> Vec<Message> {
+    let PeerMessage::HistoryQuery { peer_id, before, .. } = query else {
+        return Vec::new();
+    };
+    match client::query_any(&archive.addresses, query).await {
+        Ok(PeerMessage::HistoryPage { mut messages }) => {
+            messages.retain(|m| m.peer_id == *peer_id && before.is_none_or(|b| m.timestamp < b));
+            messages
+        }
+        Ok(other) => {
+            warn!(archive = %archive.display_name, response = ?other, "unexpected answer to history query");
+            Vec::new()
+        }
+        Err(e) => {
+            warn!(archive = %archive.display_name, error = %e, "could not fetch history from archive");
+            Vec::new()
+        }
+    }
+}
+
+/// Whether `config`'s quiet hours are on right now.
+fn in_quiet_hours(config: &AppConfig) -> bool {
+    config
+        .quiet_hours
+        .is_some_and(|hours| hours.contains(chrono::Local::now().time()))
+}
+
+/// Counts unread messages per conversation, naming senders as
+/// notifications do (`names`, else the peer ID).
+fn count_unread(db: &Database, names: &HashMap<PeerId, String>) -> Result<Unread, DatabaseError> {
+    let unread: Vec<_> = db
+        .get_conversation_summaries()?
+        .into_iter()
+        .filter(|summary| summary.unread_count > 0)
+        .collect();
+    Ok(Unread {
+        total: unread.iter().map(|summary| summary.unread_count).sum(),
+        senders: unread
+            .iter()
+            .map(|summary| {
+                names
+                    .get(&summary.peer_id)
+                    .cloned()
+                    .unwrap_or_else(|| summary.peer_id.to_string())
+            })
+            .collect(),
+    })
+}
+
+/// Writes an export to a new file at `path`, never overwriting one. A
+/// half-written file is removed.
+fn write_export(export: &Export, format: ExportFormat, path: &Path) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create_new(path)?);
+    let written = export
+        .write(format, &mut out, &chrono::Local)
+        .and_then(|()| out.flush());
+    if written.is_err() {
+        drop(out);
+        let _ = std::fs::remove_file(path);
+    }
+    written
+}
+
+#[cfg(test)]
+mod sim;
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) const PEER: &str = "peer-cocina";
+
+    pub(super) fn peer_info() -> PeerInfo {
+        PeerInfo {
+            id: PeerId::new(PEER),
+            display_name: "Cocina".to_string(),
+            addresses: vec!["192.168.1.20:9876".to_string()],
+            last_seen_at: Timestamp::from_millis(1_000),
+            online: true,
+            do_not_disturb: false,
+            away: false,
+            avatar: None,
+            accent_color: None,
+            capabilities: vec![Capability::Retract],
+            notifications: NotificationPrefs::default(),
+            interface_addresses: Default::default(),
+        }
+    }
+
+    pub(super) fn chat(id: &str, content: &str, at: i64) -> PeerMessage {
+        PeerMessage::Chat {
+            id: MessageId::new(id),
+            sender_id: PeerId::new(PEER),
+            sender_name: "Cocina".to_string(),
+            content: content.to_string(),
+            timestamp: Timestamp::from_millis(at),
+            announcement: false,
+            reply_to: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn only_peers_not_seen_during_the_sweep_are_demoted() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let (mut subscriber, _) = app.event_bus().subscribe();
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        let swept_at = peer_info().last_seen_at;
+
+        // Re-announced while the pings were out: the sweep result is stale
+        let mut reannounced = peer_info();
+        reannounced.last_seen_at = Timestamp::from_millis(2_000);
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(reannounced.clone()));
+        app.demote_unreachable(vec![(PeerId::new(PEER), swept_at)]);
+        assert!(app.online_peers.contains_key(&PeerId::new(PEER)));
+
+        app.demote_unreachable(vec![(PeerId::new(PEER), reannounced.last_seen_at)]);
+        assert!(!app.online_peers.contains_key(&PeerId::new(PEER)));
+        let mut pushed = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            pushed.push(event.message);
+        }
+        assert!(matches!(pushed.last(), Some(ServerMessage::PeerOffline { peer_id }) if peer_id.as_str() == PEER));
+    }
+
+    #[tokio::test]
+    async fn pending_messages_are_resent_when_the_peer_comes_back() {
+        // The peer's daemon: ACKs every chat on its connection
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                if let Ok(PeerMessage::Chat { id, .. }) = familycom_core::protocol::read_message(&mut stream).await {
+                    let ack = PeerMessage::Ack { message_id: id };
+                    familycom_core::protocol::write_message(&mut stream, &ack).await.unwrap();
+                }
+            }
+        });
+
+        let db = Database::open_in_memory().unwrap();
+        let mut peer = peer_info();
+        peer.addresses = vec![addr.to_string()];
+        db.upsert_peer(&peer).unwrap();
+        for (id, at) in [("s1", 1_000), ("s2", 2_000)] {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new(PEER),
+                direction: Direction::Sent,
+                content: "¿Estás?".to_string(),
+                timestamp: Timestamp::from_millis(at),
+                delivered: false,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let mut resend_rx = app.resend_rx.take().unwrap();
+        let (mut subscriber, _) = app.event_bus().subscribe();
+
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer));
+        loop {
+            let resend = resend_rx.recv().await.unwrap();
+            let finished = matches!(resend, Resend::Finished(_));
+            app.handle_resend(resend);
+            if finished {
+                break;
+            }
+        }
+
+        assert!(app.db.lock().unwrap().get_undelivered(&PeerId::new(PEER)).unwrap().is_empty());
+        let mut delivered = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            if let ServerMessage::MessageDelivered { message_id } = event.message {
+                delivered.push(message_id.as_str().to_string());
+            }
+        }
+        assert_eq!(delivered, ["s1", "s2"]);
+        assert!(app.resending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn journaled_messages_are_replayed_at_startup() {
+        // The peer's daemon: records and ACKs every chat
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (received_tx, mut received_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                if let Ok(PeerMessage::Chat { id, .. }) = familycom_core::protocol::read_message(&mut stream).await {
+                    let _ = received_tx.send(id.as_str().to_string());
+                    let ack = PeerMessage::Ack { message_id: id };
+                    familycom_core::protocol::write_message(&mut stream, &ack).await.unwrap();
+                }
+            }
+        });
+
+        // The last run stopped between journaling these and sending them; the
+        // peer isn't online yet, only known from before
+        let db = Database::open_in_memory().unwrap();
+        let mut peer = peer_info();
+        peer.addresses = vec![addr.to_string()];
+        db.upsert_peer(&peer).unwrap();
+        for (id, at, fire_and_forget) in [("j1", 1_000, false), ("j2", 2_000, true)] {
+            db.save_pending(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new(PEER),
+                direction: Direction::Sent,
+                content: "Ya voy".to_string(),
+                timestamp: Timestamp::from_millis(at),
+                delivered: false,
+                fire_and_forget,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let mut resend_rx = app.resend_rx.take().unwrap();
+
+        app.replay_journal();
+        loop {
+            let resend = resend_rx.recv().await.unwrap();
+            let finished = matches!(resend, Resend::Finished(_));
+            app.handle_resend(resend);
+            if finished {
+                break;
+            }
+        }
+
+        assert_eq!(received_rx.recv().await.as_deref(), Some("j1"));
+        assert_eq!(received_rx.recv().await.as_deref(), Some("j2"));
+        let db = app.db.lock().unwrap();
+        assert!(db.get_pending_peers().unwrap().is_empty());
+        assert!(db.get_undelivered(&PeerId::new(PEER)).unwrap().is_empty());
+        assert!(db.get_message(&MessageId::new("j1")).unwrap().unwrap().delivered);
+    }
+
+    #[tokio::test]
+    async fn a_retried_fire_and_forget_message_leaves_the_journal() {
+        // The peer's daemon: takes the chat and answers nothing
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (received_tx, mut received_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                if let Ok(PeerMessage::Chat { id, .. }) = familycom_core::protocol::read_message(&mut stream).await {
+                    let _ = received_tx.send(id.as_str().to_string());
+                }
+            }
+        });
+
+        let db = Database::open_in_memory().unwrap();
+        let mut peer = peer_info();
+        peer.addresses = vec![addr.to_string()];
+        db.upsert_peer(&peer).unwrap();
+        db.save_pending(&Message {
+            id: MessageId::new("f1"),
+            peer_id: PeerId::new(PEER),
+            direction: Direction::Sent,
+            content: "Ya voy".to_string(),
+            timestamp: Timestamp::from_millis(1_000),
+            delivered: false,
+            fire_and_forget: true,
+            announcement: false,
+            reply_to: None,
+        })
+        .unwrap();
+        let app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+
+        let response = app.handle_retry_message(&MessageId::new("f1")).await;
+        assert!(matches!(response, ServerMessage::MessageSent { .. }));
+        assert_eq!(received_rx.recv().await.as_deref(), Some("f1"));
+        // Nothing left for the next startup to send again
+        let db = app.db.lock().unwrap();
+        assert!(db.get_pending_peers().unwrap().is_empty());
+        assert!(db.get_undelivered(&PeerId::new(PEER)).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn notifications_name_known_peers() {
+        let db = Database::open_in_memory().unwrap();
+        let mut known = peer_info();
+        known.id = PeerId::new("peer-abuela");
+        known.display_name = "Abuela".to_string();
+        known.avatar = Some(familycom_core::types::Avatar::new("👵").unwrap());
+        db.upsert_peer(&known).unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let names = app.peer_names_watch();
+        // Known from the peer table, without having been seen online
+        assert_eq!(names.borrow().get(&known.id).map(String::as_str), Some("👵 Abuela"));
+
+        // A chat from a peer mDNS never reported names it after its sender name
+        app.handle_incoming_message(IncomingMessage {
+            message: PeerMessage::Chat {
+                id: MessageId::new("m1"),
+                sender_id: PeerId::new(PEER),
+                sender_name: "Cocina".to_string(),
+                content: "hola".to_string(),
+                timestamp: Timestamp::from_millis(1_000),
+                announcement: false,
+                reply_to: None,
+            },
+            from_addr: "192.168.1.20:50123".parse().unwrap(),
+            reply: None,
+        });
+        assert_eq!(names.borrow().get(&PeerId::new(PEER)).map(String::as_str), Some("Cocina"));
+    }
+
+    #[tokio::test]
+    async fn do_not_disturb_can_be_overridden_and_is_seen_on_peers() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let dnd = app.do_not_disturb_watch();
+        assert!(!*dnd.borrow(), "no quiet hours configured");
+
+        app.handle_set_do_not_disturb(Some(true));
+        assert!(*dnd.borrow());
+        assert!(matches!(
+            app.handle_set_do_not_disturb(None),
+            ServerMessage::DoNotDisturb { active: false, manual: None }
+        ));
+
+        // A peer going into do-not-disturb re-announces itself unchanged otherwise
+        let (mut subscriber, _) = app.event_bus().subscribe();
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        let mut quiet = peer_info();
+        quiet.do_not_disturb = true;
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(quiet.clone()));
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(quiet));
+        let mut onlines = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            if let ServerMessage::PeerOnline { peer } = event.message {
+                onlines.push(peer.do_not_disturb);
+            }
+        }
+        assert_eq!(onlines, [false, true]);
+    }
+
+    #[tokio::test]
+    async fn idle_makes_us_away_until_someone_is_back() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let away = app.away_watch();
+        let long_idle = Some(Duration::from_secs(11 * 60));
+
+        // Knowing nothing about input, we're not away
+        app.update_away(None);
+        assert!(!*away.borrow());
+        app.update_away(long_idle);
+        assert!(*away.borrow(), "desktop idle past the default 10 minutes");
+
+        // A key in the TUI counts even when the desktop looks idle
+        assert!(matches!(app.handle_report_activity(), ServerMessage::Ok));
+        assert!(!*away.borrow());
+        app.update_away(long_idle);
+        assert!(!*away.borrow());
+        app.last_input = Instant::now().checked_sub(Duration::from_secs(11 * 60));
+        app.update_away(None);
+        assert!(*away.borrow(), "nothing typed in the TUI for too long");
+
+        app.config.away.idle_minutes = 0;
+        app.update_away(long_idle);
+        assert!(!*away.borrow(), "automatic away turned off");
+
+        // Peers going away are pushed, and listed as away
+        let (mut subscriber, _) = app.event_bus().subscribe();
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        let mut idle_peer = peer_info();
+        idle_peer.away = true;
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(idle_peer));
+        let mut onlines = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            if let ServerMessage::PeerOnline { peer } = event.message {
+                onlines.push(peer.away);
+            }
+        }
+        assert_eq!(onlines, [false, true]);
+        let ServerMessage::PeerList { peers } = app.handle_list_peers() else {
+            panic!("expected PeerList");
+        };
+        assert!(peers.iter().all(|peer| peer.away));
+    }
+
+    #[tokio::test]
+    async fn tray_unread_follows_chats_and_mark_read() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let unread = app.unread_watch();
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        for (id, at) in [("m1", 1_000), ("m2", 2_000)] {
+            app.handle_incoming_message(IncomingMessage {
+                message: PeerMessage::Chat {
+                    id: MessageId::new(id),
+                    sender_id: PeerId::new(PEER),
+                    sender_name: "Cocina".to_string(),
+                    content: "hola".to_string(),
+                    timestamp: Timestamp::from_millis(at),
+                    announcement: false,
+                    reply_to: None,
+                },
+                from_addr: "192.168.1.20:50123".parse().unwrap(),
+                reply: None,
+            });
+        }
+        assert_eq!(
+            *unread.borrow(),
+            Unread {
+                total: 2,
+                senders: vec!["Cocina".to_string()],
+            }
+        );
+        assert_eq!(unread.borrow().tooltip(), "2 mensajes sin leer de Cocina");
+
+        app.handle_mark_read(&PeerId::new(PEER), Some(Timestamp::from_millis(1_000)));
+        assert_eq!(unread.borrow().total, 1);
+        app.handle_mark_read(&PeerId::new(PEER), Some(Timestamp::from_millis(2_000)));
+        assert_eq!(*unread.borrow(), Unread::default());
+    }
+
+    #[tokio::test]
+    async fn mark_read_covers_a_peer_whose_clock_is_ahead() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let unread = app.unread_watch();
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        let tomorrow = Timestamp::from_millis(Timestamp::now().as_millis() + 24 * 60 * 60 * 1000);
+        app.handle_incoming_message(IncomingMessage {
+            message: PeerMessage::Chat {
+                id: MessageId::new("m1"),
+                sender_id: PeerId::new(PEER),
+                sender_name: "Cocina".to_string(),
+                content: "hola".to_string(),
+                timestamp: tomorrow,
+                announcement: false,
+                reply_to: None,
+            },
+            from_addr: "192.168.1.20:50123".parse().unwrap(),
+            reply: None,
+        });
+        assert_eq!(unread.borrow().total, 1);
+
+        assert!(matches!(app.handle_mark_read(&PeerId::new(PEER), None), ServerMessage::Ok));
+        assert_eq!(*unread.borrow(), Unread::default());
+        assert_eq!(app.db.lock().unwrap().last_read_at(&PeerId::new(PEER)).unwrap(), Some(tomorrow));
+    }
+
+    #[tokio::test]
+    async fn tray_lists_online_peers_by_name() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let peers = app.tray_peers_watch();
+        let mut abuela = peer_info();
+        abuela.id = PeerId::new("peer-abuela");
+        abuela.display_name = "abuela".to_string();
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(abuela));
+        let names: Vec<String> = peers.borrow().iter().map(|peer| peer.name.clone()).collect();
+        assert_eq!(names, ["abuela", "Cocina"]);
+
+        app.handle_discovery_event(DiscoveryEvent::PeerLost(PeerId::new(PEER)));
+        assert_eq!(peers.borrow().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pausing_notifications_is_saved_to_the_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        app.set_config_path(path.clone());
+        let config = app.config_watch();
+
+        assert!(matches!(app.handle_set_notifications_enabled(false), ServerMessage::Ok));
+        assert!(!config.borrow().notifications_enabled);
+        assert!(!AppConfig::load_from(&path).unwrap().unwrap().notifications_enabled);
+    }
+
+    #[tokio::test]
+    async fn display_name_is_saved_to_the_config_file_it_was_started_with() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        app.set_config_path(path.clone());
+
+        assert!(matches!(app.handle_set_display_name("Sala de estar"), ServerMessage::Ok));
+        assert_eq!(AppConfig::load_from(&path).unwrap().unwrap().display_name, "Sala de estar");
+        // And a reload keeps it
+        assert!(matches!(app.handle_reload_config(), ServerMessage::ConfigReloaded { .. }));
+        assert_eq!(app.config.display_name, "Sala de estar");
+    }
+
+    #[tokio::test]
+    async fn overdue_messages_are_emailed_once() {
+        // A relay that accepts every email and counts them
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = listener.local_addr().unwrap();
+        let (accepted_tx, mut accepted_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut smtp = tokio::io::BufReader::new(stream);
+                smtp.get_mut().write_all(b"220 relay\r\n").await.unwrap();
+                let mut in_data = false;
+                let mut line = String::new();
+                while smtp.read_line(&mut line).await.unwrap_or(0) > 0 {
+                    let reply: &[u8] = match line.as_str() {
+                        ".\r\n" if in_data => {
+                            in_data = false;
+                            accepted_tx.send(()).unwrap();
+                            b"250 queued\r\n"
+                        }
+                        _ if in_data => b"",
+                        "DATA\r\n" => {
+                            in_data = true;
+                            b"354 go ahead\r\n"
+                        }
+                        _ => b"250 ok\r\n",
+                    };
+                    smtp.get_mut().write_all(reply).await.unwrap();
+                    line.clear();
+                }
+            }
+        });
+
+        let db = Database::open_in_memory().unwrap();
+        db.upsert_peer(&peer_info()).unwrap();
+        let hours_ago = |hours: i64| Timestamp::from_millis(Timestamp::now().as_millis() - hours * 60 * 60 * 1000);
+        for (id, at) in [("s1", hours_ago(6)), ("s2", hours_ago(5)), ("s3", hours_ago(1))] {
+            db.save_message(&Message {
+                id: MessageId::new(id),
+                peer_id: PeerId::new(PEER),
+                direction: Direction::Sent,
+                content: "Se fue la luz".to_string(),
+                timestamp: at,
+                delivered: false,
+                fire_and_forget: false,
+                announcement: false,
+                reply_to: None,
+            })
+            .unwrap();
+        }
+        let mut config = AppConfig::new_first_run("Sala");
+        config.email.smtp_relay = Some(relay.to_string());
+        let mut app = DaemonApp::new(db, config);
+        let (emailed_tx, mut emailed_rx) = mpsc::channel(1);
+
+        // Nobody to email without an address for the peer
+        assert!(!app.start_email_fallback(emailed_tx.clone()));
+
+        app.config.email.addresses.insert("Cocina".to_string(), "cocina@example.com".to_string());
+        // Not across the network in plain text unless allowed
+        app.config.email.smtp_relay = Some("192.168.1.1:25".to_string());
+        assert!(!app.start_email_fallback(emailed_tx.clone()));
+
+        app.config.email.smtp_relay = Some(relay.to_string());
+        assert!(app.start_email_fallback(emailed_tx.clone()));
+        let emailed = emailed_rx.recv().await.unwrap();
+        let ids: Vec<&str> = emailed.iter().map(|id| id.as_str()).collect();
+        assert_eq!(ids, ["s1", "s2"], "one email with both overdue messages");
+        app.record_emailed(emailed);
+        accepted_rx.recv().await.unwrap();
+        assert!(accepted_rx.try_recv().is_err());
+
+        // Already emailed, and s3 isn't overdue yet
+        assert!(!app.start_email_fallback(emailed_tx));
+    }
+
+    #[tokio::test]
+    async fn slash_commands_answer_with_system_messages() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let (mut subscriber, _) = app.event_bus().subscribe();
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        for content in ["/who", "/bailar"] {
+            let (response_tx, mut response_rx) = mpsc::channel(8);
+            let request = ClientRequest::SendMessage {
+                peer_id: PeerId::new(PEER),
+                content: content.to_string(),
+                reply_to: None,
+            };
+            app.handle_ipc_request(IpcRequest { request, response_tx }).await;
+            assert!(matches!(response_rx.try_recv(), Ok(ServerMessage::MessageSent { .. })));
+        }
+
+        // Answered here, and nothing went out to the peer
+        let stored = app.db.lock().unwrap().get_messages(&PeerId::new(PEER), 10, None).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().all(|m| m.direction == Direction::System));
+        assert!(stored.iter().any(|m| m.content == "En linea (1): Cocina."));
+        assert!(stored
+            .iter()
+            .any(|m| m.content.starts_with("Comando desconocido: /bailar. Comandos:")));
+        let mut pushed = 0;
+        while let Ok(event) = subscriber.try_recv() {
+            if matches!(event.message, ServerMessage::NewMessage { message } if message.direction == Direction::System) {
+                pushed += 1;
+            }
+        }
+        assert_eq!(pushed, 2);
+    }
+
+    #[tokio::test]
+    async fn due_scheduled_messages_are_sent_and_pushed() {
+        // The peer's daemon: ACKs every chat on its connection
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                if let Ok(PeerMessage::Chat { id, .. }) = familycom_core::protocol::read_message(&mut stream).await {
+                    let ack = PeerMessage::Ack { message_id: id };
+                    familycom_core::protocol::write_message(&mut stream, &ack).await.unwrap();
+                }
+            }
+        });
+
+        let db = Database::open_in_memory().unwrap();
+        let mut peer = peer_info();
+        peer.addresses = vec![addr.to_string()];
+        db.upsert_peer(&peer).unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer));
+        let (mut subscriber, _) = app.event_bus().subscribe();
+
+        let now = Timestamp::now().as_millis();
+        let peer_id = PeerId::new(PEER);
+        for (content, send_at) in [("Ya es hora", now - 1_000), ("Mañana", now + 86_400_000)] {
+            let response = app.handle_schedule_message(&peer_id, content, Timestamp::from_millis(send_at));
+            assert!(matches!(response, ServerMessage::Ok));
+        }
+        let unknown = app.handle_schedule_message(&PeerId::new("nadie"), "Hola", Timestamp::from_millis(now));
+        assert!(matches!(unknown, ServerMessage::Error { code: ErrorCode::PeerNotFound, .. }));
+
+        app.dispatch_scheduled().await;
+
+        let mut pushed = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            if let ServerMessage::NewMessage { message } = event.message {
+                pushed.push(message);
+            }
+        }
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(pushed[0].content, "Ya es hora");
+        assert_eq!(pushed[0].direction, Direction::Sent);
+        assert!(pushed[0].delivered);
+
+        // Only tomorrow's is left
+        let db = app.db.lock().unwrap();
+        let far_future = Timestamp::from_millis(now + 2 * 86_400_000);
+        let left: Vec<String> = db.due_scheduled_messages(far_future).unwrap().into_iter().map(|m| m.content).collect();
+        assert_eq!(left, ["Mañana"]);
+    }
+
+    #[tokio::test]
+    async fn announcements_go_to_every_online_peer() {
+        // The kitchen's daemon: ACKs every chat and reports whether it came
+        // as an announcement
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let kitchen_addr = listener.local_addr().unwrap();
+        let (received_tx, mut received_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                if let Ok(PeerMessage::Chat { id, announcement, .. }) =
+                    familycom_core::protocol::read_message(&mut stream).await
+                {
+                    let _ = received_tx.send(announcement);
+                    let ack = PeerMessage::Ack { message_id: id };
+                    familycom_core::protocol::write_message(&mut stream, &ack).await.unwrap();
+                }
+            }
+        });
+        // The study's daemon just went away: connections are refused
+        let gone = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let study_addr = gone.local_addr().unwrap();
+        drop(gone);
+
+        let mut kitchen = peer_info();
+        kitchen.addresses = vec![kitchen_addr.to_string()];
+        let study = PeerInfo {
+            id: PeerId::new("peer-estudio"),
+            display_name: "Estudio".to_string(),
+            addresses: vec![study_addr.to_string()],
+            ..peer_info()
+        };
+        let mut app = DaemonApp::new(Database::open_in_memory().unwrap(), AppConfig::new_first_run("Sala"));
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(kitchen));
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(study));
+        let (mut subscriber, _) = app.event_bus().subscribe();
+
+        let ServerMessage::Announced { message_ids } = app.handle_announce("A cenar!").await else {
+            panic!("announcement not sent");
+        };
+        assert_eq!(message_ids.len(), 2);
+        assert_eq!(received_rx.recv().await, Some(true));
+
+        let mut pushed = Vec::new();
+        let mut delivered = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            match event.message {
+                ServerMessage::NewMessage { message } => pushed.push(message),
+                ServerMessage::MessageDelivered { message_id } => delivered.push(message_id),
+                _ => {}
+            }
+        }
+        assert_eq!(pushed.len(), 2);
+        assert!(pushed.iter().all(|m| m.announcement && m.direction == Direction::Sent));
+        // Only the kitchen answered
+        assert_eq!(delivered.len(), 1);
+        let kitchen_copy = pushed.iter().find(|m| m.peer_id == PeerId::new(PEER)).unwrap();
+        assert_eq!(delivered[0], kitchen_copy.id);
+
+        // Announcements from others are stored as such, and get through
+        // do-not-disturb
+        let mut app = DaemonApp::new(Database::open_in_memory().unwrap(), AppConfig::new_first_run("Sala"));
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        app.handle_incoming_message(IncomingMessage {
+            message: PeerMessage::Chat {
+                id: MessageId::new("a1"),
+                sender_id: PeerId::new(PEER),
+                sender_name: "Cocina".to_string(),
+                content: "Nos vamos en 5 minutos".to_string(),
+                timestamp: Timestamp::from_millis(2_000),
+                announcement: true,
+                reply_to: None,
+            },
+            from_addr: "192.168.1.20:50123".parse().unwrap(),
+            reply: None,
+        });
+        let stored = app.db.lock().unwrap().get_messages(&PeerId::new(PEER), 10, None).unwrap();
+        assert!(stored[0].announcement);
+        assert!(stored[0].is_urgent());
+
+        let empty = DaemonApp::new(Database::open_in_memory().unwrap(), AppConfig::new_first_run("Sala"))
+            .handle_announce("Hola")
+            .await;
+        assert!(matches!(empty, ServerMessage::Announced { message_ids } if message_ids.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn kid_mode_ignores_peers_outside_the_allow_list() {
+        let mut config = AppConfig::new_first_run("Cuarto de Sofi");
+        config.kid_mode.enabled = true;
+        config.kid_mode.allowed = vec!["peer-mama".to_string()];
+        let mut app = DaemonApp::new(Database::open_in_memory().unwrap(), config);
+        let (mut subscriber, _) = app.event_bus().subscribe();
+        let from_addr: SocketAddr = "192.168.1.20:50123".parse().unwrap();
+
+        let stranger = chat("m1", "Hola, ¿quién eres?", 1_000);
+        let family = PeerMessage::Chat {
+            id: MessageId::new("m2"),
+            sender_id: PeerId::new("peer-mama"),
+            sender_name: "Mamá".to_string(),
+            content: "A cenar".to_string(),
+            timestamp: Timestamp::from_millis(2_000),
+            announcement: false,
+            reply_to: None,
+        };
+        for message in [stranger, family] {
+            app.handle_incoming_message(IncomingMessage {
+                message,
+                from_addr,
+                reply: None,
+            });
+        }
+
+        // Only mom's message got through; the stranger's is flagged
+        let mut pushed = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            if let ServerMessage::NewMessage { message } = event.message {
+                pushed.push(message.id.as_str().to_string());
+            }
+        }
+        assert_eq!(pushed, ["m2"]);
+        {
+            let db = app.db.lock().unwrap();
+            assert!(db.get_messages(&PeerId::new(PEER), 10, None).unwrap().is_empty());
+            let audit = db.get_audit_log(10, None).unwrap();
+            assert_eq!(audit.len(), 1);
+            assert_eq!(audit[0].action, AuditAction::KidModeBlocked);
+            assert_eq!(audit[0].peer_id, Some(PeerId::new(PEER)));
+        }
+
+        // Nor can anything be sent their way
+        app.handle_discovery_event(DiscoveryEvent::PeerFound(peer_info()));
+        let refused = app.handle_send_message(&PeerId::new(PEER), "Hola", None).await;
+        assert!(matches!(refused, ServerMessage::Error { code: ErrorCode::PeerNotAllowed, .. }));
+        let ServerMessage::Announced { message_ids } = app.handle_announce("A dormir").await else {
+            panic!("announcement not sent");
+        };
+        assert!(message_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn open_conversation_is_not_notified_while_its_client_is_there() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let viewers = app.viewers_watch();
+        let peer = PeerId::new(PEER);
+        let (tui, tui_rx) = mpsc::channel(8);
+        let (other, _other_rx) = mpsc::channel(8);
+
+        assert!(matches!(app.handle_set_viewing(&tui, Some(peer.clone()), true), ServerMessage::Ok));
+        assert!(notifications::is_viewed(&viewers.borrow(), &peer));
+
+        // Another client showing nothing doesn't undo this one
+        app.handle_set_viewing(&other, None, true);
+        assert!(notifications::is_viewed(&viewers.borrow(), &peer));
+
+        // Unfocused, or gone, it no longer counts
+        app.handle_set_viewing(&tui, Some(peer.clone()), false);
+        assert!(!notifications::is_viewed(&viewers.borrow(), &peer));
+        app.handle_set_viewing(&tui, Some(peer.clone()), true);
+        drop(tui_rx);
+        assert!(!notifications::is_viewed(&viewers.borrow(), &peer));
+
+        // and is dropped the next time anyone reports
+        app.handle_set_viewing(&other, None, false);
+        assert!(viewers.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn typing_notices_are_pushed_but_not_stored() {
+        let mut config = AppConfig::new_first_run("Cuarto de Sofi");
+        config.kid_mode.enabled = true;
+        config.kid_mode.allowed = vec!["peer-mama".to_string()];
+        let mut app = DaemonApp::new(Database::open_in_memory().unwrap(), config);
+        let (mut subscriber, _) = app.event_bus().subscribe();
+        let from_addr: SocketAddr = "192.168.1.20:50123".parse().unwrap();
+
+        for sender in [PEER, "peer-mama"] {
+            app.handle_incoming_message(IncomingMessage {
+                message: PeerMessage::Typing {
+                    sender_id: PeerId::new(sender),
+                },
+                from_addr,
+                reply: None,
+            });
+        }
+
+        // Only mom's, and a stranger typing isn't worth an audit entry
+        let mut pushed = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            pushed.push(event.message);
+        }
+        assert!(
+            matches!(&pushed[..], [ServerMessage::PeerTyping { peer_id }] if peer_id.as_str() == "peer-mama"),
+            "got {pushed:?}"
+        );
+        let db = app.db.lock().unwrap();
+        assert!(db.get_messages(&PeerId::new("peer-mama"), 10, None).unwrap().is_empty());
+        assert!(db.get_audit_log(10, None).unwrap().is_empty());
+        drop(db);
+
+        // Telling a peer we don't know about is quietly skipped
+        let reply = app.handle_report_typing(&PeerId::new("peer-mama"));
+        assert!(matches!(reply, ServerMessage::Ok));
+    }
+
+    #[tokio::test]
+    async fn read_receipts_are_saved_and_pushed() {
+        let db = Database::open_in_memory().unwrap();
+        let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+        let (mut subscriber, _) = app.event_bus().subscribe();
+        let chat = chat("m1", "¿Bajas a cenar?", 1_000);
+        // The later receipt arrives first; the earlier one changes nothing
+        let receipt = |up_to| PeerMessage::Read {
+            sender_id: PeerId::new(PEER),
+            up_to: Timestamp::from_millis(up_to),
+        };
+        for message in [chat, receipt(5_000), receipt(3_000)] {
+            app.handle_incoming_message(IncomingMessage {
+                message,
+                from_addr: "192.168.1.20:50123".parse().unwrap(),
+                reply: None,
+            });
+        }
+
+        let mut receipts = Vec::new();
+        while let Ok(event) = subscriber.try_recv() {
+            if let ServerMessage::MessagesRead { peer_id, up_to } = event.message {
+                assert_eq!(peer_id, PeerId::new(PEER));
+                receipts.push(up_to.as_millis());
+            }
+        }
+        assert_eq!(receipts, [5_000, 3_000]);
+        let summaries = app.db.lock().unwrap().get_conversation_summaries().unwrap();
+        assert_eq!(summaries[0].read_by_peer, Some(Timestamp::from_millis(5_000)));
+    }
 }
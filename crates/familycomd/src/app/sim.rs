@@ -0,0 +1,278 @@
+//! Deterministic simulation of the daemon's event loop.
+//!
+//! The main loop sees discovery, TCP and IPC events in whatever order the
+//! network and the OS deliver them: each peer message arrives on its own
+//! connection, so a retry can land after the original and a retraction
+//! before the chat it takes back. These tests feed `DaemonApp` one fixed
+//! set of events in many seeded orders and check invariants that must hold
+//! for every order. A failure names its seed and order so it can be replayed.
+
+use super::tests::{peer_info, PEER};
+use super::*;
+
+/// Orders tried per test. Each run uses a fresh in-memory database, so
+/// this stays fast.
+const SEEDS: u64 = 300;
+
+/// Xorshift PRNG: enough to shuffle, and the same seed always gives the
+/// same order.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Fisher–Yates shuffle.
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// One input to the main loop.
+#[derive(Debug, Clone)]
+enum Event {
+    Discovery(DiscoveryEvent),
+    Tcp(PeerMessage),
+    Ipc(ClientRequest),
+}
+
+impl Event {
+    fn is_discovery(&self) -> bool {
+        matches!(self, Event::Discovery(_))
+    }
+}
+
+/// Everything observable after a run.
+struct Outcome {
+    /// Messages stored for the peer, newest first.
+    stored: Vec<Message>,
+    /// Events pushed to subscribed clients, in order.
+    pushed: Vec<ServerMessage>,
+    /// Responses to the IPC requests, in order.
+    responses: Vec<ServerMessage>,
+    /// Whether the daemon considers the peer online at the end.
+    online: bool,
+}
+
+fn chat(id: &str, content: &str, at: i64) -> Event {
+    Event::Tcp(tests::chat(id, content, at))
+}
+
+/// The fixed script every test permutes: the peer announcing itself twice
+/// and leaving, three chats (the first retried, the last retracted and
+/// retried) and a client reading the conversation and marking it read.
+fn script() -> Vec<Event> {
+    vec![
+        Event::Discovery(DiscoveryEvent::PeerFound(peer_info())),
+        Event::Discovery(DiscoveryEvent::PeerFound(peer_info())),
+        Event::Discovery(DiscoveryEvent::PeerLost(PeerId::new(PEER))),
+        chat("m1", "¿Bajas a cenar?", 2_000),
+        chat("m1", "¿Bajas a cenar?", 2_000),
+        chat("m2", "Ya está la mesa", 3_000),
+        chat("m3", "mensaje equivocado", 4_000),
+        chat("m3", "mensaje equivocado", 4_000),
+        Event::Tcp(PeerMessage::Retract {
+            message_id: MessageId::new("m3"),
+            sender_id: PeerId::new(PEER),
+        }),
+        Event::Ipc(ClientRequest::GetMessages {
+            peer_id: PeerId::new(PEER),
+            limit: 50,
+            before: None,
+            cursor: None,
+        }),
+        Event::Ipc(ClientRequest::MarkRead {
+            peer_id: PeerId::new(PEER),
+            up_to: None,
+        }),
+    ]
+}
+
+/// Feeds `events` to a fresh daemon in order and collects the outcome.
+async fn run(events: Vec<Event>) -> Outcome {
+    let db = Database::open_in_memory().unwrap();
+    let mut app = DaemonApp::new(db, AppConfig::new_first_run("Sala"));
+    let (mut subscriber, _) = app.event_bus().subscribe();
+    let from_addr: SocketAddr = "192.168.1.20:50123".parse().unwrap();
+
+    let mut responses = Vec::new();
+    for event in events {
+        match event {
+            Event::Discovery(event) => app.handle_discovery_event(event),
+            Event::Tcp(message) => app.handle_incoming_message(IncomingMessage {
+                message,
+                from_addr,
+                reply: None,
+            }),
+            Event::Ipc(request) => {
+                let (response_tx, mut response_rx) = mpsc::channel(8);
+                app.handle_ipc_request(IpcRequest { request, response_tx }).await;
+                while let Ok(response) = response_rx.try_recv() {
+                    responses.push(response);
+                }
+            }
+        }
+    }
+
+    // What the main loop does when it stops
+    app.flush_floods();
+    let mut pushed = Vec::new();
+    while let Ok(event) = subscriber.try_recv() {
+        pushed.push(event.message);
+    }
+    let stored = app
+        .db
+        .lock()
+        .unwrap()
+        .get_messages(&PeerId::new(PEER), 100, None)
+        .unwrap();
+    Outcome {
+        stored,
+        pushed,
+        responses,
+        online: app.online_peers.contains_key(&PeerId::new(PEER)),
+    }
+}
+
+/// Runs the script in `SEEDS` orders, handing each outcome to `check`
+/// along with a description of the order for failure messages.
+async fn for_each_order(check: impl Fn(&Outcome, &[Event], &str)) {
+    for seed in 0..SEEDS {
+        let mut events = script();
+        Rng::new(seed).shuffle(&mut events);
+        let outcome = run(events.clone()).await;
+        check(&outcome, &events, &format!("seed {seed}: {events:?}"));
+    }
+}
+
+fn count_pushes(pushed: &[ServerMessage], id: &str) -> (usize, usize) {
+    let new = pushed
+        .iter()
+        .filter(|e| matches!(e, ServerMessage::NewMessage { message } if message.id.as_str() == id))
+        .count();
+    let deleted = pushed
+        .iter()
+        .filter(|e| matches!(e, ServerMessage::MessageDeleted { message_id, .. } if message_id.as_str() == id))
+        .count();
+    (new, deleted)
+}
+
+#[test]
+fn shuffle_is_a_permutation_and_deterministic() {
+    let mut a: Vec<u32> = (0..20).collect();
+    let mut b = a.clone();
+    Rng::new(7).shuffle(&mut a);
+    Rng::new(7).shuffle(&mut b);
+    assert_eq!(a, b);
+
+    let mut sorted = a.clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn no_message_is_lost_or_stored_twice() {
+    for_each_order(|outcome, _, order| {
+        let ids: Vec<&str> = outcome.stored.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["m2", "m1"], "{order}");
+        assert!(outcome.stored.iter().all(|m| m.direction == Direction::Received), "{order}");
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn each_message_is_pushed_once() {
+    for_each_order(|outcome, _, order| {
+        for id in ["m1", "m2"] {
+            assert_eq!(count_pushes(&outcome.pushed, id), (1, 0), "{id} in {order}");
+        }
+        // Pushed then taken back, or dropped before anyone saw it
+        let (new, deleted) = count_pushes(&outcome.pushed, "m3");
+        assert!(new <= 1 && new == deleted, "m3 pushed {new}, deleted {deleted} in {order}");
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn history_pages_never_repeat_a_message() {
+    for_each_order(|outcome, _, order| {
+        for response in &outcome.responses {
+            if let ServerMessage::Messages { messages, .. } = response {
+                let mut ids: Vec<&str> = messages.iter().map(|m| m.id.as_str()).collect();
+                ids.sort();
+                ids.dedup();
+                assert_eq!(ids.len(), messages.len(), "{order}");
+            }
+        }
+        assert!(
+            !outcome.responses.iter().any(|r| matches!(r, ServerMessage::Error { .. })),
+            "{order}: {:?}",
+            outcome.responses
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn presence_follows_the_last_discovery_event() {
+    for_each_order(|outcome, events, order| {
+        let last = events.iter().rev().find(|e| e.is_discovery()).unwrap();
+        let found = matches!(last, Event::Discovery(DiscoveryEvent::PeerFound(_)));
+        assert_eq!(outcome.online, found, "{order}");
+
+        // Clients see online/offline alternate, never the same state twice
+        let mut online = false;
+        for event in &outcome.pushed {
+            let now_online = match event {
+                ServerMessage::PeerOnline { .. } => true,
+                ServerMessage::PeerOffline { .. } => false,
+                _ => continue,
+            };
+            assert_ne!(online, now_online, "{order}");
+            online = now_online;
+        }
+        assert_eq!(online, found, "{order}");
+    })
+    .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_flood_is_pushed_as_one_batch() {
+    let mut events: Vec<Event> = (0..25).map(|i| chat(&format!("f{i}"), "jajaja", 10_000 + i)).collect();
+    events.push(Event::Tcp(PeerMessage::Retract {
+        message_id: MessageId::new("f20"),
+        sender_id: PeerId::new(PEER),
+    }));
+    let outcome = run(events).await;
+    assert_eq!(outcome.stored.len(), 24);
+
+    // The burst allowance one by one, the rest together and in order,
+    // without the one taken back before anyone saw it
+    let singles = outcome
+        .pushed
+        .iter()
+        .filter(|e| matches!(e, ServerMessage::NewMessage { .. }))
+        .count();
+    assert_eq!(singles, CHAT_BURST as usize);
+    let batches: Vec<Vec<&str>> = outcome
+        .pushed
+        .iter()
+        .filter_map(|e| match e {
+            ServerMessage::NewMessages { messages, .. } => Some(messages.iter().map(|m| m.id.as_str()).collect()),
+            _ => None,
+        })
+        .collect();
+    let expected: Vec<String> = (10..25).filter(|i| *i != 20).map(|i| format!("f{i}")).collect();
+    assert_eq!(batches, [expected.iter().map(String::as_str).collect::<Vec<_>>()]);
+}
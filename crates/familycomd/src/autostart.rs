@@ -8,17 +8,28 @@
 //!   This is the XDG Autostart standard, supported by GNOME, KDE, XFCE,
 //!   and most other desktop environments.
 //!
+//! - **Linux with `--systemd`**: Creates a user unit in
+//!   `~/.config/systemd/user/` and enables it. The unit is `Type=notify`:
+//!   the daemon reports readiness itself (see `systemd`), so
+//!   `systemctl --user status familycomd` only says "active" once it's
+//!   actually listening. It replaces the XDG entry (and vice versa), so the
+//!   daemon isn't started twice.
+//!
 //! - **macOS**: Creates a LaunchAgent plist in `~/Library/LaunchAgents/`.
 //!   launchd loads this automatically on login and keeps the daemon alive.
 //!
+//! - **Windows**: Adds a `FamilyCom` value to the per-user `Run` registry
+//!   key, which Windows starts on login. The daemon hides the console window
+//!   it gets from that (see `main`).
+//!
 //! # Binary Path Resolution
 //!
 //! The autostart config points to the *absolute path* of the currently
 //! running `familycomd` binary. This means if you move the binary after
 //! running `install`, you'll need to re-run `install` to update the path.
 
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
 
 /// The name of the Linux autostart desktop entry file.
 const DESKTOP_FILENAME: &str = "familycom.desktop";
@@ -26,15 +37,26 @@ const DESKTOP_FILENAME: &str = "familycom.desktop";
 /// The name of the macOS LaunchAgent plist file.
 const PLIST_FILENAME: &str = "com.familycom.daemon.plist";
 
+/// The name of the systemd user unit.
+const SYSTEMD_UNIT: &str = "familycomd.service";
+
 /// Installs autostart configuration for the current platform.
 ///
+/// With `systemd`, installs a systemd user unit instead (Linux only).
 /// If `dry_run` is true, prints what would be done without making changes.
-pub fn install(dry_run: bool) -> Result<()> {
+pub fn install(dry_run: bool, systemd: bool) -> Result<()> {
     let binary_path = std::env::current_exe()
         .context("could not determine path to familycomd binary")?;
 
-    if cfg!(target_os = "macos") {
+    if systemd {
+        if !cfg!(target_os = "linux") {
+            bail!("--systemd is only available on Linux");
+        }
+        install_systemd(&binary_path, dry_run)
+    } else if cfg!(target_os = "macos") {
         install_macos(&binary_path, dry_run)
+    } else if cfg!(windows) {
+        install_windows(&binary_path, dry_run)
     } else {
         install_linux(&binary_path, dry_run)
     }
@@ -46,11 +68,27 @@ pub fn install(dry_run: bool) -> Result<()> {
 pub fn uninstall(dry_run: bool) -> Result<()> {
     if cfg!(target_os = "macos") {
         uninstall_macos(dry_run)
+    } else if cfg!(windows) {
+        uninstall_windows(dry_run)
     } else {
         uninstall_linux(dry_run)
     }
 }
 
+/// Returns whether an autostart entry for the current platform exists.
+pub fn is_installed() -> Result<bool> {
+    let path = if cfg!(target_os = "macos") {
+        macos_launch_agents_dir()?.join(PLIST_FILENAME)
+    } else if cfg!(windows) {
+        return Ok(windows_run_entry_exists());
+    } else if systemd_unit_dir()?.join(SYSTEMD_UNIT).exists() {
+        return Ok(true);
+    } else {
+        linux_autostart_dir()?.join(DESKTOP_FILENAME)
+    };
+    Ok(path.exists())
+}
+
 // ---------------------------------------------------------------------------
 // Linux: XDG Autostart (.desktop file)
 // ---------------------------------------------------------------------------
@@ -65,7 +103,7 @@ fn linux_autostart_dir() -> Result<PathBuf> {
 }
 
 /// Installs the autostart desktop entry on Linux.
-fn install_linux(binary_path: &std::path::Path, dry_run: bool) -> Result<()> {
+fn install_linux(binary_path: &Path, dry_run: bool) -> Result<()> {
     let autostart_dir = linux_autostart_dir()?;
     let desktop_file = autostart_dir.join(DESKTOP_FILENAME);
 
@@ -96,6 +134,11 @@ fn install_linux(binary_path: &std::path::Path, dry_run: bool) -> Result<()> {
         return Ok(());
     }
 
+    let unit_file = systemd_unit_dir()?.join(SYSTEMD_UNIT);
+    if unit_file.exists() {
+        remove_systemd_unit(&unit_file)?;
+    }
+
     // Create the autostart directory if it doesn't exist
     std::fs::create_dir_all(&autostart_dir)
         .context("failed to create autostart directory")?;
@@ -108,29 +151,144 @@ fn install_linux(binary_path: &std::path::Path, dry_run: bool) -> Result<()> {
     Ok(())
 }
 
-/// Removes the autostart desktop entry on Linux.
+/// Removes the autostart desktop entry and/or systemd unit on Linux.
 fn uninstall_linux(dry_run: bool) -> Result<()> {
     let autostart_dir = linux_autostart_dir()?;
     let desktop_file = autostart_dir.join(DESKTOP_FILENAME);
+    let unit_file = systemd_unit_dir()?.join(SYSTEMD_UNIT);
 
-    if !desktop_file.exists() {
+    if !desktop_file.exists() && !unit_file.exists() {
         println!("No autostart configuration found at: {}", desktop_file.display());
         return Ok(());
     }
 
     if dry_run {
-        println!("[dry-run] Would remove: {}", desktop_file.display());
+        for file in [&unit_file, &desktop_file] {
+            if file.exists() {
+                println!("[dry-run] Would remove: {}", file.display());
+            }
+        }
         return Ok(());
     }
 
-    std::fs::remove_file(&desktop_file)
-        .with_context(|| format!("failed to remove {}", desktop_file.display()))?;
+    if unit_file.exists() {
+        remove_systemd_unit(&unit_file)?;
+    }
+    if desktop_file.exists() {
+        std::fs::remove_file(&desktop_file)
+            .with_context(|| format!("failed to remove {}", desktop_file.display()))?;
+        println!("Autostart removed: {}", desktop_file.display());
+    }
 
-    println!("Autostart removed: {}", desktop_file.display());
     println!("FamilyCom daemon will no longer start on login.");
     Ok(())
 }
 
+// ---------------------------------------------------------------------------
+// Linux: systemd user unit
+// ---------------------------------------------------------------------------
+
+/// Returns the directory for systemd user units
+/// (`$XDG_CONFIG_HOME/systemd/user/`).
+fn systemd_unit_dir() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir()
+        .context("could not determine XDG config directory")?;
+    Ok(config_dir.join("systemd").join("user"))
+}
+
+/// Installs and enables the systemd user unit.
+///
+/// The unit:
+/// - Waits for the daemon's `READY=1` before counting it as started
+///   (`Type=notify`)
+/// - Restarts it if it crashes (`Restart=on-failure`)
+/// - Reloads config.toml on `systemctl --user reload` (SIGHUP)
+/// - Runs it without the tray, which needs a graphical session that a
+///   user service may start before
+fn install_systemd(binary_path: &Path, dry_run: bool) -> Result<()> {
+    let unit_dir = systemd_unit_dir()?;
+    let unit_file = unit_dir.join(SYSTEMD_UNIT);
+
+    let content = format!(
+        "[Unit]\n\
+         Description=FamilyCom LAN messenger daemon\n\
+         Documentation=man:familycomd(1)\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart=\"{}\" --no-tray\n\
+         ExecReload=/bin/kill -HUP $MAINPID\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        binary_path.display()
+    );
+
+    if dry_run {
+        println!("[dry-run] Would create: {}", unit_file.display());
+        println!("[dry-run] Content:");
+        println!("{content}");
+        println!("[dry-run] Would run: systemctl --user enable {SYSTEMD_UNIT}");
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(&unit_dir)
+        .context("failed to create systemd user unit directory")?;
+
+    std::fs::write(&unit_file, content)
+        .with_context(|| format!("failed to write {}", unit_file.display()))?;
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", SYSTEMD_UNIT])?;
+
+    // Both entries would start a daemon on login
+    let desktop_file = linux_autostart_dir()?.join(DESKTOP_FILENAME);
+    if desktop_file.exists() {
+        std::fs::remove_file(&desktop_file)
+            .with_context(|| format!("failed to remove {}", desktop_file.display()))?;
+        println!("Replaced autostart entry: {}", desktop_file.display());
+    }
+
+    println!("systemd user service installed: {}", unit_file.display());
+    println!("FamilyCom daemon will start on your next login.");
+    println!();
+    println!("To start it now without logging out:");
+    println!("  systemctl --user start familycomd");
+    Ok(())
+}
+
+/// Disables (and stops) the systemd user unit, then removes it.
+fn remove_systemd_unit(unit_file: &Path) -> Result<()> {
+    // Ignore errors — might not be enabled, or systemd isn't running
+    let _ = systemctl(&["disable", "--now", SYSTEMD_UNIT]);
+
+    std::fs::remove_file(unit_file)
+        .with_context(|| format!("failed to remove {}", unit_file.display()))?;
+    let _ = systemctl(&["daemon-reload"]);
+
+    println!("systemd user service removed: {}", unit_file.display());
+    Ok(())
+}
+
+/// Runs `systemctl --user <args>`, failing if it doesn't succeed.
+fn systemctl(args: &[&str]) -> Result<()> {
+    let output = std::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .context("failed to run systemctl")?;
+    if !output.status.success() {
+        bail!(
+            "`systemctl --user {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // macOS: LaunchAgent plist
 // ---------------------------------------------------------------------------
@@ -148,7 +306,7 @@ fn macos_launch_agents_dir() -> Result<PathBuf> {
 /// - Start the daemon on login (`RunAtLoad`)
 /// - Restart it if it crashes (`KeepAlive`)
 /// - Redirect stdout/stderr to log files in /tmp/
-fn install_macos(binary_path: &std::path::Path, dry_run: bool) -> Result<()> {
+fn install_macos(binary_path: &Path, dry_run: bool) -> Result<()> {
     let agents_dir = macos_launch_agents_dir()?;
     let plist_file = agents_dir.join(PLIST_FILENAME);
 
@@ -227,3 +385,77 @@ fn uninstall_macos(dry_run: bool) -> Result<()> {
     println!("FamilyCom daemon will no longer start on login.");
     Ok(())
 }
+
+// ---------------------------------------------------------------------------
+// Windows: Run registry key
+// ---------------------------------------------------------------------------
+
+/// The per-user registry key whose values Windows starts on login.
+const WINDOWS_RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+/// Our value under `WINDOWS_RUN_KEY`.
+const WINDOWS_RUN_VALUE: &str = "FamilyCom";
+
+/// Adds the Run key entry on Windows.
+///
+/// Goes through `reg.exe`, the way macOS goes through `launchctl`, so
+/// there's no registry API to link against.
+fn install_windows(binary_path: &Path, dry_run: bool) -> Result<()> {
+    // Quoted: the path usually has spaces ("C:\Program Files\...")
+    let command = format!("\"{}\"", binary_path.display());
+
+    if dry_run {
+        println!("[dry-run] Would set: {WINDOWS_RUN_KEY}\\{WINDOWS_RUN_VALUE}");
+        println!("[dry-run] Value: {command}");
+        return Ok(());
+    }
+
+    reg(&["add", WINDOWS_RUN_KEY, "/v", WINDOWS_RUN_VALUE, "/t", "REG_SZ", "/d", &command, "/f"])?;
+
+    println!("Autostart installed: {WINDOWS_RUN_KEY}\\{WINDOWS_RUN_VALUE}");
+    println!("FamilyCom daemon will start on your next login.");
+    Ok(())
+}
+
+/// Removes the Run key entry on Windows.
+fn uninstall_windows(dry_run: bool) -> Result<()> {
+    if !windows_run_entry_exists() {
+        println!("No autostart configuration found at: {WINDOWS_RUN_KEY}\\{WINDOWS_RUN_VALUE}");
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("[dry-run] Would remove: {WINDOWS_RUN_KEY}\\{WINDOWS_RUN_VALUE}");
+        return Ok(());
+    }
+
+    reg(&["delete", WINDOWS_RUN_KEY, "/v", WINDOWS_RUN_VALUE, "/f"])?;
+
+    println!("Autostart removed: {WINDOWS_RUN_KEY}\\{WINDOWS_RUN_VALUE}");
+    println!("FamilyCom daemon will no longer start on login.");
+    Ok(())
+}
+
+/// Whether our value exists under the Run key.
+fn windows_run_entry_exists() -> bool {
+    std::process::Command::new("reg")
+        .args(["query", WINDOWS_RUN_KEY, "/v", WINDOWS_RUN_VALUE])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Runs `reg.exe <args>`, failing if it doesn't succeed.
+fn reg(args: &[&str]) -> Result<()> {
+    let output = std::process::Command::new("reg")
+        .args(args)
+        .output()
+        .context("failed to run reg.exe")?;
+    if !output.status.success() {
+        bail!(
+            "`reg {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
@@ -15,6 +15,14 @@
 //!
 //! If performance becomes an issue, we can add connection pooling later.
 //!
+//! # Fire-and-forget
+//!
+//! Peers configured with `DeliveryMode::FireAndForget` skip step 3 from
+//! the caller's point of view: the send returns as soon as the frame is
+//! written. The connection is still kept open in the background until the
+//! ACK arrives (or times out), because the receiver drops a message whose
+//! ACK it couldn't write.
+//!
 //! # Timeout
 //!
 //! All operations have a timeout to handle unreachable peers gracefully.
@@ -22,6 +30,7 @@
 //! the timeout prevents us from blocking forever.
 
 use familycom_core::protocol::{self, PeerMessage, ProtocolError};
+use familycom_core::types::DeliveryMode;
 use std::time::Duration;
 use thiserror::Error;
 use tokio::net::TcpStream;
@@ -46,6 +55,9 @@ pub enum ClientError {
     #[error("timed out waiting for ACK from {addr}")]
     AckTimeout { addr: String },
 
+    #[error("timed out waiting for a response from {addr}")]
+    ResponseTimeout { addr: String },
+
     #[error("protocol error: {0}")]
     Protocol(#[from] ProtocolError),
 
@@ -64,12 +76,18 @@ pub enum ClientError {
 ///
 /// * `addr` - The peer's address as "ip:port" string (e.g., "192.168.1.10:9876")
 /// * `message` - The message to send (usually a `PeerMessage::Chat`)
+/// * `mode` - Whether to wait for the ACK (see the module docs)
 ///
 /// # Returns
 ///
-/// `Ok(())` if the message was sent and acknowledged.
+/// `Ok(())` if the message was sent and acknowledged (or just written, for
+/// `DeliveryMode::FireAndForget`).
 /// `Err(...)` if the connection failed, timed out, or the peer didn't ACK.
-pub async fn send_message(addr: &str, message: &PeerMessage) -> Result<(), ClientError> {
+pub async fn send_message(
+    addr: &str,
+    message: &PeerMessage,
+    mode: DeliveryMode,
+) -> Result<(), ClientError> {
     // Step 1: Establish TCP connection with timeout
     debug!(addr, "connecting to peer");
     let mut stream = match timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
@@ -89,12 +107,27 @@ pub async fn send_message(addr: &str, message: &PeerMessage) -> Result<(), Clien
     };
 
     // Step 2: Send the message
-    let (mut reader, mut writer) = stream.split();
-    protocol::write_message(&mut writer, message).await?;
+    protocol::write_message(&mut stream, message).await?;
+
+    if mode == DeliveryMode::FireAndForget {
+        debug!(addr, "message sent, not waiting for ACK");
+        let addr = addr.to_string();
+        tokio::spawn(async move {
+            match timeout(ACK_TIMEOUT, protocol::read_message(&mut stream)).await {
+                Ok(Ok(PeerMessage::Ack { message_id })) => {
+                    debug!(message_id = %message_id, addr, "received ACK for fire-and-forget message");
+                }
+                Ok(Ok(_)) | Ok(Err(_)) | Err(_) => {
+                    debug!(addr, "no ACK for fire-and-forget message");
+                }
+            }
+        });
+        return Ok(());
+    }
     debug!(addr, "message sent, waiting for ACK");
 
     // Step 3: Wait for ACK with timeout
-    let response = match timeout(ACK_TIMEOUT, protocol::read_message(&mut reader)).await {
+    let response = match timeout(ACK_TIMEOUT, protocol::read_message(&mut stream)).await {
         Ok(Ok(msg)) => msg,
         Ok(Err(e)) => return Err(ClientError::Protocol(e)),
         Err(_) => {
@@ -119,6 +152,70 @@ pub async fn send_message(addr: &str, message: &PeerMessage) -> Result<(), Clien
     }
 }
 
+/// Sends a request that is answered with something other than an ACK
+/// (e.g. `HistoryQuery`) and returns the answer.
+///
+/// Tries each address in turn, like `send_to_any`.
+pub async fn query_any(addresses: &[String], request: &PeerMessage) -> Result<PeerMessage, ClientError> {
+    let mut last_error = ClientError::NoAddress;
+    for addr in addresses {
+        match query(addr, request).await {
+            Ok(response) => return Ok(response),
+            Err(e) => {
+                warn!(addr, error = %e, "query failed at this address, trying next");
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Sends `request` to one address and reads a single response frame.
+async fn query(addr: &str, request: &PeerMessage) -> Result<PeerMessage, ClientError> {
+    let mut stream = match timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            return Err(ClientError::Connect {
+                addr: addr.to_string(),
+                source: e,
+            });
+        }
+        Err(_) => {
+            return Err(ClientError::ConnectTimeout {
+                addr: addr.to_string(),
+                timeout: CONNECT_TIMEOUT,
+            });
+        }
+    };
+    protocol::write_message(&mut stream, request).await?;
+    match timeout(ACK_TIMEOUT, protocol::read_message(&mut stream)).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(ClientError::ResponseTimeout {
+            addr: addr.to_string(),
+        }),
+    }
+}
+
+/// Checks that a peer's daemon answers at any of its addresses, by sending
+/// a `Ping` and waiting for the `Pong`.
+pub async fn ping_any(addresses: &[String]) -> Result<(), ClientError> {
+    let mut last_error = ClientError::NoAddress;
+    for addr in addresses {
+        match query(addr, &PeerMessage::Ping).await {
+            Ok(PeerMessage::Pong) => return Ok(()),
+            Ok(response) => {
+                debug!(addr, ?response, "expected Pong");
+                last_error = ClientError::UnexpectedResponse { addr: addr.clone() };
+            }
+            Err(e) => {
+                debug!(addr, error = %e, "ping failed at this address");
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
 /// Tries to send a message to a peer using any of their known addresses.
 ///
 /// Iterates through the peer's address list and tries each one until
@@ -129,6 +226,7 @@ pub async fn send_message(addr: &str, message: &PeerMessage) -> Result<(), Clien
 ///
 /// * `addresses` - List of "ip:port" strings for the peer
 /// * `message` - The message to send
+/// * `mode` - Whether to wait for the ACK
 ///
 /// # Returns
 ///
@@ -137,6 +235,7 @@ pub async fn send_message(addr: &str, message: &PeerMessage) -> Result<(), Clien
 pub async fn send_to_any(
     addresses: &[String],
     message: &PeerMessage,
+    mode: DeliveryMode,
 ) -> Result<(), ClientError> {
     if addresses.is_empty() {
         return Err(ClientError::NoAddress);
@@ -145,7 +244,7 @@ pub async fn send_to_any(
     let mut last_error = None;
 
     for addr in addresses {
-        match send_message(addr, message).await {
+        match send_message(addr, message, mode).await {
             Ok(()) => return Ok(()),
             Err(e) => {
                 warn!(addr, error = %e, "failed to send to this address, trying next");
@@ -9,22 +9,35 @@
 //! without a central server. When our daemon starts, it:
 //!
 //! 1. **Registers** a service: `{display_name}._familycom._tcp.local.`
-//!    with TXT records containing our `peer_id` and `display_name`.
+//!    with TXT records containing our `peer_id` and `display_name`
+//!    (plus optional `avatar` and `color`, the `caps` we support,
+//!    `dnd=1` while we're in do-not-disturb and `away=1` while nobody is
+//!    at the keyboard).
 //! 2. **Browses** for other `_familycom._tcp.local.` services on the network.
 //!
 //! When another FamilyCom instance starts (or stops), we get notified
 //! through the mDNS browsing mechanism.
 //!
+//! A peer that vanishes without a goodbye (crash, power cut, Wi-Fi gone)
+//! is expired once its records' TTL lapses without a re-announcement, so
+//! it doesn't linger as online until the library notices.
+//!
+//! `network_interface` can name several interfaces (or `"all"` LAN ones)
+//! for homes with both wired and wireless segments: we register on each,
+//! and report which of a peer's addresses we see on which interface.
+//!
 //! # Service Type
 //!
 //! We use `_familycom._tcp.local.` as our service type. The underscore
 //! prefix is an mDNS convention for service types. The `._tcp` suffix
 //! indicates we use TCP for the actual communication.
 
-use familycom_core::types::{PeerId, PeerInfo, Timestamp};
+use familycom_core::config::NetworkInterfaces;
+use familycom_core::types::{AccentColor, Avatar, Capability, NotificationPrefs, PeerId, PeerInfo, Timestamp};
 use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent, ServiceInfo};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
@@ -33,6 +46,22 @@ use tracing::{debug, error, info, warn};
 /// All FamilyCom instances on the LAN use this same service type.
 const SERVICE_TYPE: &str = "_familycom._tcp.local.";
 
+/// How often the browse loop checks for lapsed announcements while no
+/// mDNS events arrive.
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Extra time given past a record's TTL before the peer is expired, since
+/// the refresh that renews it can land a little late.
+const EXPIRY_GRACE: Duration = Duration::from_secs(10);
+
+/// What we advertise about ourselves that changes without restarting
+/// discovery: our do-not-disturb and away status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Presence {
+    pub do_not_disturb: bool,
+    pub away: bool,
+}
+
 /// Events emitted by the discovery service.
 ///
 /// The daemon's main loop receives these via a channel and updates
@@ -67,6 +96,75 @@ pub struct DiscoveryService {
     our_peer_id: PeerId,
     /// The full service name we registered (needed for unregistration).
     our_service_fullname: String,
+    /// What we registered, to re-announce it when our presence changes.
+    registration: Registration,
+}
+
+/// One of our network interfaces that mDNS runs on.
+#[derive(Debug, Clone)]
+struct LocalInterface {
+    name: String,
+    /// Its IPv4 address and prefix length, if it has one.
+    ipv4: Option<(Ipv4Addr, u8)>,
+}
+
+impl LocalInterface {
+    fn from_netdev(iface: netdev::Interface) -> Self {
+        Self {
+            ipv4: iface.ipv4.first().map(|net| (net.addr(), net.prefix_len())),
+            name: iface.name,
+        }
+    }
+
+    /// Whether `addr` is on this interface's subnet.
+    fn reaches(&self, addr: IpAddr) -> bool {
+        let (IpAddr::V4(addr), Some((ours, prefix))) = (addr, self.ipv4) else {
+            return false;
+        };
+        let mask = u32::MAX.checked_shl(32 - u32::from(prefix.min(32))).unwrap_or(0);
+        u32::from(addr) & mask == u32::from(ours) & mask
+    }
+}
+
+/// The details of our mDNS service, kept so it can be registered again
+/// with different TXT records.
+struct Registration {
+    instance_name: String,
+    host: String,
+    /// Comma-separated addresses, or empty to let mdns-sd pick the
+    /// addresses of all active interfaces.
+    addr: String,
+    port: u16,
+    properties: HashMap<String, String>,
+}
+
+impl Registration {
+    fn service_info(&self) -> Result<ServiceInfo, DiscoveryError> {
+        let service_info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &self.instance_name,
+            &self.host,
+            &self.addr,
+            self.port,
+            self.properties.clone(),
+        )
+        .map_err(|e| DiscoveryError::Registration(e.to_string()))?;
+        Ok(if self.addr.is_empty() {
+            service_info.enable_addr_auto()
+        } else {
+            service_info // Address is explicit, don't let the lib add more
+        })
+    }
+
+    fn set_presence(&mut self, presence: Presence) {
+        for (key, on) in [("dnd", presence.do_not_disturb), ("away", presence.away)] {
+            if on {
+                self.properties.insert(key.to_string(), "1".to_string());
+            } else {
+                self.properties.remove(key);
+            }
+        }
+    }
 }
 
 impl DiscoveryService {
@@ -82,8 +180,13 @@ impl DiscoveryService {
     /// * `peer_id` - Our unique peer identifier
     /// * `display_name` - Our human-readable name (shown to other peers)
     /// * `tcp_port` - The TCP port our message server is listening on
-    /// * `network_interface` - Optional interface name override (e.g. "enp5s0").
-    ///   If `None`, auto-detects the default-route interface via `netdev`.
+    /// * `network_interface` - Optional interface override: one name (e.g.
+    ///   "enp5s0"), several, or `"all"` LAN interfaces. If `None`,
+    ///   auto-detects the default-route interface via `netdev`.
+    /// * `avatar` / `accent_color` - Optional cosmetic metadata advertised
+    ///   in TXT records so other peers can render us recognizably.
+    /// * `presence` - The do-not-disturb and away status to advertise from
+    ///   the start.
     ///
     /// # Returns
     ///
@@ -93,40 +196,47 @@ impl DiscoveryService {
         peer_id: PeerId,
         display_name: &str,
         tcp_port: u16,
-        network_interface: Option<&str>,
+        network_interface: Option<&NetworkInterfaces>,
+        avatar: Option<&Avatar>,
+        accent_color: Option<AccentColor>,
+        presence: Presence,
     ) -> Result<(Self, mpsc::Receiver<DiscoveryEvent>), DiscoveryError> {
         // Create the mDNS daemon. This starts a background thread that
         // handles all multicast networking.
         let daemon = ServiceDaemon::new().map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
 
-        // Determine which network interface and IPv4 address to use for mDNS.
-        // We resolve to a specific IPv4 address rather than an interface name
+        // Determine which network interfaces and IPv4 addresses to use for mDNS.
+        // We resolve to specific IPv4 addresses rather than interface names
         // because IfKind::Name enables both IPv4 and IPv6 addresses, and
         // disable_interface(IfKind::IPv6) is unreliable (the IPv6 addresses
         // still appear in the daemon's socket list). Using IfKind::Addr with
         // the exact IPv4 address is surgical and avoids IPv6 entirely.
-        // This matters because our TCP server binds to 0.0.0.0 (IPv4 only)
-        // and IPv6 link-local addresses lack zone IDs in std::net.
-        let (iface_name, ipv4_addr) = Self::detect_interface(network_interface);
+        // This matters because our TCP server binds to 0.0.0.0 (IPv4 only,
+        // so it accepts on every interface) and IPv6 link-local addresses
+        // lack zone IDs in std::net.
+        let interfaces = Self::detect_interfaces(network_interface);
 
-        if let Some(addr) = ipv4_addr {
-            // Best case: pin mDNS to a single IPv4 address — no IPv6 at all
-            info!(interface = %iface_name, addr = %addr, "restricting mDNS to IPv4 address");
-            daemon
-                .disable_interface(IfKind::All)
-                .map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
-            daemon
-                .enable_interface(IfKind::Addr(IpAddr::V4(addr)))
-                .map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
-        } else if !iface_name.is_empty() {
-            // Fallback: restrict by interface name if we couldn't resolve IPv4
-            warn!(interface = %iface_name, "could not find IPv4 address, falling back to interface name");
+        if !interfaces.is_empty() {
             daemon
                 .disable_interface(IfKind::All)
                 .map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
-            daemon
-                .enable_interface(IfKind::Name(iface_name.clone()))
-                .map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
+        }
+        for iface in &interfaces {
+            if let Some((addr, _)) = iface.ipv4 {
+                // Best case: pin mDNS to the IPv4 address — no IPv6 at all
+                info!(interface = %iface.name, addr = %addr, "restricting mDNS to IPv4 address");
+                daemon
+                    .enable_interface(IfKind::Addr(IpAddr::V4(addr)))
+                    .map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
+            } else {
+                // Fallback: restrict by interface name if we couldn't resolve IPv4
+                warn!(interface = %iface.name, "could not find IPv4 address, falling back to interface name");
+                daemon
+                    .enable_interface(IfKind::Name(iface.name.clone()))
+                    .map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
+            }
+        }
+        if interfaces.iter().any(|iface| iface.ipv4.is_none()) {
             daemon
                 .disable_interface(IfKind::IPv6)
                 .map_err(|e| DiscoveryError::Mdns(e.to_string()))?;
@@ -143,6 +253,13 @@ impl DiscoveryService {
         let mut properties = HashMap::new();
         properties.insert("peer_id".to_string(), peer_id.to_string());
         properties.insert("display_name".to_string(), display_name.to_string());
+        if let Some(avatar) = avatar {
+            properties.insert("avatar".to_string(), avatar.to_string());
+        }
+        if let Some(color) = accent_color {
+            properties.insert("color".to_string(), color.to_string());
+        }
+        properties.insert("caps".to_string(), Capability::join(Capability::SUPPORTED));
 
         // The hostname for our service. We use "_" as placeholder since
         // mdns-sd will use the actual local hostname.
@@ -160,24 +277,22 @@ impl DiscoveryService {
         // display name is preserved in the TXT record "display_name" property.
         let instance_name = display_name.to_lowercase();
 
-        // If we have a specific IPv4 address, pass it to ServiceInfo so
-        // the library only advertises that address. Otherwise, fall back
-        // to addr_auto which picks up all addresses on active interfaces.
-        let addr_str = ipv4_addr.map(|a| a.to_string()).unwrap_or_default();
-        let service_info = ServiceInfo::new(
-            SERVICE_TYPE,
-            &instance_name, // Lowercase to work around mdns-sd probing bug
-            &host,
-            &addr_str,
-            tcp_port,
+        // If we have specific IPv4 addresses, pass them to ServiceInfo so
+        // the library only advertises those. Otherwise, fall back to
+        // addr_auto which picks up all addresses on active interfaces.
+        let addrs: Option<Vec<String>> = interfaces
+            .iter()
+            .map(|iface| iface.ipv4.map(|(addr, _)| addr.to_string()))
+            .collect();
+        let mut registration = Registration {
+            instance_name, // Lowercase to work around mdns-sd probing bug
+            host,
+            addr: addrs.map(|addrs| addrs.join(",")).unwrap_or_default(),
+            port: tcp_port,
             properties,
-        )
-        .map_err(|e| DiscoveryError::Registration(e.to_string()))?;
-        let service_info = if ipv4_addr.is_some() {
-            service_info  // Address is explicit, don't let the lib add more
-        } else {
-            service_info.enable_addr_auto()
         };
+        registration.set_presence(presence);
+        let service_info = registration.service_info()?;
 
         // Save the full service name for later unregistration
         let fullname = service_info.get_fullname().to_string();
@@ -211,43 +326,65 @@ impl DiscoveryService {
         // blocking recv(), not async.
         let our_peer_id_clone = our_peer_id.clone();
         tokio::task::spawn_blocking(move || {
-            Self::browse_loop(browse_receiver, event_tx, &our_peer_id_clone);
+            Self::browse_loop(browse_receiver, event_tx, &our_peer_id_clone, &interfaces);
         });
 
         let service = Self {
             daemon,
             our_peer_id: peer_id,
             our_service_fullname: fullname,
+            registration,
         };
 
         Ok((service, event_rx))
     }
 
-    /// Detects the network interface and its IPv4 address for mDNS.
+    /// Detects the network interfaces and their IPv4 addresses for mDNS.
     ///
-    /// Returns `(interface_name, Option<Ipv4Addr>)`. If a specific interface
-    /// name is provided, looks it up by name. Otherwise, auto-detects the
-    /// default-route interface via `netdev`.
-    fn detect_interface(override_name: Option<&str>) -> (String, Option<Ipv4Addr>) {
-        match override_name {
-            Some(name) => {
-                // Manual override: look up the named interface's IPv4 address
-                let addr = netdev::get_interfaces()
+    /// Named interfaces are looked up by name (kept without an address if
+    /// they're missing); `"all"` is every interface that's up with a private
+    /// IPv4 address. Otherwise, auto-detects the default-route interface via
+    /// `netdev`. Empty means mDNS runs on all interfaces.
+    fn detect_interfaces(setting: Option<&NetworkInterfaces>) -> Vec<LocalInterface> {
+        match setting {
+            Some(setting) if setting.is_all() => {
+                let interfaces: Vec<LocalInterface> = netdev::get_interfaces()
                     .into_iter()
-                    .find(|iface| iface.name == name)
-                    .and_then(|iface| iface.ipv4.first().map(|net| net.addr()));
-                (name.to_string(), addr)
+                    .filter(|iface| iface.is_up() && !iface.is_loopback())
+                    .map(LocalInterface::from_netdev)
+                    .filter(|iface| iface.ipv4.is_some_and(|(addr, _)| addr.is_private()))
+                    .collect();
+                if interfaces.is_empty() {
+                    warn!("no LAN interface found, using all");
+                }
+                interfaces
+            }
+            Some(setting) => {
+                // Manual override: look up each named interface's IPv4 address
+                let available = netdev::get_interfaces();
+                setting
+                    .names()
+                    .into_iter()
+                    .map(|name| {
+                        available
+                            .iter()
+                            .find(|iface| iface.name == name)
+                            .cloned()
+                            .map(LocalInterface::from_netdev)
+                            .unwrap_or_else(|| LocalInterface {
+                                name: name.to_string(),
+                                ipv4: None,
+                            })
+                    })
+                    .collect()
             }
             None => {
                 // Auto-detect: use the interface that holds the default route
                 match netdev::get_default_interface() {
-                    Ok(iface) => {
-                        let addr = iface.ipv4.first().map(|net| net.addr());
-                        (iface.name, addr)
-                    }
+                    Ok(iface) => vec![LocalInterface::from_netdev(iface)],
                     Err(e) => {
                         warn!(error = %e, "could not detect default network interface, using all");
-                        (String::new(), None)
+                        Vec::new()
                     }
                 }
             }
@@ -264,14 +401,36 @@ impl DiscoveryService {
         browse_receiver: mdns_sd::Receiver<ServiceEvent>,
         event_tx: mpsc::Sender<DiscoveryEvent>,
         our_peer_id: &PeerId,
+        interfaces: &[LocalInterface],
     ) {
         // Track mDNS fullname → PeerId so we can emit correct PeerLost events.
         // ServiceRemoved only gives us the fullname (e.g. "ChuiMachine._familycom._tcp.local."),
-        // not the TXT records with the UUID peer_id. This map lets us look it up.
-        let mut fullname_to_peer_id: HashMap<String, PeerId> = HashMap::new();
+        // not the TXT records with the UUID peer_id. This also tracks when
+        // each announcement lapses.
+        let mut announced = Announcements::default();
+
+        loop {
+            // Waits for an event, waking up now and then to expire peers
+            let event = match browse_receiver.recv_timeout(EXPIRY_CHECK_INTERVAL) {
+                Ok(event) => Some(event),
+                Err(_) if browse_receiver.is_disconnected() => break,
+                Err(_) => None,
+            };
+            let mut closed = false;
+            for peer_id in announced.expire(Instant::now()) {
+                info!(peer_id = %peer_id, "peer's mDNS announcement expired");
+                if event_tx.blocking_send(DiscoveryEvent::PeerLost(peer_id)).is_err() {
+                    closed = true;
+                    break;
+                }
+            }
+            if closed {
+                break;
+            }
+            let Some(event) = event else {
+                continue;
+            };
 
-        // recv() blocks until an event is available or the channel is closed
-        while let Ok(event) = browse_receiver.recv() {
             match event {
                 ServiceEvent::ServiceResolved(info) => {
                     // A service was fully resolved — we know its name, IP, port, TXT records.
@@ -301,27 +460,47 @@ impl DiscoveryService {
                         .unwrap_or("Unknown")
                         .to_string();
 
+                    // Cosmetic metadata is optional; ignore anything malformed
+                    // (older peers simply don't send these records).
+                    let avatar = properties
+                        .get_property_val_str("avatar")
+                        .and_then(|a| Avatar::new(a).ok());
+                    let accent_color = properties
+                        .get_property_val_str("color")
+                        .and_then(|c| AccentColor::parse(c).ok());
+                    let capabilities = properties
+                        .get_property_val_str("caps")
+                        .map(Capability::parse_list)
+                        .unwrap_or_default();
+                    let do_not_disturb = properties.get_property_val_str("dnd") == Some("1");
+                    let away = properties.get_property_val_str("away") == Some("1");
+
                     // Build the list of reachable addresses (IP:port).
                     // Filter out IPv6 link-local addresses (fe80::/10) because
                     // std::net doesn't support zone IDs (%iface) and our TCP
                     // server only binds IPv4 anyway.
                     let port = info.get_port();
-                    let addresses: Vec<String> = info
+                    let reachable: Vec<IpAddr> = info
                         .get_addresses()
                         .iter()
                         .filter(|addr| !is_ipv6_link_local(addr))
-                        .map(|addr| format!("{addr}:{port}"))
+                        .copied()
                         .collect();
+                    let addresses: Vec<String> =
+                        reachable.iter().map(|addr| format!("{addr}:{port}")).collect();
 
                     if addresses.is_empty() {
                         warn!(peer_id = %peer_id, "peer has no addresses, skipping");
                         continue;
                     }
 
-                    // Remember the fullname → peer_id mapping for ServiceRemoved
-                    fullname_to_peer_id.insert(
-                        info.get_fullname().to_string(),
-                        peer_id.clone(),
+                    // Remember the fullname → peer_id mapping for ServiceRemoved,
+                    // and the SRV/TXT records' TTL for expiry
+                    announced.refresh(
+                        info.get_fullname(),
+                        &peer_id,
+                        Duration::from_secs(u64::from(info.get_other_ttl())),
+                        Instant::now(),
                     );
 
                     let peer_info = PeerInfo {
@@ -330,6 +509,14 @@ impl DiscoveryService {
                         addresses: addresses.clone(),
                         last_seen_at: Timestamp::now(),
                         online: true,
+                        do_not_disturb,
+                        away,
+                        avatar,
+                        accent_color,
+                        capabilities,
+                        // Local settings; the daemon fills them in
+                        notifications: NotificationPrefs::default(),
+                        interface_addresses: by_interface(interfaces, &reachable, port),
                     };
 
                     info!(
@@ -350,7 +537,7 @@ impl DiscoveryService {
                     // A service was removed (peer went offline or unregistered).
                     // Look up the real PeerId from our fullname map so the daemon
                     // can correctly remove the peer from its online_peers.
-                    if let Some(peer_id) = fullname_to_peer_id.remove(&fullname) {
+                    if let Some(peer_id) = announced.remove(&fullname) {
                         info!(
                             peer_id = %peer_id,
                             service = fullname,
@@ -390,6 +577,19 @@ impl DiscoveryService {
         debug!("browse loop exited");
     }
 
+    /// Registers our service again with do-not-disturb or away turned on
+    /// or off. Peers resolve the new TXT records as an update, so we never
+    /// appear to go offline.
+    pub fn set_presence(&mut self, presence: Presence) -> Result<(), DiscoveryError> {
+        self.registration.set_presence(presence);
+        let service_info = self.registration.service_info()?;
+        self.daemon
+            .register(service_info)
+            .map_err(|e| DiscoveryError::Registration(e.to_string()))?;
+        info!(?presence, "re-announced mDNS service");
+        Ok(())
+    }
+
     /// Unregisters our service from the network and shuts down the mDNS daemon.
     ///
     /// Call this during graceful shutdown so other peers know we're going offline
@@ -431,6 +631,63 @@ impl DiscoveryService {
     }
 }
 
+/// The services we've resolved, by mDNS fullname, and when each lapses
+/// unless it's announced again.
+#[derive(Debug, Default)]
+struct Announcements {
+    services: HashMap<String, (PeerId, Instant)>,
+}
+
+impl Announcements {
+    /// Records an announcement of `fullname` by `peer_id`, valid for `ttl`.
+    ///
+    /// A peer has one service; one under another name (from before a
+    /// rename) is forgotten, so it can't take the peer offline later.
+    fn refresh(&mut self, fullname: &str, peer_id: &PeerId, ttl: Duration, now: Instant) {
+        self.services.retain(|name, (id, _)| id != peer_id || name == fullname);
+        self.services
+            .insert(fullname.to_string(), (peer_id.clone(), now + ttl + EXPIRY_GRACE));
+    }
+
+    /// Forgets `fullname` (goodbye received), returning its peer.
+    fn remove(&mut self, fullname: &str) -> Option<PeerId> {
+        self.services.remove(fullname).map(|(peer_id, _)| peer_id)
+    }
+
+    /// Forgets and returns the peers whose announcement lapsed by `now`.
+    fn expire(&mut self, now: Instant) -> Vec<PeerId> {
+        let mut lapsed = Vec::new();
+        self.services.retain(|_, (peer_id, expires_at)| {
+            let live = *expires_at > now;
+            if !live {
+                lapsed.push(peer_id.clone());
+            }
+            live
+        });
+        lapsed
+    }
+}
+
+/// Groups a peer's `addresses` (as `ip:port`) by which of our
+/// `interfaces` reaches them. Empty unless mDNS runs on several interfaces,
+/// where it tells which segment (wired or wireless) the peer is on.
+fn by_interface(interfaces: &[LocalInterface], addresses: &[IpAddr], port: u16) -> BTreeMap<String, Vec<String>> {
+    let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    if interfaces.len() < 2 {
+        return grouped;
+    }
+    for addr in addresses {
+        if let Some(iface) = interfaces.iter().find(|iface| iface.reaches(*addr)) {
+            grouped.entry(iface.name.clone()).or_default().push(format!("{addr}:{port}"));
+        }
+    }
+    // mdns-sd hands addresses over in no particular order
+    for addrs in grouped.values_mut() {
+        addrs.sort();
+    }
+    grouped
+}
+
 /// Returns `true` if the address is an IPv6 link-local address (fe80::/10).
 ///
 /// These addresses require a zone ID (`%iface`) that `std::net` doesn't support,
@@ -447,6 +704,28 @@ mod tests {
     use super::*;
     use std::net::IpAddr;
 
+    #[test]
+    fn addresses_are_grouped_by_interface() {
+        let iface = |name: &str, addr: &str, prefix| LocalInterface {
+            name: name.to_string(),
+            ipv4: Some((addr.parse().unwrap(), prefix)),
+        };
+        let wired = iface("enp5s0", "192.168.1.20", 24);
+        let wireless = iface("wlan0", "192.168.50.7", 24);
+        let addresses: Vec<IpAddr> = ["192.168.50.31", "192.168.1.31", "10.8.0.2"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+
+        let grouped = by_interface(&[wired.clone(), wireless], &addresses, 9876);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped["enp5s0"], ["192.168.1.31:9876"]);
+        assert_eq!(grouped["wlan0"], ["192.168.50.31:9876"]);
+
+        // With a single interface there's nothing to tell apart
+        assert!(by_interface(&[wired], &addresses, 9876).is_empty());
+    }
+
     #[test]
     fn test_is_ipv6_link_local() {
         // IPv6 link-local addresses (fe80::/10) — should be filtered
@@ -470,4 +749,28 @@ mod tests {
         let v4_ll: IpAddr = "169.254.1.1".parse().unwrap();
         assert!(!is_ipv6_link_local(&v4_ll));
     }
+
+    #[test]
+    fn announcements_lapse_unless_refreshed() {
+        let start = Instant::now();
+        let ttl = Duration::from_secs(120);
+        let sala = PeerId::new("peer-sala");
+        let cocina = PeerId::new("peer-cocina");
+        let mut announced = Announcements::default();
+        announced.refresh("Sala._familycom._tcp.local.", &sala, ttl, start);
+        announced.refresh("Cocina._familycom._tcp.local.", &cocina, ttl, start);
+
+        // Sala re-announces halfway through; Cocina goes silent
+        announced.refresh("Sala._familycom._tcp.local.", &sala, ttl, start + ttl / 2);
+        assert!(announced.expire(start + ttl).is_empty(), "grace period");
+        assert_eq!(announced.expire(start + ttl + EXPIRY_GRACE), [cocina]);
+        assert!(announced.expire(start + ttl + EXPIRY_GRACE).is_empty(), "expired once");
+        assert_eq!(announced.expire(start + ttl * 2), std::slice::from_ref(&sala));
+
+        // A rename replaces the old service, whose goodbye then means nothing
+        announced.refresh("Sala._familycom._tcp.local.", &sala, ttl, start);
+        announced.refresh("Salon._familycom._tcp.local.", &sala, ttl, start);
+        assert_eq!(announced.remove("Sala._familycom._tcp.local."), None);
+        assert_eq!(announced.remove("Salon._familycom._tcp.local."), Some(sala));
+    }
 }
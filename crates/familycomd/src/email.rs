@@ -0,0 +1,276 @@
+//! Email fallback: mails a copy of messages a peer hasn't picked up.
+//!
+//! When `[email]` has an `smtp_relay`, the main loop looks for messages
+//! still undelivered `after_hours` after they were sent and hands them
+//! here, grouped by peer, to be emailed to the address configured for that
+//! family member. Each message is emailed once; it's still delivered to
+//! the peer as usual when it comes back.
+//!
+//! This is a minimal SMTP client: plain SMTP on the relay's port, no TLS
+//! and no authentication, which is what a router, NAS or home server
+//! relaying for the LAN accepts. The text goes out as UTF-8 (8bit).
+//! Since anyone on the way can read it, a relay that isn't on this
+//! machine is only used with `[email] allow_remote_relay` (see
+//! `EmailConfig::relay_allowed`).
+
+use familycom_core::types::Message;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// The port used when `smtp_relay` doesn't name one.
+const DEFAULT_PORT: u16 = 25;
+
+/// How long one email may take, connecting included.
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An email with the messages sent to one peer.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /// The email for `messages`, sent by `sender_name` (us) to `to`.
+    pub fn compose(from: &str, to: &str, sender_name: &str, messages: &[Message]) -> Self {
+        let subject = match messages.len() {
+            1 => format!("Mensaje de {sender_name} (FamilyCom)"),
+            n => format!("{n} mensajes de {sender_name} (FamilyCom)"),
+        };
+        let mut body = format!(
+            "{sender_name} te escribio por FamilyCom, pero tu computadora no los recibio:\n\n"
+        );
+        for message in messages {
+            body.push_str(&format!(
+                "[{}] {}\n",
+                message.timestamp.format_local_datetime(),
+                message.content
+            ));
+        }
+        body.push_str("\nLos mensajes van a llegar igual cuando tu computadora vuelva a conectarse.\n");
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            subject,
+            body,
+        }
+    }
+}
+
+/// Sends `email` through the SMTP relay at `relay` (`host` or
+/// `host:port`).
+pub async fn send(relay: &str, email: &Email) -> io::Result<()> {
+    let stream = TcpStream::connect(relay_addr(relay)).await?;
+    let mut smtp = BufReader::new(stream);
+
+    expect(&mut smtp, 220).await?;
+    if command(&mut smtp, "EHLO familycom").await? != 250 {
+        // Very old relays only speak the original protocol
+        check(command(&mut smtp, "HELO familycom").await?, 250)?;
+    }
+    check(command(&mut smtp, &format!("MAIL FROM:<{}>", email.from)).await?, 250)?;
+    let rcpt = command(&mut smtp, &format!("RCPT TO:<{}>", email.to)).await?;
+    if rcpt != 250 && rcpt != 251 {
+        return Err(refused(rcpt));
+    }
+    check(command(&mut smtp, "DATA").await?, 354)?;
+    smtp.get_mut().write_all(message_data(email).as_bytes()).await?;
+    expect(&mut smtp, 250).await?;
+    let _ = command(&mut smtp, "QUIT").await;
+    Ok(())
+}
+
+/// The message as sent after `DATA`: headers, body with CRLF line endings
+/// and leading dots doubled, and the terminating `.` line.
+fn message_data(email: &Email) -> String {
+    let mut data = format!(
+        "From: FamilyCom <{}>\r\n\
+         To: <{}>\r\n\
+         Subject: {}\r\n\
+         Date: {}\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\r\n",
+        email.from,
+        email.to,
+        encode_header(&email.subject),
+        chrono::Local::now().to_rfc2822()
+    );
+    for line in email.body.lines() {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push_str(".\r\n");
+    data
+}
+
+/// Encodes a header value as an RFC 2047 encoded word unless it's plain
+/// ASCII.
+fn encode_header(text: &str) -> String {
+    if text.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+        return text.to_string();
+    }
+    let mut encoded = String::from("=?UTF-8?Q?");
+    for byte in text.bytes() {
+        match byte {
+            b' ' => encoded.push('_'),
+            b if b.is_ascii_alphanumeric() || b"!*+-/".contains(&b) => encoded.push(b as char),
+            b => encoded.push_str(&format!("={b:02X}")),
+        }
+    }
+    encoded.push_str("?=");
+    encoded
+}
+
+/// Sends one command line and returns the reply code.
+async fn command(smtp: &mut BufReader<TcpStream>, line: &str) -> io::Result<u16> {
+    smtp.get_mut().write_all(format!("{line}\r\n").as_bytes()).await?;
+    reply(smtp).await
+}
+
+/// Reads a reply, which may span several `250-...` lines, and returns its
+/// code.
+async fn reply(smtp: &mut BufReader<TcpStream>) -> io::Result<u16> {
+    loop {
+        let mut line = String::new();
+        if smtp.read_line(&mut line).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let code = line
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("not an SMTP reply: {:?}", line.trim_end()))
+            })?;
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(code);
+        }
+    }
+}
+
+async fn expect(smtp: &mut BufReader<TcpStream>, code: u16) -> io::Result<()> {
+    check(reply(smtp).await?, code)
+}
+
+fn check(got: u16, expected: u16) -> io::Result<()> {
+    if got == expected {
+        Ok(())
+    } else {
+        Err(refused(got))
+    }
+}
+
+fn refused(code: u16) -> io::Error {
+    io::Error::other(format!("SMTP relay refused the email (code {code})"))
+}
+
+/// `host:port`, with the default port if `relay` has none.
+fn relay_addr(relay: &str) -> (String, u16) {
+    match relay.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => match port.parse() {
+            Ok(port) => (host.to_string(), port),
+            Err(_) => (relay.to_string(), DEFAULT_PORT),
+        },
+        _ => (relay.to_string(), DEFAULT_PORT),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use familycom_core::types::{Direction, MessageId, PeerId, Timestamp};
+    use tokio::net::TcpListener;
+
+    /// Plays a relay for one email and returns what was sent after `DATA`.
+    async fn relay_once(listener: TcpListener) -> (Vec<String>, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut client = BufReader::new(stream);
+        client.get_mut().write_all(b"220 router ESMTP\r\n").await.unwrap();
+        let mut commands = Vec::new();
+        let mut data = String::new();
+        loop {
+            let mut line = String::new();
+            if client.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let reply: &[u8] = match line.trim_end() {
+                "EHLO familycom" => b"250-router\r\n250 8BITMIME\r\n",
+                "DATA" => b"354 go ahead\r\n",
+                "QUIT" => b"221 bye\r\n",
+                _ => b"250 ok\r\n",
+            };
+            commands.push(line.trim_end().to_string());
+            client.get_mut().write_all(reply).await.unwrap();
+            if line.trim_end() == "DATA" {
+                loop {
+                    let mut line = String::new();
+                    client.read_line(&mut line).await.unwrap();
+                    if line == ".\r\n" {
+                        break;
+                    }
+                    data.push_str(&line);
+                }
+                client.get_mut().write_all(b"250 queued\r\n").await.unwrap();
+            }
+        }
+        (commands, data)
+    }
+
+    #[tokio::test]
+    async fn undelivered_messages_are_emailed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let relay = tokio::spawn(relay_once(listener));
+
+        let message = |id: &str, content: &str| Message {
+            id: MessageId::new(id),
+            peer_id: PeerId::new("peer-abuela"),
+            direction: Direction::Sent,
+            content: content.to_string(),
+            timestamp: Timestamp::from_millis(1_700_000_000_000),
+            delivered: false,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        let messages = [message("m1", "Se fue la luz"), message("m2", "Tampoco hay internet\n.")];
+        let email = Email::compose("familycom@casa.lan", "abuela@example.com", "Sala", &messages);
+        send(&format!("127.0.0.1:{port}"), &email).await.unwrap();
+
+        let (commands, data) = relay.await.unwrap();
+        assert_eq!(
+            commands,
+            [
+                "EHLO familycom",
+                "MAIL FROM:<familycom@casa.lan>",
+                "RCPT TO:<abuela@example.com>",
+                "DATA",
+                "QUIT"
+            ]
+        );
+        assert!(data.contains("Subject: 2 mensajes de Sala (FamilyCom)\r\n"));
+        assert!(data.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        assert!(data.contains("] Se fue la luz\r\n"));
+        // A lone dot would otherwise end the message early
+        assert!(data.ends_with("] Tampoco hay internet\r\n..\r\n\r\nLos mensajes van a llegar igual cuando tu computadora vuelva a conectarse.\r\n"));
+    }
+
+    #[test]
+    fn non_ascii_subjects_are_encoded() {
+        assert_eq!(encode_header("Mensaje de Sala"), "Mensaje de Sala");
+        assert_eq!(encode_header("Mensaje de Habitación"), "=?UTF-8?Q?Mensaje_de_Habitaci=C3=B3n?=");
+        assert_eq!(relay_addr("router.lan"), ("router.lan".to_string(), 25));
+        assert_eq!(relay_addr("192.168.1.1:2525"), ("192.168.1.1".to_string(), 2525));
+    }
+}
@@ -0,0 +1,236 @@
+//! Numbered stream of the events pushed to subscribed clients.
+//!
+//! Every event `DaemonApp` broadcasts (new messages, peers coming and
+//! going, ...) gets a sequence number, and the most recent ones are kept in
+//! a ring buffer. A TUI that loses its connection for a moment resubscribes
+//! with the last number it saw (`Subscribe { since }`) and has what it
+//! missed replayed, instead of silently never showing those messages. The
+//! IPC server also uses the buffer to catch up a client that fell behind
+//! the broadcast channel.
+//!
+//! Clients see sequence numbers as opaque strings, `<run>-<n>`, where `run`
+//! identifies this daemon process: numbers from before a restart don't
+//! match, and the client is told to fetch its state again.
+
+use familycom_core::ipc::ServerMessage;
+use familycom_core::types::Timestamp;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before it starts lagging.
+const BROADCAST_CAPACITY: usize = 256;
+
+/// Events kept for replay. More than `BROADCAST_CAPACITY`, so a subscriber
+/// that lagged can still be caught up from here.
+const REPLAY_CAPACITY: usize = 1024;
+
+/// One pushed event and its sequence number.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub seq: u64,
+    pub message: ServerMessage,
+}
+
+/// What a subscriber missed, from `EventBus::since`.
+#[derive(Debug)]
+pub struct Missed {
+    /// The missed events still in the buffer, oldest first.
+    pub events: Vec<Event>,
+    /// False if some were already dropped from the buffer (or the position
+    /// was unknown), so the subscriber's view may have gaps.
+    pub complete: bool,
+    /// Sequence number of the last event sent when this was taken. Events
+    /// received afterwards up to this one are already in `events`.
+    pub up_to: u64,
+}
+
+/// Broadcasts events to every subscriber, numbering and buffering them.
+/// Cheap to clone; clones share the same stream.
+#[derive(Clone)]
+pub struct EventBus {
+    /// Identifies this daemon run in the sequence strings.
+    run: u64,
+    tx: broadcast::Sender<Event>,
+    log: Arc<Mutex<Log>>,
+}
+
+struct Log {
+    /// Sequence number of the last event sent (0 before the first).
+    last_seq: u64,
+    /// The last `REPLAY_CAPACITY` events, oldest first.
+    recent: VecDeque<Event>,
+}
+
+impl EventBus {
+    /// Creates an empty stream.
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+        Self {
+            run: Timestamp::now().as_millis() as u64,
+            tx,
+            log: Arc::new(Mutex::new(Log {
+                last_seq: 0,
+                recent: VecDeque::with_capacity(REPLAY_CAPACITY),
+            })),
+        }
+    }
+
+    /// Numbers `message`, keeps it for replay and broadcasts it. Returns
+    /// its sequence number. Typing notices aren't kept: they're stale by
+    /// the time anyone resumes, and would push real events out.
+    pub fn send(&self, message: ServerMessage) -> u64 {
+        // Numbering and broadcasting under one lock keeps the broadcast in
+        // sequence order, which `subscribe` and `resume` rely on
+        let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        log.last_seq += 1;
+        let event = Event {
+            seq: log.last_seq,
+            message,
+        };
+        if !matches!(event.message, ServerMessage::PeerTyping { .. }) {
+            if log.recent.len() == REPLAY_CAPACITY {
+                log.recent.pop_front();
+            }
+            log.recent.push_back(event.clone());
+        }
+        // No subscribers is fine: the event is still kept for replay
+        let _ = self.tx.send(event);
+        log.last_seq
+    }
+
+    /// Subscribes to events sent from now on. Also returns the sequence
+    /// number of the last event sent before, for use with `since`.
+    pub fn subscribe(&self) -> (broadcast::Receiver<Event>, u64) {
+        let log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        (self.tx.subscribe(), log.last_seq)
+    }
+
+    /// Subscribes to events sent from now on, and returns the events after
+    /// `since` (a string from `seq_string`) that were sent before. Nothing
+    /// is lost or repeated between the two.
+    pub fn resume(&self, since: &str) -> (broadcast::Receiver<Event>, Missed) {
+        let log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        let missed = match self.parse_seq(since) {
+            Some(since) => missed_since(&log, since),
+            None => Missed {
+                events: Vec::new(),
+                complete: false,
+                up_to: log.last_seq,
+            },
+        };
+        (self.tx.subscribe(), missed)
+    }
+
+    /// The events after `since` still in the buffer.
+    pub fn since(&self, since: u64) -> Missed {
+        let log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        missed_since(&log, since)
+    }
+
+    /// The sequence number as sent to clients.
+    pub fn seq_string(&self, seq: u64) -> String {
+        format!("{:x}-{seq}", self.run)
+    }
+
+    /// Reads a string from `seq_string`. `None` if it's malformed or from
+    /// another daemon run.
+    fn parse_seq(&self, s: &str) -> Option<u64> {
+        let (run, seq) = s.split_once('-')?;
+        if u64::from_str_radix(run, 16).ok()? != self.run {
+            return None;
+        }
+        seq.parse().ok()
+    }
+}
+
+fn missed_since(log: &Log, since: u64) -> Missed {
+    let oldest = log.recent.front().map_or(log.last_seq + 1, |event| event.seq);
+    Missed {
+        events: log.recent.iter().filter(|event| event.seq > since).cloned().collect(),
+        // Everything after `since` is still here, and `since` isn't from
+        // the future
+        complete: since + 1 >= oldest && since <= log.last_seq,
+        up_to: log.last_seq,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use familycom_core::types::PeerId;
+
+    fn offline(peer: &str) -> ServerMessage {
+        ServerMessage::PeerOffline {
+            peer_id: PeerId::new(peer),
+        }
+    }
+
+    fn seqs(missed: &Missed) -> Vec<u64> {
+        missed.events.iter().map(|event| event.seq).collect()
+    }
+
+    #[test]
+    fn resume_replays_what_was_missed_then_continues_live() {
+        let bus = EventBus::new();
+        let (_rx, last) = bus.subscribe();
+        assert_eq!(last, 0);
+        let first = bus.send(offline("a"));
+        bus.send(offline("b"));
+        bus.send(offline("c"));
+
+        let (mut rx, missed) = bus.resume(&bus.seq_string(first));
+        assert!(missed.complete);
+        assert_eq!(seqs(&missed), vec![2, 3]);
+
+        bus.send(offline("d"));
+        assert_eq!(rx.try_recv().unwrap().seq, 4);
+    }
+
+    #[test]
+    fn resume_from_another_run_is_incomplete() {
+        let bus = EventBus::new();
+        bus.send(offline("a"));
+
+        for since in ["0-1", "not a seq", ""] {
+            let (_rx, missed) = bus.resume(since);
+            assert!(!missed.complete, "{since}");
+            assert!(missed.events.is_empty());
+        }
+    }
+
+    #[test]
+    fn typing_notices_are_not_replayed() {
+        let bus = EventBus::new();
+        let first = bus.send(offline("a"));
+        bus.send(ServerMessage::PeerTyping {
+            peer_id: PeerId::new("b"),
+        });
+        bus.send(offline("c"));
+
+        let missed = bus.since(first);
+        assert!(missed.complete);
+        assert_eq!(seqs(&missed), vec![3]);
+    }
+
+    #[test]
+    fn events_dropped_from_the_buffer_make_it_incomplete() {
+        let bus = EventBus::new();
+        for _ in 0..REPLAY_CAPACITY + 5 {
+            bus.send(offline("a"));
+        }
+
+        let missed = bus.since(1);
+        assert!(!missed.complete);
+        assert_eq!(missed.events.len(), REPLAY_CAPACITY);
+
+        // Nothing missed at all
+        let missed = bus.since((REPLAY_CAPACITY + 5) as u64);
+        assert!(missed.complete);
+        assert!(missed.events.is_empty());
+    }
+}
@@ -0,0 +1,863 @@
+//! HTTP gateway: a small JSON API over part of the IPC surface, and a
+//! web page to chat from a phone.
+//!
+//! For `curl` in a script, a browser's address bar or a phone shortcut on
+//! the LAN, none of which speak the line-based IPC protocol. Off unless
+//! `http_listen` is set:
+//!
+//! ```text
+//! GET  /                   the chat page (`assets/web.html`)
+//! GET  /ws                 WebSocket used by the page (see `websocket`)
+//! GET  /peers              known peers, online or not
+//! GET  /messages/<peer>    a conversation, newest first (?limit=50&cursor=...)
+//! POST /messages           {"to": "<peer>", "content": "..."}
+//! ```
+//!
+//! `<peer>` and `to` are a peer ID or display name, as for `familycomd
+//! send`. Each request goes through the main loop like a client's (see
+//! `IpcRequest::submit`), so kid mode, slash commands and the rest apply.
+//! Failures come back as `{"error": "<code>", "message": "..."}` with a
+//! matching status; `<code>` is the IPC `ErrorCode` when there is one.
+//!
+//! There is no authentication, like the peer port: whoever can reach the
+//! address can read and send, but nothing more (the WebSocket refuses
+//! requests the chat page doesn't need). One request per connection
+//! (`Connection: close`), and no CORS headers, so other websites open in
+//! a browser can't read the conversations. They could still make the
+//! browser send to us, so:
+//!
+//! - a request whose `Origin` isn't this server's `Host` is refused (a
+//!   page from elsewhere; browsers send `Origin` with every POST and
+//!   WebSocket, and WebSockets ignore CORS);
+//! - `Host` must be an IP address we listen on, or `localhost`, with our
+//!   port: a site whose name is made to resolve to us (DNS rebinding)
+//!   would otherwise be same-origin;
+//! - `POST /messages` must be `application/json`, which a plain HTML form
+//!   can't send.
+
+use crate::events::EventBus;
+use crate::ipc_server::IpcRequest;
+use crate::send;
+use crate::supervisor;
+use crate::websocket;
+use familycom_core::ipc::{ClientRequest, ErrorCode, ServerMessage};
+use familycom_core::types::PeerId;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+/// How long a client may take to send its whole request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request line plus headers accepted.
+const MAX_HEAD_BYTES: u64 = 8 * 1024;
+
+/// Largest request body accepted; a message is far smaller.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Messages returned by `GET /messages/<peer>` without `?limit=`.
+const DEFAULT_LIMIT: u32 = 50;
+
+/// Most messages returned at once.
+const MAX_LIMIT: u32 = 500;
+
+/// The chat page served at `/`.
+const WEB_PAGE: &str = include_str!("../../../assets/web.html");
+
+/// Serves the HTTP API on `http_listen`.
+pub struct HttpServer {
+    listener: TcpListener,
+    /// Where we listen, which every request's `Host` must name.
+    local_addr: SocketAddr,
+}
+
+impl HttpServer {
+    /// Binds the API's listening socket.
+    pub async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        info!(addr = %local_addr, "HTTP API listening");
+        Ok(Self { listener, local_addr })
+    }
+
+    /// Where the API is listening (the actual port if bound to port 0).
+    #[allow(dead_code)]
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    /// Accepts connections until `shutdown`, answering each in its own
+    /// task. Requests are handed to the main loop through `request_tx`;
+    /// WebSocket sessions also get every event from `events`.
+    pub async fn accept_loop(
+        &self,
+        request_tx: mpsc::Sender<IpcRequest>,
+        events: EventBus,
+        shutdown: CancellationToken,
+    ) {
+        let mut connections = JoinSet::new();
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+
+                // Reap finished connection tasks so the set doesn't grow forever
+                Some(_) = connections.join_next() => {}
+
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, client)) => {
+                        let request_tx = request_tx.clone();
+                        let events = events.clone();
+                        let shutdown = shutdown.clone();
+                        let local_addr = self.local_addr;
+                        connections.spawn(async move {
+                            if let Err(e) = handle_connection(stream, local_addr, &request_tx, &events, &shutdown).await {
+                                debug!(%client, error = %e, "HTTP connection error");
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!(error = %e, "failed to accept HTTP connection");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                },
+            }
+        }
+        supervisor::drain(&mut connections, "http_api").await;
+    }
+}
+
+/// A parsed request: what routing needs and nothing more.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    /// Percent-decoded path segments, e.g. `["messages", "PC Sala"]`.
+    segments: Vec<String>,
+    /// Percent-decoded query parameters, in order.
+    query: Vec<(String, String)>,
+    /// Headers, names lowercased.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// The value of header `name` (lowercase).
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Whether this asks to switch to a WebSocket.
+    fn is_upgrade(&self) -> bool {
+        self.header("upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+/// A response: JSON, except for the page itself.
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json; charset=utf-8",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, code: &str, message: impl Into<String>) -> Self {
+        Self::ok(status, json!({ "error": code, "message": message.into() }))
+    }
+
+    fn page() -> Self {
+        Self {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: WEB_PAGE.as_bytes().to_vec(),
+        }
+    }
+
+    /// The daemon refused the request.
+    fn from_error(code: ErrorCode, message: String) -> Self {
+        let status = match code {
+            ErrorCode::InvalidRequest | ErrorCode::InvalidContent | ErrorCode::InvalidName => 400,
+            ErrorCode::PeerNotAllowed | ErrorCode::NotOwner => 403,
+            ErrorCode::PeerNotFound | ErrorCode::MessageNotFound => 404,
+            _ => 500,
+        };
+        Self::error(status, code.as_str(), message)
+    }
+}
+
+/// Reads one request from `stream`, answers it and closes the connection,
+/// or runs the WebSocket session it asked for. `local_addr` is where we
+/// listen.
+async fn handle_connection(
+    stream: TcpStream,
+    local_addr: SocketAddr,
+    request_tx: &mpsc::Sender<IpcRequest>,
+    events: &EventBus,
+    shutdown: &CancellationToken,
+) -> io::Result<()> {
+    // Writes go straight through; the buffer keeps whatever was read past
+    // the request, which a WebSocket session needs
+    let mut stream = BufReader::new(stream);
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => match check_sender(&request, local_addr) {
+            Err(response) => response,
+            Ok(()) if request.segments == ["ws"] && request.is_upgrade() => match accept_upgrade(&request) {
+                Ok(accept) => {
+                    let head = format!(
+                        "HTTP/1.1 101 Switching Protocols\r\n\
+                         Upgrade: websocket\r\n\
+                         Connection: Upgrade\r\n\
+                         Sec-WebSocket-Accept: {accept}\r\n\r\n"
+                    );
+                    stream.write_all(head.as_bytes()).await?;
+                    return websocket::serve(stream, request_tx, events, shutdown).await;
+                }
+                Err(response) => response,
+            },
+            Ok(()) => route(request, request_tx).await,
+        },
+        Ok(Err(response)) => response,
+        Err(_) => Response::error(408, "timeout", "the request took too long to arrive"),
+    };
+    write_response(&mut stream, &response).await
+}
+
+/// Parses an HTTP/1.1 request, or returns the error response to send.
+async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Request, Response> {
+    let bad_request = |message: &str| Response::error(400, "invalid_request", message);
+
+    // "GET /messages/PC%20Sala?limit=20 HTTP/1.1", then headers up to a blank line
+    let mut head = (&mut *reader).take(MAX_HEAD_BYTES);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)
+        .await
+        .map_err(|_| bad_request("unreadable request line"))?;
+    let mut content_length = 0;
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        match head.read_line(&mut line).await {
+            Ok(0) | Err(_) => return Err(bad_request("request headers too long or cut short")),
+            Ok(_) if line.trim_end().is_empty() => break,
+            Ok(_) => {}
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_string());
+            if name == "content-length" {
+                content_length = value.parse().map_err(|_| bad_request("invalid Content-Length"))?;
+            }
+            headers.push((name, value));
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad_request("invalid request line"));
+    };
+    if content_length > MAX_BODY_BYTES {
+        return Err(Response::error(413, "invalid_request", "request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|_| bad_request("request body cut short"))?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Ok(Request {
+        method: method.to_string(),
+        segments: path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| percent_decode(segment, false))
+            .collect(),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key, true), percent_decode(value, true))
+            })
+            .collect(),
+        headers,
+        body,
+    })
+}
+
+/// Checks a WebSocket handshake, returning the `Sec-WebSocket-Accept` to
+/// answer with.
+fn accept_upgrade(request: &Request) -> Result<String, Response> {
+    if request.method != "GET" {
+        return Err(Response::error(405, "method_not_allowed", "a WebSocket starts with GET"));
+    }
+    if request.header("sec-websocket-version") != Some("13") {
+        return Err(Response::error(400, "invalid_request", "only WebSocket version 13 is supported"));
+    }
+    let Some(key) = request.header("sec-websocket-key") else {
+        return Err(Response::error(400, "invalid_request", "missing Sec-WebSocket-Key"));
+    };
+    Ok(websocket::accept_key(key))
+}
+
+/// Refuses requests a browser sends for a page from another site: a
+/// `Host` that isn't us (DNS rebinding), or an `Origin` that isn't `Host`.
+fn check_sender(request: &Request, local_addr: SocketAddr) -> Result<(), Response> {
+    let Some(host) = request.header("host") else {
+        return Err(Response::error(400, "invalid_request", "missing Host"));
+    };
+    if !is_our_host(host, local_addr) {
+        return Err(Response::error(
+            403,
+            "forbidden_host",
+            format!("use this server's address (port {}), not {host}", local_addr.port()),
+        ));
+    }
+    // Browsers send Origin with every POST and WebSocket; other clients
+    // don't have to
+    if let Some(origin) = request.header("origin") {
+        let origin_host = origin.split_once("://").map_or(origin, |(_, host)| host);
+        if !host.eq_ignore_ascii_case(origin_host) {
+            return Err(Response::error(403, "forbidden_origin", "pages from other sites can't use this API"));
+        }
+    }
+    Ok(())
+}
+
+/// Whether a `Host` header names the server listening on `local_addr`: our
+/// port, and one of our IP addresses or `localhost`. Any other name could
+/// be a site pointing its DNS at us.
+fn is_our_host(host: &str, local_addr: SocketAddr) -> bool {
+    // "192.168.1.10:7879", "[::1]:7879", or without the port if it's 80
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) if !port.ends_with(']') => (name, port.parse().ok()),
+        _ => (host, Some(80)),
+    };
+    if port != Some(local_addr.port()) {
+        return false;
+    }
+    let name = name.strip_prefix('[').and_then(|name| name.strip_suffix(']')).unwrap_or(name);
+    if name.eq_ignore_ascii_case("localhost") {
+        return local_addr.ip().is_unspecified() || local_addr.ip().is_loopback();
+    }
+    name.parse::<IpAddr>()
+        .is_ok_and(|ip| local_addr.ip().is_unspecified() || ip == local_addr.ip())
+}
+
+/// Decodes `%XX` escapes, and `+` as a space in query strings. Malformed
+/// escapes are kept as they are.
+fn percent_decode(s: &str, plus_is_space: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                if let Some(byte) = s.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            b'+' if plus_is_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Dispatches a request to its endpoint.
+async fn route(request: Request, request_tx: &mpsc::Sender<IpcRequest>) -> Response {
+    let segments: Vec<&str> = request.segments.iter().map(String::as_str).collect();
+    let (Ok(response) | Err(response)) = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", []) => Ok(Response::page()),
+        ("GET", ["ws"]) => Err(Response::error(426, "upgrade_required", "this is a WebSocket endpoint")),
+        ("GET", ["peers"]) => list_peers(request_tx).await,
+        ("GET", ["messages", peer]) => get_messages(request_tx, peer, &request).await,
+        ("POST", ["messages"]) => post_message(request_tx, &request).await,
+        (_, [] | ["ws"] | ["peers"] | ["messages"] | ["messages", _]) => Err(Response::error(
+            405,
+            "method_not_allowed",
+            format!("{} is not supported here", request.method),
+        )),
+        _ => Err(Response::error(404, "not_found", "no such endpoint")),
+    };
+    response
+}
+
+/// Hands `request` to the main loop, turning a refusal into its response.
+async fn submit(request_tx: &mpsc::Sender<IpcRequest>, request: ClientRequest) -> Result<ServerMessage, Response> {
+    match IpcRequest::submit(request_tx, request).await {
+        Some(ServerMessage::Error { code, message }) => Err(Response::from_error(code, message)),
+        Some(response) => Ok(response),
+        None => Err(Response::error(503, "shutting_down", "the daemon is shutting down")),
+    }
+}
+
+fn unexpected(response: ServerMessage) -> Response {
+    Response::error(500, "internal_error", format!("unexpected response from the daemon: {response:?}"))
+}
+
+/// `GET /peers`
+async fn list_peers(request_tx: &mpsc::Sender<IpcRequest>) -> Result<Response, Response> {
+    match submit(request_tx, ClientRequest::ListPeers).await? {
+        ServerMessage::PeerList { peers } => Ok(Response::ok(200, json!(peers))),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// Looks up a peer by ID or display name.
+async fn find_peer(request_tx: &mpsc::Sender<IpcRequest>, to: &str) -> Result<PeerId, Response> {
+    let peers = match submit(request_tx, ClientRequest::ListPeers).await? {
+        ServerMessage::PeerList { peers } => peers,
+        other => return Err(unexpected(other)),
+    };
+    send::find_peer(&peers, to)
+        .map(|peer| peer.id.clone())
+        .map_err(|e| Response::error(404, ErrorCode::PeerNotFound.as_str(), e.to_string()))
+}
+
+/// `GET /messages/<peer>?limit=&cursor=`
+async fn get_messages(
+    request_tx: &mpsc::Sender<IpcRequest>,
+    peer: &str,
+    request: &Request,
+) -> Result<Response, Response> {
+    let limit = match request.param("limit") {
+        None => DEFAULT_LIMIT,
+        Some(limit) => limit
+            .parse::<u32>()
+            .map_err(|_| Response::error(400, "invalid_request", "limit must be a number"))?
+            .clamp(1, MAX_LIMIT),
+    };
+    let request = ClientRequest::GetMessages {
+        peer_id: find_peer(request_tx, peer).await?,
+        limit,
+        before: None,
+        cursor: request.param("cursor").map(str::to_string),
+    };
+    match submit(request_tx, request).await? {
+        ServerMessage::Messages {
+            messages,
+            next_cursor,
+            prev_cursor,
+        } => Ok(Response::ok(
+            200,
+            json!({ "messages": messages, "next_cursor": next_cursor, "prev_cursor": prev_cursor }),
+        )),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// The body of `POST /messages`.
+#[derive(Debug, Deserialize)]
+struct NewMessage {
+    to: String,
+    content: String,
+}
+
+/// `POST /messages`
+async fn post_message(request_tx: &mpsc::Sender<IpcRequest>, request: &Request) -> Result<Response, Response> {
+    // Not something a form on another site can send ("application/json;
+    // charset=utf-8" is fine)
+    let media_type = request.header("content-type").map(|value| value.split(';').next().unwrap_or("").trim());
+    if !media_type.is_some_and(|media_type| media_type.eq_ignore_ascii_case("application/json")) {
+        return Err(Response::error(
+            415,
+            "unsupported_media_type",
+            "send the message as Content-Type: application/json",
+        ));
+    }
+    let message: NewMessage = serde_json::from_slice(&request.body).map_err(|e| {
+        Response::error(
+            400,
+            "invalid_request",
+            format!(r#"expected {{"to": ..., "content": ...}}: {e}"#),
+        )
+    })?;
+    let request = ClientRequest::SendMessage {
+        peer_id: find_peer(request_tx, &message.to).await?,
+        content: message.content,
+        reply_to: None,
+    };
+    match submit(request_tx, request).await? {
+        ServerMessage::MessageSent { message_id } => Ok(Response::ok(201, json!({ "message_id": message_id }))),
+        other => Err(unexpected(other)),
+    }
+}
+
+/// Writes `response` with the headers every answer gets.
+async fn write_response<W: AsyncWrite + Unpin>(writer: &mut W, response: &Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        426 => "Upgrade Required",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {reason}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(&response.body).await?;
+    writer.shutdown().await
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use familycom_core::types::{MessageId, NotificationPrefs, PeerInfo, Timestamp};
+
+    /// Stands in for the main loop: knows one peer and accepts every send,
+    /// reporting each request it gets.
+    fn fake_daemon() -> (mpsc::Sender<IpcRequest>, mpsc::UnboundedReceiver<ClientRequest>) {
+        let (request_tx, mut request_rx) = mpsc::channel::<IpcRequest>(8);
+        let (seen_tx, seen_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(IpcRequest { request, response_tx }) = request_rx.recv().await {
+                let response = match &request {
+                    ClientRequest::ListPeers => ServerMessage::PeerList {
+                        peers: vec![PeerInfo {
+                            id: PeerId::new("peer-sofi"),
+                            display_name: "Cuarto de Sofi".to_string(),
+                            addresses: vec!["192.168.1.30:9876".to_string()],
+                            last_seen_at: Timestamp::from_millis(1_000),
+                            online: true,
+                            do_not_disturb: false,
+                            away: false,
+                            avatar: None,
+                            accent_color: None,
+                            capabilities: Vec::new(),
+                            notifications: NotificationPrefs::default(),
+                            interface_addresses: Default::default(),
+                        }],
+                    },
+                    ClientRequest::SendMessage { content, .. } if content.is_empty() => ServerMessage::Error {
+                        code: ErrorCode::InvalidContent,
+                        message: "message is empty".to_string(),
+                    },
+                    ClientRequest::SendMessage { .. } => ServerMessage::MessageSent {
+                        message_id: MessageId::new("m1"),
+                    },
+                    ClientRequest::GetMessages { .. } => ServerMessage::Messages {
+                        messages: Vec::new(),
+                        next_cursor: None,
+                        prev_cursor: None,
+                    },
+                    _ => ServerMessage::Ok,
+                };
+                let _ = seen_tx.send(request);
+                let _ = response_tx.send(response).await;
+            }
+        });
+        (request_tx, seen_rx)
+    }
+
+    /// Sends a raw request and returns the status and JSON body.
+    async fn call(addr: SocketAddr, raw: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    /// A JSON `POST /messages` to `addr`, with `extra` headers
+    /// (`"Name: value\r\n"` each).
+    fn post(addr: SocketAddr, extra: &str, body: &str) -> String {
+        format!(
+            "POST /messages HTTP/1.1\r\nHost: {addr}\r\nContent-Type: application/json\r\n{extra}\
+             Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    async fn start() -> (SocketAddr, mpsc::UnboundedReceiver<ClientRequest>) {
+        start_with(EventBus::new()).await
+    }
+
+    async fn start_with(events: EventBus) -> (SocketAddr, mpsc::UnboundedReceiver<ClientRequest>) {
+        let (request_tx, seen_rx) = fake_daemon();
+        let server = HttpServer::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move { server.accept_loop(request_tx, events, CancellationToken::new()).await });
+        (addr, seen_rx)
+    }
+
+    /// Opens a WebSocket as a browser on `origin` would, returning the
+    /// stream after the 101 (or the status it got instead).
+    async fn open_websocket(addr: SocketAddr, origin: &str) -> Result<BufReader<TcpStream>, u16> {
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+        let handshake = format!(
+            "GET /ws HTTP/1.1\r\nHost: {addr}\r\nOrigin: {origin}\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        );
+        stream.get_mut().write_all(handshake.as_bytes()).await.unwrap();
+        let mut status_line = String::new();
+        stream.read_line(&mut status_line).await.unwrap();
+        let status = status_line.split_whitespace().nth(1).unwrap().parse().unwrap();
+        if status != 101 {
+            return Err(status);
+        }
+        let mut accepted = false;
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+            accepted |= line.trim_end() == "Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+        }
+        assert!(accepted);
+        Ok(stream)
+    }
+
+    /// Sends `text` in a frame masked with zeros, as small as the tests need.
+    async fn send_text(stream: &mut BufReader<TcpStream>, text: &str) {
+        let mut frame = vec![0x81, 0x80 | text.len() as u8, 0, 0, 0, 0];
+        frame.extend_from_slice(text.as_bytes());
+        stream.get_mut().write_all(&frame).await.unwrap();
+    }
+
+    /// Reads one unmasked text frame from the server as JSON.
+    async fn recv_json(stream: &mut BufReader<TcpStream>) -> Value {
+        let mut head = [0u8; 2];
+        stream.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[0], 0x81);
+        let len = match head[1] {
+            126 => usize::from(stream.read_u16().await.unwrap()),
+            len => usize::from(len),
+        };
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).await.unwrap();
+        serde_json::from_slice(&payload).unwrap()
+    }
+
+    #[tokio::test]
+    async fn peers_and_conversations_can_be_read() {
+        let (addr, mut seen) = start().await;
+
+        let (status, peers) = call(addr, &format!("GET /peers HTTP/1.1\r\nHost: {addr}\r\n\r\n")).await;
+        assert_eq!(status, 200);
+        assert_eq!(peers[0]["display_name"], "Cuarto de Sofi");
+
+        // By display name, escaped as a browser would
+        let (status, page) = call(
+            addr,
+            &format!("GET /messages/cuarto%20de%20sofi?limit=9999 HTTP/1.1\r\nHost: {addr}\r\n\r\n"),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert!(page["messages"].as_array().unwrap().is_empty());
+        let mut last = None;
+        while let Ok(request) = seen.try_recv() {
+            last = Some(request);
+        }
+        match last {
+            Some(ClientRequest::GetMessages { peer_id, limit, .. }) => {
+                assert_eq!(peer_id.as_str(), "peer-sofi");
+                assert_eq!(limit, MAX_LIMIT);
+            }
+            other => panic!("expected GetMessages, got {other:?}"),
+        }
+
+        let (status, error) = call(addr, &format!("GET /messages/Garaje HTTP/1.1\r\nHost: {addr}\r\n\r\n")).await;
+        assert_eq!(status, 404);
+        assert_eq!(error["error"], "peer_not_found");
+    }
+
+    #[tokio::test]
+    async fn messages_can_be_posted() {
+        let (addr, mut seen) = start().await;
+        let body = r#"{"to": "peer-sofi", "content": "¡A cenar!"}"#;
+        let raw = format!(
+            "POST /messages HTTP/1.1\r\nHost: {addr}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let (status, sent) = call(addr, &raw).await;
+        assert_eq!(status, 201);
+        assert_eq!(sent["message_id"], "m1");
+        let mut sends = Vec::new();
+        while let Ok(request) = seen.try_recv() {
+            if let ClientRequest::SendMessage { peer_id, content, .. } = request {
+                sends.push((peer_id, content));
+            }
+        }
+        assert_eq!(sends, [(PeerId::new("peer-sofi"), "¡A cenar!".to_string())]);
+
+        // The daemon's refusals keep their error code
+        let body = r#"{"to": "peer-sofi", "content": ""}"#;
+        let (status, error) = call(addr, &post(addr, "", body)).await;
+        assert_eq!(status, 400);
+        assert_eq!(error["error"], "invalid_content");
+    }
+
+    #[tokio::test]
+    async fn bad_requests_are_answered_not_dropped() {
+        let (addr, _) = start().await;
+        assert_eq!(call(addr, &format!("DELETE /peers HTTP/1.1\r\nHost: {addr}\r\n\r\n")).await.0, 405);
+        assert_eq!(call(addr, &format!("GET /admin HTTP/1.1\r\nHost: {addr}\r\n\r\n")).await.0, 404);
+        let (status, error) = call(addr, &post(addr, "", "hola!")).await;
+        assert_eq!(status, 400);
+        assert_eq!(error["error"], "invalid_request");
+    }
+
+    #[tokio::test]
+    async fn the_page_is_served_at_the_root() {
+        let (addr, _) = start().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET / HTTP/1.1\r\nHost: {addr}\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Type: text/html"));
+        assert!(response.contains("new WebSocket"));
+
+        // Without the upgrade headers /ws is no use
+        assert_eq!(call(addr, &format!("GET /ws HTTP/1.1\r\nHost: {addr}\r\n\r\n")).await.0, 426);
+    }
+
+    #[tokio::test]
+    async fn websocket_answers_requests_and_pushes_events() {
+        let events = EventBus::new();
+        let (addr, _) = start_with(events.clone()).await;
+        let mut ws = open_websocket(addr, &format!("http://{addr}")).await.unwrap();
+
+        send_text(&mut ws, r#""ListPeers""#).await;
+        let peers = recv_json(&mut ws).await;
+        assert_eq!(peers["type"], "PeerList");
+        assert_eq!(peers["peers"][0]["id"], "peer-sofi");
+        assert!(peers.get("seq").is_none());
+
+        send_text(&mut ws, "not json").await;
+        assert_eq!(recv_json(&mut ws).await["code"], "invalid_request");
+
+        events.send(ServerMessage::PeerOffline {
+            peer_id: PeerId::new("peer-sofi"),
+        });
+        let event = recv_json(&mut ws).await;
+        assert_eq!(event["type"], "PeerOffline");
+        assert!(event["seq"].is_string());
+    }
+
+    #[tokio::test]
+    async fn websocket_from_another_site_is_refused() {
+        let (addr, _) = start().await;
+        assert_eq!(open_websocket(addr, "https://example.com").await.err(), Some(403));
+    }
+
+    #[tokio::test]
+    async fn posts_other_sites_could_make_are_refused() {
+        let (addr, mut seen) = start().await;
+        let body = r#"{"to": "peer-sofi", "content": "hola"}"#;
+
+        // A form on another site: text/plain, from its own origin
+        let form = format!(
+            "POST /messages HTTP/1.1\r\nHost: {addr}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let (status, error) = call(addr, &form).await;
+        assert_eq!(status, 415);
+        assert_eq!(error["error"], "unsupported_media_type");
+        let (status, error) = call(addr, &post(addr, "Origin: https://example.com\r\n", body)).await;
+        assert_eq!(status, 403);
+        assert_eq!(error["error"], "forbidden_origin");
+
+        // Its name pointed at us: Host and Origin agree, but aren't us
+        let rebound = format!("familycom.example.com:{}", addr.port());
+        let raw = post(addr, "", body).replace(&addr.to_string(), &rebound);
+        let raw = raw.replace("Content-Type", &format!("Origin: http://{rebound}\r\nContent-Type"));
+        let (status, error) = call(addr, &raw).await;
+        assert_eq!(status, 403);
+        assert_eq!(error["error"], "forbidden_host");
+
+        let no_host = post(addr, "", body).replace(&format!("Host: {addr}\r\n"), "");
+        assert_eq!(call(addr, &no_host).await.0, 400);
+
+        // None of them got to the daemon; the page itself still can
+        assert!(seen.try_recv().is_err());
+        let same_site = post(addr, &format!("Origin: http://{addr}\r\n"), body);
+        assert_eq!(call(addr, &same_site).await.0, 201);
+    }
+
+    #[tokio::test]
+    async fn reads_from_other_sites_are_refused() {
+        let (addr, _) = start().await;
+        let raw = format!("GET /peers HTTP/1.1\r\nHost: {addr}\r\nOrigin: https://example.com\r\n\r\n");
+        assert_eq!(call(addr, &raw).await.0, 403);
+        let raw = format!("GET /peers HTTP/1.1\r\nHost: localhost:{}\r\n\r\n", addr.port());
+        assert_eq!(call(addr, &raw).await.0, 200);
+        let raw = format!("GET /peers HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n", addr.port() + 1);
+        assert_eq!(call(addr, &raw).await.0, 403);
+    }
+
+    #[test]
+    fn host_must_name_the_address_we_listen_on() {
+        let loopback: SocketAddr = "127.0.0.1:7879".parse().unwrap();
+        assert!(is_our_host("127.0.0.1:7879", loopback));
+        assert!(is_our_host("LOCALHOST:7879", loopback));
+        assert!(!is_our_host("192.168.1.10:7879", loopback));
+        assert!(!is_our_host("127.0.0.1", loopback));
+        assert!(!is_our_host("rebind.example:7879", loopback));
+
+        let lan: SocketAddr = "0.0.0.0:7879".parse().unwrap();
+        assert!(is_our_host("192.168.1.10:7879", lan));
+        assert!(is_our_host("[::1]:7879", lan));
+        assert!(!is_our_host("pc-sala.example:7879", lan));
+        assert!(!is_our_host("192.168.1.10:8080", lan));
+
+        let port_80: SocketAddr = "192.168.1.10:80".parse().unwrap();
+        assert!(is_our_host("192.168.1.10", port_80));
+        assert!(!is_our_host("192.168.1.11", port_80));
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        assert_eq!(percent_decode("Habitaci%C3%B3n%20de%20Mam%C3%A1", false), "Habitación de Mamá");
+        assert_eq!(percent_decode("a+b", false), "a+b");
+        assert_eq!(percent_decode("a+b", true), "a b");
+        assert_eq!(percent_decode("100%", false), "100%");
+    }
+}
@@ -0,0 +1,122 @@
+//! How long since the user last touched the keyboard or mouse, for
+//! automatic away (`[away]` in the config).
+//!
+//! Read from the desktop, best effort:
+//! - Linux: GNOME's idle monitor over D-Bus (`gdbus`) on Wayland, and
+//!   `xprintidle` on X11.
+//! - macOS: `HIDIdleTime` from `ioreg`.
+//! - Windows: `GetLastInputInfo`.
+//!
+//! `None` where none of these work (a headless Pi, a compositor without
+//! an idle monitor); the daemon then only counts keys pressed in the TUI.
+
+use std::time::Duration;
+
+/// Time since the last keyboard or mouse input on this machine's desktop,
+/// if it can be read. Runs a command on Linux and macOS, so call it from a
+/// blocking task.
+#[cfg(target_os = "linux")]
+pub fn system_idle() -> Option<Duration> {
+    use crate::network::command_output;
+
+    let mutter = || {
+        command_output(
+            "gdbus",
+            &[
+                "call",
+                "--session",
+                "--dest",
+                "org.gnome.Mutter.IdleMonitor",
+                "--object-path",
+                "/org/gnome/Mutter/IdleMonitor/Core",
+                "--method",
+                "org.gnome.Mutter.IdleMonitor.GetIdletime",
+            ],
+        )
+        .and_then(|out| parse_gdbus_idle(&out))
+    };
+    let x11 = || command_output("xprintidle", &[]).and_then(|out| parse_millis(&out));
+    // Under Wayland, X11 only sees input to X clients, so it's asked last
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        mutter().or_else(x11)
+    } else {
+        x11().or_else(mutter)
+    }
+}
+
+/// Time since the last keyboard or mouse input, if it can be read. Runs a
+/// command, so call it from a blocking task.
+#[cfg(target_os = "macos")]
+pub fn system_idle() -> Option<Duration> {
+    crate::network::command_output("ioreg", &["-c", "IOHIDSystem", "-d", "4"]).and_then(|out| parse_ioreg_idle(&out))
+}
+
+/// Time since the last keyboard or mouse input in this session.
+#[cfg(windows)]
+pub fn system_idle() -> Option<Duration> {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut last_input = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    // SAFETY: `last_input` is valid for writing, with `cbSize` set as required
+    if unsafe { GetLastInputInfo(&mut last_input) } == 0 {
+        return None;
+    }
+    // SAFETY: no arguments, no preconditions
+    let now = unsafe { GetTickCount() };
+    // Both are tick counts that wrap after 49.7 days
+    Some(Duration::from_millis(u64::from(now.wrapping_sub(last_input.dwTime))))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn system_idle() -> Option<Duration> {
+    None
+}
+
+/// Reads `xprintidle` output: milliseconds, e.g. `15320`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_millis(output: &str) -> Option<Duration> {
+    output.trim().parse().ok().map(Duration::from_millis)
+}
+
+/// Reads the idle monitor's answer through `gdbus`: `(uint64 15320,)`,
+/// in milliseconds.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_gdbus_idle(output: &str) -> Option<Duration> {
+    let millis = output.trim().strip_prefix("(uint64 ")?.split(',').next()?;
+    parse_millis(millis)
+}
+
+/// Finds `"HIDIdleTime" = 15320000000` (nanoseconds) in `ioreg` output.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_ioreg_idle(output: &str) -> Option<Duration> {
+    output
+        .lines()
+        .find_map(|line| line.split_once("\"HIDIdleTime\" = "))
+        .and_then(|(_, nanos)| nanos.trim().parse().ok())
+        .map(Duration::from_nanos)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_times_are_parsed_from_each_tool() {
+        assert_eq!(parse_millis("15320\n"), Some(Duration::from_millis(15_320)));
+        assert_eq!(parse_gdbus_idle("(uint64 15320,)\n"), Some(Duration::from_millis(15_320)));
+        let ioreg = "    | |   \"HIDIdleTime\" = 15320000000\n    | |   \"HIDKeyboardModifierMappingPairs\" = ()";
+        assert_eq!(parse_ioreg_idle(ioreg), Some(Duration::from_millis(15_320)));
+
+        assert_eq!(parse_millis("couldn't open display"), None);
+        assert_eq!(parse_gdbus_idle("Error: GDBus.Error:org.freedesktop.DBus.Error.ServiceUnknown"), None);
+        assert_eq!(parse_ioreg_idle(""), None);
+    }
+}
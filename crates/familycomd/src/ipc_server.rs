@@ -9,18 +9,54 @@
 //! # Protocol
 //!
 //! JSON lines over Unix socket: each message is a JSON object + newline.
-//! See `familycom_core::ipc` for the type definitions.
+//! See `familycom_core::ipc` for the type definitions. A line can instead
+//! be a JSON-RPC 2.0 request (`familycom_core::jsonrpc`); its response then
+//! comes back as JSON-RPC too.
+//!
+//! A connection's requests reach the daemon one at a time, so responses
+//! come back in the order the requests were sent; the handler keeps a
+//! queue of how each pending request wants its response written (and
+//! under which JSON-RPC `id`).
 //!
 //! # Multiple Clients
 //!
 //! Multiple TUI clients can connect simultaneously. Each gets its own
-//! connection handler task. Subscribed clients all receive the same events.
+//! connection handler task. Subscribed clients all see the same event
+//! stream, narrowed by the `EventFilter` each one subscribed with. Each
+//! event carries its `seq`, so a client that reconnects can resume where
+//! it left off (see `crate::events`).
+//!
+//! # Transports
+//!
+//! The server is generic over an `IpcTransport`: the Unix socket is the
+//! default (a named pipe on Windows, which has none), and the daemon can
+//! additionally listen on localhost TCP or a Windows named pipe
+//! (`--ipc-listen`). The JSON-lines protocol is the same
+//! on all of them.
+//!
+//! # Shutdown
+//!
+//! Cancelling the shutdown token stops the accept loop and closes every
+//! client connection; the accept loop waits (bounded) for the handlers to
+//! finish before returning.
 
-use familycom_core::ipc::{self, ClientRequest, ServerMessage};
+use crate::events::{Event, EventBus};
+use crate::supervisor;
+use familycom_core::ipc::{self, ClientRequest, ErrorCode, EventFilter, IpcError, ServerMessage, Subscription};
+use familycom_core::jsonrpc;
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::net::SocketAddr;
+#[cfg(unix)]
 use std::path::{Path, PathBuf};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 /// A request from a TUI client, tagged with a response channel.
@@ -36,21 +72,66 @@ pub struct IpcRequest {
     pub response_tx: mpsc::Sender<ServerMessage>,
 }
 
-/// The IPC server managing the Unix socket.
-pub struct IpcServer {
+impl IpcRequest {
+    /// Hands `request` to the main loop as if a client had sent it, for
+    /// parts of the daemon acting on the user's behalf (tray, SIGHUP,
+    /// MQTT, the HTTP API). Returns the response, or `None` once the loop
+    /// has exited. A chunked history page is put back together into one
+    /// `Messages`, and progress reports are skipped.
+    pub async fn submit(request_tx: &mpsc::Sender<IpcRequest>, request: ClientRequest) -> Option<ServerMessage> {
+        let (response_tx, mut response_rx) = mpsc::channel(1);
+        request_tx.send(IpcRequest { request, response_tx }).await.ok()?;
+        let mut chunks = Vec::new();
+        loop {
+            match response_rx.recv().await? {
+                ServerMessage::MessagesChunk { messages } => chunks.extend(messages),
+                ServerMessage::MessagesEnd {
+                    next_cursor,
+                    prev_cursor,
+                    ..
+                } => {
+                    return Some(ServerMessage::Messages {
+                        messages: chunks,
+                        next_cursor,
+                        prev_cursor,
+                    })
+                }
+                response if response.is_partial() => {}
+                response => return Some(response),
+            }
+        }
+    }
+}
+
+/// A way for IPC clients to reach the daemon.
+///
+/// Implementations only need to hand out connected byte streams; framing
+/// and request handling are shared.
+pub trait IpcTransport: Send + Sync + 'static {
+    /// A connected client.
+    type Stream: AsyncRead + AsyncWrite + Send + Unpin + 'static;
+
+    /// Waits for the next client to connect.
+    fn accept(&self) -> impl Future<Output = std::io::Result<Self::Stream>> + Send;
+}
+
+/// IPC over a Unix domain socket (the default on Linux and macOS).
+#[cfg(unix)]
+pub struct UnixTransport {
     /// Path to the Unix socket file.
     socket_path: PathBuf,
     /// The underlying Unix listener.
     listener: UnixListener,
 }
 
-impl IpcServer {
-    /// Creates a new IPC server bound to the given socket path.
+#[cfg(unix)]
+impl UnixTransport {
+    /// Binds the socket at `socket_path`.
     ///
     /// If a stale socket file exists (from a previous crash), it is removed
-    /// before binding. This is safe because we check for an existing daemon
-    /// process via the socket — if connecting fails, it's stale.
-    pub async fn bind(socket_path: &Path) -> Result<Self, std::io::Error> {
+    /// before binding. This is safe because `main` first checks for a
+    /// running daemon by connecting to the socket — if that fails, it's stale.
+    pub fn bind(socket_path: &Path) -> Result<Self, std::io::Error> {
         // Remove stale socket file if it exists.
         // This handles the case where the daemon crashed without cleanup.
         if socket_path.exists() {
@@ -64,59 +145,26 @@ impl IpcServer {
         }
 
         let listener = UnixListener::bind(socket_path)?;
-        info!(path = %socket_path.display(), "IPC server listening");
-
         Ok(Self {
             socket_path: socket_path.to_owned(),
             listener,
         })
     }
+}
 
-    /// Runs the accept loop for IPC clients.
-    ///
-    /// Each connected client gets its own handler task. Incoming requests
-    /// are forwarded to the daemon via `request_tx`. Real-time events are
-    /// broadcast to all subscribed clients via `event_tx`.
-    ///
-    /// # Arguments
-    ///
-    /// * `request_tx` - Channel to forward client requests to the daemon.
-    /// * `event_rx_factory` - A broadcast sender that clients subscribe to for real-time events.
-    pub async fn accept_loop(
-        self,
-        request_tx: mpsc::Sender<IpcRequest>,
-        event_tx: broadcast::Sender<ServerMessage>,
-    ) {
-        loop {
-            match self.listener.accept().await {
-                Ok((stream, _addr)) => {
-                    debug!("accepted IPC client connection");
-                    let req_tx = request_tx.clone();
-                    let evt_tx = event_tx.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = handle_ipc_client(stream, req_tx, evt_tx).await {
-                            debug!(error = %e, "IPC client disconnected");
-                        }
-                    });
-                }
-                Err(e) => {
-                    error!(error = %e, "failed to accept IPC connection");
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                }
-            }
-        }
-    }
+#[cfg(unix)]
+impl IpcTransport for UnixTransport {
+    type Stream = tokio::net::UnixStream;
 
-    /// Returns the socket path.
-    #[allow(dead_code)]
-    pub fn socket_path(&self) -> &Path {
-        &self.socket_path
+    async fn accept(&self) -> std::io::Result<Self::Stream> {
+        self.listener.accept().await.map(|(stream, _addr)| stream)
     }
 }
 
-/// Clean up the socket file when the server is dropped.
+/// Clean up the socket file when the transport is dropped.
 /// This is important so the next run doesn't find a stale socket.
-impl Drop for IpcServer {
+#[cfg(unix)]
+impl Drop for UnixTransport {
     fn drop(&mut self) {
         if self.socket_path.exists() {
             if let Err(e) = std::fs::remove_file(&self.socket_path) {
@@ -132,26 +180,190 @@ impl Drop for IpcServer {
     }
 }
 
+/// IPC over TCP on a loopback address, for tooling that can't use Unix
+/// sockets. IPC is unauthenticated, so only loopback addresses are allowed.
+pub struct TcpTransport {
+    listener: TcpListener,
+}
+
+impl TcpTransport {
+    /// Binds to `addr`, which must be a loopback address.
+    pub async fn bind(addr: SocketAddr) -> Result<Self, std::io::Error> {
+        if !addr.ip().is_loopback() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("IPC over TCP must use a loopback address, got {addr}"),
+            ));
+        }
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+        })
+    }
+
+    /// The bound address (useful when binding to port 0).
+    #[allow(dead_code)]
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl IpcTransport for TcpTransport {
+    type Stream = tokio::net::TcpStream;
+
+    async fn accept(&self) -> std::io::Result<Self::Stream> {
+        let (stream, _addr) = self.listener.accept().await?;
+        Ok(stream)
+    }
+}
+
+/// IPC over a Windows named pipe, e.g. `\\.\pipe\familycom`.
+///
+/// A pipe instance serves a single client, so a fresh instance is created
+/// as soon as one is connected.
+#[cfg(windows)]
+pub struct NamedPipeTransport {
+    name: String,
+    next: tokio::sync::Mutex<tokio::net::windows::named_pipe::NamedPipeServer>,
+}
+
+#[cfg(windows)]
+impl NamedPipeTransport {
+    /// Creates the first instance of the pipe `name`.
+    pub fn bind(name: &str) -> Result<Self, std::io::Error> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let first = ServerOptions::new().first_pipe_instance(true).create(name)?;
+        Ok(Self {
+            name: name.to_string(),
+            next: tokio::sync::Mutex::new(first),
+        })
+    }
+}
+
+#[cfg(windows)]
+impl IpcTransport for NamedPipeTransport {
+    type Stream = tokio::net::windows::named_pipe::NamedPipeServer;
+
+    async fn accept(&self) -> std::io::Result<Self::Stream> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let mut next = self.next.lock().await;
+        next.connect().await?;
+        let fresh = ServerOptions::new().create(&self.name)?;
+        Ok(std::mem::replace(&mut *next, fresh))
+    }
+}
+
+/// The IPC server: accepts clients on a transport and serves them.
+pub struct IpcServer<T: IpcTransport> {
+    transport: T,
+}
+
+#[cfg(unix)]
+impl IpcServer<UnixTransport> {
+    /// Creates a new IPC server bound to the given Unix socket path.
+    pub async fn bind(socket_path: &Path) -> Result<Self, std::io::Error> {
+        let transport = UnixTransport::bind(socket_path)?;
+        info!(path = %socket_path.display(), "IPC server listening");
+        Ok(Self::new(transport))
+    }
+
+    /// Returns the socket path.
+    #[allow(dead_code)]
+    pub fn socket_path(&self) -> &Path {
+        &self.transport.socket_path
+    }
+}
+
+impl<T: IpcTransport> IpcServer<T> {
+    /// Creates a server on an already bound transport.
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    /// Runs the accept loop for IPC clients.
+    ///
+    /// Each connected client gets its own handler task. Incoming requests
+    /// are forwarded to the daemon via `request_tx`. Real-time events are
+    /// pushed to all subscribed clients from `events`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_tx` - Channel to forward client requests to the daemon.
+    /// * `events` - The event stream clients subscribe to.
+    /// * `shutdown` - Cancelled when the daemon is stopping; the loop then
+    ///   drains client connections and returns.
+    pub async fn accept_loop(
+        &self,
+        request_tx: mpsc::Sender<IpcRequest>,
+        events: EventBus,
+        shutdown: CancellationToken,
+    ) {
+        let mut clients = JoinSet::new();
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+
+                // Reap finished client tasks so the set doesn't grow forever
+                Some(_) = clients.join_next() => {}
+
+                accepted = self.transport.accept() => match accepted {
+                    Ok(stream) => {
+                        debug!("accepted IPC client connection");
+                        let req_tx = request_tx.clone();
+                        let events = events.clone();
+                        let shutdown = shutdown.clone();
+                        clients.spawn(async move {
+                            if let Err(e) = handle_ipc_client(stream, req_tx, events, shutdown).await {
+                                debug!(error = %e, "IPC client disconnected");
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!(error = %e, "failed to accept IPC connection");
+                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                    }
+                },
+            }
+        }
+
+        debug!(clients = clients.len(), "IPC server stopping, draining clients");
+        supervisor::drain(&mut clients, "ipc_server").await;
+    }
+}
+
 /// Handles a single IPC client connection.
 ///
 /// Reads JSON-line requests from the client, forwards them to the daemon,
 /// and sends responses back. If the client sends `Subscribe`, it also
-/// receives broadcast events.
-async fn handle_ipc_client(
-    stream: UnixStream,
+/// receives the events its subscriptions let through, after replaying the
+/// ones it missed if it asked to resume. Subscription requests are
+/// answered here, without involving the daemon.
+///
+/// The next request is only read once the daemon has answered the last
+/// one. Some answers are finished in the background (e.g. a history page
+/// filled in from the archive peer), and would otherwise overtake the
+/// responses to requests sent after them.
+async fn handle_ipc_client<S>(
+    stream: S,
     request_tx: mpsc::Sender<IpcRequest>,
-    event_tx: broadcast::Sender<ServerMessage>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let (reader, mut writer) = stream.into_split();
+    events: EventBus,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
     let mut buf_reader = BufReader::new(reader);
     let mut line_buf = String::new();
 
     // Channel for responses to this specific client's requests
     let (response_tx, mut response_rx) = mpsc::channel::<ServerMessage>(32);
 
-    // Whether this client is subscribed to real-time events
-    let mut subscribed = false;
-    let mut event_rx: Option<broadcast::Receiver<ServerMessage>> = None;
+    let mut subscriptions = Subscriptions::default();
+    // How to write the response to each request forwarded to the daemon,
+    // oldest first
+    let mut pending: VecDeque<Reply> = VecDeque::new();
 
     loop {
         // Use tokio::select! to handle both:
@@ -159,8 +371,19 @@ async fn handle_ipc_client(
         // 2. Responses from the daemon (reading from response channel)
         // 3. Broadcast events (if subscribed)
         tokio::select! {
+            // The daemon is stopping: close the connection, after writing
+            // any responses already queued (e.g. the `ShuttingDown` for a
+            // `Shutdown` request from this very client)
+            _ = shutdown.cancelled() => {
+                while let Ok(response) = response_rx.try_recv() {
+                    write_reply(&mut writer, &mut pending, &response).await?;
+                }
+                debug!("closing IPC client for shutdown");
+                return Ok(());
+            }
+
             // Read next request line from the client
-            read_result = buf_reader.read_line(&mut line_buf) => {
+            read_result = buf_reader.read_line(&mut line_buf), if pending.is_empty() => {
                 match read_result {
                     Ok(0) => {
                         // Client disconnected (EOF)
@@ -168,48 +391,60 @@ async fn handle_ipc_client(
                         return Ok(());
                     }
                     Ok(_) => {
-                        // Parse the JSON request
-                        let request = match ipc::decode_request(&line_buf) {
-                            Ok(req) => req,
-                            Err(e) => {
-                                warn!(error = %e, line = %line_buf.trim(), "invalid IPC request");
-                                let error_msg = ServerMessage::Error {
-                                    code: "invalid_request".to_string(),
-                                    message: format!("failed to parse request: {e}"),
-                                };
-                                let json = ipc::encode_response(&error_msg)?;
+                        // Parse the request, JSON-RPC or native
+                        let (reply, request) = match jsonrpc::decode_call(&line_buf) {
+                            Some(Ok(call)) => (Reply::Rpc(call.id), call.request),
+                            Some(Err(e)) => {
+                                warn!(code = e.code, error = %e.message, "invalid JSON-RPC request");
+                                let json = jsonrpc::encode_error(&e)?;
                                 writer.write_all(json.as_bytes()).await?;
                                 line_buf.clear();
                                 continue;
                             }
+                            None => match ipc::decode_request(&line_buf) {
+                                Ok(req) => (Reply::Native, req),
+                                Err(e) => {
+                                    warn!(error = %e, line = %line_buf.trim(), "invalid IPC request");
+                                    let error_msg = ServerMessage::Error {
+                                        code: ErrorCode::InvalidRequest,
+                                        message: format!("failed to parse request: {e}"),
+                                    };
+                                    let json = ipc::encode_response(&error_msg)?;
+                                    writer.write_all(json.as_bytes()).await?;
+                                    line_buf.clear();
+                                    continue;
+                                }
+                            },
                         };
 
-                        // Handle Subscribe specially — we set up the broadcast receiver
-                        if matches!(request, ClientRequest::Subscribe) {
-                            if !subscribed {
-                                subscribed = true;
-                                event_rx = Some(event_tx.subscribe());
-                                debug!("IPC client subscribed to events");
-                            }
-                            // Send OK response
-                            let ok = ServerMessage::Ok;
-                            let json = ipc::encode_response(&ok)?;
-                            writer.write_all(json.as_bytes()).await?;
-                            line_buf.clear();
-                            continue;
-                        }
+                        line_buf.clear();
 
-                        // Forward the request to the daemon
-                        let ipc_request = IpcRequest {
-                            request,
-                            response_tx: response_tx.clone(),
+                        // Subscriptions belong to this connection, so they're
+                        // handled here; everything else goes to the daemon
+                        let (response, replay) = match request {
+                            ClientRequest::Subscribe { name, filter, since } => {
+                                subscriptions.jsonrpc = matches!(reply, Reply::Rpc(_));
+                                subscriptions.subscribe(&events, name, filter, since)
+                            }
+                            ClientRequest::Unsubscribe { name } => (subscriptions.unsubscribe(name), Vec::new()),
+                            ClientRequest::ListSubscriptions => (subscriptions.list(), Vec::new()),
+                            request => {
+                                let ipc_request = IpcRequest {
+                                    request,
+                                    response_tx: response_tx.clone(),
+                                };
+                                if request_tx.send(ipc_request).await.is_err() {
+                                    error!("daemon request channel closed");
+                                    return Ok(());
+                                }
+                                pending.push_back(reply);
+                                continue;
+                            }
                         };
-                        if request_tx.send(ipc_request).await.is_err() {
-                            error!("daemon request channel closed");
-                            return Ok(());
+                        if let Some(json) = reply.encode(&response)? {
+                            writer.write_all(json.as_bytes()).await?;
                         }
-
-                        line_buf.clear();
+                        write_events(&mut writer, &events, replay, subscriptions.jsonrpc).await?;
                     }
                     Err(e) => {
                         return Err(e.into());
@@ -219,28 +454,41 @@ async fn handle_ipc_client(
 
             // Send response back to client
             Some(response) = response_rx.recv() => {
-                let json = ipc::encode_response(&response)?;
-                writer.write_all(json.as_bytes()).await?;
+                write_reply(&mut writer, &mut pending, &response).await?;
             }
 
             // Forward broadcast events to subscribed clients
             event = async {
-                match &mut event_rx {
+                match &mut subscriptions.rx {
                     Some(rx) => rx.recv().await,
                     None => {
                         // If not subscribed, this branch should never resolve.
                         // We use pending() to make it sleep forever.
-                        std::future::pending::<Result<ServerMessage, broadcast::error::RecvError>>().await
+                        std::future::pending::<Result<Event, broadcast::error::RecvError>>().await
                     }
                 }
             } => {
                 match event {
-                    Ok(msg) => {
-                        let json = ipc::encode_response(&msg)?;
-                        writer.write_all(json.as_bytes()).await?;
+                    // Already sent as part of a replay
+                    Ok(event) if event.seq <= subscriptions.last_seq => {}
+                    Ok(event) => {
+                        subscriptions.last_seq = event.seq;
+                        if subscriptions.matches(&event.message) {
+                            write_events(&mut writer, &events, vec![event], subscriptions.jsonrpc).await?;
+                        }
                     }
                     Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!(missed = n, "IPC client lagged behind on events");
+                        // Catch up from the replay buffer; the receiver then
+                        // skips what that already covered
+                        let missed = events.since(subscriptions.last_seq);
+                        if missed.complete {
+                            debug!(missed = n, "IPC client lagged behind on events, replaying");
+                        } else {
+                            warn!(missed = n, "IPC client lagged behind on events");
+                        }
+                        subscriptions.last_seq = missed.up_to;
+                        let replay = subscriptions.wanted(missed.events);
+                        write_events(&mut writer, &events, replay, subscriptions.jsonrpc).await?;
                     }
                     Err(broadcast::error::RecvError::Closed) => {
                         debug!("event broadcast channel closed");
@@ -251,3 +499,610 @@ async fn handle_ipc_client(
         }
     }
 }
+
+/// Most subscriptions one connection may hold.
+const MAX_SUBSCRIPTIONS: usize = 32;
+
+/// Longest subscription name accepted, in bytes.
+const MAX_SUBSCRIPTION_NAME: usize = 64;
+
+/// One client's subscriptions and its place in the event stream.
+#[derive(Default)]
+struct Subscriptions {
+    /// Filters by subscription name; `None` is the unnamed one.
+    filters: BTreeMap<Option<String>, EventFilter>,
+    /// Events for this client while it has any subscription.
+    rx: Option<broadcast::Receiver<Event>>,
+    /// Seq of the last event it was sent (or that its filters dropped),
+    /// so that replays and the live stream never repeat an event.
+    last_seq: u64,
+    /// Write events as JSON-RPC notifications (it last subscribed over
+    /// JSON-RPC).
+    jsonrpc: bool,
+}
+
+impl Subscriptions {
+    /// Adds a subscription, or replaces the filter of the one with the
+    /// same name. Returns the response and, when resuming, the missed
+    /// events to write after it.
+    fn subscribe(
+        &mut self,
+        bus: &EventBus,
+        name: Option<String>,
+        filter: EventFilter,
+        since: Option<String>,
+    ) -> (ServerMessage, Vec<Event>) {
+        let invalid = |message: String| {
+            let error = ServerMessage::Error {
+                code: ErrorCode::InvalidRequest,
+                message,
+            };
+            (error, Vec::new())
+        };
+        if let Err(message) = filter.validate() {
+            return invalid(message);
+        }
+        if let Some(name) = &name {
+            if name.is_empty() || name.len() > MAX_SUBSCRIPTION_NAME {
+                return invalid(format!("subscription name must be 1 to {MAX_SUBSCRIPTION_NAME} bytes"));
+            }
+        }
+        if !self.filters.contains_key(&name) && self.filters.len() >= MAX_SUBSCRIPTIONS {
+            return invalid(format!("at most {MAX_SUBSCRIPTIONS} subscriptions per connection"));
+        }
+        debug!(?name, ?filter, ?since, "IPC client subscribed to events");
+        self.filters.insert(name, filter);
+
+        let Some(since) = since else {
+            if self.rx.is_none() {
+                let (rx, seq) = bus.subscribe();
+                self.rx = Some(rx);
+                self.last_seq = seq;
+            }
+            return (ServerMessage::Ok, Vec::new());
+        };
+        let (rx, missed) = bus.resume(&since);
+        self.rx = Some(rx);
+        self.last_seq = missed.up_to;
+        let replay = self.wanted(missed.events);
+        let response = ServerMessage::Resumed {
+            replayed: replay.len() as u32,
+            complete: missed.complete,
+        };
+        (response, replay)
+    }
+
+    /// Cancels a subscription. Once none are left the client stops
+    /// receiving events, and must subscribe again (with `since` to
+    /// resume) to get them back.
+    fn unsubscribe(&mut self, name: Option<String>) -> ServerMessage {
+        if self.filters.remove(&name).is_none() {
+            return ServerMessage::Error {
+                code: ErrorCode::SubscriptionNotFound,
+                message: match name {
+                    Some(name) => format!("no subscription named '{name}'"),
+                    None => "no unnamed subscription".to_string(),
+                },
+            };
+        }
+        debug!(?name, "IPC client unsubscribed");
+        if self.filters.is_empty() {
+            self.rx = None;
+        }
+        ServerMessage::Ok
+    }
+
+    fn list(&self) -> ServerMessage {
+        let subscriptions = self
+            .filters
+            .iter()
+            .map(|(name, filter)| Subscription {
+                name: name.clone(),
+                filter: filter.clone(),
+            })
+            .collect();
+        ServerMessage::Subscriptions { subscriptions }
+    }
+
+    /// Whether any subscription lets `event` through.
+    fn matches(&self, event: &ServerMessage) -> bool {
+        self.filters.values().any(|filter| filter.matches(event))
+    }
+
+    /// The events any subscription lets through.
+    fn wanted(&self, events: Vec<Event>) -> Vec<Event> {
+        events.into_iter().filter(|event| self.matches(&event.message)).collect()
+    }
+}
+
+/// How a request wants its response written.
+enum Reply {
+    Native,
+    /// Over JSON-RPC, under this `id`; a notification (no `id`) gets no
+    /// response.
+    Rpc(Option<Value>),
+}
+
+impl Reply {
+    /// Serializes one frame of the response, `None` if nothing is written.
+    fn encode(&self, response: &ServerMessage) -> Result<Option<String>, IpcError> {
+        match self {
+            Reply::Native => ipc::encode_response(response).map(Some),
+            Reply::Rpc(None) => Ok(None),
+            Reply::Rpc(Some(id)) if response.is_partial() => jsonrpc::encode_progress(id, response).map(Some),
+            Reply::Rpc(Some(id)) => jsonrpc::encode_response(id, response).map(Some),
+        }
+    }
+}
+
+/// Writes a frame from the daemon as the response to the oldest pending
+/// request, which stops pending once its last frame is through.
+async fn write_reply<W>(
+    writer: &mut W,
+    pending: &mut VecDeque<Reply>,
+    response: &ServerMessage,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    W: AsyncWrite + Unpin,
+{
+    let json = match pending.front() {
+        Some(reply) => reply.encode(response)?,
+        None => Some(ipc::encode_response(response)?),
+    };
+    if !response.is_partial() {
+        pending.pop_front();
+    }
+    if let Some(json) = json {
+        writer.write_all(json.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Writes pushed events to a client, each with its `seq`; as JSON-RPC
+/// notifications if `rpc`.
+async fn write_events<W>(
+    writer: &mut W,
+    bus: &EventBus,
+    events: Vec<Event>,
+    rpc: bool,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    W: AsyncWrite + Unpin,
+{
+    for event in events {
+        let seq = bus.seq_string(event.seq);
+        let json = if rpc {
+            jsonrpc::encode_event(&seq, &event.message)?
+        } else {
+            ipc::encode_event(&seq, &event.message)?
+        };
+        writer.write_all(json.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn submit_puts_chunked_history_back_together() {
+        use familycom_core::types::{Direction, Message, MessageId, PeerId, Timestamp};
+
+        let (request_tx, mut request_rx) = mpsc::channel::<IpcRequest>(1);
+        tokio::spawn(async move {
+            let IpcRequest { response_tx, .. } = request_rx.recv().await.unwrap();
+            let messages = (0..4)
+                .map(|i| Message {
+                    id: MessageId::new(format!("m{i}")),
+                    peer_id: PeerId::new("peer-sala"),
+                    direction: Direction::Received,
+                    content: "x".repeat(ipc::MESSAGES_CHUNK_BYTES / 3),
+                    timestamp: Timestamp::from_millis(i),
+                    delivered: true,
+                    fire_and_forget: false,
+                    announcement: false,
+                    reply_to: None,
+                })
+                .collect();
+            let frames = ipc::into_frames(ServerMessage::Messages {
+                messages,
+                next_cursor: Some("older".to_string()),
+                prev_cursor: None,
+            });
+            assert!(frames.len() > 2);
+            for frame in frames {
+                response_tx.send(frame).await.unwrap();
+            }
+        });
+
+        let request = ClientRequest::GetMessages {
+            peer_id: PeerId::new("peer-sala"),
+            limit: 4,
+            before: None,
+            cursor: None,
+        };
+        match IpcRequest::submit(&request_tx, request).await {
+            Some(ServerMessage::Messages { messages, next_cursor, .. }) => {
+                assert_eq!(messages.len(), 4);
+                assert_eq!(next_cursor.as_deref(), Some("older"));
+            }
+            other => panic!("expected Messages, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_clients_over_localhost_tcp() {
+        let transport = TcpTransport::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = transport.local_addr().unwrap();
+        let server = IpcServer::new(transport);
+        let events = EventBus::new();
+        let (request_tx, _request_rx) = mpsc::channel(4);
+        let shutdown = CancellationToken::new();
+        let running = server.accept_loop(request_tx, events, shutdown.clone());
+
+        let client = async {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = ipc::encode_request(&ClientRequest::Subscribe {
+                name: None,
+                filter: EventFilter::default(),
+                since: None,
+            })
+            .unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+
+            let mut line = String::new();
+            BufReader::new(&mut stream).read_line(&mut line).await.unwrap();
+            assert!(matches!(ipc::decode_response(&line).unwrap(), ServerMessage::Ok));
+            shutdown.cancel();
+        };
+
+        tokio::join!(running, client);
+    }
+
+    #[tokio::test]
+    async fn filtered_subscription_skips_other_events() {
+        let transport = TcpTransport::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = transport.local_addr().unwrap();
+        let server = IpcServer::new(transport);
+        let events = EventBus::new();
+        let (request_tx, _request_rx) = mpsc::channel(4);
+        let shutdown = CancellationToken::new();
+        let running = server.accept_loop(request_tx, events.clone(), shutdown.clone());
+
+        let client = async {
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let filter = EventFilter {
+                peers: vec![],
+                events: vec!["PeerOffline".to_string()],
+            };
+            let request = ipc::encode_request(&ClientRequest::Subscribe {
+                name: None,
+                filter,
+                since: None,
+            })
+            .unwrap();
+            stream.get_mut().write_all(request.as_bytes()).await.unwrap();
+
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            assert!(matches!(ipc::decode_response(&line).unwrap(), ServerMessage::Ok));
+
+            events.send(ServerMessage::MessageDelivered {
+                message_id: familycom_core::types::MessageId::new("m1"),
+            });
+            events.send(ServerMessage::PeerOffline {
+                peer_id: familycom_core::types::PeerId::new("p1"),
+            });
+
+            // The first line through is the PeerOffline; MessageDelivered was dropped
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            assert!(matches!(ipc::decode_response(&line).unwrap(), ServerMessage::PeerOffline { .. }));
+            shutdown.cancel();
+        };
+
+        tokio::join!(running, client);
+    }
+
+    #[tokio::test]
+    async fn named_subscriptions_can_be_listed_and_cancelled() {
+        let transport = TcpTransport::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = transport.local_addr().unwrap();
+        let server = IpcServer::new(transport);
+        let events = EventBus::new();
+        let (request_tx, _request_rx) = mpsc::channel(4);
+        let shutdown = CancellationToken::new();
+        let running = server.accept_loop(request_tx, events.clone(), shutdown.clone());
+
+        let only = |event: &str| EventFilter {
+            peers: vec![],
+            events: vec![event.to_string()],
+        };
+        let offline = |peer: &str| ServerMessage::PeerOffline {
+            peer_id: familycom_core::types::PeerId::new(peer),
+        };
+        async fn request(stream: &mut BufReader<tokio::net::TcpStream>, request: ClientRequest) -> ServerMessage {
+            let json = ipc::encode_request(&request).unwrap();
+            stream.get_mut().write_all(json.as_bytes()).await.unwrap();
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            ipc::decode_response(&line).unwrap()
+        }
+
+        let client = async {
+            let mut stream = BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+
+            for (name, event) in [("badge", "PeerOffline"), ("presence", "PeerOffline"), ("chat", "NewMessage")] {
+                let subscribe = ClientRequest::Subscribe {
+                    name: Some(name.to_string()),
+                    filter: only(event),
+                    since: None,
+                };
+                let response = request(&mut stream, subscribe).await;
+                assert!(matches!(response, ServerMessage::Ok));
+            }
+            match request(&mut stream, ClientRequest::ListSubscriptions).await {
+                ServerMessage::Subscriptions { subscriptions } => {
+                    let names: Vec<_> = subscriptions.iter().map(|s| s.name.as_deref().unwrap()).collect();
+                    assert_eq!(names, ["badge", "chat", "presence"]);
+                }
+                other => panic!("unexpected {other:?}"),
+            }
+
+            // Cancelling one leaves the others
+            let unsubscribe = || ClientRequest::Unsubscribe {
+                name: Some("chat".to_string()),
+            };
+            let response = request(&mut stream, unsubscribe()).await;
+            assert!(matches!(response, ServerMessage::Ok));
+            let response = request(&mut stream, unsubscribe()).await;
+            assert!(matches!(
+                response,
+                ServerMessage::Error {
+                    code: ErrorCode::SubscriptionNotFound,
+                    ..
+                }
+            ));
+
+            // Each matched by two subscriptions, pushed once
+            events.send(offline("a"));
+            events.send(offline("b"));
+            let mut peers = Vec::new();
+            for _ in 0..2 {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                match ipc::decode_response(&line).unwrap() {
+                    ServerMessage::PeerOffline { peer_id } => peers.push(peer_id.as_str().to_string()),
+                    other => panic!("unexpected {other:?}"),
+                }
+            }
+            assert_eq!(peers, ["a", "b"]);
+            shutdown.cancel();
+        };
+
+        tokio::join!(running, client);
+    }
+
+    #[tokio::test]
+    async fn resubscribing_replays_missed_events() {
+        let transport = TcpTransport::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = transport.local_addr().unwrap();
+        let server = IpcServer::new(transport);
+        let events = EventBus::new();
+        let (request_tx, _request_rx) = mpsc::channel(4);
+        let shutdown = CancellationToken::new();
+        let running = server.accept_loop(request_tx, events.clone(), shutdown.clone());
+
+        let offline = |peer: &str| ServerMessage::PeerOffline {
+            peer_id: familycom_core::types::PeerId::new(peer),
+        };
+        let subscribe = |since: Option<String>| {
+            ipc::encode_request(&ClientRequest::Subscribe {
+                name: None,
+                filter: EventFilter::default(),
+                since,
+            })
+            .unwrap()
+        };
+
+        let client = async {
+            // First connection: sees one event, then drops
+            let mut stream = BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+            stream.get_mut().write_all(subscribe(None).as_bytes()).await.unwrap();
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            assert!(matches!(ipc::decode_response(&line).unwrap(), ServerMessage::Ok));
+
+            events.send(offline("a"));
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            let (_, seq) = ipc::decode_frame(&line).unwrap();
+            drop(stream);
+
+            // Sent while disconnected
+            events.send(offline("b"));
+            events.send(offline("c"));
+
+            let mut stream = BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+            stream.get_mut().write_all(subscribe(seq).as_bytes()).await.unwrap();
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            assert!(matches!(
+                ipc::decode_response(&line).unwrap(),
+                ServerMessage::Resumed {
+                    replayed: 2,
+                    complete: true
+                }
+            ));
+
+            events.send(offline("d"));
+            let mut peers = Vec::new();
+            for _ in 0..3 {
+                line.clear();
+                stream.read_line(&mut line).await.unwrap();
+                match ipc::decode_response(&line).unwrap() {
+                    ServerMessage::PeerOffline { peer_id } => peers.push(peer_id.as_str().to_string()),
+                    other => panic!("unexpected {other:?}"),
+                }
+            }
+            assert_eq!(peers, ["b", "c", "d"]);
+            shutdown.cancel();
+        };
+
+        tokio::join!(running, client);
+    }
+
+    #[tokio::test]
+    async fn jsonrpc_calls_get_responses_under_their_id() {
+        let transport = TcpTransport::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = transport.local_addr().unwrap();
+        let server = IpcServer::new(transport);
+        let events = EventBus::new();
+        let (request_tx, mut request_rx) = mpsc::channel::<IpcRequest>(4);
+        let shutdown = CancellationToken::new();
+        let running = server.accept_loop(request_tx, events, shutdown.clone());
+
+        // Answers with a partial frame first, like an export does
+        let daemon = async {
+            let request = request_rx.recv().await.unwrap();
+            assert!(matches!(request.request, ClientRequest::GetStatus));
+            let progress = ServerMessage::ExportProgress {
+                peer_id: familycom_core::types::PeerId::new("p1"),
+                exported: 1,
+                total: 2,
+            };
+            request.response_tx.send(progress).await.unwrap();
+            request.response_tx.send(ServerMessage::Ok).await.unwrap();
+        };
+
+        let client = async {
+            let mut stream = BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+            let calls = concat!(
+                r#"{"jsonrpc":"2.0","id":1,"method":"GetStatus"}"#,
+                "\n",
+                r#"{"jsonrpc":"2.0","id":"list","method":"ListSubscriptions"}"#,
+                "\n",
+                r#"{"jsonrpc":"2.0","id":3,"method":"Fly"}"#,
+                "\n",
+            );
+            stream.get_mut().write_all(calls.as_bytes()).await.unwrap();
+
+            // The locally answered calls may overtake the daemon's
+            let mut seen = Vec::new();
+            for _ in 0..4 {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                let value: Value = serde_json::from_str(&line).unwrap();
+                assert_eq!(value["jsonrpc"], "2.0");
+                let entry = match (&value["method"], &value["result"], &value["error"]) {
+                    (Value::String(method), _, _) => format!("{method} {}", value["params"]["id"]),
+                    (_, Value::Object(result), _) => format!("{} {}", value["id"], result["type"]),
+                    (_, _, Value::Object(error)) => format!("{} {}", value["id"], error["code"]),
+                    _ => panic!("unexpected {line}"),
+                };
+                seen.push(entry);
+            }
+            seen.sort();
+            assert_eq!(
+                seen,
+                [
+                    r#""list" "Subscriptions""#,
+                    r#"1 "Ok""#,
+                    "3 -32601",
+                    "progress 1",
+                ]
+            );
+            shutdown.cancel();
+        };
+
+        tokio::join!(running, daemon, client);
+    }
+
+    #[tokio::test]
+    async fn shutdown_response_is_written_before_closing() {
+        let transport = TcpTransport::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = transport.local_addr().unwrap();
+        let server = IpcServer::new(transport);
+        let events = EventBus::new();
+        let (request_tx, mut request_rx) = mpsc::channel::<IpcRequest>(4);
+        let shutdown = CancellationToken::new();
+        let running = server.accept_loop(request_tx, events, shutdown.clone());
+
+        // Answers and cancels right away, like the daemon does for Shutdown
+        let daemon = async {
+            let request = request_rx.recv().await.unwrap();
+            assert!(matches!(request.request, ClientRequest::Shutdown));
+            request
+                .response_tx
+                .send(ServerMessage::ShuttingDown { restart: false })
+                .await
+                .unwrap();
+            shutdown.cancel();
+        };
+
+        let client = async {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = ipc::encode_request(&ClientRequest::Shutdown).unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+
+            let mut reader = BufReader::new(&mut stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            assert!(matches!(
+                ipc::decode_response(&line).unwrap(),
+                ServerMessage::ShuttingDown { restart: false }
+            ));
+            line.clear();
+            assert_eq!(reader.read_line(&mut line).await.unwrap(), 0, "connection should close");
+        };
+
+        tokio::join!(running, daemon, client);
+    }
+
+    #[tokio::test]
+    async fn tcp_transport_refuses_lan_addresses() {
+        assert!(TcpTransport::bind("0.0.0.0:0".parse().unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn accept_loop_survives_repeated_start_stop() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("familycom.sock");
+        let server = IpcServer::bind(&path).await.unwrap();
+        let events = EventBus::new();
+
+        for _ in 0..3 {
+            let (request_tx, _request_rx) = mpsc::channel(4);
+            let shutdown = CancellationToken::new();
+            let running = server.accept_loop(request_tx, events.clone(), shutdown.clone());
+
+            let client = async {
+                // Subscribe is answered by the IPC server itself, no daemon needed
+                let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+                let request = ipc::encode_request(&ClientRequest::Subscribe {
+                    name: None,
+                    filter: EventFilter::default(),
+                    since: None,
+                })
+                .unwrap();
+                stream.write_all(request.as_bytes()).await.unwrap();
+
+                let mut line = String::new();
+                BufReader::new(&mut stream).read_line(&mut line).await.unwrap();
+                assert!(matches!(ipc::decode_response(&line).unwrap(), ServerMessage::Ok));
+
+                // Stop with the client still connected; it must be closed
+                shutdown.cancel();
+                stream
+            };
+
+            let (_, _stream) = tokio::join!(running, client);
+        }
+    }
+}
@@ -7,8 +7,16 @@
 //! familycomd --no-tray          # Start without system tray (headless)
 //! familycomd --name "PC-Sala"   # Start with a specific display name
 //! familycomd --port 9876        # Use a specific TCP port
+//! familycomd --ipc-listen tcp:127.0.0.1:7878
+//!                               # Also accept IPC clients on localhost TCP
 //! familycomd install            # Set up autostart on login
+//! familycomd install --systemd  # ...as a systemd user service (Linux)
 //! familycomd uninstall          # Remove autostart configuration
+//! familycomd setup              # Interactive configuration wizard
+//! familycomd completions zsh    # Print shell completions to stdout
+//! familycomd man                # Print the man page (roff) to stdout
+//! familycomd send --to "PC-Sala" "la cena está lista"
+//!                               # Send one message (for cron jobs and scripts)
 //! ```
 //!
 //! On first run, the daemon generates a unique peer ID and prompts for
@@ -20,38 +28,75 @@
 //! The daemon spawns several concurrent tasks:
 //! 1. mDNS discovery (background thread via mdns-sd)
 //! 2. TCP message server (tokio task)
-//! 3. IPC server on Unix socket (tokio task)
+//! 3. IPC server on Unix socket, or a named pipe on Windows (tokio task)
 //! 4. System tray icon (dedicated thread with platform event loop)
 //! 5. Main event loop in DaemonApp (tokio task)
+//!
+//! The TCP server, IPC server, notification handler, webhook and MQTT
+//! bridge run under `supervisor`, which restarts them with backoff if they
+//! die and reports their health via the `GetStatus` IPC request.
+//!
+//! Discovery is owned by the `network` watcher, which keeps the daemon
+//! silent on networks not marked trusted in the config (if required).
+//!
+//! Ctrl+C, the tray's Quit item and the `Shutdown` / `Restart` IPC
+//! requests cancel a shared `CancellationToken`.
+//! The servers stop accepting, drain in-flight connections with a
+//! deadline, and the main loop exits once they have let go of its channels.
+//! After `Restart`, the daemon then starts itself again with the same
+//! arguments.
+//!
+//! On Unix, SIGHUP re-reads config.toml like the `ReloadConfig` request:
+//! the display name, notification settings and interface restriction take
+//! effect without a restart (discovery re-registers if they changed).
+//!
+//! Under systemd (`install --systemd`), the daemon reports `READY=1` once
+//! the TCP and IPC servers are listening and mDNS discovery is up (see
+//! `systemd`).
 
 mod app;
 mod autostart;
 mod client;
 mod discovery;
+mod email;
+mod events;
+mod http_api;
+mod idle;
 mod ipc_server;
+mod mqtt;
+mod network;
 mod notifications;
+mod ratelimit;
+mod send;
 mod server;
+mod setup;
+mod supervisor;
+mod systemd;
 mod tray;
+mod webhook;
+mod websocket;
 
 use anyhow::{Context, Result};
-use app::DaemonApp;
-use clap::{Parser, Subcommand};
-use discovery::DiscoveryService;
+use app::{DaemonApp, ExitAction};
+use clap::{CommandFactory, Parser, Subcommand};
+use discovery::{DiscoveryService, Presence};
+use events::EventBus;
 use familycom_core::config::AppConfig;
 use familycom_core::db::Database;
-use ipc_server::IpcServer;
-use notifications::NotificationManager;
+use familycom_core::ipc::{ClientRequest, IpcEndpoint, ServerMessage};
+use ipc_server::{IpcRequest, IpcServer, IpcTransport, TcpTransport};
 use server::MessageServer;
 use std::io::{self, Write};
 use std::path::PathBuf;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 /// FamilyCom daemon — LAN messaging background service.
 #[derive(Parser, Debug)]
 #[command(name = "familycomd", about = "FamilyCom LAN messenger daemon")]
 struct Cli {
-    /// Subcommand to run (install, uninstall). If omitted, starts the daemon.
+    /// Subcommand to run (install, uninstall, setup, completions, man, send). If omitted, starts the daemon.
     #[command(subcommand)]
     command: Option<Command>,
 
@@ -72,10 +117,17 @@ struct Cli {
     #[arg(long)]
     db: Option<PathBuf>,
 
-    /// Path to the Unix socket for IPC.
+    /// Path to the Unix socket for IPC (on Windows, the named pipe,
+    /// e.g. `\\.\pipe\familycom`).
     #[arg(long)]
     socket: Option<PathBuf>,
 
+    /// Additional IPC endpoint to listen on, besides the Unix socket:
+    /// `tcp:127.0.0.1:<port>` or `pipe:<name>` (Windows). Repeatable;
+    /// adds to `ipc_listen` in config.toml.
+    #[arg(long, value_name = "ENDPOINT")]
+    ipc_listen: Vec<IpcEndpoint>,
+
     /// Disable the system tray icon (run headless in terminal).
     #[arg(long)]
     no_tray: bool,
@@ -92,6 +144,10 @@ enum Command {
         /// Show what would be done without making changes.
         #[arg(long)]
         dry_run: bool,
+        /// Linux: install a systemd user service (Type=notify) instead of
+        /// an XDG autostart entry.
+        #[arg(long)]
+        systemd: bool,
     },
     /// Remove the autostart configuration.
     ///
@@ -101,6 +157,33 @@ enum Command {
         #[arg(long)]
         dry_run: bool,
     },
+    /// Interactive wizard to configure name, port, interface, autostart
+    /// and notifications. Writes a validated config.toml.
+    Setup,
+    /// Print shell completions for familycomd to stdout.
+    ///
+    /// Example: `familycomd completions bash > ~/.local/share/bash-completion/completions/familycomd`
+    Completions {
+        /// The shell to generate completions for.
+        shell: clap_complete::Shell,
+    },
+    /// Print the familycomd man page (roff format) to stdout.
+    ///
+    /// Example: `familycomd man > ~/.local/share/man/man1/familycomd.1`
+    Man,
+    /// Send one message to a peer, then exit.
+    ///
+    /// Goes through the running daemon if there is one; otherwise sends
+    /// directly to the peer's last known addresses.
+    ///
+    /// Example: `familycomd send --to "PC-Sala" "la cena está lista"`
+    Send {
+        /// The peer's display name (case-insensitive) or peer ID.
+        #[arg(long)]
+        to: String,
+        /// The message text.
+        message: String,
+    },
 }
 
 #[tokio::main]
@@ -108,17 +191,55 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     // Handle subcommands before initializing the full daemon.
-    // Install/uninstall don't need logging, async runtime, etc.
+    // Install/uninstall/setup/completions/man/send don't need logging, the servers, etc.
     match &cli.command {
-        Some(Command::Install { dry_run }) => {
-            return autostart::install(*dry_run);
+        Some(Command::Install { dry_run, systemd }) => {
+            return autostart::install(*dry_run, *systemd);
         }
         Some(Command::Uninstall { dry_run }) => {
             return autostart::uninstall(*dry_run);
         }
+        Some(Command::Setup) => {
+            let config_path = match &cli.config {
+                Some(path) => path.clone(),
+                None => AppConfig::config_file_path().context("could not determine config directory")?,
+            };
+            return setup::run(&config_path, get_hostname());
+        }
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Cli::command(), "familycomd", &mut io::stdout());
+            return Ok(());
+        }
+        Some(Command::Man) => {
+            clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Send { to, message }) => {
+            let config_path = match &cli.config {
+                Some(path) => path.clone(),
+                None => AppConfig::config_file_path().context("could not determine config directory")?,
+            };
+            let db_path = match &cli.db {
+                Some(path) => path.clone(),
+                None => AppConfig::default_db_path().context("could not determine data directory")?,
+            };
+            return send::run(&config_path, &db_path, &main_ipc_endpoint(&cli), to, message).await;
+        }
         None => {} // No subcommand — start the daemon
     }
 
+    // A second daemon would bind another TCP port and take the socket path
+    // away from the running one, leaving its clients talking to neither
+    let ipc_endpoint = main_ipc_endpoint(&cli);
+    if send::DaemonConnection::connect(&ipc_endpoint).await.is_some() {
+        anyhow::bail!(
+            "familycomd is already running ({ipc_endpoint}); stop it first, or use --socket for a separate instance"
+        );
+    }
+
+    #[cfg(windows)]
+    release_own_console();
+
     // Initialize logging.
     // The FAMILYCOM_LOG env var controls the log level (default: info).
     // Logs go to both stderr and a log file in the data directory.
@@ -174,61 +295,191 @@ async fn main() -> Result<()> {
         std::fs::create_dir_all(parent)?;
     }
 
-    let db = Database::open(&db_path).context("failed to open database")?;
+    // A corrupt file is moved aside and rebuilt rather than stopping the daemon
+    let (db, db_recovery) = Database::open_or_recover(&db_path).context("failed to open database")?;
+    if let Some(report) = &db_recovery {
+        warn!(
+            corrupt_path = %report.corrupt_path.display(),
+            reason = %report.reason,
+            salvaged_rows = report.salvaged_rows,
+            "database was corrupt and has been rebuilt"
+        );
+    }
     info!(path = %db_path.display(), "database opened");
 
     // -----------------------------------------------------------------------
     // Start TCP message server
     // -----------------------------------------------------------------------
+    // Every interface, so peers reach us on any of the `network_interface`s
     let bind_addr = format!("0.0.0.0:{}", config.tcp_port);
+    let trust_gate = network::TrustGate::open();
     let tcp_server = MessageServer::bind(&bind_addr)
         .await
-        .context("failed to start TCP server")?;
+        .context("failed to start TCP server")?
+        .with_trust_gate(trust_gate.clone());
 
     let tcp_port = tcp_server.port();
     info!(port = tcp_port, "TCP message server started");
 
-    // -----------------------------------------------------------------------
-    // Start mDNS discovery
-    // -----------------------------------------------------------------------
-    let peer_id = familycom_core::types::PeerId::new(&config.peer_id);
-    let (discovery, discovery_rx) =
-        DiscoveryService::new(peer_id, &config.display_name, tcp_port, config.network_interface.as_deref())
-            .context("failed to start mDNS discovery")?;
 
     // -----------------------------------------------------------------------
     // Start IPC server
     // -----------------------------------------------------------------------
-    let socket_path = match &cli.socket {
-        Some(path) => path.clone(),
-        None => AppConfig::default_socket_path(),
+    // A Unix socket, or a named pipe on Windows
+    #[cfg(unix)]
+    let ipc_server = {
+        let IpcEndpoint::Unix(socket_path) = ipc_endpoint else {
+            unreachable!("the main endpoint is a Unix socket on Unix");
+        };
+        let server = IpcServer::bind(&socket_path)
+            .await
+            .context("failed to start IPC server")?;
+        info!(path = %socket_path.display(), "IPC server started");
+        server
+    };
+    #[cfg(windows)]
+    let ipc_server = {
+        let IpcEndpoint::NamedPipe(pipe_name) = ipc_endpoint else {
+            unreachable!("the main endpoint is a named pipe on Windows");
+        };
+        let transport = ipc_server::NamedPipeTransport::bind(&pipe_name)
+            .context("failed to start IPC server")?;
+        info!(pipe = %pipe_name, "IPC server started");
+        IpcServer::new(transport)
     };
 
-    let ipc_server = IpcServer::bind(&socket_path)
-        .await
-        .context("failed to start IPC server")?;
-
-    info!(path = %socket_path.display(), "IPC server started");
+    let mut extra_ipc_endpoints = config.ipc_listen.clone();
+    extra_ipc_endpoints.extend(cli.ipc_listen.iter().cloned());
+    let http_listen = config.http_listen;
 
     // -----------------------------------------------------------------------
     // Create the daemon app and wire everything together
     // -----------------------------------------------------------------------
     let mut daemon_app = DaemonApp::new(db, config);
-    let event_tx = daemon_app.event_sender();
+    daemon_app.set_config_path(config_path);
+    if let Some(report) = db_recovery {
+        daemon_app.set_db_recovery(report);
+    }
+    let events = daemon_app.event_bus();
 
     // Channels for inter-task communication
+    let (discovery_tx, discovery_rx) = mpsc::channel(64);
     let (message_tx, message_rx) = mpsc::channel(256);
     let (ipc_request_tx, ipc_request_rx) = mpsc::channel(64);
-    let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>(1);
+    let (discovery_ready_tx, discovery_ready_rx) = tokio::sync::oneshot::channel();
+    // Cancelled by Ctrl+C or the tray's Quit item. Every subsystem gets a
+    // clone and winds itself down when it fires.
+    let shutdown = CancellationToken::new();
 
-    // Spawn the TCP server accept loop
-    tokio::spawn(async move {
-        tcp_server.accept_loop(message_tx).await;
+    // Run the long-lived subsystems under the supervisor so a panic or
+    // unexpected exit is logged, restarted with backoff, and visible via
+    // `GetStatus` (and a `SubsystemRestarted` event) instead of leaving a
+    // zombie daemon behind.
+    let health = daemon_app.health_registry();
+
+    // -----------------------------------------------------------------------
+    // Start mDNS discovery (only while on a trusted network, if required)
+    // -----------------------------------------------------------------------
+    let discovery_ready_tx = std::sync::Mutex::new(Some(discovery_ready_tx));
+    let network_task = supervisor::supervise(&health, "discovery", &shutdown, {
+        let config = daemon_app.config_watch();
+        let presence = network::PresenceWatch {
+            do_not_disturb: daemon_app.do_not_disturb_watch(),
+            away: daemon_app.away_watch(),
+        };
+        let shutdown = shutdown.clone();
+        move || {
+            network::run_watcher(
+                config.clone(),
+                presence.clone(),
+                trust_gate.clone(),
+                move |advertised: &AppConfig, presence: Presence| {
+                    DiscoveryService::new(
+                        familycom_core::types::PeerId::new(&advertised.peer_id),
+                        &advertised.display_name,
+                        tcp_port,
+                        advertised.network_interface.as_ref(),
+                        advertised.avatar.as_ref(),
+                        advertised.accent_color,
+                        presence,
+                    )
+                },
+                discovery_tx.clone(),
+                // Only the first run reports readiness
+                discovery_ready_tx.lock().ok().and_then(|mut ready| ready.take()),
+                shutdown.clone(),
+            )
+        }
+    });
+
+    let tcp_server = std::sync::Arc::new(tcp_server);
+    let tcp_task = supervisor::supervise(&health, "tcp_server", &shutdown, {
+        let shutdown = shutdown.clone();
+        move || {
+            let server = tcp_server.clone();
+            let tx = message_tx.clone();
+            let shutdown = shutdown.clone();
+            async move { server.accept_loop(tx, shutdown).await }
+        }
     });
 
-    // Spawn the IPC server accept loop
+    // Extra IPC transports (localhost TCP, named pipes) share the same
+    // request channel and event stream as the Unix socket
+    let mut extra_ipc_tasks = Vec::new();
+    for endpoint in extra_ipc_endpoints {
+        let task = spawn_extra_ipc_listener(
+            &endpoint,
+            &health,
+            &shutdown,
+            ipc_request_tx.clone(),
+            events.clone(),
+        )
+        .await
+        .with_context(|| format!("failed to start IPC listener on {endpoint}"))?;
+        extra_ipc_tasks.push(task);
+    }
+
+    // The HTTP API turns its requests into IPC ones, so it shares the
+    // request channel too; its WebSocket sessions get the events
+    let http_task = match http_listen {
+        Some(addr) => {
+            let server = std::sync::Arc::new(
+                http_api::HttpServer::bind(addr)
+                    .await
+                    .with_context(|| format!("failed to start HTTP API on {addr}"))?,
+            );
+            Some(supervisor::supervise(&health, "http_api", &shutdown, {
+                let request_tx = ipc_request_tx.clone();
+                let events = events.clone();
+                let shutdown = shutdown.clone();
+                move || {
+                    let server = server.clone();
+                    let request_tx = request_tx.clone();
+                    let events = events.clone();
+                    let shutdown = shutdown.clone();
+                    async move { server.accept_loop(request_tx, events, shutdown).await }
+                }
+            }))
+        }
+        None => None,
+    };
+
+    // SIGHUP goes through the same path as a client's `ReloadConfig`
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(ipc_request_tx.clone(), shutdown.clone()));
+    // The tray's quick replies and notification toggle go through the main
+    // loop like a client's requests
+    let tray_request_tx = ipc_request_tx.clone();
+    // ...and so do messages sent from the MQTT command topic
+    let mqtt_request_tx = ipc_request_tx.clone();
+
+    let ipc_task = supervise_ipc(ipc_server, "ipc_server", &health, &shutdown, ipc_request_tx, events.clone());
+
+    // Both servers are bound by now; readiness waits for discovery too
     tokio::spawn(async move {
-        ipc_server.accept_loop(ipc_request_tx, event_tx).await;
+        if discovery_ready_rx.await.is_ok() {
+            systemd::notify_ready();
+        }
     });
 
     // -----------------------------------------------------------------------
@@ -236,8 +487,11 @@ async fn main() -> Result<()> {
     // -----------------------------------------------------------------------
     let tray_event_rx = if !cli.no_tray {
         let (tray_event_tx, tray_event_rx) = std::sync::mpsc::channel();
+        let unread = daemon_app.unread_watch();
+        let peers = daemon_app.tray_peers_watch();
+        let config = daemon_app.config_watch();
         std::thread::spawn(move || {
-            tray::run_tray(tray_event_tx, 0);
+            tray::run_tray(tray_event_tx, unread, peers, config);
         });
         Some(tray_event_rx)
     } else {
@@ -246,64 +500,63 @@ async fn main() -> Result<()> {
     };
 
     // -----------------------------------------------------------------------
-    // Set up notification manager
+    // Set up desktop notifications
     // -----------------------------------------------------------------------
-    let mut notification_mgr = NotificationManager::new();
-
-    // Subscribe to daemon events for notifications
-    let mut notification_rx = daemon_app.event_sender().subscribe();
+    let notification_events = events.clone();
+    let notification_config = daemon_app.config_watch();
+    let notification_prefs = daemon_app.notification_prefs_watch();
+    let notification_names = daemon_app.peer_names_watch();
+    let notification_dnd = daemon_app.do_not_disturb_watch();
+    let notification_viewers = daemon_app.viewers_watch();
+    let notification_task = supervisor::supervise(&health, "notifications", &shutdown, {
+        let shutdown = shutdown.clone();
+        move || {
+            notifications::run_handler(
+                notification_events.clone(),
+                notification_config.clone(),
+                notification_prefs.clone(),
+                notification_names.clone(),
+                notification_dnd.clone(),
+                notification_viewers.clone(),
+                shutdown.clone(),
+            )
+        }
+    });
 
-    // Spawn notification handler task.
-    // We track peer display names from PeerOnline events so that
-    // notifications show the sender's actual name (e.g., "PC-Sala")
-    // instead of a generic "Peer" label.
-    tokio::spawn(async move {
-        let mut peer_names: std::collections::HashMap<
-            familycom_core::types::PeerId,
-            String,
-        > = std::collections::HashMap::new();
-
-        loop {
-            match notification_rx.recv().await {
-                Ok(familycom_core::ipc::ServerMessage::PeerOnline { ref peer }) => {
-                    // Remember display names so we can use them in notifications
-                    peer_names.insert(peer.id.clone(), peer.display_name.clone());
-                }
-                Ok(familycom_core::ipc::ServerMessage::NewMessage { ref message }) => {
-                    if message.direction == familycom_core::types::Direction::Received {
-                        let sender_name = peer_names
-                            .get(&message.peer_id)
-                            .map(|s| s.as_str())
-                            .unwrap_or("Peer");
-
-                        let preview = if message.content.len() > 100 {
-                            format!("{}...", &message.content[..message.content.floor_char_boundary(97)])
-                        } else {
-                            message.content.clone()
-                        };
-                        notification_mgr.notify_new_message(sender_name, &preview);
-                    }
-                }
-                Ok(_) => {} // Other events don't need notifications
-                Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
-                    warn!(missed = n, "notification handler lagged");
-                }
-                Err(tokio::sync::broadcast::error::RecvError::Closed) => {
-                    break;
-                }
-            }
+    // Messages and presence are bridged to MQTT while `[mqtt]` has a broker
+    let mqtt_task = supervisor::supervise(&health, "mqtt", &shutdown, {
+        let events = events.clone();
+        let config = daemon_app.config_watch();
+        let peer_names = daemon_app.peer_names_watch();
+        let shutdown = shutdown.clone();
+        move || {
+            mqtt::run_bridge(
+                events.clone(),
+                config.clone(),
+                peer_names.clone(),
+                mqtt_request_tx.clone(),
+                shutdown.clone(),
+            )
         }
     });
 
+    // Received messages are also posted to `webhook_url`, when set
+    let webhook_config = daemon_app.config_watch();
+    let webhook_names = daemon_app.peer_names_watch();
+    let webhook_task = supervisor::supervise(&health, "webhook", &shutdown, {
+        let shutdown = shutdown.clone();
+        move || webhook::run_handler(events.clone(), webhook_config.clone(), webhook_names.clone(), shutdown.clone())
+    });
+
     // -----------------------------------------------------------------------
     // Set up signal handler for graceful shutdown
     // -----------------------------------------------------------------------
-    let shutdown_tx_clone = shutdown_tx.clone();
+    let shutdown_signal = shutdown.clone();
     tokio::spawn(async move {
         match tokio::signal::ctrl_c().await {
             Ok(()) => {
                 info!("received Ctrl+C, initiating shutdown");
-                let _ = shutdown_tx_clone.send(()).await;
+                shutdown_signal.cancel();
             }
             Err(e) => {
                 error!(error = %e, "failed to listen for Ctrl+C");
@@ -326,32 +579,83 @@ async fn main() -> Result<()> {
             }
         });
 
-        // Async handler: processes tray events in the tokio runtime
-        let shutdown_tx_tray = shutdown_tx.clone();
+        // Async handler: processes tray events in the tokio runtime. It
+        // stops at shutdown so the main loop isn't kept waiting on
+        // `tray_request_tx`.
+        let shutdown_tray = shutdown.clone();
         tokio::spawn(async move {
-            while let Some(event) = tray_async_rx.recv().await {
+            loop {
+                let event = tokio::select! {
+                    _ = shutdown_tray.cancelled() => break,
+                    event = tray_async_rx.recv() => match event {
+                        Some(event) => event,
+                        None => break,
+                    },
+                };
                 match event {
                     tray::TrayEvent::OpenChat => {
-                        tray::open_chat_in_terminal();
+                        tray::open_chat_in_terminal(None);
+                    }
+                    tray::TrayEvent::OpenChatWith(peer_id) => {
+                        tray::open_chat_in_terminal(Some(&peer_id));
+                    }
+                    tray::TrayEvent::QuickReply(peer_id) => {
+                        send_quick_reply(&tray_request_tx, peer_id).await;
+                    }
+                    tray::TrayEvent::Announce(content) => {
+                        let request = ClientRequest::Announce { content };
+                        match IpcRequest::submit(&tray_request_tx, request).await {
+                            Some(ServerMessage::Announced { message_ids }) => {
+                                info!(peers = message_ids.len(), "announcement sent from tray");
+                            }
+                            Some(ServerMessage::Error { message, .. }) => {
+                                warn!(error = %message, "failed to send announcement from tray");
+                            }
+                            _ => {}
+                        }
+                    }
+                    tray::TrayEvent::PauseNotifications(paused) => {
+                        let request = ClientRequest::SetNotificationsEnabled { enabled: !paused };
+                        let response = IpcRequest::submit(&tray_request_tx, request).await;
+                        if let Some(ServerMessage::Error { message, .. }) = response {
+                            warn!(error = %message, "failed to change notifications from tray");
+                        }
                     }
                     tray::TrayEvent::Quit => {
                         info!("quit requested from tray");
-                        let _ = shutdown_tx_tray.send(()).await;
+                        shutdown_tray.cancel();
                         break;
                     }
                 }
             }
         });
+    } else {
+        // No quick replies without a tray, and the main loop only finishes
+        // once every request sender is gone
+        drop(tray_request_tx);
     }
 
     // Run the main event loop (blocks until shutdown)
     info!("daemon is running. Press Ctrl+C to stop.");
-    daemon_app
-        .run(discovery_rx, message_rx, ipc_request_rx, shutdown_rx)
+    let exit_action = daemon_app
+        .run(discovery_rx, message_rx, ipc_request_rx, shutdown.clone())
         .await;
 
+    // The main loop only returns once both servers have dropped their
+    // channels, so these complete immediately; awaiting them makes sure the
+    // listeners are no longer in use before we tear down the rest.
+    let _ = tokio::join!(tcp_task, ipc_task, notification_task, webhook_task, mqtt_task);
+    for task in extra_ipc_tasks.into_iter().chain(http_task) {
+        let _ = task.await;
+    }
+
     // Clean shutdown
     info!("shutting down...");
+    if exit_action == ExitAction::Restart {
+        systemd::notify_reloading();
+    } else {
+        systemd::notify_stopping();
+    }
 
     // Tell the tray's GTK event loop to quit so the blocking bridge
     // thread can exit and the tokio runtime shuts down cleanly.
@@ -359,15 +663,173 @@ async fn main() -> Result<()> {
         tray::request_quit();
     }
 
-    discovery.shutdown();
+    // Unregisters from mDNS (the watcher owns the discovery service)
+    let _ = network_task.await;
     info!("daemon stopped");
 
+    // The sockets are released by now, so the new instance can bind them
+    if exit_action == ExitAction::Restart {
+        restart();
+    }
+
     // Force exit to avoid hanging on lingering background threads from
     // external libraries (mdns-sd browse loop, GTK) that don't shut down
     // promptly. All graceful cleanup has already completed above.
     std::process::exit(0);
 }
 
+/// Starts the daemon again with the same arguments, for `Restart`.
+///
+/// On Unix the process image is replaced (same PID, so service managers
+/// don't notice); elsewhere a new process is spawned and this one exits.
+/// Failures are logged and the daemon simply stays stopped.
+fn restart() {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            error!(error = %e, "cannot restart: executable path unknown");
+            return;
+        }
+    };
+    let mut command = std::process::Command::new(exe);
+    command.args(std::env::args_os().skip(1));
+    info!("restarting");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Only returns on failure
+        let e = command.exec();
+        error!(error = %e, "failed to restart");
+    }
+    #[cfg(not(unix))]
+    if let Err(e) = command.spawn() {
+        error!(error = %e, "failed to restart");
+    }
+}
+
+/// Re-reads config.toml on every SIGHUP by queueing a `ReloadConfig`
+/// request for the main loop, as `familycom` would over IPC.
+///
+/// Stops at shutdown: the main loop only exits once every holder of
+/// `request_tx` has let go of it.
+#[cfg(unix)]
+async fn reload_on_sighup(request_tx: mpsc::Sender<IpcRequest>, shutdown: CancellationToken) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!(error = %e, "failed to listen for SIGHUP");
+            return;
+        }
+    };
+    loop {
+        let hangup = tokio::select! {
+            _ = shutdown.cancelled() => None,
+            hangup = hangups.recv() => hangup,
+        };
+        if hangup.is_none() {
+            return;
+        }
+        info!("received SIGHUP, reloading config");
+        match IpcRequest::submit(&request_tx, ClientRequest::ReloadConfig).await {
+            Some(ServerMessage::ConfigReloaded { applied, restart_required }) => {
+                info!(?applied, ?restart_required, "config reloaded");
+            }
+            Some(ServerMessage::Error { message, .. }) => {
+                warn!(error = %message, "config reload failed, keeping the current settings");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Sends the tray's canned reply to `peer_id`.
+async fn send_quick_reply(request_tx: &mpsc::Sender<IpcRequest>, peer_id: familycom_core::types::PeerId) {
+    let request = ClientRequest::SendMessage {
+        peer_id: peer_id.clone(),
+        content: tray::QUICK_REPLY.to_string(),
+        reply_to: None,
+    };
+    match IpcRequest::submit(request_tx, request).await {
+        Some(ServerMessage::MessageSent { .. }) => info!(peer_id = %peer_id, "quick reply sent from tray"),
+        Some(ServerMessage::Error { message, .. }) => {
+            warn!(peer_id = %peer_id, error = %message, "failed to send quick reply from tray");
+        }
+        _ => {}
+    }
+}
+
+/// Runs an IPC server's accept loop under the supervisor.
+fn supervise_ipc<T: IpcTransport>(
+    server: IpcServer<T>,
+    name: &'static str,
+    health: &supervisor::HealthRegistry,
+    shutdown: &CancellationToken,
+    request_tx: mpsc::Sender<IpcRequest>,
+    events: EventBus,
+) -> tokio::task::JoinHandle<()> {
+    let server = std::sync::Arc::new(server);
+    supervisor::supervise(health, name, shutdown, {
+        let shutdown = shutdown.clone();
+        move || {
+            let server = server.clone();
+            let req_tx = request_tx.clone();
+            let events = events.clone();
+            let shutdown = shutdown.clone();
+            async move { server.accept_loop(req_tx, events, shutdown).await }
+        }
+    })
+}
+
+/// The daemon's main IPC endpoint: `--socket`, or the default Unix
+/// socket (named pipe on Windows).
+fn main_ipc_endpoint(cli: &Cli) -> IpcEndpoint {
+    #[cfg(windows)]
+    return IpcEndpoint::NamedPipe(match &cli.socket {
+        Some(pipe) => pipe.display().to_string(),
+        None => AppConfig::default_pipe_name(),
+    });
+    #[cfg(not(windows))]
+    IpcEndpoint::Unix(match &cli.socket {
+        Some(path) => path.clone(),
+        None => AppConfig::default_socket_path(),
+    })
+}
+
+/// Binds an additional IPC endpoint (`--ipc-listen` / `ipc_listen`) and
+/// serves it like the main Unix socket.
+async fn spawn_extra_ipc_listener(
+    endpoint: &IpcEndpoint,
+    health: &supervisor::HealthRegistry,
+    shutdown: &CancellationToken,
+    request_tx: mpsc::Sender<IpcRequest>,
+    events: EventBus,
+) -> Result<tokio::task::JoinHandle<()>> {
+    let task = match endpoint {
+        IpcEndpoint::Tcp(addr) => {
+            let server = IpcServer::new(TcpTransport::bind(*addr).await?);
+            info!(%addr, "IPC server listening on TCP");
+            supervise_ipc(server, "ipc_tcp", health, shutdown, request_tx, events)
+        }
+        #[cfg(unix)]
+        IpcEndpoint::Unix(path) => {
+            let server = IpcServer::bind(path).await?;
+            supervise_ipc(server, "ipc_unix", health, shutdown, request_tx, events)
+        }
+        #[cfg(windows)]
+        IpcEndpoint::NamedPipe(name) => {
+            let server = IpcServer::new(ipc_server::NamedPipeTransport::bind(name)?);
+            info!(pipe = %name, "IPC server listening on named pipe");
+            supervise_ipc(server, "ipc_pipe", health, shutdown, request_tx, events)
+        }
+        #[allow(unreachable_patterns)]
+        other => anyhow::bail!("{} IPC endpoints are not supported on this platform", other.kind()),
+    };
+    Ok(task)
+}
+
 /// Prompts the user for a display name on first run.
 ///
 /// If stdin is not a terminal (e.g., launched by autostart), falls back
@@ -386,8 +848,33 @@ fn get_display_name() -> Result<String> {
         }
         Ok(name)
     } else {
-        // Non-interactive — use hostname
-        Ok(get_hostname())
+        // Non-interactive — use hostname, but tell the user how to change it
+        // since nobody is watching a prompt under autostart.
+        let name = get_hostname();
+        warn!(
+            display_name = %name,
+            "no terminal for first-run prompt, using hostname; run `familycomd setup` to change it"
+        );
+        Ok(name)
+    }
+}
+
+/// Closes the console window Windows opens for the daemon when it's
+/// started on login (from the Run key), which the family would otherwise
+/// close and take the daemon down with it.
+///
+/// Only when no other process shares the console, i.e. the daemon wasn't
+/// started from a terminal. Logs still go to the log file.
+#[cfg(windows)]
+fn release_own_console() {
+    use windows_sys::Win32::System::Console::{FreeConsole, GetConsoleProcessList};
+
+    let mut processes = [0u32; 2];
+    // SAFETY: the buffer is valid for the length passed
+    let attached = unsafe { GetConsoleProcessList(processes.as_mut_ptr(), processes.len() as u32) };
+    if attached == 1 {
+        // SAFETY: no handles to the console are held across this call
+        unsafe { FreeConsole() };
     }
 }
 
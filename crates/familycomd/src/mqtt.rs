@@ -0,0 +1,594 @@
+//! MQTT bridge for home automation (`[mqtt]` in config.toml).
+//!
+//! While a `broker` is configured, the daemon keeps a connection to it and:
+//!
+//! - publishes each received message to `<prefix>/messages` as JSON:
+//!   `{"sender": "🐱 PC-Sala", "sender_id": "...", "message_id": "...",
+//!   "content": "...", "timestamp": "2026-10-16T19:02:11.250Z"}`
+//! - publishes each peer's presence to `<prefix>/presence/<peer_id>`,
+//!   `online` or `offline`, retained so new subscribers see it at once
+//! - publishes its own state to `<prefix>/status` (retained; the broker
+//!   sets it to `offline` if the daemon vanishes)
+//! - sends what's published to `<prefix>/send`, written
+//!   `{"to": "Abuela", "content": "la cena está lista"}` (`to` is a display
+//!   name or peer ID), as if typed in the TUI
+//!
+//! This is a minimal MQTT 3.1.1 client: QoS 0 only and plain TCP, which is
+//! what a broker on the home LAN (Mosquitto, Home Assistant's add-on)
+//! needs. A dropped connection is retried with backoff, and a change to
+//! `[mqtt]` reconnects with the new settings.
+//!
+//! It is written here rather than taken from a client crate such as
+//! `rumqttc` on purpose. The bridge sends five kinds of packet and reads
+//! two (CONNACK, and PUBLISH at QoS 0), which takes little code. A client
+//! crate brings its own event loop that owns reconnecting and keep-alive,
+//! which here belong to `run_bridge` (backoff, reconnecting on a config
+//! reload, the supervisor), plus a TLS stack the bridge has no use for,
+//! and every install would carry all that for an optional feature. What
+//! comes from the broker is treated as untrusted: `read_packet` bounds
+//! and checks lengths, and anything malformed ends the connection
+//! (`check_connack`) or is dropped (`parse_publish`).
+
+use crate::events::EventBus;
+use crate::ipc_server::IpcRequest;
+use crate::send;
+use familycom_core::config::{AppConfig, MqttConfig};
+use familycom_core::ipc::{ClientRequest, ServerMessage};
+use familycom_core::types::{Direction, Message, MessageId, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// The port used when `broker` doesn't name one.
+const DEFAULT_PORT: u16 = 1883;
+
+/// How long connecting, including the broker's CONNACK, may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keep-alive agreed with the broker. We ping at half of it, and give up
+/// on a broker that has been silent for one and a half.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+
+/// Wait before the first reconnection attempt, doubled up to `RETRY_MAX`.
+const RETRY_MIN: Duration = Duration::from_secs(5);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// Largest packet accepted from the broker.
+const MAX_PACKET: usize = 256 * 1024;
+
+/// Keeps the bridge connected while `[mqtt]` has a broker, until
+/// `shutdown`.
+///
+/// Outgoing messages go through the main loop via `request_tx`, like a
+/// client's `SendMessage`. Runs under the supervisor, which restarts it if
+/// it ever dies.
+pub async fn run_bridge(
+    events: EventBus,
+    mut config: watch::Receiver<AppConfig>,
+    peer_names: watch::Receiver<HashMap<PeerId, String>>,
+    request_tx: mpsc::Sender<IpcRequest>,
+    shutdown: CancellationToken,
+) {
+    let mut retry = RETRY_MIN;
+    loop {
+        let settings = config.borrow_and_update().clone();
+        let result = match &settings.mqtt.broker {
+            Some(broker) => {
+                let bridge = Bridge {
+                    events: &events,
+                    config: &mut config,
+                    peer_names: &peer_names,
+                    request_tx: &request_tx,
+                    shutdown: &shutdown,
+                };
+                bridge.session(broker, &settings, &mut retry).await
+            }
+            // Off: wait for a broker to be configured
+            None => tokio::select! {
+                _ = shutdown.cancelled() => return,
+                changed = config.changed() => match changed {
+                    Ok(()) => continue,
+                    Err(_) => return,
+                },
+            },
+        };
+        if shutdown.is_cancelled() {
+            return;
+        }
+        // `Ok` means `[mqtt]` changed: reconnect right away
+        let Err(e) = result else {
+            retry = RETRY_MIN;
+            continue;
+        };
+        warn!(error = %e, retry_in = ?retry, "MQTT connection lost");
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = tokio::time::sleep(retry) => {}
+        }
+        retry = (retry * 2).min(RETRY_MAX);
+    }
+}
+
+/// What one connection to the broker works with.
+struct Bridge<'a> {
+    events: &'a EventBus,
+    config: &'a mut watch::Receiver<AppConfig>,
+    peer_names: &'a watch::Receiver<HashMap<PeerId, String>>,
+    request_tx: &'a mpsc::Sender<IpcRequest>,
+    shutdown: &'a CancellationToken,
+}
+
+impl Bridge<'_> {
+    /// Connects to `broker` and serves the connection until it fails
+    /// (`Err`), `[mqtt]` changes or the daemon shuts down (`Ok`).
+    async fn session(self, broker: &str, settings: &AppConfig, retry: &mut Duration) -> io::Result<()> {
+        let mqtt = &settings.mqtt;
+        let topics = Topics::new(&mqtt.topic_prefix);
+        let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(broker_addr(broker)))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "broker did not answer"))??;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let client_id = format!("familycom-{}", settings.peer_id.chars().take(12).collect::<String>());
+        writer.write_all(&connect_packet(&client_id, &topics.status, mqtt)).await?;
+        let (header, body) = tokio::time::timeout(CONNECT_TIMEOUT, read_packet(&mut reader))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "broker did not acknowledge"))??;
+        check_connack(header, &body)?;
+        info!(broker, "connected to MQTT broker");
+        *retry = RETRY_MIN;
+
+        // Subscribed before listing peers, so no change of presence falls
+        // in between
+        let (mut event_rx, _) = self.events.subscribe();
+        writer.write_all(&publish_packet(&topics.status, b"online", true)).await?;
+        writer.write_all(&subscribe_packet(1, &topics.send)).await?;
+        if let Some(ServerMessage::PeerList { peers }) =
+            IpcRequest::submit(self.request_tx, ClientRequest::ListPeers).await
+        {
+            for peer in peers {
+                writer.write_all(&topics.presence(&peer.id, peer.online)).await?;
+            }
+        }
+
+        // Reading a packet isn't cancel-safe, so it's done on its own task
+        let (packet_tx, mut packet_rx) = mpsc::channel(16);
+        let read_task = tokio::spawn(async move {
+            loop {
+                let packet = read_packet(&mut reader).await;
+                let failed = packet.is_err();
+                if packet_tx.send(packet).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let mut ping = tokio::time::interval(KEEP_ALIVE / 2);
+        let mut last_heard = Instant::now();
+        let result = loop {
+            tokio::select! {
+                _ = self.shutdown.cancelled() => break Ok(()),
+                changed = self.config.changed() => {
+                    if changed.is_err() || self.config.borrow_and_update().mqtt != *mqtt {
+                        info!("MQTT settings changed, reconnecting");
+                        break Ok(());
+                    }
+                }
+                _ = ping.tick() => {
+                    if last_heard.elapsed() > KEEP_ALIVE * 3 / 2 {
+                        break Err(io::Error::new(io::ErrorKind::TimedOut, "broker stopped answering"));
+                    }
+                    if let Err(e) = writer.write_all(&PINGREQ).await {
+                        break Err(e);
+                    }
+                }
+                packet = packet_rx.recv() => {
+                    let (header, body) = match packet {
+                        Some(Ok(packet)) => packet,
+                        Some(Err(e)) => break Err(e),
+                        None => break Err(io::ErrorKind::UnexpectedEof.into()),
+                    };
+                    last_heard = Instant::now();
+                    if header >> 4 != PUBLISH {
+                        continue;
+                    }
+                    match parse_publish(header, &body) {
+                        Some((topic, payload)) if topic == topics.send => {
+                            tokio::spawn(send_command(self.request_tx.clone(), payload.to_vec()));
+                        }
+                        Some((topic, _)) => debug!(topic, "ignoring MQTT message on unexpected topic"),
+                        None => debug!("ignoring malformed MQTT PUBLISH"),
+                    }
+                }
+                event = event_rx.recv() => {
+                    let packets = match event {
+                        Ok(event) => self.packets_for(&topics, event.message),
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            warn!(missed = n, "MQTT bridge lagged");
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break Ok(()),
+                    };
+                    if let Err(e) = write_all(&mut writer, &packets).await {
+                        break Err(e);
+                    }
+                }
+            }
+        };
+        read_task.abort();
+
+        if result.is_ok() {
+            // A clean goodbye doesn't trigger the will, so say it ourselves
+            let goodbye = [publish_packet(&topics.status, b"offline", true), DISCONNECT.to_vec()];
+            let _ = write_all(&mut writer, &goodbye).await;
+        }
+        result
+    }
+
+    /// What to publish for a daemon event.
+    fn packets_for(&self, topics: &Topics, event: ServerMessage) -> Vec<Vec<u8>> {
+        let messages = match event {
+            ServerMessage::NewMessage { message } => vec![message],
+            ServerMessage::NewMessages { messages, .. } => messages,
+            ServerMessage::PeerOnline { peer } => return vec![topics.presence(&peer.id, true)],
+            ServerMessage::PeerOffline { peer_id } => return vec![topics.presence(&peer_id, false)],
+            _ => return Vec::new(),
+        };
+        let names = self.peer_names.borrow();
+        messages
+            .iter()
+            .filter(|message| message.direction == Direction::Received)
+            .filter_map(|message| {
+                let sender = names
+                    .get(&message.peer_id)
+                    .cloned()
+                    .unwrap_or_else(|| message.peer_id.to_string());
+                let payload = serde_json::to_vec(&Published::new(message, sender)).ok()?;
+                Some(publish_packet(&topics.messages, &payload, false))
+            })
+            .collect()
+    }
+}
+
+async fn write_all(writer: &mut OwnedWriteHalf, packets: &[Vec<u8>]) -> io::Result<()> {
+    for packet in packets {
+        writer.write_all(packet).await?;
+    }
+    Ok(())
+}
+
+/// Sends a message published to the command topic.
+async fn send_command(request_tx: mpsc::Sender<IpcRequest>, payload: Vec<u8>) {
+    let command: Command = match serde_json::from_slice(&payload) {
+        Ok(command) => command,
+        Err(e) => {
+            warn!(error = %e, r#"ignoring MQTT command, expected {{"to": ..., "content": ...}}"#);
+            return;
+        }
+    };
+    let peers = match IpcRequest::submit(&request_tx, ClientRequest::ListPeers).await {
+        Some(ServerMessage::PeerList { peers }) => peers,
+        _ => return,
+    };
+    let peer_id = match send::find_peer(&peers, &command.to) {
+        Ok(peer) => peer.id.clone(),
+        Err(e) => {
+            warn!(error = %e, "ignoring MQTT command");
+            return;
+        }
+    };
+    let request = ClientRequest::SendMessage {
+        peer_id: peer_id.clone(),
+        content: command.content,
+        reply_to: None,
+    };
+    match IpcRequest::submit(&request_tx, request).await {
+        Some(ServerMessage::MessageSent { message_id }) => {
+            info!(peer_id = %peer_id, message_id = %message_id, "sent message from MQTT")
+        }
+        Some(ServerMessage::Error { message, .. }) => {
+            warn!(peer_id = %peer_id, error = %message, "failed to send message from MQTT")
+        }
+        _ => {}
+    }
+}
+
+/// A message to send, as published to `<prefix>/send`.
+#[derive(Debug, Deserialize)]
+struct Command {
+    to: String,
+    content: String,
+}
+
+/// A received message, as published to `<prefix>/messages`.
+#[derive(Debug, Serialize)]
+struct Published {
+    sender: String,
+    sender_id: PeerId,
+    message_id: MessageId,
+    content: String,
+    /// RFC 3339, UTC.
+    timestamp: String,
+}
+
+impl Published {
+    fn new(message: &Message, sender: String) -> Self {
+        let timestamp = chrono::DateTime::from_timestamp_millis(message.timestamp.as_millis())
+            .map(|at| at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+            .unwrap_or_default();
+        Self {
+            sender,
+            sender_id: message.peer_id.clone(),
+            message_id: message.id.clone(),
+            content: message.content.clone(),
+            timestamp,
+        }
+    }
+}
+
+/// The bridge's topics under the configured prefix.
+struct Topics {
+    messages: String,
+    presence: String,
+    status: String,
+    send: String,
+}
+
+impl Topics {
+    fn new(prefix: &str) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        Self {
+            messages: format!("{prefix}/messages"),
+            presence: format!("{prefix}/presence"),
+            status: format!("{prefix}/status"),
+            send: format!("{prefix}/send"),
+        }
+    }
+
+    /// The retained presence of `peer_id`.
+    fn presence(&self, peer_id: &PeerId, online: bool) -> Vec<u8> {
+        let state: &[u8] = if online { b"online" } else { b"offline" };
+        publish_packet(&format!("{}/{peer_id}", self.presence), state, true)
+    }
+}
+
+/// `host:port`, with the default port if `broker` has none.
+fn broker_addr(broker: &str) -> (String, u16) {
+    match broker.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => match port.parse() {
+            Ok(port) => (host.to_string(), port),
+            Err(_) => (broker.to_string(), DEFAULT_PORT),
+        },
+        _ => (broker.to_string(), DEFAULT_PORT),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// MQTT 3.1.1 packets
+// ---------------------------------------------------------------------------
+
+/// Packet types (the high nibble of the first byte).
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+
+const PINGREQ: [u8; 2] = [0xC0, 0];
+const DISCONNECT: [u8; 2] = [0xE0, 0];
+
+/// A packet: fixed header byte, remaining length, then `body`.
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+/// Appends a length-prefixed string (or binary field).
+fn put(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+/// CONNECT with a clean session and a retained `offline` will on
+/// `will_topic`.
+fn connect_packet(client_id: &str, will_topic: &str, mqtt: &MqttConfig) -> Vec<u8> {
+    let mut body = Vec::new();
+    put(&mut body, b"MQTT");
+    body.push(4); // protocol level: 3.1.1
+    let mut flags = 0x02 | 0x04 | 0x20; // clean session, will, will retain
+    if mqtt.username.is_some() {
+        flags |= 0x80;
+    }
+    if mqtt.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    put(&mut body, client_id.as_bytes());
+    put(&mut body, will_topic.as_bytes());
+    put(&mut body, b"offline");
+    if let Some(username) = &mqtt.username {
+        put(&mut body, username.as_bytes());
+    }
+    if let Some(password) = &mqtt.password {
+        put(&mut body, password.as_bytes());
+    }
+    packet(0x10, &body)
+}
+
+/// PUBLISH at QoS 0.
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::with_capacity(2 + topic.len() + payload.len());
+    put(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet((PUBLISH << 4) | u8::from(retain), &body)
+}
+
+/// SUBSCRIBE to one topic at QoS 0.
+fn subscribe_packet(packet_id: u16, topic: &str) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    put(&mut body, topic.as_bytes());
+    body.push(0);
+    packet(0x82, &body)
+}
+
+/// Reads one packet: its fixed header byte and its body.
+async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let header = reader.read_u8().await?;
+    let mut len = 0usize;
+    for shift in [0, 7, 14, 21] {
+        let byte = reader.read_u8().await?;
+        len |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            if len > MAX_PACKET {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "MQTT packet too large"));
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).await?;
+            return Ok((header, body));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "malformed MQTT remaining length"))
+}
+
+/// Whether the broker accepted our CONNECT.
+fn check_connack(header: u8, body: &[u8]) -> io::Result<()> {
+    if header != CONNACK << 4 || body.len() != 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected CONNACK"));
+    }
+    match body[1] {
+        0 => Ok(()),
+        code => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("broker refused the connection (code {code})"),
+        )),
+    }
+}
+
+/// The topic and payload of a PUBLISH, or `None` if it's malformed.
+fn parse_publish(header: u8, body: &[u8]) -> Option<(&str, &[u8])> {
+    let topic_len = usize::from(u16::from_be_bytes([*body.first()?, *body.get(1)?]));
+    let topic = std::str::from_utf8(body.get(2..2 + topic_len)?).ok()?;
+    // QoS 1 and 2 carry a packet ID before the payload; there is no QoS 3
+    let payload_start = match (header >> 1) & 0x03 {
+        0 => 2 + topic_len,
+        1 | 2 => 4 + topic_len,
+        _ => return None,
+    };
+    Some((topic, body.get(payload_start..)?))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn packets_survive_a_round_trip() {
+        let payload = vec![b'x'; 321];
+        let bytes = publish_packet("familycom/messages", &payload, true);
+        // 321 + 20 bytes of topic = 341, two bytes of remaining length
+        assert_eq!(&bytes[..3], &[0x31, 0xD5, 0x02]);
+
+        let (header, body) = read_packet(&mut bytes.as_slice()).await.unwrap();
+        assert_eq!(header >> 4, PUBLISH);
+        let (topic, received) = parse_publish(header, &body).unwrap();
+        assert_eq!(topic, "familycom/messages");
+        assert_eq!(received, payload.as_slice());
+    }
+
+    #[tokio::test]
+    async fn truncated_packets_are_errors() {
+        let bytes = publish_packet("familycom/send", b"{}", false);
+        for len in 0..bytes.len() {
+            let err = read_packet(&mut &bytes[..len]).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {len}");
+        }
+    }
+
+    #[tokio::test]
+    async fn bad_remaining_lengths_are_refused() {
+        // A fifth length byte, which MQTT doesn't allow
+        let endless = [0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        let err = read_packet(&mut endless.as_slice()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Refused from the header, before reading (or allocating) the body
+        let too_large = packet(0x30, &vec![0; MAX_PACKET + 1]);
+        let err = read_packet(&mut &too_large[..4]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn malformed_publishes_are_dropped() {
+        assert_eq!(parse_publish(0x30, b""), None);
+        assert_eq!(parse_publish(0x30, b"\x00"), None);
+        // Topic longer than the packet
+        assert_eq!(parse_publish(0x30, b"\x00\x09topic"), None);
+        assert_eq!(parse_publish(0x30, b"\x00\x02\xff\xfe{}"), None, "topic not UTF-8");
+        // QoS 1 without its packet ID
+        assert_eq!(parse_publish(0x32, b"\x00\x01t\x00"), None);
+        assert_eq!(parse_publish(0x32, b"\x00\x01t\x00\x07{}"), Some(("t", &b"{}"[..])));
+        assert_eq!(parse_publish(0x36, b"\x00\x01t\x00\x07{}"), None, "QoS 3");
+    }
+
+    #[test]
+    fn only_a_well_formed_connack_accepting_us_connects() {
+        assert!(check_connack(0x20, &[0, 0]).is_ok());
+        let refused = check_connack(0x20, &[0, 5]).unwrap_err();
+        assert_eq!(refused.kind(), io::ErrorKind::PermissionDenied);
+        for (header, body) in [(0x20, &[][..]), (0x20, &[0][..]), (0x20, &[0, 0, 0][..]), (0x30, &[0, 0][..])] {
+            let err = check_connack(header, body).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{header:#x} {body:?}");
+        }
+    }
+
+    #[test]
+    fn connect_carries_the_will_and_credentials() {
+        let mqtt = MqttConfig {
+            broker: Some("mqtt.lan".to_string()),
+            username: Some("casa".to_string()),
+            ..MqttConfig::default()
+        };
+        let bytes = connect_packet("familycom-1", "familycom/status", &mqtt);
+        let body = &bytes[2..];
+        assert_eq!(&body[..7], b"\x00\x04MQTT\x04");
+        assert_eq!(body[7], 0x80 | 0x20 | 0x04 | 0x02, "username, will retain, will, clean session");
+        assert!(body.ends_with(b"\x00\x07offline\x00\x04casa"));
+    }
+
+    #[test]
+    fn topics_and_broker_addresses() {
+        let topics = Topics::new("casa/familycom/");
+        assert_eq!(topics.send, "casa/familycom/send");
+        let presence = topics.presence(&PeerId::new("p1"), false);
+        let (topic, state) = parse_publish(presence[0], &presence[2..]).unwrap();
+        assert_eq!((topic, state), ("casa/familycom/presence/p1", &b"offline"[..]));
+        assert_eq!(presence[0] & 0x01, 1, "retained");
+
+        assert_eq!(broker_addr("192.168.1.10"), ("192.168.1.10".to_string(), 1883));
+        assert_eq!(broker_addr("mqtt.lan:8883"), ("mqtt.lan".to_string(), 8883));
+    }
+}
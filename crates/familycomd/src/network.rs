@@ -0,0 +1,427 @@
+//! Trusted network detection.
+//!
+//! A laptop that leaves home shouldn't announce itself on café or hotel
+//! networks. With `[networks] require_trusted = true` in the config, the
+//! daemon only runs mDNS discovery and accepts peer connections while the
+//! current network matches a trusted profile (see
+//! `familycom_core::config::NetworkConfig`).
+//!
+//! # Detection
+//!
+//! A network is identified by what we can observe locally, best effort:
+//! - the MAC address of the default gateway (via `netdev`), and
+//! - the Wi-Fi SSID (`iwgetid`/`nmcli` on Linux, `networksetup` on macOS,
+//!   `netsh` on Windows).
+//!
+//! # Watcher
+//!
+//! `run_watcher` re-checks the network periodically. It owns the
+//! `DiscoveryService`: it starts it when the network becomes trusted and
+//! shuts it down (sending mDNS goodbyes) when it isn't, and forwards its
+//! events into a channel that outlives any single service. It also opens
+//! and closes a `TrustGate` that the TCP server checks before accepting a
+//! connection.
+//!
+//! It follows config changes (`ReloadConfig`) too: a new display name,
+//! avatar, color, interface or `[networks]` section restarts discovery so
+//! it's advertised and applied right away.
+
+use crate::discovery::{DiscoveryError, DiscoveryEvent, DiscoveryService, Presence};
+use familycom_core::config::{AppConfig, NetworkFingerprint};
+use familycom_core::types::PeerId;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// How often the current network is re-checked (e.g. after the laptop
+/// moved from home to a café without restarting the daemon).
+const NETWORK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Shared flag saying whether peers may currently connect to us.
+///
+/// Starts open; the network watcher closes it on untrusted networks.
+#[derive(Debug, Clone)]
+pub struct TrustGate(Arc<AtomicBool>);
+
+impl TrustGate {
+    /// Creates a gate that lets connections through.
+    pub fn open() -> Self {
+        Self(Arc::new(AtomicBool::new(true)))
+    }
+
+    /// Whether connections are currently allowed.
+    pub fn is_open(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, open: bool) {
+        self.0.store(open, Ordering::Relaxed);
+    }
+}
+
+/// Observes the network we're on: gateway MAC and Wi-Fi SSID.
+///
+/// `interface` is the first interface named in `network_interface`, if any;
+/// otherwise the default-route interface is used. Blocking (may run external commands).
+pub fn detect(interface: Option<&str>) -> NetworkFingerprint {
+    let iface = match interface {
+        Some(name) => netdev::get_interfaces().into_iter().find(|i| i.name == name),
+        None => netdev::get_default_interface().ok(),
+    };
+    let Some(iface) = iface else {
+        return NetworkFingerprint::default();
+    };
+
+    let gateway_mac = iface
+        .gateway
+        .as_ref()
+        .map(|gw| gw.mac_addr.to_string())
+        // An all-zero MAC means the gateway's address wasn't resolved
+        .filter(|mac| mac != "00:00:00:00:00:00");
+
+    NetworkFingerprint {
+        gateway_mac,
+        ssid: detect_ssid(&iface.name),
+    }
+}
+
+/// Returns the SSID of the Wi-Fi network `iface` is connected to.
+#[cfg(target_os = "linux")]
+fn detect_ssid(iface: &str) -> Option<String> {
+    // wireless-tools first, NetworkManager as a fallback
+    if let Some(out) = command_output("iwgetid", &[iface, "--raw"]) {
+        let ssid = out.trim();
+        if !ssid.is_empty() {
+            return Some(ssid.to_string());
+        }
+    }
+    command_output("nmcli", &["-t", "-f", "active,ssid", "dev", "wifi"])
+        .and_then(|out| parse_nmcli_ssid(&out))
+}
+
+/// Returns the SSID of the Wi-Fi network `iface` is connected to.
+#[cfg(target_os = "macos")]
+fn detect_ssid(iface: &str) -> Option<String> {
+    command_output("networksetup", &["-getairportnetwork", iface])
+        .and_then(|out| parse_airport_ssid(&out))
+}
+
+/// Returns the SSID of the Wi-Fi network we're connected to. netsh lists
+/// Wi-Fi interfaces by their friendly name, not `iface`, so this is the
+/// first connected one.
+#[cfg(windows)]
+fn detect_ssid(_iface: &str) -> Option<String> {
+    command_output("netsh", &["wlan", "show", "interfaces"]).and_then(|out| parse_netsh_ssid(&out))
+}
+
+/// Returns the SSID of the Wi-Fi network `iface` is connected to.
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn detect_ssid(_iface: &str) -> Option<String> {
+    None
+}
+
+/// Runs a command and returns its stdout, or `None` if it isn't installed
+/// or failed.
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+pub(crate) fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Extracts the active SSID from `nmcli -t -f active,ssid dev wifi`
+/// output, whose lines look like `yes:MiCasa`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_nmcli_ssid(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("yes:"))
+        // nmcli escapes ':' inside values in terse mode
+        .map(|ssid| ssid.replace("\\:", ":"))
+        .filter(|ssid| !ssid.is_empty())
+}
+
+/// Extracts the SSID from `networksetup -getairportnetwork` output:
+/// `Current Wi-Fi Network: MiCasa`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_airport_ssid(output: &str) -> Option<String> {
+    output
+        .trim()
+        .split_once("Network: ")
+        .map(|(_, ssid)| ssid.to_string())
+        .filter(|ssid| !ssid.is_empty())
+}
+
+/// Extracts the SSID from `netsh wlan show interfaces` output, which has
+/// `    SSID                   : MiCasa` (and a `BSSID` line to skip).
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_netsh_ssid(output: &str) -> Option<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim() == "SSID")
+        .map(|(_, ssid)| ssid.trim().to_string())
+        .filter(|ssid| !ssid.is_empty())
+}
+
+/// A running discovery service plus the peers it has reported, so they can
+/// be marked lost when we go silent.
+struct ActiveDiscovery {
+    service: DiscoveryService,
+    events: mpsc::Receiver<DiscoveryEvent>,
+    found: HashSet<PeerId>,
+}
+
+/// The watches behind what discovery advertises as our `Presence`.
+#[derive(Clone)]
+pub struct PresenceWatch {
+    /// Whether we're in do-not-disturb.
+    pub do_not_disturb: watch::Receiver<bool>,
+    /// Whether nobody is at the keyboard.
+    pub away: watch::Receiver<bool>,
+}
+
+impl PresenceWatch {
+    /// Our current presence, marking both watches as seen.
+    fn current(&mut self) -> Presence {
+        Presence {
+            do_not_disturb: *self.do_not_disturb.borrow_and_update(),
+            away: *self.away.borrow_and_update(),
+        }
+    }
+}
+
+/// Re-announces discovery, if it's running, with a new presence.
+fn announce_presence(active: &mut Option<ActiveDiscovery>, presence: Presence) {
+    if let Some(discovery) = active {
+        if let Err(e) = discovery.service.set_presence(presence) {
+            warn!(error = %e, "failed to advertise presence");
+        }
+    }
+}
+
+/// Watches the network and keeps discovery and the TCP gate in line with
+/// the trusted network settings. Runs until `shutdown` is cancelled, then
+/// shuts discovery down. Also returns, after shutting it down, if the
+/// mDNS browse loop dies, so the supervisor restarts the whole watcher.
+///
+/// # Arguments
+///
+/// * `config` - The daemon's config; its `[networks]` section and
+///   `network_interface` decide where discovery runs.
+/// * `gate` - Closed while on an untrusted network.
+/// * `presence` - Whether we're in do-not-disturb, and whether nobody is
+///   at the keyboard, which discovery advertises. Changes are re-announced
+///   without restarting discovery.
+/// * `start_discovery` - Creates a new `DiscoveryService` from the current
+///   config and presence. Called again each time the network becomes trusted, when the
+///   advertised settings change, or to retry after a failure.
+/// * `discovery_tx` - Where discovery events are forwarded for the daemon.
+/// * `ready` - Fired once discovery is running, or the network turned out
+///   to be untrusted (silence is the intended state there). Not fired while
+///   discovery keeps failing to start. `None` after a restart: it was
+///   fired, or will never matter, by then.
+pub async fn run_watcher<F>(
+    mut config: watch::Receiver<AppConfig>,
+    mut presence: PresenceWatch,
+    gate: TrustGate,
+    start_discovery: F,
+    discovery_tx: mpsc::Sender<DiscoveryEvent>,
+    mut ready: Option<oneshot::Sender<()>>,
+    shutdown: CancellationToken,
+) where
+    F: Fn(&AppConfig, Presence) -> Result<(DiscoveryService, mpsc::Receiver<DiscoveryEvent>), DiscoveryError>,
+{
+    let mut current = config.borrow_and_update().clone();
+    let mut active: Option<ActiveDiscovery> = None;
+    let mut last_network: Option<NetworkFingerprint> = None;
+    let mut check = tokio::time::interval(NETWORK_CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+
+            Ok(()) = config.changed() => {
+                let new = config.borrow_and_update().clone();
+                let restart = discovery_settings_changed(&current, &new);
+                current = new;
+                if restart {
+                    info!("discovery settings changed, restarting mDNS");
+                    if let Some(discovery) = active.take() {
+                        stop(discovery, &discovery_tx).await;
+                    }
+                    last_network = None;
+                    check.reset_immediately();
+                }
+            }
+
+            Ok(()) = presence.do_not_disturb.changed() => {
+                announce_presence(&mut active, presence.current());
+            }
+
+            Ok(()) = presence.away.changed() => {
+                announce_presence(&mut active, presence.current());
+            }
+
+            _ = check.tick() => {
+                let networks = &current.networks;
+                let allowed = if networks.require_trusted {
+                    let iface = current
+                        .network_interface
+                        .as_ref()
+                        .and_then(|interfaces| interfaces.primary())
+                        .map(str::to_string);
+                    let network = tokio::task::spawn_blocking(move || detect(iface.as_deref()))
+                        .await
+                        .unwrap_or_default();
+                    if last_network.as_ref() != Some(&network) {
+                        match networks.matching(&network) {
+                            Some(trusted) => info!(network = %trusted.name, "on a trusted network"),
+                            // Logged with the fingerprint so it can be copied into the config
+                            None => warn!(%network, "untrusted network, staying silent"),
+                        }
+                        last_network = Some(network.clone());
+                    }
+                    networks.allows(&network)
+                } else {
+                    true
+                };
+                gate.set(allowed);
+
+                if allowed && active.is_none() {
+                    match start_discovery(&current, presence.current()) {
+                        Ok((service, events)) => {
+                            active = Some(ActiveDiscovery { service, events, found: HashSet::new() });
+                        }
+                        Err(e) => warn!(error = %e, "failed to start mDNS discovery, will retry"),
+                    }
+                } else if !allowed {
+                    if let Some(discovery) = active.take() {
+                        stop(discovery, &discovery_tx).await;
+                    }
+                }
+                if !allowed || active.is_some() {
+                    if let Some(ready) = ready.take() {
+                        let _ = ready.send(());
+                    }
+                }
+            }
+
+            event = async {
+                match &mut active {
+                    Some(discovery) => discovery.events.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                let Some(event) = event else {
+                    warn!("mDNS browse loop stopped");
+                    break;
+                };
+                if let Some(discovery) = &mut active {
+                    match &event {
+                        DiscoveryEvent::PeerFound(peer) => {
+                            discovery.found.insert(peer.id.clone());
+                        }
+                        DiscoveryEvent::PeerLost(peer_id) => {
+                            discovery.found.remove(peer_id);
+                        }
+                    }
+                }
+                if discovery_tx.send(event).await.is_err() {
+                    debug!("daemon stopped listening for discovery events");
+                }
+            }
+        }
+    }
+
+    if let Some(discovery) = active.take() {
+        stop(discovery, &discovery_tx).await;
+    }
+}
+
+/// Whether `old` and `new` differ in anything discovery advertises or
+/// depends on.
+fn discovery_settings_changed(old: &AppConfig, new: &AppConfig) -> bool {
+    old.display_name != new.display_name
+        || old.avatar != new.avatar
+        || old.accent_color != new.accent_color
+        || old.network_interface != new.network_interface
+        || old.networks != new.networks
+}
+
+/// Unregisters from mDNS and reports every peer it had found as lost.
+async fn stop(discovery: ActiveDiscovery, discovery_tx: &mpsc::Sender<DiscoveryEvent>) {
+    let ActiveDiscovery { service, found, .. } = discovery;
+    // shutdown() waits for mdns-sd's confirmations, which is blocking
+    if let Err(e) = tokio::task::spawn_blocking(move || service.shutdown()).await {
+        warn!(error = %e, "mDNS shutdown task failed");
+    }
+    for peer_id in found {
+        let _ = discovery_tx.send(DiscoveryEvent::PeerLost(peer_id)).await;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nmcli_active_ssid() {
+        let output = "no:Vecino\nyes:Familia\\:Pérez\nno:Cafe\n";
+        assert_eq!(parse_nmcli_ssid(output).as_deref(), Some("Familia:Pérez"));
+        assert_eq!(parse_nmcli_ssid("no:Vecino\n"), None);
+    }
+
+    #[test]
+    fn airport_ssid() {
+        assert_eq!(
+            parse_airport_ssid("Current Wi-Fi Network: MiCasa\n").as_deref(),
+            Some("MiCasa")
+        );
+        assert_eq!(
+            parse_airport_ssid("You are not associated with an AirPort network.\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn netsh_ssid() {
+        let output = "    Name                   : Wi-Fi\r\n    \
+                      State                  : connected\r\n    \
+                      SSID                   : Casa: planta baja\r\n    \
+                      BSSID                  : a4:2b:b0:12:34:56\r\n";
+        assert_eq!(parse_netsh_ssid(output).as_deref(), Some("Casa: planta baja"));
+        assert_eq!(parse_netsh_ssid("    State                  : disconnected\r\n"), None);
+    }
+
+    #[test]
+    fn only_advertised_settings_restart_discovery() {
+        let config = AppConfig::new_first_run("Sala");
+        let mut quiet = config.clone();
+        quiet.notifications_enabled = false;
+        assert!(!discovery_settings_changed(&config, &quiet));
+
+        let mut renamed = config.clone();
+        renamed.display_name = "Sala de estar".to_string();
+        assert!(discovery_settings_changed(&config, &renamed));
+    }
+
+    #[test]
+    fn gate_starts_open() {
+        let gate = TrustGate::open();
+        let shared = gate.clone();
+        assert!(shared.is_open());
+        gate.set(false);
+        assert!(!shared.is_open());
+    }
+}
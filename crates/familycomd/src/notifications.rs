@@ -10,14 +10,50 @@
 //! # Rate Limiting
 //!
 //! To avoid spamming the user with notifications when many messages
-//! arrive at once, we limit to at most one notification per second.
+//! arrive at once, we limit to at most one notification per second. A peer
+//! flooding the chat is already throttled by the daemon, which pushes its
+//! burst as one `NewMessages` event; that gets a single notification
+//! saying how many messages came in.
+//!
+//! # Do Not Disturb
+//!
+//! During the configured `quiet_hours`, or while do-not-disturb is turned
+//! on over IPC, only urgent messages (starting with `!!`, and
+//! announcements) are notified.
+//!
+//! # Open conversations
+//!
+//! A message isn't notified while a client with a focused window shows
+//! its conversation (`SetViewing`): the user is already reading it.
+//!
+//! # Announcements
+//!
+//! A house-wide announcement ("A cenar!") is titled as one, skips the rate
+//! limit and asks for critical urgency, which most notification servers
+//! keep on screen until dismissed.
 
+use crate::events::EventBus;
+use familycom_core::config::AppConfig;
+use familycom_core::ipc::ServerMessage;
+use familycom_core::types::{Direction, Message, NotificationPrefs, PeerId, PeerInfo};
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
-use tracing::{debug, error};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
 
 /// Minimum time between notifications to prevent spam.
 const MIN_NOTIFICATION_INTERVAL: Duration = Duration::from_secs(1);
 
+/// A client showing a conversation in a focused window (`SetViewing`).
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    pub peer_id: PeerId,
+    /// The client's response channel. It closes when the client
+    /// disconnects, which ends the viewing even if it never said so.
+    pub client: mpsc::Sender<ServerMessage>,
+}
+
 /// Manages desktop notification delivery.
 pub struct NotificationManager {
     /// When the last notification was shown.
@@ -43,14 +79,17 @@ impl NotificationManager {
     /// # Arguments
     ///
     /// * `sender_name` - Display name of the peer who sent the message
+    ///   (already prefixed with their avatar, if they have one)
     /// * `preview` - A preview of the message content (first ~100 chars)
-    pub fn notify_new_message(&mut self, sender_name: &str, preview: &str) {
+    /// * `sound` - If false, asks the notification server not to play a sound
+    /// * `announcement` - A house-wide announcement: urgent, never rate-limited
+    pub fn notify_new_message(&mut self, sender_name: &str, preview: &str, sound: bool, announcement: bool) {
         if !self.enabled {
             return;
         }
 
         // Rate limiting: skip if we sent a notification too recently
-        if let Some(last) = self.last_notification {
+        if let Some(last) = self.last_notification.filter(|_| !announcement) {
             if last.elapsed() < MIN_NOTIFICATION_INTERVAL {
                 debug!("notification rate-limited, skipping");
                 return;
@@ -67,12 +106,24 @@ impl NotificationManager {
         // Send the notification using notify-rust.
         // The "default" action fires when the user clicks the notification body
         // (standard D-Bus notification behavior on Linux).
-        let result = notify_rust::Notification::new()
-            .summary(&format!("FamilyCom - {sender_name}"))
+        let summary = if announcement {
+            format!("FamilyCom - Anuncio de {sender_name}")
+        } else {
+            format!("FamilyCom - {sender_name}")
+        };
+        let mut notification = notify_rust::Notification::new();
+        notification
+            .summary(&summary)
             .body(&truncated_preview)
             .action("default", "Abrir Chat")
-            .timeout(notify_rust::Timeout::Milliseconds(5000))
-            .show();
+            .timeout(notify_rust::Timeout::Milliseconds(5000));
+        if !sound {
+            suppress_sound(&mut notification);
+        }
+        if announcement {
+            mark_critical(&mut notification);
+        }
+        let result = notification.show();
 
         match result {
             Ok(handle) => {
@@ -86,7 +137,7 @@ impl NotificationManager {
                 std::thread::spawn(move || {
                     handle.wait_for_action(|action| {
                         if action == "default" {
-                            crate::tray::open_chat_in_terminal();
+                            crate::tray::open_chat_in_terminal(None);
                         }
                     });
                 });
@@ -98,8 +149,141 @@ impl NotificationManager {
     }
 
     /// Enables or disables notifications.
-    #[allow(dead_code)]
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
     }
 }
+
+/// Asks the freedesktop notification server not to play a sound.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn suppress_sound(notification: &mut notify_rust::Notification) {
+    notification.hint(notify_rust::Hint::SuppressSound(true));
+}
+
+/// macOS notifications only play a sound when given one, which we never do.
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn suppress_sound(_notification: &mut notify_rust::Notification) {}
+
+/// Asks the freedesktop notification server to keep the notification up
+/// until it's dismissed.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn mark_critical(notification: &mut notify_rust::Notification) {
+    notification.urgency(notify_rust::Urgency::Critical);
+}
+
+/// Other platforms have no urgency levels.
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn mark_critical(_notification: &mut notify_rust::Notification) {}
+
+/// Listens to daemon events and shows a notification for each received message.
+///
+/// Notifications are titled with the sender's name as `peer_names` has
+/// it (the daemon keeps it in step with the peer table), so a message from
+/// a peer that was never seen online in this session is still named.
+///
+/// Runs until the event channel closes or `shutdown` is cancelled. Takes
+/// the event bus and subscribes itself, so the supervisor can restart it
+/// with a fresh receiver if it ever dies. `config`, `prefs` (per-peer mute
+/// and sound), `peer_names`, `do_not_disturb` and `viewers` are checked for
+/// each message, so they can change while running. In do-not-disturb, only
+/// urgent messages (see `Message::is_urgent`) are notified.
+pub async fn run_handler(
+    events: EventBus,
+    config: watch::Receiver<AppConfig>,
+    prefs: watch::Receiver<HashMap<PeerId, NotificationPrefs>>,
+    peer_names: watch::Receiver<HashMap<PeerId, String>>,
+    do_not_disturb: watch::Receiver<bool>,
+    viewers: watch::Receiver<Vec<Viewer>>,
+    shutdown: CancellationToken,
+) {
+    let watches = Watches {
+        config,
+        prefs,
+        peer_names,
+        do_not_disturb,
+        viewers,
+    };
+    let (mut notification_rx, _) = events.subscribe();
+    let mut manager = NotificationManager::new();
+
+    loop {
+        let event = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            event = notification_rx.recv() => event.map(|event| event.message),
+        };
+        match event {
+            Ok(ServerMessage::NewMessage { ref message }) if message.direction == Direction::Received => {
+                notify_received(&mut manager, &watches, std::slice::from_ref(message));
+            }
+            // A flood, already coalesced by the daemon: one notification for all
+            Ok(ServerMessage::NewMessages { ref messages, .. }) => {
+                notify_received(&mut manager, &watches, messages);
+            }
+            Ok(_) => {} // Other events don't need notifications
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!(missed = n, "notification handler lagged");
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                break;
+            }
+        }
+    }
+}
+
+/// What the handler follows while running.
+struct Watches {
+    config: watch::Receiver<AppConfig>,
+    prefs: watch::Receiver<HashMap<PeerId, NotificationPrefs>>,
+    peer_names: watch::Receiver<HashMap<PeerId, String>>,
+    do_not_disturb: watch::Receiver<bool>,
+    viewers: watch::Receiver<Vec<Viewer>>,
+}
+
+/// Notifies `messages`, all received from one peer, previewing the last
+/// one, unless the peer is muted, its conversation is open in a focused
+/// client, or we're in do-not-disturb.
+fn notify_received(manager: &mut NotificationManager, watches: &Watches, messages: &[Message]) {
+    let Some(last) = messages.last() else {
+        return;
+    };
+    let peer_id = &last.peer_id;
+    let peer_prefs = watches.prefs.borrow().get(peer_id).copied().unwrap_or_default();
+    if peer_prefs.muted {
+        debug!(peer_id = %peer_id, "peer is muted, no notification");
+        return;
+    }
+    if is_viewed(&watches.viewers.borrow(), peer_id) {
+        debug!(peer_id = %peer_id, "conversation is open, no notification");
+        return;
+    }
+    if *watches.do_not_disturb.borrow() && !messages.iter().any(Message::is_urgent) {
+        debug!(peer_id = %peer_id, "do not disturb, no notification");
+        return;
+    }
+    manager.set_enabled(watches.config.borrow().notifications_enabled);
+    let sender_name = watches
+        .peer_names
+        .borrow()
+        .get(peer_id)
+        .cloned()
+        .unwrap_or_else(|| peer_id.to_string());
+    let preview = match messages.len() {
+        1 => last.content.clone(),
+        n => format!("{n} mensajes nuevos. Ultimo: {}", last.content),
+    };
+    manager.notify_new_message(&sender_name, &preview, peer_prefs.sound, last.announcement);
+}
+
+/// Whether a connected client shows `peer_id`'s conversation in a focused
+/// window.
+pub(crate) fn is_viewed(viewers: &[Viewer], peer_id: &PeerId) -> bool {
+    viewers.iter().any(|v| &v.peer_id == peer_id && !v.client.is_closed())
+}
+
+/// How a peer is named in notification titles, e.g. "🐱 PC-Sala".
+pub fn sender_label(peer: &PeerInfo) -> String {
+    match &peer.avatar {
+        Some(avatar) => format!("{avatar} {}", peer.display_name),
+        None => peer.display_name.clone(),
+    }
+}
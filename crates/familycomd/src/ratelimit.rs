@@ -0,0 +1,308 @@
+//! Rate limiting for inbound peer connections.
+//!
+//! Every source IP on the LAN gets a budget, so one misbehaving device (a
+//! buggy script, a compromised IoT gadget) can't tie up the daemon:
+//!
+//! - at most `max_connections` connections open at once,
+//! - new connections at a sustained `connections_per_second`, with bursts
+//!   up to `connection_burst`,
+//! - frames at a sustained `frames_per_second` across all its connections,
+//!   with bursts up to `frame_burst`.
+//!
+//! Connections over the first two limits are dropped right after
+//! accepting them. Frames over the last one are delayed rather than
+//! dropped: the connection stops being read until the budget refills, which
+//! slows the sender down through TCP backpressure. The defaults are far
+//! above what a family's machines send, so only abusive sources notice.
+//!
+//! Budgets are token buckets (`TokenBucket`) on tokio's clock, so tests can
+//! pause time.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// Sources idle (and without open connections) for this long are
+/// forgotten, so the table doesn't grow with every device ever seen.
+const SOURCE_IDLE: Duration = Duration::from_secs(600);
+
+/// A token bucket: holds up to `capacity` tokens, refilled continuously at
+/// `per_second`.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(per_second: f64, capacity: u32) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            per_second,
+            tokens: f64::from(capacity),
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Takes a token if one is available.
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the bucket has refilled completely, i.e. nothing has been
+    /// taken lately.
+    pub fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+
+    /// Takes a token, going into debt if there is none, and returns how
+    /// long to wait before using it. Callers that wait queue up fairly:
+    /// each one's wait includes the debt of those before it.
+    pub fn reserve(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.per_second)
+        }
+    }
+}
+
+/// Per-source-IP limits for inbound connections.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    /// Connections open at the same time.
+    pub max_connections: usize,
+    /// New connections per second, sustained.
+    pub connections_per_second: f64,
+    /// New connections allowed in a burst.
+    pub connection_burst: u32,
+    /// Frames per second across all of a source's connections, sustained.
+    pub frames_per_second: f64,
+    /// Frames allowed in a burst.
+    pub frame_burst: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 8,
+            connections_per_second: 2.0,
+            connection_burst: 20,
+            frames_per_second: 20.0,
+            frame_burst: 100,
+        }
+    }
+}
+
+/// Why a connection was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    /// The source already has `max_connections` open.
+    TooManyConnections,
+    /// The source is connecting faster than `connections_per_second`.
+    ConnectingTooFast,
+}
+
+/// What we track for one source IP.
+#[derive(Debug)]
+struct Source {
+    open: usize,
+    connections: TokenBucket,
+    frames: TokenBucket,
+    last_seen: Instant,
+    /// Whether its last connection was refused, to warn once per episode
+    /// instead of once per connection.
+    refusing: bool,
+}
+
+/// Shared rate limiter for every inbound connection.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    sources: Arc<Mutex<HashMap<IpAddr, Source>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            sources: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Admits a new connection from `ip`, or says why it must be dropped.
+    /// The permit holds a connection slot until it's dropped.
+    pub fn admit(&self, ip: IpAddr) -> Result<ConnectionPermit, Refusal> {
+        let now = Instant::now();
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        sources.retain(|_, s| s.open > 0 || now.saturating_duration_since(s.last_seen) < SOURCE_IDLE);
+
+        let limits = &self.limits;
+        let source = sources.entry(ip).or_insert_with(|| Source {
+            open: 0,
+            connections: TokenBucket::new(limits.connections_per_second, limits.connection_burst),
+            frames: TokenBucket::new(limits.frames_per_second, limits.frame_burst),
+            last_seen: now,
+            refusing: false,
+        });
+        source.last_seen = now;
+
+        let refusal = if source.open >= limits.max_connections {
+            Some(Refusal::TooManyConnections)
+        } else if !source.connections.try_take(now) {
+            Some(Refusal::ConnectingTooFast)
+        } else {
+            None
+        };
+        match refusal {
+            Some(refusal) => {
+                if !source.refusing {
+                    warn!(%ip, ?refusal, open = source.open, "rate limiting connections from this address");
+                } else {
+                    debug!(%ip, ?refusal, "refusing rate-limited connection");
+                }
+                source.refusing = true;
+                Err(refusal)
+            }
+            None => {
+                source.refusing = false;
+                source.open += 1;
+                Ok(ConnectionPermit {
+                    limiter: self.clone(),
+                    ip,
+                })
+            }
+        }
+    }
+}
+
+/// An admitted connection. Paces its frames and frees its slot on drop.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limiter: RateLimiter,
+    ip: IpAddr,
+}
+
+impl ConnectionPermit {
+    /// Accounts for one received frame, waiting first if the source is over
+    /// its frame budget.
+    pub async fn pace_frame(&self) {
+        let wait = {
+            let mut sources = self.limiter.sources.lock().unwrap_or_else(|e| e.into_inner());
+            match sources.get_mut(&self.ip) {
+                Some(source) => {
+                    source.last_seen = Instant::now();
+                    source.frames.reserve(source.last_seen)
+                }
+                None => Duration::ZERO,
+            }
+        };
+        if !wait.is_zero() {
+            debug!(ip = %self.ip, ?wait, "frame rate limit reached, delaying");
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut sources = self.limiter.sources.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(source) = sources.get_mut(&self.ip) {
+            source.open = source.open.saturating_sub(1);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 1, last])
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_refills_over_time() {
+        let mut bucket = TokenBucket::new(2.0, 2);
+        let now = Instant::now();
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(!bucket.try_take(now));
+        assert!(bucket.try_take(now + Duration::from_millis(500)));
+
+        // Reservations queue up behind each other
+        let later = now + Duration::from_millis(500);
+        assert_eq!(bucket.reserve(later), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(later), Duration::from_secs(1));
+        assert!(!bucket.is_full(later + Duration::from_secs(1)));
+        assert!(bucket.is_full(later + Duration::from_secs(2)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connections_are_limited_per_source() {
+        let limiter = RateLimiter::new(RateLimits {
+            max_connections: 2,
+            connections_per_second: 1.0,
+            connection_burst: 3,
+            ..RateLimits::default()
+        });
+
+        let first = limiter.admit(ip(10)).unwrap();
+        let _second = limiter.admit(ip(10)).unwrap();
+        assert_eq!(limiter.admit(ip(10)).unwrap_err(), Refusal::TooManyConnections);
+        // Other devices have their own budget
+        let _other = limiter.admit(ip(11)).unwrap();
+
+        // A freed slot can be reused, but the burst is spent
+        drop(first);
+        let third = limiter.admit(ip(10)).unwrap();
+        drop(third);
+        assert_eq!(limiter.admit(ip(10)).unwrap_err(), Refusal::ConnectingTooFast);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(limiter.admit(ip(10)).is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn frames_over_budget_are_delayed() {
+        let limiter = RateLimiter::new(RateLimits {
+            frames_per_second: 10.0,
+            frame_burst: 5,
+            ..RateLimits::default()
+        });
+        let permit = limiter.admit(ip(10)).unwrap();
+
+        let start = Instant::now();
+        for _ in 0..15 {
+            permit.pace_frame().await;
+        }
+        // 5 free, then 10 more at 10 per second
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(990) && elapsed <= Duration::from_millis(1010), "{elapsed:?}");
+    }
+}
@@ -0,0 +1,223 @@
+//! `familycomd send`: sends one message from a script or cron job.
+//!
+//! ```bash
+//! familycomd send --to "PC-Sala" "la cena está lista"
+//! ```
+//!
+//! If the daemon is running, the message goes through it over IPC, so it's
+//! stored and shown in open TUIs like any other. Otherwise it's sent straight
+//! to the peer's last known addresses (from the database) and stored there,
+//! so the conversation has it the next time the daemon starts.
+//!
+//! `--to` is a display name (case-insensitive) or a peer ID. Exits non-zero
+//! if the peer is unknown or the message couldn't be handed over.
+
+use crate::client;
+use anyhow::{bail, Context, Result};
+use familycom_core::config::AppConfig;
+use familycom_core::db::Database;
+use familycom_core::ipc::{self, ClientRequest, IpcEndpoint, ServerMessage};
+use familycom_core::protocol::PeerMessage;
+use familycom_core::types::{
+    DeliveryMode, Direction, Message, MessageContent, MessageId, PeerId, PeerInfo, Timestamp,
+};
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+
+/// Sends `content` to the peer named `to`, through the daemon if one is
+/// listening on `endpoint`.
+pub async fn run(config_path: &Path, db_path: &Path, endpoint: &IpcEndpoint, to: &str, content: &str) -> Result<()> {
+    MessageContent::new(content)?;
+
+    if let Some(mut daemon) = DaemonConnection::connect(endpoint).await {
+        let peers = match daemon.request(&ClientRequest::ListPeers).await? {
+            ServerMessage::PeerList { peers } => peers,
+            other => bail!("unexpected response from daemon: {other:?}"),
+        };
+        let peer = find_peer(&peers, to)?;
+        let request = ClientRequest::SendMessage {
+            peer_id: peer.id.clone(),
+            content: content.to_string(),
+            reply_to: None,
+        };
+        return match daemon.request(&request).await? {
+            ServerMessage::MessageSent { .. } => {
+                println!("Sent to {} via the daemon", peer.display_name);
+                Ok(())
+            }
+            ServerMessage::Error { message, .. } => bail!("daemon refused the message: {message}"),
+            other => bail!("unexpected response from daemon: {other:?}"),
+        };
+    }
+
+    send_directly(config_path, db_path, to, content).await
+}
+
+/// Sends without a daemon, using the config and database it would use.
+async fn send_directly(config_path: &Path, db_path: &Path, to: &str, content: &str) -> Result<()> {
+    let config = AppConfig::load_from(config_path)?
+        .with_context(|| format!("no config at {} (run `familycomd setup` first)", config_path.display()))?;
+    let db = Database::open(db_path).with_context(|| format!("failed to open database {}", db_path.display()))?;
+    let peers = db.get_peers()?;
+    let peer = find_peer(&peers, to)?;
+    if !config.kid_mode.allows(&peer.id) {
+        bail!("kid mode is on and {} is not an allowed peer", peer.display_name);
+    }
+    if peer.addresses.is_empty() {
+        bail!("no known addresses for {}", peer.display_name);
+    }
+
+    let mode = config.delivery.mode_for(&peer.id, Some(peer.display_name.as_str()));
+    let message = Message {
+        id: MessageId::generate(),
+        peer_id: peer.id.clone(),
+        direction: Direction::Sent,
+        content: content.to_string(),
+        timestamp: Timestamp::now(),
+        delivered: false,
+        fire_and_forget: mode == DeliveryMode::FireAndForget,
+        announcement: false,
+        reply_to: None,
+    };
+    let chat = PeerMessage::Chat {
+        id: message.id.clone(),
+        sender_id: PeerId::new(&config.peer_id),
+        sender_name: config.display_name.clone(),
+        content: message.content.clone(),
+        timestamp: message.timestamp,
+        announcement: message.announcement,
+        reply_to: message.reply_to.clone(),
+    };
+
+    // Journaled first, like the daemon does, so a failed send still shows
+    // up in the conversation as undelivered, and one cut short is replayed
+    // when the daemon next starts
+    db.save_pending(&message)?;
+    let sent = client::send_to_any(&peer.addresses, &chat, mode).await;
+    if sent.is_ok() && mode == DeliveryMode::AckRequired {
+        db.mark_delivered(&message.id)?;
+    } else {
+        db.clear_pending(&message.id)?;
+    }
+    sent.with_context(|| format!("could not reach {} (daemon not running)", peer.display_name))?;
+    println!("Sent to {} directly (daemon not running)", peer.display_name);
+    Ok(())
+}
+
+/// Finds a peer by peer ID or by display name, ignoring case and
+/// surrounding spaces.
+pub(crate) fn find_peer<'a>(peers: &'a [PeerInfo], to: &str) -> Result<&'a PeerInfo> {
+    if let Some(peer) = peers.iter().find(|p| p.id.as_str() == to) {
+        return Ok(peer);
+    }
+    let wanted = to.trim().to_lowercase();
+    let matches: Vec<&PeerInfo> = peers
+        .iter()
+        .filter(|p| p.display_name.to_lowercase() == wanted)
+        .collect();
+    match matches.as_slice() {
+        [peer] => Ok(peer),
+        [] => {
+            let known: Vec<&str> = peers.iter().map(|p| p.display_name.as_str()).collect();
+            bail!("no peer named '{to}' (known: {})", known.join(", "))
+        }
+        _ => bail!("several peers are named '{to}'; use the peer ID instead"),
+    }
+}
+
+/// A byte stream to the daemon, whatever the transport.
+trait IpcStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> IpcStream for S {}
+
+/// A line-based IPC connection to a running daemon.
+pub(crate) struct DaemonConnection {
+    reader: BufReader<ReadHalf<Box<dyn IpcStream>>>,
+    writer: WriteHalf<Box<dyn IpcStream>>,
+}
+
+impl DaemonConnection {
+    /// `None` if no daemon is listening (no socket, a stale one, or no
+    /// pipe).
+    pub(crate) async fn connect(endpoint: &IpcEndpoint) -> Option<Self> {
+        let stream: Box<dyn IpcStream> = match endpoint {
+            #[cfg(unix)]
+            IpcEndpoint::Unix(path) => Box::new(tokio::net::UnixStream::connect(path).await.ok()?),
+            #[cfg(windows)]
+            IpcEndpoint::NamedPipe(name) => {
+                Box::new(tokio::net::windows::named_pipe::ClientOptions::new().open(name).ok()?)
+            }
+            IpcEndpoint::Tcp(addr) => Box::new(tokio::net::TcpStream::connect(addr).await.ok()?),
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        };
+        let (reader, writer) = tokio::io::split(stream);
+        Some(Self {
+            reader: BufReader::new(reader),
+            writer,
+        })
+    }
+
+    /// Sends `request` and reads its response. Nothing is subscribed, so
+    /// the next line is always the answer.
+    async fn request(&mut self, request: &ClientRequest) -> Result<ServerMessage> {
+        self.writer.write_all(ipc::encode_request(request)?.as_bytes()).await?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            bail!("daemon closed the connection");
+        }
+        Ok(ipc::decode_response(&line)?)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(id: &str, name: &str) -> PeerInfo {
+        PeerInfo {
+            id: PeerId::new(id),
+            display_name: name.to_string(),
+            addresses: vec!["127.0.0.1:1".to_string()],
+            last_seen_at: Timestamp::now(),
+            online: false,
+            do_not_disturb: false,
+            away: false,
+            avatar: None,
+            accent_color: None,
+            capabilities: Vec::new(),
+            notifications: Default::default(),
+            interface_addresses: Default::default(),
+        }
+    }
+
+    #[test]
+    fn peers_are_found_by_name_or_id() {
+        let peers = vec![peer("p1", "PC-Sala"), peer("p2", "Abuela"), peer("p3", "abuela")];
+        assert_eq!(find_peer(&peers, " pc-sala ").unwrap().id, PeerId::new("p1"));
+        assert_eq!(find_peer(&peers, "p2").unwrap().display_name, "Abuela");
+        assert!(find_peer(&peers, "Abuela").is_err(), "ambiguous name");
+        assert!(find_peer(&peers, "Cocina").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn only_a_listening_socket_counts_as_a_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("familycom.sock");
+        let endpoint = IpcEndpoint::Unix(path.clone());
+        assert!(DaemonConnection::connect(&endpoint).await.is_none(), "no socket");
+
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        assert!(DaemonConnection::connect(&endpoint).await.is_some());
+
+        // Left behind by a crash: the file exists but nobody accepts
+        drop(listener);
+        assert!(path.exists());
+        assert!(DaemonConnection::connect(&endpoint).await.is_none(), "stale socket");
+    }
+}
@@ -9,16 +9,51 @@
 //! 1. Peer connects via TCP
 //! 2. Peer sends a `PeerMessage::Chat` frame
 //! 3. We respond with a `PeerMessage::Ack` frame
-//! 4. Connection may stay open for more messages or be closed
+//! 4. Only if the ACK was written do we forward the message to the daemon
+//! 5. Connection may stay open for more messages or be closed
+//!
+//! Every copy of a chat is ACKed, including a retry of one we already
+//! have: the sender retries because it lost our first ACK. The daemon
+//! recognizes the retry by its `MessageId` and drops it.
+//!
+//! History sync (`HistoryPush`, `HistoryQuery`) is the exception: only the
+//! daemon knows whether it is an archive and what it has stored, so those
+//! are forwarded first and the daemon's answer is written back. No answer
+//! closes the connection.
 //!
 //! Each incoming connection is handled in its own tokio task, so multiple
 //! peers can send messages simultaneously without blocking each other.
+//!
+//! # Shutdown
+//!
+//! When the shutdown token is cancelled, the accept loop stops accepting and
+//! connection handlers stop waiting for new frames. A frame that is already
+//! being processed still gets its ACK and is forwarded, so a peer never sees
+//! an ACK for a message we then throw away.
+//!
+//! # Untrusted Networks
+//!
+//! While the `TrustGate` is closed (see `network`), new connections are
+//! dropped right after accepting them.
+//!
+//! # Rate Limits
+//!
+//! Each source IP has a budget of open connections, new connections per
+//! second and frames per second (see `ratelimit`). Connections over it are
+//! dropped right after accepting them; frames over it are read more
+//! slowly.
 
+use crate::network::TrustGate;
+use crate::ratelimit::{ConnectionPermit, RateLimiter, RateLimits};
+use crate::supervisor;
 use familycom_core::protocol::{self, PeerMessage, ProtocolError};
 use std::net::SocketAddr;
 use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 /// Errors that can occur in the message server.
@@ -41,6 +76,9 @@ pub struct IncomingMessage {
     pub message: PeerMessage,
     /// The remote address of the peer who sent it.
     pub from_addr: SocketAddr,
+    /// For messages the daemon answers itself (history sync): where to
+    /// send the answer. Dropping it closes the connection unanswered.
+    pub reply: Option<oneshot::Sender<PeerMessage>>,
 }
 
 /// TCP server that accepts connections from other FamilyCom peers.
@@ -49,6 +87,10 @@ pub struct MessageServer {
     listener: TcpListener,
     /// The local address we're bound to (useful for logging and mDNS registration).
     local_addr: SocketAddr,
+    /// Whether peers may connect right now (closed on untrusted networks).
+    gate: TrustGate,
+    /// Per-source-IP connection and frame budgets.
+    limiter: RateLimiter,
 }
 
 impl MessageServer {
@@ -72,9 +114,24 @@ impl MessageServer {
         Ok(Self {
             listener,
             local_addr,
+            gate: TrustGate::open(),
+            limiter: RateLimiter::new(RateLimits::default()),
         })
     }
 
+    /// Makes the server refuse connections while `gate` is closed.
+    pub fn with_trust_gate(mut self, gate: TrustGate) -> Self {
+        self.gate = gate;
+        self
+    }
+
+    /// Replaces the default per-source-IP rate limits.
+    #[allow(dead_code)]
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.limiter = RateLimiter::new(limits);
+        self
+    }
+
     /// Returns the local address this server is bound to.
     ///
     /// Particularly useful when binding to port 0 (auto-assign) — this
@@ -91,63 +148,131 @@ impl MessageServer {
 
     /// Runs the accept loop, spawning a handler task for each incoming connection.
     ///
-    /// Received messages are sent through the returned channel. This method
-    /// runs forever (until the server is dropped or an unrecoverable error occurs).
+    /// Received messages are sent through `message_tx`. This method runs
+    /// until `shutdown` is cancelled, then drains in-flight connections
+    /// (see `supervisor::drain`) and returns. It borrows the server so the
+    /// supervisor can restart the loop on the same listener if it ever
+    /// dies, and so tests can start and stop it repeatedly.
     ///
     /// # Arguments
     ///
     /// * `message_tx` - Channel sender for forwarding received messages to the daemon.
-    pub async fn accept_loop(self, message_tx: mpsc::Sender<IncomingMessage>) {
+    /// * `shutdown` - Cancelled when the daemon is stopping.
+    pub async fn accept_loop(
+        &self,
+        message_tx: mpsc::Sender<IncomingMessage>,
+        shutdown: CancellationToken,
+    ) {
+        let mut connections = JoinSet::new();
         loop {
-            match self.listener.accept().await {
-                Ok((stream, peer_addr)) => {
-                    debug!(peer = %peer_addr, "accepted TCP connection");
-
-                    // Handle each connection in its own task so one slow peer
-                    // doesn't block others.
-                    let tx = message_tx.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = handle_connection(stream, peer_addr, tx).await {
-                            // ConnectionClosed is normal — peer just disconnected
-                            match &e {
-                                ProtocolError::ConnectionClosed => {
-                                    debug!(peer = %peer_addr, "peer disconnected");
-                                }
-                                _ => {
-                                    warn!(peer = %peer_addr, error = %e, "connection error");
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+
+                // Reap finished connection tasks so the set doesn't grow forever
+                Some(_) = connections.join_next() => {}
+
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, peer_addr)) if !self.gate.is_open() => {
+                        debug!(peer = %peer_addr, "refusing TCP connection on untrusted network");
+                        drop(stream);
+                    }
+                    Ok((stream, peer_addr)) => {
+                        // Over its budget: dropped (logged by the limiter)
+                        let Ok(permit) = self.limiter.admit(peer_addr.ip()) else {
+                            drop(stream);
+                            continue;
+                        };
+                        debug!(peer = %peer_addr, "accepted TCP connection");
+
+                        // Handle each connection in its own task so one slow peer
+                        // doesn't block others.
+                        let tx = message_tx.clone();
+                        let shutdown = shutdown.clone();
+                        connections.spawn(async move {
+                            if let Err(e) = handle_connection(stream, peer_addr, permit, tx, shutdown).await {
+                                // ConnectionClosed is normal — peer just disconnected
+                                match &e {
+                                    ProtocolError::ConnectionClosed => {
+                                        debug!(peer = %peer_addr, "peer disconnected");
+                                    }
+                                    _ => {
+                                        warn!(peer = %peer_addr, error = %e, "connection error");
+                                    }
                                 }
                             }
-                        }
-                    });
-                }
-                Err(e) => {
-                    // Accept errors are usually transient (too many open files, etc.)
-                    // Log and continue rather than crashing.
-                    error!(error = %e, "failed to accept TCP connection");
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                }
+                        });
+                    }
+                    Err(e) => {
+                        // Accept errors are usually transient (too many open files, etc.)
+                        // Log and continue rather than crashing.
+                        error!(error = %e, "failed to accept TCP connection");
+                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                    }
+                },
             }
         }
+
+        debug!(in_flight = connections.len(), "TCP server stopping, draining connections");
+        supervisor::drain(&mut connections, "tcp_server").await;
     }
 }
 
 /// Handles a single TCP connection from a peer.
 ///
-/// Reads messages in a loop until the peer disconnects or an error occurs.
-/// For each `Chat` message received, sends back an `Ack`.
+/// Splits the stream and delegates to `serve_peer`, which contains the
+/// actual read/ACK/forward loop.
 async fn handle_connection(
     mut stream: TcpStream,
     peer_addr: SocketAddr,
+    permit: ConnectionPermit,
     message_tx: mpsc::Sender<IncomingMessage>,
+    shutdown: CancellationToken,
 ) -> Result<(), ProtocolError> {
     // Split the stream so we can read and write independently.
     // This is important because we need to send Acks while potentially
     // receiving more messages.
     let (mut reader, mut writer) = stream.split();
+    serve_peer(&mut reader, &mut writer, peer_addr, &permit, message_tx, shutdown).await
+}
 
+/// Reads messages from a peer in a loop until it disconnects or an error occurs.
+///
+/// For each `Chat` message received, sends back an `Ack` **before** forwarding
+/// the message to the daemon. If the ACK can't be written (half-open socket,
+/// peer reset), the message is dropped and the connection is closed: the
+/// sender never saw our ACK, so it will retry, and forwarding now would
+/// store the same message twice.
+///
+/// Returns `Ok(())` between frames once `shutdown` is cancelled; a frame
+/// that has started arriving is read and handled to completion first.
+///
+/// Each frame is charged to `permit`, which holds it back while the source
+/// is over its frame budget.
+///
+/// Generic over the reader/writer so tests can drive it with in-memory pipes.
+async fn serve_peer<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    peer_addr: SocketAddr,
+    permit: &ConnectionPermit,
+    message_tx: mpsc::Sender<IncomingMessage>,
+    shutdown: CancellationToken,
+) -> Result<(), ProtocolError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
     loop {
-        // Read the next message from the peer
-        let msg = protocol::read_message(&mut reader).await?;
+        // Read the next message from the peer, unless we're shutting down
+        let msg = tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => {
+                debug!(peer = %peer_addr, "closing connection for shutdown");
+                return Ok(());
+            }
+            msg = protocol::read_message(reader) => msg?,
+        };
+        permit.pace_frame().await;
 
         match &msg {
             PeerMessage::Chat { id, sender_name, .. } => {
@@ -158,18 +283,43 @@ async fn handle_connection(
                     "received chat message"
                 );
 
-                // Send acknowledgment back to the sender
+                // Send acknowledgment back to the sender. Only a successful
+                // ACK lets the message through to the daemon.
                 let ack = PeerMessage::Ack {
                     message_id: id.clone(),
                 };
-                if let Err(e) = protocol::write_message(&mut writer, &ack).await {
-                    warn!(peer = %peer_addr, error = %e, "failed to send ACK");
+                if let Err(e) = protocol::write_message(writer, &ack).await {
+                    warn!(
+                        message_id = %id,
+                        peer = %peer_addr,
+                        error = %e,
+                        "failed to send ACK, dropping message (sender will retry)"
+                    );
+                    return Err(e);
+                }
+            }
+
+            PeerMessage::Retract { message_id, .. } => {
+                debug!(message_id = %message_id, peer = %peer_addr, "received retraction");
+
+                // Same rule as chat messages: no ACK, no forwarding
+                let ack = PeerMessage::Ack {
+                    message_id: message_id.clone(),
+                };
+                if let Err(e) = protocol::write_message(writer, &ack).await {
+                    warn!(
+                        message_id = %message_id,
+                        peer = %peer_addr,
+                        error = %e,
+                        "failed to ACK retraction, dropping it (sender will retry)"
+                    );
+                    return Err(e);
                 }
             }
 
             PeerMessage::Ping => {
                 debug!(peer = %peer_addr, "received ping, sending pong");
-                if let Err(e) = protocol::write_message(&mut writer, &PeerMessage::Pong).await {
+                if let Err(e) = protocol::write_message(writer, &PeerMessage::Pong).await {
                     warn!(peer = %peer_addr, error = %e, "failed to send pong");
                 }
                 // Don't forward pings to the daemon — they're just keepalive
@@ -184,12 +334,47 @@ async fn handle_connection(
             PeerMessage::Ack { message_id } => {
                 debug!(message_id = %message_id, peer = %peer_addr, "received ack");
             }
+
+            PeerMessage::Typing { .. } => {
+                debug!(peer = %peer_addr, "received typing notice");
+            }
+
+            PeerMessage::Read { up_to, .. } => {
+                debug!(peer = %peer_addr, up_to = up_to.as_millis(), "received read receipt");
+            }
+
+            // The daemon answers these: the ACK for a push must wait until
+            // the batch is stored, since the sender then drops its copies
+            PeerMessage::HistoryPush { .. } | PeerMessage::HistoryQuery { .. } => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let incoming = IncomingMessage {
+                    message: msg,
+                    from_addr: peer_addr,
+                    reply: Some(reply_tx),
+                };
+                if message_tx.send(incoming).await.is_err() {
+                    debug!("message channel closed, stopping connection handler");
+                    break;
+                }
+                let Ok(answer) = reply_rx.await else {
+                    debug!(peer = %peer_addr, "history sync not answered, closing connection");
+                    return Ok(());
+                };
+                protocol::write_message(writer, &answer).await?;
+                continue;
+            }
+
+            PeerMessage::HistoryPage { .. } => {
+                debug!(peer = %peer_addr, "ignoring unrequested history page");
+                continue;
+            }
         }
 
         // Forward the message to the daemon's main loop for processing
         let incoming = IncomingMessage {
             message: msg,
             from_addr: peer_addr,
+            reply: None,
         };
         if message_tx.send(incoming).await.is_err() {
             debug!("message channel closed, stopping connection handler");
@@ -199,3 +384,247 @@ async fn handle_connection(
 
     Ok(())
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use familycom_core::types::{MessageId, PeerId, Timestamp};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// A writer that fails every write, simulating a half-open TCP socket
+    /// where the peer has gone away but we haven't noticed yet.
+    struct BrokenWriter;
+
+    impl AsyncWrite for BrokenWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn test_addr() -> SocketAddr {
+        "192.168.1.20:9876".parse().unwrap()
+    }
+
+    fn permit() -> ConnectionPermit {
+        RateLimiter::new(RateLimits::default()).admit(test_addr().ip()).unwrap()
+    }
+
+    fn chat(id: &str) -> PeerMessage {
+        PeerMessage::Chat {
+            id: MessageId::new(id),
+            sender_id: PeerId::new("peer-1"),
+            sender_name: "PC-Sala".to_string(),
+            content: "Hola!".to_string(),
+            timestamp: Timestamp::from_millis(1000),
+            announcement: false,
+            reply_to: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_is_acked_then_forwarded() {
+        let (mut peer_side, mut our_side) = tokio::io::duplex(4096);
+        let (tx, mut rx) = mpsc::channel(4);
+
+        protocol::write_message(&mut peer_side, &chat("m1")).await.unwrap();
+
+        let (mut reader, mut writer) = tokio::io::split(&mut our_side);
+        let permit = permit();
+        let handler = serve_peer(&mut reader, &mut writer, test_addr(), &permit, tx, CancellationToken::new());
+
+        // The handler loops until the peer disconnects, so read the ACK and
+        // then drop the peer side to end it.
+        let peer = async {
+            let ack = protocol::read_message(&mut peer_side).await.unwrap();
+            drop(peer_side);
+            ack
+        };
+        let (result, ack) = tokio::join!(handler, peer);
+
+        assert!(matches!(result, Err(ProtocolError::ConnectionClosed)));
+        assert_eq!(
+            ack,
+            PeerMessage::Ack {
+                message_id: MessageId::new("m1")
+            }
+        );
+        let forwarded = rx.recv().await.expect("chat should be forwarded");
+        assert_eq!(forwarded.message, chat("m1"));
+    }
+
+    #[tokio::test]
+    async fn failed_ack_write_drops_message() {
+        let (mut peer_side, mut reader) = tokio::io::duplex(4096);
+        let (tx, mut rx) = mpsc::channel(4);
+
+        protocol::write_message(&mut peer_side, &chat("m1")).await.unwrap();
+
+        let permit = permit();
+        let result = serve_peer(&mut reader, &mut BrokenWriter, test_addr(), &permit, tx, CancellationToken::new()).await;
+
+        assert!(matches!(result, Err(ProtocolError::Io(_))));
+        // The sender never saw an ACK and will retry, so nothing may be
+        // forwarded — otherwise the retry would create a duplicate.
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_pong_write_keeps_connection() {
+        let (mut peer_side, mut reader) = tokio::io::duplex(4096);
+        let (tx, mut rx) = mpsc::channel(4);
+
+        protocol::write_message(&mut peer_side, &PeerMessage::Ping).await.unwrap();
+        protocol::write_message(
+            &mut peer_side,
+            &PeerMessage::Ack {
+                message_id: MessageId::new("m9"),
+            },
+        )
+        .await
+        .unwrap();
+        drop(peer_side);
+
+        let permit = permit();
+        let result = serve_peer(&mut reader, &mut BrokenWriter, test_addr(), &permit, tx, CancellationToken::new()).await;
+
+        // A lost pong is harmless; later frames are still processed.
+        assert!(matches!(result, Err(ProtocolError::ConnectionClosed)));
+        let forwarded = rx.recv().await.expect("ack should be forwarded");
+        assert!(matches!(forwarded.message, PeerMessage::Ack { .. }));
+    }
+
+    #[tokio::test]
+    async fn history_query_gets_the_daemons_answer() {
+        let (mut peer_side, mut our_side) = tokio::io::duplex(4096);
+        let (tx, mut rx) = mpsc::channel::<IncomingMessage>(4);
+        let query = PeerMessage::HistoryQuery {
+            owner_id: PeerId::new("peer-1"),
+            peer_id: PeerId::new("peer-2"),
+            before: None,
+            limit: 10,
+        };
+        protocol::write_message(&mut peer_side, &query).await.unwrap();
+        protocol::write_message(&mut peer_side, &query).await.unwrap();
+
+        let daemon = async {
+            // Answer the first query, ignore the second (not an archive)
+            let first = rx.recv().await.unwrap();
+            assert_eq!(first.message, query);
+            first
+                .reply
+                .unwrap()
+                .send(PeerMessage::HistoryPage { messages: Vec::new() })
+                .unwrap();
+            drop(rx.recv().await.unwrap());
+        };
+        let result = {
+            let (mut reader, mut writer) = tokio::io::split(&mut our_side);
+            let permit = permit();
+            let handler = serve_peer(&mut reader, &mut writer, test_addr(), &permit, tx, CancellationToken::new());
+            let (result, ()) = tokio::join!(handler, daemon);
+            result
+        };
+
+        // The unanswered query closed the connection cleanly
+        assert!(result.is_ok());
+        drop(our_side);
+        assert_eq!(
+            protocol::read_message(&mut peer_side).await.unwrap(),
+            PeerMessage::HistoryPage { messages: Vec::new() }
+        );
+        assert!(matches!(
+            protocol::read_message(&mut peer_side).await,
+            Err(ProtocolError::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn idle_connection_closes_on_shutdown() {
+        // The peer keeps the connection open but sends nothing
+        let (_peer_side, mut our_side) = tokio::io::duplex(4096);
+        let (tx, _rx) = mpsc::channel(4);
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+
+        let (mut reader, mut writer) = tokio::io::split(&mut our_side);
+        let permit = permit();
+        let result = serve_peer(&mut reader, &mut writer, test_addr(), &permit, tx, shutdown).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn accept_loop_survives_repeated_start_stop() {
+        let server = MessageServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr();
+
+        for round in 0..3 {
+            let (tx, mut rx) = mpsc::channel(4);
+            let shutdown = CancellationToken::new();
+            let running = server.accept_loop(tx, shutdown.clone());
+
+            let client = async {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                let id = format!("m{round}");
+                protocol::write_message(&mut stream, &chat(&id)).await.unwrap();
+                let ack = protocol::read_message(&mut stream).await.unwrap();
+                assert_eq!(ack, PeerMessage::Ack { message_id: MessageId::new(&id) });
+                let forwarded = rx.recv().await.unwrap();
+                assert_eq!(forwarded.message, chat(&id));
+                // Stop while the connection is still open; it must drain
+                shutdown.cancel();
+                stream
+            };
+
+            let (_, _stream) = tokio::join!(running, client);
+        }
+    }
+
+    #[tokio::test]
+    async fn connections_over_the_limit_are_dropped() {
+        let server = MessageServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .with_rate_limits(RateLimits {
+                max_connections: 1,
+                ..RateLimits::default()
+            });
+        let addr = server.local_addr();
+        let (tx, mut rx) = mpsc::channel(4);
+        let shutdown = CancellationToken::new();
+        let running = server.accept_loop(tx, shutdown.clone());
+
+        let clients = async {
+            let mut first = TcpStream::connect(addr).await.unwrap();
+            protocol::write_message(&mut first, &chat("m1")).await.unwrap();
+            protocol::read_message(&mut first).await.unwrap();
+            rx.recv().await.unwrap();
+
+            // Same address, while the first connection is still open
+            let mut second = TcpStream::connect(addr).await.unwrap();
+            let _ = protocol::write_message(&mut second, &chat("m2")).await;
+            assert!(protocol::read_message(&mut second).await.is_err());
+            shutdown.cancel();
+            first
+        };
+
+        let (_, _first) = tokio::join!(running, clients);
+        assert!(rx.try_recv().is_err(), "the refused message must not be forwarded");
+    }
+}
@@ -0,0 +1,422 @@
+//! Interactive setup wizard (`familycomd setup`).
+//!
+//! A small ratatui form that walks the user through the settings that
+//! matter on a new machine: display name, TCP port, network interface,
+//! autostart, and desktop notifications. The result is validated before
+//! being written to `config.toml`.
+//!
+//! ```text
+//! +-- FamilyCom - Configuracion ----------------------+
+//! | > Nombre:          PC-Sala                         |
+//! |   Puerto TCP:      0 (automatico)                  |
+//! |   Interfaz:        < auto >                        |
+//! |   Inicio auto:     [x]                             |
+//! |   Notificaciones:  [x]                             |
+//! |                                                    |
+//! |   [ Guardar ]                                      |
+//! +----------------------------------------------------+
+//! ```
+//!
+//! # Why a wizard?
+//!
+//! The first-run prompt in `main.rs` only asks for a name, and when the
+//! daemon is started by autostart (no terminal) it silently falls back to
+//! the hostname. Running `familycomd setup` once from a terminal gives the
+//! user a chance to review everything in one place.
+
+use crate::autostart;
+use anyhow::{Context, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use familycom_core::config::{AppConfig, NetworkInterfaces};
+use familycom_core::types::DisplayName;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use std::io::stdout;
+use std::path::Path;
+
+/// The editable fields of the form, in display order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Name,
+    Port,
+    Interface,
+    Autostart,
+    Notifications,
+    Save,
+}
+
+impl Field {
+    const ALL: [Field; 6] = [
+        Field::Name,
+        Field::Port,
+        Field::Interface,
+        Field::Autostart,
+        Field::Notifications,
+        Field::Save,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Field::Name => "Nombre:",
+            Field::Port => "Puerto TCP:",
+            Field::Interface => "Interfaz:",
+            Field::Autostart => "Inicio auto:",
+            Field::Notifications => "Notificaciones:",
+            Field::Save => "",
+        }
+    }
+}
+
+/// The wizard's in-progress state.
+struct SetupForm {
+    name: String,
+    port: String,
+    /// Candidate interfaces. Index 0 is always "auto" (detect default route)
+    /// and index 1 "all" (every LAN interface).
+    interfaces: Vec<String>,
+    interface_idx: usize,
+    autostart: bool,
+    notifications: bool,
+    selected: usize,
+    /// Validation error from the last save attempt.
+    error: Option<String>,
+}
+
+/// What the user decided when the wizard closed.
+enum Outcome {
+    Save,
+    Cancel,
+}
+
+impl SetupForm {
+    /// Builds the form pre-filled from an existing config (or defaults).
+    fn new(existing: Option<&AppConfig>, default_name: String, autostart: bool) -> Self {
+        let mut interfaces = vec!["auto".to_string(), NetworkInterfaces::ALL.to_string()];
+        interfaces.extend(
+            netdev::get_interfaces()
+                .into_iter()
+                .filter(|iface| !iface.is_loopback() && !iface.ipv4.is_empty())
+                .map(|iface| iface.name),
+        );
+
+        let current_iface = existing.and_then(|c| c.network_interface.as_ref());
+        let interface_idx = match current_iface.map(ToString::to_string) {
+            Some(name) => match interfaces.iter().position(|i| *i == name) {
+                Some(idx) => idx,
+                None => {
+                    // Keep a configured interface (or list of them) even if
+                    // it's currently down
+                    interfaces.push(name);
+                    interfaces.len() - 1
+                }
+            },
+            None => 0,
+        };
+
+        Self {
+            name: existing.map(|c| c.display_name.clone()).unwrap_or(default_name),
+            port: existing.map(|c| c.tcp_port).unwrap_or(0).to_string(),
+            interfaces,
+            interface_idx,
+            autostart,
+            notifications: existing.map(|c| c.notifications_enabled).unwrap_or(true),
+            selected: 0,
+            error: None,
+        }
+    }
+
+    fn field(&self) -> Field {
+        Field::ALL[self.selected]
+    }
+
+    /// Handles a key press. Returns `Some` when the wizard should close.
+    fn handle_key(&mut self, code: KeyCode) -> Option<Outcome> {
+        match code {
+            KeyCode::Esc => return Some(Outcome::Cancel),
+            KeyCode::Up | KeyCode::BackTab => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Tab => {
+                self.selected = (self.selected + 1).min(Field::ALL.len() - 1);
+            }
+            KeyCode::Enter => {
+                if self.field() == Field::Save {
+                    match self.validate() {
+                        Ok(()) => return Some(Outcome::Save),
+                        Err(e) => self.error = Some(e),
+                    }
+                } else {
+                    self.selected = (self.selected + 1).min(Field::ALL.len() - 1);
+                }
+            }
+            KeyCode::Left | KeyCode::Right | KeyCode::Char(' ')
+                if matches!(
+                    self.field(),
+                    Field::Interface | Field::Autostart | Field::Notifications
+                ) =>
+            {
+                self.toggle(code == KeyCode::Left);
+            }
+            KeyCode::Backspace => match self.field() {
+                Field::Name => {
+                    self.name.pop();
+                }
+                Field::Port => {
+                    self.port.pop();
+                }
+                _ => {}
+            },
+            KeyCode::Char(c) => match self.field() {
+                Field::Name => self.name.push(c),
+                Field::Port if c.is_ascii_digit() => self.port.push(c),
+                _ => {}
+            },
+            _ => {}
+        }
+        None
+    }
+
+    /// Flips a boolean field or cycles the interface choice.
+    fn toggle(&mut self, backwards: bool) {
+        match self.field() {
+            Field::Interface => {
+                let n = self.interfaces.len();
+                self.interface_idx = if backwards {
+                    (self.interface_idx + n - 1) % n
+                } else {
+                    (self.interface_idx + 1) % n
+                };
+            }
+            Field::Autostart => self.autostart = !self.autostart,
+            Field::Notifications => self.notifications = !self.notifications,
+            _ => {}
+        }
+    }
+
+    /// Checks the form using the same rules the daemon enforces at runtime.
+    fn validate(&self) -> Result<(), String> {
+        DisplayName::new(self.name.as_str()).map_err(|e| format!("Nombre invalido: {e}"))?;
+        self.port
+            .parse::<u16>()
+            .map_err(|_| "El puerto debe ser un numero entre 0 y 65535".to_string())?;
+        Ok(())
+    }
+
+    /// Applies the form to a config, creating a fresh one on first run.
+    fn apply(&self, existing: Option<AppConfig>) -> AppConfig {
+        let mut config = existing.unwrap_or_else(|| AppConfig::new_first_run(&self.name));
+        config.display_name = self.name.trim().to_string();
+        config.tcp_port = self.port.parse().unwrap_or(0);
+        config.network_interface = match self.interface_idx {
+            0 => None,
+            idx => Some(NetworkInterfaces::parse(&self.interfaces[idx])),
+        };
+        config.notifications_enabled = self.notifications;
+        config
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let area = frame.area();
+        let block = Block::default()
+            .title(" FamilyCom - Configuracion ")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan));
+
+        let mut lines: Vec<Line> = Vec::new();
+        for (idx, field) in Field::ALL.iter().enumerate() {
+            let marker = if idx == self.selected { "> " } else { "  " };
+            let value = match field {
+                Field::Name => self.name.clone(),
+                Field::Port if self.port == "0" => "0 (automatico)".to_string(),
+                Field::Port => self.port.clone(),
+                Field::Interface => format!("< {} >", self.interfaces[self.interface_idx]),
+                Field::Autostart => checkbox(self.autostart),
+                Field::Notifications => checkbox(self.notifications),
+                Field::Save => {
+                    lines.push(Line::from(""));
+                    "[ Guardar ]".to_string()
+                }
+            };
+            let style = if idx == self.selected {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            lines.push(Line::from(vec![
+                Span::styled(marker, style),
+                Span::styled(format!("{:<17}", field.label()), Style::default().fg(Color::DarkGray)),
+                Span::styled(value, style),
+            ]));
+        }
+
+        lines.push(Line::from(""));
+        if let Some(err) = &self.error {
+            lines.push(Line::from(Span::styled(err.as_str(), Style::default().fg(Color::Red))));
+        }
+        lines.push(Line::from(Span::styled(
+            "Arriba/Abajo: moverse | Espacio/Izq/Der: cambiar | Enter: guardar | Esc: cancelar",
+            Style::default().fg(Color::DarkGray),
+        )));
+
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+fn checkbox(on: bool) -> String {
+    if on { "[x]" } else { "[ ]" }.to_string()
+}
+
+/// Runs the setup wizard and writes the resulting config to `config_path`.
+///
+/// Also installs or removes autostart to match the user's choice.
+/// Cancelling with Esc leaves everything untouched.
+pub fn run(config_path: &Path, default_name: String) -> Result<()> {
+    if !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+        anyhow::bail!("`familycomd setup` needs an interactive terminal");
+    }
+
+    let existing = AppConfig::load_from(config_path)?;
+    let autostart_installed = autostart::is_installed().unwrap_or(false);
+    let mut form = SetupForm::new(existing.as_ref(), default_name, autostart_installed);
+
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let outcome = run_form(&mut form);
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+
+    match outcome? {
+        Outcome::Cancel => {
+            println!("Configuracion cancelada, no se hicieron cambios.");
+        }
+        Outcome::Save => {
+            let config = form.apply(existing);
+            config
+                .save_to(config_path)
+                .with_context(|| format!("failed to write {}", config_path.display()))?;
+            println!("Configuracion guardada en {}", config_path.display());
+
+            if form.autostart && !autostart_installed {
+                autostart::install(false, false)?;
+            } else if !form.autostart && autostart_installed {
+                autostart::uninstall(false)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Draw/input loop. Kept separate so the terminal is always restored by
+/// the caller, even when this returns an error.
+fn run_form(form: &mut SetupForm) -> Result<Outcome> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    loop {
+        terminal.draw(|frame| form.render(frame))?;
+        if let Event::Key(key) = event::read()? {
+            // Ignore key release events (reported on Windows)
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(outcome) = form.handle_key(key.code) {
+                return Ok(outcome);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form() -> SetupForm {
+        SetupForm::new(None, "PC-Sala".to_string(), false)
+    }
+
+    #[test]
+    fn empty_or_invalid_name_is_rejected() {
+        let mut form = form();
+        form.name = "   ".to_string();
+        assert!(form.validate().unwrap_err().starts_with("Nombre invalido"));
+        form.name = "x".repeat(100);
+        assert!(form.validate().is_err());
+
+        form.name = "PC-Sala".to_string();
+        form.port = "70000".to_string();
+        assert!(form.validate().unwrap_err().contains("puerto"));
+    }
+
+    #[test]
+    fn saving_an_invalid_form_shows_the_error_instead() {
+        let mut form = form();
+        form.name.clear();
+        form.selected = Field::ALL.len() - 1;
+        assert!(form.handle_key(KeyCode::Enter).is_none());
+        assert!(form.error.is_some());
+
+        form.name = "Cocina".to_string();
+        assert!(matches!(form.handle_key(KeyCode::Enter), Some(Outcome::Save)));
+    }
+
+    #[test]
+    fn valid_input_is_applied_to_the_config() {
+        let mut form = form();
+        form.name = "  Cocina ".to_string();
+        form.port = "5000".to_string();
+        form.interface_idx = 1;
+        form.notifications = false;
+
+        let mut existing = AppConfig::new_first_run("PC-Sala");
+        existing.peer_id = "peer-1".to_string();
+        let config = form.apply(Some(existing));
+        assert_eq!(config.peer_id, "peer-1");
+        assert_eq!(config.display_name, "Cocina");
+        assert_eq!(config.tcp_port, 5000);
+        assert_eq!(config.network_interface, Some(NetworkInterfaces::parse(NetworkInterfaces::ALL)));
+        assert!(!config.notifications_enabled);
+
+        form.interface_idx = 0;
+        assert_eq!(form.apply(None).network_interface, None);
+    }
+
+    #[test]
+    fn keys_move_between_fields_and_edit_them() {
+        let mut form = form();
+        assert_eq!(form.field(), Field::Name);
+        form.handle_key(KeyCode::Backspace);
+        form.handle_key(KeyCode::Char('!'));
+        assert_eq!(form.name, "PC-Sal!");
+
+        // Enter on a field moves to the next one
+        form.handle_key(KeyCode::Enter);
+        assert_eq!(form.field(), Field::Port);
+        form.handle_key(KeyCode::Char('a'));
+        form.handle_key(KeyCode::Char('7'));
+        assert_eq!(form.port, "07");
+
+        form.handle_key(KeyCode::Tab);
+        assert_eq!(form.field(), Field::Interface);
+        form.handle_key(KeyCode::Right);
+        assert_eq!(form.interface_idx, 1);
+        form.handle_key(KeyCode::Left);
+        form.handle_key(KeyCode::Left);
+        assert_eq!(form.interface_idx, form.interfaces.len() - 1);
+
+        form.handle_key(KeyCode::Down);
+        form.handle_key(KeyCode::Char(' '));
+        assert!(form.autostart);
+
+        form.handle_key(KeyCode::BackTab);
+        form.handle_key(KeyCode::Up);
+        form.handle_key(KeyCode::Up);
+        form.handle_key(KeyCode::Up);
+        assert_eq!(form.field(), Field::Name);
+
+        for _ in 0..10 {
+            form.handle_key(KeyCode::Down);
+        }
+        assert_eq!(form.field(), Field::Save);
+        assert!(matches!(form.handle_key(KeyCode::Esc), Some(Outcome::Cancel)));
+    }
+}
@@ -0,0 +1,355 @@
+//! Supervision of long-running daemon subsystems.
+//!
+//! The mDNS network watcher, TCP accept loop, IPC accept loop, and
+//! notification handler each run in their own tokio task. If one of them panics or returns, nothing else
+//! in the daemon notices — the process keeps running, but e.g. no longer
+//! accepts TUI connections. This module wraps each subsystem in a
+//! supervisor task that:
+//!
+//! 1. Logs when the subsystem exits or panics
+//! 2. Restarts it after an exponential backoff (1s, 2s, 4s, ... up to 60s)
+//! 3. Records its health in a shared `HealthRegistry`, which `DaemonApp`
+//!    reports to TUI clients via `GetStatus`, and pushes a
+//!    `SubsystemRestarted` event to them on every restart
+//!
+//! ```text
+//! supervise("tcp_server", factory)
+//!   loop {
+//!       handle = spawn(factory())      <- state: Running
+//!       result = handle.await          <- task exited or panicked
+//!       log + record error             <- state: Restarting
+//!       sleep(backoff)
+//!       push SubsystemRestarted
+//!   }
+//! ```
+//!
+//! # Shutdown
+//!
+//! Every subsystem receives a `CancellationToken` from `main`. Once it is
+//! cancelled, the supervisor stops restarting and waits (bounded by
+//! `SHUTDOWN_GRACE`) for the subsystem to return. Accept loops stop
+//! accepting, then use `drain` to let in-flight connections finish within
+//! `DRAIN_TIMEOUT` before aborting the stragglers.
+
+use crate::events::EventBus;
+use familycom_core::ipc::{ServerMessage, SubsystemState, SubsystemStatus};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Delay before the first restart of a failed subsystem.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound for the restart delay.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// If a subsystem ran at least this long before failing, its backoff is
+/// reset — it was healthy, so this failure is treated as a fresh incident.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+/// How long an accept loop waits for in-flight connections on shutdown.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the supervisor waits for a cancelled subsystem to return before
+/// aborting it. Slightly longer than `DRAIN_TIMEOUT` so draining can finish.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(6);
+
+/// Shared, cloneable view of every supervised subsystem's health.
+///
+/// Cloning is cheap (it's an `Arc`). The supervisor tasks write to it and
+/// `DaemonApp` reads a snapshot when answering `GetStatus`.
+#[derive(Clone, Default)]
+pub struct HealthRegistry {
+    /// Keyed by subsystem name. A `BTreeMap` keeps `snapshot()` sorted so
+    /// clients see subsystems in a stable order.
+    inner: Arc<Mutex<BTreeMap<String, SubsystemStatus>>>,
+    /// Where restarts are announced, if anywhere.
+    events: Option<EventBus>,
+}
+
+impl HealthRegistry {
+    /// Creates an empty registry.
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty registry that pushes `SubsystemRestarted` to
+    /// `events` whenever a subsystem is restarted.
+    pub fn with_events(events: EventBus) -> Self {
+        Self {
+            events: Some(events),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the current health of all subsystems, sorted by name.
+    pub fn snapshot(&self) -> Vec<SubsystemStatus> {
+        match self.inner.lock() {
+            Ok(map) => map.values().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Applies `f` to the named subsystem's entry, creating it if needed.
+    fn update(&self, name: &str, f: impl FnOnce(&mut SubsystemStatus)) {
+        if let Ok(mut map) = self.inner.lock() {
+            let entry = map.entry(name.to_string()).or_insert_with(|| SubsystemStatus {
+                name: name.to_string(),
+                state: SubsystemState::Running,
+                restarts: 0,
+                last_error: None,
+            });
+            f(entry);
+        }
+    }
+
+    /// Marks the named subsystem as running again after a failure and
+    /// tells subscribed clients.
+    fn restarted(&self, name: &str) {
+        let mut status = None;
+        self.update(name, |s| {
+            s.state = SubsystemState::Running;
+            status = Some(s.clone());
+        });
+        if let (Some(events), Some(status)) = (&self.events, status) {
+            let _ = events.send(ServerMessage::SubsystemRestarted {
+                name: status.name,
+                restarts: status.restarts,
+                reason: status.last_error.unwrap_or_default(),
+            });
+        }
+    }
+}
+
+/// Spawns a supervised subsystem.
+///
+/// `factory` is called once per (re)start and must return the subsystem's
+/// main future. It is a factory rather than a single future because a
+/// future can only be polled to completion once — each restart needs a
+/// fresh one.
+///
+/// The subsystem is expected to watch `shutdown` itself and return once it
+/// is cancelled. The returned handle completes when the subsystem has
+/// stopped for good.
+pub fn supervise<F, Fut>(
+    registry: &HealthRegistry,
+    name: &'static str,
+    shutdown: &CancellationToken,
+    factory: F,
+) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let registry = registry.clone();
+    let shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        let mut backoff = INITIAL_BACKOFF;
+        registry.update(name, |s| s.state = SubsystemState::Running);
+        while !shutdown.is_cancelled() {
+            let started = Instant::now();
+
+            // Spawn (rather than await directly) so a panic inside the
+            // subsystem is caught as a JoinError instead of killing us.
+            let mut handle = tokio::spawn(factory());
+            let result = tokio::select! {
+                result = &mut handle => Some(result),
+                _ = shutdown.cancelled() => None,
+            };
+            let Some(result) = result else {
+                stop(name, handle).await;
+                return;
+            };
+
+            let reason = match result {
+                Ok(()) if shutdown.is_cancelled() => return,
+                Ok(()) => "exited unexpectedly".to_string(),
+                Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e)),
+                Err(e) => format!("was cancelled: {e}"),
+            };
+
+            if started.elapsed() >= HEALTHY_RUN {
+                backoff = INITIAL_BACKOFF;
+            }
+
+            error!(subsystem = name, reason = %reason, retry_in = ?backoff, "subsystem failed, restarting");
+            registry.update(name, |s| {
+                s.state = SubsystemState::Restarting;
+                s.restarts += 1;
+                s.last_error = Some(reason);
+            });
+
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = shutdown.cancelled() => return,
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            info!(subsystem = name, "restarting subsystem");
+            registry.restarted(name);
+        }
+    })
+}
+
+/// Waits for a cancelled subsystem to return, aborting it after `SHUTDOWN_GRACE`.
+async fn stop(name: &'static str, mut handle: JoinHandle<()>) {
+    if tokio::time::timeout(SHUTDOWN_GRACE, &mut handle).await.is_err() {
+        warn!(subsystem = name, "subsystem ignored shutdown, aborting it");
+        handle.abort();
+    } else {
+        info!(subsystem = name, "subsystem stopped");
+    }
+}
+
+/// Waits for an accept loop's in-flight connection tasks to finish.
+///
+/// Connections still running after `DRAIN_TIMEOUT` are aborted, so a peer
+/// that stops mid-frame can't hold up shutdown.
+pub async fn drain(connections: &mut JoinSet<()>, subsystem: &'static str) {
+    let drained = tokio::time::timeout(DRAIN_TIMEOUT, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        warn!(
+            subsystem,
+            remaining = connections.len(),
+            "connections did not finish in time, aborting them"
+        );
+        connections.shutdown().await;
+    }
+}
+
+/// Extracts a readable message from a panicked task's payload.
+fn panic_message(err: tokio::task::JoinError) -> String {
+    match err.try_into_panic() {
+        Ok(payload) => {
+            if let Some(s) = payload.downcast_ref::<&str>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "unknown panic payload".to_string()
+            }
+        }
+        Err(e) => {
+            warn!(error = %e, "join error was not a panic");
+            e.to_string()
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test(start_paused = true)]
+    async fn panicking_subsystem_is_restarted() {
+        let events = EventBus::new();
+        let (mut subscriber, _) = events.subscribe();
+        let registry = HealthRegistry::with_events(events);
+        let starts = Arc::new(AtomicU32::new(0));
+
+        let counter = starts.clone();
+        supervise(&registry, "flaky", &CancellationToken::new(), move || {
+            let counter = counter.clone();
+            async move {
+                // Panic on the first run, then behave like a healthy loop
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("boom");
+                }
+                std::future::pending::<()>().await;
+            }
+        });
+
+        // Paused time auto-advances through the backoff sleep
+        tokio::time::sleep(INITIAL_BACKOFF * 2).await;
+
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        let status = registry.snapshot();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].name, "flaky");
+        assert_eq!(status[0].state, SubsystemState::Running);
+        assert_eq!(status[0].restarts, 1);
+        assert_eq!(status[0].last_error.as_deref(), Some("panicked: boom"));
+
+        // Clients heard about the restart
+        let event = subscriber.try_recv().unwrap();
+        match event.message {
+            ServerMessage::SubsystemRestarted { name, restarts, reason } => {
+                assert_eq!(name, "flaky");
+                assert_eq!(restarts, 1);
+                assert_eq!(reason, "panicked: boom");
+            }
+            other => panic!("expected SubsystemRestarted, got {other:?}"),
+        }
+        assert!(subscriber.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff_grows_between_restarts() {
+        let registry = HealthRegistry::new();
+        let starts = Arc::new(AtomicU32::new(0));
+
+        let counter = starts.clone();
+        supervise(&registry, "dies", &CancellationToken::new(), move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        // Restarts at t=0, 1s, 3s, 7s: four starts within 7.5 seconds
+        tokio::time::sleep(Duration::from_millis(7_500)).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 4);
+        assert_eq!(registry.snapshot()[0].state, SubsystemState::Restarting);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_subsystem_is_not_restarted() {
+        let registry = HealthRegistry::new();
+        let shutdown = CancellationToken::new();
+        let starts = Arc::new(AtomicU32::new(0));
+
+        let counter = starts.clone();
+        let token = shutdown.clone();
+        let handle = supervise(&registry, "server", &shutdown, move || {
+            let counter = counter.clone();
+            let token = token.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                token.cancelled().await;
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        shutdown.cancel();
+        handle.await.unwrap();
+
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        assert_eq!(registry.snapshot()[0].restarts, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_aborts_stuck_connections() {
+        let mut connections = JoinSet::new();
+        connections.spawn(async {});
+        connections.spawn(std::future::pending::<()>());
+
+        let started = Instant::now();
+        drain(&mut connections, "test").await;
+
+        assert!(connections.is_empty());
+        assert!(started.elapsed() >= DRAIN_TIMEOUT);
+    }
+}
@@ -0,0 +1,94 @@
+//! Readiness notifications for systemd (`sd_notify`).
+//!
+//! The unit written by `familycomd install --systemd` is `Type=notify`, so
+//! systemd counts the daemon as started only once it sends `READY=1`: after
+//! the TCP and IPC servers are listening and the first network check has
+//! started mDNS discovery (or decided to stay silent on an untrusted
+//! network).
+//!
+//! The protocol is a datagram of `KEY=VALUE` lines sent to the Unix socket
+//! named by `$NOTIFY_SOCKET` (a leading `@` means an abstract socket).
+//! Outside systemd the variable isn't set and these functions do nothing,
+//! so there's no need for a dependency on libsystemd.
+
+use std::ffi::OsStr;
+use std::io;
+use tracing::{debug, warn};
+
+/// The daemon is up and serving.
+pub fn notify_ready() {
+    notify("READY=1\nSTATUS=Listening for peers and clients");
+}
+
+/// The daemon is re-executing itself (`Restart`) and will send `READY=1`
+/// again when it's back.
+pub fn notify_reloading() {
+    notify("RELOADING=1\nSTATUS=Restarting");
+}
+
+/// The daemon is shutting down.
+pub fn notify_stopping() {
+    notify("STOPPING=1\nSTATUS=Shutting down");
+}
+
+fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    match send(&socket, state) {
+        Ok(()) => debug!(state, "notified systemd"),
+        Err(e) => warn!(error = %e, "failed to notify systemd"),
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    if let Some(name) = socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let _ = name;
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "abstract sockets need Linux"));
+        }
+    }
+    datagram.send_to(state.as_bytes(), socket)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "sd_notify needs Unix sockets"))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn state_is_sent_as_one_datagram() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let systemd = UnixDatagram::bind(&path).unwrap();
+
+        send(path.as_os_str(), "READY=1\nSTATUS=ok").unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\nSTATUS=ok");
+    }
+}
@@ -9,6 +9,8 @@
 //! - **Linux**: Requires GTK3 and libappindicator3. The tray icon runs
 //!   on a dedicated thread with its own GTK event loop.
 //! - **macOS**: Uses NSApplication run loop. Must run on the main thread.
+//! - **Windows**: The icon lives on the thread that created it, which has
+//!   to pump Win32 messages for its menu to work.
 //!
 //! # Architecture
 //!
@@ -22,11 +24,25 @@
 //! │ GTK/Cocoa    │──TrayEvent──>│ DaemonApp    │
 //! │ event loop   │              │ main loop    │
 //! └──────────────┘              └──────────────┘
+//!        ^                              │
+//!        └─── Unread, peers, config ────┘
 //! ```
+//!
+//! While there are unread messages the icon gets a red dot and the tooltip
+//! says how many and from whom ("3 mensajes sin leer de PC-Sala"). The menu
+//! lists the online peers, each with "Abrir chat" and a canned quick reply,
+//! so a short answer doesn't need the TUI, an "Anunciar a todos" submenu
+//! with canned house-wide announcements, and "Silenciar notificaciones",
+//! ticked while `notifications_enabled` is off.
 
-use muda::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use familycom_core::config::AppConfig;
+use familycom_core::types::PeerId;
+use image::{Rgba, RgbaImage};
+use muda::{CheckMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu};
+use std::collections::HashMap;
 use std::sync::mpsc as std_mpsc;
-use tray_icon::TrayIconBuilder;
+use tokio::sync::watch;
+use tray_icon::{TrayIcon, TrayIconBuilder};
 use tracing::{debug, error, info};
 
 /// Events from the tray icon to the daemon.
@@ -34,10 +50,74 @@ use tracing::{debug, error, info};
 pub enum TrayEvent {
     /// User clicked "Open Chat" — daemon should launch the TUI.
     OpenChat,
+    /// User clicked "Abrir chat" under a peer — daemon should launch the
+    /// TUI on that conversation.
+    OpenChatWith(PeerId),
+    /// User clicked the quick reply under a peer — daemon should send it
+    /// (`QUICK_REPLY`) without opening the TUI.
+    QuickReply(PeerId),
+    /// User picked one of `ANNOUNCEMENTS` — daemon should send it to every
+    /// online peer as an announcement.
+    Announce(String),
+    /// User ticked (true) or unticked "Silenciar notificaciones" — daemon
+    /// should turn desktop notifications off or back on, and remember it.
+    PauseNotifications(bool),
     /// User clicked "Quit" — daemon should shut down.
     Quit,
 }
 
+/// The canned reply offered under each online peer.
+pub const QUICK_REPLY: &str = "Voy enseguida";
+
+/// The canned announcements offered while someone is online.
+pub const ANNOUNCEMENTS: &[&str] = &["A cenar!", "A almorzar!", "Nos vamos en 5 minutos", "Llego visita"];
+
+/// An online peer, as listed in the tray menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrayPeer {
+    pub id: PeerId,
+    pub name: String,
+}
+
+/// Tooltip while nothing is unread.
+const IDLE_TOOLTIP: &str = "FamilyCom - LAN Messenger";
+
+/// How many senders the tooltip names before summing up the rest.
+const TOOLTIP_SENDERS: usize = 3;
+
+/// Unread received messages, as published by the daemon's main loop.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unread {
+    /// Across all conversations.
+    pub total: u32,
+    /// Who they're from, most recently active conversation first.
+    pub senders: Vec<String>,
+}
+
+impl Unread {
+    /// The tray tooltip, e.g. "3 mensajes sin leer de PC-Sala".
+    pub fn tooltip(&self) -> String {
+        if self.total == 0 {
+            return IDLE_TOOLTIP.to_string();
+        }
+        let count = match self.total {
+            1 => "1 mensaje sin leer".to_string(),
+            n => format!("{n} mensajes sin leer"),
+        };
+        let Some((last, named)) = self.senders[..self.senders.len().min(TOOLTIP_SENDERS)].split_last() else {
+            return count;
+        };
+        let rest = self.senders.len() - named.len() - 1;
+        if named.is_empty() {
+            format!("{count} de {last}")
+        } else if rest == 0 {
+            format!("{count} de {} y {last}", named.join(", "))
+        } else {
+            format!("{count} de {}, {last} y {rest} mas", named.join(", "))
+        }
+    }
+}
+
 /// Starts the system tray icon on the current thread.
 ///
 /// **This function blocks** — it runs the platform's event loop (GTK/Cocoa).
@@ -46,12 +126,21 @@ pub enum TrayEvent {
 /// # Arguments
 ///
 /// * `event_tx` - Channel to send tray events to the daemon's main loop.
-/// * `peer_count` - Initial peer count to display in the status menu item.
+/// * `unread` - Unread messages, shown as a dot on the icon and in the
+///   tooltip.
+/// * `peers` - Online peers, each listed in the menu with quick actions.
+/// * `config` - The daemon's config, for the "Silenciar notificaciones"
+///   tick.
 ///
 /// # Returns
 ///
 /// Only returns when the tray event loop exits (e.g., after Quit).
-pub fn run_tray(event_tx: std_mpsc::Sender<TrayEvent>, _peer_count: usize) {
+pub fn run_tray(
+    event_tx: std_mpsc::Sender<TrayEvent>,
+    unread: watch::Receiver<Unread>,
+    peers: watch::Receiver<Vec<TrayPeer>>,
+    config: watch::Receiver<AppConfig>,
+) {
     // Initialize GTK (required on Linux before creating tray/menu widgets).
     // If GTK init fails (e.g., no display available), the daemon continues
     // without a tray icon — equivalent to running with --no-tray.
@@ -61,36 +150,7 @@ pub fn run_tray(event_tx: std_mpsc::Sender<TrayEvent>, _peer_count: usize) {
         return;
     }
 
-    // Load the icon from the embedded PNG bytes.
-    // include_bytes! embeds the file at compile time, so no runtime file I/O.
-    let icon_bytes = include_bytes!("../../../assets/icon.png");
-    let icon = load_icon(icon_bytes);
-
-    // Build the context menu
-    let menu = Menu::new();
-
-    let open_item = MenuItem::new("Abrir Chat", true, None);
-    let status_item = MenuItem::new("Estado: En linea", false, None);
-    let quit_item = MenuItem::new("Salir", true, None);
-
-    // Store the IDs for matching events later
-    let open_id = open_item.id().clone();
-    let quit_id = quit_item.id().clone();
-
-    menu.append(&open_item).expect("failed to add menu item");
-    menu.append(&PredefinedMenuItem::separator()).expect("failed to add separator");
-    menu.append(&status_item).expect("failed to add menu item");
-    menu.append(&PredefinedMenuItem::separator()).expect("failed to add separator");
-    menu.append(&quit_item).expect("failed to add menu item");
-
-    // Create the tray icon
-    let _tray_icon = TrayIconBuilder::new()
-        .with_menu(Box::new(menu))
-        .with_tooltip("FamilyCom - LAN Messenger")
-        .with_icon(icon)
-        .build()
-        .expect("failed to create tray icon");
-
+    let mut tray = TrayMenu::new(unread, peers, config);
     info!("system tray icon created");
 
     // Subscribe to menu events
@@ -107,18 +167,15 @@ pub fn run_tray(event_tx: std_mpsc::Sender<TrayEvent>, _peer_count: usize) {
         // glib::timeout_add_local runs a callback at regular intervals
         // on the GTK thread, which is exactly what we need.
         gtk::glib::timeout_add_local(std::time::Duration::from_millis(50), move || {
+            tray.refresh();
             if let Ok(event) = menu_rx.try_recv() {
-                if event.id() == &open_id {
-                    debug!("tray: Open Chat clicked");
-                    if event_tx.send(TrayEvent::OpenChat).is_err() {
+                if let Some(action) = tray.action(event.id()) {
+                    debug!(?action, "tray menu item clicked");
+                    let quit = matches!(action, TrayEvent::Quit);
+                    if event_tx.send(action).is_err() || quit {
                         gtk::main_quit();
                         return gtk::glib::ControlFlow::Break;
                     }
-                } else if event.id() == &quit_id {
-                    debug!("tray: Quit clicked");
-                    let _ = event_tx.send(TrayEvent::Quit);
-                    gtk::main_quit();
-                    return gtk::glib::ControlFlow::Break;
                 }
             }
             gtk::glib::ControlFlow::Continue
@@ -132,16 +189,17 @@ pub fn run_tray(event_tx: std_mpsc::Sender<TrayEvent>, _peer_count: usize) {
     #[cfg(not(target_os = "linux"))]
     {
         loop {
+            #[cfg(windows)]
+            pump_windows_messages();
+
+            tray.refresh();
             if let Ok(event) = menu_rx.try_recv() {
-                if event.id() == &open_id {
-                    debug!("tray: Open Chat clicked");
-                    if event_tx.send(TrayEvent::OpenChat).is_err() {
+                if let Some(action) = tray.action(event.id()) {
+                    debug!(?action, "tray menu item clicked");
+                    let quit = matches!(action, TrayEvent::Quit);
+                    if event_tx.send(action).is_err() || quit {
                         break;
                     }
-                } else if event.id() == &quit_id {
-                    debug!("tray: Quit clicked");
-                    let _ = event_tx.send(TrayEvent::Quit);
-                    break;
                 }
             }
             std::thread::sleep(std::time::Duration::from_millis(50));
@@ -151,38 +209,214 @@ pub fn run_tray(event_tx: std_mpsc::Sender<TrayEvent>, _peer_count: usize) {
     info!("tray event loop exited");
 }
 
-/// Loads a tray icon from PNG bytes.
+/// The tray icon and its menu, kept in step with the daemon.
+struct TrayMenu {
+    icon: TrayIcon,
+    plain_image: RgbaImage,
+    badged_image: RgbaImage,
+    unread: watch::Receiver<Unread>,
+    peers: watch::Receiver<Vec<TrayPeer>>,
+    config: watch::Receiver<AppConfig>,
+    /// What each item of the current menu does. Rebuilt with the menu.
+    actions: HashMap<MenuId, TrayEvent>,
+    /// "Silenciar notificaciones", whose event depends on its tick.
+    pause_item: CheckMenuItem,
+}
+
+impl TrayMenu {
+    fn new(
+        mut unread: watch::Receiver<Unread>,
+        mut peers: watch::Receiver<Vec<TrayPeer>>,
+        mut config: watch::Receiver<AppConfig>,
+    ) -> Self {
+        // Load the icon from the embedded PNG bytes.
+        // include_bytes! embeds the file at compile time, so no runtime file I/O.
+        let icon_bytes = include_bytes!("../../../assets/icon.png");
+        let plain_image = decode_icon(icon_bytes);
+        let mut badged_image = plain_image.clone();
+        add_unread_dot(&mut badged_image);
+
+        let paused = !config.borrow_and_update().notifications_enabled;
+        let (menu, actions, pause_item) = build_menu(&peers.borrow_and_update(), paused);
+        let icon = TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_tooltip(IDLE_TOOLTIP)
+            .with_icon(to_tray_icon(&plain_image))
+            .build()
+            .expect("failed to create tray icon");
+        // Show what was already unread when the daemon started
+        unread.mark_changed();
+
+        Self {
+            icon,
+            plain_image,
+            badged_image,
+            unread,
+            peers,
+            config,
+            actions,
+            pause_item,
+        }
+    }
+
+    /// Applies what changed since the last call: the unread dot and
+    /// tooltip, the peers in the menu and the notifications tick.
+    fn refresh(&mut self) {
+        if self.unread.has_changed().unwrap_or(false) {
+            let unread = self.unread.borrow_and_update().clone();
+            let image = if unread.total > 0 { &self.badged_image } else { &self.plain_image };
+            if let Err(e) = self.icon.set_icon(Some(to_tray_icon(image))) {
+                debug!(error = %e, "failed to update tray icon");
+            }
+            if let Err(e) = self.icon.set_tooltip(Some(unread.tooltip())) {
+                debug!(error = %e, "failed to update tray tooltip");
+            }
+        }
+        if self.config.has_changed().unwrap_or(false) {
+            let paused = !self.config.borrow_and_update().notifications_enabled;
+            self.pause_item.set_checked(paused);
+        }
+        if self.peers.has_changed().unwrap_or(false) {
+            let paused = self.pause_item.is_checked();
+            let (menu, actions, pause_item) = build_menu(&self.peers.borrow_and_update(), paused);
+            self.icon.set_menu(Some(Box::new(menu)));
+            self.actions = actions;
+            self.pause_item = pause_item;
+        }
+    }
+
+    /// What clicking the menu item `id` asks the daemon to do.
+    fn action(&self, id: &MenuId) -> Option<TrayEvent> {
+        // The item has already toggled its own tick by now
+        if id == self.pause_item.id() {
+            return Some(TrayEvent::PauseNotifications(self.pause_item.is_checked()));
+        }
+        self.actions.get(id).cloned()
+    }
+}
+
+/// Builds the context menu: "Abrir Chat", a submenu per online peer and
+/// "Anunciar a todos" (if anyone is online), the status line, "Silenciar notificaciones" (ticked if `paused`) and "Salir".
+fn build_menu(peers: &[TrayPeer], paused: bool) -> (Menu, HashMap<MenuId, TrayEvent>, CheckMenuItem) {
+    let menu = Menu::new();
+    let mut actions = HashMap::new();
+
+    let open_item = MenuItem::new("Abrir Chat", true, None);
+    actions.insert(open_item.id().clone(), TrayEvent::OpenChat);
+    menu.append(&open_item).expect("failed to add menu item");
+    menu.append(&PredefinedMenuItem::separator()).expect("failed to add separator");
+
+    for peer in peers {
+        let open_item = MenuItem::new("Abrir chat", true, None);
+        let reply_item = MenuItem::new(format!("Responder \"{QUICK_REPLY}\""), true, None);
+        actions.insert(open_item.id().clone(), TrayEvent::OpenChatWith(peer.id.clone()));
+        actions.insert(reply_item.id().clone(), TrayEvent::QuickReply(peer.id.clone()));
+        let submenu = Submenu::with_items(&peer.name, true, &[&open_item, &reply_item])
+            .expect("failed to build peer submenu");
+        menu.append(&submenu).expect("failed to add menu item");
+    }
+    if !peers.is_empty() {
+        let announce = Submenu::new("Anunciar a todos", true);
+        for text in ANNOUNCEMENTS {
+            let item = MenuItem::new(*text, true, None);
+            actions.insert(item.id().clone(), TrayEvent::Announce(text.to_string()));
+            announce.append(&item).expect("failed to add menu item");
+        }
+        menu.append(&announce).expect("failed to add menu item");
+        menu.append(&PredefinedMenuItem::separator()).expect("failed to add separator");
+    }
+
+    let status_item = MenuItem::new("Estado: En linea", false, None);
+    menu.append(&status_item).expect("failed to add menu item");
+    let pause_item = CheckMenuItem::new("Silenciar notificaciones", true, paused, None);
+    menu.append(&pause_item).expect("failed to add menu item");
+    menu.append(&PredefinedMenuItem::separator()).expect("failed to add separator");
+
+    let quit_item = MenuItem::new("Salir", true, None);
+    actions.insert(quit_item.id().clone(), TrayEvent::Quit);
+    menu.append(&quit_item).expect("failed to add menu item");
+
+    (menu, actions, pause_item)
+}
+
+/// Dispatches the Win32 messages waiting for this thread, which is how the
+/// tray icon learns about clicks.
+#[cfg(windows)]
+fn pump_windows_messages() {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DispatchMessageW, PeekMessageW, TranslateMessage, MSG, PM_REMOVE,
+    };
+
+    // SAFETY: `msg` is a plain struct filled in by PeekMessageW before use
+    unsafe {
+        let mut msg: MSG = std::mem::zeroed();
+        while PeekMessageW(&mut msg, std::ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+}
+
+/// Decodes the embedded PNG icon.
 ///
 /// The tray-icon crate requires an `Icon` in RGBA format.
 /// We use the `image` crate to decode the PNG and extract the raw pixels.
-fn load_icon(png_bytes: &[u8]) -> tray_icon::Icon {
-    let img = image::load_from_memory(png_bytes)
+fn decode_icon(png_bytes: &[u8]) -> RgbaImage {
+    image::load_from_memory(png_bytes)
         .expect("failed to decode embedded icon PNG")
-        .into_rgba8();
+        .into_rgba8()
+}
 
-    let (width, height) = img.dimensions();
-    let rgba = img.into_raw();
+/// Paints the unread dot: a red disc in the icon's top-right corner, a
+/// third of its width across.
+fn add_unread_dot(image: &mut RgbaImage) {
+    let (width, height) = image.dimensions();
+    let radius = (width.min(height) / 6).max(1) as i64;
+    let (cx, cy) = (width as i64 - radius - 1, radius);
+    for y in 0..height {
+        for x in 0..width {
+            let (dx, dy) = (x as i64 - cx, y as i64 - cy);
+            if dx * dx + dy * dy <= radius * radius {
+                image.put_pixel(x, y, Rgba([220, 40, 40, 255]));
+            }
+        }
+    }
+}
 
-    tray_icon::Icon::from_rgba(rgba, width, height)
+fn to_tray_icon(image: &RgbaImage) -> tray_icon::Icon {
+    tray_icon::Icon::from_rgba(image.as_raw().clone(), image.width(), image.height())
         .expect("failed to create tray icon from RGBA data")
 }
 
-/// Launches the TUI in a new terminal window.
+/// Launches the TUI in a new terminal window, on the conversation with
+/// `peer` if given (`familycom --peer`).
 ///
 /// Tries to find an appropriate terminal emulator and opens the
-/// `familycom` binary in it.
-pub fn open_chat_in_terminal() {
+/// `familycom` binary in it. Terminal.app can't pass arguments, so on
+/// macOS the TUI opens on its usual first conversation.
+pub fn open_chat_in_terminal(peer: Option<&PeerId>) {
     // Try to find the familycom binary in PATH or next to familycomd
     let familycom_path = find_familycom_binary();
+    let mut command = vec![familycom_path.as_str()];
+    if let Some(peer) = peer {
+        command.extend(["--peer", peer.as_str()]);
+    }
 
     let result = if cfg!(target_os = "macos") {
         // macOS: use `open` to launch Terminal.app
         std::process::Command::new("open")
             .args(["-a", "Terminal", &familycom_path])
             .spawn()
+    } else if cfg!(windows) {
+        // Windows: `start` opens the TUI in a new console window (the
+        // empty first argument is the window title)
+        std::process::Command::new("cmd")
+            .args(["/C", "start", ""])
+            .args(&command)
+            .spawn()
     } else {
         // Linux: try common terminal emulators in order of preference
-        try_linux_terminals(&familycom_path)
+        try_linux_terminals(&command)
     };
 
     match result {
@@ -191,14 +425,10 @@ pub fn open_chat_in_terminal() {
     }
 }
 
-/// Finds the familycom binary path.
-///
-/// First checks if it's in the same directory as familycomd,
-/// then falls back to looking in PATH.
 fn find_familycom_binary() -> String {
     // Try same directory as current binary
     if let Ok(current_exe) = std::env::current_exe() {
-        let sibling = current_exe.with_file_name("familycom");
+        let sibling = current_exe.with_file_name(format!("familycom{}", std::env::consts::EXE_SUFFIX));
         if sibling.exists() {
             return sibling.to_string_lossy().to_string();
         }
@@ -212,14 +442,15 @@ fn find_familycom_binary() -> String {
 /// First checks the `$TERMINAL` environment variable (the standard way to
 /// specify a preferred terminal on Linux), then falls back to a list of
 /// common terminal emulators in order of popularity.
-fn try_linux_terminals(command: &str) -> Result<std::process::Child, std::io::Error> {
+fn try_linux_terminals(command: &[&str]) -> Result<std::process::Child, std::io::Error> {
     // Try the user's preferred terminal first ($TERMINAL is the de facto
     // standard on Linux for specifying the default terminal emulator).
     if let Ok(term) = std::env::var("TERMINAL") {
         if !term.is_empty() {
             debug!(terminal = %term, "trying $TERMINAL");
             match std::process::Command::new(&term)
-                .args(["-e", command])
+                .arg("-e")
+                .args(command)
                 .spawn()
             {
                 Ok(child) => return Ok(child),
@@ -251,7 +482,7 @@ fn try_linux_terminals(command: &str) -> Result<std::process::Child, std::io::Er
 
     for (terminal, args) in &terminals {
         let mut cmd_args: Vec<&str> = args.clone();
-        cmd_args.push(command);
+        cmd_args.extend(command);
 
         match std::process::Command::new(terminal)
             .args(&cmd_args)
@@ -280,3 +511,40 @@ pub fn request_quit() {
         gtk::glib::ControlFlow::Break
     });
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unread(total: u32, senders: &[&str]) -> Unread {
+        Unread {
+            total,
+            senders: senders.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn tooltip_names_the_senders() {
+        assert_eq!(unread(0, &[]).tooltip(), IDLE_TOOLTIP);
+        assert_eq!(unread(1, &["PC-Sala"]).tooltip(), "1 mensaje sin leer de PC-Sala");
+        assert_eq!(unread(3, &["PC-Sala"]).tooltip(), "3 mensajes sin leer de PC-Sala");
+        assert_eq!(unread(4, &["PC-Sala", "Abuela"]).tooltip(), "4 mensajes sin leer de PC-Sala y Abuela");
+        assert_eq!(
+            unread(9, &["PC-Sala", "Abuela", "Cocina", "Tablet", "Papa"]).tooltip(),
+            "9 mensajes sin leer de PC-Sala, Abuela, Cocina y 2 mas"
+        );
+    }
+
+    #[test]
+    fn unread_dot_sits_in_the_top_right_corner() {
+        let mut image = RgbaImage::new(24, 24);
+        add_unread_dot(&mut image);
+        assert_eq!(image.get_pixel(19, 4)[0], 220);
+        assert_eq!(image.get_pixel(4, 4)[3], 0, "left side untouched");
+        assert_eq!(image.get_pixel(19, 19)[3], 0, "bottom untouched");
+    }
+}
@@ -0,0 +1,238 @@
+//! Webhook: POSTs each received message to `webhook_url`.
+//!
+//! For piping family messages into Home Assistant, ntfy or a script. The
+//! body is JSON:
+//!
+//! ```json
+//! {"sender": "🐱 PC-Sala", "sender_id": "550e8400-...", "message_id": "...",
+//!  "preview": "la cena está lista", "timestamp": "2026-10-16T19:02:11.250Z"}
+//! ```
+//!
+//! `sender` is named as in notifications and `preview` is cut like the TUI's
+//! peer list. The URL is plain HTTP (see `WebhookUrl`) and is read for every
+//! message, so a config reload can set, change or remove it.
+//!
+//! Delivery is best effort: a post that fails or times out is logged, not
+//! retried, and if the endpoint falls more than `QUEUE_SIZE` messages
+//! behind, newer ones are dropped.
+
+use crate::events::EventBus;
+use familycom_core::config::{AppConfig, WebhookUrl};
+use familycom_core::ipc::ServerMessage;
+use familycom_core::types::{ConversationSummary, Direction, Message, MessageId, PeerId};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
+
+/// How long one post may take, connecting included.
+const POST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Messages waiting to be posted before new ones are dropped.
+const QUEUE_SIZE: usize = 64;
+
+/// The JSON body posted for one message.
+#[derive(Debug, Serialize)]
+struct Payload {
+    sender: String,
+    sender_id: PeerId,
+    message_id: MessageId,
+    preview: String,
+    /// RFC 3339, UTC.
+    timestamp: String,
+}
+
+impl Payload {
+    fn new(message: &Message, sender: String) -> Self {
+        let timestamp = chrono::DateTime::from_timestamp_millis(message.timestamp.as_millis())
+            .map(|at| at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+            .unwrap_or_default();
+        Self {
+            sender,
+            sender_id: message.peer_id.clone(),
+            message_id: message.id.clone(),
+            preview: ConversationSummary::preview(&message.content),
+            timestamp,
+        }
+    }
+}
+
+/// Listens to daemon events and posts each received message to the
+/// configured webhook, if any.
+///
+/// Runs until the event channel closes or `shutdown` is cancelled, and
+/// subscribes itself so the supervisor can restart it. Posts happen on a
+/// separate task, so a slow endpoint doesn't hold up the event stream.
+pub async fn run_handler(
+    events: EventBus,
+    config: watch::Receiver<AppConfig>,
+    peer_names: watch::Receiver<HashMap<PeerId, String>>,
+    shutdown: CancellationToken,
+) {
+    let (queue_tx, queue_rx) = mpsc::channel(QUEUE_SIZE);
+    let poster = tokio::spawn(post_queued(queue_rx));
+    let (mut event_rx, _) = events.subscribe();
+
+    loop {
+        let event = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            event = event_rx.recv() => event.map(|event| event.message),
+        };
+        let messages = match event {
+            Ok(ServerMessage::NewMessage { message }) => vec![message],
+            Ok(ServerMessage::NewMessages { messages, .. }) => messages,
+            Ok(_) => continue,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!(missed = n, "webhook handler lagged");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let Some(url) = config.borrow().webhook_url.clone() else {
+            continue;
+        };
+        for message in messages.iter().filter(|m| m.direction == Direction::Received) {
+            let sender = peer_names
+                .borrow()
+                .get(&message.peer_id)
+                .cloned()
+                .unwrap_or_else(|| message.peer_id.to_string());
+            if queue_tx.try_send((url.clone(), Payload::new(message, sender))).is_err() {
+                warn!(message_id = %message.id, "webhook is falling behind, dropping message");
+            }
+        }
+    }
+
+    // Whatever is still queued isn't worth holding up shutdown for
+    poster.abort();
+}
+
+/// Posts queued messages one at a time, in order.
+async fn post_queued(mut queue: mpsc::Receiver<(WebhookUrl, Payload)>) {
+    while let Some((url, payload)) = queue.recv().await {
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                error!(error = %e, "failed to encode webhook payload");
+                continue;
+            }
+        };
+        match tokio::time::timeout(POST_TIMEOUT, post(&url, &body)).await {
+            Ok(Ok(status)) if (200..300).contains(&status) => {
+                debug!(message_id = %payload.message_id, status, "posted message to webhook");
+            }
+            Ok(Ok(status)) => warn!(%url, status, "webhook refused the message"),
+            Ok(Err(e)) => warn!(%url, error = %e, "failed to post message to webhook"),
+            Err(_) => warn!(%url, "webhook did not answer in time"),
+        }
+    }
+}
+
+/// Sends `body` as a JSON HTTP/1.1 POST and returns the response's status
+/// code. The rest of the response is ignored.
+async fn post(url: &WebhookUrl, body: &[u8]) -> io::Result<u16> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+    let head = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         User-Agent: familycomd/{}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        url.path,
+        url.authority(),
+        env!("CARGO_PKG_VERSION"),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    // "HTTP/1.1 204 No Content"
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line).await?;
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not an HTTP response: {:?}", status_line.trim_end()),
+            )
+        })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use familycom_core::types::Timestamp;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// Accepts one request, answers `status` and returns the request's
+    /// head and body.
+    async fn serve_once(listener: TcpListener, status: &'static str) -> (String, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            head.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.unwrap();
+        let mut stream = reader.into_inner();
+        stream.write_all(format!("HTTP/1.1 {status}\r\n\r\n").as_bytes()).await.unwrap();
+        (head, String::from_utf8(body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn a_message_is_posted_as_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve_once(listener, "204 No Content"));
+
+        let url: WebhookUrl = format!("http://127.0.0.1:{port}/api/webhook/familia").parse().unwrap();
+        let message = Message {
+            id: MessageId::new("m1"),
+            peer_id: PeerId::new("peer-sala"),
+            direction: Direction::Received,
+            content: "la cena\nestá lista".to_string(),
+            timestamp: Timestamp::from_millis(1_700_000_000_250),
+            delivered: true,
+            fire_and_forget: false,
+            announcement: false,
+            reply_to: None,
+        };
+        let body = serde_json::to_vec(&Payload::new(&message, "PC-Sala".to_string())).unwrap();
+        assert_eq!(post(&url, &body).await.unwrap(), 204);
+
+        let (head, body) = server.await.unwrap();
+        assert!(head.starts_with("POST /api/webhook/familia HTTP/1.1\r\n"));
+        assert!(head.contains(&format!("Host: 127.0.0.1:{port}\r\n")));
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["sender"], "PC-Sala");
+        assert_eq!(json["sender_id"], "peer-sala");
+        assert_eq!(json["preview"], "la cena está lista");
+        assert_eq!(json["timestamp"], "2023-11-14T22:13:20.250Z");
+    }
+}
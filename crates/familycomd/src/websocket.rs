@@ -0,0 +1,331 @@
+//! WebSocket sessions for the web page the HTTP API serves.
+//!
+//! A phone browser can't hold a raw IPC connection, so `GET /ws` on the
+//! HTTP API upgrades to a WebSocket (RFC 6455) that carries the same JSON
+//! as the IPC protocol, one text frame per message:
+//!
+//! ```text
+//! Browser → Daemon:  "ListPeers"
+//! Daemon → Browser:  {"type":"PeerList","peers":[...]}
+//! Browser → Daemon:  {"SendMessage":{"peer_id":"...","content":"Hola"}}
+//! Daemon → Browser:  {"type":"MessageSent","message_id":"..."}
+//! ... whenever something happens ...
+//! Daemon → Browser:  {"type":"NewMessage","message":{...},"seq":"..."}
+//! ```
+//!
+//! Requests go through the main loop like a client's and are answered in
+//! order. A session is always subscribed to every event; pushed events
+//! carry a `seq`, responses don't, which is how the page tells them apart.
+//!
+//! The socket is unauthenticated and may be reachable from the whole LAN,
+//! so only the requests the chat page needs are accepted (see
+//! `allowed_on_web`); anything that changes settings, deletes history,
+//! writes files or stops the daemon is refused.
+//!
+//! The protocol itself (handshake key, framing, masking, ping/pong,
+//! the closing handshake and RFC 6455's limits on control frames) is
+//! `tungstenite`'s; this module only carries messages. Text messages
+//! only: a binary one closes the session.
+
+use crate::events::EventBus;
+use crate::ipc_server::IpcRequest;
+use familycom_core::ipc::{self, ClientRequest, ErrorCode, ServerMessage};
+use futures_util::{SinkExt, StreamExt};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// Largest message accepted from the browser, fragments included.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// The `Sec-WebSocket-Accept` answering a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    derive_accept_key(key.trim().as_bytes())
+}
+
+/// Runs one session on an upgraded connection (the `101` already sent)
+/// until the browser closes it or `shutdown`.
+pub async fn serve<S>(
+    stream: S,
+    request_tx: &mpsc::Sender<IpcRequest>,
+    events: &EventBus,
+    shutdown: &CancellationToken,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE_BYTES))
+        .max_frame_size(Some(MAX_MESSAGE_BYTES));
+    let mut socket = WebSocketStream::from_raw_socket(stream, Role::Server, Some(config)).await;
+    match session(&mut socket, request_tx, events, shutdown).await {
+        Ok(()) | Err(WsError::ConnectionClosed | WsError::AlreadyClosed) => Ok(()),
+        Err(e) => {
+            // Tell a browser that broke the protocol why, if it's still there
+            let code = match &e {
+                WsError::Capacity(_) => Some(CloseCode::Size),
+                WsError::Protocol(_) | WsError::Utf8(_) => Some(CloseCode::Protocol),
+                _ => None,
+            };
+            if let Some(code) = code {
+                let _ = close(&mut socket, code).await;
+            }
+            Err(io::Error::other(e))
+        }
+    }
+}
+
+async fn session<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut WebSocketStream<S>,
+    request_tx: &mpsc::Sender<IpcRequest>,
+    events: &EventBus,
+    shutdown: &CancellationToken,
+) -> Result<(), WsError> {
+    let (mut event_rx, mut last_seq) = events.subscribe();
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return close(socket, CloseCode::Away).await,
+
+            // A frame read halfway when another branch wins stays buffered
+            // in the socket for the next call
+            incoming = socket.next() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    let response = match ipc::decode_request(&text) {
+                        Ok(
+                            ClientRequest::Subscribe { .. }
+                            | ClientRequest::Unsubscribe { .. }
+                            | ClientRequest::ListSubscriptions,
+                        ) => ServerMessage::Error {
+                            code: ErrorCode::InvalidRequest,
+                            message: "a web session always receives every event".to_string(),
+                        },
+                        Ok(request) if !allowed_on_web(&request) => ServerMessage::Error {
+                            code: ErrorCode::InvalidRequest,
+                            message: "request not available from the web page".to_string(),
+                        },
+                        Ok(request) => match IpcRequest::submit(request_tx, request).await {
+                            Some(response) => response,
+                            None => return close(socket, CloseCode::Away).await,
+                        },
+                        Err(e) => ServerMessage::Error {
+                            code: ErrorCode::InvalidRequest,
+                            message: format!("failed to parse request: {e}"),
+                        },
+                    };
+                    let json = ipc::encode_response(&response).map_err(|e| WsError::Io(io::Error::other(e)))?;
+                    socket.send(Message::text(json.trim_end())).await?;
+                }
+                Some(Ok(Message::Binary(_))) => return close(socket, CloseCode::Unsupported).await,
+                // Pings are answered by the socket itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                // The socket has queued its answer to the browser's close
+                Some(Ok(Message::Close(_))) => return socket.flush().await,
+                Some(Err(e)) => return Err(e),
+                None => return Ok(()),
+            },
+
+            event = event_rx.recv() => match event {
+                // Already sent while catching up
+                Ok(event) if event.seq <= last_seq => {}
+                Ok(event) => {
+                    last_seq = event.seq;
+                    write_event(socket, events, event.seq, &event.message).await?;
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    let missed = events.since(last_seq);
+                    if !missed.complete {
+                        warn!(missed = n, "web session lagged behind on events");
+                    }
+                    last_seq = missed.up_to;
+                    for event in missed.events {
+                        write_event(socket, events, event.seq, &event.message).await?;
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Whether a web session may send `request`: only chatting, since
+/// anyone on the network can open one.
+fn allowed_on_web(request: &ClientRequest) -> bool {
+    matches!(
+        request,
+        ClientRequest::ListPeers
+            | ClientRequest::GetConversations
+            | ClientRequest::GetMessages { .. }
+            | ClientRequest::SendMessage { .. }
+            | ClientRequest::MarkRead { .. }
+    )
+}
+
+async fn write_event<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut WebSocketStream<S>,
+    events: &EventBus,
+    seq: u64,
+    message: &ServerMessage,
+) -> Result<(), WsError> {
+    let json = ipc::encode_event(&events.seq_string(seq), message).map_err(|e| WsError::Io(io::Error::other(e)))?;
+    socket.send(Message::text(json.trim_end())).await
+}
+
+/// Starts the closing handshake with `code`.
+async fn close<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut WebSocketStream<S>,
+    code: CloseCode,
+) -> Result<(), WsError> {
+    socket
+        .close(Some(CloseFrame {
+            code,
+            reason: "".into(),
+        }))
+        .await
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio::task::JoinHandle;
+
+    const OP_CONTINUATION: u8 = 0x0;
+    const OP_TEXT: u8 = 0x1;
+    const OP_BINARY: u8 = 0x2;
+    const OP_CLOSE: u8 = 0x8;
+    const OP_PING: u8 = 0x9;
+    const OP_PONG: u8 = 0xA;
+
+    /// A frame as a browser sends it, masked.
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let fin = if fin { 0x80 } else { 0 };
+        let mut frame = vec![fin | opcode];
+        if payload.len() < 126 {
+            frame.push(0x80 | payload.len() as u8);
+        } else {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        frame
+    }
+
+    /// A session on one end of an in-memory pipe, with a daemon that
+    /// answers `Ok` to everything; the browser gets the other end.
+    fn open_session() -> (DuplexStream, JoinHandle<io::Result<()>>) {
+        let (browser, server) = tokio::io::duplex(64 * 1024);
+        let (request_tx, mut request_rx) = mpsc::channel::<IpcRequest>(8);
+        tokio::spawn(async move {
+            while let Some(IpcRequest { response_tx, .. }) = request_rx.recv().await {
+                let _ = response_tx.send(ServerMessage::Ok).await;
+            }
+        });
+        let session =
+            tokio::spawn(async move { serve(server, &request_tx, &EventBus::new(), &CancellationToken::new()).await });
+        (browser, session)
+    }
+
+    /// The next frame from the server as (first byte, payload).
+    async fn server_frame(browser: &mut DuplexStream) -> (u8, Vec<u8>) {
+        let mut head = [0u8; 2];
+        browser.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[1] & 0x80, 0, "server frames are not masked");
+        let len = match head[1] {
+            126 => usize::from(browser.read_u16().await.unwrap()),
+            len => usize::from(len),
+        };
+        let mut payload = vec![0; len];
+        browser.read_exact(&mut payload).await.unwrap();
+        (head[0], payload)
+    }
+
+    /// Sends `raw` and expects the session to close with `code`.
+    async fn refused_with(raw: &[u8], code: u16) {
+        let (mut browser, session) = open_session();
+        browser.write_all(raw).await.unwrap();
+        let (head, payload) = server_frame(&mut browser).await;
+        assert_eq!(head, 0x80 | OP_CLOSE);
+        assert_eq!(payload[..2], code.to_be_bytes());
+        drop(browser);
+        assert!(session.await.unwrap().is_err());
+    }
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[tokio::test]
+    async fn fragmented_text_is_answered_and_pings_get_a_pong() {
+        let (mut browser, session) = open_session();
+        let mut raw = client_frame(false, OP_TEXT, br#""List"#);
+        raw.extend(client_frame(true, OP_PING, b"hi"));
+        raw.extend(client_frame(true, OP_CONTINUATION, br#"Peers""#));
+        browser.write_all(&raw).await.unwrap();
+
+        assert_eq!(server_frame(&mut browser).await, (0x80 | OP_PONG, b"hi".to_vec()));
+        assert_eq!(
+            server_frame(&mut browser).await,
+            (0x80 | OP_TEXT, br#"{"type":"Ok"}"#.to_vec())
+        );
+
+        browser.write_all(&client_frame(true, OP_CLOSE, &[])).await.unwrap();
+        assert_eq!(server_frame(&mut browser).await.0, 0x80 | OP_CLOSE);
+        assert!(session.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn control_frames_must_be_whole_and_short() {
+        // RFC 6455 §5.5: no fragmented control frames, none over 125 bytes
+        refused_with(&client_frame(false, OP_PING, b"hi"), 1002).await;
+        refused_with(&client_frame(true, OP_PING, &[b'x'; 126]), 1002).await;
+    }
+
+    #[tokio::test]
+    async fn unmasked_oversized_and_binary_frames_are_refused() {
+        refused_with(&[0x81, 0x02, b'h', b'i'], 1002).await;
+        let mut oversized = vec![0x81, 0x80 | 127];
+        oversized.extend_from_slice(&(MAX_MESSAGE_BYTES as u64 + 1).to_be_bytes());
+        oversized.extend_from_slice(&[0; 4]);
+        refused_with(&oversized, 1009).await;
+
+        let (mut browser, session) = open_session();
+        browser
+            .write_all(&client_frame(true, OP_BINARY, b"\x00\x01"))
+            .await
+            .unwrap();
+        let (head, payload) = server_frame(&mut browser).await;
+        assert_eq!(
+            (head, payload[..2].to_vec()),
+            (0x80 | OP_CLOSE, 1003u16.to_be_bytes().to_vec())
+        );
+        drop(browser);
+        assert!(session.await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn only_chat_requests_are_allowed_on_the_web() {
+        let allowed = |json: &str| allowed_on_web(&ipc::decode_request(json).unwrap());
+        assert!(allowed(r#""ListPeers""#));
+        assert!(allowed(r#"{"SendMessage":{"peer_id":"p","content":"Hola"}}"#));
+        assert!(allowed(r#"{"MarkRead":{"peer_id":"p"}}"#));
+        assert!(!allowed(r#""Shutdown""#));
+        assert!(!allowed(r#"{"SetDisplayName":{"name":"Intruso"}}"#));
+        assert!(!allowed(
+            r#"{"ExportConversation":{"peer_id":"p","format":"text","path":"/tmp/x"}}"#
+        ));
+    }
+}